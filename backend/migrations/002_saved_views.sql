@@ -0,0 +1,21 @@
+-- Saved views: named, reusable upload filters
+
+-- ============= VIEWS =============
+
+-- Create views table
+CREATE TABLE IF NOT EXISTS views (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    tag_expression TEXT, -- e.g. "failed AND NOT .log"
+    created_after TEXT,
+    created_before TEXT,
+    within_days INTEGER, -- relative date range, evaluated at query time
+    sort_by TEXT NOT NULL DEFAULT 'created_at',
+    sort_order TEXT NOT NULL DEFAULT 'desc',
+    created_at TEXT NOT NULL,
+    CHECK (sort_by IN ('created_at', 'name', 'size')),
+    CHECK (sort_order IN ('asc', 'desc'))
+);
+
+-- View indexes
+CREATE INDEX IF NOT EXISTS idx_views_created_at ON views(created_at);
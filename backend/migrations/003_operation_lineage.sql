@@ -0,0 +1,30 @@
+-- Lineage for files derived by built-in operations (query, pivot, sample) instead of functions
+
+-- ============= FILE LINEAGE =============
+
+-- SQLite cannot relax NOT NULL in place, so rebuild the table with a nullable function_id
+CREATE TABLE file_lineage_new (
+    id TEXT PRIMARY KEY,
+    output_upload_id TEXT NOT NULL,
+    source_upload_id TEXT NOT NULL,
+    function_id TEXT,
+    operation TEXT, -- e.g. "pivot", set when no function produced the file
+    query TEXT, -- JSON parameters of the operation, enough to reproduce it
+    success INTEGER NOT NULL DEFAULT 1,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (output_upload_id) REFERENCES uploads(id) ON DELETE CASCADE,
+    FOREIGN KEY (source_upload_id) REFERENCES uploads(id) ON DELETE CASCADE,
+    FOREIGN KEY (function_id) REFERENCES functions(id) ON DELETE CASCADE,
+    CHECK (function_id IS NOT NULL OR operation IS NOT NULL)
+);
+
+INSERT INTO file_lineage_new (id, output_upload_id, source_upload_id, function_id, success, created_at)
+SELECT id, output_upload_id, source_upload_id, function_id, success, created_at FROM file_lineage;
+
+DROP TABLE file_lineage;
+ALTER TABLE file_lineage_new RENAME TO file_lineage;
+
+-- Lineage indexes (dropped along with the old table)
+CREATE INDEX IF NOT EXISTS idx_file_lineage_output_upload_id ON file_lineage(output_upload_id);
+CREATE INDEX IF NOT EXISTS idx_file_lineage_source_upload_id ON file_lineage(source_upload_id);
+CREATE INDEX IF NOT EXISTS idx_file_lineage_function_id ON file_lineage(function_id);
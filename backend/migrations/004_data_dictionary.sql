@@ -0,0 +1,13 @@
+-- Data dictionary: human-readable descriptions and units for the columns of tabular uploads
+
+-- ============= DATA DICTIONARY =============
+
+-- Create column dictionary table
+CREATE TABLE IF NOT EXISTS column_dictionary (
+    upload_id TEXT NOT NULL,
+    column_name TEXT NOT NULL,
+    description TEXT, -- e.g. "temperature at probe 1"
+    unit TEXT, -- e.g. "°C"
+    PRIMARY KEY (upload_id, column_name),
+    FOREIGN KEY (upload_id) REFERENCES uploads(id) ON DELETE CASCADE
+);
@@ -0,0 +1,8 @@
+-- Content checksums for spotting (and optionally deduplicating) identical uploads
+
+-- ============= UPLOADS =============
+
+-- SHA-256 of the file content as lowercase hex; NULL for files uploaded before this migration
+ALTER TABLE uploads ADD COLUMN sha256 TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_uploads_sha256 ON uploads(sha256);
@@ -0,0 +1,18 @@
+-- Report templates: Markdown/HTML documents filled with query results and pipeline activity
+
+-- ============= REPORTS =============
+
+-- Create report templates table
+CREATE TABLE IF NOT EXISTS report_templates (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    description TEXT,
+    format TEXT NOT NULL DEFAULT 'markdown',
+    template TEXT NOT NULL, -- Jinja-style template source
+    queries TEXT NOT NULL DEFAULT '{}', -- JSON map of name -> SQL request, run before rendering
+    schedule_hours INTEGER, -- render automatically every N hours; NULL for on demand only
+    last_rendered_at TEXT,
+    created_at TEXT NOT NULL,
+    CHECK (format IN ('markdown', 'html')),
+    CHECK (schedule_hours IS NULL OR schedule_hours > 0)
+);
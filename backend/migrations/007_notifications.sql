@@ -0,0 +1,17 @@
+-- In-app notification inbox, shared by everyone using this instance
+
+-- ============= NOTIFICATIONS =============
+
+CREATE TABLE IF NOT EXISTS notifications (
+    id TEXT PRIMARY KEY NOT NULL,
+    kind TEXT NOT NULL, -- job_failed
+    title TEXT NOT NULL,
+    message TEXT,
+    upload_id TEXT, -- kept after the upload or job is deleted, so no foreign keys
+    job_id TEXT,
+    read_at TEXT,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_notifications_created_at ON notifications(created_at);
+CREATE INDEX IF NOT EXISTS idx_notifications_read_at ON notifications(read_at);
@@ -0,0 +1,10 @@
+-- Ownership for triaging uploads and failed jobs
+
+-- ============= ASSIGNEES =============
+
+-- Free-form name or handle; there are no user accounts to reference yet
+ALTER TABLE uploads ADD COLUMN assignee TEXT;
+ALTER TABLE jobs ADD COLUMN assignee TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_uploads_assignee ON uploads(assignee);
+CREATE INDEX IF NOT EXISTS idx_jobs_assignee ON jobs(assignee);
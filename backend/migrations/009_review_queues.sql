@@ -0,0 +1,36 @@
+-- Human review steps: uploads with a queue's tag wait for an approve/reject decision
+
+-- ============= REVIEW QUEUES =============
+
+CREATE TABLE IF NOT EXISTS review_queues (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    description TEXT,
+    input_tag_id TEXT NOT NULL, -- uploads with this tag enter the queue
+    approve_tag_id TEXT NOT NULL, -- applied on approval, which may trigger functions
+    reject_tag_id TEXT, -- optionally applied on rejection
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (input_tag_id) REFERENCES tags(id) ON DELETE CASCADE,
+    FOREIGN KEY (approve_tag_id) REFERENCES tags(id) ON DELETE CASCADE,
+    FOREIGN KEY (reject_tag_id) REFERENCES tags(id) ON DELETE SET NULL
+);
+
+-- ============= REVIEWS =============
+
+-- One decision per upload and queue
+CREATE TABLE IF NOT EXISTS reviews (
+    id TEXT PRIMARY KEY,
+    queue_id TEXT NOT NULL,
+    upload_id TEXT NOT NULL,
+    decision TEXT NOT NULL,
+    comment TEXT,
+    reviewer TEXT, -- free-form name, like assignees
+    created_at TEXT NOT NULL,
+    UNIQUE (queue_id, upload_id),
+    FOREIGN KEY (queue_id) REFERENCES review_queues(id) ON DELETE CASCADE,
+    FOREIGN KEY (upload_id) REFERENCES uploads(id) ON DELETE CASCADE,
+    CHECK (decision IN ('approved', 'rejected'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_reviews_queue_id ON reviews(queue_id);
+CREATE INDEX IF NOT EXISTS idx_reviews_upload_id ON reviews(upload_id);
@@ -0,0 +1,6 @@
+-- Run heavy functions on a Slurm cluster or Kubernetes instead of the DataLab host
+
+-- ============= FUNCTION EXECUTORS =============
+
+ALTER TABLE functions ADD COLUMN executor TEXT NOT NULL DEFAULT 'local'
+    CHECK (executor IN ('local', 'slurm', 'kubernetes'));
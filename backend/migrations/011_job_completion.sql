@@ -0,0 +1,8 @@
+-- Back-channel for remote workers to push job results
+
+-- ============= JOB COMPLETION =============
+
+-- SHA-256 of the token handed to a remote run; only its holder may complete the job
+ALTER TABLE jobs ADD COLUMN completion_token_hash TEXT;
+-- Who delivered the results: executor or worker. Set once, so results are registered only once
+ALTER TABLE jobs ADD COLUMN completed_via TEXT;
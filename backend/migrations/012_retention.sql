@@ -0,0 +1,30 @@
+-- Automatic expiry of old uploads
+
+-- ============= RETENTION RULES =============
+
+-- Uploads older than max_age_days are deleted; without a tag a rule applies to every upload
+CREATE TABLE IF NOT EXISTS retention_rules (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    tag_id TEXT,
+    error_logs_only INTEGER NOT NULL DEFAULT 0, -- only outputs of failed function runs
+    max_age_days INTEGER NOT NULL CHECK (max_age_days > 0),
+    enabled INTEGER NOT NULL DEFAULT 1,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE
+);
+
+-- What the sweeper deleted; kept after the upload and the rule are gone
+CREATE TABLE IF NOT EXISTS retention_purges (
+    id TEXT PRIMARY KEY,
+    rule_id TEXT,
+    rule_name TEXT NOT NULL,
+    upload_id TEXT NOT NULL,
+    original_filename TEXT NOT NULL,
+    file_size INTEGER NOT NULL,
+    uploaded_at TEXT NOT NULL,
+    purged_at TEXT NOT NULL,
+    FOREIGN KEY (rule_id) REFERENCES retention_rules(id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_retention_purges_purged_at ON retention_purges(purged_at);
@@ -0,0 +1,6 @@
+-- Narrow function triggers beyond input tags (filename, MIME type, size, lineage, tag expression)
+
+-- ============= TRIGGER CONDITIONS =============
+
+-- JSON array of conditions that must all hold, e.g. [{"type": "filename", "pattern": "*_raw.csv"}]
+ALTER TABLE functions ADD COLUMN trigger_conditions TEXT NOT NULL DEFAULT '[]';
@@ -0,0 +1,11 @@
+-- Limits on how much data can be stored, per tag (the global limit is a server setting)
+
+-- ============= STORAGE QUOTAS =============
+
+-- Uploads carrying the tag may not add up to more than max_bytes
+CREATE TABLE IF NOT EXISTS storage_quotas (
+    tag_id TEXT PRIMARY KEY,
+    max_bytes INTEGER NOT NULL CHECK (max_bytes >= 0),
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE
+);
@@ -0,0 +1,7 @@
+-- MIME types detected from file content, next to the type the client claimed
+
+-- ============= UPLOADS =============
+
+-- Sniffed from the first bytes of the file; NULL for unknown binary formats, empty files and
+-- files uploaded before this migration
+ALTER TABLE uploads ADD COLUMN detected_mime_type TEXT;
@@ -0,0 +1,10 @@
+-- Indexes for listing uploads by origin (root vs derived) and by the function that made them
+
+-- ============= FILE LINEAGE =============
+
+-- "Outputs of function X" is answered from the index alone
+CREATE INDEX IF NOT EXISTS idx_file_lineage_function_output ON file_lineage(function_id, output_upload_id);
+
+-- ============= UPLOADS =============
+
+CREATE INDEX IF NOT EXISTS idx_uploads_created_at ON uploads(created_at);
@@ -0,0 +1,11 @@
+-- Tell data files apart from logs the system writes, such as the error log of a failed run
+
+-- ============= UPLOADS =============
+
+-- 'data' for files users and functions produce, 'error_log' for logs of failed function runs
+ALTER TABLE uploads ADD COLUMN artifact_type TEXT NOT NULL DEFAULT 'data' CHECK (artifact_type IN ('data', 'error_log'));
+
+UPDATE uploads SET artifact_type = 'error_log'
+WHERE id IN (SELECT output_upload_id FROM file_lineage WHERE success = 0);
+
+CREATE INDEX IF NOT EXISTS idx_uploads_artifact_type ON uploads(artifact_type, created_at);
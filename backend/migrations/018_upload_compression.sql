@@ -0,0 +1,6 @@
+-- Uploads stored compressed on disk (--compress-uploads)
+
+-- ============= UPLOADS =============
+
+-- NULL for files stored as uploaded, 'zstd' for zstd-compressed files
+ALTER TABLE uploads ADD COLUMN compression TEXT CHECK (compression IN ('zstd'));
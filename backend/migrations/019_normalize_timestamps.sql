@@ -0,0 +1,82 @@
+-- Store every timestamp in one form: UTC, millisecond precision, 'Z' suffix
+-- (2024-05-01T12:30:00.000Z). Values of equal width compare as text in chronological order;
+-- earlier rows were written with nanoseconds and '+00:00', and saved views with any offset.
+
+-- ============= TAGS =============
+
+UPDATE tags SET created_at = strftime('%Y-%m-%dT%H:%M:%fZ', created_at)
+WHERE strftime('%Y-%m-%dT%H:%M:%fZ', created_at) IS NOT NULL;
+
+-- ============= UPLOADS =============
+
+UPDATE uploads SET created_at = strftime('%Y-%m-%dT%H:%M:%fZ', created_at)
+WHERE strftime('%Y-%m-%dT%H:%M:%fZ', created_at) IS NOT NULL;
+
+-- ============= FUNCTIONS =============
+
+UPDATE functions SET created_at = strftime('%Y-%m-%dT%H:%M:%fZ', created_at)
+WHERE strftime('%Y-%m-%dT%H:%M:%fZ', created_at) IS NOT NULL;
+
+-- ============= JOBS =============
+
+UPDATE jobs SET created_at = strftime('%Y-%m-%dT%H:%M:%fZ', created_at)
+WHERE strftime('%Y-%m-%dT%H:%M:%fZ', created_at) IS NOT NULL;
+UPDATE jobs SET started_at = strftime('%Y-%m-%dT%H:%M:%fZ', started_at)
+WHERE strftime('%Y-%m-%dT%H:%M:%fZ', started_at) IS NOT NULL;
+UPDATE jobs SET completed_at = strftime('%Y-%m-%dT%H:%M:%fZ', completed_at)
+WHERE strftime('%Y-%m-%dT%H:%M:%fZ', completed_at) IS NOT NULL;
+
+-- ============= VIEWS =============
+
+UPDATE views SET created_after = strftime('%Y-%m-%dT%H:%M:%fZ', created_after)
+WHERE strftime('%Y-%m-%dT%H:%M:%fZ', created_after) IS NOT NULL;
+UPDATE views SET created_before = strftime('%Y-%m-%dT%H:%M:%fZ', created_before)
+WHERE strftime('%Y-%m-%dT%H:%M:%fZ', created_before) IS NOT NULL;
+UPDATE views SET created_at = strftime('%Y-%m-%dT%H:%M:%fZ', created_at)
+WHERE strftime('%Y-%m-%dT%H:%M:%fZ', created_at) IS NOT NULL;
+
+-- ============= FILE LINEAGE =============
+
+UPDATE file_lineage SET created_at = strftime('%Y-%m-%dT%H:%M:%fZ', created_at)
+WHERE strftime('%Y-%m-%dT%H:%M:%fZ', created_at) IS NOT NULL;
+
+-- ============= REPORT TEMPLATES =============
+
+UPDATE report_templates SET last_rendered_at = strftime('%Y-%m-%dT%H:%M:%fZ', last_rendered_at)
+WHERE strftime('%Y-%m-%dT%H:%M:%fZ', last_rendered_at) IS NOT NULL;
+UPDATE report_templates SET created_at = strftime('%Y-%m-%dT%H:%M:%fZ', created_at)
+WHERE strftime('%Y-%m-%dT%H:%M:%fZ', created_at) IS NOT NULL;
+
+-- ============= NOTIFICATIONS =============
+
+UPDATE notifications SET read_at = strftime('%Y-%m-%dT%H:%M:%fZ', read_at)
+WHERE strftime('%Y-%m-%dT%H:%M:%fZ', read_at) IS NOT NULL;
+UPDATE notifications SET created_at = strftime('%Y-%m-%dT%H:%M:%fZ', created_at)
+WHERE strftime('%Y-%m-%dT%H:%M:%fZ', created_at) IS NOT NULL;
+
+-- ============= REVIEW QUEUES =============
+
+UPDATE review_queues SET created_at = strftime('%Y-%m-%dT%H:%M:%fZ', created_at)
+WHERE strftime('%Y-%m-%dT%H:%M:%fZ', created_at) IS NOT NULL;
+
+-- ============= REVIEWS =============
+
+UPDATE reviews SET created_at = strftime('%Y-%m-%dT%H:%M:%fZ', created_at)
+WHERE strftime('%Y-%m-%dT%H:%M:%fZ', created_at) IS NOT NULL;
+
+-- ============= RETENTION RULES =============
+
+UPDATE retention_rules SET created_at = strftime('%Y-%m-%dT%H:%M:%fZ', created_at)
+WHERE strftime('%Y-%m-%dT%H:%M:%fZ', created_at) IS NOT NULL;
+
+-- ============= RETENTION PURGES =============
+
+UPDATE retention_purges SET uploaded_at = strftime('%Y-%m-%dT%H:%M:%fZ', uploaded_at)
+WHERE strftime('%Y-%m-%dT%H:%M:%fZ', uploaded_at) IS NOT NULL;
+UPDATE retention_purges SET purged_at = strftime('%Y-%m-%dT%H:%M:%fZ', purged_at)
+WHERE strftime('%Y-%m-%dT%H:%M:%fZ', purged_at) IS NOT NULL;
+
+-- ============= STORAGE QUOTAS =============
+
+UPDATE storage_quotas SET created_at = strftime('%Y-%m-%dT%H:%M:%fZ', created_at)
+WHERE strftime('%Y-%m-%dT%H:%M:%fZ', created_at) IS NOT NULL;
@@ -0,0 +1,18 @@
+-- Files from clients that the malware scan flagged, kept aside instead of becoming uploads
+
+-- ============= QUARANTINE =============
+
+CREATE TABLE IF NOT EXISTS quarantined_uploads (
+    id TEXT PRIMARY KEY NOT NULL,
+    filename TEXT NOT NULL,          -- name in the quarantine directory
+    original_filename TEXT NOT NULL,
+    file_size INTEGER NOT NULL,
+    mime_type TEXT,
+    sha256 TEXT NOT NULL,
+    status TEXT NOT NULL CHECK (status IN ('infected', 'scan_failed')),
+    finding TEXT NOT NULL,           -- what the scanner found, or why it could not scan
+    tag_ids TEXT NOT NULL,           -- JSON array of the tags the upload asked for
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_quarantined_uploads_created_at ON quarantined_uploads(created_at);
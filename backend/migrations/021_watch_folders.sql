@@ -0,0 +1,15 @@
+-- Files picked up from watch folders, so each is registered once (and again only if it changes)
+
+-- ============= WATCH FOLDERS =============
+
+CREATE TABLE IF NOT EXISTS watched_files (
+    path TEXT PRIMARY KEY NOT NULL,
+    file_size INTEGER NOT NULL,
+    modified_at TEXT NOT NULL,       -- the file's modification time when it was picked up
+    status TEXT NOT NULL CHECK (status IN ('ingested', 'quarantined', 'rejected')),
+    upload_id TEXT,                  -- set for ingested files; kept after the upload is deleted
+    message TEXT,                    -- why the file was quarantined or rejected
+    picked_up_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_watched_files_picked_up_at ON watched_files(picked_up_at);
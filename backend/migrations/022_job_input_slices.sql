@@ -0,0 +1,5 @@
+-- Trial runs of a function on part of its input
+
+-- ============= JOBS =============
+
+ALTER TABLE jobs ADD COLUMN input_slice TEXT; -- JSON, e.g. {"unit": "rows", "start": 0, "end": 1000}; NULL for whole files
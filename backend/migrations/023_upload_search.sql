@@ -0,0 +1,85 @@
+-- Full-text search over uploads: filenames, tag names and metadata, ranked with bm25
+
+-- ============= UPLOAD SEARCH =============
+
+-- One row per upload; only the FTS index is used, rows are looked up by upload_id
+CREATE VIRTUAL TABLE IF NOT EXISTS uploads_fts USING fts5(
+    upload_id UNINDEXED,
+    filename,
+    tags,
+    metadata, -- MIME types, assignee and data dictionary columns, descriptions and units
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
+-- What gets indexed for an upload
+CREATE VIEW IF NOT EXISTS upload_search_documents AS
+SELECT
+    u.id AS upload_id,
+    u.original_filename AS filename,
+    COALESCE((SELECT group_concat(t.name, ' ')
+              FROM upload_tags ut JOIN tags t ON t.id = ut.tag_id
+              WHERE ut.upload_id = u.id), '') AS tags,
+    COALESCE(u.mime_type, '') || ' ' || COALESCE(u.detected_mime_type, '') || ' ' ||
+    COALESCE(u.assignee, '') || ' ' ||
+    COALESCE((SELECT group_concat(cd.column_name || ' ' || COALESCE(cd.description, '') || ' ' ||
+                                  COALESCE(cd.unit, ''), ' ')
+              FROM column_dictionary cd
+              WHERE cd.upload_id = u.id), '') AS metadata
+FROM uploads u;
+
+INSERT INTO uploads_fts (upload_id, filename, tags, metadata)
+SELECT upload_id, filename, tags, metadata FROM upload_search_documents;
+
+-- Keep the index in step with uploads, their tags and their data dictionaries
+CREATE TRIGGER IF NOT EXISTS uploads_fts_insert AFTER INSERT ON uploads BEGIN
+    INSERT INTO uploads_fts (upload_id, filename, tags, metadata)
+    SELECT upload_id, filename, tags, metadata FROM upload_search_documents WHERE upload_id = NEW.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS uploads_fts_update AFTER UPDATE ON uploads BEGIN
+    DELETE FROM uploads_fts WHERE upload_id = OLD.id;
+    INSERT INTO uploads_fts (upload_id, filename, tags, metadata)
+    SELECT upload_id, filename, tags, metadata FROM upload_search_documents WHERE upload_id = NEW.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS uploads_fts_delete AFTER DELETE ON uploads BEGIN
+    DELETE FROM uploads_fts WHERE upload_id = OLD.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS uploads_fts_tag_added AFTER INSERT ON upload_tags BEGIN
+    DELETE FROM uploads_fts WHERE upload_id = NEW.upload_id;
+    INSERT INTO uploads_fts (upload_id, filename, tags, metadata)
+    SELECT upload_id, filename, tags, metadata FROM upload_search_documents WHERE upload_id = NEW.upload_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS uploads_fts_tag_removed AFTER DELETE ON upload_tags BEGIN
+    DELETE FROM uploads_fts WHERE upload_id = OLD.upload_id;
+    INSERT INTO uploads_fts (upload_id, filename, tags, metadata)
+    SELECT upload_id, filename, tags, metadata FROM upload_search_documents WHERE upload_id = OLD.upload_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS uploads_fts_tag_renamed AFTER UPDATE OF name ON tags BEGIN
+    DELETE FROM uploads_fts
+    WHERE upload_id IN (SELECT upload_id FROM upload_tags WHERE tag_id = NEW.id);
+    INSERT INTO uploads_fts (upload_id, filename, tags, metadata)
+    SELECT upload_id, filename, tags, metadata FROM upload_search_documents
+    WHERE upload_id IN (SELECT upload_id FROM upload_tags WHERE tag_id = NEW.id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS uploads_fts_dictionary_insert AFTER INSERT ON column_dictionary BEGIN
+    DELETE FROM uploads_fts WHERE upload_id = NEW.upload_id;
+    INSERT INTO uploads_fts (upload_id, filename, tags, metadata)
+    SELECT upload_id, filename, tags, metadata FROM upload_search_documents WHERE upload_id = NEW.upload_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS uploads_fts_dictionary_update AFTER UPDATE ON column_dictionary BEGIN
+    DELETE FROM uploads_fts WHERE upload_id = NEW.upload_id;
+    INSERT INTO uploads_fts (upload_id, filename, tags, metadata)
+    SELECT upload_id, filename, tags, metadata FROM upload_search_documents WHERE upload_id = NEW.upload_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS uploads_fts_dictionary_delete AFTER DELETE ON column_dictionary BEGIN
+    DELETE FROM uploads_fts WHERE upload_id = OLD.upload_id;
+    INSERT INTO uploads_fts (upload_id, filename, tags, metadata)
+    SELECT upload_id, filename, tags, metadata FROM upload_search_documents WHERE upload_id = OLD.upload_id;
+END;
@@ -0,0 +1,15 @@
+-- Full-text index of the contents of small text uploads (CSV, JSON, logs...), for finding the
+-- files that mention a string such as a device serial number
+
+-- ============= UPLOAD CONTENTS =============
+
+-- Filled by the server when a text upload at most --content-index-max-mb large is created
+CREATE VIRTUAL TABLE IF NOT EXISTS upload_contents_fts USING fts5(
+    upload_id UNINDEXED,
+    content,
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
+CREATE TRIGGER IF NOT EXISTS upload_contents_fts_delete AFTER DELETE ON uploads BEGIN
+    DELETE FROM upload_contents_fts WHERE upload_id = OLD.id;
+END;
@@ -0,0 +1,7 @@
+-- Quick functions: a SQL query over the input, run by the server itself instead of a script
+
+-- ============= QUICK FUNCTIONS =============
+
+-- A SELECT over the input file as table `data`; NULL for script functions. Quick functions
+-- have no script file, their script_filename is empty.
+ALTER TABLE functions ADD COLUMN expression TEXT;
@@ -0,0 +1,12 @@
+-- Functions disabled automatically because their runs kept failing
+
+-- ============= FUNCTION QUARANTINE =============
+
+-- Set when the health check disabled the function, with the failures that made it do so;
+-- cleared when someone enables the function again
+ALTER TABLE functions ADD COLUMN quarantined_at TEXT;
+ALTER TABLE functions ADD COLUMN quarantine_reason TEXT;
+
+-- Runs that completed before this do not count towards the health check, so a function
+-- enabled again after a fix starts with a clean slate
+ALTER TABLE functions ADD COLUMN health_since TEXT;
@@ -0,0 +1,16 @@
+-- Notes on jobs with a triage label, e.g. `flaky` for a failure caused by an instrument glitch
+
+-- ============= JOB ANNOTATIONS =============
+
+CREATE TABLE IF NOT EXISTS job_annotations (
+    id TEXT PRIMARY KEY,
+    job_id TEXT NOT NULL,
+    label TEXT NOT NULL, -- lowercase, e.g. `flaky` or `fixed-in-v3`
+    note TEXT,
+    author TEXT, -- free-form name, like assignees
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (job_id) REFERENCES jobs(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_job_annotations_job ON job_annotations(job_id);
+CREATE INDEX IF NOT EXISTS idx_job_annotations_label ON job_annotations(label);
@@ -0,0 +1,7 @@
+-- Uploads that cannot be deleted, e.g. reference calibration files
+
+-- ============= PROTECTED UPLOADS =============
+
+-- Deleting a protected upload is refused, and retention rules pass it over, until someone
+-- clears the flag again
+ALTER TABLE uploads ADD COLUMN protected INTEGER NOT NULL DEFAULT 0;
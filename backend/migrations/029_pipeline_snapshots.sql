@@ -0,0 +1,26 @@
+-- Named snapshots of the whole pipeline: functions, tags and the wiring between them
+
+-- ============= PIPELINE SNAPSHOTS =============
+
+CREATE TABLE IF NOT EXISTS pipeline_snapshots (
+    id TEXT PRIMARY KEY,
+    label TEXT NOT NULL UNIQUE,
+    description TEXT,
+    pipeline TEXT NOT NULL, -- JSON: functions with their script hashes, tags and trigger wiring
+    created_at TEXT NOT NULL,
+    activated_at TEXT NOT NULL -- taken or last restored; the latest one is the active snapshot
+);
+
+CREATE INDEX IF NOT EXISTS idx_pipeline_snapshots_activated_at ON pipeline_snapshots(activated_at);
+
+-- Script contents by hash, shared by all snapshots, so that any snapshot can be restored
+-- after the script files were edited or cleaned up
+CREATE TABLE IF NOT EXISTS snapshot_scripts (
+    sha256 TEXT PRIMARY KEY,
+    content TEXT NOT NULL
+);
+
+-- The snapshot that was active when the job was queued
+ALTER TABLE jobs ADD COLUMN snapshot_id TEXT REFERENCES pipeline_snapshots(id) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS idx_jobs_snapshot_id ON jobs(snapshot_id);
@@ -0,0 +1,28 @@
+-- Releases: frozen, checksummed sets of uploads, e.g. the supporting data of a paper
+
+-- ============= RELEASES =============
+
+CREATE TABLE IF NOT EXISTS releases (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    identifier TEXT UNIQUE, -- DOI-style, e.g. 10.5281/zenodo.1234567; may be set once later
+    description TEXT,
+    selection TEXT NOT NULL, -- JSON: the upload IDs and tag expression the files were picked by
+    checksum TEXT NOT NULL,  -- SHA-256 of the SHA256SUMS listing of the files
+    created_at TEXT NOT NULL
+);
+
+-- The files as they were released; the uploads are protected so they stay
+CREATE TABLE IF NOT EXISTS release_files (
+    release_id TEXT NOT NULL,
+    upload_id TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    name TEXT NOT NULL, -- entry name in the bundle, unique within the release
+    sha256 TEXT NOT NULL,
+    file_size INTEGER NOT NULL,
+    PRIMARY KEY (release_id, upload_id),
+    FOREIGN KEY (release_id) REFERENCES releases(id) ON DELETE CASCADE,
+    FOREIGN KEY (upload_id) REFERENCES uploads(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_release_files_upload_id ON release_files(upload_id);
@@ -0,0 +1,19 @@
+-- Share links: public tokens that give read access to a single upload
+
+-- ============= SHARE LINKS =============
+
+-- Revoked links are kept, so their access counts stay visible; the token is the whole secret
+CREATE TABLE IF NOT EXISTS share_links (
+    token TEXT PRIMARY KEY,
+    upload_id TEXT NOT NULL,
+    label TEXT, -- who or what the link was made for
+    created_at TEXT NOT NULL,
+    expires_at TEXT,
+    revoked_at TEXT,
+    access_count INTEGER NOT NULL DEFAULT 0, -- metadata, preview and download requests
+    download_count INTEGER NOT NULL DEFAULT 0,
+    last_accessed_at TEXT,
+    FOREIGN KEY (upload_id) REFERENCES uploads(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_share_links_upload_id ON share_links(upload_id);
@@ -0,0 +1,32 @@
+-- Chain of custody: who sent each upload, and what was done to it since
+
+-- ============= UPLOAD ORIGINS =============
+
+-- How an upload came in; files made by functions and operations have lineage instead
+CREATE TABLE IF NOT EXISTS upload_origins (
+    upload_id TEXT PRIMARY KEY,
+    received_via TEXT NOT NULL, -- api, url or webdav
+    source_url TEXT,            -- for uploads fetched from a URL
+    uploaded_by TEXT,           -- as the client stated it in X-DataLab-User
+    client_ip TEXT,
+    forwarded_for TEXT,
+    user_agent TEXT,
+    signature TEXT,             -- as the client sent it, over the uploaded bytes
+    FOREIGN KEY (upload_id) REFERENCES uploads(id) ON DELETE CASCADE
+);
+
+-- ============= UPLOAD AUDIT =============
+
+-- Changes made to an upload through the API, e.g. renamed, tagged, protected or shared
+CREATE TABLE IF NOT EXISTS upload_audit (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    upload_id TEXT NOT NULL,
+    action TEXT NOT NULL,
+    detail TEXT,
+    actor TEXT,
+    client_ip TEXT,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (upload_id) REFERENCES uploads(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_upload_audit_upload_id ON upload_audit(upload_id);
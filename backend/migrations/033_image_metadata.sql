@@ -0,0 +1,9 @@
+-- Dimensions and EXIF/TIFF tags of image uploads, read once when they are stored
+CREATE TABLE IF NOT EXISTS upload_image_metadata (
+    upload_id TEXT PRIMARY KEY,
+    format TEXT NOT NULL, -- png, jpg or tiff
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    exif TEXT NOT NULL,   -- JSON object by tag name
+    FOREIGN KEY (upload_id) REFERENCES uploads(id) ON DELETE CASCADE
+);
@@ -0,0 +1,23 @@
+-- Datasets: named groups of uploads, e.g. the files of one sweep, handled as a unit
+
+-- ============= DATASETS =============
+
+CREATE TABLE IF NOT EXISTS datasets (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    description TEXT,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL -- last change to the name, description or members
+);
+
+-- An upload can be in any number of datasets; deleting either side drops the membership
+CREATE TABLE IF NOT EXISTS dataset_uploads (
+    dataset_id TEXT NOT NULL,
+    upload_id TEXT NOT NULL,
+    added_at TEXT NOT NULL,
+    PRIMARY KEY (dataset_id, upload_id),
+    FOREIGN KEY (dataset_id) REFERENCES datasets(id) ON DELETE CASCADE,
+    FOREIGN KEY (upload_id) REFERENCES uploads(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_dataset_uploads_upload_id ON dataset_uploads(upload_id);
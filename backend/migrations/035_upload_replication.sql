@@ -0,0 +1,11 @@
+-- Whether each upload's stored file was copied to the --mirror-dir
+CREATE TABLE IF NOT EXISTS upload_replication (
+    upload_id TEXT PRIMARY KEY,
+    status TEXT NOT NULL, -- pending, mirrored or failed
+    error TEXT,           -- why the last copy failed
+    updated_at TEXT NOT NULL,
+    FOREIGN KEY (upload_id) REFERENCES uploads(id) ON DELETE CASCADE,
+    CHECK (status IN ('pending', 'mirrored', 'failed'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_upload_replication_status ON upload_replication(status);
@@ -0,0 +1,3 @@
+-- What a tag means, e.g. which checks an upload tagged "validated" has passed
+
+ALTER TABLE tags ADD COLUMN description TEXT;
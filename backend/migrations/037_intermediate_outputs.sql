@@ -0,0 +1,15 @@
+-- Intermediate outputs: files a function produces along the way (checkpoints, partial
+-- results) that are kept for a while for debugging, then deleted
+
+-- ============= FUNCTIONS =============
+
+-- JSON array of filename patterns (`*` and `?`) marking outputs as intermediate
+ALTER TABLE functions ADD COLUMN intermediate_outputs TEXT NOT NULL DEFAULT '[]';
+ALTER TABLE functions ADD COLUMN intermediate_ttl_hours INTEGER NOT NULL DEFAULT 24 CHECK (intermediate_ttl_hours > 0);
+
+-- ============= UPLOADS =============
+
+-- Set for intermediate outputs only: when the retention sweeper deletes them
+ALTER TABLE uploads ADD COLUMN expires_at TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_uploads_expires_at ON uploads(expires_at);
@@ -0,0 +1,14 @@
+-- Tags that keep a function from running on an upload, e.g. "processed", on top of the input
+-- tags it requires
+
+-- ============= FUNCTIONS =============
+
+CREATE TABLE IF NOT EXISTS function_excluded_tags (
+    function_id TEXT NOT NULL,
+    tag_id TEXT NOT NULL,
+    PRIMARY KEY (function_id, tag_id),
+    FOREIGN KEY (function_id) REFERENCES functions(id) ON DELETE CASCADE,
+    FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_function_excluded_tags_tag_id ON function_excluded_tags(tag_id);
@@ -0,0 +1,8 @@
+-- Other DataLab instances whose uploads can be browsed and imported
+
+CREATE TABLE IF NOT EXISTS remotes (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    base_url TEXT NOT NULL, -- where the remote is served, without /api
+    created_at TEXT NOT NULL
+);
@@ -0,0 +1,12 @@
+-- Restricted tags: uploads carrying one are only visible to the users and roles listed for it,
+-- e.g. "patient-data". A tag without rows here is not restricted.
+
+-- ============= TAGS =============
+
+CREATE TABLE IF NOT EXISTS tag_access (
+    tag_id TEXT NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('user', 'role')),
+    name TEXT NOT NULL, -- as sent in X-DataLab-User or X-DataLab-Roles
+    PRIMARY KEY (tag_id, kind, name),
+    FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE
+);
@@ -0,0 +1,5 @@
+-- Saved views can also filter on the original filename, with a glob such as "*.s2p"
+
+-- ============= VIEWS =============
+
+ALTER TABLE views ADD COLUMN name_pattern TEXT; -- `*` and `?` wildcards, case-insensitive
@@ -0,0 +1,32 @@
+-- Import manifests: files to copy in from URLs or server paths, imported one row at a time
+-- in the background with the outcome of each row kept
+
+-- ============= IMPORT BATCHES =============
+
+CREATE TABLE IF NOT EXISTS import_batches (
+    id TEXT PRIMARY KEY,
+    status TEXT NOT NULL CHECK (status IN ('running', 'completed')),
+    created_by TEXT, -- as the client stated it in X-DataLab-User
+    client_ip TEXT,
+    created_at TEXT NOT NULL,
+    finished_at TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_import_batches_created_at ON import_batches(created_at);
+
+-- ============= IMPORT ROWS =============
+
+CREATE TABLE IF NOT EXISTS import_rows (
+    batch_id TEXT NOT NULL,
+    row_number INTEGER NOT NULL, -- from 1, in manifest order
+    source TEXT NOT NULL,        -- an http(s) URL or a path under one of the import directories
+    filename TEXT,               -- instead of the name the source gives
+    tag_ids TEXT NOT NULL,       -- JSON array
+    assignee TEXT,
+    metadata TEXT NOT NULL,      -- JSON object, recorded in the custody trail of the upload
+    status TEXT NOT NULL CHECK (status IN ('pending', 'imported', 'failed', 'quarantined')),
+    upload_id TEXT,              -- set for imported rows; kept after the upload is deleted
+    message TEXT,                -- why the row failed or was quarantined
+    PRIMARY KEY (batch_id, row_number),
+    FOREIGN KEY (batch_id) REFERENCES import_batches(id) ON DELETE CASCADE
+);
@@ -0,0 +1,138 @@
+//! Tag-driven access restrictions. An upload carrying a restricted tag is only listed, shown,
+//! downloaded and previewed for the users and roles the tag names. DataLab has no accounts, so
+//! the requester is whoever `X-DataLab-User` and `X-DataLab-Roles` say; restrictions only hold
+//! behind a proxy that authenticates clients and sets (or strips) those headers.
+
+use crate::custody::USER_HEADER;
+use axum::http::HeaderMap;
+use serde::{Deserialize, Serialize};
+
+pub const ROLES_HEADER: &str = "x-datalab-roles";
+
+/// Who a restricted tag's uploads are visible to; both empty means the tag is not restricted
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TagAccess {
+    #[serde(default)]
+    pub users: Vec<String>,
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+impl TagAccess {
+    pub fn is_restricted(&self) -> bool {
+        !self.users.is_empty() || !self.roles.is_empty()
+    }
+
+    /// The same users and roles trimmed, each once, in order; Err names an invalid one
+    pub fn normalized(&self) -> Result<Self, String> {
+        Ok(Self {
+            users: normalize_names(&self.users)?,
+            roles: normalize_names(&self.roles)?,
+        })
+    }
+}
+
+fn normalize_names(names: &[String]) -> Result<Vec<String>, String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for name in names {
+        let name = name.trim();
+        if name.is_empty() || name.contains(',') {
+            return Err(format!(
+                "Invalid user or role {:?}; names are non-empty and without commas",
+                name
+            ));
+        }
+        if !normalized.iter().any(|seen| seen == name) {
+            normalized.push(name.to_string());
+        }
+    }
+    Ok(normalized)
+}
+
+/// The user and roles a request is made with
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Requester {
+    pub user: Option<String>,
+    pub roles: Vec<String>,
+}
+
+impl Requester {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let text = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+        Self {
+            user: text(USER_HEADER)
+                .map(str::trim)
+                .filter(|user| !user.is_empty())
+                .map(str::to_string),
+            roles: text(ROLES_HEADER)
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|role| !role.is_empty())
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+
+    /// Whether the uploads of a tag with this access are visible to the requester
+    pub fn is_allowed(&self, access: &TagAccess) -> bool {
+        !access.is_restricted()
+            || self
+                .user
+                .as_ref()
+                .is_some_and(|user| access.users.contains(user))
+            || self.roles.iter().any(|role| access.roles.contains(role))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn access(users: &[&str], roles: &[&str]) -> TagAccess {
+        TagAccess {
+            users: users.iter().map(|user| user.to_string()).collect(),
+            roles: roles.iter().map(|role| role.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_requester_from_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(USER_HEADER, HeaderValue::from_static(" alice "));
+        headers.insert(ROLES_HEADER, HeaderValue::from_static("clinician, ,admin"));
+        assert_eq!(
+            Requester::from_headers(&headers),
+            Requester {
+                user: Some("alice".to_string()),
+                roles: vec!["clinician".to_string(), "admin".to_string()],
+            }
+        );
+        assert_eq!(
+            Requester::from_headers(&HeaderMap::new()),
+            Requester::default()
+        );
+    }
+
+    #[test]
+    fn test_restricted_tags_allow_listed_users_and_roles() {
+        let alice = Requester {
+            user: Some("alice".to_string()),
+            roles: vec!["student".to_string()],
+        };
+        assert!(alice.is_allowed(&TagAccess::default()));
+        assert!(alice.is_allowed(&access(&["alice"], &[])));
+        assert!(alice.is_allowed(&access(&["bob"], &["student"])));
+        assert!(!alice.is_allowed(&access(&["bob"], &["clinician"])));
+        assert!(!Requester::default().is_allowed(&access(&[], &["clinician"])));
+    }
+
+    #[test]
+    fn test_access_is_normalized() {
+        let normalized = access(&[" alice", "alice"], &["clinician"]).normalized();
+        assert_eq!(normalized, Ok(access(&["alice"], &["clinician"])));
+        assert!(access(&[""], &[]).normalized().is_err());
+        assert!(access(&[], &["a,b"]).normalized().is_err());
+    }
+}
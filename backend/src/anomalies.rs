@@ -0,0 +1,194 @@
+use crate::table_parser::scan_table;
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub const ANOMALY_TAG: &str = "has-anomalies";
+
+// Row indices listed per column in a report; the counts are always complete
+const MAX_FLAGGED_ROWS: usize = 100;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutlierMethod {
+    #[default]
+    Zscore,
+    Iqr,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct AnomalyQuery {
+    #[serde(default)]
+    pub method: OutlierMethod,
+    pub threshold: Option<f64>, // |z| above this (default 3), or IQRs beyond the quartiles (default 1.5)
+}
+
+impl AnomalyQuery {
+    pub fn threshold(&self) -> f64 {
+        self.threshold.unwrap_or(match self.method {
+            OutlierMethod::Zscore => 3.0,
+            OutlierMethod::Iqr => 1.5,
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ColumnOutliers {
+    pub column: String,
+    pub count: usize, // non-null values
+    pub mean: f64,
+    pub std: f64,
+    pub q1: f64,
+    pub median: f64,
+    pub q3: f64,
+    pub lower_bound: f64,
+    pub upper_bound: f64,
+    pub outliers: usize,
+    pub rows: Vec<usize>, // zero-based row indices of the first outliers
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnomalyReport {
+    pub method: OutlierMethod,
+    pub threshold: f64,
+    pub total_rows: usize,
+    pub has_anomalies: bool,
+    pub columns: Vec<ColumnOutliers>,
+}
+
+/// Linearly interpolated quantile of sorted values
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let position = q * (sorted.len() - 1) as f64;
+    let lower = position.floor() as usize;
+    let upper = position.ceil() as usize;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (position - lower as f64)
+}
+
+/// Flag values outside `[lower_bound, upper_bound]` for one column of (row, value) pairs
+fn column_outliers(
+    column: &str,
+    values: &[(usize, f64)],
+    method: OutlierMethod,
+    threshold: f64,
+) -> Option<ColumnOutliers> {
+    if values.is_empty() {
+        return None;
+    }
+
+    let count = values.len();
+    let mean = values.iter().map(|(_, v)| v).sum::<f64>() / count as f64;
+    let variance =
+        values.iter().map(|(_, v)| (v - mean).powi(2)).sum::<f64>() / (count.max(2) - 1) as f64;
+    let std = variance.sqrt();
+
+    let mut sorted: Vec<f64> = values.iter().map(|(_, v)| *v).collect();
+    sorted.sort_by(f64::total_cmp);
+    let (q1, median, q3) = (
+        quantile(&sorted, 0.25),
+        quantile(&sorted, 0.5),
+        quantile(&sorted, 0.75),
+    );
+
+    let (lower_bound, upper_bound) = match method {
+        OutlierMethod::Zscore => (mean - threshold * std, mean + threshold * std),
+        OutlierMethod::Iqr => (q1 - threshold * (q3 - q1), q3 + threshold * (q3 - q1)),
+    };
+
+    let flagged: Vec<usize> = values
+        .iter()
+        .filter(|(_, v)| *v < lower_bound || *v > upper_bound)
+        .map(|(row, _)| *row)
+        .collect();
+
+    Some(ColumnOutliers {
+        column: column.to_string(),
+        count,
+        mean,
+        std,
+        q1,
+        median,
+        q3,
+        lower_bound,
+        upper_bound,
+        outliers: flagged.len(),
+        rows: flagged.into_iter().take(MAX_FLAGGED_ROWS).collect(),
+    })
+}
+
+/// Compute outlier statistics for every numeric column of a table
+pub fn detect_anomalies(df: &DataFrame, query: &AnomalyQuery) -> PolarsResult<AnomalyReport> {
+    let threshold = query.threshold();
+    let mut columns = Vec::new();
+
+    for column in df.get_columns() {
+        if !column.dtype().is_primitive_numeric() {
+            continue;
+        }
+        let series = column.as_materialized_series().cast(&DataType::Float64)?;
+        let values: Vec<(usize, f64)> = series
+            .f64()?
+            .iter()
+            .enumerate()
+            .filter_map(|(row, value)| value.filter(|v| v.is_finite()).map(|v| (row, v)))
+            .collect();
+        columns.extend(column_outliers(
+            column.name(),
+            &values,
+            query.method,
+            threshold,
+        ));
+    }
+
+    Ok(AnomalyReport {
+        method: query.method,
+        threshold,
+        total_rows: df.height(),
+        has_anomalies: columns.iter().any(|c| c.outliers > 0),
+        columns,
+    })
+}
+
+pub fn detect_table_anomalies(
+    file_path: &str,
+    file_extension: &str,
+    query: &AnomalyQuery,
+) -> Result<AnomalyReport, Box<dyn std::error::Error>> {
+    let df = scan_table(file_path, &file_extension.to_lowercase())?.collect()?;
+    Ok(detect_anomalies(&df, query)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> DataFrame {
+        df!(
+            "id" => (0i64..20).collect::<Vec<_>>(),
+            "value" => (0..20).map(|i| if i == 7 { 100.0 } else { 10.0 + (i % 3) as f64 }).collect::<Vec<_>>(),
+            "site" => (0..20).map(|_| "north").collect::<Vec<_>>(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_flags_outliers_per_numeric_column() {
+        let report = detect_anomalies(&table(), &AnomalyQuery::default()).unwrap();
+        assert!(report.has_anomalies);
+        assert_eq!(report.columns.len(), 2);
+        assert_eq!(report.columns[0].outliers, 0);
+        assert_eq!(report.columns[1].column, "value");
+        assert_eq!(report.columns[1].rows, vec![7]);
+    }
+
+    #[test]
+    fn test_iqr_bounds() {
+        let query = AnomalyQuery {
+            method: OutlierMethod::Iqr,
+            threshold: None,
+        };
+        let report = detect_anomalies(&table(), &query).unwrap();
+        let value = &report.columns[1];
+        assert_eq!((value.q1, value.median, value.q3), (10.0, 11.0, 12.0));
+        assert_eq!((value.lower_bound, value.upper_bound), (7.0, 15.0));
+        assert_eq!(value.rows, vec![7]);
+    }
+}
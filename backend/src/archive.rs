@@ -0,0 +1,292 @@
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use tokio::sync::mpsc::Sender;
+use zip::write::SimpleFileOptions;
+use zip::CompressionMethod;
+
+/// A stored file to include in an archive
+pub struct ArchiveEntry {
+    pub name: String,
+    pub path: PathBuf,
+    pub created_at: String,
+}
+
+/// Chunks of the archive as they are produced, in the shape `Body::from_stream` expects
+pub type ArchiveChunk = Result<Vec<u8>, io::Error>;
+
+// Formats that are already compressed; deflating them again only costs CPU
+const STORED_EXTENSIONS: &[&str] = &[
+    "zip", "gz", "bz2", "xz", "zst", "7z", "parquet", "png", "jpg", "jpeg", "gif", "webp", "mp3",
+    "mp4", "flac", "npz", "h5",
+];
+
+/// Sink that lets `ZipWriter` (which needs `Seek`) write to a channel. Bytes of the entry being
+/// written stay buffered so its local header can still be patched, and are sent on `flush`,
+/// which the writer calls once an entry is complete.
+struct ChannelSink {
+    tx: Sender<ArchiveChunk>,
+    sent: u64,
+    buffer: Vec<u8>,
+    position: u64,
+}
+
+impl ChannelSink {
+    fn new(tx: Sender<ArchiveChunk>) -> Self {
+        Self {
+            tx,
+            sent: 0,
+            buffer: Vec::new(),
+            position: 0,
+        }
+    }
+}
+
+impl Write for ChannelSink {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let offset = (self.position - self.sent) as usize;
+        let end = offset + data.len();
+        if end > self.buffer.len() {
+            self.buffer.resize(end, 0);
+        }
+        self.buffer[offset..end].copy_from_slice(data);
+        self.position += data.len() as u64;
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::take(&mut self.buffer);
+        self.sent += chunk.len() as u64;
+        self.position = self.sent;
+        self.tx
+            .blocking_send(Ok(chunk))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "client went away"))
+    }
+}
+
+// Only needed to satisfy the bounds of `set_flush_on_finish_file`; zip reads back solely
+// when appending or copying entries, which we never do
+impl Read for ChannelSink {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "archive stream is write-only",
+        ))
+    }
+}
+
+impl Seek for ChannelSink {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let end = self.sent + self.buffer.len() as u64;
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => end.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+        };
+        match target {
+            Some(target) if target >= self.sent => {
+                self.position = target;
+                Ok(target)
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "cannot seek into data that was already streamed",
+            )),
+        }
+    }
+}
+
+/// Make entry names safe and distinct: path separators are replaced so nothing extracts
+/// outside the target directory, and repeated names become `name (2).ext`, `name (3).ext`, ...
+pub fn unique_entry_names<'a>(names: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    names
+        .into_iter()
+        .map(|name| {
+            let name = name.replace(['/', '\\'], "_");
+            let name = if name.is_empty() || name == "." || name == ".." {
+                "file".to_string()
+            } else {
+                name
+            };
+            let (stem, extension) = match name.rsplit_once('.') {
+                Some((stem, extension)) if !stem.is_empty() => {
+                    (stem.to_string(), format!(".{}", extension))
+                }
+                _ => (name.clone(), String::new()),
+            };
+            let mut candidate = name;
+            let mut counter = 2;
+            while !seen.insert(candidate.to_lowercase()) {
+                candidate = format!("{} ({}){}", stem, counter, extension);
+                counter += 1;
+            }
+            candidate
+        })
+        .collect()
+}
+
+fn entry_options(entry: &ArchiveEntry, size: u64) -> SimpleFileOptions {
+    let extension = entry.name.rsplit('.').next().unwrap_or("").to_lowercase();
+    let method = if STORED_EXTENSIONS.contains(&extension.as_str()) {
+        CompressionMethod::Stored
+    } else {
+        CompressionMethod::Deflated
+    };
+
+    let mut options = SimpleFileOptions::default()
+        .compression_method(method)
+        .large_file(size >= u32::MAX as u64);
+    if let Ok(created) = entry.created_at.parse::<DateTime<Utc>>() {
+        if let Ok(modified) = zip::DateTime::from_date_and_time(
+            created.year() as u16,
+            created.month() as u8,
+            created.day() as u8,
+            created.hour() as u8,
+            created.minute() as u8,
+            created.second() as u8,
+        ) {
+            options = options.last_modified_time(modified);
+        }
+    }
+    options
+}
+
+/// Write a zip of the given files to `tx`, one entry at a time. Only the entry currently being
+/// compressed is held in memory. Files missing on disk are skipped.
+pub fn write_archive(
+    entries: &[ArchiveEntry],
+    tx: Sender<ArchiveChunk>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    write_archive_with(&[], entries, tx)
+}
+
+/// Like `write_archive`, with generated `(name, content)` entries such as a manifest first
+pub fn write_archive_with(
+    generated: &[(String, Vec<u8>)],
+    entries: &[ArchiveEntry],
+    tx: Sender<ArchiveChunk>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut zip = zip::ZipWriter::new(ChannelSink::new(tx));
+    zip.set_flush_on_finish_file(true);
+
+    for (name, content) in generated {
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        zip.start_file(name.as_str(), options)?;
+        zip.write_all(content)?;
+    }
+    for entry in entries {
+        let mut file = match File::open(&entry.path) {
+            Ok(file) => file,
+            Err(e) => {
+                tracing::warn!("Skipping {} in archive: {}", entry.path.display(), e);
+                continue;
+            }
+        };
+        let size = file.metadata()?.len();
+        zip.start_file(entry.name.as_str(), entry_options(entry, size))?;
+        io::copy(&mut file, &mut zip)?;
+    }
+
+    zip.finish()?.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unique_entry_names() {
+        let names = unique_entry_names(["data.csv", "Data.csv", "a/b.txt", "data.csv", "README"]);
+        assert_eq!(
+            names,
+            [
+                "data.csv",
+                "Data (2).csv",
+                "a_b.txt",
+                "data (3).csv",
+                "README"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_archive_streams_valid_zip() {
+        let dir = std::env::temp_dir().join(format!("datalab-archive-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let csv = dir.join("a.csv");
+        std::fs::write(&csv, "x,y\n1,2\n".repeat(1000)).unwrap();
+        let png = dir.join("b.png");
+        std::fs::write(&png, [0u8, 1, 2, 3]).unwrap();
+
+        let entries = vec![
+            ArchiveEntry {
+                name: "a.csv".into(),
+                path: csv,
+                created_at: "2024-05-01T12:30:00+00:00".into(),
+            },
+            ArchiveEntry {
+                name: "missing.txt".into(),
+                path: dir.join("missing.txt"),
+                created_at: String::new(),
+            },
+            ArchiveEntry {
+                name: "b.png".into(),
+                path: png,
+                created_at: String::new(),
+            },
+        ];
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(64);
+        write_archive(&entries, tx).unwrap();
+        let mut chunks = 0;
+        let mut bytes = Vec::new();
+        while let Ok(chunk) = rx.try_recv() {
+            bytes.extend(chunk.unwrap());
+            chunks += 1;
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(chunks > 1, "archive should arrive in several chunks");
+
+        let mut archive = zip::ZipArchive::new(io::Cursor::new(bytes)).unwrap();
+        assert_eq!(archive.len(), 2);
+        let mut content = String::new();
+        let mut file = archive.by_name("a.csv").unwrap();
+        assert_eq!(file.compression(), CompressionMethod::Deflated);
+        assert_eq!(file.last_modified().unwrap().year(), 2024);
+        file.read_to_string(&mut content).unwrap();
+        assert_eq!(content.len(), 8000);
+        drop(file);
+        assert_eq!(
+            archive.by_name("b.png").unwrap().compression(),
+            CompressionMethod::Stored
+        );
+    }
+
+    #[test]
+    fn test_generated_entries_come_first() {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(64);
+        let generated = vec![("manifest.json".to_string(), b"{}".to_vec())];
+        write_archive_with(&generated, &[], tx).unwrap();
+        let mut bytes = Vec::new();
+        while let Ok(chunk) = rx.try_recv() {
+            bytes.extend(chunk.unwrap());
+        }
+
+        let mut archive = zip::ZipArchive::new(io::Cursor::new(bytes)).unwrap();
+        assert_eq!(archive.len(), 1);
+        let mut content = String::new();
+        archive
+            .by_index(0)
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "{}");
+    }
+}
@@ -0,0 +1,282 @@
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Read;
+
+const NPY_MAGIC: &[u8] = b"\x93NUMPY";
+// Headers are padded to at most a few KB; anything larger is not a sane .npy file
+const MAX_HEADER_LEN: usize = 1 << 20;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArrayInfo {
+    pub name: String,
+    pub descr: String,
+    pub dtype: String,
+    pub shape: Vec<u64>,
+    pub fortran_order: bool,
+    pub element_count: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nbytes: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArrayInspection {
+    pub file_type: String,
+    pub arrays: Vec<ArrayInfo>,
+}
+
+/// Formats that can execute arbitrary code when loaded and are therefore never opened
+pub fn is_unsafe_serialization(extension: &str) -> bool {
+    matches!(
+        extension.to_lowercase().as_str(),
+        "pkl" | "pickle" | "joblib" | "dill"
+    )
+}
+
+/// Inspect a .npy or .npz file without loading array data. Only the textual
+/// header of each array is parsed, so no Python objects are ever deserialized.
+pub fn inspect_array_file(
+    file_path: &str,
+    file_extension: &str,
+) -> Result<ArrayInspection, Box<dyn std::error::Error>> {
+    match file_extension.to_lowercase().as_str() {
+        "npy" => {
+            let mut file = File::open(file_path)?;
+            let info = read_npy_header(&mut file, "array")?;
+            Ok(ArrayInspection {
+                file_type: "npy".to_string(),
+                arrays: vec![info],
+            })
+        }
+        "npz" => {
+            let file = File::open(file_path)?;
+            let mut archive = zip::ZipArchive::new(file)?;
+            let mut arrays = Vec::new();
+            for i in 0..archive.len() {
+                let mut entry = archive.by_index(i)?;
+                let name = entry.name().to_string();
+                let Some(array_name) = name.strip_suffix(".npy") else {
+                    continue;
+                };
+                arrays.push(read_npy_header(&mut entry, array_name)?);
+            }
+            Ok(ArrayInspection {
+                file_type: "npz".to_string(),
+                arrays,
+            })
+        }
+        _ => Err(format!("Unsupported array file type: {}", file_extension).into()),
+    }
+}
+
+fn read_npy_header<R: Read>(
+    reader: &mut R,
+    name: &str,
+) -> Result<ArrayInfo, Box<dyn std::error::Error>> {
+    let mut preamble = [0u8; 8];
+    reader.read_exact(&mut preamble)?;
+    if &preamble[..6] != NPY_MAGIC {
+        return Err(format!("{} is not a valid .npy array", name).into());
+    }
+
+    let major_version = preamble[6];
+    let header_len = if major_version == 1 {
+        let mut len = [0u8; 2];
+        reader.read_exact(&mut len)?;
+        u16::from_le_bytes(len) as usize
+    } else {
+        let mut len = [0u8; 4];
+        reader.read_exact(&mut len)?;
+        u32::from_le_bytes(len) as usize
+    };
+    if header_len > MAX_HEADER_LEN {
+        return Err(format!("Header of {} is too large", name).into());
+    }
+
+    let mut header = vec![0u8; header_len];
+    reader.read_exact(&mut header)?;
+    let header = String::from_utf8_lossy(&header);
+
+    let descr = extract_header_value(&header, "descr")
+        .ok_or("Array header is missing 'descr'")?
+        .trim_matches(|c| c == '\'' || c == '"')
+        .to_string();
+    let fortran_order = extract_header_value(&header, "fortran_order")
+        .map(|v| v == "True")
+        .unwrap_or(false);
+    let shape = parse_shape(
+        &extract_header_value(&header, "shape").ok_or("Array header is missing 'shape'")?,
+    )?;
+
+    let element_count = shape
+        .iter()
+        .try_fold(1u64, |count, dim| count.checked_mul(*dim))
+        .ok_or_else(|| format!("Shape of {} is too large", name))?;
+    let (dtype, itemsize) = describe_dtype(&descr);
+
+    Ok(ArrayInfo {
+        name: name.to_string(),
+        descr,
+        dtype,
+        shape,
+        fortran_order,
+        element_count,
+        nbytes: itemsize.and_then(|size| size.checked_mul(element_count)),
+    })
+}
+
+/// Extract the raw value for a key from the Python dict literal in a .npy header
+fn extract_header_value(header: &str, key: &str) -> Option<String> {
+    let key_pos = header
+        .find(&format!("'{}'", key))
+        .or_else(|| header.find(&format!("\"{}\"", key)))?;
+    let rest = &header[key_pos + key.len() + 2..];
+    let rest = rest.trim_start().strip_prefix(':')?.trim_start();
+
+    // Values are either a tuple/list (shape, structured descr), a quoted string, or a bare word
+    let end = match rest.chars().next()? {
+        '(' | '[' => {
+            let mut depth = 0;
+            let mut end = rest.len();
+            for (i, c) in rest.char_indices() {
+                match c {
+                    '(' | '[' => depth += 1,
+                    ')' | ']' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            end = i + 1;
+                            break;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            end
+        }
+        quote @ ('\'' | '"') => rest[1..].find(quote).map(|i| i + 2)?,
+        _ => rest.find([',', '}']).unwrap_or(rest.len()),
+    };
+
+    Some(rest[..end].trim().to_string())
+}
+
+fn parse_shape(value: &str) -> Result<Vec<u64>, Box<dyn std::error::Error>> {
+    value
+        .trim_matches(|c| c == '(' || c == ')')
+        .split(',')
+        .map(str::trim)
+        .filter(|dim| !dim.is_empty())
+        .map(|dim| {
+            dim.trim_end_matches('L')
+                .parse::<u64>()
+                .map_err(|_| format!("Invalid shape dimension: {}", dim).into())
+        })
+        .collect()
+}
+
+/// Translate a numpy type string (e.g. `<f8`) into a readable dtype name and item size
+fn describe_dtype(descr: &str) -> (String, Option<u64>) {
+    if descr.starts_with('[') {
+        return ("structured".to_string(), None);
+    }
+
+    let mut body = descr.trim_start_matches(['<', '>', '|', '=']).chars();
+    let Some(kind) = body.next() else {
+        return (descr.to_string(), None);
+    };
+    let size: Option<u64> = body.as_str().parse().ok();
+    let bits = size.and_then(|s| s.checked_mul(8));
+
+    let name = match (kind, size, bits) {
+        ('b', Some(1), _) => "bool".to_string(),
+        ('i', _, Some(b)) => format!("int{}", b),
+        ('u', _, Some(b)) => format!("uint{}", b),
+        ('f', _, Some(b)) => format!("float{}", b),
+        ('c', _, Some(b)) => format!("complex{}", b),
+        ('U', Some(s), _) => format!("str{}", s),
+        ('S', Some(s), _) => format!("bytes{}", s),
+        ('M', _, _) => "datetime64".to_string(),
+        ('m', _, _) => "timedelta64".to_string(),
+        ('O', _, _) => "object".to_string(),
+        _ => descr.to_string(),
+    };
+
+    // Unicode strings are stored as UCS-4, four bytes per character
+    let itemsize = match kind {
+        'U' => size.and_then(|s| s.checked_mul(4)),
+        'M' | 'm' => Some(8),
+        'O' => None,
+        _ => size,
+    };
+
+    (name, itemsize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn npy(header: &str) -> Vec<u8> {
+        let mut bytes = NPY_MAGIC.to_vec();
+        bytes.extend([1, 0]);
+        bytes.extend((header.len() as u16).to_le_bytes());
+        bytes.extend(header.as_bytes());
+        bytes
+    }
+
+    #[test]
+    fn test_describe_dtype() {
+        assert_eq!(describe_dtype("<f8"), ("float64".to_string(), Some(8)));
+        assert_eq!(describe_dtype("|b1"), ("bool".to_string(), Some(1)));
+        assert_eq!(describe_dtype("<U10"), ("str10".to_string(), Some(40)));
+        assert_eq!(
+            describe_dtype("<M8[ns]"),
+            ("datetime64".to_string(), Some(8))
+        );
+        assert_eq!(describe_dtype("|O"), ("object".to_string(), None));
+        assert_eq!(describe_dtype("[('x', '<f4')]").0, "structured");
+    }
+
+    #[test]
+    fn test_malformed_dtypes_do_not_panic() {
+        assert_eq!(describe_dtype(""), (String::new(), None));
+        assert_eq!(describe_dtype("<"), ("<".to_string(), None));
+        assert_eq!(describe_dtype("<i"), ("<i".to_string(), None));
+        let huge = format!("<i{}", u64::MAX);
+        assert_eq!(describe_dtype(&huge), (huge.clone(), Some(u64::MAX)));
+        let huge = format!("<U{}", u64::MAX);
+        assert_eq!(describe_dtype(&huge).1, None);
+    }
+
+    #[test]
+    fn test_read_npy_header() {
+        let bytes = npy("{'descr': '<i4', 'fortran_order': False, 'shape': (3, 4), }");
+        let info = read_npy_header(&mut bytes.as_slice(), "array").unwrap();
+        assert_eq!(info.dtype, "int32");
+        assert_eq!(info.shape, [3, 4]);
+        assert_eq!(info.element_count, 12);
+        assert_eq!(info.nbytes, Some(48));
+
+        let bytes = npy("{'descr': '', 'fortran_order': False, 'shape': (3,), }");
+        let info = read_npy_header(&mut bytes.as_slice(), "array").unwrap();
+        assert_eq!(info.nbytes, None);
+    }
+
+    #[test]
+    fn test_oversized_shapes_are_errors() {
+        let shape = format!("({}, {})", u64::MAX, 2);
+        let bytes = npy(&format!(
+            "{{'descr': '<f8', 'fortran_order': False, 'shape': {}, }}",
+            shape
+        ));
+        assert!(read_npy_header(&mut bytes.as_slice(), "array").is_err());
+
+        // The element count fits, the byte count does not
+        let bytes = npy(&format!(
+            "{{'descr': '<f8', 'fortran_order': False, 'shape': ({},), }}",
+            u64::MAX / 2
+        ));
+        let info = read_npy_header(&mut bytes.as_slice(), "array").unwrap();
+        assert_eq!(info.element_count, u64::MAX / 2);
+        assert_eq!(info.nbytes, None);
+    }
+}
@@ -0,0 +1,203 @@
+//! Capacity planning for --max-concurrent-jobs: earlier local runs, with the time each job was
+//! queued and how long it ran, are replayed through a given number of slots to see how long
+//! jobs would have waited for one. Slots are handed out first come, first served; the fair
+//! turns across sources only change which job waits, not how busy the slots are.
+
+use serde::Serialize;
+
+/// Most slots a scenario may have
+pub const MAX_CONCURRENCY: usize = 256;
+/// Most scenarios in one request
+pub const MAX_SCENARIOS: usize = 16;
+
+/// One completed local run, as `JobRepo::local_runs` returns it
+pub struct JobRun {
+    pub queued_at: f64, // seconds since the Unix epoch
+    pub waited_seconds: f64,
+    pub duration_seconds: f64,
+}
+
+/// How long jobs waited for a slot, in seconds
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct WaitTimes {
+    pub mean: f64,
+    pub median: f64,
+    pub p95: f64,
+    pub max: f64,
+    pub waited: usize, // jobs that did not get a slot right away
+}
+
+impl WaitTimes {
+    fn from_waits(mut waits: Vec<f64>) -> Self {
+        if waits.is_empty() {
+            return Self::default();
+        }
+        waits.sort_by(|a, b| a.total_cmp(b));
+        let at = |fraction: f64| waits[((waits.len() - 1) as f64 * fraction).round() as usize];
+        Self {
+            mean: waits.iter().sum::<f64>() / waits.len() as f64,
+            median: at(0.5),
+            p95: at(0.95),
+            max: waits[waits.len() - 1],
+            waited: waits.iter().filter(|wait| **wait > 0.0).count(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Scenario {
+    pub concurrency: usize,
+    pub wait_seconds: WaitTimes,
+    pub utilization: f64, // share of the slots' time spent running jobs, from 0 to 1
+}
+
+/// The replay of `runs` through each number of slots in `concurrencies`, next to the waits
+/// that were observed
+#[derive(Debug, Serialize)]
+pub struct CapacityReport {
+    pub current_concurrency: usize,
+    pub jobs: usize,
+    pub observed_wait_seconds: WaitTimes,
+    pub scenarios: Vec<Scenario>,
+}
+
+/// Parse `concurrency=2,4,8`; None or an empty list means the current setting
+pub fn parse_concurrencies(value: Option<&str>, current: usize) -> Result<Vec<usize>, String> {
+    let mut concurrencies = Vec::new();
+    for item in value.unwrap_or_default().split(',').map(str::trim) {
+        if item.is_empty() {
+            continue;
+        }
+        match item.parse::<usize>() {
+            Ok(n) if (1..=MAX_CONCURRENCY).contains(&n) => {
+                if !concurrencies.contains(&n) {
+                    concurrencies.push(n);
+                }
+            }
+            _ => {
+                return Err(format!(
+                    "concurrency must be whole numbers from 1 to {}, not {}",
+                    MAX_CONCURRENCY, item
+                ))
+            }
+        }
+    }
+    if concurrencies.len() > MAX_SCENARIOS {
+        return Err(format!(
+            "At most {} concurrency values at once",
+            MAX_SCENARIOS
+        ));
+    }
+    if concurrencies.is_empty() {
+        concurrencies.push(current);
+    }
+    Ok(concurrencies)
+}
+
+/// Replay the runs through `concurrency` slots
+pub fn simulate(runs: &[JobRun], concurrency: usize) -> Scenario {
+    let mut order: Vec<&JobRun> = runs.iter().collect();
+    order.sort_by(|a, b| a.queued_at.total_cmp(&b.queued_at));
+
+    let mut free_at = vec![f64::NEG_INFINITY; concurrency.max(1)];
+    let mut waits = Vec::with_capacity(order.len());
+    let mut busy = 0.0;
+    let mut last_finish = f64::NEG_INFINITY;
+    for run in &order {
+        // The slot that frees up first
+        let (slot, free) = free_at
+            .iter()
+            .copied()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .unwrap_or((0, f64::NEG_INFINITY));
+        let start = run.queued_at.max(free);
+        let finish = start + run.duration_seconds;
+        free_at[slot] = finish;
+        waits.push(start - run.queued_at);
+        busy += run.duration_seconds;
+        last_finish = last_finish.max(finish);
+    }
+
+    let span = match order.first() {
+        Some(first) => last_finish - first.queued_at,
+        None => 0.0,
+    };
+    Scenario {
+        concurrency,
+        wait_seconds: WaitTimes::from_waits(waits),
+        utilization: if span > 0.0 {
+            (busy / (span * concurrency as f64)).min(1.0)
+        } else {
+            0.0
+        },
+    }
+}
+
+pub fn report(runs: &[JobRun], concurrencies: &[usize], current: usize) -> CapacityReport {
+    CapacityReport {
+        current_concurrency: current,
+        jobs: runs.len(),
+        observed_wait_seconds: WaitTimes::from_waits(
+            runs.iter().map(|run| run.waited_seconds.max(0.0)).collect(),
+        ),
+        scenarios: concurrencies
+            .iter()
+            .map(|concurrency| simulate(runs, *concurrency))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(queued_at: f64, duration_seconds: f64) -> JobRun {
+        JobRun {
+            queued_at,
+            waited_seconds: 0.0,
+            duration_seconds,
+        }
+    }
+
+    #[test]
+    fn test_burst_waits_less_with_more_slots() {
+        // Four 10 second jobs queued at once
+        let runs = [
+            run(0.0, 10.0),
+            run(0.0, 10.0),
+            run(0.0, 10.0),
+            run(0.0, 10.0),
+        ];
+        let one = simulate(&runs, 1);
+        assert_eq!(one.wait_seconds.max, 30.0);
+        assert_eq!(one.wait_seconds.mean, 15.0);
+        assert_eq!(one.wait_seconds.waited, 3);
+        assert_eq!(one.utilization, 1.0);
+
+        let two = simulate(&runs, 2);
+        assert_eq!(two.wait_seconds.max, 10.0);
+        assert_eq!(two.wait_seconds.waited, 2);
+        let four = simulate(&runs, 4);
+        assert_eq!(four.wait_seconds, WaitTimes::default());
+        assert_eq!(four.utilization, 1.0);
+    }
+
+    #[test]
+    fn test_spread_out_jobs_never_wait() {
+        let runs = [run(20.0, 5.0), run(0.0, 5.0), run(10.0, 5.0)];
+        let scenario = simulate(&runs, 1);
+        assert_eq!(scenario.wait_seconds.waited, 0);
+        assert_eq!(scenario.utilization, 15.0 / 25.0);
+        assert_eq!(simulate(&[], 3).wait_seconds, WaitTimes::default());
+    }
+
+    #[test]
+    fn test_parse_concurrencies() {
+        assert_eq!(parse_concurrencies(None, 10), Ok(vec![10]));
+        assert_eq!(parse_concurrencies(Some("2, 4,2,"), 10), Ok(vec![2, 4]));
+        assert!(parse_concurrencies(Some("0"), 10).is_err());
+        assert!(parse_concurrencies(Some("many"), 10).is_err());
+        assert!(parse_concurrencies(Some("1000"), 10).is_err());
+    }
+}
@@ -0,0 +1,446 @@
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// Settings for running functions on a Slurm cluster or Kubernetes. Inputs, the wrapped script
+/// and outputs live under `shared_dir`, which must be mounted at the same path on the DataLab
+/// host and on the compute nodes.
+pub struct ClusterConfig {
+    pub shared_dir: PathBuf,
+    pub poll_interval: Duration,
+    pub sbatch_template: String,
+    pub kubectl_bin: PathBuf,
+    pub k8s_namespace: String,
+    pub k8s_image: Option<String>, // Kubernetes runs are unavailable without an image
+    pub k8s_volume_claim: Option<String>, // PVC mounted at `shared_dir` inside the pod
+}
+
+/// Paths of one staged function run, all inside the shared directory
+pub struct StagedRun<'a> {
+    pub name: &'a str, // used as the Slurm/Kubernetes job name
+    pub work_dir: &'a Path,
+    pub script_path: &'a Path,
+    pub source_path: &'a Path,
+    pub manifest_path: &'a Path,
+    pub output_dir: &'a Path, // where scripts without a return value leave their outputs
+    pub env: &'a [(String, String)], // extra variables for the script, e.g. the job token
+}
+
+/// Result of a finished function run, local or remote
+pub struct RunOutput {
+    pub success: bool,
+    pub exit_code: i32,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Used unless `--slurm-template` points at a site-specific one (partition, account, modules...)
+pub const DEFAULT_SBATCH_TEMPLATE: &str = r#"#!/bin/bash
+#SBATCH --job-name={{ job_name }}
+#SBATCH --chdir={{ work_dir }}
+#SBATCH --output={{ log_path }}
+#SBATCH --ntasks=1
+
+{{ command }}
+"#;
+
+// Quote a value for a POSIX shell
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Render the batch script for a run. Templates can use `job_name`, `work_dir`, `log_path`,
+/// `script`, `source_path`, `manifest_path`, `output_dir` and `command`, the complete invocation.
+pub fn render_sbatch_script(
+    template: &str,
+    run: &StagedRun,
+    log_path: &Path,
+) -> Result<String, String> {
+    let mut command = String::new();
+    for (name, value) in run.env {
+        command.push_str(&format!("export {}={}\n", name, shell_quote(value)));
+    }
+    command.push_str(&format!(
+        "export SOURCE_PATH={}\nexport OUTPUT_MANIFEST={}\nexport OUTPUT_DIR={}\nuv run --script {}",
+        shell_quote(&run.source_path.to_string_lossy()),
+        shell_quote(&run.manifest_path.to_string_lossy()),
+        shell_quote(&run.output_dir.to_string_lossy()),
+        shell_quote(&run.script_path.to_string_lossy()),
+    ));
+    minijinja::Environment::new()
+        .render_str(
+            template,
+            minijinja::context! {
+                job_name => run.name,
+                work_dir => run.work_dir.to_string_lossy(),
+                log_path => log_path.to_string_lossy(),
+                script => run.script_path.to_string_lossy(),
+                source_path => run.source_path.to_string_lossy(),
+                manifest_path => run.manifest_path.to_string_lossy(),
+                output_dir => run.output_dir.to_string_lossy(),
+                command => command,
+            },
+        )
+        .map_err(|e| format!("Invalid sbatch template: {}", e))
+}
+
+/// State of a Slurm job as reported by `sacct`
+#[derive(Debug, PartialEq)]
+pub enum SlurmState {
+    Pending, // queued, running, or not yet known to accounting
+    Finished { success: bool, exit_code: i32 },
+}
+
+// Non-terminal Slurm job states
+const SLURM_ACTIVE_STATES: &[&str] = &[
+    "PENDING",
+    "RUNNING",
+    "CONFIGURING",
+    "COMPLETING",
+    "REQUEUED",
+    "REQUEUE_FED",
+    "REQUEUE_HOLD",
+    "RESIZING",
+    "SUSPENDED",
+    "SIGNALING",
+    "STAGE_OUT",
+    "STOPPED",
+];
+
+/// Parse `sacct -X -n -P -o State,ExitCode` output, e.g. `COMPLETED|0:0` or
+/// `CANCELLED by 1000|0:15`
+pub fn parse_sacct_state(output: &str) -> SlurmState {
+    let Some(line) = output.lines().map(str::trim).find(|l| !l.is_empty()) else {
+        return SlurmState::Pending;
+    };
+    let (state, exit_code) = line.split_once('|').unwrap_or((line, "0:0"));
+    let state = state.split_whitespace().next().unwrap_or("");
+    if SLURM_ACTIVE_STATES.contains(&state) {
+        return SlurmState::Pending;
+    }
+
+    // ExitCode is `<exit status>:<signal>`
+    let mut parts = exit_code
+        .split(':')
+        .map(|p| p.trim().parse::<i32>().unwrap_or(0));
+    let status = parts.next().unwrap_or(0);
+    let signal = parts.next().unwrap_or(0);
+    let success = state == "COMPLETED" && status == 0;
+    let exit_code = match (status, signal) {
+        (0, 0) if !success => 1,
+        (0, signal) if signal != 0 => 128 + signal,
+        (status, _) => status,
+    };
+    SlurmState::Finished { success, exit_code }
+}
+
+/// Parse `kubectl get job -o jsonpath={.status.succeeded}/{.status.failed}`; None while running
+pub fn parse_k8s_job_status(output: &str) -> Option<bool> {
+    let (succeeded, failed) = output.trim().split_once('/')?;
+    let count = |value: &str| value.trim().parse::<i64>().unwrap_or(0);
+    if count(succeeded) > 0 {
+        Some(true)
+    } else if count(failed) > 0 {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// Run a cluster CLI to completion, returning stdout or a message with its stderr
+async fn run_cli(command: &mut Command, stdin: Option<&str>) -> Result<String, String> {
+    let program = command
+        .as_std()
+        .get_program()
+        .to_string_lossy()
+        .into_owned();
+    let mut child = command
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+    if let (Some(input), Some(mut pipe)) = (stdin, child.stdin.take()) {
+        pipe.write_all(input.as_bytes())
+            .await
+            .map_err(|e| format!("Failed to write to {}: {}", program, e))?;
+    }
+    let output = child
+        .wait_with_output()
+        .await
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+impl ClusterConfig {
+    /// Submit a staged run with `sbatch` and wait until `sacct` reports it finished
+    pub async fn run_slurm(&self, run: &StagedRun<'_>) -> Result<RunOutput, String> {
+        let log_path = run.work_dir.join("slurm.log");
+        let batch_script = render_sbatch_script(&self.sbatch_template, run, &log_path)?;
+        let batch_path = run.work_dir.join("job.sbatch");
+        tokio::fs::write(&batch_path, batch_script)
+            .await
+            .map_err(|e| format!("Failed to write batch script: {}", e))?;
+
+        // `--parsable` prints `<job id>` or `<job id>;<cluster>`
+        let submitted = run_cli(
+            Command::new("sbatch").arg("--parsable").arg(&batch_path),
+            None,
+        )
+        .await?;
+        let slurm_id = submitted.trim().split(';').next().unwrap_or("").to_string();
+        if slurm_id.is_empty() {
+            return Err("sbatch did not return a job ID".to_string());
+        }
+        tracing::info!("Submitted {} to Slurm as job {}", run.name, slurm_id);
+
+        let (success, exit_code) = loop {
+            tokio::time::sleep(self.poll_interval).await;
+            let output = run_cli(
+                Command::new("sacct").args([
+                    "-j",
+                    &slurm_id,
+                    "-X",
+                    "-n",
+                    "-P",
+                    "-o",
+                    "State,ExitCode",
+                ]),
+                None,
+            )
+            .await?;
+            if let SlurmState::Finished { success, exit_code } = parse_sacct_state(&output) {
+                break (success, exit_code);
+            }
+        };
+
+        tracing::info!("Slurm job {} finished (exit code {})", slurm_id, exit_code);
+        Ok(RunOutput {
+            success,
+            exit_code,
+            stdout: tokio::fs::read_to_string(&log_path)
+                .await
+                .unwrap_or_default(),
+            stderr: String::new(), // Slurm writes both streams to the log
+        })
+    }
+
+    fn kubectl(&self) -> Command {
+        let mut command = Command::new(&self.kubectl_bin);
+        command.arg("--namespace").arg(&self.k8s_namespace);
+        command
+    }
+
+    fn k8s_job_manifest(&self, run: &StagedRun, image: &str) -> serde_json::Value {
+        let shared_dir = self.shared_dir.to_string_lossy();
+        let mut container = serde_json::json!({
+            "name": "function",
+            "image": image,
+            "command": ["uv", "run", "--script", run.script_path.to_string_lossy()],
+            "workingDir": run.work_dir.to_string_lossy(),
+            "env": [
+                { "name": "SOURCE_PATH", "value": run.source_path.to_string_lossy() },
+                { "name": "OUTPUT_MANIFEST", "value": run.manifest_path.to_string_lossy() },
+                { "name": "OUTPUT_DIR", "value": run.output_dir.to_string_lossy() },
+            ],
+        });
+        for (name, value) in run.env {
+            container["env"]
+                .as_array_mut()
+                .expect("env is an array")
+                .push(serde_json::json!({ "name": name, "value": value }));
+        }
+        let mut pod_spec = serde_json::json!({ "restartPolicy": "Never" });
+        if let Some(claim) = &self.k8s_volume_claim {
+            container["volumeMounts"] =
+                serde_json::json!([{ "name": "shared", "mountPath": shared_dir }]);
+            pod_spec["volumes"] = serde_json::json!([
+                { "name": "shared", "persistentVolumeClaim": { "claimName": claim } }
+            ]);
+        }
+        pod_spec["containers"] = serde_json::json!([container]);
+
+        serde_json::json!({
+            "apiVersion": "batch/v1",
+            "kind": "Job",
+            "metadata": {
+                "name": run.name,
+                "labels": { "app.kubernetes.io/managed-by": "datalab" },
+            },
+            "spec": {
+                "backoffLimit": 0,
+                "template": { "spec": pod_spec },
+            },
+        })
+    }
+
+    /// Create a Kubernetes Job for a staged run and wait until it succeeds or fails
+    pub async fn run_kubernetes(&self, run: &StagedRun<'_>) -> Result<RunOutput, String> {
+        let image = self
+            .k8s_image
+            .as_deref()
+            .ok_or("Kubernetes runs need --k8s-image")?;
+        let manifest = self.k8s_job_manifest(run, image).to_string();
+        run_cli(self.kubectl().args(["create", "-f", "-"]), Some(&manifest)).await?;
+        tracing::info!(
+            "Created Kubernetes job {} in {}",
+            run.name,
+            self.k8s_namespace
+        );
+
+        let result = self.wait_for_k8s_job(run.name).await;
+
+        // Jobs are not needed once their logs are collected
+        if let Err(e) = run_cli(
+            self.kubectl()
+                .args([
+                    "delete",
+                    "job",
+                    run.name,
+                    "--ignore-not-found",
+                    "--wait=false",
+                ])
+                .arg("--cascade=background"),
+            None,
+        )
+        .await
+        {
+            tracing::warn!("Failed to delete Kubernetes job {}: {}", run.name, e);
+        }
+        result
+    }
+
+    async fn wait_for_k8s_job(&self, name: &str) -> Result<RunOutput, String> {
+        let success = loop {
+            tokio::time::sleep(self.poll_interval).await;
+            let output = run_cli(
+                self.kubectl().args([
+                    "get",
+                    "job",
+                    name,
+                    "-o",
+                    "jsonpath={.status.succeeded}/{.status.failed}",
+                ]),
+                None,
+            )
+            .await?;
+            if let Some(success) = parse_k8s_job_status(&output) {
+                break success;
+            }
+        };
+
+        let exit_code = run_cli(
+            self.kubectl().args([
+                "get",
+                "pods",
+                "-l",
+                &format!("job-name={}", name),
+                "-o",
+                "jsonpath={.items[0].status.containerStatuses[0].state.terminated.exitCode}",
+            ]),
+            None,
+        )
+        .await
+        .ok()
+        .and_then(|code| code.trim().parse().ok())
+        .unwrap_or(if success { 0 } else { 1 });
+        let logs = run_cli(
+            self.kubectl()
+                .args(["logs", &format!("job/{}", name), "--all-containers"]),
+            None,
+        )
+        .await
+        .unwrap_or_else(|e| format!("(logs unavailable: {})", e));
+
+        tracing::info!("Kubernetes job {} finished (exit code {})", name, exit_code);
+        Ok(RunOutput {
+            success,
+            exit_code,
+            stdout: logs,
+            stderr: String::new(), // kubectl logs interleaves both streams
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sacct_state() {
+        assert_eq!(parse_sacct_state(""), SlurmState::Pending);
+        assert_eq!(parse_sacct_state("RUNNING|0:0\n"), SlurmState::Pending);
+        assert_eq!(
+            parse_sacct_state("COMPLETED|0:0"),
+            SlurmState::Finished {
+                success: true,
+                exit_code: 0
+            }
+        );
+        assert_eq!(
+            parse_sacct_state("FAILED|2:0"),
+            SlurmState::Finished {
+                success: false,
+                exit_code: 2
+            }
+        );
+        assert_eq!(
+            parse_sacct_state("CANCELLED by 1000|0:15"),
+            SlurmState::Finished {
+                success: false,
+                exit_code: 143
+            }
+        );
+        assert_eq!(
+            parse_sacct_state("TIMEOUT|0:0"),
+            SlurmState::Finished {
+                success: false,
+                exit_code: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_render_sbatch_script_quotes_paths() {
+        let run = StagedRun {
+            name: "datalab-1",
+            work_dir: Path::new("/shared/run"),
+            script_path: Path::new("/shared/run/script.py"),
+            source_path: Path::new("/shared/run/it's.csv"),
+            manifest_path: Path::new("/shared/run/output_manifest.json"),
+            output_dir: Path::new("/shared/run/outputs"),
+            env: &[("DATALAB_JOB_ID".to_string(), "job-1".to_string())],
+        };
+        let script = render_sbatch_script(
+            DEFAULT_SBATCH_TEMPLATE,
+            &run,
+            Path::new("/shared/run/slurm.log"),
+        )
+        .unwrap();
+        assert!(script.contains("#SBATCH --job-name=datalab-1\n"));
+        assert!(script.contains("export DATALAB_JOB_ID='job-1'\n"));
+        assert!(script.contains("export SOURCE_PATH='/shared/run/it'\\''s.csv'\n"));
+        assert!(script.contains("export OUTPUT_DIR='/shared/run/outputs'\n"));
+        assert!(script.contains("uv run --script '/shared/run/script.py'"));
+    }
+
+    #[test]
+    fn test_parse_k8s_job_status() {
+        assert_eq!(parse_k8s_job_status("/"), None);
+        assert_eq!(parse_k8s_job_status("1/"), Some(true));
+        assert_eq!(parse_k8s_job_status("/1"), Some(false));
+    }
+}
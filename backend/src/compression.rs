@@ -0,0 +1,71 @@
+use crate::mime_sniff::is_text_type;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+/// The only compression uploads are stored with, as recorded in `uploads.compression`
+pub const ZSTD: &str = "zstd";
+
+const ZSTD_LEVEL: i32 = 3;
+
+/// Text (CSV, logs, JSON) shrinks several times over; most binary formats are compressed
+/// already and are stored as they are
+pub fn is_compressible(detected_mime_type: Option<&str>) -> bool {
+    detected_mime_type.is_some_and(is_text_type)
+}
+
+/// Replace a file by its zstd-compressed content. The compressed copy is written next to
+/// it first, so a failure leaves the original in place.
+pub fn compress_file(path: &Path) -> io::Result<()> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".zst.partial");
+    let result = (|| {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut encoder = zstd::Encoder::new(BufWriter::new(File::create(&partial)?), ZSTD_LEVEL)?;
+        io::copy(&mut reader, &mut encoder)?;
+        encoder.finish()?.into_inner()?.sync_all()?;
+        std::fs::rename(&partial, path)
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+    result
+}
+
+/// Write the decompressed content of `source` to `dest`
+pub fn decompress_file(source: &Path, dest: &Path) -> io::Result<()> {
+    let reader = BufReader::new(File::open(source)?);
+    let mut writer = BufWriter::new(File::create(dest)?);
+    zstd::stream::copy_decode(reader, &mut writer)?;
+    writer.into_inner()?.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compress_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("datalab-compression-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("run.csv");
+        let csv = "t,value\n".to_string() + &"0.5,12.25\n".repeat(10_000);
+        std::fs::write(&path, &csv).unwrap();
+
+        compress_file(&path).unwrap();
+        let stored = std::fs::read(&path).unwrap();
+        assert!(stored.len() < csv.len() / 10);
+        assert!(stored.starts_with(b"\x28\xb5\x2f\xfd"));
+        assert!(!dir.join("run.csv.zst.partial").exists());
+
+        let plain = dir.join("plain.csv");
+        decompress_file(&path, &plain).unwrap();
+        assert_eq!(std::fs::read_to_string(&plain).unwrap(), csv);
+
+        assert!(is_compressible(Some("text/csv")));
+        assert!(is_compressible(Some("application/json")));
+        assert!(!is_compressible(Some("application/vnd.apache.parquet")));
+        assert!(!is_compressible(None));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,133 @@
+//! Expected duration and output size of a run, from the function's earlier successful runs.
+//! Both are fitted as a fixed part plus a part growing with the input size; when the earlier
+//! inputs were all the same size (or bigger inputs did not take longer) the median is used.
+
+use serde::{Deserialize, Serialize};
+
+/// How many of the latest successful runs an estimate is based on
+pub const ESTIMATE_RUNS: i64 = 50;
+
+/// One successful run, as `JobRepo::run_samples` returns it
+pub struct RunSample {
+    pub input_bytes: i64,
+    pub duration_seconds: f64,
+    pub output_bytes: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CostEstimate {
+    pub input_bytes: i64,
+    pub duration_seconds: f64,
+    pub output_bytes: i64,
+    pub based_on_runs: usize,
+    pub scaled_to_input: bool, // false if the median of the runs was taken as it is
+}
+
+fn median(values: &mut [f64]) -> f64 {
+    values.sort_by(|a, b| a.total_cmp(b));
+    let middle = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[middle - 1] + values[middle]) / 2.0
+    } else {
+        values[middle]
+    }
+}
+
+// Least squares fit of `y = a + b * x`; None if x does not vary or y does not grow with it
+fn fit(points: &[(f64, f64)]) -> Option<(f64, f64)> {
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let spread: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+    if spread == 0.0 {
+        return None;
+    }
+    let slope = points
+        .iter()
+        .map(|(x, y)| (x - mean_x) * (y - mean_y))
+        .sum::<f64>()
+        / spread;
+    (slope > 0.0).then_some((mean_y - slope * mean_x, slope))
+}
+
+// The value expected at `x`, and whether it was scaled to it
+fn predict(points: &[(f64, f64)], x: f64) -> (f64, bool) {
+    match fit(points) {
+        Some((intercept, slope)) => ((intercept + slope * x).max(0.0), true),
+        None => (
+            median(&mut points.iter().map(|(_, y)| *y).collect::<Vec<_>>()),
+            false,
+        ),
+    }
+}
+
+/// The expected cost of running on `input_bytes`; None without earlier runs to go by
+pub fn estimate(samples: &[RunSample], input_bytes: i64) -> Option<CostEstimate> {
+    if samples.is_empty() {
+        return None;
+    }
+    let durations: Vec<(f64, f64)> = samples
+        .iter()
+        .map(|s| (s.input_bytes as f64, s.duration_seconds))
+        .collect();
+    let outputs: Vec<(f64, f64)> = samples
+        .iter()
+        .map(|s| (s.input_bytes as f64, s.output_bytes as f64))
+        .collect();
+    let (duration_seconds, duration_scaled) = predict(&durations, input_bytes as f64);
+    let (output_bytes, output_scaled) = predict(&outputs, input_bytes as f64);
+    Some(CostEstimate {
+        input_bytes,
+        duration_seconds: (duration_seconds * 10.0).round() / 10.0,
+        output_bytes: output_bytes.round() as i64,
+        based_on_runs: samples.len(),
+        scaled_to_input: duration_scaled || output_scaled,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(input_bytes: i64, duration_seconds: f64, output_bytes: i64) -> RunSample {
+        RunSample {
+            input_bytes,
+            duration_seconds,
+            output_bytes,
+        }
+    }
+
+    #[test]
+    fn test_estimate_scales_with_the_input() {
+        // Two seconds to start, then a second and half the input size per MB
+        let mb = 1024 * 1024;
+        let runs = [
+            run(mb, 3.0, mb / 2),
+            run(2 * mb, 4.0, mb),
+            run(4 * mb, 6.0, 2 * mb),
+        ];
+        let estimate = estimate(&runs, 10 * mb).unwrap();
+        assert_eq!(estimate.duration_seconds, 12.0);
+        assert_eq!(estimate.output_bytes, 5 * mb);
+        assert_eq!(estimate.based_on_runs, 3);
+        assert!(estimate.scaled_to_input);
+    }
+
+    #[test]
+    fn test_estimate_falls_back_to_the_median() {
+        assert_eq!(estimate(&[], 100), None);
+
+        // Same input size every time: nothing to scale by
+        let runs = [run(100, 1.0, 10), run(100, 5.0, 30), run(100, 2.0, 20)];
+        let same_size = estimate(&runs, 1000).unwrap();
+        assert_eq!(
+            (same_size.duration_seconds, same_size.output_bytes),
+            (2.0, 20)
+        );
+        assert!(!same_size.scaled_to_input);
+
+        // Bigger inputs were not slower
+        let runs = [run(100, 4.0, 0), run(1000, 2.0, 0)];
+        assert_eq!(estimate(&runs, 5000).unwrap().duration_seconds, 3.0);
+    }
+}
@@ -0,0 +1,324 @@
+//! `datalab-backend ctl`: client subcommands that drive a running server over its HTTP API,
+//! for scripts and terminals. Tags and functions are named as in the UI; IDs work too.
+
+use crate::models::{Function, Job, QueuedJob, Tag, UploadResponse};
+use clap::{Args, Subcommand};
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+use std::time::Duration;
+
+type CtlResult<T> = Result<T, Box<dyn std::error::Error>>;
+
+#[derive(Args, Debug)]
+pub struct CtlArgs {
+    /// Base URL of the DataLab server
+    #[arg(
+        long,
+        global = true,
+        env = "DL_URL",
+        default_value = "http://127.0.0.1:8080"
+    )]
+    url: String,
+
+    /// Seconds between status checks while waiting for jobs
+    #[arg(long, global = true, default_value = "2")]
+    poll_seconds: u64,
+
+    #[command(subcommand)]
+    command: CtlCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum CtlCommand {
+    /// Upload files, printing the ID of each new upload
+    Upload {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// Tag to add to every file (repeatable)
+        #[arg(long = "tag", short)]
+        tags: Vec<String>,
+        /// Wait for the jobs the uploads trigger; fails if any of them fails
+        #[arg(long)]
+        wait: bool,
+    },
+    /// Add tags to an upload, triggering the functions they match
+    Tag {
+        upload_id: String,
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+    /// Run a function on an upload
+    Run {
+        function: String,
+        upload_id: String,
+        /// Wait for the job to finish; fails if it fails
+        #[arg(long)]
+        wait: bool,
+    },
+    /// List jobs, or watch them live
+    Jobs {
+        #[command(subcommand)]
+        command: Option<JobsCommand>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum JobsCommand {
+    /// Most recent jobs, newest first
+    List {
+        #[arg(long, default_value = "20")]
+        limit: usize,
+    },
+    /// Print status changes until the jobs finish (by default, the ones queued or running
+    /// now); fails if any of them fails
+    Watch { job_ids: Vec<String> },
+}
+
+pub async fn run(args: CtlArgs) -> CtlResult<()> {
+    // Name the local user in the chain of custody of what it uploads
+    let mut headers = reqwest::header::HeaderMap::new();
+    if let Some(user) = std::env::var("USER")
+        .ok()
+        .and_then(|user| user.parse().ok())
+    {
+        headers.insert(crate::custody::USER_HEADER, user);
+    }
+    let client = Client {
+        http: reqwest::Client::builder()
+            .default_headers(headers)
+            .build()?,
+        base: format!("{}/api", args.url.trim_end_matches('/')),
+        poll_interval: Duration::from_secs(args.poll_seconds.max(1)),
+    };
+    match args.command {
+        CtlCommand::Upload { files, tags, wait } => {
+            let tag_ids = client.tag_ids(&tags).await?;
+            let started = crate::timestamps::now();
+            let mut jobs = Vec::new();
+            for path in &files {
+                let upload = client.upload(path, &tag_ids).await?;
+                println!("{}  {}", upload.id, upload.original_filename);
+                jobs.extend(upload.jobs);
+            }
+            if wait {
+                client.wait_for(&jobs, &started).await?;
+            }
+        }
+        CtlCommand::Tag { upload_id, tags } => {
+            let tag_ids = client.tag_ids(&tags).await?;
+            client
+                .send(
+                    client
+                        .http
+                        .post(client.url(&format!("/uploads/{}/tags", upload_id)))
+                        .json(&tag_ids),
+                )
+                .await?;
+        }
+        CtlCommand::Run {
+            function,
+            upload_id,
+            wait,
+        } => {
+            let function = client.function(&function).await?;
+            let started = crate::timestamps::now();
+            let triggered: Triggered =
+                client
+                    .send(client.http.post(
+                        client.url(&format!("/uploads/{}/trigger/{}", upload_id, function.id)),
+                    ))
+                    .await?
+                    .json()
+                    .await?;
+            for job in &triggered.jobs {
+                println!("{}  started {} on {}", job.id, job.function_name, upload_id);
+            }
+            if wait {
+                client.wait_for(&triggered.jobs, &started).await?;
+            }
+        }
+        CtlCommand::Jobs { command } => match command.unwrap_or(JobsCommand::List { limit: 20 }) {
+            JobsCommand::List { limit } => {
+                for job in client.jobs(None).await?.iter().take(limit) {
+                    println!("{}", describe_job(job));
+                }
+            }
+            JobsCommand::Watch { job_ids } => {
+                let job_ids: HashSet<String> = if job_ids.is_empty() {
+                    client
+                        .jobs(None)
+                        .await?
+                        .into_iter()
+                        .filter(|job| !is_finished(job))
+                        .map(|job| job.id)
+                        .collect()
+                } else {
+                    job_ids.into_iter().collect()
+                };
+                if job_ids.is_empty() {
+                    println!("No jobs queued or running");
+                    return Ok(());
+                }
+                client.watch(None, |job| job_ids.contains(&job.id)).await?;
+            }
+        },
+    }
+    Ok(())
+}
+
+#[derive(serde::Deserialize)]
+struct Triggered {
+    jobs: Vec<QueuedJob>,
+}
+
+struct Client {
+    http: reqwest::Client,
+    base: String,
+    poll_interval: Duration,
+}
+
+impl Client {
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base, path)
+    }
+
+    /// Send a request, turning error responses into their `error` message
+    async fn send(&self, request: reqwest::RequestBuilder) -> CtlResult<reqwest::Response> {
+        let response = request.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let body = response.text().await.unwrap_or_default();
+        let message = serde_json::from_str::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|value| value["error"].as_str().map(str::to_string))
+            .unwrap_or(body);
+        Err(format!("{}: {}", status, message.trim()).into())
+    }
+
+    async fn get<T: serde::de::DeserializeOwned>(&self, path: &str) -> CtlResult<T> {
+        Ok(self
+            .send(self.http.get(self.url(path)))
+            .await?
+            .json()
+            .await?)
+    }
+
+    // Tag names (or IDs) to IDs; unknown names are an error rather than silently skipped
+    async fn tag_ids(&self, names: &[String]) -> CtlResult<Vec<String>> {
+        if names.is_empty() {
+            return Ok(Vec::new());
+        }
+        let tags: Vec<Tag> = self.get("/tags").await?;
+        names
+            .iter()
+            .map(|name| {
+                tags.iter()
+                    .find(|tag| tag.name == *name || tag.id == *name)
+                    .map(|tag| tag.id.clone())
+                    .ok_or_else(|| format!("Unknown tag: {}", name).into())
+            })
+            .collect()
+    }
+
+    async fn function(&self, name: &str) -> CtlResult<Function> {
+        let functions: Vec<Function> = self.get("/functions").await?;
+        functions
+            .into_iter()
+            .find(|function| function.name == name || function.id == name)
+            .ok_or_else(|| format!("Unknown function: {}", name).into())
+    }
+
+    async fn upload(
+        &self,
+        path: &std::path::Path,
+        tag_ids: &[String],
+    ) -> CtlResult<UploadResponse> {
+        let data = tokio::fs::read(path)
+            .await
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .ok_or_else(|| format!("{} is not a file", path.display()))?;
+        let form = reqwest::multipart::Form::new()
+            .part(
+                "file",
+                reqwest::multipart::Part::bytes(data).file_name(name),
+            )
+            .text("tags", serde_json::to_string(tag_ids)?);
+        Ok(self
+            .send(self.http.post(self.url("/uploads")).multipart(form))
+            .await?
+            .json()
+            .await?)
+    }
+
+    async fn jobs(&self, created_after: Option<&str>) -> CtlResult<Vec<Job>> {
+        let mut request = self.http.get(self.url("/jobs"));
+        if let Some(created_after) = created_after {
+            request = request.query(&[("created_after", created_after)]);
+        }
+        Ok(self.send(request).await?.json().await?)
+    }
+
+    async fn wait_for(&self, jobs: &[QueuedJob], since: &str) -> CtlResult<()> {
+        if jobs.is_empty() {
+            println!("No jobs were triggered");
+            return Ok(());
+        }
+        let job_ids: HashSet<&str> = jobs.iter().map(|job| job.id.as_str()).collect();
+        self.watch(Some(since), |job| job_ids.contains(job.id.as_str()))
+            .await
+    }
+
+    // Print each status change of the jobs `selected` picks until none of them is queued or
+    // running
+    async fn watch(&self, since: Option<&str>, selected: impl Fn(&Job) -> bool) -> CtlResult<()> {
+        let mut seen: BTreeMap<String, String> = BTreeMap::new();
+        loop {
+            let jobs: Vec<Job> = self
+                .jobs(since)
+                .await?
+                .into_iter()
+                .filter(|job| selected(job))
+                .collect();
+            for job in &jobs {
+                if seen.get(&job.id) != Some(&job.status) {
+                    println!("{}", describe_job(job));
+                    seen.insert(job.id.clone(), job.status.clone());
+                }
+            }
+            if jobs.iter().all(is_finished) {
+                let failed = jobs.iter().filter(|job| job.status == "FAILED").count();
+                return match failed {
+                    0 => Ok(()),
+                    n => Err(format!("{} of {} job(s) failed", n, jobs.len()).into()),
+                };
+            }
+            tokio::time::sleep(self.poll_interval).await;
+        }
+    }
+}
+
+fn is_finished(job: &Job) -> bool {
+    matches!(job.status.as_str(), "SUCCESS" | "FAILED")
+}
+
+fn describe_job(job: &Job) -> String {
+    let mut line = format!(
+        "{}  {:<9} {} on {}",
+        job.id,
+        job.status,
+        job.function_name.as_deref().unwrap_or(&job.function_id),
+        job.upload_filename.as_deref().unwrap_or(&job.upload_id)
+    );
+    if let Some(seconds) = job.duration_seconds {
+        line.push_str(&format!(" ({:.1}s)", seconds));
+    }
+    if let Some(error) = &job.error_message {
+        line.push_str(&format!(": {}", error.lines().next().unwrap_or_default()));
+    }
+    line
+}
@@ -0,0 +1,98 @@
+//! Who did what to an upload, for chain-of-custody documents. DataLab has no accounts, so the
+//! identity is whatever the client states in `X-DataLab-User`; the connecting address is
+//! recorded next to it, and a signature the client made over the file is kept as it was sent.
+
+use axum::http::HeaderMap;
+use std::net::SocketAddr;
+
+pub const USER_HEADER: &str = "x-datalab-user";
+pub const SIGNATURE_HEADER: &str = "x-datalab-signature";
+/// Longest signature accepted; detached PGP and SSH signatures are well below this
+pub const MAX_SIGNATURE_BYTES: usize = 16 * 1024;
+const MAX_HEADER_CHARS: usize = 256;
+
+/// The client behind a request
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RequestOrigin {
+    pub user: Option<String>,
+    pub client_ip: Option<String>,     // the connecting address
+    pub forwarded_for: Option<String>, // `X-Forwarded-For` as sent; only a proxy makes it trustworthy
+    pub user_agent: Option<String>,
+}
+
+impl RequestOrigin {
+    pub fn from_request(headers: &HeaderMap, peer: Option<SocketAddr>) -> Self {
+        Self {
+            user: header_text(headers, USER_HEADER),
+            client_ip: peer.map(|peer| peer.ip().to_string()),
+            forwarded_for: header_text(headers, "x-forwarded-for"),
+            user_agent: header_text(headers, "user-agent"),
+        }
+    }
+}
+
+// A trimmed, non-empty header value, cut to a length fit for a record
+fn header_text(headers: &HeaderMap, name: &str) -> Option<String> {
+    let value = headers.get(name)?.to_str().ok()?.trim();
+    (!value.is_empty()).then(|| value.chars().take(MAX_HEADER_CHARS).collect())
+}
+
+/// The signature a client sent for its upload; Err if it is too long to be one
+pub fn request_signature(headers: &HeaderMap) -> Result<Option<String>, String> {
+    let Some(value) = headers.get(SIGNATURE_HEADER) else {
+        return Ok(None);
+    };
+    if value.len() > MAX_SIGNATURE_BYTES {
+        return Err(format!(
+            "Signature is longer than {} bytes",
+            MAX_SIGNATURE_BYTES
+        ));
+    }
+    let value = value
+        .to_str()
+        .map_err(|_| "Signature must be ASCII, e.g. base64 or armored".to_string())?
+        .trim();
+    Ok((!value.is_empty()).then(|| value.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_origin_from_request() {
+        let mut headers = HeaderMap::new();
+        headers.insert(USER_HEADER, HeaderValue::from_static("  alice "));
+        headers.insert("x-forwarded-for", HeaderValue::from_static("10.0.0.7"));
+        headers.insert("user-agent", HeaderValue::from_static(""));
+        let origin = RequestOrigin::from_request(&headers, Some("192.0.2.1:5000".parse().unwrap()));
+        assert_eq!(
+            origin,
+            RequestOrigin {
+                user: Some("alice".to_string()),
+                client_ip: Some("192.0.2.1".to_string()),
+                forwarded_for: Some("10.0.0.7".to_string()),
+                user_agent: None,
+            }
+        );
+        assert_eq!(
+            RequestOrigin::from_request(&HeaderMap::new(), None),
+            RequestOrigin::default()
+        );
+    }
+
+    #[test]
+    fn test_request_signature() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(request_signature(&headers), Ok(None));
+        headers.insert(SIGNATURE_HEADER, HeaderValue::from_static("c2lnbmF0dXJl"));
+        assert_eq!(
+            request_signature(&headers),
+            Ok(Some("c2lnbmF0dXJl".to_string()))
+        );
+        let long = "x".repeat(MAX_SIGNATURE_BYTES + 1);
+        headers.insert(SIGNATURE_HEADER, HeaderValue::from_str(&long).unwrap());
+        assert!(request_signature(&headers).is_err());
+    }
+}
@@ -0,0 +1,200 @@
+//! Provenance and pipeline graphs as text diagrams, for embedding in wikis and papers:
+//! Graphviz DOT (`dot -Tsvg`) and Mermaid (rendered by GitHub, GitLab and most wikis).
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiagramFormat {
+    Dot,
+    Mermaid,
+}
+
+impl DiagramFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            DiagramFormat::Dot => "text/vnd.graphviz; charset=utf-8",
+            DiagramFormat::Mermaid => "text/plain; charset=utf-8",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shape {
+    File,
+    Tag,
+    Function,
+}
+
+pub struct Node {
+    pub label: String,
+    pub shape: Shape,
+    pub highlight: bool, // the upload a lineage graph was asked for
+    pub muted: bool,     // disabled functions
+}
+
+pub struct Edge {
+    pub from: usize, // indices into `Diagram::nodes`
+    pub to: usize,
+    pub label: Option<String>,
+    pub failed: bool, // runs that only left an error log
+}
+
+#[derive(Default)]
+pub struct Diagram {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+}
+
+impl Diagram {
+    pub fn add_node(&mut self, label: &str, shape: Shape) -> usize {
+        self.nodes.push(Node {
+            label: label.to_string(),
+            shape,
+            highlight: false,
+            muted: false,
+        });
+        self.nodes.len() - 1
+    }
+
+    pub fn add_edge(&mut self, from: usize, to: usize, label: Option<&str>, failed: bool) {
+        self.edges.push(Edge {
+            from,
+            to,
+            label: label.map(str::to_string),
+            failed,
+        });
+    }
+
+    pub fn render(&self, format: DiagramFormat, name: &str) -> String {
+        match format {
+            DiagramFormat::Dot => self.to_dot(name),
+            DiagramFormat::Mermaid => self.to_mermaid(),
+        }
+    }
+
+    pub fn to_dot(&self, name: &str) -> String {
+        let mut out = format!("digraph \"{}\" {{\n  rankdir=LR;\n", dot_escape(name));
+        out.push_str("  node [fontname=\"Helvetica\"];\n  edge [fontname=\"Helvetica\"];\n");
+        for (i, node) in self.nodes.iter().enumerate() {
+            let shape = match node.shape {
+                Shape::File => "note",
+                Shape::Tag => "ellipse",
+                Shape::Function => "box",
+            };
+            let mut attributes = format!("label=\"{}\", shape={}", dot_escape(&node.label), shape);
+            if node.highlight {
+                attributes.push_str(", style=filled, fillcolor=\"#fde68a\"");
+            } else if node.muted {
+                attributes.push_str(", style=dashed, fontcolor=gray40");
+            }
+            out.push_str(&format!("  n{} [{}];\n", i, attributes));
+        }
+        for edge in &self.edges {
+            let mut attributes = Vec::new();
+            if let Some(label) = &edge.label {
+                attributes.push(format!("label=\"{}\"", dot_escape(label)));
+            }
+            if edge.failed {
+                attributes.push("color=red, style=dashed".to_string());
+            }
+            match attributes.is_empty() {
+                true => out.push_str(&format!("  n{} -> n{};\n", edge.from, edge.to)),
+                false => out.push_str(&format!(
+                    "  n{} -> n{} [{}];\n",
+                    edge.from,
+                    edge.to,
+                    attributes.join(", ")
+                )),
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    pub fn to_mermaid(&self) -> String {
+        let mut out = String::from("flowchart LR\n");
+        for (i, node) in self.nodes.iter().enumerate() {
+            let label = mermaid_escape(&node.label);
+            let node = match node.shape {
+                Shape::File => format!("  n{}[/\"{}\"/]\n", i, label),
+                Shape::Tag => format!("  n{}([\"{}\"])\n", i, label),
+                Shape::Function => format!("  n{}[\"{}\"]\n", i, label),
+            };
+            out.push_str(&node);
+        }
+        for (i, edge) in self.edges.iter().enumerate() {
+            let arrow = if edge.failed { "-.->" } else { "-->" };
+            match &edge.label {
+                Some(label) => out.push_str(&format!(
+                    "  n{} {}|\"{}\"| n{}\n",
+                    edge.from,
+                    arrow,
+                    mermaid_escape(label),
+                    edge.to
+                )),
+                None => out.push_str(&format!("  n{} {} n{}\n", edge.from, arrow, edge.to)),
+            }
+            if edge.failed {
+                out.push_str(&format!("  linkStyle {} stroke:red\n", i));
+            }
+        }
+        for (i, node) in self.nodes.iter().enumerate() {
+            if node.highlight {
+                out.push_str(&format!("  style n{} fill:#fde68a\n", i));
+            } else if node.muted {
+                out.push_str(&format!(
+                    "  style n{} color:#666,stroke-dasharray: 5 5\n",
+                    i
+                ));
+            }
+        }
+        out
+    }
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// Mermaid labels are quoted; quotes inside them need its entity codes
+fn mermaid_escape(text: &str) -> String {
+    text.replace('"', "#quot;").replace('\n', " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Diagram {
+        let mut diagram = Diagram::default();
+        let raw = diagram.add_node("raw \"plate\".csv", Shape::File);
+        let clean = diagram.add_node("clean.csv", Shape::File);
+        let log = diagram.add_node("error.log", Shape::File);
+        diagram.nodes[raw].highlight = true;
+        diagram.add_edge(raw, clean, Some("Normalize"), false);
+        diagram.add_edge(raw, log, Some("Plot"), true);
+        diagram
+    }
+
+    #[test]
+    fn test_dot() {
+        let dot = sample().to_dot("lineage");
+        assert!(dot.starts_with("digraph \"lineage\" {\n"));
+        assert!(dot.contains(
+            "  n0 [label=\"raw \\\"plate\\\".csv\", shape=note, style=filled, fillcolor=\"#fde68a\"];\n"
+        ));
+        assert!(dot.contains("  n0 -> n1 [label=\"Normalize\"];\n"));
+        assert!(dot.contains("  n0 -> n2 [label=\"Plot\", color=red, style=dashed];\n"));
+        assert!(dot.ends_with("}\n"));
+    }
+
+    #[test]
+    fn test_mermaid() {
+        let mermaid = sample().to_mermaid();
+        assert!(mermaid.starts_with("flowchart LR\n"));
+        assert!(mermaid.contains("  n0[/\"raw #quot;plate#quot;.csv\"/]\n"));
+        assert!(mermaid.contains("  n0 -->|\"Normalize\"| n1\n"));
+        assert!(mermaid.contains("  n0 -.->|\"Plot\"| n2\n  linkStyle 1 stroke:red\n"));
+        assert!(mermaid.contains("  style n0 fill:#fde68a\n"));
+    }
+}
@@ -1,11 +1,119 @@
-use std::path::PathBuf;
+use crate::cluster::{ClusterConfig, RunOutput, StagedRun};
+use crate::compression::decompress_file;
+use crate::models::InputSlice;
+use crate::sql_query::transform_table;
+use crate::table_parser::{sample_table, slice_table_rows, SampleQuery};
+use crate::warm_pool::WarmPool;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use tokio::process::Command;
 
+/// Where a function's script runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComputeBackend {
+    Local,
+    Slurm,
+    Kubernetes,
+}
+
+impl ComputeBackend {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "local" => Some(Self::Local),
+            "slurm" => Some(Self::Slurm),
+            "kubernetes" => Some(Self::Kubernetes),
+            _ => None,
+        }
+    }
+}
+
+/// The upload a function runs on
+pub struct RunInput<'a> {
+    pub filename: &'a str, // stored name in the uploads directory
+    pub compression: Option<&'a str>,
+    pub original_filename: &'a str, // name the function sees
+    pub slice: Option<InputSlice>,  // for trial runs on part of the file
+}
+
+// Replace a staged input by the part of it a trial run asked for
+fn cut_input(path: &Path, slice: InputSlice) -> Result<(), Box<dyn std::error::Error>> {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let data = match slice {
+        InputSlice::Bytes { start, end } => {
+            let mut file = std::fs::File::open(path)?;
+            file.seek(SeekFrom::Start(start))?;
+            let mut data = Vec::new();
+            file.take(end - start).read_to_end(&mut data)?;
+            data
+        }
+        InputSlice::Rows { start, end } => {
+            slice_table_rows(&path.to_string_lossy(), &extension, start, end)?.write_file()?
+        }
+        InputSlice::Sample { rows, seed } => {
+            let query = SampleQuery {
+                n: Some(rows as usize),
+                method: Some("random".to_string()),
+                seed,
+            };
+            sample_table(&path.to_string_lossy(), &extension, &query)?.write_file()?
+        }
+    };
+    // Written next to the input first: Polars may still map the file it read
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let cut_path = path.with_file_name(format!(".slice_{}", name));
+    std::fs::write(&cut_path, data)?;
+    std::fs::rename(&cut_path, path)?;
+    Ok(())
+}
+
+// `data_drop_nulls.csv` for `data.csv` and a quick function named "Drop nulls"
+fn quick_output_name(original_filename: &str, function_name: &str) -> String {
+    let path = Path::new(original_filename);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let suffix: String = function_name
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("_");
+    match path.extension() {
+        Some(extension) => format!("{}_{}.{}", stem, suffix, extension.to_string_lossy()),
+        None => format!("{}_{}", stem, suffix),
+    }
+}
+
+// Paths of the files a script left in its OUTPUT_DIR; column dictionaries travel with their
+// output
+fn files_left_in(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_file()))
+        .map(|entry| entry.path().to_string_lossy().to_string())
+        .filter(|path| {
+            let name = Path::new(path)
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy();
+            !name.starts_with('.') && !name.ends_with(".dictionary.json")
+        })
+        .collect();
+    names.sort();
+    names
+}
+
 pub struct ScriptExecutor {
     scripts_dir: PathBuf,
     uploads_dir: PathBuf,
     output_dir: PathBuf,
+    uv_bin: PathBuf,
+    cluster: Option<ClusterConfig>,
+    warm_pool: Option<WarmPool>,
 }
 
 impl ScriptExecutor {
@@ -14,6 +122,59 @@ impl ScriptExecutor {
             scripts_dir,
             uploads_dir,
             output_dir,
+            uv_bin: PathBuf::from("uv"),
+            cluster: None,
+            warm_pool: None,
+        }
+    }
+
+    /// Run local scripts with this `uv` binary instead of the one on PATH
+    pub fn with_uv_bin(mut self, uv_bin: PathBuf) -> Self {
+        self.uv_bin = uv_bin;
+        self
+    }
+
+    /// Keep the resolved environments of up to `size` function versions for local runs; call
+    /// after `with_uv_bin`
+    pub fn with_warm_pool(mut self, size: usize) -> Self {
+        let work_dir = std::env::temp_dir().join("datalab_warm_pool");
+        self.warm_pool = Some(WarmPool::new(size, self.uv_bin.clone(), work_dir));
+        self
+    }
+
+    pub fn warm_pool(&self) -> Option<&WarmPool> {
+        self.warm_pool.as_ref()
+    }
+
+    /// Allow functions to run on a Slurm cluster or Kubernetes
+    pub fn with_cluster(mut self, cluster: ClusterConfig) -> Self {
+        self.cluster = Some(cluster);
+        self
+    }
+
+    /// Where uploaded files are stored
+    pub fn uploads_dir(&self) -> &Path {
+        &self.uploads_dir
+    }
+
+    pub fn scripts_dir(&self) -> &Path {
+        &self.scripts_dir
+    }
+
+    /// Where function outputs wait before they are registered as uploads
+    pub fn output_dir(&self) -> &Path {
+        &self.output_dir
+    }
+
+    /// Whether this server is configured to run functions on `backend`
+    pub fn supports(&self, backend: ComputeBackend) -> bool {
+        match backend {
+            ComputeBackend::Local => true,
+            ComputeBackend::Slurm => self.cluster.is_some(),
+            ComputeBackend::Kubernetes => self
+                .cluster
+                .as_ref()
+                .is_some_and(|cluster| cluster.k8s_image.is_some()),
         }
     }
 
@@ -30,8 +191,9 @@ if __name__ == "__main__":
     source_path = Path(os.environ["SOURCE_PATH"])
     manifest_path = Path(os.environ["OUTPUT_MANIFEST"])
     
-    # Call the main function
-    result = main(source_path)
+    # Call the main function; legacy scripts without one have already run and leave their
+    # outputs in OUTPUT_DIR
+    result = main(source_path) if "main" in globals() else None
     
     # Handle return value - can be single Path or list/tuple of Paths
     if result is None:
@@ -64,10 +226,11 @@ if __name__ == "__main__":
         .to_string()
     }
 
-    /// Create a temporary script file with wrapper code
+    /// Create a temporary script file with wrapper code in `dir`
     async fn create_wrapped_script(
         &self,
         original_script_path: &PathBuf,
+        dir: &Path,
     ) -> Result<PathBuf, String> {
         // Read the original script
         let original_content = tokio::fs::read_to_string(original_script_path)
@@ -79,9 +242,7 @@ if __name__ == "__main__":
         let wrapped_content = format!("{}\n{}", original_content, wrapper_code);
 
         // Create a temporary script file
-        let temp_script_path = self
-            .scripts_dir
-            .join(format!("temp_{}.py", uuid::Uuid::new_v4()));
+        let temp_script_path = dir.join(format!("temp_{}.py", uuid::Uuid::new_v4()));
         tokio::fs::write(&temp_script_path, wrapped_content)
             .await
             .map_err(|e| format!("Failed to write temporary script: {}", e))?;
@@ -93,29 +254,44 @@ if __name__ == "__main__":
     async fn manage_output_directory(
         &self,
         manifest_path: &std::path::Path,
+        run_output_dir: &std::path::Path,
     ) -> Result<Vec<String>, String> {
         // Ensure output directory exists
         tokio::fs::create_dir_all(&self.output_dir)
             .await
             .map_err(|e| format!("Failed to create output dir: {}", e))?;
 
-        // Read the output manifest from the function
-        let manifest_content = tokio::fs::read_to_string(manifest_path)
-            .await
-            .map_err(|e| format!("Failed to read output manifest: {}", e))?;
-
-        let manifest: serde_json::Value = serde_json::from_str(&manifest_content)
-            .map_err(|e| format!("Failed to parse output manifest: {}", e))?;
-
-        let function_outputs: Vec<String> = manifest
-            .get("outputs")
-            .and_then(|v| v.as_array())
-            .map(|arr| {
-                arr.iter()
-                    .filter_map(|v| v.as_str().map(|s| s.to_string()))
-                    .collect()
-            })
-            .unwrap_or_default();
+        // Read the output manifest from the function, if it wrote one
+        let mut function_outputs: Vec<String> = Vec::new();
+        if manifest_path.exists() {
+            let manifest_content = tokio::fs::read_to_string(manifest_path)
+                .await
+                .map_err(|e| format!("Failed to read output manifest: {}", e))?;
+
+            let manifest: serde_json::Value = serde_json::from_str(&manifest_content)
+                .map_err(|e| format!("Failed to parse output manifest: {}", e))?;
+
+            function_outputs = manifest
+                .get("outputs")
+                .and_then(|v| v.as_array())
+                .map(|arr| {
+                    arr.iter()
+                        .filter_map(|v| v.as_str().map(|s| s.to_string()))
+                        .collect()
+                })
+                .unwrap_or_default();
+        }
+
+        // Scripts that return nothing may still have written files into OUTPUT_DIR
+        if function_outputs.is_empty() {
+            function_outputs = files_left_in(run_output_dir);
+            if !function_outputs.is_empty() {
+                tracing::info!(
+                    "Collected {} output(s) the script left in OUTPUT_DIR",
+                    function_outputs.len()
+                );
+            }
+        }
 
         // Get current files in output directory
         let mut current_files = std::collections::HashSet::new();
@@ -161,6 +337,19 @@ if __name__ == "__main__":
                 current_files.remove(filename);
                 result_files.push(filename.to_string());
             }
+
+            // Keep an optional `<output>.dictionary.json` describing the output's columns
+            let dictionary_name = format!("{}.dictionary.json", filename);
+            let dictionary_path = output_path.with_file_name(&dictionary_name);
+            if dictionary_path.exists() {
+                let dest_path = self.output_dir.join(&dictionary_name);
+                if dictionary_path != dest_path {
+                    tokio::fs::copy(&dictionary_path, &dest_path)
+                        .await
+                        .map_err(|e| format!("Failed to copy {}: {}", dictionary_name, e))?;
+                }
+                current_files.remove(&dictionary_name);
+            }
         }
 
         // Remove any files in output directory that weren't in the function outputs
@@ -172,14 +361,101 @@ if __name__ == "__main__":
         Ok(result_files)
     }
 
+    // Copy the input with its original name into `dir`; functions always see plain content,
+    // and trial runs only their slice of it
+    async fn stage_input(&self, input: &RunInput<'_>, dir: &Path) -> Result<PathBuf, String> {
+        let input_path = self.uploads_dir.join(input.filename);
+        let temp_input_path = dir.join(input.original_filename);
+        match input.compression {
+            Some(_) => tokio::task::spawn_blocking({
+                let temp_input_path = temp_input_path.clone();
+                move || decompress_file(&input_path, &temp_input_path)
+            })
+            .await
+            .map_err(|e| e.to_string())?
+            .map_err(|e| format!("Failed to decompress input file: {}", e))?,
+            None => {
+                tokio::fs::copy(&input_path, &temp_input_path)
+                    .await
+                    .map_err(|e| format!("Failed to copy input file: {}", e))?;
+            }
+        }
+        if let Some(slice) = input.slice {
+            tokio::task::spawn_blocking({
+                let temp_input_path = temp_input_path.clone();
+                move || cut_input(&temp_input_path, slice).map_err(|e| e.to_string())
+            })
+            .await
+            .map_err(|e| e.to_string())?
+            .map_err(|e| format!("Failed to cut the input to {}: {}", slice.describe(), e))?;
+        }
+        Ok(temp_input_path)
+    }
+
+    /// Run a quick function: its query over the input, in this process, with the result in
+    /// the input's format. Like a failing script, a failing query leaves an error log.
+    pub async fn execute_expression(
+        &self,
+        function_name: &str,
+        expression: &str,
+        input: &RunInput<'_>,
+    ) -> Result<Vec<String>, String> {
+        tokio::fs::create_dir_all(&self.output_dir)
+            .await
+            .map_err(|e| format!("Failed to create output dir: {}", e))?;
+        let temp_dir = std::env::temp_dir().join(format!("datalab_temp_{}", uuid::Uuid::new_v4()));
+        tokio::fs::create_dir_all(&temp_dir)
+            .await
+            .map_err(|e| format!("Failed to create temp dir: {}", e))?;
+
+        let staged = self.stage_input(input, &temp_dir).await;
+        let result = match staged {
+            Ok(path) => {
+                let file_type = path
+                    .extension()
+                    .map(|e| e.to_string_lossy().to_lowercase())
+                    .unwrap_or_default();
+                let expression = expression.to_string();
+                tokio::task::spawn_blocking(move || {
+                    transform_table(&expression, &path.to_string_lossy(), &file_type)
+                        .and_then(|mut table| table.write_file())
+                        .map_err(|e| e.to_string())
+                })
+                .await
+                .map_err(|e| e.to_string())?
+            }
+            Err(e) => Err(e),
+        };
+        let _ = tokio::fs::remove_dir_all(&temp_dir).await;
+
+        let (filename, content) = match result {
+            Ok(data) => (
+                quick_output_name(input.original_filename, function_name),
+                data,
+            ),
+            Err(e) => (
+                format!("error_{}.log", uuid::Uuid::new_v4()),
+                format!(
+                    "Quick function query failed:\n\n{}\n\nQuery:\n{}",
+                    e, expression
+                )
+                .into_bytes(),
+            ),
+        };
+        tokio::fs::write(self.output_dir.join(&filename), content)
+            .await
+            .map_err(|e| format!("Failed to write {}: {}", filename, e))?;
+        Ok(vec![filename])
+    }
+
     pub async fn execute_function(
         &self,
         script_filename: &str,
-        input_filename: &str,
-        original_filename: &str,
+        input: &RunInput<'_>,
+        backend: ComputeBackend,
+        env: &[(String, String)],
     ) -> Result<Vec<String>, String> {
         let script_path = self.scripts_dir.join(script_filename);
-        let input_path = self.uploads_dir.join(input_filename);
 
         // Ensure directories exist
         tokio::fs::create_dir_all(&self.uploads_dir)
@@ -189,44 +465,73 @@ if __name__ == "__main__":
             .await
             .map_err(|e| format!("Failed to create output dir: {}", e))?;
 
-        // Create a temp directory for this execution
-        let temp_dir = std::env::temp_dir().join(format!("datalab_temp_{}", uuid::Uuid::new_v4()));
+        // Create a temp directory for this execution; remote runs stage it on shared storage
+        let run_id = uuid::Uuid::new_v4();
+        let cluster = match backend {
+            ComputeBackend::Local => None,
+            _ => Some(
+                self.cluster
+                    .as_ref()
+                    .filter(|_| self.supports(backend))
+                    .ok_or_else(|| format!("{:?} execution is not configured", backend))?,
+            ),
+        };
+        let temp_dir = match cluster {
+            None => std::env::temp_dir().join(format!("datalab_temp_{}", run_id)),
+            Some(cluster) => cluster.shared_dir.join(format!("datalab_{}", run_id)),
+        };
         tokio::fs::create_dir_all(&temp_dir)
             .await
             .map_err(|e| format!("Failed to create temp dir: {}", e))?;
 
-        // Copy file with original name to temp directory
-        let temp_input_path = temp_dir.join(original_filename);
-        tokio::fs::copy(&input_path, &temp_input_path)
-            .await
-            .map_err(|e| format!("Failed to copy input file: {}", e))?;
+        let temp_input_path = self.stage_input(input, &temp_dir).await?;
 
         // Create wrapped script with main() function call
-        let wrapped_script_path = self.create_wrapped_script(&script_path).await?;
+        let script_dir = if cluster.is_some() {
+            &temp_dir
+        } else {
+            &self.scripts_dir
+        };
+        let wrapped_script_path = self.create_wrapped_script(&script_path, script_dir).await?;
 
-        // Create manifest file for communication
+        // Create manifest file for communication, and a directory for scripts that write their
+        // outputs to OUTPUT_DIR instead of returning them
         let manifest_path = temp_dir.join("output_manifest.json");
-
-        // Execute wrapped script with uv
-        let output = Command::new("uv")
-            .arg("run")
-            .arg("--script")
-            .arg(&wrapped_script_path)
-            .env("SOURCE_PATH", &temp_input_path)
-            .env("OUTPUT_MANIFEST", &manifest_path)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .output()
+        let run_output_dir = temp_dir.join("outputs");
+        tokio::fs::create_dir_all(&run_output_dir)
             .await
-            .map_err(|e| format!("Failed to execute script: {}", e))?;
+            .map_err(|e| format!("Failed to create output dir: {}", e))?;
+
+        let run_name = format!("datalab-{}", run_id);
+        let staged = StagedRun {
+            name: &run_name,
+            work_dir: &temp_dir,
+            script_path: &wrapped_script_path,
+            source_path: &temp_input_path,
+            manifest_path: &manifest_path,
+            output_dir: &run_output_dir,
+            env,
+        };
+        let output = match (backend, cluster) {
+            (ComputeBackend::Slurm, Some(cluster)) => cluster.run_slurm(&staged).await?,
+            (ComputeBackend::Kubernetes, Some(cluster)) => cluster.run_kubernetes(&staged).await?,
+            _ => {
+                let python = match &self.warm_pool {
+                    Some(pool) => match tokio::fs::read_to_string(&script_path).await {
+                        Ok(script) => pool.python_for(script_filename, &script).await,
+                        Err(_) => None,
+                    },
+                    None => None,
+                };
+                self.run_local(&staged, python.as_deref()).await?
+            }
+        };
 
         // If script failed, write error log
-        if !output.status.success() {
+        if !output.success {
             let error_log = format!(
                 "Exit code: {}\n\nSTDOUT:\n{}\n\nSTDERR:\n{}",
-                output.status.code().unwrap_or(-1),
-                String::from_utf8_lossy(&output.stdout),
-                String::from_utf8_lossy(&output.stderr)
+                output.exit_code, output.stdout, output.stderr
             );
 
             let log_filename = format!("error_{}.log", uuid::Uuid::new_v4());
@@ -235,16 +540,16 @@ if __name__ == "__main__":
                 .await
                 .map_err(|e| format!("Failed to write error log: {}", e))?;
 
+            if cluster.is_some() {
+                let _ = tokio::fs::remove_dir_all(&temp_dir).await;
+            }
             return Ok(vec![log_filename]);
         }
 
         // Manage output directory based on function results
-        let output_files = if manifest_path.exists() {
-            self.manage_output_directory(&manifest_path).await?
-        } else {
-            // If no manifest was created, function had no outputs
-            Vec::new()
-        };
+        let output_files = self
+            .manage_output_directory(&manifest_path, &run_output_dir)
+            .await?;
 
         // Clean up temp directory and temporary script (do this after reading manifest)
         let _ = tokio::fs::remove_dir_all(&temp_dir).await;
@@ -252,4 +557,39 @@ if __name__ == "__main__":
 
         Ok(output_files)
     }
+
+    /// Execute a wrapped script on this machine, with uv or the interpreter of its warm
+    /// environment
+    async fn run_local(
+        &self,
+        run: &StagedRun<'_>,
+        python: Option<&Path>,
+    ) -> Result<RunOutput, String> {
+        let mut command = match python {
+            Some(python) => Command::new(python),
+            None => {
+                let mut command = Command::new(&self.uv_bin);
+                command.arg("run").arg("--script");
+                command
+            }
+        };
+        let output = command
+            .arg(run.script_path)
+            .env("SOURCE_PATH", run.source_path)
+            .env("OUTPUT_MANIFEST", run.manifest_path)
+            .env("OUTPUT_DIR", run.output_dir)
+            .envs(run.env.iter().map(|(name, value)| (name, value)))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .await
+            .map_err(|e| format!("Failed to execute script: {}", e))?;
+
+        Ok(RunOutput {
+            success: output.status.success(),
+            exit_code: output.status.code().unwrap_or(-1),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        })
+    }
 }
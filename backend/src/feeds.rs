@@ -0,0 +1,197 @@
+use chrono::{DateTime, Utc};
+
+/// A single pipeline event rendered into a feed (one job execution)
+pub struct FeedEntry {
+    pub id: String,
+    pub status: String,
+    pub function_name: String,
+    pub upload_filename: String,
+    pub error_message: Option<String>,
+    pub created_at: String,
+    pub started_at: Option<String>,
+    pub completed_at: Option<String>,
+}
+
+impl FeedEntry {
+    fn title(&self) -> String {
+        format!(
+            "{}: {} on {}",
+            self.status, self.function_name, self.upload_filename
+        )
+    }
+
+    fn description(&self) -> String {
+        let mut description = format!(
+            "Function {} ran on {} with status {}.",
+            self.function_name, self.upload_filename, self.status
+        );
+        if let Some(error) = &self.error_message {
+            description.push_str(&format!(" Error: {}", error));
+        }
+        description
+    }
+
+    /// The most recent timestamp of the job, used as the event time
+    fn event_time(&self) -> Option<DateTime<Utc>> {
+        self.completed_at
+            .as_deref()
+            .or(self.started_at.as_deref())
+            .unwrap_or(&self.created_at)
+            .parse::<DateTime<Utc>>()
+            .ok()
+    }
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Render an RSS 2.0 channel. `base_url` is used to build item links (e.g. `http://host:8080`).
+pub fn render_rss(title: &str, base_url: &str, entries: &[FeedEntry]) -> String {
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<rss version=\"2.0\">\n<channel>\n");
+    xml.push_str(&format!("<title>{}</title>\n", escape_xml(title)));
+    xml.push_str(&format!("<link>{}/api/jobs</link>\n", escape_xml(base_url)));
+    xml.push_str("<description>DataLab pipeline activity</description>\n");
+    if let Some(latest) = entries.iter().filter_map(|e| e.event_time()).max() {
+        xml.push_str(&format!(
+            "<lastBuildDate>{}</lastBuildDate>\n",
+            latest.to_rfc2822()
+        ));
+    }
+
+    for entry in entries {
+        xml.push_str("<item>\n");
+        xml.push_str(&format!("<title>{}</title>\n", escape_xml(&entry.title())));
+        xml.push_str(&format!(
+            "<link>{}/api/jobs/{}</link>\n",
+            escape_xml(base_url),
+            escape_xml(&entry.id)
+        ));
+        xml.push_str(&format!(
+            "<guid isPermaLink=\"false\">{}-{}</guid>\n",
+            escape_xml(&entry.id),
+            escape_xml(&entry.status)
+        ));
+        xml.push_str(&format!(
+            "<description>{}</description>\n",
+            escape_xml(&entry.description())
+        ));
+        xml.push_str(&format!(
+            "<category>{}</category>\n",
+            escape_xml(&entry.status)
+        ));
+        if let Some(time) = entry.event_time() {
+            xml.push_str(&format!("<pubDate>{}</pubDate>\n", time.to_rfc2822()));
+        }
+        xml.push_str("</item>\n");
+    }
+
+    xml.push_str("</channel>\n</rss>\n");
+    xml
+}
+
+fn escape_ics(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+fn ics_time(value: &str) -> Option<String> {
+    value
+        .parse::<DateTime<Utc>>()
+        .ok()
+        .map(|dt| dt.format("%Y%m%dT%H%M%SZ").to_string())
+}
+
+/// Render an iCalendar feed where each job is an event spanning its execution
+pub fn render_ics(title: &str, entries: &[FeedEntry]) -> String {
+    let mut ics = String::new();
+    ics.push_str("BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//DataLab//Pipeline Activity//EN\r\n");
+    ics.push_str(&format!("X-WR-CALNAME:{}\r\n", escape_ics(title)));
+
+    for entry in entries {
+        let Some(start) = ics_time(entry.started_at.as_deref().unwrap_or(&entry.created_at)) else {
+            continue;
+        };
+        let end = entry
+            .completed_at
+            .as_deref()
+            .and_then(ics_time)
+            .unwrap_or_else(|| start.clone());
+
+        ics.push_str("BEGIN:VEVENT\r\n");
+        ics.push_str(&format!("UID:{}@datalab\r\n", entry.id));
+        if let Some(stamp) = ics_time(&entry.created_at) {
+            ics.push_str(&format!("DTSTAMP:{}\r\n", stamp));
+        }
+        ics.push_str(&format!("DTSTART:{}\r\n", start));
+        ics.push_str(&format!("DTEND:{}\r\n", end));
+        ics.push_str(&format!("SUMMARY:{}\r\n", escape_ics(&entry.title())));
+        ics.push_str(&format!(
+            "DESCRIPTION:{}\r\n",
+            escape_ics(&entry.description())
+        ));
+        ics.push_str(&format!("CATEGORIES:{}\r\n", escape_ics(&entry.status)));
+        ics.push_str("END:VEVENT\r\n");
+    }
+
+    ics.push_str("END:VCALENDAR\r\n");
+    ics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(status: &str, error_message: Option<&str>) -> FeedEntry {
+        FeedEntry {
+            id: "job-1".to_string(),
+            status: status.to_string(),
+            function_name: "to_parquet".to_string(),
+            upload_filename: "run <1> & co.csv".to_string(),
+            error_message: error_message.map(str::to_string),
+            created_at: "2024-05-01T10:00:00Z".to_string(),
+            started_at: Some("2024-05-01T10:00:05Z".to_string()),
+            completed_at: Some("2024-05-01T10:01:00Z".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_render_rss() {
+        let rss = render_rss("Jobs", "http://localhost:8080", &[entry("failed", None)]);
+        assert!(rss.contains("<title>failed: to_parquet on run &lt;1&gt; &amp; co.csv</title>"));
+        assert!(rss.contains("<link>http://localhost:8080/api/jobs/job-1</link>"));
+        assert!(rss.contains("<guid isPermaLink=\"false\">job-1-failed</guid>"));
+        assert!(rss.contains("<pubDate>Wed, 1 May 2024 10:01:00 +0000</pubDate>"));
+        assert_eq!(rss.matches("<item>").count(), 1);
+
+        let empty = render_rss("Jobs", "http://localhost:8080", &[]);
+        assert!(!empty.contains("<item>") && !empty.contains("<lastBuildDate>"));
+    }
+
+    #[test]
+    fn test_render_ics() {
+        let ics = render_ics(
+            "Jobs",
+            &[entry("failed", Some("bad header; line 1, col 2"))],
+        );
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.contains("UID:job-1@datalab\r\n"));
+        assert!(ics.contains("DTSTART:20240501T100005Z\r\nDTEND:20240501T100100Z\r\n"));
+        assert!(ics.contains("Error: bad header\\; line 1\\, col 2"));
+
+        let mut unstarted = entry("pending", None);
+        unstarted.created_at = "not a time".to_string();
+        unstarted.started_at = None;
+        assert!(!render_ics("Jobs", &[unstarted]).contains("BEGIN:VEVENT"));
+    }
+}
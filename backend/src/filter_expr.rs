@@ -0,0 +1,394 @@
+use polars::prelude::*;
+
+/// A row filter over table columns, e.g. `temperature > 3.5 AND site == "north"`
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterExpr {
+    Compare {
+        column: String,
+        op: CompareOp,
+        value: Literal,
+    },
+    Not(Box<FilterExpr>),
+    And(Box<FilterExpr>, Box<FilterExpr>),
+    Or(Box<FilterExpr>, Box<FilterExpr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Bool(bool),
+    Null,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Number(String),
+    Op(CompareOp),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+}
+
+impl FilterExpr {
+    /// Parse a filter. Comparisons are `column OP value` with OP one of
+    /// `== != < <= > >=`; combine them with `AND`, `OR`, `NOT` (case-insensitive)
+    /// and parentheses. Strings are single- or double-quoted, column names with
+    /// spaces are backtick-quoted, and `== null` / `!= null` test for missing values.
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let tokens = tokenize(input)?;
+        if tokens.is_empty() {
+            return Err("Empty filter expression".to_string());
+        }
+
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.parse_or()?;
+        if parser.pos < parser.tokens.len() {
+            return Err(format!(
+                "Unexpected token at position {} in filter expression",
+                parser.pos
+            ));
+        }
+        Ok(expr)
+    }
+
+    /// Check that every referenced column exists and each comparison makes sense for its type
+    pub fn check_types(&self, schema: &Schema) -> Result<(), String> {
+        match self {
+            FilterExpr::Compare { column, op, value } => {
+                let dtype = schema
+                    .get(column.as_str())
+                    .ok_or_else(|| format!("Unknown column: {}", column))?;
+                let compatible = match value {
+                    Literal::Null => matches!(op, CompareOp::Eq | CompareOp::Ne),
+                    Literal::Int(_) | Literal::Float(_) => dtype.is_primitive_numeric(),
+                    Literal::Str(_) => dtype.is_string(),
+                    Literal::Bool(_) => dtype.is_bool(),
+                };
+                if compatible {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "Cannot compare column {} of type {} with {}",
+                        column,
+                        dtype,
+                        value.describe()
+                    ))
+                }
+            }
+            FilterExpr::Not(inner) => inner.check_types(schema),
+            FilterExpr::And(lhs, rhs) | FilterExpr::Or(lhs, rhs) => {
+                lhs.check_types(schema)?;
+                rhs.check_types(schema)
+            }
+        }
+    }
+
+    /// Compile into a Polars expression so the predicate is pushed down into the scan
+    pub fn to_polars(&self) -> Expr {
+        match self {
+            FilterExpr::Compare {
+                column,
+                op,
+                value: Literal::Null,
+            } => match op {
+                CompareOp::Ne => col(column.as_str()).is_not_null(),
+                _ => col(column.as_str()).is_null(),
+            },
+            FilterExpr::Compare { column, op, value } => {
+                let lhs = col(column.as_str());
+                let rhs = match value {
+                    Literal::Int(v) => lit(*v),
+                    Literal::Float(v) => lit(*v),
+                    Literal::Str(v) => lit(v.clone()),
+                    Literal::Bool(v) => lit(*v),
+                    Literal::Null => unreachable!(),
+                };
+                match op {
+                    CompareOp::Eq => lhs.eq(rhs),
+                    CompareOp::Ne => lhs.neq(rhs),
+                    CompareOp::Lt => lhs.lt(rhs),
+                    CompareOp::Le => lhs.lt_eq(rhs),
+                    CompareOp::Gt => lhs.gt(rhs),
+                    CompareOp::Ge => lhs.gt_eq(rhs),
+                }
+            }
+            FilterExpr::Not(inner) => inner.to_polars().not(),
+            FilterExpr::And(lhs, rhs) => lhs.to_polars().and(rhs.to_polars()),
+            FilterExpr::Or(lhs, rhs) => lhs.to_polars().or(rhs.to_polars()),
+        }
+    }
+}
+
+impl Literal {
+    fn describe(&self) -> &'static str {
+        match self {
+            Literal::Int(_) | Literal::Float(_) => "a number",
+            Literal::Str(_) => "a string",
+            Literal::Bool(_) => "a boolean",
+            Literal::Null => "null using an ordering operator",
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::LParen);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::RParen);
+            }
+            '"' | '\'' | '`' => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some(q) if q == c => break,
+                        Some(other) => text.push(other),
+                        None => return Err("Unterminated quote in filter expression".to_string()),
+                    }
+                }
+                tokens.push(if c == '`' {
+                    Token::Ident(text)
+                } else {
+                    Token::Str(text)
+                });
+            }
+            '=' | '!' | '<' | '>' => {
+                chars.next();
+                let followed_by_eq = chars.peek() == Some(&'=');
+                if followed_by_eq {
+                    chars.next();
+                }
+                let op = match (c, followed_by_eq) {
+                    ('=', true) => CompareOp::Eq,
+                    ('!', true) => CompareOp::Ne,
+                    ('<', false) => CompareOp::Lt,
+                    ('<', true) => CompareOp::Le,
+                    ('>', false) => CompareOp::Gt,
+                    ('>', true) => CompareOp::Ge,
+                    _ => return Err(format!("Unknown operator '{}' in filter expression", c)),
+                };
+                tokens.push(Token::Op(op));
+            }
+            c if c.is_ascii_digit() || c == '-' || c == '.' => {
+                let mut number = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '+') {
+                        break;
+                    }
+                    number.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Number(number));
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_' || c == '.') {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                let token = match word.to_uppercase().as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => Token::Ident(word),
+                };
+                tokens.push(token);
+            }
+            _ => return Err(format!("Unexpected character '{}' in filter expression", c)),
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent parser, precedence: comparison > NOT > AND > OR
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn parse_or(&mut self) -> Result<FilterExpr, String> {
+        let mut lhs = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            let rhs = self.parse_and()?;
+            lhs = FilterExpr::Or(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_and(&mut self) -> Result<FilterExpr, String> {
+        let mut lhs = self.parse_not()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            let rhs = self.parse_not()?;
+            lhs = FilterExpr::And(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_not(&mut self) -> Result<FilterExpr, String> {
+        if self.peek() == Some(&Token::Not) {
+            self.pos += 1;
+            let inner = self.parse_not()?;
+            return Ok(FilterExpr::Not(Box::new(inner)));
+        }
+        self.parse_atom()
+    }
+
+    fn parse_atom(&mut self) -> Result<FilterExpr, String> {
+        match self.next() {
+            Some(Token::LParen) => {
+                let expr = self.parse_or()?;
+                if self.next() != Some(Token::RParen) {
+                    return Err("Missing closing parenthesis in filter expression".to_string());
+                }
+                Ok(expr)
+            }
+            Some(Token::Ident(column)) => {
+                let Some(Token::Op(op)) = self.next() else {
+                    return Err(format!("Expected comparison operator after {}", column));
+                };
+                let value = self.parse_literal()?;
+                Ok(FilterExpr::Compare { column, op, value })
+            }
+            Some(_) => Err(format!(
+                "Expected column name at position {} in filter expression",
+                self.pos - 1
+            )),
+            None => Err("Unexpected end of filter expression".to_string()),
+        }
+    }
+
+    fn parse_literal(&mut self) -> Result<Literal, String> {
+        match self.next() {
+            Some(Token::Str(text)) => Ok(Literal::Str(text)),
+            Some(Token::Number(text)) => text
+                .parse::<i64>()
+                .map(Literal::Int)
+                .or_else(|_| text.parse::<f64>().map(Literal::Float))
+                .map_err(|_| format!("Invalid number: {}", text)),
+            Some(Token::Ident(word)) => match word.to_lowercase().as_str() {
+                "true" => Ok(Literal::Bool(true)),
+                "false" => Ok(Literal::Bool(false)),
+                "null" => Ok(Literal::Null),
+                _ => Err(format!(
+                    "Expected a value but found {}; quote strings with \"...\"",
+                    word
+                )),
+            },
+            _ => Err("Expected a value after comparison operator".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter_rows(df: &DataFrame, filter: &str) -> Vec<i64> {
+        let expr = FilterExpr::parse(filter).unwrap().to_polars();
+        let out = df.clone().lazy().filter(expr).collect().unwrap();
+        out.column("id")
+            .unwrap()
+            .i64()
+            .unwrap()
+            .into_no_null_iter()
+            .collect()
+    }
+
+    #[test]
+    fn test_precedence_and_grouping() {
+        let df = df!(
+            "id" => [1i64, 2, 3, 4],
+            "value" => [1.5, 3.0, 4.5, 6.0],
+            "site" => ["north", "south", "north", "south"],
+        )
+        .unwrap();
+
+        assert_eq!(
+            filter_rows(&df, r#"value > 3 AND site == "north""#),
+            vec![3]
+        );
+        assert_eq!(
+            filter_rows(&df, "id == 1 OR id >= 3 and site != 'north'"),
+            vec![1, 4]
+        );
+        assert_eq!(filter_rows(&df, "NOT (value <= 3.0 or id == 4)"), vec![3]);
+    }
+
+    #[test]
+    fn test_columns_and_types() {
+        let expr = FilterExpr::parse("`air temp` > -2.5e1 and site == 'x'").unwrap();
+        let schema = Schema::from_iter([
+            Field::new("air temp".into(), DataType::Float64),
+            Field::new("site".into(), DataType::String),
+        ]);
+        assert!(expr.check_types(&schema).is_ok());
+        assert!(FilterExpr::parse("missing == 1")
+            .unwrap()
+            .check_types(&schema)
+            .is_err());
+        assert!(FilterExpr::parse("site > 3")
+            .unwrap()
+            .check_types(&schema)
+            .is_err());
+        assert!(FilterExpr::parse("site < null")
+            .unwrap()
+            .check_types(&schema)
+            .is_err());
+    }
+
+    #[test]
+    fn test_invalid_expressions() {
+        assert!(FilterExpr::parse("").is_err());
+        assert!(FilterExpr::parse("a >").is_err());
+        assert!(FilterExpr::parse("a > 3 AND").is_err());
+        assert!(FilterExpr::parse("(a > 3").is_err());
+        assert!(FilterExpr::parse("a = 3").is_err());
+        assert!(FilterExpr::parse("a == north").is_err());
+        assert!(FilterExpr::parse("a == 'unterminated").is_err());
+        assert!(FilterExpr::parse("a; drop table").is_err());
+    }
+}
@@ -0,0 +1,83 @@
+//! When a function is failing often enough to be taken out of service. A run failed if its job
+//! failed or it produced an error log; a broken script would otherwise keep turning every
+//! matching upload into another error artifact until someone notices.
+
+/// Limits on a function's recent failures; with both unset functions are never quarantined
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HealthPolicy {
+    pub max_consecutive_failures: Option<usize>, // the latest this many runs all failed
+    pub max_failure_percent: Option<f64>,        // more than this share of the window failed
+    pub window: usize,                           // runs the failure rate is taken over
+}
+
+impl HealthPolicy {
+    /// How many of the latest runs `verdict` needs to see
+    pub fn runs_needed(&self) -> usize {
+        let consecutive = self.max_consecutive_failures.unwrap_or(0);
+        let window = if self.max_failure_percent.is_some() {
+            self.window
+        } else {
+            0
+        };
+        consecutive.max(window)
+    }
+
+    /// Why a function with these outcomes, newest first (true = failed), should be quarantined;
+    /// None if it is healthy enough. The rate is only judged once the window is full.
+    pub fn verdict(&self, failed: &[bool]) -> Option<String> {
+        if let Some(limit) = self.max_consecutive_failures.filter(|&limit| limit > 0) {
+            if failed.len() >= limit && failed[..limit].iter().all(|&f| f) {
+                return Some(format!("The last {} runs failed", limit));
+            }
+        }
+        if let Some(percent) = self.max_failure_percent {
+            if self.window > 0 && failed.len() >= self.window {
+                let failures = failed[..self.window].iter().filter(|&&f| f).count();
+                if failures as f64 * 100.0 > percent * self.window as f64 {
+                    return Some(format!(
+                        "{} of the last {} runs failed (more than {}%)",
+                        failures, self.window, percent
+                    ));
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_consecutive_failures() {
+        let policy = HealthPolicy {
+            max_consecutive_failures: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(policy.runs_needed(), 3);
+        assert_eq!(policy.verdict(&[true, true]), None);
+        assert_eq!(policy.verdict(&[true, true, false, true]), None);
+        assert_eq!(
+            policy.verdict(&[true, true, true, false]).as_deref(),
+            Some("The last 3 runs failed")
+        );
+        assert_eq!(HealthPolicy::default().verdict(&[true; 10]), None);
+    }
+
+    #[test]
+    fn test_failure_rate_over_full_window() {
+        let policy = HealthPolicy {
+            max_consecutive_failures: None,
+            max_failure_percent: Some(50.0),
+            window: 4,
+        };
+        assert_eq!(policy.runs_needed(), 4);
+        assert_eq!(policy.verdict(&[true, true, true]), None); // window not full yet
+        assert_eq!(policy.verdict(&[true, false, true, false]), None);
+        assert_eq!(
+            policy.verdict(&[true, false, true, true]).as_deref(),
+            Some("3 of the last 4 runs failed (more than 50%)")
+        );
+    }
+}
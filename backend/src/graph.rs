@@ -15,10 +15,7 @@ impl DirectedGraph {
 
     /// Add an edge from source to target
     pub fn add_edge(&mut self, from: String, to: String) {
-        self.edges
-            .entry(from)
-            .or_insert_with(HashSet::new)
-            .insert(to);
+        self.edges.entry(from).or_default().insert(to);
     }
 
     /// Add multiple edges (from each 'from' node to each 'to' node)
@@ -36,10 +33,8 @@ impl DirectedGraph {
         let mut rec_stack = HashSet::new();
 
         for node in self.edges.keys() {
-            if !visited.contains(node) {
-                if self.dfs_has_cycle(node, &mut visited, &mut rec_stack) {
-                    return true;
-                }
+            if !visited.contains(node) && self.dfs_has_cycle(node, &mut visited, &mut rec_stack) {
+                return true;
             }
         }
 
@@ -0,0 +1,342 @@
+use crate::repos::{StoredUpload, TagRepo};
+use crate::scanner::{ScanVerdict, Scanner};
+use crate::services::{
+    applied_extension_tag, index_upload_contents, remove_decompressed, remove_mirrored,
+    remove_thumbnails, run_anomaly_check, spawn_image_metadata, spawn_mirror_upload,
+    spawn_thumbnail, TagService,
+};
+use crate::AppState;
+use async_trait::async_trait;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Behavior that runs whenever uploads come and go, whoever created or deleted them: direct
+/// uploads, function outputs, built-in operations and retention sweeps. Hooks run in order and
+/// are awaited, so slow work belongs in a task on `state.tasks`; failures are logged by the
+/// hook itself and never stop the upload.
+#[async_trait]
+pub trait UploadHook: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Before a file sent by a client (`POST /uploads`, `/uploads/from-url`) is stored; any
+    /// verdict but `Clean` quarantines it instead
+    async fn on_received(
+        &self,
+        _state: &Arc<AppState>,
+        _original_filename: &str,
+        _data: &[u8],
+    ) -> ScanVerdict {
+        ScanVerdict::Clean
+    }
+
+    /// The same for a file streamed to disk before it is stored (WebDAV `PUT`); hooks that
+    /// look at the content implement both
+    async fn on_received_file(
+        &self,
+        _state: &Arc<AppState>,
+        _original_filename: &str,
+        _path: &Path,
+    ) -> ScanVerdict {
+        ScanVerdict::Clean
+    }
+
+    /// After the upload is stored and carries the tags it was created with
+    async fn on_created(&self, _state: &Arc<AppState>, _upload: &StoredUpload) {}
+
+    /// After `tag_ids` were added to an existing upload
+    async fn on_tagged(&self, _state: &Arc<AppState>, _upload: &StoredUpload, _tag_ids: &[String]) {
+    }
+
+    /// After the row is deleted; the file itself is removed by the caller
+    async fn on_deleted(&self, _state: &Arc<AppState>, _upload: &StoredUpload) {}
+}
+
+/// The hooks registered with the server, in the order they run
+#[derive(Default)]
+pub struct UploadHooks(Vec<Box<dyn UploadHook>>);
+
+impl UploadHooks {
+    /// The malware scan if a `scanner` is configured, extension tags, the outlier check if
+    /// `anomaly_tag` is set, thumbnails, image metadata, the decompressed-copy cache and the
+    /// content index
+    pub fn builtin(anomaly_tag: Option<String>, scanner: Option<Scanner>) -> Self {
+        let hooks = match scanner {
+            Some(scanner) => Self::default().register(VirusScanHook { scanner }),
+            None => Self::default(),
+        };
+        let hooks = hooks.register(ExtensionTagHook);
+        let hooks = match anomaly_tag {
+            Some(tag) => hooks.register(AnomalyCheckHook { tag }),
+            None => hooks,
+        };
+        hooks
+            .register(ThumbnailHook)
+            .register(ImageMetadataHook)
+            .register(DecompressedCacheHook)
+            .register(ContentIndexHook)
+            .register(MirrorHook)
+    }
+
+    pub fn register(mut self, hook: impl UploadHook + 'static) -> Self {
+        self.0.push(Box::new(hook));
+        self
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.0.iter().map(|hook| hook.name()).collect()
+    }
+
+    /// The first verdict other than `Clean`, if any hook objects to the file
+    pub async fn received(
+        &self,
+        state: &Arc<AppState>,
+        original_filename: &str,
+        data: &[u8],
+    ) -> ScanVerdict {
+        for hook in &self.0 {
+            let verdict = hook.on_received(state, original_filename, data).await;
+            if verdict != ScanVerdict::Clean {
+                return verdict;
+            }
+        }
+        ScanVerdict::Clean
+    }
+
+    /// The same for a file streamed to disk
+    pub async fn received_file(
+        &self,
+        state: &Arc<AppState>,
+        original_filename: &str,
+        path: &Path,
+    ) -> ScanVerdict {
+        for hook in &self.0 {
+            let verdict = hook.on_received_file(state, original_filename, path).await;
+            if verdict != ScanVerdict::Clean {
+                return verdict;
+            }
+        }
+        ScanVerdict::Clean
+    }
+
+    pub async fn created(&self, state: &Arc<AppState>, upload: &StoredUpload) {
+        for hook in &self.0 {
+            hook.on_created(state, upload).await;
+        }
+    }
+
+    pub async fn tagged(&self, state: &Arc<AppState>, upload: &StoredUpload, tag_ids: &[String]) {
+        for hook in &self.0 {
+            hook.on_tagged(state, upload, tag_ids).await;
+        }
+    }
+
+    pub async fn deleted(&self, state: &Arc<AppState>, upload: &StoredUpload) {
+        for hook in &self.0 {
+            hook.on_deleted(state, upload).await;
+        }
+    }
+}
+
+/// Passes files from clients through a malware scanner (--scan-command or --clamd-socket)
+pub struct VirusScanHook {
+    pub scanner: Scanner,
+}
+
+#[async_trait]
+impl UploadHook for VirusScanHook {
+    fn name(&self) -> &'static str {
+        "virus_scan"
+    }
+
+    async fn on_received(
+        &self,
+        _state: &Arc<AppState>,
+        original_filename: &str,
+        data: &[u8],
+    ) -> ScanVerdict {
+        log_verdict(original_filename, self.scanner.scan(data).await)
+    }
+
+    async fn on_received_file(
+        &self,
+        _state: &Arc<AppState>,
+        original_filename: &str,
+        path: &Path,
+    ) -> ScanVerdict {
+        log_verdict(original_filename, self.scanner.scan_file(path).await)
+    }
+}
+
+fn log_verdict(original_filename: &str, verdict: ScanVerdict) -> ScanVerdict {
+    match &verdict {
+        ScanVerdict::Clean => {}
+        ScanVerdict::Infected(finding) => {
+            tracing::warn!("🦠 {} is infected: {}", original_filename, finding)
+        }
+        ScanVerdict::Failed(e) => tracing::warn!("Could not scan {}: {}", original_filename, e),
+    }
+    verdict
+}
+
+/// Tags every upload with its extension (`.csv`), creating the tag on first use, as far as
+/// the extension tagging settings allow
+pub struct ExtensionTagHook;
+
+#[async_trait]
+impl UploadHook for ExtensionTagHook {
+    fn name(&self) -> &'static str {
+        "extension_tags"
+    }
+
+    async fn on_created(&self, state: &Arc<AppState>, upload: &StoredUpload) {
+        let settings = state.settings();
+        let policy = &settings.tag_policy;
+        let Some(tag_name) = applied_extension_tag(
+            policy,
+            &upload.original_filename,
+            upload.detected_mime_type.as_deref(),
+        ) else {
+            return;
+        };
+        match TagService::new(state)
+            .ensure(&tag_name, &policy.extension_tags.color)
+            .await
+        {
+            Ok(tag_id) => {
+                let _ = TagRepo::new(&state.db)
+                    .tag_upload(&upload.id, &tag_id)
+                    .await;
+            }
+            Err(e) => tracing::warn!("Could not create extension tag {}: {}", tag_name, e),
+        }
+    }
+}
+
+/// Runs the built-in outlier check on tables carrying `tag` (--anomaly-tag); tables with
+/// outliers get tagged `has-anomalies`
+pub struct AnomalyCheckHook {
+    pub tag: String,
+}
+
+impl AnomalyCheckHook {
+    fn spawn_check(&self, state: &Arc<AppState>, upload: &StoredUpload) {
+        state.tasks.spawn(
+            "anomaly_check",
+            run_anomaly_check(state.clone(), upload.id.clone()),
+        );
+    }
+}
+
+#[async_trait]
+impl UploadHook for AnomalyCheckHook {
+    fn name(&self) -> &'static str {
+        "anomaly_check"
+    }
+
+    async fn on_created(&self, state: &Arc<AppState>, upload: &StoredUpload) {
+        let tags = TagRepo::new(&state.db)
+            .for_upload(&upload.id)
+            .await
+            .unwrap_or_default();
+        if tags.iter().any(|t| t.name == self.tag) {
+            self.spawn_check(state, upload);
+        }
+    }
+
+    async fn on_tagged(&self, state: &Arc<AppState>, upload: &StoredUpload, tag_ids: &[String]) {
+        if let Ok(Some(tag_id)) = TagService::new(state).id_by_name(&self.tag).await {
+            if tag_ids.contains(&tag_id) {
+                self.spawn_check(state, upload);
+            }
+        }
+    }
+}
+
+/// Renders gallery thumbnails of new images and drops them with the upload
+pub struct ThumbnailHook;
+
+#[async_trait]
+impl UploadHook for ThumbnailHook {
+    fn name(&self) -> &'static str {
+        "thumbnails"
+    }
+
+    async fn on_created(&self, state: &Arc<AppState>, upload: &StoredUpload) {
+        spawn_thumbnail(
+            state,
+            &upload.id,
+            &upload.filename,
+            &upload.original_filename,
+            upload.detected_mime_type.as_deref(),
+        );
+    }
+
+    async fn on_deleted(&self, state: &Arc<AppState>, upload: &StoredUpload) {
+        remove_thumbnails(state, &upload.id).await;
+    }
+}
+
+/// Reads the dimensions and EXIF tags of new images; the rows go with the upload row
+pub struct ImageMetadataHook;
+
+#[async_trait]
+impl UploadHook for ImageMetadataHook {
+    fn name(&self) -> &'static str {
+        "image_metadata"
+    }
+
+    async fn on_created(&self, state: &Arc<AppState>, upload: &StoredUpload) {
+        spawn_image_metadata(state, upload);
+    }
+}
+
+/// Drops the decompressed copy previews of a compressed upload may have left behind
+pub struct DecompressedCacheHook;
+
+#[async_trait]
+impl UploadHook for DecompressedCacheHook {
+    fn name(&self) -> &'static str {
+        "decompressed_cache"
+    }
+
+    async fn on_deleted(&self, state: &Arc<AppState>, upload: &StoredUpload) {
+        if upload.compression.is_some() {
+            remove_decompressed(state, &upload.filename).await;
+        }
+    }
+}
+
+/// Indexes the contents of small text uploads for `GET /search/contents`; the index rows go
+/// with the upload row
+pub struct ContentIndexHook;
+
+#[async_trait]
+impl UploadHook for ContentIndexHook {
+    fn name(&self) -> &'static str {
+        "content_index"
+    }
+
+    async fn on_created(&self, state: &Arc<AppState>, upload: &StoredUpload) {
+        if let Err(e) = index_upload_contents(state, upload).await {
+            tracing::warn!("{}", e);
+        }
+    }
+}
+
+/// Copies new uploads to the --mirror-dir, and drops the copies of deleted ones
+pub struct MirrorHook;
+
+#[async_trait]
+impl UploadHook for MirrorHook {
+    fn name(&self) -> &'static str {
+        "mirror"
+    }
+
+    async fn on_created(&self, state: &Arc<AppState>, upload: &StoredUpload) {
+        spawn_mirror_upload(state, upload);
+    }
+
+    async fn on_deleted(&self, state: &Arc<AppState>, upload: &StoredUpload) {
+        remove_mirrored(state, &upload.filename).await;
+    }
+}
@@ -0,0 +1,400 @@
+//! Dimensions and EXIF/TIFF tags of image uploads, so microscope and camera captures carry
+//! their acquisition parameters (exposure, objective, instrument, time) next to the pixels.
+//! JPEG and PNG keep EXIF as an embedded TIFF structure; TIFF files hold the same tags
+//! in their own directories, so both are read by one directory walker.
+
+use image::{ImageDecoder, ImageFormat, ImageReader};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fs::File;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
+
+// Longest array and text values kept; longer ones are lookup tables or embedded documents
+const MAX_VALUES: u64 = 64;
+const MAX_TEXT_BYTES: usize = 4096;
+// A directory with more entries than this is not a real one
+const MAX_ENTRIES: u16 = 1024;
+
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_GPS_IFD: u16 = 0x8825;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageMetadata {
+    pub format: String, // png, jpg or tiff
+    pub width: u32,
+    pub height: u32,
+    pub exif: Map<String, Value>, // by EXIF tag name, e.g. `ExposureTime` or `Model`
+}
+
+/// Whether uploads of this extension get image metadata
+pub fn has_image_metadata(extension: &str) -> bool {
+    matches!(
+        extension.to_lowercase().as_str(),
+        "png" | "jpg" | "jpeg" | "tif" | "tiff"
+    )
+}
+
+/// Read the dimensions and EXIF tags of an image file without decoding its pixels
+pub fn read_image_metadata(path: &Path) -> Result<ImageMetadata, String> {
+    let reader = ImageReader::open(path)
+        .map_err(|e| e.to_string())?
+        .with_guessed_format()
+        .map_err(|e| e.to_string())?;
+    let format = reader
+        .format()
+        .ok_or_else(|| "Not a recognized image format".to_string())?;
+    let mut decoder = reader.into_decoder().map_err(|e| e.to_string())?;
+    let (width, height) = decoder.dimensions();
+
+    let exif = match format {
+        ImageFormat::Tiff => {
+            let file = File::open(path).map_err(|e| e.to_string())?;
+            read_exif(file)
+        }
+        _ => match decoder.exif_metadata().map_err(|e| e.to_string())? {
+            Some(blob) => {
+                // Some writers keep the APP1 marker's "Exif\0\0" in front of the TIFF header
+                let tiff = blob.strip_prefix(b"Exif\0\0").unwrap_or(&blob);
+                read_exif(Cursor::new(tiff))
+            }
+            None => Ok(Map::new()),
+        },
+    }
+    .map_err(|e| format!("Could not read EXIF: {}", e))?;
+
+    Ok(ImageMetadata {
+        format: format.extensions_str()[0].to_string(),
+        width,
+        height,
+        exif,
+    })
+}
+
+/// The named tags of a TIFF structure: the first image directory with its EXIF and GPS
+/// directories. BigTIFF files have no EXIF writers in practice and give no tags.
+pub fn read_exif<R: Read + Seek>(source: R) -> std::io::Result<Map<String, Value>> {
+    let mut reader = IfdReader {
+        source,
+        little_endian: true,
+    };
+    let header = reader.read_at(0, 8)?;
+    reader.little_endian = match &header[..2] {
+        b"II" => true,
+        b"MM" => false,
+        _ => return Err(std::io::Error::other("Not a TIFF structure")),
+    };
+    if reader.u16(&header[2..4]) != 42 {
+        return Ok(Map::new());
+    }
+
+    let mut tags = Map::new();
+    let ifd0 = reader.u32(&header[4..8]) as u64;
+    let mut sub_ifds = Vec::new();
+    for entry in reader.read_ifd(ifd0)? {
+        match entry.tag {
+            TAG_EXIF_IFD | TAG_GPS_IFD => {
+                if let Some(Value::Number(offset)) = reader.value(&entry)? {
+                    sub_ifds.push((entry.tag, offset.as_u64().unwrap_or_default()));
+                }
+            }
+            tag => reader.insert(&mut tags, tag_name(tag), &entry)?,
+        }
+    }
+    for (kind, offset) in sub_ifds {
+        // A broken sub-directory should not cost the tags already read
+        let Ok(entries) = reader.read_ifd(offset) else {
+            continue;
+        };
+        for entry in entries {
+            let name = match kind {
+                TAG_GPS_IFD => gps_tag_name(entry.tag),
+                _ => tag_name(entry.tag),
+            };
+            reader.insert(&mut tags, name, &entry)?;
+        }
+    }
+    Ok(tags)
+}
+
+struct IfdEntry {
+    tag: u16,
+    field_type: u16,
+    count: u32,
+    value_or_offset: [u8; 4],
+}
+
+struct IfdReader<R> {
+    source: R,
+    little_endian: bool,
+}
+
+impl<R: Read + Seek> IfdReader<R> {
+    fn u16(&self, bytes: &[u8]) -> u16 {
+        let b = [bytes[0], bytes[1]];
+        match self.little_endian {
+            true => u16::from_le_bytes(b),
+            false => u16::from_be_bytes(b),
+        }
+    }
+
+    fn u32(&self, bytes: &[u8]) -> u32 {
+        let b = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        match self.little_endian {
+            true => u32::from_le_bytes(b),
+            false => u32::from_be_bytes(b),
+        }
+    }
+
+    fn read_at(&mut self, offset: u64, len: usize) -> std::io::Result<Vec<u8>> {
+        let mut buf = vec![0u8; len];
+        self.source.seek(SeekFrom::Start(offset))?;
+        self.source.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn read_ifd(&mut self, offset: u64) -> std::io::Result<Vec<IfdEntry>> {
+        let count_bytes = self.read_at(offset, 2)?;
+        let count = self.u16(&count_bytes);
+        if count > MAX_ENTRIES {
+            return Err(std::io::Error::other("Implausible directory size"));
+        }
+        let table = self.read_at(offset + 2, count as usize * 12)?;
+        Ok(table
+            .chunks_exact(12)
+            .map(|raw| IfdEntry {
+                tag: self.u16(&raw[0..2]),
+                field_type: self.u16(&raw[2..4]),
+                count: self.u32(&raw[4..8]),
+                value_or_offset: [raw[8], raw[9], raw[10], raw[11]],
+            })
+            .collect())
+    }
+
+    fn insert(
+        &mut self,
+        tags: &mut Map<String, Value>,
+        name: Option<&str>,
+        entry: &IfdEntry,
+    ) -> std::io::Result<()> {
+        if let Some(name) = name {
+            if let Some(value) = self.value(entry)? {
+                tags.insert(name.to_string(), value);
+            }
+        }
+        Ok(())
+    }
+
+    /// The value of an entry as JSON: text, a number, or an array of numbers. None for
+    /// undefined bytes (maker notes, thumbnails) and overlong arrays.
+    fn value(&mut self, entry: &IfdEntry) -> std::io::Result<Option<Value>> {
+        let type_size: u64 = match entry.field_type {
+            2 => 1,               // ASCII
+            3 | 8 => 2,           // SHORT, SSHORT
+            4 | 9 | 11 => 4,      // LONG, SLONG, FLOAT
+            5 | 10 | 12 => 8,     // RATIONAL, SRATIONAL, DOUBLE
+            1 | 6 => 1,           // BYTE, SBYTE
+            _ => return Ok(None), // UNDEFINED and unknown types
+        };
+        let count = entry.count as u64;
+        if count == 0 || (entry.field_type != 2 && count > MAX_VALUES) {
+            return Ok(None);
+        }
+        let len = (type_size * count).min(MAX_TEXT_BYTES as u64) as usize;
+        let bytes = match len <= 4 {
+            true => entry.value_or_offset[..len].to_vec(),
+            false => self.read_at(self.u32(&entry.value_or_offset) as u64, len)?,
+        };
+
+        if entry.field_type == 2 {
+            let text = String::from_utf8_lossy(&bytes);
+            let text = text.trim_end_matches('\0').trim();
+            return Ok((!text.is_empty()).then(|| Value::String(text.to_string())));
+        }
+        let numbers: Vec<Value> = bytes
+            .chunks_exact(type_size as usize)
+            .map(|c| match entry.field_type {
+                1 => Value::from(c[0]),
+                6 => Value::from(c[0] as i8),
+                3 => Value::from(self.u16(c)),
+                8 => Value::from(self.u16(c) as i16),
+                4 => Value::from(self.u32(c)),
+                9 => Value::from(self.u32(c) as i32),
+                11 => Value::from(f32::from_bits(self.u32(c)) as f64),
+                12 => {
+                    let b: [u8; 8] = c.try_into().unwrap_or_default();
+                    Value::from(match self.little_endian {
+                        true => f64::from_le_bytes(b),
+                        false => f64::from_be_bytes(b),
+                    })
+                }
+                5 => rational(self.u32(&c[0..4]) as f64, self.u32(&c[4..8]) as f64),
+                _ => rational(
+                    self.u32(&c[0..4]) as i32 as f64,
+                    self.u32(&c[4..8]) as i32 as f64,
+                ),
+            })
+            .collect();
+        Ok(match numbers.len() {
+            1 => numbers.into_iter().next(),
+            _ => Some(Value::Array(numbers)),
+        })
+    }
+}
+
+// Rationals as decimals, e.g. an exposure of 1/250 as 0.004; 0/0 (unknown) as null
+fn rational(numerator: f64, denominator: f64) -> Value {
+    match denominator == 0.0 {
+        true => Value::Null,
+        false => Value::from(numerator / denominator),
+    }
+}
+
+/// Names of the image and EXIF tags worth keeping; structural tags (strip offsets, color
+/// maps) are left out
+fn tag_name(tag: u16) -> Option<&'static str> {
+    Some(match tag {
+        0x010e => "ImageDescription",
+        0x010f => "Make",
+        0x0110 => "Model",
+        0x0112 => "Orientation",
+        0x011a => "XResolution",
+        0x011b => "YResolution",
+        0x0128 => "ResolutionUnit",
+        0x0131 => "Software",
+        0x0132 => "DateTime",
+        0x013b => "Artist",
+        0x013c => "HostComputer",
+        0x8298 => "Copyright",
+        0x829a => "ExposureTime",
+        0x829d => "FNumber",
+        0x8822 => "ExposureProgram",
+        0x8827 => "ISOSpeedRatings",
+        0x9003 => "DateTimeOriginal",
+        0x9004 => "DateTimeDigitized",
+        0x9010 => "OffsetTime",
+        0x9011 => "OffsetTimeOriginal",
+        0x9201 => "ShutterSpeedValue",
+        0x9202 => "ApertureValue",
+        0x9204 => "ExposureBiasValue",
+        0x9206 => "SubjectDistance",
+        0x9207 => "MeteringMode",
+        0x9208 => "LightSource",
+        0x9209 => "Flash",
+        0x920a => "FocalLength",
+        0x9290 => "SubSecTime",
+        0x9291 => "SubSecTimeOriginal",
+        0xa002 => "PixelXDimension",
+        0xa003 => "PixelYDimension",
+        0xa20e => "FocalPlaneXResolution",
+        0xa20f => "FocalPlaneYResolution",
+        0xa210 => "FocalPlaneResolutionUnit",
+        0xa402 => "ExposureMode",
+        0xa403 => "WhiteBalance",
+        0xa404 => "DigitalZoomRatio",
+        0xa405 => "FocalLengthIn35mmFilm",
+        0xa420 => "ImageUniqueID",
+        0xa430 => "CameraOwnerName",
+        0xa431 => "BodySerialNumber",
+        0xa432 => "LensSpecification",
+        0xa433 => "LensMake",
+        0xa434 => "LensModel",
+        0xa435 => "LensSerialNumber",
+        _ => return None,
+    })
+}
+
+fn gps_tag_name(tag: u16) -> Option<&'static str> {
+    Some(match tag {
+        0x0001 => "GPSLatitudeRef",
+        0x0002 => "GPSLatitude",
+        0x0003 => "GPSLongitudeRef",
+        0x0004 => "GPSLongitude",
+        0x0005 => "GPSAltitudeRef",
+        0x0006 => "GPSAltitude",
+        0x0007 => "GPSTimeStamp",
+        0x0012 => "GPSMapDatum",
+        0x001d => "GPSDateStamp",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // A little-endian TIFF structure: Make and an EXIF directory in IFD0, an exposure time,
+    // an ISO and a lens model in the EXIF directory
+    fn sample_exif() -> Vec<u8> {
+        let mut tiff = b"II*\0\x08\0\0\0".to_vec();
+        let entry = |tag: u16, field_type: u16, count: u32, value: u32| {
+            [
+                &tag.to_le_bytes()[..],
+                &field_type.to_le_bytes(),
+                &count.to_le_bytes(),
+                &value.to_le_bytes(),
+            ]
+            .concat()
+        };
+        // IFD0 at 8: 2 entries, then the next-IFD offset; its data follows at 38
+        tiff.extend(2u16.to_le_bytes());
+        tiff.extend(entry(0x010f, 2, 6, 38));
+        tiff.extend(entry(0x8769, 4, 1, 44));
+        tiff.extend(0u32.to_le_bytes());
+        tiff.extend(b"Nikon\0");
+        // EXIF IFD at 44: 3 entries; the rational follows at 86, the text at 94
+        tiff.extend(3u16.to_le_bytes());
+        tiff.extend(entry(0x829a, 5, 1, 86));
+        tiff.extend(entry(0x8827, 3, 1, 400));
+        tiff.extend(entry(0xa434, 2, 10, 94));
+        tiff.extend(0u32.to_le_bytes());
+        tiff.extend(1u32.to_le_bytes());
+        tiff.extend(250u32.to_le_bytes());
+        tiff.extend(b"Plan 40x\0\0");
+        tiff
+    }
+
+    #[test]
+    fn test_read_exif() {
+        let tags = read_exif(Cursor::new(sample_exif())).unwrap();
+        assert_eq!(
+            Value::Object(tags),
+            json!({
+                "Make": "Nikon",
+                "ExposureTime": 0.004,
+                "ISOSpeedRatings": 400,
+                "LensModel": "Plan 40x",
+            })
+        );
+        assert!(read_exif(Cursor::new(b"GIF89a\0\0".to_vec())).is_err());
+    }
+
+    #[test]
+    fn test_read_image_metadata() {
+        let dir = std::env::temp_dir().join(format!("datalab-image-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("scan.png");
+        image::RgbImage::new(30, 20).save(&path).unwrap();
+        assert_eq!(
+            read_image_metadata(&path).unwrap(),
+            ImageMetadata {
+                format: "png".to_string(),
+                width: 30,
+                height: 20,
+                exif: Map::new(),
+            }
+        );
+
+        // A TIFF file is read as a whole, so its own tags are found
+        let path = dir.join("stack.tif");
+        image::GrayImage::new(8, 4).save(&path).unwrap();
+        let metadata = read_image_metadata(&path).unwrap();
+        assert_eq!((metadata.format.as_str(), metadata.width), ("tiff", 8));
+        assert_eq!(metadata.exif.get("ResolutionUnit"), Some(&Value::from(1)));
+
+        std::fs::write(dir.join("notes.png"), "not an image").unwrap();
+        assert!(read_image_metadata(&dir.join("notes.png")).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,160 @@
+//! Failure rates of functions over their completed runs, with triage labels separating
+//! environmental noise (a `flaky` instrument, a full disk) from real regressions. A run failed
+//! if its job failed or it left an error log, as for the health check.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// One completed run, as `JobRepo::outcomes` returns it
+pub struct JobOutcome {
+    pub function_id: String,
+    pub function_name: String,
+    pub failed: bool,
+    pub labels: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct OutcomeCounts {
+    pub runs: i64,
+    pub succeeded: i64,
+    pub failed: i64,
+    pub excluded: i64,             // failed runs carrying an excluded label
+    pub failure_rate: Option<f64>, // of the runs left after the exclusions; None without runs
+}
+
+impl OutcomeCounts {
+    fn add(&mut self, failed: bool, excluded: bool) {
+        self.runs += 1;
+        match (failed, excluded) {
+            (false, _) => self.succeeded += 1,
+            (true, false) => self.failed += 1,
+            (true, true) => {
+                self.failed += 1;
+                self.excluded += 1;
+            }
+        }
+    }
+
+    fn finish(&mut self) {
+        let counted = self.runs - self.excluded;
+        self.failure_rate =
+            (counted > 0).then(|| (self.failed - self.excluded) as f64 / counted as f64);
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct FunctionJobStats {
+    pub function_id: String,
+    pub function_name: String,
+    #[serde(flatten)]
+    pub counts: OutcomeCounts,
+    pub failures_by_label: BTreeMap<String, i64>, // a failure with two labels counts for both
+    pub unlabeled_failures: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JobStats {
+    pub excluded_labels: Vec<String>,
+    pub total: OutcomeCounts,
+    pub functions: Vec<FunctionJobStats>, // highest failure rate first
+}
+
+/// Trimmed and lowercased; None if empty or too long to be a label
+pub fn normalize_label(label: &str) -> Option<String> {
+    let label = label.trim().to_lowercase();
+    (!label.is_empty() && label.chars().count() <= 64).then_some(label)
+}
+
+/// Count the outcomes per function; failures carrying one of `excluded_labels` are left out
+/// of the failure rates
+pub fn summarize(outcomes: Vec<JobOutcome>, excluded_labels: Vec<String>) -> JobStats {
+    let mut total = OutcomeCounts::default();
+    let mut functions: BTreeMap<String, FunctionJobStats> = BTreeMap::new();
+    for outcome in outcomes {
+        let excluded = outcome
+            .labels
+            .iter()
+            .any(|label| excluded_labels.contains(label));
+        total.add(outcome.failed, excluded);
+        let stats = functions
+            .entry(outcome.function_id.clone())
+            .or_insert_with(|| FunctionJobStats {
+                function_id: outcome.function_id,
+                function_name: outcome.function_name,
+                counts: OutcomeCounts::default(),
+                failures_by_label: BTreeMap::new(),
+                unlabeled_failures: 0,
+            });
+        stats.counts.add(outcome.failed, excluded);
+        if outcome.failed {
+            if outcome.labels.is_empty() {
+                stats.unlabeled_failures += 1;
+            }
+            for label in outcome.labels {
+                *stats.failures_by_label.entry(label).or_default() += 1;
+            }
+        }
+    }
+
+    total.finish();
+    let mut functions: Vec<FunctionJobStats> = functions.into_values().collect();
+    for stats in &mut functions {
+        stats.counts.finish();
+    }
+    functions.sort_by(|a, b| {
+        let rate = |stats: &FunctionJobStats| stats.counts.failure_rate.unwrap_or(0.0);
+        rate(b)
+            .total_cmp(&rate(a))
+            .then_with(|| a.function_name.cmp(&b.function_name))
+    });
+    JobStats {
+        excluded_labels,
+        total,
+        functions,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outcome(function: &str, failed: bool, labels: &[&str]) -> JobOutcome {
+        JobOutcome {
+            function_id: function.to_string(),
+            function_name: function.to_string(),
+            failed,
+            labels: labels.iter().map(|label| label.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_normalize_label() {
+        assert_eq!(normalize_label("  Flaky "), Some("flaky".to_string()));
+        assert_eq!(normalize_label(" "), None);
+        assert_eq!(normalize_label(&"x".repeat(65)), None);
+    }
+
+    #[test]
+    fn test_excluded_labels_leave_the_failure_rate() {
+        let outcomes = vec![
+            outcome("a", true, &["flaky"]),
+            outcome("a", true, &[]),
+            outcome("a", false, &[]),
+            outcome("a", false, &["flaky"]),
+            outcome("b", false, &[]),
+        ];
+        let stats = summarize(outcomes, vec!["flaky".to_string()]);
+        let a = &stats.functions[0];
+        assert_eq!(a.function_id, "a");
+        assert_eq!(
+            (a.counts.runs, a.counts.failed, a.counts.excluded),
+            (4, 2, 1)
+        );
+        assert_eq!(a.counts.failure_rate, Some(1.0 / 3.0));
+        assert_eq!(a.failures_by_label["flaky"], 1);
+        assert_eq!(a.unlabeled_failures, 1);
+        assert_eq!(stats.functions[1].counts.failure_rate, Some(0.0));
+        assert_eq!(stats.total.runs, 5);
+        assert_eq!(stats.total.failure_rate, Some(1.0 / 4.0));
+    }
+}
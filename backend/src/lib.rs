@@ -0,0 +1,773 @@
+//! The DataLab server as a library: build a [`Server`] from a [`Config`] to embed it in another
+//! application or to run it in-process in end-to-end tests, or [`serve`] it like the
+//! `datalab-backend` binary does.
+
+mod access;
+mod anomalies;
+mod archive;
+mod array_inspector;
+mod capacity;
+mod cluster;
+mod compression;
+mod cost_estimate;
+pub mod ctl;
+mod custody;
+mod diagrams;
+mod executor;
+mod feeds;
+mod filter_expr;
+mod function_health;
+mod graph;
+mod hooks;
+mod image_metadata;
+mod job_stats;
+mod manifest;
+mod media_info;
+mod mime_sniff;
+mod models;
+mod orphans;
+mod plot;
+mod preflight;
+mod reports;
+mod repos;
+mod routes;
+mod scanner;
+mod scheduler;
+mod script_analysis;
+mod search;
+mod services;
+mod settings;
+mod snapshots;
+mod sql_query;
+mod supervisor;
+mod table_parser;
+mod tag_expr;
+mod tag_policy;
+mod text_preview;
+mod throttle;
+mod thumbnails;
+mod timestamps;
+mod triggers;
+mod units;
+mod warm_pool;
+mod watch_folders;
+mod waveform;
+mod webdav;
+
+use axum::Router;
+use clap::Args;
+use cluster::ClusterConfig;
+use executor::ScriptExecutor;
+use hooks::UploadHooks;
+use scanner::Scanner;
+use services::TagCache;
+use settings::{SettingValues, Settings};
+use sqlx::sqlite::SqlitePool;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use supervisor::TaskSupervisor;
+use tower_http::cors::{Any, CorsLayer};
+use tower_http::trace::TraceLayer;
+
+pub use webdav::DavAccess;
+
+/// Errors setting up or running the server
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Server settings; the `datalab-backend` binary reads them from its command line and `DL_*`
+/// environment variables
+#[derive(Args, Debug, Clone)]
+pub struct Config {
+    /// Server host address
+    #[arg(long, env = "DL_HOST", default_value = "127.0.0.1")]
+    pub host: String,
+
+    /// Server port
+    #[arg(short, long, env = "DL_PORT", default_value = "8080")]
+    pub port: u16,
+
+    /// Database URL
+    #[arg(long, env = "DL_DATABASE_URL", default_value = "sqlite:../datalab.db")]
+    pub database_url: String,
+
+    /// JSON file with settings to use instead of their command-line values; re-read on SIGHUP
+    /// or `POST /api/admin/reload` (see `settings.rs` for what it can set)
+    #[arg(long, env = "DL_CONFIG_FILE")]
+    pub config_file: Option<PathBuf>,
+
+    /// Maximum concurrent function executions
+    #[arg(long, env = "DL_MAX_CONCURRENT_JOBS", default_value = "10")]
+    pub max_concurrent_jobs: usize,
+
+    /// Uploads directory
+    #[arg(long, env = "DL_UPLOADS_DIR", default_value = "uploads")]
+    pub uploads_dir: PathBuf,
+
+    /// Scripts directory
+    #[arg(long, env = "DL_SCRIPTS_DIR", default_value = "scripts")]
+    pub scripts_dir: PathBuf,
+
+    /// Output directory
+    #[arg(long, env = "DL_OUTPUT_DIR", default_value = "output")]
+    pub output_dir: PathBuf,
+
+    /// Cache of image thumbnails
+    #[arg(long, env = "DL_THUMBNAILS_DIR", default_value = "thumbnails")]
+    pub thumbnails_dir: PathBuf,
+
+    /// Cache of compressed uploads decompressed for previews; safe to clear at any time
+    #[arg(long, env = "DL_DECOMPRESSED_DIR", default_value = "decompressed")]
+    pub decompressed_dir: PathBuf,
+
+    /// Files the malware scan flagged, kept until released or discarded
+    #[arg(long, env = "DL_QUARANTINE_DIR", default_value = "quarantine")]
+    pub quarantine_dir: PathBuf,
+
+    /// uv binary used to run functions locally
+    #[arg(long, env = "DL_UV_BIN", default_value = "uv")]
+    pub uv_bin: PathBuf,
+
+    /// Keep the resolved environments of this many function versions so local runs skip
+    /// `uv run` startup (0 disables the warm pool)
+    #[arg(long, env = "DL_WARM_POOL_SIZE", default_value = "0")]
+    pub warm_pool_size: usize,
+
+    /// Disable a function after this many consecutive failed runs (0 never does), until
+    /// someone enables it again
+    #[arg(long, env = "DL_QUARANTINE_AFTER_FAILURES", default_value = "10")]
+    pub quarantine_after_failures: usize,
+
+    /// Also disable a function when more than this percentage of its last
+    /// --quarantine-window runs failed (unset never does)
+    #[arg(long, env = "DL_QUARANTINE_FAILURE_PERCENT")]
+    pub quarantine_failure_percent: Option<f64>,
+
+    /// Number of latest runs --quarantine-failure-percent is taken over
+    #[arg(long, env = "DL_QUARANTINE_WINDOW", default_value = "20")]
+    pub quarantine_window: usize,
+
+    /// Hard-link uploads whose content already exists instead of storing another copy
+    #[arg(long, env = "DL_DEDUPE_UPLOADS")]
+    pub dedupe_uploads: bool,
+
+    /// Store text uploads (CSV, logs, JSON) zstd-compressed; they are decompressed on download
+    /// and when staged for functions
+    #[arg(long, env = "DL_COMPRESS_UPLOADS")]
+    pub compress_uploads: bool,
+
+    /// Tag that runs the built-in outlier check on CSV/Parquet uploads (disabled if unset)
+    #[arg(long, env = "DL_ANOMALY_TAG")]
+    pub anomaly_tag: Option<String>,
+
+    /// Malware scanner run on every file clients upload: gets the file on stdin, exits 0 when
+    /// clean and 1 when infected (e.g. `clamscan --no-summary -`)
+    #[arg(long, env = "DL_SCAN_COMMAND")]
+    pub scan_command: Option<String>,
+
+    /// Scan uploads with the clamd daemon on this Unix socket (unless --scan-command is set)
+    #[arg(long, env = "DL_CLAMD_SOCKET")]
+    pub clamd_socket: Option<PathBuf>,
+
+    /// Largest request body, and so the largest upload, in MB (all files of a multipart upload
+    /// together)
+    #[arg(long, env = "DL_MAX_UPLOAD_SIZE_MB", default_value = "1024")]
+    pub max_upload_size_mb: u64,
+
+    /// Fastest a single upload or download may go, in KB/s (unlimited if unset), so large
+    /// exports do not crowd out instruments on a shared network
+    #[arg(long, env = "DL_MAX_TRANSFER_RATE")]
+    pub max_transfer_rate: Option<u64>,
+
+    /// Copy every stored file to this directory (e.g. a mounted bucket or another disk) in the
+    /// background, with periodic snapshots of the database, for disaster recovery
+    #[arg(long, env = "DL_MIRROR_DIR")]
+    pub mirror_dir: Option<PathBuf>,
+
+    /// Minutes between retries of uploads missing from the --mirror-dir and database snapshots
+    #[arg(long, env = "DL_MIRROR_INTERVAL_MINUTES", default_value = "60")]
+    pub mirror_interval_minutes: u64,
+
+    /// Serve the uploads as a WebDAV folder at `/dav/` (disabled if unset)
+    #[arg(long, env = "DL_WEBDAV", value_enum)]
+    pub webdav: Option<webdav::DavAccess>,
+
+    /// Directories whose new files are registered as uploads, comma-separated (e.g. the share
+    /// an instrument writes to); files are left in place
+    #[arg(long, env = "DL_WATCH_DIRS", value_delimiter = ',')]
+    pub watch_dirs: Vec<PathBuf>,
+
+    /// Tags given to files picked up from the watch folders, comma-separated; missing tags are
+    /// created
+    #[arg(long, env = "DL_WATCH_TAGS", value_delimiter = ',')]
+    pub watch_tags: Vec<String>,
+
+    /// Seconds between polls of the watch folders; a file is picked up once it did not change
+    /// between two polls
+    #[arg(long, env = "DL_WATCH_INTERVAL_SECONDS", default_value = "10")]
+    pub watch_interval_seconds: u64,
+
+    /// Directories that import manifests may name files in, comma-separated (e.g. where an old
+    /// archive is mounted); without any, manifests can only name URLs
+    #[arg(long, env = "DL_IMPORT_DIRS", value_delimiter = ',')]
+    pub import_dirs: Vec<PathBuf>,
+
+    /// Color of the tags DataLab creates itself, e.g. extension and watch folder tags
+    #[arg(long, env = "DL_SYSTEM_TAG_COLOR", default_value = tag_policy::DEFAULT_SYSTEM_COLOR)]
+    pub system_tag_color: String,
+
+    /// Name prefixes of system tags, comma-separated; those tags cannot be renamed.
+    /// Extension tags are named `.csv` etc., so keep `.` among them
+    #[arg(
+        long,
+        env = "DL_SYSTEM_TAG_PREFIXES",
+        value_delimiter = ',',
+        default_value = tag_policy::DEFAULT_SYSTEM_PREFIX
+    )]
+    pub system_tag_prefixes: Vec<String>,
+
+    /// Characters tag names may not contain
+    #[arg(long, env = "DL_TAG_FORBIDDEN_CHARS", default_value = tag_policy::DEFAULT_FORBIDDEN_CHARACTERS)]
+    pub tag_forbidden_chars: String,
+
+    /// Hex colors, comma-separated, given in turn to tags created without a color
+    #[arg(
+        long,
+        env = "DL_TAG_PALETTE",
+        value_delimiter = ',',
+        default_value = tag_policy::DEFAULT_PALETTE
+    )]
+    pub tag_palette: Vec<String>,
+
+    /// Tag every upload with its extension, e.g. `.csv` (`--extension-tags false` turns it off)
+    #[arg(long, env = "DL_EXTENSION_TAGS", default_value_t = true, action = clap::ArgAction::Set)]
+    pub extension_tags: bool,
+
+    /// Only tag uploads with these extensions, comma-separated (e.g. `csv,parquet`; all if unset)
+    #[arg(long, env = "DL_EXTENSION_TAG_ALLOWLIST", value_delimiter = ',')]
+    pub extension_tag_allowlist: Vec<String>,
+
+    /// Color of the extension tags DataLab creates (--system-tag-color if unset)
+    #[arg(long, env = "DL_EXTENSION_TAG_COLOR")]
+    pub extension_tag_color: Option<String>,
+
+    /// Largest file `/uploads/from-url` will download, in MB
+    #[arg(long, env = "DL_URL_MAX_SIZE_MB", default_value = "1024")]
+    pub url_max_size_mb: u64,
+
+    /// MIME types `/uploads/from-url` accepts, comma-separated (e.g. `text/csv,image/*`; any if
+    /// unset)
+    #[arg(long, env = "DL_URL_ALLOWED_TYPES", value_delimiter = ',')]
+    pub url_allowed_types: Vec<String>,
+
+    /// Total size all uploads may take up, in MB (unlimited if unset)
+    #[arg(long, env = "DL_STORAGE_QUOTA_MB")]
+    pub storage_quota_mb: Option<u64>,
+
+    /// Index the contents of text uploads up to this size, in MB, for content search (0
+    /// disables it)
+    #[arg(long, env = "DL_CONTENT_INDEX_MAX_MB", default_value = "1")]
+    pub content_index_max_mb: u64,
+
+    /// Log files on disk without a database row (and rows without a file) at startup
+    #[arg(long, env = "DL_GC_ON_STARTUP")]
+    pub gc_on_startup: bool,
+
+    /// With --gc-on-startup, also delete the orphaned files
+    #[arg(long, env = "DL_GC_DELETE_ORPHANS", requires = "gc_on_startup")]
+    pub gc_delete_orphans: bool,
+
+    /// Seconds to wait for running jobs on shutdown before marking them failed
+    #[arg(long, env = "DL_SHUTDOWN_TIMEOUT_SECS", default_value = "30")]
+    pub shutdown_timeout_secs: u64,
+
+    /// Base URL remote workers use to reach this server, e.g. `http://datalab.lab:8080`
+    #[arg(long, env = "DL_PUBLIC_URL")]
+    pub public_url: Option<String>,
+
+    /// Directory shared with the Slurm/Kubernetes nodes, mounted at the same path; enables remote
+    /// executors
+    #[arg(long, env = "DL_CLUSTER_SHARED_DIR")]
+    pub cluster_shared_dir: Option<PathBuf>,
+
+    /// Seconds between status checks of remote jobs
+    #[arg(long, env = "DL_CLUSTER_POLL_SECONDS", default_value = "15")]
+    pub cluster_poll_seconds: u64,
+
+    /// sbatch script template for Slurm runs (built-in default if unset)
+    #[arg(long, env = "DL_SLURM_TEMPLATE")]
+    pub slurm_template: Option<PathBuf>,
+
+    /// kubectl binary used for Kubernetes runs
+    #[arg(long, env = "DL_KUBECTL_BIN", default_value = "kubectl")]
+    pub kubectl_bin: PathBuf,
+
+    /// Namespace Kubernetes jobs are created in
+    #[arg(long, env = "DL_K8S_NAMESPACE", default_value = "default")]
+    pub k8s_namespace: String,
+
+    /// Container image for Kubernetes runs, with `uv` installed (Kubernetes disabled if unset)
+    #[arg(long, env = "DL_K8S_IMAGE")]
+    pub k8s_image: Option<String>,
+
+    /// PersistentVolumeClaim mounted at the shared directory in Kubernetes pods
+    #[arg(long, env = "DL_K8S_VOLUME_CLAIM")]
+    pub k8s_volume_claim: Option<String>,
+
+    /// DuckDB CLI used for `"engine": "duckdb"` SQL queries
+    #[cfg(feature = "duckdb")]
+    #[arg(long, env = "DL_DUCKDB_BIN", default_value = "duckdb")]
+    pub duckdb_bin: PathBuf,
+}
+
+// The same settings as the command line without arguments
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            host: "127.0.0.1".to_string(),
+            port: 8080,
+            database_url: "sqlite:../datalab.db".to_string(),
+            config_file: None,
+            max_concurrent_jobs: 10,
+            uploads_dir: PathBuf::from("uploads"),
+            scripts_dir: PathBuf::from("scripts"),
+            output_dir: PathBuf::from("output"),
+            thumbnails_dir: PathBuf::from("thumbnails"),
+            decompressed_dir: PathBuf::from("decompressed"),
+            quarantine_dir: PathBuf::from("quarantine"),
+            uv_bin: PathBuf::from("uv"),
+            warm_pool_size: 0,
+            quarantine_after_failures: 10,
+            quarantine_failure_percent: None,
+            quarantine_window: 20,
+            dedupe_uploads: false,
+            compress_uploads: false,
+            anomaly_tag: None,
+            scan_command: None,
+            clamd_socket: None,
+            max_upload_size_mb: 1024,
+            max_transfer_rate: None,
+            mirror_dir: None,
+            mirror_interval_minutes: 60,
+            webdav: None,
+            watch_dirs: Vec::new(),
+            watch_tags: Vec::new(),
+            watch_interval_seconds: 10,
+            import_dirs: Vec::new(),
+            system_tag_color: tag_policy::DEFAULT_SYSTEM_COLOR.to_string(),
+            system_tag_prefixes: vec![tag_policy::DEFAULT_SYSTEM_PREFIX.to_string()],
+            tag_forbidden_chars: tag_policy::DEFAULT_FORBIDDEN_CHARACTERS.to_string(),
+            tag_palette: tag_policy::DEFAULT_PALETTE
+                .split(',')
+                .map(str::to_string)
+                .collect(),
+            extension_tags: true,
+            extension_tag_allowlist: Vec::new(),
+            extension_tag_color: None,
+            url_max_size_mb: 1024,
+            url_allowed_types: Vec::new(),
+            storage_quota_mb: None,
+            content_index_max_mb: 1,
+            gc_on_startup: false,
+            gc_delete_orphans: false,
+            shutdown_timeout_secs: 30,
+            public_url: None,
+            cluster_shared_dir: None,
+            cluster_poll_seconds: 15,
+            slurm_template: None,
+            kubectl_bin: PathBuf::from("kubectl"),
+            k8s_namespace: "default".to_string(),
+            k8s_image: None,
+            k8s_volume_claim: None,
+            #[cfg(feature = "duckdb")]
+            duckdb_bin: PathBuf::from("duckdb"),
+        }
+    }
+}
+
+/// Shared state of a server; handlers and background tasks hold it as `Arc<AppState>`
+pub struct AppState {
+    db: SqlitePool,
+    executor: ScriptExecutor,
+    job_slots: Arc<scheduler::FairScheduler>, // local executions, shared fairly across sources
+    duckdb_bin: Option<PathBuf>,              // None unless built with the `duckdb` feature
+    hooks: UploadHooks,
+    tag_cache: TagCache,
+    dedupe_uploads: bool,
+    compress_uploads: bool,
+    max_upload_bytes: u64,
+    http: reqwest::Client,
+    public_url: Option<String>,
+    content_index_max_bytes: u64, // 0 disables content search
+    thumbnails_dir: PathBuf,
+    decompressed_dir: PathBuf,
+    quarantine_dir: PathBuf,
+    webdav: Option<webdav::DavAccess>,
+    watch_folders: Vec<watch_folders::WatchFolder>,
+    import_dirs: Vec<PathBuf>,   // where import manifests may read files
+    mirror_dir: Option<PathBuf>, // where stored files and database snapshots are copied to
+    settings: RwLock<Arc<Settings>>, // replaced as a whole when reloaded
+    command_line_settings: SettingValues, // what a reload falls back to
+    config_file: Option<PathBuf>,
+    tasks: TaskSupervisor,
+}
+
+impl AppState {
+    /// The database, e.g. to inspect what requests did in tests
+    pub fn db(&self) -> &SqlitePool {
+        &self.db
+    }
+
+    /// The settings in effect; hold on to them for one request or job step, so a reload in
+    /// between does not mix old and new values
+    fn settings(&self) -> Arc<Settings> {
+        self.settings.read().unwrap().clone()
+    }
+}
+
+/// A DataLab server with its database migrated and background work started, not yet
+/// accepting requests
+pub struct Server {
+    state: Arc<AppState>,
+    router: Router,
+    shutdown_timeout: Duration,
+}
+
+impl Server {
+    /// Create the storage directories, connect to and migrate the database, and start the
+    /// background tasks (report scheduler, retention sweeper, watch folders, mirroring)
+    pub async fn new(config: Config) -> Result<Self, Error> {
+        let command_line_settings = SettingValues::from_config(&config);
+        let settings = Settings::load(&command_line_settings, config.config_file.as_deref())?;
+
+        // Create necessary directories
+        tokio::fs::create_dir_all(&config.uploads_dir).await?;
+        tokio::fs::create_dir_all(&config.scripts_dir).await?;
+        tokio::fs::create_dir_all(&config.output_dir).await?;
+        tokio::fs::create_dir_all(&config.thumbnails_dir).await?;
+        tokio::fs::create_dir_all(&config.decompressed_dir).await?;
+        tokio::fs::create_dir_all(&config.quarantine_dir).await?;
+
+        // Initialize database
+        let db = SqlitePool::connect(&config.database_url).await?;
+
+        // Run database migrations
+        sqlx::migrate!("./migrations").run(&db).await?;
+
+        tracing::info!("✅ Database initialized");
+
+        // Initialize script executor
+        let mut executor =
+            ScriptExecutor::new(config.scripts_dir, config.uploads_dir, config.output_dir)
+                .with_uv_bin(config.uv_bin);
+        if config.warm_pool_size > 0 {
+            executor = executor.with_warm_pool(config.warm_pool_size);
+            tracing::info!(
+                "✅ Warm pool enabled ({} environments)",
+                config.warm_pool_size
+            );
+        }
+        if let Some(shared_dir) = config.cluster_shared_dir {
+            tokio::fs::create_dir_all(&shared_dir).await?;
+            let sbatch_template = match &config.slurm_template {
+                Some(path) => tokio::fs::read_to_string(path).await?,
+                None => cluster::DEFAULT_SBATCH_TEMPLATE.to_string(),
+            };
+            tracing::info!(
+                "✅ Cluster execution enabled (shared dir: {})",
+                shared_dir.display()
+            );
+            executor = executor.with_cluster(ClusterConfig {
+                shared_dir,
+                poll_interval: std::time::Duration::from_secs(config.cluster_poll_seconds.max(1)),
+                sbatch_template,
+                kubectl_bin: config.kubectl_bin,
+                k8s_namespace: config.k8s_namespace,
+                k8s_image: config.k8s_image,
+                k8s_volume_claim: config.k8s_volume_claim,
+            });
+        }
+
+        // Limit concurrent function executions, taking turns across job sources
+        let job_slots = scheduler::FairScheduler::new(settings.values.max_concurrent_jobs);
+        tracing::info!(
+            "✅ Job scheduler initialized (max concurrent: {})",
+            settings.values.max_concurrent_jobs
+        );
+
+        // Create shared application state
+        #[cfg(feature = "duckdb")]
+        let duckdb_bin = Some(config.duckdb_bin);
+        #[cfg(not(feature = "duckdb"))]
+        let duckdb_bin = None;
+
+        let scanner = Scanner::from_config(config.scan_command.as_deref(), config.clamd_socket);
+        match &scanner {
+            Some(scanner) => tracing::info!("✅ Scanning uploads with {}", scanner.describe()),
+            None => tracing::info!("Uploads are not scanned for malware"),
+        }
+        let hooks = UploadHooks::builtin(config.anomaly_tag, scanner);
+        tracing::info!("✅ Upload hooks: {}", hooks.names().join(", "));
+
+        for name in &config.watch_tags {
+            settings
+                .tag_policy
+                .validate_name(name)
+                .map_err(|e| format!("Invalid watch tag {}: {}", name, e))?;
+        }
+
+        let watch_folders: Vec<watch_folders::WatchFolder> = config
+            .watch_dirs
+            .into_iter()
+            .map(|path| watch_folders::WatchFolder {
+                path,
+                tags: config.watch_tags.clone(),
+            })
+            .collect();
+        for folder in &watch_folders {
+            if !folder.path.is_dir() {
+                tracing::warn!(
+                    "Watch folder {} is not a directory (yet)",
+                    folder.path.display()
+                );
+            }
+            tracing::info!("✅ Watching {} for new files", folder.path.display());
+        }
+
+        let state = Arc::new(AppState {
+            db,
+            executor,
+            job_slots,
+            duckdb_bin,
+            hooks,
+            tag_cache: TagCache::default(),
+            dedupe_uploads: config.dedupe_uploads,
+            compress_uploads: config.compress_uploads,
+            max_upload_bytes: config.max_upload_size_mb * 1024 * 1024,
+            http: reqwest::Client::builder()
+                .connect_timeout(std::time::Duration::from_secs(30))
+                .build()?,
+            public_url: config.public_url,
+            content_index_max_bytes: config.content_index_max_mb * 1024 * 1024,
+            thumbnails_dir: config.thumbnails_dir,
+            decompressed_dir: config.decompressed_dir,
+            quarantine_dir: config.quarantine_dir,
+            webdav: config.webdav,
+            watch_folders,
+            import_dirs: config.import_dirs,
+            mirror_dir: config.mirror_dir,
+            settings: RwLock::new(Arc::new(settings)),
+            command_line_settings,
+            config_file: config.config_file,
+            tasks: TaskSupervisor::new(),
+        });
+
+        // Re-read the config file on SIGHUP
+        if let Some(config_file) = &state.config_file {
+            tracing::info!("✅ Settings from {}", config_file.display());
+            spawn_reload_on_hangup(state.clone());
+        }
+
+        // Render scheduled reports in the background
+        routes::spawn_report_scheduler(state.clone());
+
+        // Delete uploads that outlived their retention rules
+        routes::spawn_retention_sweeper(state.clone());
+
+        // Register files dropped into the watch folders
+        routes::spawn_watch_folders(
+            state.clone(),
+            std::time::Duration::from_secs(config.watch_interval_seconds.max(1)),
+        );
+
+        // Carry on with import manifests a restart interrupted
+        routes::resume_manifest_imports(state.clone()).await;
+
+        // Copy what is missing from the mirror, and the database, now and then
+        if let Some(mirror_dir) = &state.mirror_dir {
+            tracing::info!("✅ Mirroring uploads to {}", mirror_dir.display());
+        }
+        services::spawn_mirror_sync(
+            state.clone(),
+            std::time::Duration::from_secs(config.mirror_interval_minutes.max(1) * 60),
+        );
+
+        // Index the text uploads stored before content search was enabled
+        let indexing_state = state.clone();
+        state.tasks.spawn("content_index", async move {
+            match services::index_missing_contents(&indexing_state).await {
+                Ok(0) => {}
+                Ok(indexed) => tracing::info!("✅ Indexed the contents of {} upload(s)", indexed),
+                Err(e) => tracing::warn!("Content indexing failed: {}", e),
+            }
+        });
+
+        // Check storage against the database
+        if config.gc_on_startup {
+            routes::spawn_orphan_scan(state.clone(), config.gc_delete_orphans);
+        }
+
+        // Build our application with routes
+        // Enable CORS for frontend communication and share links. Not for WebDAV: the layer
+        // answers every OPTIONS request itself, and WebDAV clients need the server's own answer.
+        let mut app = Router::new()
+            .nest("/api", routes::api_routes(state.clone()))
+            .merge(routes::share_routes())
+            .layer(
+                CorsLayer::new()
+                    .allow_origin(Any)
+                    .allow_methods(Any)
+                    .allow_headers(Any),
+            );
+        if let Some(access) = state.webdav {
+            tracing::info!("✅ WebDAV ({:?}) at /dav/", access);
+            app = app.merge(routes::webdav_routes(&state));
+        }
+        let app = app
+            .with_state(state.clone())
+            // Add tracing
+            .layer(TraceLayer::new_for_http());
+        let app = match config.max_transfer_rate {
+            Some(kb_per_second) => {
+                tracing::info!("✅ Transfers limited to {} KB/s", kb_per_second);
+                app.layer(axum::middleware::from_fn_with_state(
+                    kb_per_second * 1024,
+                    throttle::limit_transfer_rate,
+                ))
+            }
+            None => app,
+        };
+
+        Ok(Self {
+            state,
+            router: app,
+            shutdown_timeout: Duration::from_secs(config.shutdown_timeout_secs),
+        })
+    }
+
+    pub fn state(&self) -> &Arc<AppState> {
+        &self.state
+    }
+
+    /// The whole application (`/api`, share links and WebDAV) with its middleware; serve it
+    /// with connect info, as `run` does, or call it directly with `tower::ServiceExt::oneshot`
+    pub fn router(&self) -> Router {
+        self.router.clone()
+    }
+
+    /// Serve requests on `listener` until `shutdown` resolves, then let running jobs finish
+    /// for up to `--shutdown-timeout-secs`; jobs still running after that are marked failed
+    pub async fn run(
+        self,
+        listener: tokio::net::TcpListener,
+        shutdown: impl Future<Output = ()> + Send + 'static,
+    ) -> Result<(), Error> {
+        axum::serve(
+            listener,
+            self.router
+                .into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .with_graceful_shutdown(shutdown)
+        .await?;
+
+        // Let running jobs finish; whatever is still running after the timeout is abandoned
+        tracing::info!("🛑 Shutting down, waiting for running jobs...");
+        let unfinished = self.state.tasks.shutdown(self.shutdown_timeout).await;
+        let jobs = repos::JobRepo::new(&self.state.db);
+        for task in &unfinished {
+            if let Some(job_id) = &task.job_id {
+                tracing::warn!("Job {} did not finish before shutdown", job_id);
+                let _ = jobs.mark_failed(job_id, services::SHUTDOWN_MESSAGE).await;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check that the server can start (uv, directories, database schema, disk space, port) and
+/// print the results as a table; false if a check failed
+pub async fn run_preflight(config: &Config) -> bool {
+    let (report, _) = preflight::run_checks(config).await;
+    eprint!("{}", report.render());
+    report.passed()
+}
+
+/// Run the preflight checks, then a server on `--host`/`--port` until Ctrl+C or SIGTERM; a
+/// failed check stops it before anything is set up
+pub async fn serve(config: Config) -> Result<(), Error> {
+    let (report, listener) = preflight::run_checks(&config).await;
+    eprint!("{}", report.render());
+    let listener = match listener {
+        Some(listener) if report.passed() => listener,
+        _ => return Err("Preflight checks failed, see the hints above".into()),
+    };
+    let addr = listener.local_addr()?;
+    let server = Server::new(config).await?;
+    tracing::info!("🚀 Server starting on http://{}", addr);
+    server.run(listener, shutdown_signal()).await
+}
+
+// Reload the settings on every SIGHUP, as `kill -HUP` from an operator or a service manager's
+// reload sends it
+fn spawn_reload_on_hangup(state: Arc<AppState>) {
+    #[cfg(unix)]
+    tokio::spawn(async move {
+        let Ok(mut hangups) =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+        else {
+            tracing::warn!("Cannot listen for SIGHUP; reload with POST /api/admin/reload");
+            return;
+        };
+        while hangups.recv().await.is_some() {
+            if let Err(e) = services::reload_settings(&state) {
+                tracing::error!("{}", e);
+            }
+        }
+    });
+    #[cfg(not(unix))]
+    let _ = state;
+}
+
+// Resolves on Ctrl+C or SIGTERM
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        config: Config,
+    }
+
+    #[test]
+    fn test_default_config_matches_command_line_defaults() {
+        // Unless DL_* variables are set
+        let parsed = Cli::parse_from(["datalab-backend"]).config;
+        assert_eq!(format!("{:?}", parsed), format!("{:?}", Config::default()));
+    }
+}
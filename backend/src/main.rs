@@ -1,63 +1,45 @@
-mod executor;
-mod graph;
-mod models;
-mod routes;
-mod table_parser;
-
-use axum::Router;
-use clap::Parser;
-use executor::ScriptExecutor;
-use sqlx::sqlite::SqlitePool;
-use std::net::SocketAddr;
-use std::path::PathBuf;
-use std::sync::Arc;
-use tokio::sync::Semaphore;
-use tower_http::cors::{Any, CorsLayer};
-use tower_http::trace::TraceLayer;
+use clap::{Parser, Subcommand};
+use datalab_backend::{ctl, run_preflight, serve, Config};
 
 #[derive(Parser, Debug)]
 #[command(name = "datalab-backend")]
 #[command(about = "DataLab Backend Server", long_about = None)]
 struct Args {
-    /// Server host address
-    #[arg(long, env = "DL_HOST", default_value = "127.0.0.1")]
-    host: String,
-
-    /// Server port
-    #[arg(short, long, env = "DL_PORT", default_value = "8080")]
-    port: u16,
-
-    /// Database URL
-    #[arg(long, env = "DL_DATABASE_URL", default_value = "sqlite:../datalab.db")]
-    database_url: String,
-
-    /// Maximum concurrent function executions
-    #[arg(long, env = "DL_MAX_CONCURRENT_JOBS", default_value = "10")]
-    max_concurrent_jobs: usize,
-
-    /// Uploads directory
-    #[arg(long, env = "DL_UPLOADS_DIR", default_value = "uploads")]
-    uploads_dir: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
 
-    /// Scripts directory
-    #[arg(long, env = "DL_SCRIPTS_DIR", default_value = "scripts")]
-    scripts_dir: PathBuf,
-
-    /// Output directory
-    #[arg(long, env = "DL_OUTPUT_DIR", default_value = "output")]
-    output_dir: PathBuf,
+    #[command(flatten)]
+    config: Config,
 }
 
-pub struct AppState {
-    db: SqlitePool,
-    executor: ScriptExecutor,
-    execution_semaphore: Arc<Semaphore>,
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Client commands for a running server (upload, tag, run, jobs)
+    Ctl(ctl::CtlArgs),
+    /// Run the startup checks against the given options and exit (1 if one failed)
+    Preflight,
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() -> Result<(), datalab_backend::Error> {
     // Parse command line arguments
     let args = Args::parse();
+    match args.command {
+        Some(Command::Ctl(ctl)) => {
+            if let Err(e) = ctl::run(ctl).await {
+                eprintln!("❌ {}", e);
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Command::Preflight) => {
+            if !run_preflight(&args.config).await {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        None => {}
+    }
 
     // Initialize tracing
     tracing_subscriber::fmt()
@@ -65,73 +47,5 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .compact()
         .init();
 
-    // Create necessary directories
-    tokio::fs::create_dir_all(&args.uploads_dir).await?;
-    tokio::fs::create_dir_all(&args.scripts_dir).await?;
-    tokio::fs::create_dir_all(&args.output_dir).await?;
-
-    // Initialize database
-    let db = SqlitePool::connect(&args.database_url).await?;
-
-    // Run initial database migration
-    let migration = include_str!("../migrations/001_initial_database.sql");
-    sqlx::query(migration).execute(&db).await?;
-
-    tracing::info!("✅ Database initialized");
-
-    // Initialize script executor
-    let executor = ScriptExecutor::new(args.scripts_dir, args.uploads_dir, args.output_dir);
-
-    // Create execution semaphore (limit concurrent function executions)
-    let execution_semaphore = Arc::new(Semaphore::new(args.max_concurrent_jobs));
-    tracing::info!(
-        "✅ Execution semaphore initialized (max concurrent: {})",
-        args.max_concurrent_jobs
-    );
-
-    // Create shared application state
-    let state = Arc::new(AppState {
-        db,
-        executor,
-        execution_semaphore,
-    });
-
-    // Build our application with routes
-    let app = Router::new()
-        .nest("/api", routes::api_routes())
-        .with_state(state)
-        // Enable CORS for frontend communication
-        .layer(
-            CorsLayer::new()
-                .allow_origin(Any)
-                .allow_methods(Any)
-                .allow_headers(Any),
-        )
-        // Add tracing
-        .layer(TraceLayer::new_for_http());
-
-    // Run the server
-    let addr: SocketAddr = format!("{}:{}", args.host, args.port)
-        .parse()
-        .map_err(|e| format!("Invalid host/port: {}", e))?;
-
-    let listener = match tokio::net::TcpListener::bind(addr).await {
-        Ok(listener) => {
-            tracing::info!("🚀 Server starting on http://{}", addr);
-            listener
-        }
-        Err(e) => {
-            eprintln!("❌ Failed to bind to {}", addr);
-            eprintln!("   Error: {}", e);
-            eprintln!("\n💡 Port {} might already be in use.", args.port);
-            eprintln!(
-                "   Try stopping other processes with: lsof -ti:{} | xargs kill -9",
-                args.port
-            );
-            return Err(e.into());
-        }
-    };
-
-    axum::serve(listener, app).await?;
-    Ok(())
+    serve(args.config).await
 }
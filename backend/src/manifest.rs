@@ -0,0 +1,182 @@
+//! Import manifests: the files to bring in at once, e.g. when migrating a historical archive.
+//! Each row names a source (an http(s) URL, or a path on the server under one of the import
+//! directories) with an optional filename, tags, assignee and free-form metadata.
+//!
+//! JSON manifests are an array of rows. CSV manifests have a header with a `source` column;
+//! `filename`, `tags` (separated by `;`) and `assignee` columns are read as such and any other
+//! column is metadata.
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::path::{Component, Path, PathBuf};
+
+/// Most rows in one manifest
+pub const MAX_ROWS: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ManifestRow {
+    pub source: String,
+    #[serde(default)]
+    pub filename: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>, // by name; missing tags are created
+    #[serde(default)]
+    pub assignee: Option<String>,
+    #[serde(default)]
+    pub metadata: Map<String, Value>,
+}
+
+/// Where the file of a row comes from
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Url(reqwest::Url),
+    Path(PathBuf),
+}
+
+impl Source {
+    /// Parse a row's source; paths must be absolute and under one of `import_dirs`
+    pub fn parse(source: &str, import_dirs: &[PathBuf]) -> Result<Self, String> {
+        let source = source.trim();
+        if source.starts_with("http://") || source.starts_with("https://") {
+            return reqwest::Url::parse(source)
+                .map(Source::Url)
+                .map_err(|e| format!("Invalid URL {}: {}", source, e));
+        }
+        let path = Path::new(source);
+        if !path.is_absolute() {
+            return Err(format!(
+                "{:?} is neither an http(s) URL nor an absolute path",
+                source
+            ));
+        }
+        if path.components().any(|c| c == Component::ParentDir)
+            || !import_dirs.iter().any(|dir| path.starts_with(dir))
+        {
+            return Err(format!("{} is not inside an import directory", source));
+        }
+        Ok(Source::Path(path.to_path_buf()))
+    }
+}
+
+/// Parse a manifest sent with the given content type; CSV for `text/csv`, JSON otherwise
+pub fn parse(body: &[u8], content_type: Option<&str>) -> Result<Vec<ManifestRow>, String> {
+    let is_csv = content_type
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|mime| {
+            matches!(
+                mime.trim().to_ascii_lowercase().as_str(),
+                "text/csv" | "application/csv"
+            )
+        });
+    let rows = if is_csv {
+        parse_csv(body)?
+    } else {
+        serde_json::from_slice::<Vec<ManifestRow>>(body)
+            .map_err(|e| format!("Invalid manifest: {}", e))?
+    };
+    if rows.is_empty() {
+        return Err("The manifest has no rows".to_string());
+    }
+    if rows.len() > MAX_ROWS {
+        return Err(format!("At most {} rows per manifest", MAX_ROWS));
+    }
+    Ok(rows)
+}
+
+fn parse_csv(body: &[u8]) -> Result<Vec<ManifestRow>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(body);
+    let headers = reader
+        .headers()
+        .map_err(|e| format!("Invalid manifest: {}", e))?
+        .clone();
+    if !headers.iter().any(|header| header == "source") {
+        return Err("CSV manifests need a source column".to_string());
+    }
+
+    let mut rows = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        let record = record.map_err(|e| format!("Row {}: {}", index + 1, e))?;
+        let mut row = ManifestRow {
+            source: String::new(),
+            filename: None,
+            tags: Vec::new(),
+            assignee: None,
+            metadata: Map::new(),
+        };
+        for (header, value) in headers.iter().zip(record.iter()) {
+            if value.is_empty() && header != "source" {
+                continue;
+            }
+            match header {
+                "source" => row.source = value.to_string(),
+                "filename" => row.filename = Some(value.to_string()),
+                "tags" => {
+                    row.tags = value
+                        .split(';')
+                        .map(str::trim)
+                        .filter(|tag| !tag.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                }
+                "assignee" => row.assignee = Some(value.to_string()),
+                _ => {
+                    row.metadata
+                        .insert(header.to_string(), Value::String(value.to_string()));
+                }
+            }
+        }
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv_manifest() {
+        let body = b"source,tags,assignee,instrument\n\
+            https://archive.example.org/run1.s2p, raw;2019 ,@alice,VNA-2\n\
+            /data/archive/run2.s2p,,,\n";
+        let rows = parse(body, Some("text/csv; charset=utf-8")).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].source, "https://archive.example.org/run1.s2p");
+        assert_eq!(rows[0].tags, ["raw", "2019"]);
+        assert_eq!(rows[0].assignee.as_deref(), Some("@alice"));
+        assert_eq!(rows[0].metadata["instrument"], "VNA-2");
+        assert!(rows[1].tags.is_empty());
+        assert!(rows[1].metadata.is_empty());
+
+        assert!(parse(b"path\n/data/a.csv\n", Some("text/csv")).is_err());
+        assert!(parse(b"source\n", Some("text/csv")).is_err());
+    }
+
+    #[test]
+    fn test_parse_json_manifest() {
+        let body = br#"[{"source": "/data/a.csv", "tags": ["raw"], "metadata": {"run": 3}}]"#;
+        let rows = parse(body, Some("application/json")).unwrap();
+        assert_eq!(rows[0].tags, ["raw"]);
+        assert_eq!(rows[0].metadata["run"], 3);
+        assert!(parse(br#"[{"tags": ["raw"]}]"#, None).is_err());
+    }
+
+    #[test]
+    fn test_sources_stay_inside_import_dirs() {
+        let dirs = [PathBuf::from("/data/archive")];
+        assert!(matches!(
+            Source::parse("https://example.org/a.csv", &[]),
+            Ok(Source::Url(_))
+        ));
+        assert_eq!(
+            Source::parse("/data/archive/2019/a.csv", &dirs),
+            Ok(Source::Path(PathBuf::from("/data/archive/2019/a.csv")))
+        );
+        assert!(Source::parse("/data/archive/../secrets.csv", &dirs).is_err());
+        assert!(Source::parse("/etc/passwd", &dirs).is_err());
+        assert!(Source::parse("archive/a.csv", &dirs).is_err());
+        assert!(Source::parse("ftp://example.org/a.csv", &dirs).is_err());
+    }
+}
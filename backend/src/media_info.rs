@@ -0,0 +1,668 @@
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+
+// Stop walking the IFD chain after this many pages (stacks can be huge)
+const MAX_PAGES: usize = 100_000;
+// Ignore absurd tag payloads instead of allocating them
+const MAX_TAG_BYTES: u64 = 1 << 20;
+
+const TAG_IMAGE_WIDTH: u16 = 256;
+const TAG_IMAGE_LENGTH: u16 = 257;
+const TAG_BITS_PER_SAMPLE: u16 = 258;
+const TAG_COMPRESSION: u16 = 259;
+const TAG_PHOTOMETRIC: u16 = 262;
+const TAG_IMAGE_DESCRIPTION: u16 = 270;
+const TAG_SAMPLES_PER_PIXEL: u16 = 277;
+const TAG_X_RESOLUTION: u16 = 282;
+const TAG_Y_RESOLUTION: u16 = 283;
+const TAG_RESOLUTION_UNIT: u16 = 296;
+const TAG_SOFTWARE: u16 = 305;
+const TAG_SAMPLE_FORMAT: u16 = 339;
+const TAG_MODEL_PIXEL_SCALE: u16 = 33550;
+const TAG_MODEL_TIEPOINT: u16 = 33922;
+const TAG_GEO_KEY_DIRECTORY: u16 = 34735;
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct MediaInfo {
+    pub format: String,
+    pub width: Option<u64>,
+    pub height: Option<u64>,
+    pub channels: Option<u64>,
+    pub bits_per_sample: Vec<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample_format: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compression: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub photometric: Option<String>,
+    pub page_count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pixel_size: Option<PixelSize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub geo: Option<GeoInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stack: Option<StackInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub software: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PixelSize {
+    pub x: f64,
+    pub y: f64,
+    pub unit: String,
+    pub source: String, // geotiff, ome, imagej, resolution
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GeoInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub epsg: Option<u64>,
+    pub model_pixel_scale: Vec<f64>,
+    pub tiepoint: Vec<f64>,
+}
+
+/// Hyperstack dimensions as recorded by ImageJ/Fiji or OME-TIFF writers
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct StackInfo {
+    pub channels: Option<u64>,
+    pub slices: Option<u64>,
+    pub frames: Option<u64>,
+}
+
+/// Extensions of formats that are TIFF containers (OME-TIFF, Zeiss LSM, Aperio SVS, Hamamatsu NDPI)
+pub fn is_tiff_extension(extension: &str) -> bool {
+    matches!(
+        extension.to_lowercase().as_str(),
+        "tif" | "tiff" | "lsm" | "svs" | "ndpi" | "gtiff"
+    )
+}
+
+pub fn read_media_info(
+    file_path: &str,
+    file_extension: &str,
+) -> Result<MediaInfo, Box<dyn std::error::Error>> {
+    if is_tiff_extension(file_extension) {
+        read_tiff_info(file_path)
+    } else {
+        Err(format!("Unsupported media type: {}", file_extension).into())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ByteOrder {
+    Little,
+    Big,
+}
+
+struct TiffReader {
+    file: File,
+    file_len: u64,
+    order: ByteOrder,
+    big_tiff: bool,
+}
+
+/// A raw IFD entry; values are decoded lazily since most tags are skipped
+struct IfdEntry {
+    tag: u16,
+    field_type: u16,
+    count: u64,
+    // Either the inline value bytes or the offset of the payload
+    value_or_offset: [u8; 8],
+}
+
+impl TiffReader {
+    fn u16(&self, bytes: &[u8]) -> u16 {
+        let b = [bytes[0], bytes[1]];
+        match self.order {
+            ByteOrder::Little => u16::from_le_bytes(b),
+            ByteOrder::Big => u16::from_be_bytes(b),
+        }
+    }
+
+    fn u32(&self, bytes: &[u8]) -> u32 {
+        let b = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        match self.order {
+            ByteOrder::Little => u32::from_le_bytes(b),
+            ByteOrder::Big => u32::from_be_bytes(b),
+        }
+    }
+
+    fn u64(&self, bytes: &[u8]) -> u64 {
+        let mut b = [0u8; 8];
+        b.copy_from_slice(&bytes[..8]);
+        match self.order {
+            ByteOrder::Little => u64::from_le_bytes(b),
+            ByteOrder::Big => u64::from_be_bytes(b),
+        }
+    }
+
+    fn read_at(&mut self, offset: u64, len: usize) -> std::io::Result<Vec<u8>> {
+        let mut buf = vec![0u8; len];
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn read_ifd(&mut self, offset: u64) -> std::io::Result<(Vec<IfdEntry>, u64)> {
+        let (count_len, entry_len, next_len) = if self.big_tiff {
+            (8, 20, 8)
+        } else {
+            (2, 12, 4)
+        };
+
+        let count_bytes = self.read_at(offset, count_len)?;
+        let count = if self.big_tiff {
+            self.u64(&count_bytes)
+        } else {
+            self.u16(&count_bytes) as u64
+        };
+
+        // A BigTIFF count is 64 bits wide; the entries must still fit in what is left of the file
+        let table_offset = offset + count_len as u64;
+        let table_len = count
+            .checked_mul(entry_len as u64)
+            .filter(|len| *len <= self.file_len.saturating_sub(table_offset))
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!(
+                        "IFD at {} claims {} entries, past the end of the file",
+                        offset, count
+                    ),
+                )
+            })?;
+        let table = self.read_at(table_offset, table_len as usize)?;
+        let mut entries = Vec::with_capacity(table.len() / entry_len);
+        for raw in table.chunks_exact(entry_len) {
+            let mut value_or_offset = [0u8; 8];
+            let (entry_count, value) = if self.big_tiff {
+                (self.u64(&raw[4..12]), &raw[12..20])
+            } else {
+                (self.u32(&raw[4..8]) as u64, &raw[8..12])
+            };
+            value_or_offset[..value.len()].copy_from_slice(value);
+            entries.push(IfdEntry {
+                tag: self.u16(&raw[0..2]),
+                field_type: self.u16(&raw[2..4]),
+                count: entry_count,
+                value_or_offset,
+            });
+        }
+
+        let next_bytes = self.read_at(table_offset + table_len, next_len)?;
+        let next = if self.big_tiff {
+            self.u64(&next_bytes)
+        } else {
+            self.u32(&next_bytes) as u64
+        };
+
+        Ok((entries, next))
+    }
+
+    fn payload(&mut self, entry: &IfdEntry) -> std::io::Result<Option<Vec<u8>>> {
+        let type_size: u64 = match entry.field_type {
+            1 | 2 | 6 | 7 => 1,   // BYTE, ASCII, SBYTE, UNDEFINED
+            3 | 8 => 2,           // SHORT, SSHORT
+            4 | 9 | 11 => 4,      // LONG, SLONG, FLOAT
+            5 | 10 | 12 => 8,     // RATIONAL, SRATIONAL, DOUBLE
+            16..=18 => 8,         // LONG8, SLONG8, IFD8
+            _ => return Ok(None), // Unknown types are skipped
+        };
+        let Some(len) = entry
+            .count
+            .checked_mul(type_size)
+            .filter(|len| *len <= MAX_TAG_BYTES)
+        else {
+            return Ok(None);
+        };
+
+        let inline_len = if self.big_tiff { 8 } else { 4 };
+        if len <= inline_len {
+            return Ok(Some(entry.value_or_offset[..len as usize].to_vec()));
+        }
+
+        let offset = if self.big_tiff {
+            self.u64(&entry.value_or_offset)
+        } else {
+            self.u32(&entry.value_or_offset) as u64
+        };
+        self.read_at(offset, len as usize).map(Some)
+    }
+
+    fn unsigned_values(&mut self, entry: &IfdEntry) -> std::io::Result<Vec<u64>> {
+        let Some(bytes) = self.payload(entry)? else {
+            return Ok(Vec::new());
+        };
+        Ok(match entry.field_type {
+            1 | 7 => bytes.iter().map(|b| *b as u64).collect(),
+            3 => bytes.chunks_exact(2).map(|c| self.u16(c) as u64).collect(),
+            4 => bytes.chunks_exact(4).map(|c| self.u32(c) as u64).collect(),
+            16 => bytes.chunks_exact(8).map(|c| self.u64(c)).collect(),
+            _ => Vec::new(),
+        })
+    }
+
+    fn float_values(&mut self, entry: &IfdEntry) -> std::io::Result<Vec<f64>> {
+        let Some(bytes) = self.payload(entry)? else {
+            return Ok(Vec::new());
+        };
+        Ok(match entry.field_type {
+            5 => bytes
+                .chunks_exact(8)
+                .map(|c| {
+                    let denominator = self.u32(&c[4..8]);
+                    if denominator == 0 {
+                        0.0
+                    } else {
+                        self.u32(&c[0..4]) as f64 / denominator as f64
+                    }
+                })
+                .collect(),
+            11 => bytes
+                .chunks_exact(4)
+                .map(|c| f32::from_bits(self.u32(c)) as f64)
+                .collect(),
+            12 => bytes
+                .chunks_exact(8)
+                .map(|c| f64::from_bits(self.u64(c)))
+                .collect(),
+            _ => self
+                .unsigned_values(entry)?
+                .into_iter()
+                .map(|v| v as f64)
+                .collect(),
+        })
+    }
+
+    fn ascii_value(&mut self, entry: &IfdEntry) -> std::io::Result<Option<String>> {
+        Ok(self.payload(entry)?.map(|bytes| {
+            String::from_utf8_lossy(&bytes)
+                .trim_end_matches('\0')
+                .to_string()
+        }))
+    }
+}
+
+fn read_tiff_info(file_path: &str) -> Result<MediaInfo, Box<dyn std::error::Error>> {
+    let mut file = File::open(file_path)?;
+    let file_len = file.metadata()?.len();
+    let mut header = [0u8; 16];
+    file.read_exact(&mut header[..8])?;
+
+    let order = match &header[..2] {
+        b"II" => ByteOrder::Little,
+        b"MM" => ByteOrder::Big,
+        _ => return Err("Not a TIFF file".into()),
+    };
+    let mut reader = TiffReader {
+        file,
+        file_len,
+        order,
+        big_tiff: false,
+    };
+
+    let first_ifd = match reader.u16(&header[2..4]) {
+        42 => reader.u32(&header[4..8]) as u64,
+        43 => {
+            reader.big_tiff = true;
+            let rest = reader.read_at(8, 8)?;
+            reader.u64(&rest)
+        }
+        _ => return Err("Not a TIFF file".into()),
+    };
+
+    let (entries, mut next) = reader.read_ifd(first_ifd)?;
+    let mut info = MediaInfo {
+        format: if reader.big_tiff { "bigtiff" } else { "tiff" }.to_string(),
+        page_count: 1,
+        ..Default::default()
+    };
+
+    let mut x_resolution = None;
+    let mut y_resolution = None;
+    let mut resolution_unit = 2; // TIFF default: inch
+    let mut model_pixel_scale = Vec::new();
+    let mut tiepoint = Vec::new();
+    let mut geo_keys = Vec::new();
+
+    for entry in &entries {
+        match entry.tag {
+            TAG_IMAGE_WIDTH => info.width = reader.unsigned_values(entry)?.first().copied(),
+            TAG_IMAGE_LENGTH => info.height = reader.unsigned_values(entry)?.first().copied(),
+            TAG_BITS_PER_SAMPLE => info.bits_per_sample = reader.unsigned_values(entry)?,
+            TAG_SAMPLES_PER_PIXEL => {
+                info.channels = reader.unsigned_values(entry)?.first().copied()
+            }
+            TAG_COMPRESSION => {
+                info.compression = reader
+                    .unsigned_values(entry)?
+                    .first()
+                    .map(|c| compression_name(*c))
+            }
+            TAG_PHOTOMETRIC => {
+                info.photometric = reader
+                    .unsigned_values(entry)?
+                    .first()
+                    .map(|p| photometric_name(*p))
+            }
+            TAG_SAMPLE_FORMAT => {
+                info.sample_format = reader
+                    .unsigned_values(entry)?
+                    .first()
+                    .map(|f| sample_format_name(*f))
+            }
+            TAG_IMAGE_DESCRIPTION => info.description = reader.ascii_value(entry)?,
+            TAG_SOFTWARE => info.software = reader.ascii_value(entry)?,
+            TAG_X_RESOLUTION => x_resolution = reader.float_values(entry)?.first().copied(),
+            TAG_Y_RESOLUTION => y_resolution = reader.float_values(entry)?.first().copied(),
+            TAG_RESOLUTION_UNIT => {
+                resolution_unit = reader.unsigned_values(entry)?.first().copied().unwrap_or(2)
+            }
+            TAG_MODEL_PIXEL_SCALE => model_pixel_scale = reader.float_values(entry)?,
+            TAG_MODEL_TIEPOINT => tiepoint = reader.float_values(entry)?,
+            TAG_GEO_KEY_DIRECTORY => geo_keys = reader.unsigned_values(entry)?,
+            _ => {}
+        }
+    }
+
+    // Count remaining pages without decoding them
+    while next != 0 && info.page_count < MAX_PAGES {
+        let (_, following) = reader.read_ifd(next)?;
+        info.page_count += 1;
+        next = following;
+    }
+
+    if !model_pixel_scale.is_empty() || !geo_keys.is_empty() {
+        info.geo = Some(GeoInfo {
+            epsg: geo_epsg(&geo_keys),
+            model_pixel_scale: model_pixel_scale.clone(),
+            tiepoint,
+        });
+    }
+
+    let description = info.description.clone().unwrap_or_default();
+    info.stack = parse_stack_info(&description);
+    info.pixel_size = geo_pixel_size(&model_pixel_scale)
+        .or_else(|| ome_pixel_size(&description))
+        .or_else(|| imagej_pixel_size(&description, x_resolution, y_resolution))
+        .or_else(|| resolution_pixel_size(x_resolution, y_resolution, resolution_unit));
+
+    // OME-XML descriptions can be megabytes; keep the payload small
+    if let Some(description) = &mut info.description {
+        if description.len() > 4096 {
+            let mut end = 4096;
+            while !description.is_char_boundary(end) {
+                end -= 1;
+            }
+            description.truncate(end);
+        }
+    }
+
+    Ok(info)
+}
+
+fn compression_name(code: u64) -> String {
+    match code {
+        1 => "none".to_string(),
+        5 => "lzw".to_string(),
+        6 | 7 => "jpeg".to_string(),
+        8 | 32946 => "deflate".to_string(),
+        32773 => "packbits".to_string(),
+        33003 | 33005 => "jpeg2000".to_string(),
+        34887 => "lerc".to_string(),
+        50000 => "zstd".to_string(),
+        other => format!("unknown ({})", other),
+    }
+}
+
+fn photometric_name(code: u64) -> String {
+    match code {
+        0 => "min-is-white".to_string(),
+        1 => "min-is-black".to_string(),
+        2 => "rgb".to_string(),
+        3 => "palette".to_string(),
+        5 => "cmyk".to_string(),
+        6 => "ycbcr".to_string(),
+        other => format!("unknown ({})", other),
+    }
+}
+
+fn sample_format_name(code: u64) -> String {
+    match code {
+        1 => "uint".to_string(),
+        2 => "int".to_string(),
+        3 => "float".to_string(),
+        other => format!("unknown ({})", other),
+    }
+}
+
+/// Find ProjectedCSTypeGeoKey (3072) or GeographicTypeGeoKey (2048) in the GeoKey directory
+fn geo_epsg(geo_keys: &[u64]) -> Option<u64> {
+    // Header is 4 shorts, followed by 4-short entries: key id, location, count, value
+    let entries = geo_keys.get(4..)?;
+    let lookup = |key: u64| {
+        entries
+            .chunks_exact(4)
+            .find(|e| e[0] == key && e[1] == 0)
+            .map(|e| e[3])
+    };
+    lookup(3072).or_else(|| lookup(2048))
+}
+
+fn geo_pixel_size(scale: &[f64]) -> Option<PixelSize> {
+    if scale.len() < 2 {
+        return None;
+    }
+    Some(PixelSize {
+        x: scale[0],
+        y: scale[1],
+        unit: "crs-units".to_string(),
+        source: "geotiff".to_string(),
+    })
+}
+
+fn xml_attribute(xml: &str, name: &str) -> Option<String> {
+    let start = xml.find(&format!("{}=\"", name))? + name.len() + 2;
+    let end = xml[start..].find('"')?;
+    Some(xml[start..start + end].to_string())
+}
+
+fn ome_pixel_size(description: &str) -> Option<PixelSize> {
+    if !description.contains("<OME") {
+        return None;
+    }
+    let x = xml_attribute(description, "PhysicalSizeX")?.parse().ok()?;
+    let y = xml_attribute(description, "PhysicalSizeY")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(x);
+    let unit = xml_attribute(description, "PhysicalSizeXUnit").unwrap_or_else(|| "µm".to_string());
+    Some(PixelSize {
+        x,
+        y,
+        unit,
+        source: "ome".to_string(),
+    })
+}
+
+fn imagej_value<'a>(description: &'a str, key: &str) -> Option<&'a str> {
+    description
+        .lines()
+        .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+}
+
+/// ImageJ stores the calibration unit in the description and pixels-per-unit in XResolution
+fn imagej_pixel_size(
+    description: &str,
+    x_resolution: Option<f64>,
+    y_resolution: Option<f64>,
+) -> Option<PixelSize> {
+    if !description.starts_with("ImageJ=") {
+        return None;
+    }
+    let unit = imagej_value(description, "unit")?.replace("\\u00B5", "µ");
+    let x_resolution = x_resolution.filter(|r| *r > 0.0)?;
+    let y_resolution = y_resolution.filter(|r| *r > 0.0).unwrap_or(x_resolution);
+    Some(PixelSize {
+        x: 1.0 / x_resolution,
+        y: 1.0 / y_resolution,
+        unit,
+        source: "imagej".to_string(),
+    })
+}
+
+fn resolution_pixel_size(
+    x_resolution: Option<f64>,
+    y_resolution: Option<f64>,
+    resolution_unit: u64,
+) -> Option<PixelSize> {
+    let unit = match resolution_unit {
+        2 => "inch",
+        3 => "cm",
+        _ => return None,
+    };
+    let x_resolution = x_resolution.filter(|r| *r > 0.0)?;
+    let y_resolution = y_resolution.filter(|r| *r > 0.0).unwrap_or(x_resolution);
+    Some(PixelSize {
+        x: 1.0 / x_resolution,
+        y: 1.0 / y_resolution,
+        unit: unit.to_string(),
+        source: "resolution".to_string(),
+    })
+}
+
+fn parse_stack_info(description: &str) -> Option<StackInfo> {
+    if description.starts_with("ImageJ=") {
+        let value = |key| imagej_value(description, key).and_then(|v| v.parse().ok());
+        return Some(StackInfo {
+            channels: value("channels"),
+            slices: value("slices"),
+            frames: value("frames"),
+        });
+    }
+    if description.contains("<OME") {
+        let value = |key| xml_attribute(description, key).and_then(|v| v.parse().ok());
+        return Some(StackInfo {
+            channels: value("SizeC"),
+            slices: value("SizeZ"),
+            frames: value("SizeT"),
+        });
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_bytes(name: &str, bytes: &[u8]) -> Result<MediaInfo, Box<dyn std::error::Error>> {
+        let dir = std::env::temp_dir().join(format!("datalab-media-info-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, bytes).unwrap();
+        read_media_info(path.to_str().unwrap(), "tif")
+    }
+
+    // A little-endian TIFF with one IFD of SHORT entries
+    fn classic_tiff(entries: &[(u16, u16)]) -> Vec<u8> {
+        let mut bytes = b"II".to_vec();
+        bytes.extend(42u16.to_le_bytes());
+        bytes.extend(8u32.to_le_bytes());
+        bytes.extend((entries.len() as u16).to_le_bytes());
+        for (tag, value) in entries {
+            bytes.extend(tag.to_le_bytes());
+            bytes.extend(3u16.to_le_bytes());
+            bytes.extend(1u32.to_le_bytes());
+            bytes.extend(value.to_le_bytes());
+            bytes.extend([0, 0]);
+        }
+        bytes.extend(0u32.to_le_bytes());
+        bytes
+    }
+
+    // A little-endian BigTIFF whose first IFD claims `count` entries and holds `entries`
+    fn big_tiff(count: u64, entries: &[(u16, u16, u64, u64)]) -> Vec<u8> {
+        let mut bytes = b"II".to_vec();
+        bytes.extend(43u16.to_le_bytes());
+        bytes.extend(8u16.to_le_bytes());
+        bytes.extend(0u16.to_le_bytes());
+        bytes.extend(16u64.to_le_bytes());
+        bytes.extend(count.to_le_bytes());
+        for (tag, field_type, count, value) in entries {
+            bytes.extend(tag.to_le_bytes());
+            bytes.extend(field_type.to_le_bytes());
+            bytes.extend(count.to_le_bytes());
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes.extend(0u64.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn test_read_tiff_info() {
+        let info = read_bytes(
+            "classic.tif",
+            &classic_tiff(&[
+                (TAG_IMAGE_WIDTH, 30),
+                (TAG_IMAGE_LENGTH, 20),
+                (TAG_BITS_PER_SAMPLE, 16),
+                (TAG_COMPRESSION, 5),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(info.format, "tiff");
+        assert_eq!((info.width, info.height), (Some(30), Some(20)));
+        assert_eq!(info.bits_per_sample, [16]);
+        assert_eq!(info.compression.as_deref(), Some("lzw"));
+        assert_eq!(info.page_count, 1);
+
+        let info =
+            read_bytes("big.tif", &big_tiff(1, &[(TAG_IMAGE_WIDTH, 16, 1, 70_000)])).unwrap();
+        assert_eq!(info.format, "bigtiff");
+        assert_eq!(info.width, Some(70_000));
+    }
+
+    #[test]
+    fn test_truncated_tiffs_are_errors() {
+        assert!(read_bytes("empty.tif", b"").is_err());
+        assert!(read_bytes("header.tif", b"II*\0").is_err());
+        assert!(read_bytes("not-tiff.tif", b"PK\x03\x04\0\0\0\0").is_err());
+
+        // The first IFD lies past the end of the file
+        let mut bytes = b"II".to_vec();
+        bytes.extend(42u16.to_le_bytes());
+        bytes.extend(4096u32.to_le_bytes());
+        assert!(read_bytes("no-ifd.tif", &bytes).is_err());
+
+        // The entries stop short of the count
+        let mut bytes = classic_tiff(&[(TAG_IMAGE_WIDTH, 30), (TAG_IMAGE_LENGTH, 20)]);
+        bytes.truncate(bytes.len() - 10);
+        assert!(read_bytes("short-ifd.tif", &bytes).is_err());
+    }
+
+    #[test]
+    fn test_malicious_bigtiff_counts_are_errors() {
+        // count * 20 overflows u64
+        assert!(read_bytes("overflow.tif", &big_tiff(u64::MAX, &[])).is_err());
+        // No overflow, but far more entries than the file holds
+        assert!(read_bytes("huge.tif", &big_tiff(1 << 40, &[])).is_err());
+
+        // A tag claiming u64::MAX values is skipped rather than allocated
+        let info = read_bytes(
+            "huge-tag.tif",
+            &big_tiff(
+                2,
+                &[
+                    (TAG_IMAGE_WIDTH, 16, 1, 64),
+                    (TAG_BITS_PER_SAMPLE, 16, u64::MAX, 0),
+                ],
+            ),
+        )
+        .unwrap();
+        assert_eq!(info.width, Some(64));
+        assert!(info.bits_per_sample.is_empty());
+    }
+}
@@ -0,0 +1,219 @@
+/// Bytes of an upload looked at to detect its type
+pub const SNIFF_BYTES: usize = 8 * 1024;
+
+// Signatures at the start of the file, most specific first
+const SIGNATURES: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"II*\x00", "image/tiff"),
+    (b"MM\x00*", "image/tiff"),
+    (b"II+\x00", "image/tiff"), // BigTIFF
+    (b"MM\x00+", "image/tiff"),
+    (b"%PDF-", "application/pdf"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"PK\x05\x06", "application/zip"), // empty archive
+    (b"\x1f\x8b", "application/gzip"),
+    (b"BZh", "application/x-bzip2"),
+    (b"\x28\xb5\x2f\xfd", "application/zstd"),
+    (b"\xfd7zXZ\x00", "application/x-xz"),
+    (b"PAR1", "application/vnd.apache.parquet"),
+    (b"ARROW1", "application/vnd.apache.arrow.file"),
+    (b"\x89HDF\r\n\x1a\n", "application/x-hdf5"),
+    (b"\x93NUMPY", "application/x-npy"),
+    (b"fLaC", "audio/flac"),
+    (b"OggS", "audio/ogg"),
+    (b"ID3", "audio/mpeg"),
+    (b"SQLite format 3\x00", "application/vnd.sqlite3"),
+    (b"\x7fELF", "application/x-executable"),
+];
+
+// Known types with the extensions they go by; the first one is used when the filename
+// disagrees with the content
+const EXTENSIONS: &[(&str, &[&str])] = &[
+    ("image/png", &["png"]),
+    ("image/jpeg", &["jpg", "jpeg"]),
+    ("image/gif", &["gif"]),
+    ("image/tiff", &["tif", "tiff"]),
+    ("image/webp", &["webp"]),
+    ("application/pdf", &["pdf"]),
+    (
+        "application/zip",
+        &["zip", "npz", "xlsx", "docx", "pptx", "jar", "whl"],
+    ),
+    ("application/gzip", &["gz", "tgz"]),
+    ("application/x-bzip2", &["bz2"]),
+    ("application/zstd", &["zst"]),
+    ("application/x-xz", &["xz"]),
+    ("application/vnd.apache.parquet", &["parquet", "pq"]),
+    (
+        "application/vnd.apache.arrow.file",
+        &["arrow", "feather", "ipc"],
+    ),
+    ("application/x-hdf5", &["h5", "hdf5", "he5", "nc"]),
+    ("application/x-npy", &["npy"]),
+    ("audio/wav", &["wav"]),
+    ("audio/flac", &["flac"]),
+    ("audio/ogg", &["ogg", "oga", "opus"]),
+    ("audio/mpeg", &["mp3"]),
+    ("video/mp4", &["mp4", "m4a", "mov"]),
+    ("application/vnd.sqlite3", &["sqlite", "sqlite3", "db"]),
+    ("application/x-executable", &["bin"]),
+    ("application/json", &["json"]),
+    ("application/x-ndjson", &["jsonl", "ndjson"]),
+    ("application/xml", &["xml"]),
+    ("text/html", &["html", "htm"]),
+    ("text/csv", &["csv"]),
+    ("text/tab-separated-values", &["tsv"]),
+];
+
+fn lowercase_extension(filename: &str) -> Option<String> {
+    match filename.rsplit_once('.') {
+        Some((_, extension)) if !extension.is_empty() => Some(extension.to_lowercase()),
+        _ => None,
+    }
+}
+
+// Decoded text without NUL bytes; the sample may end in the middle of a character
+fn is_text(head: &[u8]) -> bool {
+    !head.contains(&0)
+        && match std::str::from_utf8(head) {
+            Ok(_) => true,
+            Err(e) => e.error_len().is_none(),
+        }
+}
+
+fn sniff_text(head: &[u8], extension: Option<&str>) -> &'static str {
+    let text = String::from_utf8_lossy(head);
+    let start = text.trim_start_matches('\u{feff}').trim_start();
+    let lower: String = start.chars().take(64).collect::<String>().to_lowercase();
+    match extension {
+        Some("jsonl" | "ndjson") if start.starts_with('{') => "application/x-ndjson",
+        _ if start.starts_with('{') || start.starts_with('[') => "application/json",
+        _ if lower.starts_with("<!doctype html") || lower.starts_with("<html") => "text/html",
+        _ if lower.starts_with("<?xml") => "application/xml",
+        Some("csv") => "text/csv",
+        Some("tsv") => "text/tab-separated-values",
+        _ => "text/plain",
+    }
+}
+
+/// Detect the type of a file from its first bytes (at least `SNIFF_BYTES` where available).
+/// Binary formats are recognised by their signature; for text the filename decides between
+/// e.g. CSV and plain text. None for empty files and unknown binary formats.
+pub fn detect_mime_type(head: &[u8], filename: &str) -> Option<&'static str> {
+    if head.is_empty() {
+        return None;
+    }
+    if let Some((_, mime)) = SIGNATURES.iter().find(|(magic, _)| head.starts_with(magic)) {
+        return Some(mime);
+    }
+    // RIFF and ISO media containers carry their format after a length field
+    if head.len() >= 12 && &head[..4] == b"RIFF" {
+        match &head[8..12] {
+            b"WAVE" => return Some("audio/wav"),
+            b"WEBP" => return Some("image/webp"),
+            _ => {}
+        }
+    }
+    if head.len() >= 8 && &head[4..8] == b"ftyp" {
+        return Some("video/mp4");
+    }
+    if is_text(head) {
+        return Some(sniff_text(head, lowercase_extension(filename).as_deref()));
+    }
+    None
+}
+
+pub fn is_text_type(mime: &str) -> bool {
+    mime.starts_with("text/")
+        || matches!(
+            mime,
+            "application/json" | "application/x-ndjson" | "application/xml"
+        )
+}
+
+/// The extension previews and extension tags go by: the filename's, unless the content is a
+/// binary format the extension does not fit (a Parquet file named `.dat`, a PNG without an
+/// extension). Text keeps whatever extension it was given.
+pub fn effective_extension(filename: &str, detected_mime_type: Option<&str>) -> Option<String> {
+    let claimed = lowercase_extension(filename);
+    let Some(mime) = detected_mime_type else {
+        return claimed;
+    };
+    let Some((_, extensions)) = EXTENSIONS.iter().find(|(known, _)| *known == mime) else {
+        return claimed;
+    };
+    match &claimed {
+        Some(extension) if is_text_type(mime) || extensions.contains(&extension.as_str()) => {
+            claimed
+        }
+        _ => Some(extensions[0].to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_mime_type() {
+        assert_eq!(
+            detect_mime_type(b"\x89PNG\r\n\x1a\n\x00\x00", "scan.tif"),
+            Some("image/png")
+        );
+        assert_eq!(
+            detect_mime_type(b"PAR1\x15\x04", "run.dat"),
+            Some("application/vnd.apache.parquet")
+        );
+        assert_eq!(
+            detect_mime_type(b"RIFF\x24\x08\x00\x00WAVEfmt ", "a.bin"),
+            Some("audio/wav")
+        );
+        assert_eq!(detect_mime_type(b"a,b\n1,2\n", "x.csv"), Some("text/csv"));
+        assert_eq!(detect_mime_type(b"a,b\n1,2\n", "x.txt"), Some("text/plain"));
+        assert_eq!(
+            detect_mime_type(b"  {\"a\": 1}", "x.txt"),
+            Some("application/json")
+        );
+        // "é" cut in half at the end of the sample is still text
+        assert_eq!(detect_mime_type(b"caf\xc3", "x"), Some("text/plain"));
+        assert_eq!(detect_mime_type(b"\x00\x01\x02\x03", "x.bin"), None);
+        assert_eq!(detect_mime_type(b"", "x.csv"), None);
+    }
+
+    #[test]
+    fn test_effective_extension() {
+        assert_eq!(
+            effective_extension("run.dat", Some("application/vnd.apache.parquet")).as_deref(),
+            Some("parquet")
+        );
+        assert_eq!(
+            effective_extension("scan", Some("image/png")).as_deref(),
+            Some("png")
+        );
+        assert_eq!(
+            effective_extension("photo.JPEG", Some("image/jpeg")).as_deref(),
+            Some("jpeg")
+        );
+        assert_eq!(
+            effective_extension("arrays.npz", Some("application/zip")).as_deref(),
+            Some("npz")
+        );
+        // Text is trusted to be what its name says, and unknown types change nothing
+        assert_eq!(
+            effective_extension("data.csv", Some("application/json")).as_deref(),
+            Some("csv")
+        );
+        assert_eq!(
+            effective_extension("notes.log", Some("text/plain")).as_deref(),
+            Some("log")
+        );
+        assert_eq!(
+            effective_extension("blob.xyz", None).as_deref(),
+            Some("xyz")
+        );
+        assert_eq!(effective_extension("README", Some("text/plain")), None);
+    }
+}
@@ -1,33 +1,100 @@
+use crate::cost_estimate::CostEstimate;
+use crate::image_metadata::ImageMetadata;
+use crate::script_analysis::ScriptAnalysis;
+use crate::snapshots::{Pipeline, PipelineDiff};
+use crate::sql_query::SqlRequest;
+use crate::table_parser::{SampleQuery, TablePreview};
+use crate::triggers::TriggerCondition;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Tag {
     pub id: String,
     pub name: String,
     pub color: String,
+    pub description: Option<String>, // what the tag means, for whoever applies it
     pub created_at: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct FileLineage {
-    pub id: String,
-    pub output_upload_id: String,
-    pub source_upload_id: String,
-    pub function_id: String,
-    pub success: bool,
-    pub created_at: String,
+/// A tag with how much refers to it, so unused tags can be told apart in listings
+#[derive(Debug, Serialize)]
+pub struct TagUsage {
+    #[serde(flatten)]
+    pub tag: Tag,
+    pub upload_count: i64,
+    pub input_function_count: i64,    // functions triggered by the tag
+    pub output_function_count: i64,   // functions tagging their outputs with it
+    pub excluded_function_count: i64, // functions skipping uploads with it
+}
+
+/// What deleting a tag still in use took away with it
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TagDeletion {
+    pub tag_id: String,
+    pub uploads_untagged: u64,
+    pub input_functions: u64,    // functions no longer triggered by the tag
+    pub output_functions: u64,   // functions no longer tagging their outputs with it
+    pub excluded_functions: u64, // functions no longer skipping uploads with it
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateTag {
     pub name: String,
-    pub color: String,
+    pub color: Option<String>, // the next one from the palette if left out
+    pub description: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UpdateTag {
     pub name: Option<String>,
     pub color: Option<String>,
+    pub description: Option<String>, // an empty one removes the description
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateUpload {
+    pub original_filename: Option<String>,
+    pub protected: Option<bool>,
+    pub intermediate: Option<bool>, // false keeps an intermediate output for good
+}
+
+/// A public link to one upload; anyone holding the token can view and download it
+#[derive(Debug, Serialize)]
+pub struct ShareLink {
+    pub token: String, // served at /share/<token>
+    pub upload_id: String,
+    pub label: Option<String>,
+    pub created_at: String,
+    pub expires_at: Option<String>,
+    pub revoked_at: Option<String>,
+    pub access_count: i64,
+    pub download_count: i64,
+    pub last_accessed_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct CreateShareLink {
+    pub label: Option<String>,
+    pub expires_in_hours: Option<i64>, // never expires if unset
+}
+
+/// What a share link shows of its upload: no tags, lineage or storage details
+#[derive(Debug, Serialize)]
+pub struct SharedUpload {
+    pub filename: String,
+    pub file_size: i64,
+    pub mime_type: Option<String>,
+    pub created_at: String,
+    pub sha256: Option<String>,
+    pub expires_at: Option<String>,
+    pub preview_url: Option<String>, // for CSV and Parquet files
+    pub download_url: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct CopyUpload {
+    pub original_filename: Option<String>, // defaults to the source's name
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -36,29 +103,144 @@ pub struct Upload {
     pub filename: String,
     pub original_filename: String,
     pub file_size: i64,
-    pub mime_type: Option<String>,
+    pub mime_type: Option<String>,          // as claimed by the client
+    pub detected_mime_type: Option<String>, // sniffed from the content
     pub created_at: String,
+    pub sha256: Option<String>,
+    pub assignee: Option<String>, // who is looking into this file
+    pub artifact_type: String,    // `data`, or `error_log` for logs of failed runs
+    #[serde(default)]
+    pub protected: bool, // cannot be deleted, also not by retention rules
+    // Intermediate outputs are deleted at this time, unless kept or protected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
     #[serde(default)]
     pub tags: Vec<Tag>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub lineage: Option<FileLineageInfo>,
+    // Dimensions and EXIF tags of images; only `GET /uploads/:id` includes them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_metadata: Option<ImageMetadata>,
+    // Whether the file is in the --mirror-dir; only `GET /uploads/:id` includes it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replication: Option<Replication>,
+}
+
+/// Copying of an upload's file to the --mirror-dir
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Replication {
+    pub status: String, // pending, mirrored or failed
+    pub error: Option<String>,
+    pub updated_at: String,
+}
+
+/// A page of `GET /uploads`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UploadPage {
+    pub uploads: Vec<Upload>,
+    pub total: i64, // uploads matching the filters, on all pages
+    pub limit: Option<i64>,
+    pub offset: i64,
+}
+
+/// An upload found by `GET /search`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub upload: Upload,
+    pub score: f64, // bm25, lower is a better match
+}
+
+/// A line of an upload that contains the searched string
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct LineMatch {
+    pub line: usize, // from 1
+    pub text: String,
+}
+
+/// An upload whose contents matched `GET /search/contents`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContentHit {
+    pub upload: Upload,
+    pub lines: Vec<LineMatch>, // the first few matching lines
+}
+
+/// Answer of `GET /search/contents`, best matches first
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContentSearchResults {
+    pub query: String,
+    pub hits: Vec<ContentHit>,
+}
+
+/// Answer of `GET /search`, best matches first
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchResults {
+    pub query: String,
+    pub hits: Vec<SearchHit>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct FileLineageInfo {
     pub source_upload_id: String,
     pub source_filename: String,
-    pub function_id: String,
-    pub function_name: String,
+    pub function_id: Option<String>, // None for built-in operations
+    pub function_name: String,       // function name, or the operation name
+    pub operation: Option<String>,
+    pub query: Option<String>, // JSON parameters of the operation
     pub success: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub other_sources: Vec<LineageSource>, // further inputs of multi-source operations like compare
+}
+
+/// How an upload came in, as recorded when it was received
+#[derive(Debug, Serialize)]
+pub struct UploadOrigin {
+    pub received_via: String, // api, url, webdav, remote or manifest
+    pub source_url: Option<String>,
+    pub uploaded_by: Option<String>, // as stated by the client
+    pub client_ip: Option<String>,
+    pub forwarded_for: Option<String>,
+    pub user_agent: Option<String>,
+    pub signature: Option<String>, // as sent, over the uploaded bytes
+}
+
+/// One entry of a chain of custody
+#[derive(Debug, Serialize)]
+pub struct CustodyEvent {
+    pub at: String,
+    pub action: String, // e.g. uploaded, renamed, tagged, processed, review_approved
+    pub actor: Option<String>,
+    pub client_ip: Option<String>,
+    pub detail: Option<String>,
+    pub job_id: Option<String>,
+}
+
+/// Everything known about where an upload came from and what happened to it
+#[derive(Debug, Serialize)]
+pub struct ChainOfCustody {
+    pub upload_id: String,
+    pub filename: String,
+    pub file_size: i64,
+    pub sha256: Option<String>,
+    pub created_at: String,
+    pub origin: Option<UploadOrigin>, // None for files made by functions and operations
+    pub produced_from: Option<FileLineageInfo>,
+    pub events: Vec<CustodyEvent>, // oldest first
+    pub generated_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LineageSource {
+    pub upload_id: String,
+    pub filename: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct DerivedFile {
     pub output_upload_id: String,
     pub output_filename: String,
-    pub function_id: String,
+    pub function_id: Option<String>,
     pub function_name: String,
+    pub operation: Option<String>,
     pub success: bool,
     pub created_at: String,
 }
@@ -70,7 +252,49 @@ pub struct UploadResponse {
     pub original_filename: String,
     pub file_size: i64,
     pub mime_type: Option<String>,
+    pub detected_mime_type: Option<String>,
     pub created_at: String,
+    pub sha256: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub duplicate_of: Vec<String>, // earlier uploads with identical content
+    #[serde(default)]
+    pub jobs: Vec<QueuedJob>, // started by the functions the new upload triggered
+}
+
+/// A file a client is about to upload, for `POST /uploads/precheck`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UploadPrecheck {
+    pub filename: String,
+    pub size: i64, // bytes
+    #[serde(default)]
+    pub tags: Vec<String>, // tag IDs, as sent with the upload
+    pub mime_type: Option<String>,
+    #[serde(default)]
+    pub from_url: bool, // checked against the limits of `/uploads/from-url` instead
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PrecheckReport {
+    pub accepted: bool,
+    pub problems: Vec<String>, // why the upload would be refused
+    pub extension_tag: Option<String>,
+    pub functions: Vec<PrecheckFunction>, // would be triggered by the upload
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PrecheckFunction {
+    pub id: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub estimate: Option<CostEstimate>, // for the announced size
+}
+
+/// A job an upload or trigger request started; poll `GET /jobs/:id` for its progress
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QueuedJob {
+    pub id: String,
+    pub function_id: String,
+    pub function_name: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -80,37 +304,79 @@ pub struct Function {
     pub script_filename: String,
     pub enabled: bool,
     pub function_type: String,
+    pub executor: String, // local, slurm or kubernetes
+    pub trigger_conditions: Vec<TriggerCondition>,
+    #[serde(default)]
+    pub intermediate_outputs: Vec<String>, // filename patterns of outputs that expire
+    #[serde(default = "default_intermediate_ttl_hours")]
+    pub intermediate_ttl_hours: i64,
     pub created_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expression: Option<String>, // the SQL of a quick function
+    #[serde(default)]
+    pub quarantined_at: Option<String>, // disabled by the health check until enabled again
+    #[serde(default)]
+    pub quarantine_reason: Option<String>,
     #[serde(default)]
     pub input_tags: Vec<Tag>,
     #[serde(default)]
     pub output_tags: Vec<Tag>,
+    #[serde(default)]
+    pub excluded_tags: Vec<Tag>, // uploads with any of these never trigger the function
     #[serde(skip_serializing_if = "Option::is_none")]
     pub script_content: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub script_analysis: Option<ScriptAnalysis>, // what the script looks like it does, with the content
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateFunction {
     pub name: String,
+    #[serde(default)]
     pub script_content: String,
+    pub expression: Option<String>, // instead of a script: a quick function, see `quick_function`
     pub input_tag_ids: Vec<String>,
     pub output_tag_ids: Vec<String>,
+    #[serde(default)]
+    pub excluded_tag_ids: Vec<String>,
     #[serde(default = "default_function_type")]
     pub function_type: String,
+    #[serde(default = "default_executor")]
+    pub executor: String,
+    #[serde(default)]
+    pub trigger_conditions: Vec<TriggerCondition>,
+    #[serde(default)]
+    pub intermediate_outputs: Vec<String>,
+    #[serde(default = "default_intermediate_ttl_hours")]
+    pub intermediate_ttl_hours: i64,
 }
 
 fn default_function_type() -> String {
     "transform".to_string() // Default to transformation for backward compatibility
 }
 
+fn default_executor() -> String {
+    "local".to_string()
+}
+
+fn default_intermediate_ttl_hours() -> i64 {
+    24
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UpdateFunction {
     pub name: Option<String>,
     pub script_content: Option<String>,
+    pub expression: Option<String>, // quick functions only
     pub input_tag_ids: Option<Vec<String>>,
     pub output_tag_ids: Option<Vec<String>>,
+    pub excluded_tag_ids: Option<Vec<String>>,
     pub enabled: Option<bool>,
     pub function_type: Option<String>,
+    pub executor: Option<String>,
+    pub trigger_conditions: Option<Vec<TriggerCondition>>,
+    pub intermediate_outputs: Option<Vec<String>>,
+    pub intermediate_ttl_hours: Option<i64>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -124,6 +390,11 @@ pub struct Job {
     pub created_at: String,
     pub started_at: Option<String>,
     pub completed_at: Option<String>,
+    pub queued_seconds: Option<f64>, // from submission until it started running
+    pub duration_seconds: Option<f64>, // from start to completion; None until it finished
+    pub assignee: Option<String>,    // who is triaging this job
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input_slice: Option<InputSlice>, // set for trial runs on part of the input
     // Populated from joins
     #[serde(skip_serializing_if = "Option::is_none")]
     pub upload_filename: Option<String>,
@@ -131,4 +402,732 @@ pub struct Job {
     pub function_name: Option<String>,
     #[serde(default)]
     pub output_filenames: Vec<String>,
+    #[serde(default)]
+    pub labels: Vec<String>, // triage labels of its annotations
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snapshot_id: Option<String>, // the pipeline snapshot active when it was queued
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snapshot_label: Option<String>,
+    // What a job still waiting for a slot is expected to take, from the function's history
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub estimate: Option<CostEstimate>,
+}
+
+/// A named version of the whole pipeline, see `snapshots`
+#[derive(Debug, Serialize)]
+pub struct PipelineSnapshot {
+    pub id: String,
+    pub label: String,
+    pub description: Option<String>,
+    pub created_at: String,
+    pub activated_at: String, // taken or last restored
+    pub active: bool,         // activated last; new jobs record it
+    pub function_count: usize,
+    pub tag_count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pipeline: Option<Pipeline>, // only for a single snapshot
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreatePipelineSnapshot {
+    pub label: String,
+    pub description: Option<String>,
+}
+
+/// The answer to restoring a snapshot
+#[derive(Debug, Serialize)]
+pub struct SnapshotRestore {
+    pub snapshot: PipelineSnapshot,
+    pub changes: PipelineDiff, // from the pipeline before the restore
+    pub disabled_functions: Vec<String>, // created after the snapshot; disabled, not deleted
+}
+
+/// A note on a job with a triage label; labels feed into `GET /stats/jobs`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JobAnnotation {
+    pub id: String,
+    pub job_id: String,
+    pub label: String,
+    pub note: Option<String>,
+    pub author: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateJobAnnotation {
+    pub label: String,
+    pub note: Option<String>,
+    pub author: Option<String>,
+}
+
+/// Part of an upload a trial run works on instead of the whole file; `end` is exclusive
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(tag = "unit", rename_all = "lowercase")]
+pub enum InputSlice {
+    Rows { start: u64, end: u64 }, // data rows of a CSV or Parquet file; the header is kept
+    Bytes { start: u64, end: u64 },
+    Sample { rows: u64, seed: Option<u64> }, // distinct random rows of a CSV or Parquet file
+}
+
+impl InputSlice {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Self::Rows { start, end } | Self::Bytes { start, end } if end <= start => {
+                Err("The slice must end after it starts".to_string())
+            }
+            Self::Sample { rows: 0, .. } => Err("The sample must have rows".to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Rows { start, end } => format!("rows {}..{}", start, end),
+            Self::Bytes { start, end } => format!("bytes {}..{}", start, end),
+            Self::Sample { rows, .. } => format!("a random sample of {} rows", rows),
+        }
+    }
+
+    /// Whether the slice counts table rows, so needs a CSV or Parquet file
+    pub fn is_tabular(&self) -> bool {
+        !matches!(self, Self::Bytes { .. })
+    }
+}
+
+/// Optional body of `POST /uploads/:id/trigger/:function_id`
+#[derive(Debug, Default, Deserialize)]
+pub struct TriggerRequest {
+    pub slice: Option<InputSlice>, // run just this function on part of the upload
+}
+
+/// Body of `POST /functions/:id/preview`
+#[derive(Debug, Deserialize)]
+pub struct FunctionPreviewRequest {
+    pub upload_id: String,
+    #[serde(flatten)]
+    pub sample: SampleQuery, // the rows the function runs on; the first 100 by default
+}
+
+/// What a function made of a sample, without recording a job or keeping its outputs
+#[derive(Debug, Serialize)]
+pub struct FunctionPreview {
+    pub sample: String,         // e.g. "rows 0..100"
+    pub outputs: Vec<String>,   // names the outputs would have been uploaded under
+    pub output: Option<String>, // the tabular output previewed, if any
+    pub preview: Option<TablePreview>,
+    pub error_log: Option<String>, // the log of a failing run
+}
+
+/// Manifest a remote worker sends with `POST /jobs/:id/complete`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JobCompletion {
+    #[serde(default = "default_true")]
+    pub success: bool, // false turns `log` into an error log output, like a failing script
+    pub log: Option<String>,
+    pub error_message: Option<String>, // the function could not be run at all; fails the job
+    #[serde(default)]
+    pub dictionaries: BTreeMap<String, DataDictionary>, // keyed by output filename
+}
+
+impl Default for JobCompletion {
+    fn default() -> Self {
+        Self {
+            success: true,
+            log: None,
+            error_message: None,
+            dictionaries: BTreeMap::new(),
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SavedView {
+    pub id: String,
+    pub name: String,
+    pub tag_expression: Option<String>,
+    pub name_pattern: Option<String>, // glob over the original filename, e.g. "*.s2p"
+    pub created_after: Option<String>,
+    pub created_before: Option<String>,
+    pub within_days: Option<i64>,
+    pub sort_by: String,    // created_at, name, size
+    pub sort_order: String, // asc, desc
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateView {
+    pub name: String,
+    pub tag_expression: Option<String>,
+    pub name_pattern: Option<String>,
+    pub created_after: Option<String>,
+    pub created_before: Option<String>,
+    pub timezone: Option<String>, // for dates and times without an offset; UTC if unset
+    pub within_days: Option<i64>,
+    #[serde(default = "default_sort_by")]
+    pub sort_by: String,
+    #[serde(default = "default_sort_order")]
+    pub sort_order: String,
+}
+
+fn default_sort_by() -> String {
+    "created_at".to_string()
+}
+
+fn default_sort_order() -> String {
+    "desc".to_string()
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateView {
+    pub name: Option<String>,
+    pub tag_expression: Option<String>,
+    pub name_pattern: Option<String>,
+    pub created_after: Option<String>,
+    pub created_before: Option<String>,
+    pub timezone: Option<String>, // for dates and times without an offset; UTC if unset
+    #[serde(default, deserialize_with = "present")]
+    pub within_days: Option<Option<i64>>, // null clears it
+    pub sort_by: Option<String>,
+    pub sort_order: Option<String>,
+}
+
+// Tell a field sent as null (Some(None)) from one left out (None)
+fn present<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ColumnInfo {
+    pub description: Option<String>,
+    pub unit: Option<String>,
+}
+
+/// Column descriptions and units for a tabular upload, keyed by column name.
+/// Functions can emit the same JSON as `<output>.dictionary.json` next to an output file.
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct DataDictionary {
+    pub columns: BTreeMap<String, ColumnInfo>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReportTemplate {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub format: String, // markdown or html
+    pub template: String,
+    pub queries: BTreeMap<String, SqlRequest>, // run before rendering, available as `queries.<name>`
+    pub schedule_hours: Option<i64>,           // render automatically every N hours
+    pub last_rendered_at: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateReport {
+    pub name: String,
+    pub description: Option<String>,
+    #[serde(default = "default_report_format")]
+    pub format: String,
+    pub template: String,
+    #[serde(default)]
+    pub queries: BTreeMap<String, SqlRequest>,
+    pub schedule_hours: Option<i64>,
+}
+
+fn default_report_format() -> String {
+    "markdown".to_string()
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateReport {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub format: Option<String>,
+    pub template: Option<String>,
+    pub queries: Option<BTreeMap<String, SqlRequest>>,
+    pub schedule_hours: Option<i64>, // 0 switches the schedule off
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Notification {
+    pub id: String,
+    pub kind: String, // job_failed, upload_quarantined
+    pub title: String,
+    pub message: Option<String>,
+    pub upload_id: Option<String>,
+    pub job_id: Option<String>,
+    pub read_at: Option<String>,
+    pub created_at: String,
+}
+
+/// A file the malware scan flagged; it is kept aside until released or discarded
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QuarantinedUpload {
+    pub id: String,
+    pub original_filename: String,
+    pub file_size: i64,
+    pub mime_type: Option<String>,
+    pub sha256: String,
+    pub status: String,  // infected, scan_failed
+    pub finding: String, // the scanner's finding or error
+    pub tag_ids: Vec<String>,
+    pub created_at: String,
+}
+
+/// A file a watch folder poll picked up, and what became of it
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WatchedFile {
+    pub path: String,
+    pub file_size: i64,
+    pub modified_at: String,
+    pub status: String, // ingested, quarantined, rejected
+    pub upload_id: Option<String>,
+    pub message: Option<String>, // why it was quarantined or rejected
+    pub picked_up_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NotificationList {
+    pub notifications: Vec<Notification>,
+    pub unread: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AssignRequest {
+    pub assignee: Option<String>, // a name or @handle; null or empty unassigns
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReviewQueue {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub input_tag: Tag,
+    pub approve_tag: Tag,
+    pub reject_tag: Option<Tag>,
+    pub pending: i64, // uploads with the input tag that have not been reviewed yet
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateReviewQueue {
+    pub name: String,
+    pub description: Option<String>,
+    pub input_tag_id: String,
+    pub approve_tag_id: String,
+    pub reject_tag_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateReviewQueue {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub input_tag_id: Option<String>,
+    pub approve_tag_id: Option<String>,
+    pub reject_tag_id: Option<String>, // empty string removes the reject tag
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Review {
+    pub id: String,
+    pub queue_id: String,
+    pub upload_id: String,
+    pub decision: String, // approved or rejected
+    pub comment: Option<String>,
+    pub reviewer: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReviewItem {
+    pub upload: Upload,
+    pub review: Option<Review>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SubmitReview {
+    pub decision: String, // approve or reject
+    pub comment: Option<String>,
+    pub reviewer: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchiveRequest {
+    #[serde(default)]
+    pub upload_ids: Vec<String>,
+    pub tag_expression: Option<String>, // adds every upload whose tags match
+    pub filename: Option<String>,       // defaults to datalab-export.zip
+}
+
+/// Which uploads go into a release, as for archives: the listed ones, then those whose tags
+/// match the expression
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct ReleaseSelection {
+    #[serde(default)]
+    pub upload_ids: Vec<String>,
+    pub tag_expression: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateRelease {
+    pub name: String,
+    pub identifier: Option<String>, // DOI-style, e.g. 10.5281/zenodo.1234567
+    pub description: Option<String>,
+    #[serde(flatten)]
+    pub selection: ReleaseSelection,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateRelease {
+    pub identifier: String,
+}
+
+/// A named group of uploads, e.g. the files of one sweep, that is tagged and exported as one
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Dataset {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub created_at: String,
+    pub updated_at: String, // last change to the name, description or members
+    pub upload_count: i64,
+    pub total_bytes: i64,
+}
+
+/// `GET /datasets/:id`: the dataset with its uploads, in the order they were added
+#[derive(Debug, Serialize)]
+pub struct DatasetDetail {
+    #[serde(flatten)]
+    pub dataset: Dataset,
+    pub uploads: Vec<Upload>,
+}
+
+/// Uploads to add to a dataset: the listed ones, then those whose tags match the expression,
+/// then those the filter picks
+#[derive(Debug, Deserialize, Default)]
+pub struct DatasetMembers {
+    #[serde(default)]
+    pub upload_ids: Vec<String>,
+    pub tag_expression: Option<String>,
+    pub filter: Option<UploadSelector>,
+}
+
+impl DatasetMembers {
+    pub fn is_empty(&self) -> bool {
+        self.upload_ids.is_empty() && self.tag_expression.is_none() && self.filter.is_none()
+    }
+}
+
+/// Uploads picked as `GET /uploads` filters them; intermediate outputs and error logs are left
+/// out. An empty selector picks every upload.
+#[derive(Debug, Deserialize, Default)]
+pub struct UploadSelector {
+    pub filename: Option<String>, // case-insensitive substring
+    pub created_after: Option<String>,
+    pub created_before: Option<String>,
+    pub tz: Option<String>, // for dates without a timezone
+    pub derived: Option<bool>,
+    #[serde(default)]
+    pub tag_ids: Vec<String>, // uploads with all of these tags
+    #[serde(default)]
+    pub tag_names: Vec<String>,
+}
+
+/// Another DataLab instance whose uploads can be browsed and imported
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Remote {
+    pub id: String,
+    pub name: String,
+    pub base_url: String, // e.g. `https://datalab.other-site.org`, without `/api`
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateRemote {
+    pub name: String,
+    pub base_url: String,
+}
+
+/// `POST /remotes/:id/import`: uploads of the remote to copy in
+#[derive(Debug, Deserialize)]
+pub struct RemoteImport {
+    pub upload_ids: Vec<String>, // as the remote knows them
+    #[serde(default)]
+    pub tags: Vec<String>, // local tag IDs to add to every imported upload
+    #[serde(default)]
+    pub copy_tags: bool, // also tag them with the remote's tags, by name
+}
+
+/// One imported upload, under the ID the remote gave it
+#[derive(Debug, Serialize)]
+pub struct ImportedUpload {
+    pub remote_upload_id: String,
+    pub upload: UploadResponse,
+}
+
+/// `POST /uploads/import-manifest`: a manifest imported in the background, one row at a time
+#[derive(Debug, Serialize)]
+pub struct ImportBatch {
+    pub id: String,
+    pub status: String, // running, completed
+    pub created_by: Option<String>,
+    pub created_at: String,
+    pub finished_at: Option<String>,
+    pub total: i64, // rows, then the number of rows by status
+    pub pending: i64,
+    pub imported: i64,
+    pub failed: i64,
+    pub quarantined: i64,
+}
+
+/// A batch with its rows, in manifest order
+#[derive(Debug, Serialize)]
+pub struct ImportBatchDetail {
+    #[serde(flatten)]
+    pub batch: ImportBatch,
+    pub rows: Vec<ImportRow>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportRow {
+    pub row_number: i64, // from 1
+    pub source: String,
+    pub filename: Option<String>,
+    pub tags: Vec<String>, // tag IDs
+    pub assignee: Option<String>,
+    pub metadata: serde_json::Map<String, serde_json::Value>,
+    pub status: String, // pending, imported, failed, quarantined
+    pub upload_id: Option<String>,
+    pub message: Option<String>, // why the row failed or was quarantined
+}
+
+/// `POST /tags/:id/assign` and `/unassign`: the listed uploads, then those the filter picks
+#[derive(Debug, Deserialize)]
+pub struct BulkTagRequest {
+    #[serde(default)]
+    pub upload_ids: Vec<String>,
+    pub filter: Option<UploadSelector>,
+    #[serde(default)]
+    pub triggers: BulkTriggers, // assigning only
+}
+
+/// How functions are triggered when a tag is assigned to many uploads at once
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BulkTriggers {
+    #[default]
+    Each, // as if every upload was tagged on its own
+    Batch, // one pass over the newly tagged uploads, once all of them are tagged
+    None,  // no functions run; e.g. for re-labelling what was processed already
+}
+
+#[derive(Debug, Serialize)]
+pub struct BulkTagResult {
+    pub tag_id: String,
+    pub matched: usize, // uploads selected
+    pub changed: usize, // of those, the ones that were (un)tagged; the others already were
+    pub triggers: BulkTriggers,
+}
+
+/// `GET /datasets/:id/stats`: what the uploads of a dataset are made of
+#[derive(Debug, Serialize)]
+pub struct DatasetStats {
+    pub dataset_id: String,
+    pub upload_count: i64,
+    pub total_bytes: i64,
+    pub derived_count: i64, // outputs of functions and operations
+    pub first_created_at: Option<String>,
+    pub last_created_at: Option<String>,
+    pub by_extension: Vec<ExtensionStorage>,
+    pub by_tag: Vec<TagStorage>, // tags on at least one of its uploads
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateDataset {
+    pub name: String,
+    pub description: Option<String>,
+    #[serde(flatten)]
+    pub members: DatasetMembers, // optional; a dataset can start empty
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateDataset {
+    pub name: Option<String>,
+    pub description: Option<String>,
+}
+
+/// A file as it was released
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReleaseFile {
+    pub upload_id: String,
+    pub name: String, // in the bundle
+    pub sha256: String,
+    pub file_size: i64,
+}
+
+/// A frozen, checksummed set of uploads; with its files it is the manifest of the bundle
+#[derive(Debug, Serialize)]
+pub struct Release {
+    pub id: String,
+    pub name: String,
+    pub identifier: Option<String>,
+    pub description: Option<String>,
+    pub created_at: String,
+    pub checksum: String, // SHA-256 of the bundle's SHA256SUMS
+    pub file_count: i64,
+    pub total_bytes: i64,
+    pub selection: ReleaseSelection,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub files: Option<Vec<ReleaseFile>>,
+}
+
+/// Whether the files of a release still hash to what was released
+#[derive(Debug, Serialize)]
+pub struct ReleaseVerification {
+    pub release_id: String,
+    pub intact: bool,
+    pub checked: usize,
+    pub mismatched: Vec<String>, // names of files whose content changed
+    pub missing: Vec<String>,    // names of files no longer on disk
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RetentionRule {
+    pub id: String,
+    pub name: String,
+    pub tag: Option<Tag>,      // None applies the rule to every upload
+    pub error_logs_only: bool, // only outputs of failed function runs
+    pub max_age_days: i64,
+    pub enabled: bool,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateRetentionRule {
+    pub name: String,
+    pub tag_id: Option<String>,
+    #[serde(default)]
+    pub error_logs_only: bool,
+    pub max_age_days: i64,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateRetentionRule {
+    pub name: Option<String>,
+    pub tag_id: Option<String>, // empty string makes the rule global
+    pub error_logs_only: Option<bool>,
+    pub max_age_days: Option<i64>,
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RetentionPurge {
+    pub id: String,
+    pub rule_id: Option<String>,
+    pub rule_name: String,
+    pub upload_id: String,
+    pub original_filename: String,
+    pub file_size: i64,
+    pub uploaded_at: String,
+    pub purged_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RetentionSweep {
+    pub dry_run: bool,
+    pub purged: Vec<RetentionPurge>, // with dry_run, what would be deleted
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TagStorageUsage {
+    pub tag: Tag,
+    pub used_bytes: i64,
+    pub upload_count: i64,
+    pub max_bytes: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StorageUsage {
+    pub used_bytes: i64,
+    pub upload_count: i64,
+    pub max_bytes: Option<i64>,     // global quota, None if unlimited
+    pub tags: Vec<TagStorageUsage>, // tags with a quota
+}
+
+/// `GET /mirror`: how far the --mirror-dir is behind
+#[derive(Debug, Serialize)]
+pub struct MirrorStatus {
+    pub directory: String,
+    pub mirrored: i64,
+    pub pending: i64,
+    pub failed: i64,
+    pub unmirrored: i64, // stored before mirroring was set up, until the next sync
+    pub database_mirrored_at: Option<String>,
+    pub failures: Vec<MirrorFailure>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MirrorFailure {
+    pub upload_id: String,
+    pub filename: String,
+    pub error: Option<String>,
+    pub updated_at: String,
+}
+
+/// What one pass over the uploads not yet in the mirror did
+#[derive(Debug, Serialize)]
+pub struct MirrorSync {
+    pub mirrored: usize,
+    pub failed: usize,
+    pub database_mirrored: bool,
+}
+
+/// `GET /stats/storage`: what the uploads take up, and what is left on their volume
+#[derive(Debug, Serialize)]
+pub struct StorageStats {
+    pub upload_count: i64,
+    pub used_bytes: i64,    // sizes as uploaded, as counted against quotas
+    pub on_disk_bytes: u64, // after compression and deduplication
+    pub by_extension: Vec<ExtensionStorage>,
+    pub by_tag: Vec<TagStorage>,
+    pub disk: DiskSpace,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExtensionStorage {
+    pub extension: Option<String>, // e.g. `.csv`; None for files without a recognised one
+    pub upload_count: i64,
+    pub used_bytes: i64,
+}
+
+/// Uploads carrying a tag; an upload with several tags counts towards each
+#[derive(Debug, Serialize)]
+pub struct TagStorage {
+    pub tag_id: String,
+    pub name: String,
+    pub upload_count: i64,
+    pub used_bytes: i64,
+}
+
+/// The filesystem holding the uploads directory
+#[derive(Debug, Serialize)]
+pub struct DiskSpace {
+    pub path: String,
+    pub total_bytes: u64,
+    pub available_bytes: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SetStorageQuota {
+    pub max_bytes: i64,
 }
@@ -0,0 +1,146 @@
+use crate::timestamps;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Anything younger than this is left alone: uploads are written to disk before their row is
+/// inserted, and running jobs keep temporary scripts and outputs around
+pub const ORPHAN_GRACE: Duration = Duration::from_secs(60 * 60);
+
+/// A file or directory on disk that no database row points to
+#[derive(Debug, Serialize)]
+pub struct OrphanFile {
+    pub directory: String,
+    pub name: String,
+    pub size_bytes: u64,
+    pub modified_at: String,
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+/// A database row whose file is gone
+#[derive(Debug, Serialize)]
+pub struct MissingFile {
+    pub directory: String,
+    pub name: String,
+    pub referenced_by: String, // e.g. `upload <id>`
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct OrphanReport {
+    pub orphans: Vec<OrphanFile>,
+    pub missing: Vec<MissingFile>,
+    pub orphan_bytes: u64,
+    pub deleted: bool, // whether the orphans were removed
+}
+
+fn entry_size(path: &Path) -> u64 {
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    std::fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry_size(&entry.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// Entries of `dir` whose name is not in `referenced` and that were last modified before
+/// `grace` ago. A missing directory has no orphans.
+pub fn scan_directory(
+    dir: &Path,
+    label: &str,
+    referenced: &HashSet<String>,
+    grace: Duration,
+) -> io::Result<Vec<OrphanFile>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let cutoff = SystemTime::now()
+        .checked_sub(grace)
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+
+    let mut orphans = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if referenced.contains(&name) {
+            continue;
+        }
+        let modified = entry.metadata()?.modified()?;
+        if modified > cutoff {
+            continue;
+        }
+        orphans.push(OrphanFile {
+            directory: label.to_string(),
+            name,
+            size_bytes: entry_size(&entry.path()),
+            modified_at: timestamps::format(DateTime::<Utc>::from(modified)),
+            path: entry.path(),
+        });
+    }
+    orphans.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(orphans)
+}
+
+/// Remove the given orphans from disk; returns how many could not be removed
+pub fn delete_orphans(orphans: &[OrphanFile]) -> usize {
+    orphans
+        .iter()
+        .filter(|orphan| {
+            let result = if orphan.path.is_dir() {
+                std::fs::remove_dir_all(&orphan.path)
+            } else {
+                std::fs::remove_file(&orphan.path)
+            };
+            if let Err(e) = &result {
+                tracing::warn!("Failed to delete {}: {}", orphan.path.display(), e);
+            }
+            result.is_err()
+        })
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_and_delete_orphans() {
+        let dir = std::env::temp_dir().join(format!("datalab-orphans-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("incoming_1")).unwrap();
+        std::fs::write(dir.join("incoming_1").join("part.csv"), "abc").unwrap();
+        std::fs::write(dir.join("known.csv"), "x").unwrap();
+        std::fs::write(dir.join("stray.csv"), "12345").unwrap();
+
+        let referenced = HashSet::from(["known.csv".to_string()]);
+        let recent = scan_directory(&dir, "uploads", &referenced, ORPHAN_GRACE).unwrap();
+        assert!(recent.is_empty(), "fresh files are within the grace period");
+
+        let orphans = scan_directory(&dir, "uploads", &referenced, Duration::ZERO).unwrap();
+        let found: Vec<(&str, u64)> = orphans
+            .iter()
+            .map(|o| (o.name.as_str(), o.size_bytes))
+            .collect();
+        assert_eq!(found, [("incoming_1", 3), ("stray.csv", 5)]);
+
+        assert_eq!(delete_orphans(&orphans), 0);
+        let left: Vec<_> = std::fs::read_dir(&dir).unwrap().flatten().collect();
+        assert_eq!(left.len(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let missing = scan_directory(&dir, "uploads", &referenced, Duration::ZERO).unwrap();
+        assert!(missing.is_empty());
+    }
+}
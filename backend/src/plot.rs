@@ -0,0 +1,497 @@
+use crate::filter_expr::FilterExpr;
+use crate::table_parser::scan_table_with_dates;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+
+// Rows read for a plot; larger tables are cut off (filter them down first)
+pub const MAX_PLOT_POINTS: usize = 100_000;
+const MAX_BARS: usize = 200;
+const MAX_BINS: usize = 500;
+const MAX_PLOT_SIZE: u32 = 4000;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PlotKind {
+    #[default]
+    Line,
+    Scatter,
+    Bar,
+    Histogram,
+}
+
+impl PlotKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PlotKind::Line => "line",
+            PlotKind::Scatter => "scatter",
+            PlotKind::Bar => "bar",
+            PlotKind::Histogram => "histogram",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PlotFormat {
+    #[default]
+    Png,
+    Svg,
+}
+
+impl PlotFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            PlotFormat::Png => "png",
+            PlotFormat::Svg => "svg",
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            PlotFormat::Png => "image/png",
+            PlotFormat::Svg => "image/svg+xml",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlotRequest {
+    pub x: String, // the binned column for histograms, category labels for bar charts
+    #[serde(default)]
+    pub y: Vec<String>, // one series per column; unused for histograms
+    #[serde(default)]
+    pub kind: PlotKind,
+    pub filter: Option<String>, // same grammar as the preview `filter=` parameter
+    #[serde(default)]
+    pub format: PlotFormat,
+    pub width: Option<u32>,  // default 800
+    pub height: Option<u32>, // default 500
+    pub title: Option<String>,
+    pub bins: Option<usize>, // histogram bins, default 20
+    // Only used when saving the plot as an upload
+    #[serde(default, skip_serializing)]
+    pub filename: Option<String>,
+    #[serde(default, skip_serializing)]
+    pub tags: Vec<String>,
+}
+
+impl PlotRequest {
+    fn size(&self) -> (u32, u32) {
+        (self.width.unwrap_or(800), self.height.unwrap_or(500))
+    }
+
+    fn bins(&self) -> usize {
+        self.bins.unwrap_or(20)
+    }
+
+    fn filter(&self) -> Result<Option<FilterExpr>, String> {
+        match self.filter.as_deref().map(str::trim) {
+            Some(filter) if !filter.is_empty() => FilterExpr::parse(filter).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    fn scan(&self, file_path: &str, file_extension: &str) -> Result<LazyFrame, String> {
+        let mut lf = scan_table_with_dates(file_path, &file_extension.to_lowercase())
+            .map_err(|e| format!("Failed to read table: {}", e))?;
+        if let Some(filter) = self.filter()? {
+            lf = lf.filter(filter.to_polars());
+        }
+        Ok(lf)
+    }
+}
+
+pub struct RenderedPlot {
+    pub data: Vec<u8>,
+    pub format: PlotFormat,
+}
+
+/// Check the request against the table schema, returning a user-facing message on failure
+pub fn validate_plot(
+    file_path: &str,
+    file_extension: &str,
+    request: &PlotRequest,
+) -> Result<(), String> {
+    let (width, height) = request.size();
+    if !(100..=MAX_PLOT_SIZE).contains(&width) || !(100..=MAX_PLOT_SIZE).contains(&height) {
+        return Err(format!(
+            "Plot width and height must be between 100 and {} pixels",
+            MAX_PLOT_SIZE
+        ));
+    }
+
+    let mut lf = request.scan(file_path, file_extension)?;
+    let schema = lf
+        .collect_schema()
+        .map_err(|e| format!("Failed to read table schema: {}", e))?;
+    if let Some(filter) = request.filter()? {
+        filter.check_types(&schema)?;
+    }
+
+    let x_dtype = schema
+        .get(request.x.as_str())
+        .ok_or_else(|| format!("Unknown column: {}", request.x))?;
+    for (i, column) in request.y.iter().enumerate() {
+        if request.y[..i].contains(column) {
+            return Err(format!("Column {} is listed twice", column));
+        }
+        let dtype = schema
+            .get(column.as_str())
+            .ok_or_else(|| format!("Unknown column: {}", column))?;
+        if !dtype.is_primitive_numeric() {
+            return Err(format!("Column {} is not numeric ({})", column, dtype));
+        }
+    }
+
+    match request.kind {
+        PlotKind::Histogram => {
+            if !request.y.is_empty() {
+                return Err("Histograms bin the x column; leave y empty".to_string());
+            }
+            if !x_dtype.is_primitive_numeric() {
+                return Err(format!("Column {} is not numeric ({})", request.x, x_dtype));
+            }
+            if !(1..=MAX_BINS).contains(&request.bins()) {
+                return Err(format!("bins must be between 1 and {}", MAX_BINS));
+            }
+        }
+        PlotKind::Line | PlotKind::Scatter | PlotKind::Bar if request.y.is_empty() => {
+            return Err("Set at least one y column".to_string());
+        }
+        PlotKind::Line | PlotKind::Scatter => {
+            if !(x_dtype.is_primitive_numeric() || x_dtype.is_temporal()) {
+                return Err(format!(
+                    "Column {} must be numeric or a date to use as x ({})",
+                    request.x, x_dtype
+                ));
+            }
+        }
+        PlotKind::Bar => {
+            let rows = lf
+                .select([len()])
+                .collect()
+                .and_then(|df| df.column("len")?.get(0).map(|v| v.extract::<usize>()))
+                .map_err(|e| format!("Failed to count rows: {}", e))?
+                .unwrap_or(0);
+            if rows > MAX_BARS {
+                return Err(format!(
+                    "Bar charts are limited to {} bars, got {} rows; filter or aggregate first",
+                    MAX_BARS, rows
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Values of the x axis: numbers (dates as milliseconds since the epoch) or category labels
+enum XValues {
+    Numbers(Vec<Option<f64>>),
+    Dates(Vec<Option<f64>>),
+    Labels(Vec<String>),
+}
+
+struct PlotData {
+    x: XValues,
+    series: Vec<(String, Vec<Option<f64>>)>,
+}
+
+fn load_plot_data(
+    file_path: &str,
+    file_extension: &str,
+    request: &PlotRequest,
+) -> Result<PlotData, Box<dyn std::error::Error>> {
+    let mut lf = request.scan(file_path, file_extension)?;
+    let x_dtype = lf
+        .collect_schema()?
+        .get(request.x.as_str())
+        .cloned()
+        .ok_or_else(|| format!("Unknown column: {}", request.x))?;
+
+    let x_expr = match (&request.kind, &x_dtype) {
+        (PlotKind::Bar, _) => col(request.x.as_str()).cast(DataType::String),
+        (_, dtype) if dtype.is_temporal() => col(request.x.as_str())
+            .dt()
+            .timestamp(TimeUnit::Milliseconds)
+            .cast(DataType::Float64),
+        _ => col(request.x.as_str()).cast(DataType::Float64),
+    };
+    let mut columns = vec![x_expr.alias("__x")];
+    columns.extend(
+        request
+            .y
+            .iter()
+            .map(|c| col(c.as_str()).cast(DataType::Float64)),
+    );
+    let df = lf
+        .select(columns)
+        .limit(MAX_PLOT_POINTS as IdxSize)
+        .collect()?;
+
+    let floats = |name: &str| -> PolarsResult<Vec<Option<f64>>> {
+        Ok(df.column(name)?.f64()?.iter().collect())
+    };
+    let x = match request.kind {
+        PlotKind::Bar => XValues::Labels(
+            df.column("__x")?
+                .str()?
+                .iter()
+                .map(|v| v.unwrap_or("null").to_string())
+                .collect(),
+        ),
+        _ if x_dtype.is_temporal() => XValues::Dates(floats("__x")?),
+        _ => XValues::Numbers(floats("__x")?),
+    };
+    let series = request
+        .y
+        .iter()
+        .map(|name| Ok((name.clone(), floats(name)?)))
+        .collect::<PolarsResult<_>>()?;
+
+    Ok(PlotData { x, series })
+}
+
+/// Smallest and largest finite value, widened when all values are equal
+fn value_range(values: impl Iterator<Item = f64>) -> (f64, f64) {
+    let (min, max) = values
+        .filter(|v| v.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| {
+            (lo.min(v), hi.max(v))
+        });
+    if min > max {
+        (0.0, 1.0)
+    } else if min == max {
+        (min - 0.5, max + 0.5)
+    } else {
+        (min, max)
+    }
+}
+
+/// Count values into equal-width bins, returning (start, end, count) per bin
+fn histogram_bins(values: &[Option<f64>], bins: usize) -> Vec<(f64, f64, usize)> {
+    let (min, max) = value_range(values.iter().flatten().copied());
+    let width = (max - min) / bins as f64;
+    let mut counts = vec![0; bins];
+    for v in values.iter().flatten().filter(|v| v.is_finite()) {
+        let bin = (((v - min) / width) as usize).min(bins - 1);
+        counts[bin] += 1;
+    }
+    counts
+        .into_iter()
+        .enumerate()
+        .map(|(i, count)| (min + i as f64 * width, min + (i + 1) as f64 * width, count))
+        .collect()
+}
+
+fn format_date(millis: f64) -> String {
+    chrono::DateTime::from_timestamp_millis(millis as i64)
+        .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default()
+}
+
+fn draw_plot<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    data: &PlotData,
+    request: &PlotRequest,
+) -> Result<(), DrawingAreaErrorKind<DB::ErrorType>> {
+    root.fill(&WHITE)?;
+    let mut builder = ChartBuilder::on(root);
+    builder
+        .margin(15)
+        .x_label_area_size(40)
+        .y_label_area_size(60);
+    if let Some(title) = &request.title {
+        builder.caption(title, ("sans-serif", 22));
+    }
+    let y_desc = match (request.kind, request.y.as_slice()) {
+        (PlotKind::Histogram, _) => "count",
+        (_, [single]) => single.as_str(),
+        _ => "",
+    };
+
+    let y_values = || {
+        data.series
+            .iter()
+            .flat_map(|(_, v)| v.iter().flatten().copied())
+    };
+    match (&data.x, request.kind) {
+        (XValues::Numbers(values), PlotKind::Histogram) => {
+            let bins = histogram_bins(values, request.bins());
+            let max_count = bins.iter().map(|(_, _, c)| *c).max().unwrap_or(0);
+            let mut chart = builder.build_cartesian_2d(
+                bins[0].0..bins[bins.len() - 1].1,
+                0.0..(max_count.max(1) as f64 * 1.05),
+            )?;
+            chart
+                .configure_mesh()
+                .x_desc(request.x.as_str())
+                .y_desc(y_desc)
+                .draw()?;
+            let color = Palette99::pick(0).to_rgba();
+            chart.draw_series(bins.iter().map(|(start, end, count)| {
+                let mut bar =
+                    Rectangle::new([(*start, 0.0), (*end, *count as f64)], color.filled());
+                bar.set_margin(0, 0, 1, 1);
+                bar
+            }))?;
+        }
+        (XValues::Labels(labels), _) => {
+            let (min, max) = value_range(y_values().chain([0.0]));
+            let mut chart = builder.build_cartesian_2d(
+                -0.5..(labels.len() as f64 - 0.5),
+                min..(max + (max - min) * 0.05),
+            )?;
+            let label_at = |v: &f64| {
+                let index = v.round();
+                if (v - index).abs() < 1e-6 && index >= 0.0 {
+                    labels.get(index as usize).cloned().unwrap_or_default()
+                } else {
+                    String::new()
+                }
+            };
+            chart
+                .configure_mesh()
+                .x_labels(labels.len().min(20))
+                .x_label_formatter(&label_at)
+                .x_desc(request.x.as_str())
+                .y_desc(y_desc)
+                .disable_x_mesh()
+                .draw()?;
+            // Bars of several series stand side by side within each category
+            let width = 0.8 / data.series.len() as f64;
+            for (i, (name, values)) in data.series.iter().enumerate() {
+                let color = Palette99::pick(i).to_rgba();
+                chart
+                    .draw_series(values.iter().enumerate().filter_map(|(row, v)| {
+                        let start = row as f64 - 0.4 + i as f64 * width;
+                        v.map(|v| {
+                            Rectangle::new([(start, 0.0), (start + width, v)], color.filled())
+                        })
+                    }))?
+                    .label(name.as_str())
+                    .legend(move |(x, y)| {
+                        Rectangle::new([(x, y - 5), (x + 10, y + 5)], color.filled())
+                    });
+            }
+            if data.series.len() > 1 {
+                chart
+                    .configure_series_labels()
+                    .background_style(WHITE.mix(0.8))
+                    .border_style(BLACK)
+                    .draw()?;
+            }
+        }
+        (XValues::Numbers(x) | XValues::Dates(x), kind) => {
+            let (x_min, x_max) = value_range(x.iter().flatten().copied());
+            let (y_min, y_max) = value_range(y_values());
+            let y_pad = (y_max - y_min) * 0.05;
+            let mut chart =
+                builder.build_cartesian_2d(x_min..x_max, (y_min - y_pad)..(y_max + y_pad))?;
+            let mut mesh = chart.configure_mesh();
+            mesh.x_desc(request.x.as_str()).y_desc(y_desc);
+            if matches!(data.x, XValues::Dates(_)) {
+                mesh.x_label_formatter(&|v| format_date(*v)).x_labels(6);
+            }
+            mesh.draw()?;
+
+            for (i, (name, values)) in data.series.iter().enumerate() {
+                let color = Palette99::pick(i).to_rgba();
+                let points = x.iter().zip(values).filter_map(|(x, y)| {
+                    Some((x.filter(|v| v.is_finite())?, y.filter(|v| v.is_finite())?))
+                });
+                let annotation = if kind == PlotKind::Scatter {
+                    chart.draw_series(points.map(|p| Circle::new(p, 3, color.filled())))?
+                } else {
+                    chart.draw_series(LineSeries::new(points, color.stroke_width(2)))?
+                };
+                annotation.label(name.as_str()).legend(move |(x, y)| {
+                    PathElement::new(vec![(x, y), (x + 20, y)], color.stroke_width(2))
+                });
+            }
+            if data.series.len() > 1 {
+                chart
+                    .configure_series_labels()
+                    .background_style(WHITE.mix(0.8))
+                    .border_style(BLACK)
+                    .draw()?;
+            }
+        }
+    }
+
+    root.present()
+}
+
+/// Render a plot of a table, expecting a request that passed `validate_plot`
+pub fn render_table_plot(
+    file_path: &str,
+    file_extension: &str,
+    request: &PlotRequest,
+) -> Result<RenderedPlot, Box<dyn std::error::Error>> {
+    let data = load_plot_data(file_path, file_extension, request)?;
+    let (width, height) = request.size();
+
+    let data = match request.format {
+        PlotFormat::Svg => {
+            let mut svg = String::new();
+            {
+                let root = SVGBackend::with_string(&mut svg, (width, height)).into_drawing_area();
+                draw_plot(&root, &data, request).map_err(|e| e.to_string())?;
+            }
+            svg.into_bytes()
+        }
+        PlotFormat::Png => {
+            let mut pixels = vec![0u8; (width * height * 3) as usize];
+            {
+                let root =
+                    BitMapBackend::with_buffer(&mut pixels, (width, height)).into_drawing_area();
+                draw_plot(&root, &data, request).map_err(|e| e.to_string())?;
+            }
+            let mut png = Vec::new();
+            let mut encoder = png::Encoder::new(&mut png, width, height);
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.write_header()?.write_image_data(&pixels)?;
+            png
+        }
+    };
+
+    Ok(RenderedPlot {
+        data,
+        format: request.format,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_bins() {
+        let values = [
+            Some(0.0),
+            Some(1.0),
+            Some(2.5),
+            None,
+            Some(10.0),
+            Some(f64::NAN),
+        ];
+        let bins = histogram_bins(&values, 4);
+        assert_eq!(bins.len(), 4);
+        assert_eq!(bins[0], (0.0, 2.5, 2));
+        assert_eq!(bins[1].2, 1);
+        assert_eq!(bins[3], (7.5, 10.0, 1));
+    }
+
+    #[test]
+    fn test_value_range_widens_constant_values() {
+        assert_eq!(value_range([2.0, 2.0].into_iter()), (1.5, 2.5));
+        assert_eq!(value_range(std::iter::empty()), (0.0, 1.0));
+        assert_eq!(
+            value_range([3.0, -1.0, f64::INFINITY].into_iter()),
+            (-1.0, 3.0)
+        );
+    }
+}
@@ -0,0 +1,443 @@
+//! Checks run before the server starts, so a missing `uv`, a read-only directory or a
+//! database from a newer DataLab show up at boot with a hint instead of at the first job.
+//! Failed checks stop the server; warnings only leave it with less to offer.
+
+use crate::repos::{AppliedMigration, SchemaRepo};
+use crate::settings::{SettingValues, Settings};
+use crate::Config;
+use sqlx::sqlite::SqlitePool;
+use std::path::Path;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+/// `uv run --script` needs at least this version
+pub const MIN_UV_VERSION: (u64, u64, u64) = (0, 5, 0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    Warning, // the server starts, with something not working
+    Failed,  // the server does not start
+}
+
+#[derive(Debug)]
+pub struct Check {
+    pub name: String,
+    pub status: Status,
+    pub detail: String,
+    pub hint: Option<String>, // what to do about a warning or failure
+}
+
+impl Check {
+    fn ok(name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            status: Status::Ok,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn warning(
+        name: impl Into<String>,
+        detail: impl Into<String>,
+        hint: impl Into<String>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            status: Status::Warning,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+
+    fn failed(name: impl Into<String>, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            status: Status::Failed,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.checks
+            .iter()
+            .all(|check| check.status != Status::Failed)
+    }
+
+    /// One row per check, with the hints below the checks they belong to
+    pub fn render(&self) -> String {
+        let name_width = self
+            .checks
+            .iter()
+            .map(|check| check.name.chars().count())
+            .chain(["Check".len()])
+            .max()
+            .unwrap_or(0);
+        let mut table = format!("{:<name_width$}  {:<7}  Details\n", "Check", "Status");
+        for check in &self.checks {
+            let status = match check.status {
+                Status::Ok => "ok",
+                Status::Warning => "warning",
+                Status::Failed => "FAILED",
+            };
+            table.push_str(&format!(
+                "{:<name_width$}  {:<7}  {}\n",
+                check.name, status, check.detail
+            ));
+            if let Some(hint) = &check.hint {
+                table.push_str(&format!("{:<name_width$}  {:<7}  → {}\n", "", "", hint));
+            }
+        }
+        table
+    }
+}
+
+/// Version in `uv --version` output, e.g. `uv 0.5.11 (c4d0caaee 2024-12-19)`
+pub fn parse_uv_version(output: &str) -> Option<(u64, u64, u64)> {
+    let mut words = output.split_whitespace();
+    if words.next()? != "uv" {
+        return None;
+    }
+    let version = words.next()?;
+    let mut parts = version.split('.').map(|part| {
+        part.chars()
+            .take_while(char::is_ascii_digit)
+            .collect::<String>()
+            .parse::<u64>()
+            .ok()
+    });
+    Some((
+        parts.next()??,
+        parts.next()??,
+        parts.next().flatten().unwrap_or(0),
+    ))
+}
+
+async fn check_uv(uv_bin: &Path) -> Check {
+    let hint = "Install uv (https://docs.astral.sh/uv/) or point --uv-bin at it; until then functions run with the local executor fail";
+    let output = tokio::time::timeout(
+        Duration::from_secs(10),
+        tokio::process::Command::new(uv_bin)
+            .arg("--version")
+            .output(),
+    )
+    .await;
+    let output = match output {
+        Ok(Ok(output)) if output.status.success() => output,
+        Ok(Ok(output)) => {
+            return Check::warning(
+                "uv",
+                format!(
+                    "{} --version exited with {}",
+                    uv_bin.display(),
+                    output.status
+                ),
+                hint,
+            )
+        }
+        Ok(Err(e)) => {
+            return Check::warning("uv", format!("{} not found: {}", uv_bin.display(), e), hint)
+        }
+        Err(_) => {
+            return Check::warning(
+                "uv",
+                format!("{} --version did not answer", uv_bin.display()),
+                hint,
+            )
+        }
+    };
+    let text = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    match parse_uv_version(&text) {
+        Some(version) if version < MIN_UV_VERSION => Check::warning(
+            "uv",
+            format!("{} is too old", text),
+            format!(
+                "Upgrade to uv {}.{}.{} or later (`uv self update`)",
+                MIN_UV_VERSION.0, MIN_UV_VERSION.1, MIN_UV_VERSION.2
+            ),
+        ),
+        Some(_) => Check::ok("uv", text),
+        None => Check::warning(
+            "uv",
+            format!("Unrecognized version: {}", text),
+            format!("Check that --uv-bin ({}) is uv", uv_bin.display()),
+        ),
+    }
+}
+
+// Create the directory if needed and write and remove a file in it
+async fn check_directory(name: &str, path: &Path) -> Check {
+    let probe = path.join(format!(".datalab-preflight-{}", std::process::id()));
+    let result = async {
+        tokio::fs::create_dir_all(path).await?;
+        tokio::fs::write(&probe, b"").await?;
+        tokio::fs::remove_file(&probe).await
+    }
+    .await;
+    match result {
+        Ok(()) => Check::ok(name, format!("{} is writable", path.display())),
+        Err(e) => Check::failed(
+            name,
+            format!("{} is not writable: {}", path.display(), e),
+            format!(
+                "Fix the permissions of {} for this user, or pass another directory",
+                path.display()
+            ),
+        ),
+    }
+}
+
+/// Compare the migrations applied to a database with the ones this version ships. Ok with the
+/// number still to apply, or why this version cannot use the database.
+pub fn compare_migrations(
+    known: &[(i64, &[u8])],
+    applied: &[AppliedMigration],
+) -> Result<usize, String> {
+    for migration in applied {
+        if !migration.success {
+            return Err(format!(
+                "Migration {} was interrupted and left the schema half-changed",
+                migration.version
+            ));
+        }
+        match known
+            .iter()
+            .find(|(version, _)| *version == migration.version)
+        {
+            None => {
+                return Err(format!(
+                    "Migration {} is newer than this version of DataLab",
+                    migration.version
+                ))
+            }
+            Some((_, checksum)) if *checksum != migration.checksum.as_slice() => {
+                return Err(format!(
+                    "Migration {} was changed since it was applied",
+                    migration.version
+                ))
+            }
+            Some(_) => {}
+        }
+    }
+    Ok(known.len().saturating_sub(applied.len()))
+}
+
+async fn check_database(database_url: &str) -> Check {
+    let db = match SqlitePool::connect(database_url).await {
+        Ok(db) => db,
+        Err(e) => {
+            return Check::failed(
+                "database",
+                format!("Cannot open {}: {}", database_url, e),
+                "Check --database-url; add `?mode=rwc` to create a new database",
+            )
+        }
+    };
+    let applied = SchemaRepo::new(&db).applied_migrations().await;
+    db.close().await;
+    let applied = match applied {
+        Ok(applied) => applied,
+        Err(e) => {
+            return Check::failed(
+                "database",
+                format!("Cannot read the schema version: {}", e),
+                "Check that --database-url points at a DataLab database",
+            )
+        }
+    };
+    let migrator = sqlx::migrate!("./migrations");
+    let known: Vec<(i64, &[u8])> = migrator
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .map(|migration| (migration.version, migration.checksum.as_ref()))
+        .collect();
+    match compare_migrations(&known, &applied) {
+        Ok(0) => Check::ok(
+            "database",
+            format!("Schema up to date ({} migrations)", known.len()),
+        ),
+        Ok(pending) => Check::ok(
+            "database",
+            format!("{} migration(s) to apply at startup", pending),
+        ),
+        Err(e) => Check::failed(
+            "database",
+            e,
+            "Run the DataLab version that last migrated this database, or restore a backup",
+        ),
+    }
+}
+
+fn check_disk_space(path: &Path, largest_upload_bytes: u64) -> Check {
+    let available = match fs4::available_space(path) {
+        Ok(available) => available,
+        Err(e) => {
+            return Check::warning(
+                "disk space",
+                format!("Cannot tell the free space of {}: {}", path.display(), e),
+                "Make sure the uploads volume has room",
+            )
+        }
+    };
+    let detail = format!(
+        "{} MB free for {}",
+        available / (1024 * 1024),
+        path.display()
+    );
+    if available < largest_upload_bytes {
+        Check::warning(
+            "disk space",
+            detail,
+            "The largest allowed upload would not fit; free up space or lower --max-upload-size-mb",
+        )
+    } else {
+        Check::ok("disk space", detail)
+    }
+}
+
+// The settings the server would start with, command line and --config-file together
+fn check_settings(config: &Config) -> Check {
+    let command_line = SettingValues::from_config(config);
+    match Settings::load(&command_line, config.config_file.as_deref()) {
+        Ok(_) => Check::ok(
+            "settings",
+            match &config.config_file {
+                Some(path) => format!("{} is valid", path.display()),
+                None => "No config file, command-line settings are valid".to_string(),
+            },
+        ),
+        Err(e) => Check::failed(
+            "settings",
+            e,
+            "Fix the setting, or leave it out of the config file to use the command-line value",
+        ),
+    }
+}
+
+async fn check_port(host: &str, port: u16) -> (Check, Option<TcpListener>) {
+    let addr = format!("{}:{}", host, port);
+    match TcpListener::bind(&addr).await {
+        Ok(listener) => (
+            Check::ok("port", format!("{} is free", addr)),
+            Some(listener),
+        ),
+        Err(e) => (
+            Check::failed(
+                "port",
+                format!("Cannot listen on {}: {}", addr, e),
+                format!(
+                    "Pick another --port, or stop what uses it: lsof -ti:{} | xargs kill -9",
+                    port
+                ),
+            ),
+            None,
+        ),
+    }
+}
+
+/// Run every check. The listener bound by the port check is handed back, so the server can
+/// use it and nothing takes the port in between.
+pub async fn run_checks(config: &Config) -> (Report, Option<TcpListener>) {
+    let mut report = Report::default();
+    report.checks.push(check_uv(&config.uv_bin).await);
+    report.checks.push(check_settings(config));
+
+    let mut directories = vec![
+        ("uploads dir", &config.uploads_dir),
+        ("scripts dir", &config.scripts_dir),
+        ("output dir", &config.output_dir),
+        ("thumbnails dir", &config.thumbnails_dir),
+        ("decompressed dir", &config.decompressed_dir),
+        ("quarantine dir", &config.quarantine_dir),
+    ];
+    if let Some(mirror_dir) = &config.mirror_dir {
+        directories.push(("mirror dir", mirror_dir));
+    }
+    if let Some(shared_dir) = &config.cluster_shared_dir {
+        directories.push(("cluster shared dir", shared_dir));
+    }
+    for (name, path) in directories {
+        report.checks.push(check_directory(name, path).await);
+    }
+
+    report
+        .checks
+        .push(check_database(&config.database_url).await);
+    report.checks.push(check_disk_space(
+        &config.uploads_dir,
+        config.max_upload_size_mb * 1024 * 1024,
+    ));
+
+    let (check, listener) = check_port(&config.host, config.port).await;
+    report.checks.push(check);
+    (report, listener)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_uv_version() {
+        assert_eq!(
+            parse_uv_version("uv 0.5.11 (c4d0caaee 2024-12-19)"),
+            Some((0, 5, 11))
+        );
+        assert_eq!(parse_uv_version("uv 0.4.0"), Some((0, 4, 0)));
+        assert_eq!(parse_uv_version("uv 1.2"), Some((1, 2, 0)));
+        assert_eq!(parse_uv_version("uv 0.6.0-rc1"), Some((0, 6, 0)));
+        assert_eq!(parse_uv_version("uv"), None);
+        assert_eq!(parse_uv_version("Python 3.12.1"), None);
+    }
+
+    #[test]
+    fn test_compare_migrations() {
+        let applied = |version, checksum: &[u8], success| AppliedMigration {
+            version,
+            checksum: checksum.to_vec(),
+            success,
+        };
+        let known: Vec<(i64, &[u8])> = vec![(1, b"a"), (2, b"b"), (3, b"c")];
+
+        assert_eq!(compare_migrations(&known, &[]), Ok(3));
+        assert_eq!(
+            compare_migrations(&known, &[applied(1, b"a", true), applied(2, b"b", true)]),
+            Ok(1)
+        );
+        assert!(compare_migrations(&known, &[applied(4, b"d", true)])
+            .unwrap_err()
+            .contains("newer"));
+        assert!(compare_migrations(&known, &[applied(1, b"x", true)])
+            .unwrap_err()
+            .contains("changed"));
+        assert!(compare_migrations(&known, &[applied(1, b"a", false)])
+            .unwrap_err()
+            .contains("interrupted"));
+    }
+
+    #[test]
+    fn test_report_render() {
+        let report = Report {
+            checks: vec![
+                Check::ok("uv", "uv 0.5.11"),
+                Check::failed("port", "Cannot listen", "Pick another --port"),
+            ],
+        };
+        assert!(!report.passed());
+        let table = report.render();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[0], "Check  Status   Details");
+        assert_eq!(lines[1], "uv     ok       uv 0.5.11");
+        assert_eq!(lines[2], "port   FAILED   Cannot listen");
+        assert_eq!(lines[3], "                → Pick another --port");
+    }
+}
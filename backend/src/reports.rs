@@ -0,0 +1,152 @@
+use crate::sql_query::SqlResult;
+use minijinja::{AutoEscape, Environment};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Formats a report template can render to, with the extension of the resulting upload
+pub fn report_extension(format: &str) -> Option<&'static str> {
+    match format {
+        "markdown" => Some("md"),
+        "html" => Some("html"),
+        _ => None,
+    }
+}
+
+/// Pipeline activity since the previous rendering of a report
+#[derive(Debug, Serialize)]
+pub struct JobSummary {
+    pub since: String,
+    pub total: i64,
+    pub success: i64,
+    pub failed: i64,
+    pub pending: i64, // submitted or still running
+    pub uploads: i64,
+    pub failures: Vec<FailedJob>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FailedJob {
+    pub id: String,
+    pub function_name: String,
+    pub filename: String,
+    pub error_message: Option<String>,
+    pub completed_at: Option<String>,
+}
+
+/// Everything a template can reference
+#[derive(Debug, Serialize)]
+pub struct ReportContext {
+    pub report: ReportInfo,
+    pub now: String,
+    pub today: String,
+    pub jobs: JobSummary,
+    pub queries: BTreeMap<String, SqlResult>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReportInfo {
+    pub name: String,
+    pub description: Option<String>,
+}
+
+fn environment(format: &str) -> Environment<'static> {
+    let mut env = Environment::new();
+    // Escape query results in HTML reports; Markdown is written verbatim
+    let escape = if format == "html" {
+        AutoEscape::Html
+    } else {
+        AutoEscape::None
+    };
+    env.set_auto_escape_callback(move |_| escape);
+    env
+}
+
+/// Check that a template parses, returning a message pointing at the problem if not
+pub fn validate_template(format: &str, template: &str) -> Result<(), String> {
+    environment(format)
+        .template_from_str(template)
+        .map(|_| ())
+        .map_err(|e| format!("Invalid template: {}", e))
+}
+
+/// Render a Jinja-style template. Query results are available as
+/// `queries.<name>.headers` / `.rows`, and `queries.<name> | tojson` embeds them as chart data.
+pub fn render_report(
+    format: &str,
+    template: &str,
+    context: &ReportContext,
+) -> Result<String, String> {
+    let env = environment(format);
+    let template = env
+        .template_from_str(template)
+        .map_err(|e| format!("Invalid template: {}", e))?;
+    template
+        .render(context)
+        .map_err(|e| format!("Failed to render report: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql_query::SqlEngine;
+
+    fn context() -> ReportContext {
+        let mut queries = BTreeMap::new();
+        queries.insert(
+            "runs".to_string(),
+            SqlResult {
+                engine: SqlEngine::Polars,
+                headers: vec!["run".to_string(), "od".to_string()],
+                rows: vec![vec!["<b>1</b>".to_string(), "0.5".to_string()]],
+                row_count: 1,
+                truncated: false,
+            },
+        );
+        ReportContext {
+            report: ReportInfo {
+                name: "weekly".to_string(),
+                description: None,
+            },
+            now: "2024-01-08T00:00:00+00:00".to_string(),
+            today: "2024-01-08".to_string(),
+            jobs: JobSummary {
+                since: "2024-01-01T00:00:00+00:00".to_string(),
+                total: 3,
+                success: 2,
+                failed: 1,
+                pending: 0,
+                uploads: 5,
+                failures: Vec::new(),
+            },
+            queries,
+        }
+    }
+
+    #[test]
+    fn test_renders_queries_and_job_summary() {
+        let template = "# {{ report.name }} {{ today }}\n\
+            {{ jobs.failed }}/{{ jobs.total }} failed\n\
+            {% for row in queries.runs.rows %}{{ row | join(',') }}{% endfor %}";
+        let output = render_report("markdown", template, &context()).unwrap();
+        assert_eq!(output, "# weekly 2024-01-08\n1/3 failed\n<b>1</b>,0.5");
+    }
+
+    #[test]
+    fn test_html_escapes_values() {
+        let output =
+            render_report("html", "<td>{{ queries.runs.rows[0][0] }}</td>", &context()).unwrap();
+        assert_eq!(output, "<td>&lt;b&gt;1&lt;&#x2f;b&gt;</td>");
+        assert!(
+            render_report("html", "{{ queries.runs | tojson }}", &context())
+                .unwrap()
+                .contains("\"headers\"")
+        );
+    }
+
+    #[test]
+    fn test_invalid_templates() {
+        assert!(validate_template("markdown", "{% for x in y %}").is_err());
+        assert!(validate_template("markdown", "{{ jobs.total }}").is_ok());
+        assert_eq!(report_extension("pdf"), None);
+    }
+}
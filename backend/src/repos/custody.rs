@@ -0,0 +1,156 @@
+use crate::custody::RequestOrigin;
+use crate::models::{CustodyEvent, UploadOrigin};
+use sqlx::SqlitePool;
+
+pub struct CustodyRepo<'a> {
+    db: &'a SqlitePool,
+}
+
+impl<'a> CustodyRepo<'a> {
+    pub fn new(db: &'a SqlitePool) -> Self {
+        Self { db }
+    }
+
+    /// Record how an upload was received
+    pub async fn set_origin(
+        &self,
+        upload_id: &str,
+        received_via: &str,
+        source_url: Option<&str>,
+        origin: &RequestOrigin,
+        signature: Option<&str>,
+    ) -> sqlx::Result<()> {
+        sqlx::query!(
+            "INSERT INTO upload_origins (upload_id, received_via, source_url, uploaded_by, client_ip, forwarded_for, user_agent, signature) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            upload_id,
+            received_via,
+            source_url,
+            origin.user,
+            origin.client_ip,
+            origin.forwarded_for,
+            origin.user_agent,
+            signature
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn origin(&self, upload_id: &str) -> sqlx::Result<Option<UploadOrigin>> {
+        sqlx::query_as!(
+            UploadOrigin,
+            r#"SELECT received_via as "received_via!", source_url, uploaded_by, client_ip, forwarded_for, user_agent, signature
+               FROM upload_origins WHERE upload_id = ?"#,
+            upload_id
+        )
+        .fetch_optional(self.db)
+        .await
+    }
+
+    pub async fn record(
+        &self,
+        upload_id: &str,
+        action: &str,
+        detail: Option<&str>,
+        origin: &RequestOrigin,
+        created_at: &str,
+    ) -> sqlx::Result<()> {
+        sqlx::query!(
+            "INSERT INTO upload_audit (upload_id, action, detail, actor, client_ip, created_at) VALUES (?, ?, ?, ?, ?, ?)",
+            upload_id,
+            action,
+            detail,
+            origin.user,
+            origin.client_ip,
+            created_at
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// The audit entries of an upload in the order they were recorded
+    pub async fn audit(&self, upload_id: &str) -> sqlx::Result<Vec<CustodyEvent>> {
+        let rows = sqlx::query!(
+            r#"SELECT action as "action!", detail, actor, client_ip, created_at as "created_at!"
+               FROM upload_audit WHERE upload_id = ? ORDER BY id"#,
+            upload_id
+        )
+        .fetch_all(self.db)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| CustodyEvent {
+                at: row.created_at,
+                action: row.action,
+                actor: row.actor,
+                client_ip: row.client_ip,
+                detail: row.detail,
+                job_id: None,
+            })
+            .collect())
+    }
+
+    /// The jobs run on an upload, as `processed`, `processing_failed` or (while unfinished)
+    /// `processing` entries
+    pub async fn job_events(&self, upload_id: &str) -> sqlx::Result<Vec<CustodyEvent>> {
+        let rows = sqlx::query!(
+            r#"SELECT j.id as "id!", j.status as "status!", j.created_at as "created_at!", j.completed_at, j.error_message, f.name as "function_name!"
+               FROM jobs j INNER JOIN functions f ON f.id = j.function_id
+               WHERE j.upload_id = ? ORDER BY j.created_at, j.rowid"#,
+            upload_id
+        )
+        .fetch_all(self.db)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let (action, detail) = match row.status.as_str() {
+                    "SUCCESS" => ("processed", row.function_name),
+                    "FAILED" => (
+                        "processing_failed",
+                        match row.error_message {
+                            Some(error) => format!("{}: {}", row.function_name, error),
+                            None => row.function_name,
+                        },
+                    ),
+                    _ => ("processing", row.function_name),
+                };
+                CustodyEvent {
+                    at: row.completed_at.unwrap_or(row.created_at),
+                    action: action.to_string(),
+                    actor: None,
+                    client_ip: None,
+                    detail: Some(detail),
+                    job_id: Some(row.id),
+                }
+            })
+            .collect())
+    }
+
+    /// Review decisions on an upload, as `review_approved` and `review_rejected` entries
+    pub async fn review_events(&self, upload_id: &str) -> sqlx::Result<Vec<CustodyEvent>> {
+        let rows = sqlx::query!(
+            r#"SELECT r.decision as "decision!", r.comment, r.reviewer, r.created_at as "created_at!", q.name as "queue_name!"
+               FROM reviews r INNER JOIN review_queues q ON q.id = r.queue_id
+               WHERE r.upload_id = ? ORDER BY r.created_at"#,
+            upload_id
+        )
+        .fetch_all(self.db)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| CustodyEvent {
+                at: row.created_at,
+                action: format!("review_{}", row.decision),
+                actor: row.reviewer,
+                client_ip: None,
+                detail: Some(match row.comment {
+                    Some(comment) => format!("{}: {}", row.queue_name, comment),
+                    None => row.queue_name,
+                }),
+                job_id: None,
+            })
+            .collect())
+    }
+}
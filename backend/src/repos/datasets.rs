@@ -0,0 +1,189 @@
+use crate::models::{Dataset, TagStorage};
+use sqlx::SqlitePool;
+
+pub struct DatasetRepo<'a> {
+    db: &'a SqlitePool,
+}
+
+impl<'a> DatasetRepo<'a> {
+    pub fn new(db: &'a SqlitePool) -> Self {
+        Self { db }
+    }
+
+    /// All datasets by name, with the number and total size of their uploads
+    pub async fn list(&self) -> sqlx::Result<Vec<Dataset>> {
+        sqlx::query_as!(
+            Dataset,
+            r#"SELECT d.id as "id!", d.name as "name!", d.description, d.created_at as "created_at!", d.updated_at as "updated_at!",
+                      (SELECT COUNT(*) FROM dataset_uploads du WHERE du.dataset_id = d.id) as "upload_count!: i64",
+                      (SELECT COALESCE(SUM(u.file_size), 0) FROM dataset_uploads du INNER JOIN uploads u ON u.id = du.upload_id WHERE du.dataset_id = d.id) as "total_bytes!: i64"
+               FROM datasets d ORDER BY d.name"#
+        )
+        .fetch_all(self.db)
+        .await
+    }
+
+    pub async fn get(&self, id: &str) -> sqlx::Result<Option<Dataset>> {
+        sqlx::query_as!(
+            Dataset,
+            r#"SELECT d.id as "id!", d.name as "name!", d.description, d.created_at as "created_at!", d.updated_at as "updated_at!",
+                      (SELECT COUNT(*) FROM dataset_uploads du WHERE du.dataset_id = d.id) as "upload_count!: i64",
+                      (SELECT COALESCE(SUM(u.file_size), 0) FROM dataset_uploads du INNER JOIN uploads u ON u.id = du.upload_id WHERE du.dataset_id = d.id) as "total_bytes!: i64"
+               FROM datasets d WHERE d.id = ?"#,
+            id
+        )
+        .fetch_optional(self.db)
+        .await
+    }
+
+    /// Fails with a UNIQUE constraint error if the name is taken
+    pub async fn insert(
+        &self,
+        id: &str,
+        name: &str,
+        description: Option<&str>,
+        created_at: &str,
+    ) -> sqlx::Result<()> {
+        sqlx::query!(
+            "INSERT INTO datasets (id, name, description, created_at, updated_at) VALUES (?, ?, ?, ?, ?)",
+            id,
+            name,
+            description,
+            created_at,
+            created_at
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Change the name and/or description; None leaves them as they are. Returns false if
+    /// there is no such dataset
+    pub async fn update(
+        &self,
+        id: &str,
+        name: Option<&str>,
+        description: Option<&str>,
+        updated_at: &str,
+    ) -> sqlx::Result<bool> {
+        let result = sqlx::query!(
+            "UPDATE datasets SET name = COALESCE(?, name), description = COALESCE(?, description), updated_at = ? WHERE id = ?",
+            name,
+            description,
+            updated_at,
+            id
+        )
+        .execute(self.db)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Delete a dataset; its uploads stay
+    pub async fn delete(&self, id: &str) -> sqlx::Result<bool> {
+        let result = sqlx::query!("DELETE FROM datasets WHERE id = ?", id)
+            .execute(self.db)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// IDs of the uploads of a dataset, in the order they were added
+    pub async fn upload_ids(&self, id: &str) -> sqlx::Result<Vec<String>> {
+        sqlx::query_scalar!(
+            r#"SELECT upload_id as "upload_id!" FROM dataset_uploads WHERE dataset_id = ? ORDER BY added_at, rowid"#,
+            id
+        )
+        .fetch_all(self.db)
+        .await
+    }
+
+    /// Uploads and bytes per tag over the uploads of a dataset, largest first
+    pub async fn tag_storage(&self, id: &str) -> sqlx::Result<Vec<TagStorage>> {
+        sqlx::query_as!(
+            TagStorage,
+            r#"SELECT t.id as "tag_id!", t.name as "name!",
+                      COUNT(u.id) as "upload_count!: i64", COALESCE(SUM(u.file_size), 0) as "used_bytes!: i64"
+               FROM dataset_uploads du
+               INNER JOIN uploads u ON u.id = du.upload_id
+               INNER JOIN upload_tags ut ON ut.upload_id = u.id
+               INNER JOIN tags t ON t.id = ut.tag_id
+               WHERE du.dataset_id = ?
+               GROUP BY t.id
+               ORDER BY 4 DESC, t.name"#,
+            id
+        )
+        .fetch_all(self.db)
+        .await
+    }
+
+    /// How many uploads of a dataset were made by a function or operation
+    pub async fn derived_count(&self, id: &str) -> sqlx::Result<i64> {
+        sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count!: i64" FROM dataset_uploads du
+               WHERE du.dataset_id = ? AND EXISTS (SELECT 1 FROM file_lineage fl WHERE fl.output_upload_id = du.upload_id)"#,
+            id
+        )
+        .fetch_one(self.db)
+        .await
+    }
+
+    /// Add uploads, all or nothing; ones already in the dataset are skipped. Returns the IDs
+    /// that were added.
+    pub async fn add_uploads(
+        &self,
+        id: &str,
+        upload_ids: &[String],
+        added_at: &str,
+    ) -> sqlx::Result<Vec<String>> {
+        let mut tx = self.db.begin().await?;
+        let mut added = Vec::new();
+        for upload_id in upload_ids {
+            let result = sqlx::query!(
+                "INSERT OR IGNORE INTO dataset_uploads (dataset_id, upload_id, added_at) VALUES (?, ?, ?)",
+                id,
+                upload_id,
+                added_at
+            )
+            .execute(&mut *tx)
+            .await?;
+            if result.rows_affected() > 0 {
+                added.push(upload_id.clone());
+            }
+        }
+        sqlx::query!(
+            "UPDATE datasets SET updated_at = ? WHERE id = ?",
+            added_at,
+            id
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(added)
+    }
+
+    /// Returns false if the upload was not in the dataset
+    pub async fn remove_upload(
+        &self,
+        id: &str,
+        upload_id: &str,
+        updated_at: &str,
+    ) -> sqlx::Result<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM dataset_uploads WHERE dataset_id = ? AND upload_id = ?",
+            id,
+            upload_id
+        )
+        .execute(self.db)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+        sqlx::query!(
+            "UPDATE datasets SET updated_at = ? WHERE id = ?",
+            updated_at,
+            id
+        )
+        .execute(self.db)
+        .await?;
+        Ok(true)
+    }
+}
@@ -0,0 +1,395 @@
+use crate::models::{Function, Tag};
+use crate::script_analysis;
+use crate::timestamps;
+use crate::triggers::{glob_match, TriggerCondition};
+use sqlx::SqlitePool;
+
+/// A `functions` row
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct StoredFunction {
+    pub id: String,
+    pub name: String,
+    pub script_filename: String,
+    pub enabled: bool,
+    pub function_type: String,
+    pub executor: String,
+    pub trigger_conditions: String,   // JSON array
+    pub intermediate_outputs: String, // JSON array of filename patterns
+    pub intermediate_ttl_hours: i64,  // how long intermediate outputs are kept
+    pub created_at: String,
+    pub expression: Option<String>, // set for quick functions, which have no script
+    pub quarantined_at: Option<String>, // disabled by the health check
+    pub quarantine_reason: Option<String>,
+    pub health_since: Option<String>, // runs completed earlier do not count towards the check
+}
+
+impl StoredFunction {
+    pub fn conditions(&self) -> Vec<TriggerCondition> {
+        serde_json::from_str(&self.trigger_conditions).unwrap_or_default()
+    }
+
+    pub fn intermediate_patterns(&self) -> Vec<String> {
+        serde_json::from_str(&self.intermediate_outputs).unwrap_or_default()
+    }
+
+    /// Whether the output called `filename` is intermediate; like trigger patterns, case
+    /// does not matter
+    pub fn is_intermediate(&self, filename: &str) -> bool {
+        let filename = filename.to_lowercase();
+        self.intermediate_patterns()
+            .iter()
+            .any(|pattern| glob_match(&pattern.to_lowercase(), &filename))
+    }
+
+    pub fn into_function(
+        self,
+        input_tags: Vec<Tag>,
+        output_tags: Vec<Tag>,
+        excluded_tags: Vec<Tag>,
+        script_content: Option<String>,
+    ) -> Function {
+        Function {
+            trigger_conditions: self.conditions(),
+            intermediate_outputs: self.intermediate_patterns(),
+            intermediate_ttl_hours: self.intermediate_ttl_hours,
+            id: self.id,
+            name: self.name,
+            script_filename: self.script_filename,
+            enabled: self.enabled,
+            function_type: self.function_type,
+            executor: self.executor,
+            created_at: self.created_at,
+            expression: self.expression,
+            quarantined_at: self.quarantined_at,
+            quarantine_reason: self.quarantine_reason,
+            input_tags,
+            output_tags,
+            excluded_tags,
+            script_analysis: script_content.as_deref().map(script_analysis::analyze),
+            script_content,
+        }
+    }
+}
+
+pub struct NewFunction<'a> {
+    pub id: &'a str,
+    pub name: &'a str,
+    pub script_filename: &'a str,
+    pub function_type: &'a str,
+    pub executor: &'a str,
+    pub trigger_conditions: &'a [TriggerCondition],
+    pub created_at: &'a str,
+    pub expression: Option<&'a str>,
+}
+
+pub struct FunctionRepo<'a> {
+    db: &'a SqlitePool,
+}
+
+impl<'a> FunctionRepo<'a> {
+    pub fn new(db: &'a SqlitePool) -> Self {
+        Self { db }
+    }
+
+    /// All functions, newest first
+    pub async fn list(&self) -> sqlx::Result<Vec<StoredFunction>> {
+        sqlx::query_as!(
+            StoredFunction,
+            r#"SELECT id as "id!", name as "name!", script_filename as "script_filename!", enabled as "enabled!: bool", function_type as "function_type!", executor as "executor!", trigger_conditions as "trigger_conditions!", intermediate_outputs as "intermediate_outputs!", intermediate_ttl_hours as "intermediate_ttl_hours!", created_at as "created_at!", expression, quarantined_at, quarantine_reason, health_since FROM functions ORDER BY created_at DESC"#
+        )
+        .fetch_all(self.db)
+        .await
+    }
+
+    /// Functions that run automatically on matching uploads
+    pub async fn enabled(&self) -> sqlx::Result<Vec<StoredFunction>> {
+        sqlx::query_as!(
+            StoredFunction,
+            r#"SELECT id as "id!", name as "name!", script_filename as "script_filename!", enabled as "enabled!: bool", function_type as "function_type!", executor as "executor!", trigger_conditions as "trigger_conditions!", intermediate_outputs as "intermediate_outputs!", intermediate_ttl_hours as "intermediate_ttl_hours!", created_at as "created_at!", expression, quarantined_at, quarantine_reason, health_since FROM functions WHERE enabled = 1"#
+        )
+        .fetch_all(self.db)
+        .await
+    }
+
+    pub async fn get(&self, id: &str) -> sqlx::Result<Option<StoredFunction>> {
+        sqlx::query_as!(
+            StoredFunction,
+            r#"SELECT id as "id!", name as "name!", script_filename as "script_filename!", enabled as "enabled!: bool", function_type as "function_type!", executor as "executor!", trigger_conditions as "trigger_conditions!", intermediate_outputs as "intermediate_outputs!", intermediate_ttl_hours as "intermediate_ttl_hours!", created_at as "created_at!", expression, quarantined_at, quarantine_reason, health_since FROM functions WHERE id = ?"#,
+            id
+        )
+        .fetch_optional(self.db)
+        .await
+    }
+
+    /// Insert a disabled function; fails with a UNIQUE constraint error if the name is taken
+    pub async fn insert(&self, function: &NewFunction<'_>) -> sqlx::Result<()> {
+        let trigger_conditions = serde_json::to_string(function.trigger_conditions)
+            .map_err(|e| sqlx::Error::Encode(Box::new(e)))?;
+        sqlx::query!(
+            "INSERT INTO functions (id, name, script_filename, function_type, executor, trigger_conditions, created_at, expression) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            function.id,
+            function.name,
+            function.script_filename,
+            function.function_type,
+            function.executor,
+            trigger_conditions,
+            function.created_at,
+            function.expression
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns false if there was no such function
+    pub async fn delete(&self, id: &str) -> sqlx::Result<bool> {
+        let result = sqlx::query!("DELETE FROM functions WHERE id = ?", id)
+            .execute(self.db)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn rename(&self, id: &str, name: &str) -> sqlx::Result<()> {
+        sqlx::query!("UPDATE functions SET name = ? WHERE id = ?", name, id)
+            .execute(self.db)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn set_script(&self, id: &str, script_filename: &str) -> sqlx::Result<()> {
+        sqlx::query!(
+            "UPDATE functions SET script_filename = ? WHERE id = ?",
+            script_filename,
+            id
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn set_expression(&self, id: &str, expression: &str) -> sqlx::Result<()> {
+        sqlx::query!(
+            "UPDATE functions SET expression = ? WHERE id = ?",
+            expression,
+            id
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn set_function_type(&self, id: &str, function_type: &str) -> sqlx::Result<()> {
+        sqlx::query!(
+            "UPDATE functions SET function_type = ? WHERE id = ?",
+            function_type,
+            id
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn set_executor(&self, id: &str, executor: &str) -> sqlx::Result<()> {
+        sqlx::query!(
+            "UPDATE functions SET executor = ? WHERE id = ?",
+            executor,
+            id
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn set_trigger_conditions(
+        &self,
+        id: &str,
+        conditions: &[TriggerCondition],
+    ) -> sqlx::Result<()> {
+        let trigger_conditions =
+            serde_json::to_string(conditions).map_err(|e| sqlx::Error::Encode(Box::new(e)))?;
+        sqlx::query!(
+            "UPDATE functions SET trigger_conditions = ? WHERE id = ?",
+            trigger_conditions,
+            id
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Outputs matching one of `patterns` are intermediate and expire `ttl_hours` after
+    /// they were produced
+    pub async fn set_intermediate_outputs(
+        &self,
+        id: &str,
+        patterns: &[String],
+        ttl_hours: i64,
+    ) -> sqlx::Result<()> {
+        let patterns =
+            serde_json::to_string(patterns).map_err(|e| sqlx::Error::Encode(Box::new(e)))?;
+        sqlx::query!(
+            "UPDATE functions SET intermediate_outputs = ?, intermediate_ttl_hours = ? WHERE id = ?",
+            patterns,
+            ttl_hours,
+            id
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn set_enabled(&self, id: &str, enabled: bool) -> sqlx::Result<()> {
+        sqlx::query!("UPDATE functions SET enabled = ? WHERE id = ?", enabled, id)
+            .execute(self.db)
+            .await?;
+        Ok(())
+    }
+
+    /// Disable a function for failing too often; returns false if it was already quarantined
+    pub async fn quarantine(&self, id: &str, reason: &str) -> sqlx::Result<bool> {
+        let quarantined_at = timestamps::now();
+        let result = sqlx::query!(
+            "UPDATE functions SET enabled = 0, quarantined_at = ?, quarantine_reason = ? WHERE id = ? AND quarantined_at IS NULL",
+            quarantined_at,
+            reason,
+            id
+        )
+        .execute(self.db)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Lift a quarantine; earlier runs no longer count towards the health check
+    pub async fn release(&self, id: &str) -> sqlx::Result<()> {
+        let health_since = timestamps::now();
+        sqlx::query!(
+            "UPDATE functions SET quarantined_at = NULL, quarantine_reason = NULL, health_since = ? WHERE id = ?",
+            health_since,
+            id
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn input_tags(&self, id: &str) -> sqlx::Result<Vec<Tag>> {
+        sqlx::query_as!(
+            Tag,
+            r#"SELECT t.id as "id!", t.name as "name!", t.color as "color!", t.description, t.created_at as "created_at!"
+               FROM tags t
+               INNER JOIN function_input_tags fit ON t.id = fit.tag_id
+               WHERE fit.function_id = ?"#,
+            id
+        )
+        .fetch_all(self.db)
+        .await
+    }
+
+    pub async fn output_tags(&self, id: &str) -> sqlx::Result<Vec<Tag>> {
+        sqlx::query_as!(
+            Tag,
+            r#"SELECT t.id as "id!", t.name as "name!", t.color as "color!", t.description, t.created_at as "created_at!"
+               FROM tags t
+               INNER JOIN function_output_tags fot ON t.id = fot.tag_id
+               WHERE fot.function_id = ?"#,
+            id
+        )
+        .fetch_all(self.db)
+        .await
+    }
+
+    pub async fn input_tag_ids(&self, id: &str) -> sqlx::Result<Vec<String>> {
+        sqlx::query_scalar!(
+            r#"SELECT tag_id as "tag_id!" FROM function_input_tags WHERE function_id = ?"#,
+            id
+        )
+        .fetch_all(self.db)
+        .await
+    }
+
+    pub async fn excluded_tags(&self, id: &str) -> sqlx::Result<Vec<Tag>> {
+        sqlx::query_as!(
+            Tag,
+            r#"SELECT t.id as "id!", t.name as "name!", t.color as "color!", t.description, t.created_at as "created_at!"
+               FROM tags t
+               INNER JOIN function_excluded_tags fet ON t.id = fet.tag_id
+               WHERE fet.function_id = ?"#,
+            id
+        )
+        .fetch_all(self.db)
+        .await
+    }
+
+    pub async fn excluded_tag_ids(&self, id: &str) -> sqlx::Result<Vec<String>> {
+        sqlx::query_scalar!(
+            r#"SELECT tag_id as "tag_id!" FROM function_excluded_tags WHERE function_id = ?"#,
+            id
+        )
+        .fetch_all(self.db)
+        .await
+    }
+
+    pub async fn output_tag_ids(&self, id: &str) -> sqlx::Result<Vec<String>> {
+        sqlx::query_scalar!(
+            r#"SELECT tag_id as "tag_id!" FROM function_output_tags WHERE function_id = ?"#,
+            id
+        )
+        .fetch_all(self.db)
+        .await
+    }
+
+    /// Replace the tags an upload needs to trigger the function. Unknown tag IDs are skipped.
+    pub async fn set_input_tags(&self, id: &str, tag_ids: &[String]) -> sqlx::Result<()> {
+        sqlx::query!("DELETE FROM function_input_tags WHERE function_id = ?", id)
+            .execute(self.db)
+            .await?;
+        for tag_id in tag_ids {
+            let _ = sqlx::query!(
+                "INSERT INTO function_input_tags (function_id, tag_id) VALUES (?, ?)",
+                id,
+                tag_id
+            )
+            .execute(self.db)
+            .await;
+        }
+        Ok(())
+    }
+
+    /// Replace the tags applied to successful outputs. Unknown tag IDs are skipped.
+    pub async fn set_output_tags(&self, id: &str, tag_ids: &[String]) -> sqlx::Result<()> {
+        sqlx::query!("DELETE FROM function_output_tags WHERE function_id = ?", id)
+            .execute(self.db)
+            .await?;
+        for tag_id in tag_ids {
+            let _ = sqlx::query!(
+                "INSERT INTO function_output_tags (function_id, tag_id) VALUES (?, ?)",
+                id,
+                tag_id
+            )
+            .execute(self.db)
+            .await;
+        }
+        Ok(())
+    }
+
+    /// Replace the tags that keep an upload from triggering the function. Unknown tag IDs are
+    /// skipped.
+    pub async fn set_excluded_tags(&self, id: &str, tag_ids: &[String]) -> sqlx::Result<()> {
+        sqlx::query!(
+            "DELETE FROM function_excluded_tags WHERE function_id = ?",
+            id
+        )
+        .execute(self.db)
+        .await?;
+        for tag_id in tag_ids {
+            let _ = sqlx::query!(
+                "INSERT INTO function_excluded_tags (function_id, tag_id) VALUES (?, ?)",
+                id,
+                tag_id
+            )
+            .execute(self.db)
+            .await;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,44 @@
+use crate::image_metadata::ImageMetadata;
+use sqlx::SqlitePool;
+
+pub struct ImageMetadataRepo<'a> {
+    db: &'a SqlitePool,
+}
+
+impl<'a> ImageMetadataRepo<'a> {
+    pub fn new(db: &'a SqlitePool) -> Self {
+        Self { db }
+    }
+
+    /// Store what was read from an image upload, replacing an earlier reading
+    pub async fn set(&self, upload_id: &str, metadata: &ImageMetadata) -> sqlx::Result<()> {
+        let exif = serde_json::Value::Object(metadata.exif.clone()).to_string();
+        sqlx::query!(
+            "INSERT OR REPLACE INTO upload_image_metadata (upload_id, format, width, height, exif) VALUES (?, ?, ?, ?, ?)",
+            upload_id,
+            metadata.format,
+            metadata.width,
+            metadata.height,
+            exif
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get(&self, upload_id: &str) -> sqlx::Result<Option<ImageMetadata>> {
+        let row = sqlx::query!(
+            r#"SELECT format as "format!", width as "width!: u32", height as "height!: u32", exif as "exif!"
+               FROM upload_image_metadata WHERE upload_id = ?"#,
+            upload_id
+        )
+        .fetch_optional(self.db)
+        .await?;
+        Ok(row.map(|row| ImageMetadata {
+            format: row.format,
+            width: row.width,
+            height: row.height,
+            exif: serde_json::from_str(&row.exif).unwrap_or_default(),
+        }))
+    }
+}
@@ -0,0 +1,216 @@
+use crate::custody::RequestOrigin;
+use crate::models::{ImportBatch, ImportRow, WatchedFile};
+use sqlx::SqlitePool;
+
+pub struct ImportRepo<'a> {
+    db: &'a SqlitePool,
+}
+
+impl<'a> ImportRepo<'a> {
+    pub fn new(db: &'a SqlitePool) -> Self {
+        Self { db }
+    }
+
+    /// Insert a running batch with its rows, all pending
+    pub async fn create(
+        &self,
+        id: &str,
+        origin: &RequestOrigin,
+        created_at: &str,
+        rows: &[ImportRow],
+    ) -> sqlx::Result<()> {
+        let mut tx = self.db.begin().await?;
+        sqlx::query!(
+            "INSERT INTO import_batches (id, status, created_by, client_ip, created_at) VALUES (?, 'running', ?, ?, ?)",
+            id,
+            origin.user,
+            origin.client_ip,
+            created_at
+        )
+        .execute(&mut *tx)
+        .await?;
+        for row in rows {
+            let tag_ids = serde_json::to_string(&row.tags).unwrap_or_else(|_| "[]".to_string());
+            let metadata =
+                serde_json::to_string(&row.metadata).unwrap_or_else(|_| "{}".to_string());
+            sqlx::query!(
+                "INSERT INTO import_rows (batch_id, row_number, source, filename, tag_ids, assignee, metadata, status) VALUES (?, ?, ?, ?, ?, ?, ?, 'pending')",
+                id,
+                row.row_number,
+                row.source,
+                row.filename,
+                tag_ids,
+                row.assignee,
+                metadata
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await
+    }
+
+    /// All batches, newest first
+    pub async fn list(&self) -> sqlx::Result<Vec<ImportBatch>> {
+        sqlx::query_as!(
+            ImportBatch,
+            r#"SELECT b.id as "id!", b.status as "status!", b.created_by, b.created_at as "created_at!", b.finished_at,
+                      (SELECT COUNT(*) FROM import_rows r WHERE r.batch_id = b.id) as "total!: i64",
+                      (SELECT COUNT(*) FROM import_rows r WHERE r.batch_id = b.id AND r.status = 'pending') as "pending!: i64",
+                      (SELECT COUNT(*) FROM import_rows r WHERE r.batch_id = b.id AND r.status = 'imported') as "imported!: i64",
+                      (SELECT COUNT(*) FROM import_rows r WHERE r.batch_id = b.id AND r.status = 'failed') as "failed!: i64",
+                      (SELECT COUNT(*) FROM import_rows r WHERE r.batch_id = b.id AND r.status = 'quarantined') as "quarantined!: i64"
+               FROM import_batches b ORDER BY b.created_at DESC"#
+        )
+        .fetch_all(self.db)
+        .await
+    }
+
+    pub async fn get(&self, id: &str) -> sqlx::Result<Option<ImportBatch>> {
+        sqlx::query_as!(
+            ImportBatch,
+            r#"SELECT b.id as "id!", b.status as "status!", b.created_by, b.created_at as "created_at!", b.finished_at,
+                      (SELECT COUNT(*) FROM import_rows r WHERE r.batch_id = b.id) as "total!: i64",
+                      (SELECT COUNT(*) FROM import_rows r WHERE r.batch_id = b.id AND r.status = 'pending') as "pending!: i64",
+                      (SELECT COUNT(*) FROM import_rows r WHERE r.batch_id = b.id AND r.status = 'imported') as "imported!: i64",
+                      (SELECT COUNT(*) FROM import_rows r WHERE r.batch_id = b.id AND r.status = 'failed') as "failed!: i64",
+                      (SELECT COUNT(*) FROM import_rows r WHERE r.batch_id = b.id AND r.status = 'quarantined') as "quarantined!: i64"
+               FROM import_batches b WHERE b.id = ?"#,
+            id
+        )
+        .fetch_optional(self.db)
+        .await
+    }
+
+    /// Who sent the manifest of a batch, as far as it was recorded
+    pub async fn origin(&self, id: &str) -> sqlx::Result<RequestOrigin> {
+        let row = sqlx::query!(
+            "SELECT created_by, client_ip FROM import_batches WHERE id = ?",
+            id
+        )
+        .fetch_one(self.db)
+        .await?;
+        Ok(RequestOrigin {
+            user: row.created_by,
+            client_ip: row.client_ip,
+            ..RequestOrigin::default()
+        })
+    }
+
+    /// The rows of a batch in manifest order, only those with `status` if given
+    pub async fn rows(&self, batch_id: &str, status: Option<&str>) -> sqlx::Result<Vec<ImportRow>> {
+        let rows = sqlx::query!(
+            r#"SELECT row_number as "row_number!", source as "source!", filename, tag_ids as "tag_ids!", assignee,
+                      metadata as "metadata!", status as "status!", upload_id, message
+               FROM import_rows WHERE batch_id = ? AND (? IS NULL OR status = ?)
+               ORDER BY row_number"#,
+            batch_id,
+            status,
+            status
+        )
+        .fetch_all(self.db)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| ImportRow {
+                row_number: row.row_number,
+                source: row.source,
+                filename: row.filename,
+                tags: serde_json::from_str(&row.tag_ids).unwrap_or_default(),
+                assignee: row.assignee,
+                metadata: serde_json::from_str(&row.metadata).unwrap_or_default(),
+                status: row.status,
+                upload_id: row.upload_id,
+                message: row.message,
+            })
+            .collect())
+    }
+
+    pub async fn set_row_status(
+        &self,
+        batch_id: &str,
+        row_number: i64,
+        status: &str,
+        upload_id: Option<&str>,
+        message: Option<&str>,
+    ) -> sqlx::Result<()> {
+        sqlx::query!(
+            "UPDATE import_rows SET status = ?, upload_id = ?, message = ? WHERE batch_id = ? AND row_number = ?",
+            status,
+            upload_id,
+            message,
+            batch_id,
+            row_number
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn finish(&self, id: &str, finished_at: &str) -> sqlx::Result<()> {
+        sqlx::query!(
+            "UPDATE import_batches SET status = 'completed', finished_at = ? WHERE id = ?",
+            finished_at,
+            id
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Batches still running, e.g. when a restart interrupted them; oldest first
+    pub async fn running_ids(&self) -> sqlx::Result<Vec<String>> {
+        sqlx::query_scalar!(
+            r#"SELECT id as "id!" FROM import_batches WHERE status = 'running' ORDER BY created_at"#
+        )
+        .fetch_all(self.db)
+        .await
+    }
+
+    /// Whether this version of a file in a watch folder was picked up before
+    pub async fn watched_file_seen(
+        &self,
+        path: &str,
+        file_size: i64,
+        modified_at: &str,
+    ) -> sqlx::Result<bool> {
+        let count = sqlx::query_scalar!(
+            "SELECT COUNT(*) FROM watched_files WHERE path = ? AND file_size = ? AND modified_at = ?",
+            path,
+            file_size,
+            modified_at
+        )
+        .fetch_one(self.db)
+        .await?;
+        Ok(count > 0)
+    }
+
+    /// Remember what became of a watched file; a new version replaces the earlier one
+    pub async fn record_watched_file(&self, file: &WatchedFile) -> sqlx::Result<()> {
+        sqlx::query!(
+            "INSERT INTO watched_files (path, file_size, modified_at, status, upload_id, message, picked_up_at) VALUES (?, ?, ?, ?, ?, ?, ?)
+             ON CONFLICT(path) DO UPDATE SET file_size = excluded.file_size, modified_at = excluded.modified_at, status = excluded.status, upload_id = excluded.upload_id, message = excluded.message, picked_up_at = excluded.picked_up_at",
+            file.path,
+            file.file_size,
+            file.modified_at,
+            file.status,
+            file.upload_id,
+            file.message,
+            file.picked_up_at
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Files picked up from watch folders, most recent first
+    pub async fn watched_files(&self, limit: i64) -> sqlx::Result<Vec<WatchedFile>> {
+        sqlx::query_as!(
+            WatchedFile,
+            r#"SELECT path as "path!", file_size as "file_size!", modified_at as "modified_at!", status as "status!", upload_id, message, picked_up_at as "picked_up_at!"
+               FROM watched_files ORDER BY picked_up_at DESC LIMIT ?"#,
+            limit
+        )
+        .fetch_all(self.db)
+        .await
+    }
+}
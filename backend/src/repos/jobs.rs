@@ -0,0 +1,535 @@
+use super::Sort;
+use crate::capacity::JobRun;
+use crate::cost_estimate::RunSample;
+use crate::job_stats::JobOutcome;
+use crate::models::{InputSlice, Job, JobAnnotation};
+use crate::timestamps;
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+#[derive(sqlx::FromRow)]
+struct JobRow {
+    id: String,
+    upload_id: String,
+    function_id: String,
+    status: String,
+    error_message: Option<String>,
+    output_upload_ids: Option<String>,
+    created_at: String,
+    started_at: Option<String>,
+    completed_at: Option<String>,
+    assignee: Option<String>,
+    input_slice: Option<String>,
+    snapshot_id: Option<String>,
+}
+
+/// Which jobs `JobRepo::list` returns; None fields do not filter
+#[derive(Default)]
+pub struct JobFilter<'a> {
+    pub assignee: Option<&'a str>,
+    pub upload_id: Option<&'a str>,
+    pub created_after: Option<&'a str>, // stored form, see `timestamps`
+    pub created_before: Option<&'a str>,
+    pub label: Option<&'a str>, // jobs with an annotation carrying this label
+    pub snapshot_id: Option<&'a str>, // jobs queued while this pipeline snapshot was active
+}
+
+/// What `POST /jobs/:id/complete` needs to authorize and deduplicate a worker's report
+pub struct CompletionState {
+    pub upload_id: String,
+    pub function_id: String,
+    pub completion_token_hash: Option<String>,
+    pub completed_via: Option<String>,
+}
+
+pub struct JobRepo<'a> {
+    db: &'a SqlitePool,
+}
+
+impl<'a> JobRepo<'a> {
+    pub fn new(db: &'a SqlitePool) -> Self {
+        Self { db }
+    }
+
+    // Add the upload filename, function name and output filenames to a row
+    async fn with_names(&self, row: JobRow) -> Job {
+        let upload_filename = sqlx::query_scalar!(
+            r#"SELECT original_filename as "original_filename!" FROM uploads WHERE id = ?"#,
+            row.upload_id
+        )
+        .fetch_optional(self.db)
+        .await
+        .ok()
+        .flatten();
+
+        let function_name = sqlx::query_scalar!(
+            r#"SELECT name as "name!" FROM functions WHERE id = ?"#,
+            row.function_id
+        )
+        .fetch_optional(self.db)
+        .await
+        .ok()
+        .flatten();
+
+        let output_upload_ids: Vec<String> = row
+            .output_upload_ids
+            .as_ref()
+            .and_then(|s| serde_json::from_str(s).ok())
+            .unwrap_or_default();
+
+        let mut output_filenames = Vec::new();
+        for output_id in &output_upload_ids {
+            if let Ok(Some(filename)) = sqlx::query_scalar!(
+                r#"SELECT original_filename as "original_filename!" FROM uploads WHERE id = ?"#,
+                output_id
+            )
+            .fetch_optional(self.db)
+            .await
+            {
+                output_filenames.push(filename);
+            }
+        }
+
+        let snapshot_label = match &row.snapshot_id {
+            Some(snapshot_id) => sqlx::query_scalar!(
+                r#"SELECT label as "label!" FROM pipeline_snapshots WHERE id = ?"#,
+                snapshot_id
+            )
+            .fetch_optional(self.db)
+            .await
+            .ok()
+            .flatten(),
+            None => None,
+        };
+
+        let labels = sqlx::query_scalar!(
+            r#"SELECT DISTINCT label as "label!" FROM job_annotations WHERE job_id = ? ORDER BY label"#,
+            row.id
+        )
+        .fetch_all(self.db)
+        .await
+        .unwrap_or_default();
+
+        let queued_seconds = row
+            .started_at
+            .as_deref()
+            .and_then(|started_at| timestamps::seconds_between(&row.created_at, started_at));
+        let duration_seconds = row
+            .started_at
+            .as_deref()
+            .zip(row.completed_at.as_deref())
+            .and_then(|(started_at, completed_at)| {
+                timestamps::seconds_between(started_at, completed_at)
+            });
+
+        Job {
+            id: row.id,
+            upload_id: row.upload_id,
+            function_id: row.function_id,
+            status: row.status,
+            error_message: row.error_message,
+            output_upload_ids,
+            created_at: row.created_at,
+            started_at: row.started_at,
+            completed_at: row.completed_at,
+            queued_seconds,
+            duration_seconds,
+            assignee: row.assignee,
+            input_slice: row
+                .input_slice
+                .as_deref()
+                .and_then(|s| serde_json::from_str(s).ok()),
+            upload_filename,
+            function_name,
+            output_filenames,
+            labels,
+            snapshot_id: row.snapshot_id,
+            snapshot_label,
+            estimate: None,
+        }
+    }
+
+    /// Jobs matching `filter` in `sort` order; by name means by function name and by size, by
+    /// the size of the input upload
+    pub async fn list(&self, filter: &JobFilter<'_>, sort: Sort) -> sqlx::Result<Vec<Job>> {
+        let (key, ascending) = (sort.key_name(), sort.ascending());
+        let rows = sqlx::query_as!(
+            JobRow,
+            r#"SELECT 
+                id as "id!", 
+                upload_id as "upload_id!", 
+                function_id as "function_id!", 
+                status as "status!", 
+                error_message, 
+                output_upload_ids, 
+                created_at as "created_at!", 
+                started_at, 
+                completed_at,
+                assignee,
+                input_slice,
+                snapshot_id
+            FROM jobs 
+            WHERE (? IS NULL OR assignee = ?)
+              AND (? IS NULL OR upload_id = ?)
+              AND (? IS NULL OR created_at >= ?)
+              AND (? IS NULL OR created_at < ?)
+              AND (? IS NULL OR EXISTS (SELECT 1 FROM job_annotations a WHERE a.job_id = jobs.id AND a.label = ?))
+              AND (? IS NULL OR snapshot_id = ?)
+            ORDER BY
+              CASE WHEN ? THEN (CASE ? WHEN 'name' THEN (SELECT lower(f.name) FROM functions f WHERE f.id = jobs.function_id) WHEN 'size' THEN (SELECT u.file_size FROM uploads u WHERE u.id = jobs.upload_id) ELSE created_at END) END ASC,
+              CASE WHEN ? THEN NULL ELSE (CASE ? WHEN 'name' THEN (SELECT lower(f.name) FROM functions f WHERE f.id = jobs.function_id) WHEN 'size' THEN (SELECT u.file_size FROM uploads u WHERE u.id = jobs.upload_id) ELSE created_at END) END DESC,
+              created_at DESC, id"#,
+            filter.assignee,
+            filter.assignee,
+            filter.upload_id,
+            filter.upload_id,
+            filter.created_after,
+            filter.created_after,
+            filter.created_before,
+            filter.created_before,
+            filter.label,
+            filter.label,
+            filter.snapshot_id,
+            filter.snapshot_id,
+            ascending,
+            key,
+            ascending,
+            key
+        )
+        .fetch_all(self.db)
+        .await?;
+
+        let mut jobs = Vec::with_capacity(rows.len());
+        for row in rows {
+            jobs.push(self.with_names(row).await);
+        }
+        Ok(jobs)
+    }
+
+    pub async fn get(&self, id: &str) -> sqlx::Result<Option<Job>> {
+        let row = sqlx::query_as!(
+            JobRow,
+            r#"SELECT 
+                id as "id!", 
+                upload_id as "upload_id!", 
+                function_id as "function_id!", 
+                status as "status!", 
+                error_message, 
+                output_upload_ids, 
+                created_at as "created_at!", 
+                started_at, 
+                completed_at,
+                assignee,
+                input_slice,
+                snapshot_id
+            FROM jobs 
+            WHERE id = ?"#,
+            id
+        )
+        .fetch_optional(self.db)
+        .await?;
+
+        Ok(match row {
+            Some(row) => Some(self.with_names(row).await),
+            None => None,
+        })
+    }
+
+    /// The input size, duration and output size of a function's latest successful runs,
+    /// newest first; trial runs are left out
+    pub async fn run_samples(&self, function_id: &str, limit: i64) -> sqlx::Result<Vec<RunSample>> {
+        let rows = sqlx::query!(
+            r#"SELECT u.file_size as "input_bytes!: i64", j.started_at as "started_at!", j.completed_at as "completed_at!",
+                      (SELECT COALESCE(SUM(o.file_size), 0) FROM json_each(COALESCE(j.output_upload_ids, '[]')) e
+                       INNER JOIN uploads o ON o.id = e.value) as "output_bytes!: i64"
+               FROM jobs j INNER JOIN uploads u ON u.id = j.upload_id
+               WHERE j.function_id = ? AND j.status = 'SUCCESS' AND j.input_slice IS NULL
+                 AND j.started_at IS NOT NULL AND j.completed_at IS NOT NULL
+               ORDER BY j.completed_at DESC LIMIT ?"#,
+            function_id,
+            limit
+        )
+        .fetch_all(self.db)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| {
+                Some(RunSample {
+                    input_bytes: row.input_bytes,
+                    duration_seconds: timestamps::seconds_between(
+                        &row.started_at,
+                        &row.completed_at,
+                    )?,
+                    output_bytes: row.output_bytes,
+                })
+            })
+            .collect())
+    }
+
+    /// When finished jobs of local functions were queued, how long they waited for a slot and
+    /// how long they ran, for those queued in the range (either end may be open)
+    pub async fn local_runs(
+        &self,
+        created_after: Option<&str>,
+        created_before: Option<&str>,
+    ) -> sqlx::Result<Vec<JobRun>> {
+        let rows = sqlx::query!(
+            r#"SELECT j.created_at as "created_at!", j.started_at as "started_at!", j.completed_at as "completed_at!"
+               FROM jobs j INNER JOIN functions f ON f.id = j.function_id
+               WHERE f.executor = 'local' AND j.status IN ('SUCCESS', 'FAILED')
+                 AND j.started_at IS NOT NULL AND j.completed_at IS NOT NULL
+                 AND (? IS NULL OR j.created_at >= ?)
+                 AND (? IS NULL OR j.created_at < ?)
+               ORDER BY j.created_at"#,
+            created_after,
+            created_after,
+            created_before,
+            created_before
+        )
+        .fetch_all(self.db)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| {
+                let queued_at = timestamps::to_utc(&row.created_at)?;
+                Some(JobRun {
+                    queued_at: queued_at.timestamp_millis() as f64 / 1000.0,
+                    waited_seconds: timestamps::seconds_between(&row.created_at, &row.started_at)?,
+                    duration_seconds: timestamps::seconds_between(
+                        &row.started_at,
+                        &row.completed_at,
+                    )?,
+                })
+            })
+            .collect())
+    }
+
+    /// Outcomes of a function's latest completed runs, newest first: true for a run that
+    /// failed or left an error log. Trial runs, runs completed before `since` and runs the
+    /// server stopped (`skip_error`) are left out.
+    pub async fn recent_outcomes(
+        &self,
+        function_id: &str,
+        since: Option<&str>,
+        skip_error: &str,
+        limit: usize,
+    ) -> sqlx::Result<Vec<bool>> {
+        let since = since.unwrap_or("");
+        let limit = limit as i64;
+        sqlx::query_scalar!(
+            r#"SELECT (j.status = 'FAILED' OR EXISTS (
+                    SELECT 1 FROM json_each(COALESCE(j.output_upload_ids, '[]')) o
+                    INNER JOIN uploads u ON u.id = o.value
+                    WHERE u.artifact_type = 'error_log'
+                )) as "failed!: bool"
+               FROM jobs j
+               WHERE j.function_id = ? AND j.status IN ('SUCCESS', 'FAILED')
+                 AND j.input_slice IS NULL AND j.completed_at >= ?
+                 AND COALESCE(j.error_message, '') != ?
+               ORDER BY j.completed_at DESC
+               LIMIT ?"#,
+            function_id,
+            since,
+            skip_error,
+            limit
+        )
+        .fetch_all(self.db)
+        .await
+    }
+
+    /// Every completed run in the creation range, for `job_stats::summarize`. Trial runs and
+    /// runs the server stopped (`skip_error`) are left out.
+    pub async fn outcomes(
+        &self,
+        created_after: Option<&str>,
+        created_before: Option<&str>,
+        skip_error: &str,
+    ) -> sqlx::Result<Vec<JobOutcome>> {
+        let rows = sqlx::query!(
+            r#"SELECT
+                j.function_id as "function_id!",
+                COALESCE(f.name, j.function_id) as "function_name!: String",
+                (j.status = 'FAILED' OR EXISTS (
+                    SELECT 1 FROM json_each(COALESCE(j.output_upload_ids, '[]')) o
+                    INNER JOIN uploads u ON u.id = o.value
+                    WHERE u.artifact_type = 'error_log'
+                )) as "failed!: bool",
+                (SELECT json_group_array(DISTINCT a.label) FROM job_annotations a WHERE a.job_id = j.id) as "labels!: String"
+               FROM jobs j
+               LEFT JOIN functions f ON f.id = j.function_id
+               WHERE j.status IN ('SUCCESS', 'FAILED') AND j.input_slice IS NULL
+                 AND (? IS NULL OR j.created_at >= ?)
+                 AND (? IS NULL OR j.created_at < ?)
+                 AND COALESCE(j.error_message, '') != ?"#,
+            created_after,
+            created_after,
+            created_before,
+            created_before,
+            skip_error
+        )
+        .fetch_all(self.db)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| JobOutcome {
+                function_id: row.function_id,
+                function_name: row.function_name,
+                failed: row.failed,
+                labels: serde_json::from_str(&row.labels).unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    /// Annotations of a job, oldest first
+    pub async fn annotations(&self, job_id: &str) -> sqlx::Result<Vec<JobAnnotation>> {
+        sqlx::query_as!(
+            JobAnnotation,
+            r#"SELECT id as "id!", job_id as "job_id!", label as "label!", note, author, created_at as "created_at!"
+               FROM job_annotations WHERE job_id = ? ORDER BY created_at, id"#,
+            job_id
+        )
+        .fetch_all(self.db)
+        .await
+    }
+
+    pub async fn add_annotation(
+        &self,
+        job_id: &str,
+        label: &str,
+        note: Option<&str>,
+        author: Option<&str>,
+    ) -> sqlx::Result<JobAnnotation> {
+        let annotation = JobAnnotation {
+            id: Uuid::new_v4().to_string(),
+            job_id: job_id.to_string(),
+            label: label.to_string(),
+            note: note.map(str::to_string),
+            author: author.map(str::to_string),
+            created_at: timestamps::now(),
+        };
+        sqlx::query!(
+            "INSERT INTO job_annotations (id, job_id, label, note, author, created_at) VALUES (?, ?, ?, ?, ?, ?)",
+            annotation.id,
+            annotation.job_id,
+            annotation.label,
+            annotation.note,
+            annotation.author,
+            annotation.created_at
+        )
+        .execute(self.db)
+        .await?;
+        Ok(annotation)
+    }
+
+    /// Returns false if the job has no such annotation
+    pub async fn delete_annotation(&self, job_id: &str, id: &str) -> sqlx::Result<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM job_annotations WHERE id = ? AND job_id = ?",
+            id,
+            job_id
+        )
+        .execute(self.db)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Record a new job in the SUBMITTED state and return its ID
+    pub async fn create(
+        &self,
+        upload_id: &str,
+        function_id: &str,
+        input_slice: Option<&InputSlice>,
+    ) -> sqlx::Result<String> {
+        let id = Uuid::new_v4().to_string();
+        let created_at = timestamps::now();
+        let input_slice = input_slice.and_then(|slice| serde_json::to_string(slice).ok());
+        sqlx::query!(
+            "INSERT INTO jobs (id, upload_id, function_id, status, created_at, input_slice, snapshot_id) VALUES (?, ?, ?, ?, ?, ?, (SELECT id FROM pipeline_snapshots ORDER BY activated_at DESC, rowid DESC LIMIT 1))",
+            id,
+            upload_id,
+            function_id,
+            "SUBMITTED",
+            created_at,
+            input_slice
+        )
+        .execute(self.db)
+        .await?;
+        Ok(id)
+    }
+
+    pub async fn mark_running(&self, id: &str) -> sqlx::Result<()> {
+        let started_at = timestamps::now();
+        sqlx::query!(
+            "UPDATE jobs SET status = ?, started_at = ? WHERE id = ?",
+            "RUNNING",
+            started_at,
+            id
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn mark_succeeded(&self, id: &str, output_upload_ids: &[String]) -> sqlx::Result<()> {
+        let completed_at = timestamps::now();
+        let output_ids_json = serde_json::to_string(output_upload_ids).unwrap_or_default();
+        sqlx::query!(
+            "UPDATE jobs SET status = ?, output_upload_ids = ?, completed_at = ? WHERE id = ?",
+            "SUCCESS",
+            output_ids_json,
+            completed_at,
+            id
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn mark_failed(&self, id: &str, error_message: &str) -> sqlx::Result<()> {
+        let completed_at = timestamps::now();
+        sqlx::query!(
+            "UPDATE jobs SET status = ?, error_message = ?, completed_at = ? WHERE id = ?",
+            "FAILED",
+            error_message,
+            completed_at,
+            id
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Store the hash of the token a remote worker authenticates its results with
+    pub async fn set_completion_token_hash(&self, id: &str, token_hash: &str) -> sqlx::Result<()> {
+        sqlx::query!(
+            "UPDATE jobs SET completion_token_hash = ? WHERE id = ?",
+            token_hash,
+            id
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn completion_state(&self, id: &str) -> sqlx::Result<Option<CompletionState>> {
+        sqlx::query_as!(
+            CompletionState,
+            r#"SELECT upload_id as "upload_id!", function_id as "function_id!", completion_token_hash, completed_via FROM jobs WHERE id = ?"#,
+            id
+        )
+        .fetch_optional(self.db)
+        .await
+    }
+
+    /// Take over finishing a job; false if the executor or a worker already did
+    pub async fn claim_completion(&self, id: &str, via: &str) -> bool {
+        sqlx::query!(
+            "UPDATE jobs SET completed_via = ? WHERE id = ? AND completed_via IS NULL",
+            via,
+            id
+        )
+        .execute(self.db)
+        .await
+        .is_ok_and(|result| result.rows_affected() == 1)
+    }
+}
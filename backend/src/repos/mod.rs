@@ -0,0 +1,36 @@
+//! Database access, one repository per table group. Repositories only run SQL; the handlers
+//! in `routes` and the orchestration in `services` decide what to do with the results.
+
+mod custody;
+mod datasets;
+mod functions;
+mod image_metadata;
+mod imports;
+mod jobs;
+mod quarantine;
+mod releases;
+mod remotes;
+mod replication;
+mod schema;
+mod shares;
+mod snapshots;
+mod sort;
+mod tags;
+mod uploads;
+
+pub use custody::CustodyRepo;
+pub use datasets::DatasetRepo;
+pub use functions::{FunctionRepo, NewFunction, StoredFunction};
+pub use image_metadata::ImageMetadataRepo;
+pub use imports::ImportRepo;
+pub use jobs::{JobFilter, JobRepo};
+pub use quarantine::{QuarantineRepo, StoredQuarantine};
+pub use releases::{NewRelease, ReleaseRepo, StoredRelease};
+pub use remotes::RemoteRepo;
+pub use replication::ReplicationRepo;
+pub use schema::{AppliedMigration, SchemaRepo};
+pub use shares::ShareRepo;
+pub use snapshots::{SnapshotRepo, StoredSnapshot};
+pub use sort::{Sort, SortKey, SortOrder};
+pub use tags::TagRepo;
+pub use uploads::{NewLineage, NewUpload, StoredUpload, UploadFilter, UploadRepo};
@@ -0,0 +1,90 @@
+use crate::models::QuarantinedUpload;
+use sqlx::SqlitePool;
+
+pub struct StoredQuarantine {
+    pub id: String,
+    pub filename: String, // in the quarantine directory
+    pub original_filename: String,
+    pub file_size: i64,
+    pub mime_type: Option<String>,
+    pub sha256: String,
+    pub status: String,
+    pub finding: String,
+    pub tag_ids: String, // JSON array of the tags it was sent with
+    pub created_at: String,
+}
+
+impl StoredQuarantine {
+    pub fn into_model(self) -> QuarantinedUpload {
+        QuarantinedUpload {
+            id: self.id,
+            original_filename: self.original_filename,
+            file_size: self.file_size,
+            mime_type: self.mime_type,
+            sha256: self.sha256,
+            status: self.status,
+            finding: self.finding,
+            tag_ids: serde_json::from_str(&self.tag_ids).unwrap_or_default(),
+            created_at: self.created_at,
+        }
+    }
+}
+
+pub struct QuarantineRepo<'a> {
+    db: &'a SqlitePool,
+}
+
+impl<'a> QuarantineRepo<'a> {
+    pub fn new(db: &'a SqlitePool) -> Self {
+        Self { db }
+    }
+
+    /// Quarantined files, newest first
+    pub async fn list(&self) -> sqlx::Result<Vec<StoredQuarantine>> {
+        sqlx::query_as!(
+            StoredQuarantine,
+            r#"SELECT id as "id!", filename as "filename!", original_filename as "original_filename!", file_size as "file_size!", mime_type, sha256 as "sha256!", status as "status!", finding as "finding!", tag_ids as "tag_ids!", created_at as "created_at!"
+               FROM quarantined_uploads ORDER BY created_at DESC"#
+        )
+        .fetch_all(self.db)
+        .await
+    }
+
+    pub async fn get(&self, id: &str) -> sqlx::Result<Option<StoredQuarantine>> {
+        sqlx::query_as!(
+            StoredQuarantine,
+            r#"SELECT id as "id!", filename as "filename!", original_filename as "original_filename!", file_size as "file_size!", mime_type, sha256 as "sha256!", status as "status!", finding as "finding!", tag_ids as "tag_ids!", created_at as "created_at!"
+               FROM quarantined_uploads WHERE id = ?"#,
+            id
+        )
+        .fetch_optional(self.db)
+        .await
+    }
+
+    pub async fn insert(&self, quarantined: &StoredQuarantine) -> sqlx::Result<()> {
+        sqlx::query!(
+            "INSERT INTO quarantined_uploads (id, filename, original_filename, file_size, mime_type, sha256, status, finding, tag_ids, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            quarantined.id,
+            quarantined.filename,
+            quarantined.original_filename,
+            quarantined.file_size,
+            quarantined.mime_type,
+            quarantined.sha256,
+            quarantined.status,
+            quarantined.finding,
+            quarantined.tag_ids,
+            quarantined.created_at
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns false if there was no such file; the file itself is left to the caller
+    pub async fn delete(&self, id: &str) -> sqlx::Result<bool> {
+        let result = sqlx::query!("DELETE FROM quarantined_uploads WHERE id = ?", id)
+            .execute(self.db)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
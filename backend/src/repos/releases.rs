@@ -0,0 +1,139 @@
+use crate::models::ReleaseFile;
+use sqlx::SqlitePool;
+
+pub struct StoredRelease {
+    pub id: String,
+    pub name: String,
+    pub identifier: Option<String>,
+    pub description: Option<String>,
+    pub selection: String, // JSON
+    pub checksum: String,
+    pub created_at: String,
+    pub file_count: i64,
+    pub total_bytes: i64,
+}
+
+pub struct NewRelease<'a> {
+    pub id: &'a str,
+    pub name: &'a str,
+    pub identifier: Option<&'a str>,
+    pub description: Option<&'a str>,
+    pub selection: &'a str,
+    pub checksum: &'a str,
+    pub created_at: &'a str,
+}
+
+pub struct ReleaseRepo<'a> {
+    db: &'a SqlitePool,
+}
+
+impl<'a> ReleaseRepo<'a> {
+    pub fn new(db: &'a SqlitePool) -> Self {
+        Self { db }
+    }
+
+    /// All releases, newest first
+    pub async fn list(&self) -> sqlx::Result<Vec<StoredRelease>> {
+        sqlx::query_as!(
+            StoredRelease,
+            r#"SELECT r.id as "id!", r.name as "name!", r.identifier, r.description, r.selection as "selection!", r.checksum as "checksum!", r.created_at as "created_at!",
+                      (SELECT COUNT(*) FROM release_files rf WHERE rf.release_id = r.id) as "file_count!: i64",
+                      (SELECT COALESCE(SUM(rf.file_size), 0) FROM release_files rf WHERE rf.release_id = r.id) as "total_bytes!: i64"
+               FROM releases r ORDER BY r.created_at DESC"#
+        )
+        .fetch_all(self.db)
+        .await
+    }
+
+    pub async fn get(&self, id: &str) -> sqlx::Result<Option<StoredRelease>> {
+        sqlx::query_as!(
+            StoredRelease,
+            r#"SELECT r.id as "id!", r.name as "name!", r.identifier, r.description, r.selection as "selection!", r.checksum as "checksum!", r.created_at as "created_at!",
+                      (SELECT COUNT(*) FROM release_files rf WHERE rf.release_id = r.id) as "file_count!: i64",
+                      (SELECT COALESCE(SUM(rf.file_size), 0) FROM release_files rf WHERE rf.release_id = r.id) as "total_bytes!: i64"
+               FROM releases r WHERE r.id = ?"#,
+            id
+        )
+        .fetch_optional(self.db)
+        .await
+    }
+
+    /// The files of a release in bundle order
+    pub async fn files(&self, id: &str) -> sqlx::Result<Vec<ReleaseFile>> {
+        sqlx::query_as!(
+            ReleaseFile,
+            r#"SELECT upload_id as "upload_id!", name as "name!", sha256 as "sha256!", file_size as "file_size!"
+               FROM release_files WHERE release_id = ? ORDER BY position"#,
+            id
+        )
+        .fetch_all(self.db)
+        .await
+    }
+
+    /// Store a release with its files and protect the uploads, all or nothing; fails with a
+    /// UNIQUE constraint error if the name or identifier is taken
+    pub async fn insert(
+        &self,
+        release: &NewRelease<'_>,
+        files: &[ReleaseFile],
+    ) -> sqlx::Result<()> {
+        let mut tx = self.db.begin().await?;
+        sqlx::query!(
+            "INSERT INTO releases (id, name, identifier, description, selection, checksum, created_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+            release.id,
+            release.name,
+            release.identifier,
+            release.description,
+            release.selection,
+            release.checksum,
+            release.created_at
+        )
+        .execute(&mut *tx)
+        .await?;
+        for (position, file) in files.iter().enumerate() {
+            let position = position as i64;
+            sqlx::query!(
+                "INSERT INTO release_files (release_id, upload_id, position, name, sha256, file_size) VALUES (?, ?, ?, ?, ?, ?)",
+                release.id,
+                file.upload_id,
+                position,
+                file.name,
+                file.sha256,
+                file.file_size
+            )
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query!(
+                "UPDATE uploads SET protected = 1 WHERE id = ?",
+                file.upload_id
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await
+    }
+
+    /// Record the identifier minted for a release; returns false if it already has one
+    pub async fn set_identifier(&self, id: &str, identifier: &str) -> sqlx::Result<bool> {
+        let result = sqlx::query!(
+            "UPDATE releases SET identifier = ? WHERE id = ? AND identifier IS NULL",
+            identifier,
+            id
+        )
+        .execute(self.db)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Names of the releases an upload is part of
+    pub async fn names_for_upload(&self, upload_id: &str) -> sqlx::Result<Vec<String>> {
+        sqlx::query_scalar!(
+            r#"SELECT r.name as "name!" FROM releases r
+               INNER JOIN release_files rf ON rf.release_id = r.id
+               WHERE rf.upload_id = ? ORDER BY r.created_at"#,
+            upload_id
+        )
+        .fetch_all(self.db)
+        .await
+    }
+}
@@ -0,0 +1,56 @@
+use crate::models::Remote;
+use sqlx::SqlitePool;
+
+pub struct RemoteRepo<'a> {
+    db: &'a SqlitePool,
+}
+
+impl<'a> RemoteRepo<'a> {
+    pub fn new(db: &'a SqlitePool) -> Self {
+        Self { db }
+    }
+
+    /// All remotes by name
+    pub async fn list(&self) -> sqlx::Result<Vec<Remote>> {
+        sqlx::query_as!(
+            Remote,
+            r#"SELECT id as "id!", name as "name!", base_url as "base_url!", created_at as "created_at!"
+               FROM remotes ORDER BY name"#
+        )
+        .fetch_all(self.db)
+        .await
+    }
+
+    pub async fn get(&self, id: &str) -> sqlx::Result<Option<Remote>> {
+        sqlx::query_as!(
+            Remote,
+            r#"SELECT id as "id!", name as "name!", base_url as "base_url!", created_at as "created_at!"
+               FROM remotes WHERE id = ?"#,
+            id
+        )
+        .fetch_optional(self.db)
+        .await
+    }
+
+    /// Fails with a UNIQUE constraint error if the name is taken
+    pub async fn insert(&self, remote: &Remote) -> sqlx::Result<()> {
+        sqlx::query!(
+            "INSERT INTO remotes (id, name, base_url, created_at) VALUES (?, ?, ?, ?)",
+            remote.id,
+            remote.name,
+            remote.base_url,
+            remote.created_at
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns false if there was no such remote; what was imported from it stays
+    pub async fn delete(&self, id: &str) -> sqlx::Result<bool> {
+        let result = sqlx::query!("DELETE FROM remotes WHERE id = ?", id)
+            .execute(self.db)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,88 @@
+use crate::models::{MirrorFailure, Replication};
+use sqlx::SqlitePool;
+
+pub struct ReplicationRepo<'a> {
+    db: &'a SqlitePool,
+}
+
+impl<'a> ReplicationRepo<'a> {
+    pub fn new(db: &'a SqlitePool) -> Self {
+        Self { db }
+    }
+
+    pub async fn set(
+        &self,
+        upload_id: &str,
+        status: &str,
+        error: Option<&str>,
+        updated_at: &str,
+    ) -> sqlx::Result<()> {
+        sqlx::query!(
+            "INSERT OR REPLACE INTO upload_replication (upload_id, status, error, updated_at) VALUES (?, ?, ?, ?)",
+            upload_id,
+            status,
+            error,
+            updated_at
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get(&self, upload_id: &str) -> sqlx::Result<Option<Replication>> {
+        sqlx::query_as!(
+            Replication,
+            r#"SELECT status as "status!", error, updated_at as "updated_at!"
+               FROM upload_replication WHERE upload_id = ?"#,
+            upload_id
+        )
+        .fetch_optional(self.db)
+        .await
+    }
+
+    /// Uploads whose file is not known to be in the mirror: never copied, still being
+    /// copied, or failed
+    pub async fn unmirrored_ids(&self) -> sqlx::Result<Vec<String>> {
+        sqlx::query_scalar!(
+            r#"SELECT u.id as "id!" FROM uploads u
+               LEFT JOIN upload_replication r ON r.upload_id = u.id
+               WHERE r.status IS NULL OR r.status != 'mirrored'
+               ORDER BY u.created_at"#
+        )
+        .fetch_all(self.db)
+        .await
+    }
+
+    /// Number of uploads by replication status, `None` for uploads never copied
+    pub async fn counts(&self) -> sqlx::Result<Vec<(Option<String>, i64)>> {
+        let rows = sqlx::query!(
+            r#"SELECT r.status, COUNT(*) as "count!: i64" FROM uploads u
+               LEFT JOIN upload_replication r ON r.upload_id = u.id
+               GROUP BY r.status"#
+        )
+        .fetch_all(self.db)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.status, row.count))
+            .collect())
+    }
+
+    pub async fn failures(&self) -> sqlx::Result<Vec<MirrorFailure>> {
+        sqlx::query_as!(
+            MirrorFailure,
+            r#"SELECT r.upload_id as "upload_id!", u.original_filename as "filename!",
+                      r.error, r.updated_at as "updated_at!"
+               FROM upload_replication r JOIN uploads u ON u.id = r.upload_id
+               WHERE r.status = 'failed' ORDER BY r.updated_at DESC"#
+        )
+        .fetch_all(self.db)
+        .await
+    }
+
+    /// Write a consistent copy of the whole database to `path`, which must not exist yet
+    pub async fn snapshot_database(&self, path: &str) -> sqlx::Result<()> {
+        sqlx::query!("VACUUM INTO ?", path).execute(self.db).await?;
+        Ok(())
+    }
+}
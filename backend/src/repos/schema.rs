@@ -0,0 +1,47 @@
+use sqlx::SqlitePool;
+
+/// A migration recorded in the database by `sqlx::migrate!`
+pub struct AppliedMigration {
+    pub version: i64,
+    pub checksum: Vec<u8>,
+    pub success: bool,
+}
+
+/// The migrations bookkeeping of the database, read before migrating it
+pub struct SchemaRepo<'a> {
+    db: &'a SqlitePool,
+}
+
+impl<'a> SchemaRepo<'a> {
+    pub fn new(db: &'a SqlitePool) -> Self {
+        Self { db }
+    }
+
+    /// Migrations applied so far; none for a database never migrated
+    pub async fn applied_migrations(&self) -> sqlx::Result<Vec<AppliedMigration>> {
+        let migrated = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count!: i64" FROM sqlite_master
+               WHERE type = 'table' AND name = '_sqlx_migrations'"#
+        )
+        .fetch_one(self.db)
+        .await?;
+        if migrated == 0 {
+            return Ok(Vec::new());
+        }
+        // Not checked at compile time: sqlx creates this table, not our migrations, so
+        // development databases built from the migration files do not have it
+        let rows: Vec<(i64, Vec<u8>, bool)> = sqlx::query_as(
+            "SELECT version, checksum, success FROM _sqlx_migrations ORDER BY version",
+        )
+        .fetch_all(self.db)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|(version, checksum, success)| AppliedMigration {
+                version,
+                checksum,
+                success,
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,86 @@
+use crate::models::ShareLink;
+use sqlx::SqlitePool;
+
+pub struct ShareRepo<'a> {
+    db: &'a SqlitePool,
+}
+
+impl<'a> ShareRepo<'a> {
+    pub fn new(db: &'a SqlitePool) -> Self {
+        Self { db }
+    }
+
+    /// The share links of an upload, newest first, revoked ones included
+    pub async fn list_for_upload(&self, upload_id: &str) -> sqlx::Result<Vec<ShareLink>> {
+        sqlx::query_as!(
+            ShareLink,
+            r#"SELECT token as "token!", upload_id as "upload_id!", label, created_at as "created_at!", expires_at, revoked_at,
+                      access_count as "access_count!", download_count as "download_count!", last_accessed_at
+               FROM share_links WHERE upload_id = ? ORDER BY created_at DESC, rowid DESC"#,
+            upload_id
+        )
+        .fetch_all(self.db)
+        .await
+    }
+
+    pub async fn get(&self, token: &str) -> sqlx::Result<Option<ShareLink>> {
+        sqlx::query_as!(
+            ShareLink,
+            r#"SELECT token as "token!", upload_id as "upload_id!", label, created_at as "created_at!", expires_at, revoked_at,
+                      access_count as "access_count!", download_count as "download_count!", last_accessed_at
+               FROM share_links WHERE token = ?"#,
+            token
+        )
+        .fetch_optional(self.db)
+        .await
+    }
+
+    pub async fn insert(
+        &self,
+        token: &str,
+        upload_id: &str,
+        label: Option<&str>,
+        created_at: &str,
+        expires_at: Option<&str>,
+    ) -> sqlx::Result<()> {
+        sqlx::query!(
+            "INSERT INTO share_links (token, upload_id, label, created_at, expires_at) VALUES (?, ?, ?, ?, ?)",
+            token,
+            upload_id,
+            label,
+            created_at,
+            expires_at
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Revoke a link of an upload; returns false if there is no such link or it was revoked
+    /// already
+    pub async fn revoke(&self, upload_id: &str, token: &str, now: &str) -> sqlx::Result<bool> {
+        let result = sqlx::query!(
+            "UPDATE share_links SET revoked_at = ? WHERE token = ? AND upload_id = ? AND revoked_at IS NULL",
+            now,
+            token,
+            upload_id
+        )
+        .execute(self.db)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Count one request through a link; `download` also counts it as a download
+    pub async fn record_access(&self, token: &str, download: bool, now: &str) -> sqlx::Result<()> {
+        let downloads = i64::from(download);
+        sqlx::query!(
+            "UPDATE share_links SET access_count = access_count + 1, download_count = download_count + ?, last_accessed_at = ? WHERE token = ?",
+            downloads,
+            now,
+            token
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,117 @@
+use crate::timestamps;
+use sqlx::SqlitePool;
+
+pub struct StoredSnapshot {
+    pub id: String,
+    pub label: String,
+    pub description: Option<String>,
+    pub pipeline: String, // JSON, see `snapshots::Pipeline`
+    pub created_at: String,
+    pub activated_at: String,
+}
+
+pub struct SnapshotRepo<'a> {
+    db: &'a SqlitePool,
+}
+
+impl<'a> SnapshotRepo<'a> {
+    pub fn new(db: &'a SqlitePool) -> Self {
+        Self { db }
+    }
+
+    /// All snapshots, newest first
+    pub async fn list(&self) -> sqlx::Result<Vec<StoredSnapshot>> {
+        sqlx::query_as!(
+            StoredSnapshot,
+            r#"SELECT id as "id!", label as "label!", description, pipeline as "pipeline!", created_at as "created_at!", activated_at as "activated_at!"
+               FROM pipeline_snapshots ORDER BY created_at DESC, rowid DESC"#
+        )
+        .fetch_all(self.db)
+        .await
+    }
+
+    pub async fn get(&self, id: &str) -> sqlx::Result<Option<StoredSnapshot>> {
+        sqlx::query_as!(
+            StoredSnapshot,
+            r#"SELECT id as "id!", label as "label!", description, pipeline as "pipeline!", created_at as "created_at!", activated_at as "activated_at!"
+               FROM pipeline_snapshots WHERE id = ?"#,
+            id
+        )
+        .fetch_optional(self.db)
+        .await
+    }
+
+    /// The snapshot taken or restored last; new jobs record it
+    pub async fn active_id(&self) -> sqlx::Result<Option<String>> {
+        sqlx::query_scalar!(
+            r#"SELECT id as "id!" FROM pipeline_snapshots ORDER BY activated_at DESC, rowid DESC LIMIT 1"#
+        )
+        .fetch_optional(self.db)
+        .await
+    }
+
+    /// Fails with a UNIQUE constraint error if the label is taken
+    pub async fn insert(
+        &self,
+        id: &str,
+        label: &str,
+        description: Option<&str>,
+        pipeline: &str,
+    ) -> sqlx::Result<()> {
+        let created_at = timestamps::now();
+        sqlx::query!(
+            "INSERT INTO pipeline_snapshots (id, label, description, pipeline, created_at, activated_at) VALUES (?, ?, ?, ?, ?, ?)",
+            id,
+            label,
+            description,
+            pipeline,
+            created_at,
+            created_at
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Make a snapshot the active one again, after restoring it
+    pub async fn activate(&self, id: &str) -> sqlx::Result<()> {
+        let activated_at = timestamps::now();
+        sqlx::query!(
+            "UPDATE pipeline_snapshots SET activated_at = ? WHERE id = ?",
+            activated_at,
+            id
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns false if there was no such snapshot; the jobs that recorded it keep running
+    /// without one
+    pub async fn delete(&self, id: &str) -> sqlx::Result<bool> {
+        let result = sqlx::query!("DELETE FROM pipeline_snapshots WHERE id = ?", id)
+            .execute(self.db)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn save_script(&self, sha256: &str, content: &str) -> sqlx::Result<()> {
+        sqlx::query!(
+            "INSERT OR IGNORE INTO snapshot_scripts (sha256, content) VALUES (?, ?)",
+            sha256,
+            content
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn script(&self, sha256: &str) -> sqlx::Result<Option<String>> {
+        sqlx::query_scalar!(
+            r#"SELECT content as "content!" FROM snapshot_scripts WHERE sha256 = ?"#,
+            sha256
+        )
+        .fetch_optional(self.db)
+        .await
+    }
+}
@@ -0,0 +1,44 @@
+use serde::Deserialize;
+
+/// What list endpoints order by (`sort=`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    #[default]
+    CreatedAt,
+    Name,
+    Size,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+/// Sort key and direction; names read A to Z and everything else newest or largest first
+/// unless an order is given
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Sort {
+    pub key: SortKey,
+    pub order: Option<SortOrder>,
+}
+
+impl Sort {
+    pub fn ascending(&self) -> bool {
+        match self.order {
+            Some(order) => order == SortOrder::Asc,
+            None => self.key == SortKey::Name,
+        }
+    }
+
+    // The key as bound into the `ORDER BY CASE` of the list queries
+    pub(crate) fn key_name(&self) -> &'static str {
+        match self.key {
+            SortKey::CreatedAt => "created_at",
+            SortKey::Name => "name",
+            SortKey::Size => "size",
+        }
+    }
+}
@@ -0,0 +1,317 @@
+use crate::access::{Requester, TagAccess};
+use crate::models::{Tag, TagDeletion, TagStorage, TagUsage};
+use crate::timestamps;
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+pub struct TagRepo<'a> {
+    db: &'a SqlitePool,
+}
+
+impl<'a> TagRepo<'a> {
+    pub fn new(db: &'a SqlitePool) -> Self {
+        Self { db }
+    }
+
+    /// All tags, newest first
+    pub async fn list(&self) -> sqlx::Result<Vec<Tag>> {
+        sqlx::query_as!(
+            Tag,
+            r#"SELECT id as "id!", name as "name!", color as "color!", description, created_at as "created_at!" FROM tags ORDER BY created_at DESC"#
+        )
+        .fetch_all(self.db)
+        .await
+    }
+
+    /// All tags, newest first, with the uploads and functions referring to each
+    pub async fn list_with_usage(&self) -> sqlx::Result<Vec<TagUsage>> {
+        let rows = sqlx::query!(
+            r#"SELECT t.id as "id!", t.name as "name!", t.color as "color!", t.description,
+                      t.created_at as "created_at!",
+                      (SELECT COUNT(*) FROM upload_tags ut WHERE ut.tag_id = t.id) as "upload_count!: i64",
+                      (SELECT COUNT(*) FROM function_input_tags fi WHERE fi.tag_id = t.id) as "input_function_count!: i64",
+                      (SELECT COUNT(*) FROM function_output_tags fo WHERE fo.tag_id = t.id) as "output_function_count!: i64",
+                      (SELECT COUNT(*) FROM function_excluded_tags fe WHERE fe.tag_id = t.id) as "excluded_function_count!: i64"
+               FROM tags t ORDER BY t.created_at DESC"#
+        )
+        .fetch_all(self.db)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| TagUsage {
+                tag: Tag {
+                    id: row.id,
+                    name: row.name,
+                    color: row.color,
+                    description: row.description,
+                    created_at: row.created_at,
+                },
+                upload_count: row.upload_count,
+                input_function_count: row.input_function_count,
+                output_function_count: row.output_function_count,
+                excluded_function_count: row.excluded_function_count,
+            })
+            .collect())
+    }
+
+    pub async fn get(&self, id: &str) -> sqlx::Result<Option<Tag>> {
+        sqlx::query_as!(
+            Tag,
+            r#"SELECT id as "id!", name as "name!", color as "color!", description, created_at as "created_at!" FROM tags WHERE id = ?"#,
+            id
+        )
+        .fetch_optional(self.db)
+        .await
+    }
+
+    pub async fn id_by_name(&self, name: &str) -> sqlx::Result<Option<String>> {
+        sqlx::query_scalar!(r#"SELECT id as "id!" FROM tags WHERE name = ?"#, name)
+            .fetch_optional(self.db)
+            .await
+    }
+
+    /// Insert a tag; fails with a UNIQUE constraint error if the name is taken
+    pub async fn create(
+        &self,
+        name: &str,
+        color: &str,
+        description: Option<&str>,
+    ) -> sqlx::Result<Tag> {
+        let tag = Tag {
+            id: Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            color: color.to_string(),
+            description: description.map(str::to_string),
+            created_at: timestamps::now(),
+        };
+        sqlx::query!(
+            "INSERT INTO tags (id, name, color, description, created_at) VALUES (?, ?, ?, ?, ?)",
+            tag.id,
+            tag.name,
+            tag.color,
+            tag.description,
+            tag.created_at
+        )
+        .execute(self.db)
+        .await?;
+        Ok(tag)
+    }
+
+    /// Ids of the tags with these names, as (name, id); unknown names are left out
+    pub async fn ids_by_names(&self, names: &[&str]) -> sqlx::Result<Vec<(String, String)>> {
+        let names = serde_json::to_string(names).unwrap_or_default();
+        let rows = sqlx::query!(
+            r#"SELECT name as "name!", id as "id!" FROM tags WHERE name IN (SELECT value FROM json_each(?))"#,
+            names
+        )
+        .fetch_all(self.db)
+        .await?;
+        Ok(rows.into_iter().map(|row| (row.name, row.id)).collect())
+    }
+
+    /// Insert a tag with the given color unless the name is taken, and return the id of the
+    /// tag with that name either way. Safe against concurrent callers creating the same tag.
+    pub async fn upsert(&self, name: &str, color: &str) -> sqlx::Result<String> {
+        let id = Uuid::new_v4().to_string();
+        let created_at = timestamps::now();
+        sqlx::query!(
+            "INSERT INTO tags (id, name, color, created_at) VALUES (?, ?, ?, ?) ON CONFLICT(name) DO NOTHING",
+            id,
+            name,
+            color,
+            created_at
+        )
+        .execute(self.db)
+        .await?;
+        self.id_by_name(name).await?.ok_or(sqlx::Error::RowNotFound)
+    }
+
+    pub async fn rename(&self, id: &str, name: &str) -> sqlx::Result<()> {
+        sqlx::query!("UPDATE tags SET name = ? WHERE id = ?", name, id)
+            .execute(self.db)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn set_color(&self, id: &str, color: &str) -> sqlx::Result<()> {
+        sqlx::query!("UPDATE tags SET color = ? WHERE id = ?", color, id)
+            .execute(self.db)
+            .await?;
+        Ok(())
+    }
+
+    /// None removes the description
+    pub async fn set_description(&self, id: &str, description: Option<&str>) -> sqlx::Result<()> {
+        sqlx::query!(
+            "UPDATE tags SET description = ? WHERE id = ?",
+            description,
+            id
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Number of uploads carrying the tag
+    pub async fn usage_count(&self, id: &str) -> sqlx::Result<i64> {
+        sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count!: i64" FROM upload_tags WHERE tag_id = ?"#,
+            id
+        )
+        .fetch_one(self.db)
+        .await
+    }
+
+    /// Uploads and bytes per tag, largest first; tags without uploads are included
+    pub async fn storage(&self) -> sqlx::Result<Vec<TagStorage>> {
+        sqlx::query_as!(
+            TagStorage,
+            r#"SELECT t.id as "tag_id!", t.name as "name!",
+                      COUNT(u.id) as "upload_count!: i64", COALESCE(SUM(u.file_size), 0) as "used_bytes!: i64"
+               FROM tags t
+               LEFT JOIN upload_tags ut ON t.id = ut.tag_id
+               LEFT JOIN uploads u ON u.id = ut.upload_id
+               GROUP BY t.id
+               ORDER BY 4 DESC, t.name"#
+        )
+        .fetch_all(self.db)
+        .await
+    }
+
+    /// Returns false if there was no such tag
+    pub async fn delete(&self, id: &str) -> sqlx::Result<bool> {
+        let result = sqlx::query!("DELETE FROM tags WHERE id = ?", id)
+            .execute(self.db)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Delete a tag along with its uploads' and functions' references to it, as one
+    /// transaction; None if there was no such tag
+    pub async fn delete_with_usages(&self, id: &str) -> sqlx::Result<Option<TagDeletion>> {
+        let mut tx = self.db.begin().await?;
+        let uploads = sqlx::query!("DELETE FROM upload_tags WHERE tag_id = ?", id)
+            .execute(&mut *tx)
+            .await?;
+        let inputs = sqlx::query!("DELETE FROM function_input_tags WHERE tag_id = ?", id)
+            .execute(&mut *tx)
+            .await?;
+        let outputs = sqlx::query!("DELETE FROM function_output_tags WHERE tag_id = ?", id)
+            .execute(&mut *tx)
+            .await?;
+        let excluded = sqlx::query!("DELETE FROM function_excluded_tags WHERE tag_id = ?", id)
+            .execute(&mut *tx)
+            .await?;
+        let tag = sqlx::query!("DELETE FROM tags WHERE id = ?", id)
+            .execute(&mut *tx)
+            .await?;
+        if tag.rows_affected() == 0 {
+            tx.rollback().await?;
+            return Ok(None);
+        }
+        tx.commit().await?;
+        Ok(Some(TagDeletion {
+            tag_id: id.to_string(),
+            uploads_untagged: uploads.rows_affected(),
+            input_functions: inputs.rows_affected(),
+            output_functions: outputs.rows_affected(),
+            excluded_functions: excluded.rows_affected(),
+        }))
+    }
+
+    /// Who the tag's uploads are visible to; empty if it is not restricted
+    pub async fn access(&self, id: &str) -> sqlx::Result<TagAccess> {
+        let rows = sqlx::query!(
+            r#"SELECT kind as "kind!", name as "name!" FROM tag_access WHERE tag_id = ? ORDER BY name"#,
+            id
+        )
+        .fetch_all(self.db)
+        .await?;
+        let mut access = TagAccess::default();
+        for row in rows {
+            match row.kind.as_str() {
+                "user" => access.users.push(row.name),
+                _ => access.roles.push(row.name),
+            }
+        }
+        Ok(access)
+    }
+
+    /// Replace who the tag's uploads are visible to; an empty `access` lifts the restriction
+    pub async fn set_access(&self, id: &str, access: &TagAccess) -> sqlx::Result<()> {
+        let mut tx = self.db.begin().await?;
+        sqlx::query!("DELETE FROM tag_access WHERE tag_id = ?", id)
+            .execute(&mut *tx)
+            .await?;
+        let entries = access
+            .users
+            .iter()
+            .map(|user| ("user", user))
+            .chain(access.roles.iter().map(|role| ("role", role)));
+        for (kind, name) in entries {
+            sqlx::query!(
+                "INSERT OR IGNORE INTO tag_access (tag_id, kind, name) VALUES (?, ?, ?)",
+                id,
+                kind,
+                name
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await
+    }
+
+    /// Ids of the restricted tags whose uploads the requester may not see
+    pub async fn restricted_from(&self, requester: &Requester) -> sqlx::Result<Vec<String>> {
+        let roles = serde_json::to_string(&requester.roles).unwrap_or_default();
+        sqlx::query_scalar!(
+            r#"SELECT DISTINCT ta.tag_id as "tag_id!" FROM tag_access ta
+               WHERE NOT EXISTS (
+                   SELECT 1 FROM tag_access a WHERE a.tag_id = ta.tag_id
+                     AND ((a.kind = 'user' AND a.name = ?)
+                       OR (a.kind = 'role' AND a.name IN (SELECT value FROM json_each(?))))
+               )"#,
+            requester.user,
+            roles
+        )
+        .fetch_all(self.db)
+        .await
+    }
+
+    pub async fn for_upload(&self, upload_id: &str) -> sqlx::Result<Vec<Tag>> {
+        sqlx::query_as!(
+            Tag,
+            r#"SELECT t.id as "id!", t.name as "name!", t.color as "color!", t.description, t.created_at as "created_at!"
+               FROM tags t
+               INNER JOIN upload_tags ut ON t.id = ut.tag_id
+               WHERE ut.upload_id = ?"#,
+            upload_id
+        )
+        .fetch_all(self.db)
+        .await
+    }
+
+    /// Returns false if the upload did not have the tag
+    pub async fn untag_upload(&self, upload_id: &str, tag_id: &str) -> sqlx::Result<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM upload_tags WHERE upload_id = ? AND tag_id = ?",
+            upload_id,
+            tag_id
+        )
+        .execute(self.db)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Tag an upload; tagging it twice is a no-op. Returns false if it already had the tag
+    pub async fn tag_upload(&self, upload_id: &str, tag_id: &str) -> sqlx::Result<bool> {
+        let result = sqlx::query!(
+            "INSERT OR IGNORE INTO upload_tags (upload_id, tag_id) VALUES (?, ?)",
+            upload_id,
+            tag_id
+        )
+        .execute(self.db)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
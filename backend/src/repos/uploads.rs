@@ -0,0 +1,603 @@
+use super::Sort;
+use crate::models::{ColumnInfo, DataDictionary, FileLineageInfo, LineageSource, Tag, Upload};
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+/// An `uploads` row
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct StoredUpload {
+    pub id: String,
+    pub filename: String, // name on disk, inside the uploads directory
+    pub original_filename: String,
+    pub file_size: i64,
+    pub mime_type: Option<String>,          // as claimed by the client
+    pub detected_mime_type: Option<String>, // sniffed from the content
+    pub created_at: String,
+    pub sha256: Option<String>,
+    pub assignee: Option<String>,
+    pub artifact_type: String, // `data`, or `error_log` for logs of failed runs
+    pub compression: Option<String>, // how the file is stored on disk, e.g. `zstd`
+    pub protected: bool,       // deletion is refused until the flag is cleared
+    pub expires_at: Option<String>, // intermediate outputs only, see `FunctionRepo`
+}
+
+impl StoredUpload {
+    pub fn into_upload(self, tags: Vec<Tag>, lineage: Option<FileLineageInfo>) -> Upload {
+        Upload {
+            id: self.id,
+            filename: self.filename,
+            original_filename: self.original_filename,
+            file_size: self.file_size,
+            mime_type: self.mime_type,
+            detected_mime_type: self.detected_mime_type,
+            created_at: self.created_at,
+            sha256: self.sha256,
+            assignee: self.assignee,
+            artifact_type: self.artifact_type,
+            protected: self.protected,
+            expires_at: self.expires_at,
+            tags,
+            lineage,
+            image_metadata: None,
+            replication: None,
+        }
+    }
+}
+
+pub struct NewUpload<'a> {
+    pub id: &'a str,
+    pub filename: &'a str,
+    pub original_filename: &'a str,
+    pub file_size: i64,
+    pub mime_type: Option<&'a str>,
+    pub detected_mime_type: Option<&'a str>,
+    pub created_at: &'a str,
+    pub sha256: Option<&'a str>,
+    pub artifact_type: &'a str,
+    pub compression: Option<&'a str>,
+    pub expires_at: Option<&'a str>,
+}
+
+/// How an output was derived: by a function, or by a built-in operation such as `pivot`
+pub struct NewLineage<'a> {
+    pub output_upload_id: &'a str,
+    pub source_upload_id: &'a str,
+    pub function_id: Option<&'a str>,
+    pub operation: Option<&'a str>,
+    pub query: Option<&'a str>, // JSON parameters of the operation
+    pub success: bool,
+    pub created_at: &'a str,
+}
+
+/// Which uploads `UploadRepo::list` returns; None fields do not filter
+#[derive(Default)]
+pub struct UploadFilter<'a> {
+    pub assignee: Option<&'a str>,
+    pub derived: Option<bool>, // false for files without lineage (raw uploads), true for outputs
+    pub produced_by_function: Option<&'a str>,
+    pub artifact_type: Option<&'a str>,
+    pub intermediate: Option<bool>, // intermediate outputs, or everything else
+    pub created_after: Option<&'a str>, // stored form, see `timestamps`
+    pub created_before: Option<&'a str>,
+    pub tag_ids: &'a [String],   // uploads with all of these tags
+    pub tag_names: &'a [String], // the same, by name
+    pub filename_contains: Option<&'a str>, // case-insensitive
+    pub hidden_tag_ids: &'a [String], // uploads with any of these tags are left out
+}
+
+// A JSON array of the distinct `values`, for `json_each`, and how many there are
+fn json_set(values: &[String]) -> (String, i64) {
+    let distinct: std::collections::BTreeSet<&String> = values.iter().collect();
+    (
+        serde_json::to_string(&distinct).unwrap_or_else(|_| "[]".to_string()),
+        distinct.len() as i64,
+    )
+}
+
+/// The primary lineage row of a derived upload
+pub struct LineageLink {
+    pub function_id: Option<String>, // None for built-in operations
+}
+
+/// One step of a lineage graph: `step` (a function or operation) made `output` from `source`
+pub struct LineageEdge {
+    pub source_id: String,
+    pub source_filename: String,
+    pub output_id: String,
+    pub output_filename: String,
+    pub step: String, // the function's name, or the operation
+    pub success: bool,
+}
+
+pub struct UploadRepo<'a> {
+    db: &'a SqlitePool,
+}
+
+impl<'a> UploadRepo<'a> {
+    pub fn new(db: &'a SqlitePool) -> Self {
+        Self { db }
+    }
+
+    /// Uploads matching `filter`, newest first
+    pub async fn list(&self, filter: &UploadFilter<'_>) -> sqlx::Result<Vec<StoredUpload>> {
+        self.page(filter, Sort::default(), None, 0).await
+    }
+
+    /// At most `limit` (all if None) of the uploads matching `filter` in `sort` order, after
+    /// skipping `offset` of them
+    pub async fn page(
+        &self,
+        filter: &UploadFilter<'_>,
+        sort: Sort,
+        limit: Option<i64>,
+        offset: i64,
+    ) -> sqlx::Result<Vec<StoredUpload>> {
+        let limit = limit.unwrap_or(-1); // no limit
+        let (key, ascending) = (sort.key_name(), sort.ascending());
+        let (tag_ids, tag_count) = json_set(filter.tag_ids);
+        let (tag_names, name_count) = json_set(filter.tag_names);
+        let (hidden_tag_ids, _) = json_set(filter.hidden_tag_ids);
+        sqlx::query_as!(
+            StoredUpload,
+            r#"SELECT u.id as "id!", u.filename as "filename!", u.original_filename as "original_filename!", u.file_size as "file_size!", u.mime_type, u.detected_mime_type, u.created_at as "created_at!", u.sha256, u.assignee, u.artifact_type as "artifact_type!", u.compression, u.protected as "protected!: bool", u.expires_at
+               FROM uploads u
+               WHERE (? IS NULL OR u.assignee = ?)
+                 AND (? IS NULL OR EXISTS (SELECT 1 FROM file_lineage fl WHERE fl.output_upload_id = u.id) = ?)
+                 AND (? IS NULL OR EXISTS (SELECT 1 FROM file_lineage fl WHERE fl.function_id = ? AND fl.output_upload_id = u.id))
+                 AND (? IS NULL OR u.artifact_type = ?)
+                 AND (? IS NULL OR (u.expires_at IS NOT NULL) = ?)
+                 AND (? IS NULL OR u.created_at >= ?)
+                 AND (? IS NULL OR u.created_at < ?)
+                 AND (? = 0 OR (SELECT COUNT(DISTINCT ut.tag_id) FROM upload_tags ut WHERE ut.upload_id = u.id AND ut.tag_id IN (SELECT value FROM json_each(?))) = ?)
+                 AND (? = 0 OR (SELECT COUNT(DISTINCT t.name) FROM upload_tags ut JOIN tags t ON t.id = ut.tag_id WHERE ut.upload_id = u.id AND t.name IN (SELECT value FROM json_each(?))) = ?)
+                 AND (? IS NULL OR instr(lower(u.original_filename), lower(?)) > 0)
+                 AND NOT EXISTS (SELECT 1 FROM upload_tags ut WHERE ut.upload_id = u.id AND ut.tag_id IN (SELECT value FROM json_each(?)))
+               ORDER BY
+                 CASE WHEN ? THEN (CASE ? WHEN 'name' THEN lower(u.original_filename) WHEN 'size' THEN u.file_size ELSE u.created_at END) END ASC,
+                 CASE WHEN ? THEN NULL ELSE (CASE ? WHEN 'name' THEN lower(u.original_filename) WHEN 'size' THEN u.file_size ELSE u.created_at END) END DESC,
+                 u.created_at DESC, u.id
+               LIMIT ? OFFSET ?"#,
+            filter.assignee,
+            filter.assignee,
+            filter.derived,
+            filter.derived,
+            filter.produced_by_function,
+            filter.produced_by_function,
+            filter.artifact_type,
+            filter.artifact_type,
+            filter.intermediate,
+            filter.intermediate,
+            filter.created_after,
+            filter.created_after,
+            filter.created_before,
+            filter.created_before,
+            tag_count,
+            tag_ids,
+            tag_count,
+            name_count,
+            tag_names,
+            name_count,
+            filter.filename_contains,
+            filter.filename_contains,
+            hidden_tag_ids,
+            ascending,
+            key,
+            ascending,
+            key,
+            limit,
+            offset
+        )
+        .fetch_all(self.db)
+        .await
+    }
+
+    /// How many uploads match `filter`
+    pub async fn count(&self, filter: &UploadFilter<'_>) -> sqlx::Result<i64> {
+        let (tag_ids, tag_count) = json_set(filter.tag_ids);
+        let (tag_names, name_count) = json_set(filter.tag_names);
+        let (hidden_tag_ids, _) = json_set(filter.hidden_tag_ids);
+        sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count!: i64"
+               FROM uploads u
+               WHERE (? IS NULL OR u.assignee = ?)
+                 AND (? IS NULL OR EXISTS (SELECT 1 FROM file_lineage fl WHERE fl.output_upload_id = u.id) = ?)
+                 AND (? IS NULL OR EXISTS (SELECT 1 FROM file_lineage fl WHERE fl.function_id = ? AND fl.output_upload_id = u.id))
+                 AND (? IS NULL OR u.artifact_type = ?)
+                 AND (? IS NULL OR (u.expires_at IS NOT NULL) = ?)
+                 AND (? IS NULL OR u.created_at >= ?)
+                 AND (? IS NULL OR u.created_at < ?)
+                 AND (? = 0 OR (SELECT COUNT(DISTINCT ut.tag_id) FROM upload_tags ut WHERE ut.upload_id = u.id AND ut.tag_id IN (SELECT value FROM json_each(?))) = ?)
+                 AND (? = 0 OR (SELECT COUNT(DISTINCT t.name) FROM upload_tags ut JOIN tags t ON t.id = ut.tag_id WHERE ut.upload_id = u.id AND t.name IN (SELECT value FROM json_each(?))) = ?)
+                 AND (? IS NULL OR instr(lower(u.original_filename), lower(?)) > 0)
+                 AND NOT EXISTS (SELECT 1 FROM upload_tags ut WHERE ut.upload_id = u.id AND ut.tag_id IN (SELECT value FROM json_each(?)))"#,
+            filter.assignee,
+            filter.assignee,
+            filter.derived,
+            filter.derived,
+            filter.produced_by_function,
+            filter.produced_by_function,
+            filter.artifact_type,
+            filter.artifact_type,
+            filter.intermediate,
+            filter.intermediate,
+            filter.created_after,
+            filter.created_after,
+            filter.created_before,
+            filter.created_before,
+            tag_count,
+            tag_ids,
+            tag_count,
+            name_count,
+            tag_names,
+            name_count,
+            filter.filename_contains,
+            filter.filename_contains,
+            hidden_tag_ids
+        )
+        .fetch_one(self.db)
+        .await
+    }
+
+    pub async fn get(&self, id: &str) -> sqlx::Result<Option<StoredUpload>> {
+        sqlx::query_as!(
+            StoredUpload,
+            r#"SELECT id as "id!", filename as "filename!", original_filename as "original_filename!", file_size as "file_size!", mime_type, detected_mime_type, created_at as "created_at!", sha256, assignee, artifact_type as "artifact_type!", compression, protected as "protected!: bool", expires_at FROM uploads WHERE id = ?"#,
+            id
+        )
+        .fetch_optional(self.db)
+        .await
+    }
+
+    /// Uploads matching an FTS5 `match_expression` (see `search`), best first, with their bm25
+    /// scores (lower is better); filename matches weigh most, then tags, then metadata
+    pub async fn search(
+        &self,
+        match_expression: &str,
+        hidden_tag_ids: &[String],
+        limit: i64,
+    ) -> sqlx::Result<Vec<(StoredUpload, f64)>> {
+        let (hidden_tag_ids, _) = json_set(hidden_tag_ids);
+        let hits = sqlx::query!(
+            r#"SELECT upload_id as "upload_id!: String", bm25(uploads_fts, 0.0, 10.0, 5.0, 1.0) as "score!: f64"
+               FROM uploads_fts
+               WHERE uploads_fts MATCH ?
+                 AND NOT EXISTS (SELECT 1 FROM upload_tags ut WHERE ut.upload_id = uploads_fts.upload_id AND ut.tag_id IN (SELECT value FROM json_each(?)))
+               ORDER BY 2
+               LIMIT ?"#,
+            match_expression,
+            hidden_tag_ids,
+            limit
+        )
+        .fetch_all(self.db)
+        .await?;
+
+        let mut found = Vec::with_capacity(hits.len());
+        for hit in hits {
+            if let Some(upload) = self.get(&hit.upload_id).await? {
+                found.push((upload, hit.score));
+            }
+        }
+        Ok(found)
+    }
+
+    /// Replace the indexed contents of an upload
+    pub async fn index_content(&self, id: &str, content: &str) -> sqlx::Result<()> {
+        let mut tx = self.db.begin().await?;
+        sqlx::query!("DELETE FROM upload_contents_fts WHERE upload_id = ?", id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!(
+            "INSERT INTO upload_contents_fts (upload_id, content) VALUES (?, ?)",
+            id,
+            content
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await
+    }
+
+    /// Uploads whose indexed contents match an FTS5 `match_expression`, best first
+    pub async fn search_contents(
+        &self,
+        match_expression: &str,
+        hidden_tag_ids: &[String],
+        limit: i64,
+    ) -> sqlx::Result<Vec<StoredUpload>> {
+        let (hidden_tag_ids, _) = json_set(hidden_tag_ids);
+        let ids = sqlx::query_scalar!(
+            r#"SELECT upload_id as "upload_id!: String"
+               FROM upload_contents_fts
+               WHERE upload_contents_fts MATCH ?
+                 AND NOT EXISTS (SELECT 1 FROM upload_tags ut WHERE ut.upload_id = upload_contents_fts.upload_id AND ut.tag_id IN (SELECT value FROM json_each(?)))
+               ORDER BY rank
+               LIMIT ?"#,
+            match_expression,
+            hidden_tag_ids,
+            limit
+        )
+        .fetch_all(self.db)
+        .await?;
+
+        let mut found = Vec::with_capacity(ids.len());
+        for id in ids {
+            if let Some(upload) = self.get(&id).await? {
+                found.push(upload);
+            }
+        }
+        Ok(found)
+    }
+
+    /// Uploads up to `max_bytes` large whose contents are not indexed, e.g. those stored before
+    /// content indexing was enabled
+    pub async fn without_content_index(&self, max_bytes: i64) -> sqlx::Result<Vec<StoredUpload>> {
+        sqlx::query_as!(
+            StoredUpload,
+            r#"SELECT id as "id!", filename as "filename!", original_filename as "original_filename!", file_size as "file_size!", mime_type, detected_mime_type, created_at as "created_at!", sha256, assignee, artifact_type as "artifact_type!", compression, protected as "protected!: bool", expires_at
+               FROM uploads
+               WHERE file_size <= ?
+                 AND id NOT IN (SELECT upload_id FROM upload_contents_fts)"#,
+            max_bytes
+        )
+        .fetch_all(self.db)
+        .await
+    }
+
+    /// Unprotected intermediate outputs whose TTL ran out before `now`, in order of expiry
+    pub async fn expired(&self, now: &str) -> sqlx::Result<Vec<StoredUpload>> {
+        sqlx::query_as!(
+            StoredUpload,
+            r#"SELECT id as "id!", filename as "filename!", original_filename as "original_filename!", file_size as "file_size!", mime_type, detected_mime_type, created_at as "created_at!", sha256, assignee, artifact_type as "artifact_type!", compression, protected as "protected!: bool", expires_at
+               FROM uploads
+               WHERE expires_at < ? AND protected = 0
+               ORDER BY expires_at"#,
+            now
+        )
+        .fetch_all(self.db)
+        .await
+    }
+
+    /// Uploads with the given content hash, oldest first
+    pub async fn with_sha256(&self, sha256: &str) -> sqlx::Result<Vec<StoredUpload>> {
+        sqlx::query_as!(
+            StoredUpload,
+            r#"SELECT id as "id!", filename as "filename!", original_filename as "original_filename!", file_size as "file_size!", mime_type, detected_mime_type, created_at as "created_at!", sha256, assignee, artifact_type as "artifact_type!", compression, protected as "protected!: bool", expires_at FROM uploads WHERE sha256 = ? ORDER BY created_at"#,
+            sha256
+        )
+        .fetch_all(self.db)
+        .await
+    }
+
+    pub async fn insert(&self, upload: &NewUpload<'_>) -> sqlx::Result<()> {
+        sqlx::query!(
+            "INSERT INTO uploads (id, filename, original_filename, file_size, mime_type, detected_mime_type, created_at, sha256, artifact_type, compression, expires_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            upload.id,
+            upload.filename,
+            upload.original_filename,
+            upload.file_size,
+            upload.mime_type,
+            upload.detected_mime_type,
+            upload.created_at,
+            upload.sha256,
+            upload.artifact_type,
+            upload.compression,
+            upload.expires_at
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Change the name an upload is shown and downloaded as; the file on disk keeps its name
+    pub async fn rename(&self, id: &str, original_filename: &str) -> sqlx::Result<()> {
+        sqlx::query!(
+            "UPDATE uploads SET original_filename = ? WHERE id = ?",
+            original_filename,
+            id
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Keep an intermediate output like any other upload
+    pub async fn clear_expiry(&self, id: &str) -> sqlx::Result<()> {
+        sqlx::query!("UPDATE uploads SET expires_at = NULL WHERE id = ?", id)
+            .execute(self.db)
+            .await?;
+        Ok(())
+    }
+
+    /// None unassigns the upload
+    pub async fn set_assignee(&self, id: &str, assignee: Option<&str>) -> sqlx::Result<()> {
+        sqlx::query!("UPDATE uploads SET assignee = ? WHERE id = ?", assignee, id)
+            .execute(self.db)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn set_protected(&self, id: &str, protected: bool) -> sqlx::Result<()> {
+        sqlx::query!(
+            "UPDATE uploads SET protected = ? WHERE id = ?",
+            protected,
+            id
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns false if there was no such upload or it is protected; the file on disk is left
+    /// to the caller
+    pub async fn delete(&self, id: &str) -> sqlx::Result<bool> {
+        let result = sqlx::query!("DELETE FROM uploads WHERE id = ? AND protected = 0", id)
+            .execute(self.db)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn add_lineage(&self, lineage: &NewLineage<'_>) -> sqlx::Result<()> {
+        let id = Uuid::new_v4().to_string();
+        sqlx::query!(
+            "INSERT INTO file_lineage (id, output_upload_id, source_upload_id, function_id, operation, query, success, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            id,
+            lineage.output_upload_id,
+            lineage.source_upload_id,
+            lineage.function_id,
+            lineage.operation,
+            lineage.query,
+            lineage.success,
+            lineage.created_at
+        )
+        .execute(self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Where an upload came from, if it was produced by a function or operation
+    pub async fn lineage_link(&self, upload_id: &str) -> sqlx::Result<Option<LineageLink>> {
+        sqlx::query_as!(
+            LineageLink,
+            "SELECT function_id FROM file_lineage WHERE output_upload_id = ? ORDER BY rowid LIMIT 1",
+            upload_id
+        )
+        .fetch_optional(self.db)
+        .await
+    }
+
+    /// The lineage of an upload with source and function names, for display
+    pub async fn lineage(&self, upload_id: &str) -> sqlx::Result<Option<FileLineageInfo>> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT 
+                fl.success as "success!",
+                fl.source_upload_id as "source_upload_id!",
+                fl.function_id as "function_id?",
+                fl.operation as "operation?",
+                fl.query as "query?",
+                u.original_filename as "source_filename!",
+                COALESCE(f.name, fl.operation) as "function_name!: String"
+            FROM file_lineage fl
+            INNER JOIN uploads u ON fl.source_upload_id = u.id
+            LEFT JOIN functions f ON fl.function_id = f.id
+            WHERE fl.output_upload_id = ?
+            ORDER BY fl.rowid
+            "#,
+            upload_id
+        )
+        .fetch_all(self.db)
+        .await?;
+
+        // Multi-source operations record one lineage row per input, the first being the primary one
+        let mut rows = rows.into_iter();
+        let Some(row) = rows.next() else {
+            return Ok(None);
+        };
+        Ok(Some(FileLineageInfo {
+            source_upload_id: row.source_upload_id,
+            source_filename: row.source_filename,
+            function_id: row.function_id,
+            function_name: row.function_name,
+            operation: row.operation,
+            query: row.query,
+            success: row.success != 0,
+            other_sources: rows
+                .map(|row| LineageSource {
+                    upload_id: row.source_upload_id,
+                    filename: row.source_filename,
+                })
+                .collect(),
+        }))
+    }
+
+    /// Every lineage step leading to an upload and following from it, however many steps
+    /// away, in the order they were recorded
+    pub async fn lineage_graph(&self, upload_id: &str) -> sqlx::Result<Vec<LineageEdge>> {
+        sqlx::query_as!(
+            LineageEdge,
+            r#"
+            WITH RECURSIVE
+                ancestors(id) AS (
+                    SELECT ?
+                    UNION
+                    SELECT fl.source_upload_id FROM file_lineage fl
+                    INNER JOIN ancestors a ON fl.output_upload_id = a.id
+                ),
+                descendants(id) AS (
+                    SELECT ?
+                    UNION
+                    SELECT fl.output_upload_id FROM file_lineage fl
+                    INNER JOIN descendants d ON fl.source_upload_id = d.id
+                )
+            SELECT
+                fl.source_upload_id as "source_id!",
+                src.original_filename as "source_filename!",
+                fl.output_upload_id as "output_id!",
+                out.original_filename as "output_filename!",
+                COALESCE(f.name, fl.operation) as "step!: String",
+                fl.success as "success!: bool"
+            FROM file_lineage fl
+            INNER JOIN uploads src ON src.id = fl.source_upload_id
+            INNER JOIN uploads out ON out.id = fl.output_upload_id
+            LEFT JOIN functions f ON f.id = fl.function_id
+            WHERE fl.output_upload_id IN (SELECT id FROM ancestors)
+               OR fl.source_upload_id IN (SELECT id FROM descendants)
+            ORDER BY fl.created_at, fl.rowid
+            "#,
+            upload_id,
+            upload_id
+        )
+        .fetch_all(self.db)
+        .await
+    }
+
+    pub async fn dictionary(&self, upload_id: &str) -> sqlx::Result<DataDictionary> {
+        let rows = sqlx::query!(
+            r#"SELECT column_name as "column_name!", description, unit FROM column_dictionary WHERE upload_id = ? ORDER BY column_name"#,
+            upload_id
+        )
+        .fetch_all(self.db)
+        .await?;
+
+        Ok(DataDictionary {
+            columns: rows
+                .into_iter()
+                .map(|row| {
+                    (
+                        row.column_name,
+                        ColumnInfo {
+                            description: row.description,
+                            unit: row.unit,
+                        },
+                    )
+                })
+                .collect(),
+        })
+    }
+
+    /// Replace the whole dictionary of an upload
+    pub async fn set_dictionary(
+        &self,
+        upload_id: &str,
+        dictionary: &DataDictionary,
+    ) -> sqlx::Result<()> {
+        let mut tx = self.db.begin().await?;
+        sqlx::query!(
+            "DELETE FROM column_dictionary WHERE upload_id = ?",
+            upload_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        for (column_name, info) in &dictionary.columns {
+            sqlx::query!(
+                "INSERT INTO column_dictionary (upload_id, column_name, description, unit) VALUES (?, ?, ?, ?)",
+                upload_id,
+                column_name,
+                info.description,
+                info.unit
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await
+    }
+}
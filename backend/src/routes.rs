@@ -1,36 +1,147 @@
+use crate::access::{Requester, TagAccess};
+use crate::anomalies::{detect_table_anomalies, AnomalyQuery, AnomalyReport};
+use crate::archive::{
+    unique_entry_names, write_archive, write_archive_with, ArchiveChunk, ArchiveEntry,
+};
+use crate::array_inspector::{inspect_array_file, is_unsafe_serialization, ArrayInspection};
+use crate::capacity::{self, parse_concurrencies};
+use crate::custody::{request_signature, RequestOrigin};
+use crate::diagrams::DiagramFormat;
+use crate::executor::ComputeBackend;
+use crate::feeds::{render_ics, render_rss, FeedEntry};
 use crate::graph::DirectedGraph;
+use crate::job_stats::{normalize_label, summarize};
+use crate::manifest::{self, Source};
+use crate::media_info::{read_media_info, MediaInfo};
+use crate::mime_sniff::{detect_mime_type, effective_extension, SNIFF_BYTES};
 use crate::models::{
-    CreateFunction, CreateTag, DerivedFile, Function, Job, Tag, UpdateFunction, UpdateTag, Upload,
-    UploadResponse,
+    ArchiveRequest, AssignRequest, BulkTagRequest, BulkTagResult, BulkTriggers, ChainOfCustody,
+    ColumnInfo, ContentHit, ContentSearchResults, CopyUpload, CreateDataset, CreateFunction,
+    CreateJobAnnotation, CreatePipelineSnapshot, CreateRelease, CreateRemote, CreateReport,
+    CreateRetentionRule, CreateReviewQueue, CreateShareLink, CreateTag, CreateView, DataDictionary,
+    Dataset, DatasetDetail, DatasetMembers, DatasetStats, DerivedFile, Function,
+    FunctionPreviewRequest, ImportBatch, ImportBatchDetail, ImportRow, ImportedUpload, InputSlice,
+    Job, JobCompletion, Notification, NotificationList, PipelineSnapshot, PrecheckFunction,
+    PrecheckReport, QuarantinedUpload, Release, ReleaseVerification, Remote, RemoteImport,
+    ReportTemplate, RetentionPurge, RetentionRule, RetentionSweep, Review, ReviewItem, ReviewQueue,
+    SavedView, SearchHit, SearchResults, SetStorageQuota, ShareLink, SharedUpload, SnapshotRestore,
+    StorageUsage, SubmitReview, Tag, TagStorageUsage, TagUsage, TriggerRequest, UpdateDataset,
+    UpdateFunction, UpdateRelease, UpdateReport, UpdateRetentionRule, UpdateReviewQueue, UpdateTag,
+    UpdateUpload, UpdateView, Upload, UploadPage, UploadPrecheck, UploadResponse, UploadSelector,
+    WatchedFile,
+};
+use crate::orphans::{delete_orphans, scan_directory, MissingFile, OrphanReport, ORPHAN_GRACE};
+use crate::plot::{render_table_plot, validate_plot, PlotRequest};
+use crate::reports::{
+    render_report, report_extension, validate_template, FailedJob, JobSummary, ReportContext,
+    ReportInfo,
+};
+use crate::repos::{
+    DatasetRepo, FunctionRepo, ImportRepo, JobFilter, JobRepo, NewFunction, NewLineage, NewRelease,
+    ReleaseRepo, RemoteRepo, ReplicationRepo, ShareRepo, SnapshotRepo, Sort, SortKey, SortOrder,
+    StoredRelease, StoredSnapshot, StoredUpload, TagRepo, UploadFilter, UploadRepo,
+};
+use crate::scanner::ScanVerdict;
+use crate::scheduler::QueueSummary;
+use crate::script_analysis::{self, ScriptAnalysis};
+use crate::search::{match_expression, phrase_expression};
+use crate::services::{
+    add_estimates, add_notification, applied_extension_tag, browse_remote, cached_thumbnail,
+    chain_of_custody, change_settings, current_pipeline, dataset_stats, discard_quarantined,
+    enqueue_functions_for_upload, estimate_run, extension_tag_name, fail_job, fetch_remote_upload,
+    finish_job, get_quarantined, image_metadata, is_doi_like, lineage_diagram, list_quarantined,
+    matching_functions, mirror_status, pipeline_diagram, plain_upload_path, preview_function,
+    quarantine_file, read_upload, record_custody_event, record_upload_origin, register_job_outputs,
+    release_files, release_quarantined, reload_settings, remote_base_url, remote_upload,
+    restore_conflicts, restore_pipeline, run_function_on_slice, search_contents, sha256_hex,
+    sha256sums, storage_stats, store_upload, store_upload_file, sync_mirror,
+    trigger_functions_for_upload, trigger_functions_for_uploads, verify_release, JobOutput,
+    RemoteFetch, TagService, CHECKSUMS_NAME, MANIFEST_NAME, SHUTDOWN_MESSAGE,
+};
+use crate::snapshots::{self, Pipeline};
+use crate::sql_query::{
+    is_valid_table_name, run_sql, validate_quick_function, validate_sql, SqlEngine, SqlRequest,
+    SqlRows, SqlTable,
+};
+use crate::supervisor::TaskSummary;
+use crate::table_parser::{
+    compare_tables, pivot_table, plan_table_page, read_table_schema, sample_table,
+    validate_comparison, validate_pivot_request, validate_table_query, CompareRequest,
+    DeriveOperation, DeriveRequest, PivotRequest, ResampleRequest, RunTable, SampleQuery,
+    TableQuery, TableSchema, TableSlice, MAX_COMPARE_UPLOADS, MAX_RESAMPLE_PREVIEW_ROWS,
+};
+use crate::tag_expr::TagExpr;
+use crate::tag_policy::TagPolicy;
+use crate::text_preview::{
+    decode_text, syntax_hint, ContentQuery, TextContent, DEFAULT_PREVIEW_KB, MAX_PREVIEW_KB,
+};
+use crate::thumbnails::{
+    is_image_extension, thumbnail_names, ThumbnailQuery, DEFAULT_THUMBNAIL_SIZE, THUMBNAIL_SIZES,
 };
-use crate::table_parser::{get_table_preview as parse_table_preview, TablePreview, TableQuery};
+use crate::timestamps;
+use crate::triggers::{compile_condition, glob_match, TriggerCondition, UploadFacts};
+use crate::units::detect_units;
+use crate::watch_folders::{
+    list_files, FolderEntry, SettleTracker, WatchFolder, WatchFolderReport,
+};
+use crate::waveform::{
+    is_audio_extension, summarize_audio, WaveformQuery, WaveformSummary, DEFAULT_POINTS,
+};
+use crate::webdav::{file_href, http_date, render_multistatus, DavAccess, DavResource};
 use crate::AppState;
 use axum::{
-    extract::{Multipart, Path, Query, State},
-    http::{header, StatusCode},
-    response::Response,
-    routing::{delete, get, post},
+    body::Bytes,
+    extract::{
+        multipart::MultipartError, rejection::BytesRejection, ConnectInfo, DefaultBodyLimit,
+        Multipart, Path, Query, RawQuery, Request, State,
+    },
+    http::{header, HeaderMap, Method, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{any, delete, get, post, put},
     Json, Router,
 };
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::SocketAddr;
 use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::StreamExt;
 use uuid::Uuid;
 
-pub fn api_routes() -> Router<Arc<AppState>> {
+// Size of the chunks flushed to the client when streaming NDJSON previews
+const NDJSON_CHUNK_BYTES: usize = 64 * 1024;
+// Rows read from the file at a time when streaming a table page as NDJSON
+const NDJSON_BATCH_ROWS: usize = 10_000;
+
+pub fn api_routes(state: Arc<AppState>) -> Router<Arc<AppState>> {
     Router::new()
         .route("/health", get(health_check))
         .route("/tags", get(list_tags).post(create_tag))
         .route("/tags/:id", get(get_tag).put(update_tag).delete(delete_tag))
-        .route("/uploads", get(list_uploads).post(upload_file))
-        .route("/uploads/:id", get(get_upload).delete(delete_upload))
-        .route("/uploads/:id/download", get(download_file))
-        .route("/uploads/:id/table-preview", get(get_table_preview))
-        .route("/uploads/:id/tags", post(add_tags_to_upload))
-        .route("/uploads/:id/tags/:tag_id", delete(remove_tag_from_upload))
-        .route("/uploads/:id/derived", get(get_derived_files))
+        .route("/tags/:id/assign", post(assign_tag))
+        .route("/tags/:id/unassign", post(unassign_tag))
+        .route("/tags/:id/access", get(get_tag_access).put(set_tag_access))
+        .route("/config/tag-policy", get(get_tag_policy))
+        .merge(
+            Router::new()
+                .route("/uploads", get(list_uploads).post(upload_file))
+                .route("/uploads/raw", post(upload_raw))
+                .route("/jobs/:id/complete", post(complete_job))
+                .route_layer(upload_body_limit(&state)),
+        )
+        .route("/uploads/from-url", post(upload_from_url))
+        .route("/uploads/precheck", post(precheck_upload))
         .route(
-            "/uploads/:id/trigger/:function_id",
-            post(trigger_function_manually),
+            "/uploads/import-manifest",
+            get(list_import_batches).post(import_manifest),
         )
+        .route("/uploads/import-manifest/:id", get(get_import_batch))
+        .route("/search", get(search_uploads))
+        .route("/search/contents", get(search_upload_contents))
+        .route("/uploads/error-logs/purge", post(purge_error_logs))
+        .route("/uploads/archive", post(archive_uploads))
+        .merge(upload_routes(state))
         .route("/functions", get(list_functions).post(create_function))
         .route(
             "/functions/:id",
@@ -38,8 +149,228 @@ pub fn api_routes() -> Router<Arc<AppState>> {
                 .put(update_function)
                 .delete(delete_function),
         )
+        .route("/functions/analyze", post(analyze_function_script))
+        .route("/functions/:id/preview", post(preview_function_on_sample))
+        .route("/functions/:id/estimate", get(estimate_function_run))
         .route("/jobs", get(list_jobs))
         .route("/jobs/:id", get(get_job))
+        .route(
+            "/jobs/:id/annotations",
+            get(list_job_annotations).post(annotate_job),
+        )
+        .route(
+            "/jobs/:id/annotations/:annotation_id",
+            delete(delete_job_annotation),
+        )
+        .route("/compare", post(compare_uploads))
+        .route("/sql", post(run_sql_query))
+        .route("/views", get(list_views).post(create_view))
+        .route(
+            "/views/:id",
+            get(get_view).put(update_view).delete(delete_view),
+        )
+        .route("/views/:id/uploads", get(list_view_uploads))
+        .route("/reports", get(list_reports).post(create_report))
+        .route(
+            "/reports/:id",
+            get(get_report).put(update_report).delete(delete_report),
+        )
+        .route("/reports/:id/preview", get(preview_report))
+        .route("/reports/:id/render", post(render_report_upload))
+        .route(
+            "/review-queues",
+            get(list_review_queues).post(create_review_queue),
+        )
+        .route(
+            "/review-queues/:id",
+            get(get_review_queue)
+                .put(update_review_queue)
+                .delete(delete_review_queue),
+        )
+        .route("/review-queues/:id/items", get(list_review_items))
+        .route("/review-queues/:id/items/:upload_id", post(submit_review))
+        .route("/jobs/:id/assignee", put(assign_job))
+        .route("/notifications", get(list_notifications))
+        .route("/notifications/read-all", post(mark_all_notifications_read))
+        .route("/notifications/:id/read", post(mark_notification_read))
+        .route("/watch-folders", get(list_watch_folders))
+        .route("/quarantine", get(list_quarantine))
+        .route(
+            "/quarantine/:id",
+            get(get_quarantined_upload).delete(discard_quarantined_upload),
+        )
+        .route("/quarantine/:id/release", post(release_quarantined_upload))
+        .route(
+            "/retention/rules",
+            get(list_retention_rules).post(create_retention_rule),
+        )
+        .route(
+            "/retention/rules/:id",
+            get(get_retention_rule)
+                .put(update_retention_rule)
+                .delete(delete_retention_rule),
+        )
+        .route("/retention/sweep", post(run_retention_sweep))
+        .route("/retention/purges", get(list_retention_purges))
+        .route("/storage/usage", get(get_storage_usage))
+        .route("/stats/storage", get(get_storage_stats))
+        .route("/stats/jobs", get(get_job_stats))
+        .route("/stats/capacity", get(get_capacity))
+        .route("/pipeline/graph.dot", get(get_pipeline_dot))
+        .route("/pipeline/graph.mmd", get(get_pipeline_mermaid))
+        .route(
+            "/pipeline/snapshots",
+            get(list_pipeline_snapshots).post(create_pipeline_snapshot),
+        )
+        .route(
+            "/pipeline/snapshots/:id",
+            get(get_pipeline_snapshot).delete(delete_pipeline_snapshot),
+        )
+        .route("/pipeline/snapshots/:id/diff", get(diff_pipeline_snapshot))
+        .route("/releases", get(list_releases).post(create_release))
+        .route("/releases/:id", get(get_release).patch(update_release))
+        .route("/releases/:id/download", get(download_release))
+        .route("/releases/:id/verify", get(verify_release_files))
+        .route("/remotes", get(list_remotes).post(create_remote))
+        .route("/remotes/:id", get(get_remote).delete(delete_remote))
+        .route("/remotes/:id/uploads", get(browse_remote_uploads))
+        .route("/remotes/:id/uploads/:upload_id", get(get_remote_upload))
+        .route("/remotes/:id/import", post(import_remote_uploads))
+        .route("/datasets", get(list_datasets).post(create_dataset))
+        .route(
+            "/datasets/:id",
+            get(get_dataset)
+                .patch(update_dataset)
+                .delete(delete_dataset),
+        )
+        .route("/datasets/:id/uploads", post(add_dataset_uploads))
+        .route(
+            "/datasets/:id/uploads/:upload_id",
+            delete(remove_dataset_upload),
+        )
+        .route("/datasets/:id/tags", post(tag_dataset))
+        .route("/datasets/:id/download", get(download_dataset))
+        .route("/datasets/:id/stats", get(get_dataset_stats))
+        .route(
+            "/pipeline/snapshots/:id/restore",
+            post(restore_pipeline_snapshot),
+        )
+        .route(
+            "/storage/quotas/:tag_id",
+            put(set_storage_quota).delete(delete_storage_quota),
+        )
+        .route("/admin/orphans", get(list_orphans))
+        .route("/admin/orphans/cleanup", post(cleanup_orphans))
+        .route("/admin/tasks", get(list_tasks))
+        .route("/admin/job-queue", get(job_queue))
+        .route("/admin/warm-pool", get(warm_pool))
+        .route("/admin/mirror", get(get_mirror_status))
+        .route("/admin/mirror/sync", post(sync_mirror_now))
+        .route("/admin/settings", get(get_settings).patch(update_settings))
+        .route("/admin/reload", post(reload_config))
+        .route("/feeds/jobs.rss", get(jobs_rss_feed))
+        .route("/feeds/failures.rss", get(failures_rss_feed))
+        .route("/feeds/jobs.ics", get(jobs_ics_feed))
+}
+
+// The routes of one upload, none of which answer for an upload hidden from the requester
+fn upload_routes(state: Arc<AppState>) -> Router<Arc<AppState>> {
+    Router::new()
+        .route(
+            "/uploads/:id",
+            get(upload_detail)
+                .patch(update_upload)
+                .delete(delete_upload),
+        )
+        .route("/uploads/:id/copy", post(copy_upload))
+        .route(
+            "/uploads/:id/shares",
+            get(list_share_links).post(create_share_link),
+        )
+        .route("/uploads/:id/shares/:token", delete(revoke_share_link))
+        .route("/uploads/:id/custody", get(get_upload_custody))
+        .route("/uploads/:id/download", get(send_upload_file))
+        .route("/uploads/:id/table-preview", get(get_table_preview))
+        .route("/uploads/:id/content", get(get_upload_content))
+        .route("/uploads/:id/pivot", post(pivot_upload))
+        .route("/uploads/:id/resample", post(resample_upload))
+        .route("/uploads/:id/plot", post(plot_upload))
+        .route("/uploads/:id/derive", post(derive_upload))
+        .route("/uploads/:id/schema", get(get_table_schema))
+        .route("/uploads/:id/units", get(get_table_units))
+        .route("/uploads/:id/anomalies", get(get_table_anomalies))
+        .route(
+            "/uploads/:id/dictionary",
+            get(get_dictionary).put(update_dictionary),
+        )
+        .route(
+            "/uploads/:id/sample",
+            get(get_table_sample).post(materialize_table_sample),
+        )
+        .route("/uploads/:id/array-info", get(get_array_info))
+        .route("/uploads/:id/media-info", get(get_media_info))
+        .route("/uploads/:id/waveform", get(get_waveform))
+        .route("/uploads/:id/thumbnail", get(get_thumbnail))
+        .route("/uploads/:id/tags", post(add_tags_to_upload))
+        .route("/uploads/:id/tags/:tag_id", delete(remove_tag_from_upload))
+        .route("/uploads/:id/derived", get(get_derived_files))
+        .route("/uploads/:id/lineage.dot", get(get_lineage_dot))
+        .route("/uploads/:id/lineage.mmd", get(get_lineage_mermaid))
+        .route(
+            "/uploads/:id/trigger/:function_id",
+            post(trigger_function_manually),
+        )
+        .route("/uploads/:id/assignee", put(assign_upload))
+        .route_layer(middleware::from_fn_with_state(state, require_upload_access))
+}
+
+// Bodies over --max-upload-size-mb fail while being read, and handlers answer 413; only the
+// routes that take files get this limit, the others keep axum's default
+fn upload_body_limit(state: &AppState) -> DefaultBodyLimit {
+    DefaultBodyLimit::max(usize::try_from(state.max_upload_bytes).unwrap_or(usize::MAX))
+}
+
+// Error response with a human-readable message for the UI
+fn json_error(
+    status: StatusCode,
+    message: impl Into<String>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    (status, Json(serde_json::json!({ "error": message.into() })))
+}
+
+// A body over --max-upload-size-mb gets a 413 naming the limit; other multipart errors are
+// malformed requests
+fn multipart_error(state: &AppState, e: MultipartError) -> Response {
+    upload_body_error(state, e.status(), e.body_text())
+}
+
+fn upload_body_error(state: &AppState, status: StatusCode, message: String) -> Response {
+    if status == StatusCode::PAYLOAD_TOO_LARGE {
+        return json_error(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!(
+                "Upload exceeds the limit of {} MB",
+                state.max_upload_bytes / (1024 * 1024)
+            ),
+        )
+        .into_response();
+    }
+    json_error(status, message).into_response()
+}
+
+// A UNIQUE constraint violation (e.g. a duplicate name) is the client's fault
+fn conflict_or_internal(e: sqlx::Error) -> StatusCode {
+    if e.to_string().contains("UNIQUE constraint failed") {
+        StatusCode::CONFLICT
+    } else {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+// Services report failures as messages; handlers log them and answer 500
+fn internal_error(e: String) -> StatusCode {
+    tracing::error!("{}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
 }
 
 async fn health_check() -> Json<serde_json::Value> {
@@ -51,17 +382,14 @@ async fn health_check() -> Json<serde_json::Value> {
 
 // ============= TAGS =============
 
-async fn list_tags(State(state): State<Arc<AppState>>) -> Result<Json<Vec<Tag>>, StatusCode> {
-    let tags = sqlx::query_as!(
-        Tag,
-        r#"SELECT id as "id!", name as "name!", color as "color!", created_at as "created_at!" FROM tags ORDER BY created_at DESC"#
-    )
-    .fetch_all(&state.db)
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to fetch tags: {}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
+async fn list_tags(State(state): State<Arc<AppState>>) -> Result<Json<Vec<TagUsage>>, StatusCode> {
+    let tags = TagRepo::new(&state.db)
+        .list_with_usage()
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to fetch tags: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
 
     Ok(Json(tags))
 }
@@ -69,60 +397,62 @@ async fn list_tags(State(state): State<Arc<AppState>>) -> Result<Json<Vec<Tag>>,
 async fn create_tag(
     State(state): State<Arc<AppState>>,
     Json(payload): Json<CreateTag>,
-) -> Result<(StatusCode, Json<Tag>), StatusCode> {
-    // Validate tag name doesn't contain forbidden character
-    if payload.name.contains('~') {
-        return Err(StatusCode::BAD_REQUEST);
+) -> Result<Response, StatusCode> {
+    let settings = state.settings();
+    let policy = &settings.tag_policy;
+    if let Err(message) = policy.validate_name(&payload.name) {
+        return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response());
     }
-
-    let id = Uuid::new_v4().to_string();
-    let created_at = chrono::Utc::now().to_rfc3339();
-
-    sqlx::query!(
-        "INSERT INTO tags (id, name, color, created_at) VALUES (?, ?, ?, ?)",
-        id,
-        payload.name,
-        payload.color,
-        created_at
-    )
-    .execute(&state.db)
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to create tag: {}", e);
-        // Check if it's a unique constraint violation (duplicate name)
-        if e.to_string().contains("UNIQUE constraint failed") {
-            StatusCode::CONFLICT
-        } else {
-            StatusCode::INTERNAL_SERVER_ERROR
+    let color = match &payload.color {
+        Some(color) => {
+            if let Err(message) = policy.validate_color(color) {
+                return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response());
+            }
+            color.clone()
+        }
+        None => {
+            let used: Vec<String> = TagRepo::new(&state.db)
+                .list()
+                .await
+                .map_err(|e| internal_error(e.to_string()))?
+                .into_iter()
+                .map(|tag| tag.color)
+                .collect();
+            policy.next_color(&used)
         }
-    })?;
-
-    let tag = Tag {
-        id,
-        name: payload.name,
-        color: payload.color,
-        created_at,
     };
 
-    Ok((StatusCode::CREATED, Json(tag)))
+    let tag = TagService::new(&state)
+        .create(&payload.name, &color, tag_description(&payload.description))
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to create tag: {}", e);
+            conflict_or_internal(e)
+        })?;
+
+    Ok((StatusCode::CREATED, Json(tag)).into_response())
+}
+
+// Descriptions are free text; a blank one is no description
+fn tag_description(description: &Option<String>) -> Option<&str> {
+    description
+        .as_deref()
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
 }
 
 async fn get_tag(
     State(state): State<Arc<AppState>>,
     Path(id): Path<String>,
 ) -> Result<Json<Tag>, StatusCode> {
-    let tag = sqlx::query_as!(
-        Tag,
-        r#"SELECT id as "id!", name as "name!", color as "color!", created_at as "created_at!" FROM tags WHERE id = ?"#,
-        id
-    )
-    .fetch_optional(&state.db)
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to fetch tag: {}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?
-    .ok_or(StatusCode::NOT_FOUND)?;
+    let tag = TagRepo::new(&state.db)
+        .get(&id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to fetch tag: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
 
     Ok(Json(tag))
 }
@@ -131,430 +461,1505 @@ async fn update_tag(
     State(state): State<Arc<AppState>>,
     Path(id): Path<String>,
     Json(payload): Json<UpdateTag>,
-) -> Result<Json<Tag>, StatusCode> {
+) -> Result<Response, StatusCode> {
+    let tags = TagRepo::new(&state.db);
+
     // First check if tag exists
-    let existing = sqlx::query!(r#"SELECT name as "name!" FROM tags WHERE id = ?"#, id)
-        .fetch_optional(&state.db)
+    let existing = tags
+        .get(&id)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
         .ok_or(StatusCode::NOT_FOUND)?;
 
-    // Check if it's an extension tag - these cannot have their name changed
-    let is_extension_tag = existing.name.starts_with('.');
+    if let Some(color) = &payload.color {
+        if let Err(message) = state.settings().tag_policy.validate_color(color) {
+            return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response());
+        }
+    }
 
     if let Some(name) = &payload.name {
-        if is_extension_tag {
-            return Err(StatusCode::FORBIDDEN); // 403 Forbidden - cannot rename extension tags
+        // System tags such as extension tags are matched by name
+        if state.settings().tag_policy.is_system_tag(&existing.name) {
+            return Ok(
+                json_error(StatusCode::FORBIDDEN, "System tags cannot be renamed").into_response(),
+            );
         }
-        // Validate tag name doesn't contain forbidden character
-        if name.contains('~') {
-            return Err(StatusCode::BAD_REQUEST);
+        if let Err(message) = state.settings().tag_policy.validate_name(name) {
+            return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response());
         }
-        sqlx::query!("UPDATE tags SET name = ? WHERE id = ?", name, id)
-            .execute(&state.db)
+        TagService::new(&state)
+            .rename(&id, name)
             .await
-            .map_err(|e| {
-                // Check if it's a unique constraint violation (duplicate name)
-                if e.to_string().contains("UNIQUE constraint failed") {
-                    StatusCode::CONFLICT
-                } else {
-                    StatusCode::INTERNAL_SERVER_ERROR
-                }
-            })?;
+            .map_err(conflict_or_internal)?;
     }
 
     if let Some(color) = &payload.color {
-        sqlx::query!("UPDATE tags SET color = ? WHERE id = ?", color, id)
-            .execute(&state.db)
+        tags.set_color(&id, color)
             .await
             .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     }
 
-    let tag = sqlx::query_as!(
-        Tag,
-        r#"SELECT id as "id!", name as "name!", color as "color!", created_at as "created_at!" FROM tags WHERE id = ?"#,
-        id
-    )
-    .fetch_one(&state.db)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if payload.description.is_some() {
+        tags.set_description(&id, tag_description(&payload.description))
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
 
-    Ok(Json(tag))
+    let tag = tags
+        .get(&id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(tag).into_response())
+}
+
+async fn get_tag_access(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<TagAccess>, StatusCode> {
+    let tags = TagRepo::new(&state.db);
+    tags.get(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let access = tags
+        .access(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?;
+    Ok(Json(access))
+}
+
+// Restrict the uploads of a tag to some users and roles, or lift the restriction with empty
+// lists. Only those a tag is restricted to can change who else it is restricted to.
+async fn set_tag_access(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    Json(payload): Json<TagAccess>,
+) -> Result<Response, StatusCode> {
+    let access = match payload.normalized() {
+        Ok(access) => access,
+        Err(message) => return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response()),
+    };
+    let tags = TagRepo::new(&state.db);
+    tags.get(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let current = tags
+        .access(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?;
+    if !Requester::from_headers(&headers).is_allowed(&current) {
+        return Ok(json_error(
+            StatusCode::FORBIDDEN,
+            "Only the users and roles the tag is restricted to can change its access",
+        )
+        .into_response());
+    }
+    tags.set_access(&id, &access)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?;
+    Ok(Json(access).into_response())
+}
+
+// The uploads a bulk tag request selects, each once, or the response refusing it
+async fn bulk_tag_selection(
+    state: &AppState,
+    requester: &Requester,
+    request: &BulkTagRequest,
+) -> Result<Result<Vec<StoredUpload>, Response>, StatusCode> {
+    if request.upload_ids.is_empty() && request.filter.is_none() {
+        return Ok(Err(json_error(
+            StatusCode::BAD_REQUEST,
+            "Provide upload_ids, a filter, or both",
+        )
+        .into_response()));
+    }
+    let repo = UploadRepo::new(&state.db);
+    let mut selected = Vec::new();
+    let mut unknown = Vec::new();
+    for id in &request.upload_ids {
+        let upload = match check_upload_access(state, requester, id).await {
+            Ok(()) => repo
+                .get(id)
+                .await
+                .map_err(|e| internal_error(e.to_string()))?,
+            Err(StatusCode::NOT_FOUND) => None,
+            Err(status) => return Err(status),
+        };
+        match upload {
+            Some(upload) => selected.push(upload),
+            None => unknown.push(id.as_str()),
+        }
+    }
+    if !unknown.is_empty() {
+        return Ok(Err(json_error(
+            StatusCode::NOT_FOUND,
+            format!("Unknown upload IDs: {}", unknown.join(", ")),
+        )
+        .into_response()));
+    }
+    if let Some(selector) = &request.filter {
+        match filter_uploads(state, requester, selector).await? {
+            Ok(uploads) => selected.extend(uploads),
+            Err(response) => return Ok(Err(response)),
+        }
+    }
+    let mut seen = HashSet::new();
+    selected.retain(|upload| seen.insert(upload.id.clone()));
+    Ok(Ok(selected))
+}
+
+// Tag many uploads at once. Each newly tagged upload goes through hooks and its audit trail
+// as with `POST /uploads/:id/tags`; functions are triggered as the request asks.
+async fn assign_tag(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    Json(request): Json<BulkTagRequest>,
+) -> Result<Response, StatusCode> {
+    let tags = TagRepo::new(&state.db);
+    let tag = tags
+        .get(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let uploads =
+        match bulk_tag_selection(&state, &Requester::from_headers(&headers), &request).await? {
+            Ok(uploads) => uploads,
+            Err(response) => return Ok(response),
+        };
+
+    let origin = request_origin(&headers, peer);
+    let mut tagged = Vec::new();
+    for upload in &uploads {
+        if !tags
+            .tag_upload(&upload.id, &tag.id)
+            .await
+            .map_err(|e| internal_error(e.to_string()))?
+        {
+            continue;
+        }
+        state
+            .hooks
+            .tagged(&state, upload, std::slice::from_ref(&tag.id))
+            .await;
+        record_custody_event(&state, &upload.id, "tagged", Some(&tag.name), &origin).await;
+        if request.triggers == BulkTriggers::Each {
+            trigger_functions_for_upload(state.clone(), upload.id.clone());
+        }
+        tagged.push(upload.id.clone());
+    }
+    tracing::info!(
+        "🏷️ Tagged {} of {} upload(s) with {}",
+        tagged.len(),
+        uploads.len(),
+        tag.name
+    );
+
+    let changed = tagged.len();
+    if request.triggers == BulkTriggers::Batch && !tagged.is_empty() {
+        trigger_functions_for_uploads(state.clone(), tagged);
+    }
+    Ok(Json(BulkTagResult {
+        tag_id: tag.id,
+        matched: uploads.len(),
+        changed,
+        triggers: request.triggers,
+    })
+    .into_response())
+}
+
+// Take a tag off many uploads at once; like removing it from one upload, nothing is triggered
+async fn unassign_tag(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    Json(request): Json<BulkTagRequest>,
+) -> Result<Response, StatusCode> {
+    let tags = TagRepo::new(&state.db);
+    let tag = tags
+        .get(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let uploads =
+        match bulk_tag_selection(&state, &Requester::from_headers(&headers), &request).await? {
+            Ok(uploads) => uploads,
+            Err(response) => return Ok(response),
+        };
+
+    let origin = request_origin(&headers, peer);
+    let mut changed = 0;
+    for upload in &uploads {
+        if tags
+            .untag_upload(&upload.id, &tag.id)
+            .await
+            .map_err(|e| internal_error(e.to_string()))?
+        {
+            record_custody_event(&state, &upload.id, "untagged", Some(&tag.name), &origin).await;
+            changed += 1;
+        }
+    }
+    tracing::info!(
+        "🏷️ Untagged {} of {} upload(s) from {}",
+        changed,
+        uploads.len(),
+        tag.name
+    );
+    Ok(Json(BulkTagResult {
+        tag_id: tag.id,
+        matched: uploads.len(),
+        changed,
+        triggers: BulkTriggers::None,
+    })
+    .into_response())
+}
+
+// The tag naming rules, so UIs can check names before sending them
+async fn get_tag_policy(State(state): State<Arc<AppState>>) -> Json<TagPolicy> {
+    Json(state.settings().tag_policy.clone())
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct DeleteTagQuery {
+    #[serde(default)]
+    force: bool,
 }
 
+// A tag still on uploads is refused with 409, unless `force` is given: then it is taken off
+// the uploads and functions using it as well, and what that removed is reported.
 async fn delete_tag(
     State(state): State<Arc<AppState>>,
     Path(id): Path<String>,
-) -> Result<StatusCode, StatusCode> {
+    Query(query): Query<DeleteTagQuery>,
+) -> Result<Response, StatusCode> {
+    if query.force {
+        return match TagService::new(&state)
+            .delete_with_usages(&id)
+            .await
+            .map_err(|e| internal_error(e.to_string()))?
+        {
+            Some(deletion) => Ok(Json(deletion).into_response()),
+            None => Err(StatusCode::NOT_FOUND),
+        };
+    }
+
+    let tags = TagRepo::new(&state.db);
+
     // Check if tag is in use
-    let usage_count = sqlx::query!(
-        "SELECT COUNT(*) as count FROM upload_tags WHERE tag_id = ?",
-        id
-    )
-    .fetch_one(&state.db)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let usage_count = tags
+        .usage_count(&id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    if usage_count.count > 0 {
+    if usage_count > 0 {
         return Err(StatusCode::CONFLICT); // 409 Conflict - tag is in use
     }
 
-    let result = sqlx::query!("DELETE FROM tags WHERE id = ?", id)
-        .execute(&state.db)
+    if !TagService::new(&state)
+        .delete(&id)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    if result.rows_affected() == 0 {
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    {
         return Err(StatusCode::NOT_FOUND);
     }
 
-    Ok(StatusCode::NO_CONTENT)
+    Ok(StatusCode::NO_CONTENT.into_response())
 }
 
 // ============= UPLOADS =============
 
+// Store one or more "file" parts, applying the "tags" part to every file. A single file is
+// answered with its UploadResponse, several files with an array in the order they were sent.
 async fn upload_file(
     State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    peer: Option<ConnectInfo<SocketAddr>>,
     mut multipart: Multipart,
-) -> Result<(StatusCode, Json<UploadResponse>), StatusCode> {
-    let mut file_data: Option<Vec<u8>> = None;
-    let mut original_filename: Option<String> = None;
-    let mut mime_type: Option<String> = None;
+) -> Result<Response, StatusCode> {
+    let origin = request_origin(&headers, peer);
+    let signature = match request_signature(&headers) {
+        Ok(signature) => signature,
+        Err(message) => return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response()),
+    };
+    let mut files: Vec<(String, Option<String>, Vec<u8>)> = Vec::new();
     let mut tag_ids: Vec<String> = Vec::new();
 
-    while let Some(field) = multipart.next_field().await.unwrap() {
-        let name = field.name().unwrap().to_string();
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => return Ok(multipart_error(&state, e)),
+        };
 
-        match name.as_str() {
-            "file" => {
-                original_filename = field.file_name().map(|s| s.to_string());
-                mime_type = field.content_type().map(|s| s.to_string());
-                file_data = Some(field.bytes().await.unwrap().to_vec());
+        match field.name() {
+            Some("file") => {
+                let original_filename = field
+                    .file_name()
+                    .map(|s| s.to_string())
+                    .ok_or(StatusCode::BAD_REQUEST)?;
+                let mime_type = field.content_type().map(|s| s.to_string());
+                let file_data = match field.bytes().await {
+                    Ok(data) => data.to_vec(),
+                    Err(e) => return Ok(multipart_error(&state, e)),
+                };
+                files.push((original_filename, mime_type, file_data));
             }
-            "tags" => {
-                let tags_str = field.text().await.unwrap();
+            Some("tags") => {
+                let tags_str = match field.text().await {
+                    Ok(text) => text,
+                    Err(e) => return Ok(multipart_error(&state, e)),
+                };
                 tag_ids = serde_json::from_str(&tags_str).unwrap_or_default();
             }
             _ => {}
         }
     }
 
-    let file_data = file_data.ok_or(StatusCode::BAD_REQUEST)?;
-    let original_filename = original_filename.ok_or(StatusCode::BAD_REQUEST)?;
-
-    let id = Uuid::new_v4().to_string();
-    let filename = format!("{}_{}", id, original_filename);
-    let file_path = format!("uploads/{}", filename);
-    let file_size = file_data.len() as i64;
-    let created_at = chrono::Utc::now().to_rfc3339();
+    if files.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if signature.is_some() && files.len() > 1 {
+        return Ok(json_error(
+            StatusCode::BAD_REQUEST,
+            "A signature covers a single file; upload signed files one at a time",
+        )
+        .into_response());
+    }
 
-    // Save file to disk
-    tokio::fs::write(&file_path, file_data)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let contents: Vec<(&str, &[u8])> = files
+        .iter()
+        .map(|(name, _, data)| (name.as_str(), data.as_slice()))
+        .collect();
+    if let Some(message) = check_storage_quota(&state, &contents, &tag_ids).await? {
+        return Ok(json_error(StatusCode::INSUFFICIENT_STORAGE, message).into_response());
+    }
+    let received: Vec<(&str, Option<&str>, &[u8])> = files
+        .iter()
+        .map(|(name, mime_type, data)| (name.as_str(), mime_type.as_deref(), data.as_slice()))
+        .collect();
+    if let Some(refused) = screen_uploads(&state, &received, &tag_ids).await? {
+        return Ok(refused);
+    }
 
-    // Save to database
-    sqlx::query!(
-        "INSERT INTO uploads (id, filename, original_filename, file_size, mime_type, created_at) VALUES (?, ?, ?, ?, ?, ?)",
-        id,
-        filename,
-        original_filename,
-        file_size,
-        mime_type,
-        created_at
+    let single = files.len() == 1;
+    let mut uploads = Vec::with_capacity(files.len());
+    for (original_filename, mime_type, file_data) in files {
+        let upload = store_upload(
+            &state,
+            original_filename,
+            file_data,
+            mime_type,
+            tag_ids.clone(),
+        )
+        .await
+        .map_err(internal_error)?;
+        record_upload_origin(
+            &state,
+            &upload.id,
+            "api",
+            None,
+            &origin,
+            signature.as_deref(),
+        )
+        .await;
+        uploads.push(upload);
+    }
+
+    if single {
+        let upload = uploads.remove(0);
+        let location = jobs_location(&upload.id);
+        Ok((StatusCode::CREATED, location, Json(upload)).into_response())
+    } else {
+        Ok((StatusCode::CREATED, Json(uploads)).into_response())
+    }
+}
+
+const FILENAME_HEADER: &str = "x-datalab-filename";
+const TAGS_HEADER: &str = "x-datalab-tags";
+const TAG_NAMES_HEADER: &str = "x-datalab-tag-names";
+
+/// Name and tags of a `POST /uploads/raw`; each can also be sent as a header, which the
+/// query parameter overrides
+#[derive(Debug, serde::Deserialize)]
+struct RawUploadQuery {
+    filename: Option<String>,
+    tags: Option<String>,      // comma-separated tag IDs
+    tag_names: Option<String>, // the same, by name
+}
+
+// A header value as text; percent-decoded, so names that are not ASCII can be sent
+fn decoded_header(headers: &HeaderMap, name: &str) -> Option<String> {
+    let value = headers.get(name)?.to_str().ok()?;
+    Some(
+        percent_encoding::percent_decode_str(value)
+            .decode_utf8_lossy()
+            .to_string(),
     )
-    .execute(&state.db)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+}
 
-    // Extract file extension and create/find extension tag
-    if let Some(extension) = original_filename.rsplit('.').next() {
-        if !extension.is_empty() && extension != original_filename {
-            let ext_tag_name = format!(".{}", extension.to_lowercase());
+// Store the request body as a file, for `curl --data-binary @file` and scripts that cannot
+// build multipart requests. The name comes from the query, `X-DataLab-Filename` or
+// `Content-Disposition`; tags from `tags`/`tag_names` or their `X-DataLab-*` headers.
+async fn upload_raw(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<RawUploadQuery>,
+    headers: HeaderMap,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    body: Result<Bytes, BytesRejection>,
+) -> Result<Response, StatusCode> {
+    let body = match body {
+        Ok(body) => body,
+        Err(e) => return Ok(upload_body_error(&state, e.status(), e.body_text())),
+    };
+    let signature = match request_signature(&headers) {
+        Ok(signature) => signature,
+        Err(message) => return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response()),
+    };
+    let Some(original_filename) = query
+        .filename
+        .or_else(|| decoded_header(&headers, FILENAME_HEADER))
+        .or_else(|| content_disposition_filename(&headers))
+        .map(|name| name.trim().to_string())
+    else {
+        return Ok(json_error(
+            StatusCode::BAD_REQUEST,
+            "Name the file with ?filename= or an X-DataLab-Filename header",
+        )
+        .into_response());
+    };
+    if !is_valid_filename(&original_filename)
+        || original_filename == "."
+        || original_filename == ".."
+    {
+        return Ok(json_error(
+            StatusCode::BAD_REQUEST,
+            "Filename must not be empty or contain path separators",
+        )
+        .into_response());
+    }
+    if body.is_empty() {
+        return Ok(json_error(StatusCode::BAD_REQUEST, "Request body is empty").into_response());
+    }
 
-            // Try to find existing extension tag
-            let existing_tag = sqlx::query!(
-                r#"SELECT id as "id!" FROM tags WHERE name = ?"#,
-                ext_tag_name
-            )
-            .fetch_optional(&state.db)
+    // Every tag must exist; a typo in a script should not store the file untagged
+    let mut tag_ids = comma_list(
+        query
+            .tags
+            .or_else(|| decoded_header(&headers, TAGS_HEADER))
+            .as_deref(),
+    );
+    let tag_repo = TagRepo::new(&state.db);
+    for tag_id in &tag_ids {
+        if tag_repo
+            .get(tag_id)
             .await
-            .ok()
-            .flatten();
+            .map_err(|e| internal_error(e.to_string()))?
+            .is_none()
+        {
+            return Ok(
+                json_error(StatusCode::BAD_REQUEST, format!("Unknown tag: {}", tag_id))
+                    .into_response(),
+            );
+        }
+    }
+    let tag_names = query
+        .tag_names
+        .or_else(|| decoded_header(&headers, TAG_NAMES_HEADER));
+    let tag_service = TagService::new(&state);
+    for name in comma_list(tag_names.as_deref()) {
+        match tag_service
+            .id_by_name(&name)
+            .await
+            .map_err(|e| internal_error(e.to_string()))?
+        {
+            Some(tag_id) if !tag_ids.contains(&tag_id) => tag_ids.push(tag_id),
+            Some(_) => {}
+            None => {
+                return Ok(
+                    json_error(StatusCode::BAD_REQUEST, format!("Unknown tag: {}", name))
+                        .into_response(),
+                )
+            }
+        }
+    }
 
-            let ext_tag_id = if let Some(tag) = existing_tag {
-                tag.id
-            } else {
-                // Create new extension tag with a default color (gray)
-                let new_tag_id = Uuid::new_v4().to_string();
-                let tag_created_at = chrono::Utc::now().to_rfc3339();
-                let default_color = "#6b7280"; // gray-500
-
-                sqlx::query!(
-                    "INSERT INTO tags (id, name, color, created_at) VALUES (?, ?, ?, ?)",
-                    new_tag_id,
-                    ext_tag_name,
-                    default_color,
-                    tag_created_at
+    // curl sends --data-binary as a form unless told otherwise; that says nothing about the file
+    let mime_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.split(';').next().unwrap_or(v).trim().to_string())
+        .filter(|v| !v.is_empty() && v != "application/x-www-form-urlencoded");
+
+    let file_data = body.to_vec();
+    let content = [(original_filename.as_str(), file_data.as_slice())];
+    if let Some(message) = check_storage_quota(&state, &content, &tag_ids).await? {
+        return Ok(json_error(StatusCode::INSUFFICIENT_STORAGE, message).into_response());
+    }
+    let received = [(
+        original_filename.as_str(),
+        mime_type.as_deref(),
+        file_data.as_slice(),
+    )];
+    if let Some(refused) = screen_uploads(&state, &received, &tag_ids).await? {
+        return Ok(refused);
+    }
+    let upload = store_upload(&state, original_filename, file_data, mime_type, tag_ids)
+        .await
+        .map_err(internal_error)?;
+    let origin = request_origin(&headers, peer);
+    record_upload_origin(
+        &state,
+        &upload.id,
+        "api",
+        None,
+        &origin,
+        signature.as_deref(),
+    )
+    .await;
+    let location = jobs_location(&upload.id);
+    Ok((StatusCode::CREATED, location, Json(upload)).into_response())
+}
+
+// Pass files from a client (name, MIME type, content) past the `on_received` hooks. If any
+// is flagged, the flagged ones are quarantined, none are stored, and the 422 to answer with
+// is returned.
+async fn screen_uploads(
+    state: &Arc<AppState>,
+    files: &[(&str, Option<&str>, &[u8])],
+    tag_ids: &[String],
+) -> Result<Option<Response>, StatusCode> {
+    let mut quarantined = Vec::new();
+    for (original_filename, mime_type, data) in files {
+        let verdict = state.hooks.received(state, original_filename, data).await;
+        if verdict != ScanVerdict::Clean {
+            quarantined.push(
+                quarantine_file(
+                    state,
+                    original_filename,
+                    *mime_type,
+                    data,
+                    tag_ids,
+                    &verdict,
                 )
-                .execute(&state.db)
                 .await
-                .ok();
+                .map_err(internal_error)?,
+            );
+        }
+    }
+    if quarantined.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(quarantined_response(&quarantined)))
+}
 
-                new_tag_id
-            };
+// The same for a file streamed to disk; it is only read into memory when flagged
+async fn screen_staged_upload(
+    state: &Arc<AppState>,
+    original_filename: &str,
+    staged: &std::path::Path,
+) -> Result<Option<Response>, StatusCode> {
+    let verdict = state
+        .hooks
+        .received_file(state, original_filename, staged)
+        .await;
+    if verdict == ScanVerdict::Clean {
+        return Ok(None);
+    }
+    let data = tokio::fs::read(staged)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?;
+    let quarantined = quarantine_file(state, original_filename, None, &data, &[], &verdict)
+        .await
+        .map_err(internal_error)?;
+    Ok(Some(quarantined_response(&[quarantined])))
+}
 
-            // Add extension tag to the upload
-            let _ = sqlx::query!(
-                "INSERT INTO upload_tags (upload_id, tag_id) VALUES (?, ?)",
-                id,
-                ext_tag_id
-            )
-            .execute(&state.db)
-            .await;
+fn quarantined_response(quarantined: &[QuarantinedUpload]) -> Response {
+    let flagged: Vec<String> = quarantined
+        .iter()
+        .map(|q| format!("{} ({})", q.original_filename, q.finding))
+        .collect();
+    let body = serde_json::json!({
+        "error": format!("Upload refused, file(s) quarantined: {}", flagged.join(", ")),
+        "quarantined": quarantined,
+    });
+    (StatusCode::UNPROCESSABLE_ENTITY, Json(body)).into_response()
+}
+
+// Tell a client whether a file would be accepted, and what it would trigger, before it
+// sends the content. Only the name and claimed type are known, so the extension tag goes by
+// the name, and malware scans cannot be predicted.
+async fn precheck_upload(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<UploadPrecheck>,
+) -> Result<Json<PrecheckReport>, StatusCode> {
+    let settings = state.settings();
+    let mut problems = Vec::new();
+    let name = request.filename.trim();
+    if !is_valid_filename(name) || name == "." || name == ".." {
+        problems.push("Filename must not be empty or contain path separators".to_string());
+    }
+    if request.size < 0 {
+        problems.push("Size must not be negative".to_string());
+    }
+    let (max_bytes, limit) = if request.from_url {
+        (settings.url_max_bytes, "download limit")
+    } else {
+        (state.max_upload_bytes, "upload limit")
+    };
+    if request.size.max(0) as u64 > max_bytes {
+        problems.push(format!(
+            "File exceeds the {} of {} MB",
+            limit,
+            max_bytes / (1024 * 1024)
+        ));
+    }
+    if request.from_url {
+        let mime_type = request.mime_type.as_deref().unwrap_or("");
+        if !is_allowed_type(&settings.values.url_allowed_types, mime_type) {
+            problems.push(format!(
+                "Content type {} is not allowed",
+                request.mime_type.as_deref().unwrap_or("(none)")
+            ));
         }
     }
 
-    // Add user-selected tags if provided
-    for tag_id in tag_ids {
-        let _ = sqlx::query!(
-            "INSERT OR IGNORE INTO upload_tags (upload_id, tag_id) VALUES (?, ?)",
-            id,
-            tag_id
-        )
-        .execute(&state.db)
-        .await;
+    let tag_repo = TagRepo::new(&state.db);
+    let mut tags = Vec::new();
+    for tag_id in &request.tags {
+        match tag_repo
+            .get(tag_id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        {
+            Some(tag) => tags.push(tag),
+            None => problems.push(format!("Unknown tag: {}", tag_id)),
+        }
     }
 
-    // Trigger function execution in the background
-    let upload_id_clone = id.clone();
-    let state_clone = state.clone();
-    trigger_functions_for_upload(state_clone, upload_id_clone);
+    let extension_tag = applied_extension_tag(&state.settings().tag_policy, name, None);
+    let files = [(extension_tag.clone(), request.size.max(0))];
+    if let Some(message) = check_quota_for_sizes(&state, &files, &request.tags).await? {
+        problems.push(message);
+    }
 
-    Ok((
-        StatusCode::CREATED,
-        Json(UploadResponse {
-            id,
-            filename,
-            original_filename,
-            file_size,
-            mime_type,
-            created_at,
-        }),
-    ))
+    // Uploads carry their extension tag by the time triggers are evaluated
+    let mut facts = UploadFacts {
+        filename: name.to_string(),
+        mime_type: request.mime_type.clone(),
+        file_size: request.size,
+        tag_ids: tags.iter().map(|tag| tag.id.clone()).collect(),
+        tag_names: tags.into_iter().map(|tag| tag.name).collect(),
+        source_function_id: None,
+        derived: false,
+    };
+    if let Some(extension_tag) = &extension_tag {
+        if let Some(tag_id) = TagService::new(&state)
+            .id_by_name(extension_tag)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        {
+            facts.tag_ids.insert(tag_id);
+        }
+        facts.tag_names.insert(extension_tag.clone());
+    }
+    let mut functions = Vec::new();
+    for function in matching_functions(&state, &facts).await {
+        let estimate = estimate_run(&state, &function.id, request.size.max(0))
+            .await
+            .map_err(internal_error)?;
+        functions.push(PrecheckFunction {
+            id: function.id,
+            name: function.name,
+            estimate,
+        });
+    }
+
+    Ok(Json(PrecheckReport {
+        accepted: problems.is_empty(),
+        problems,
+        extension_tag,
+        functions,
+    }))
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct FromUrlRequest {
+    url: String,
+    filename: Option<String>, // defaults to the server's suggested name or the last path segment
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+// Name from `Content-Disposition: attachment; filename="..."`, if the server sent one
+fn content_disposition_filename(headers: &HeaderMap) -> Option<String> {
+    let value = headers.get(header::CONTENT_DISPOSITION)?.to_str().ok()?;
+    value.split(';').find_map(|part| {
+        let name = part.trim().strip_prefix("filename=")?;
+        Some(name.trim_matches('"').to_string())
+    })
+}
+
+// `text/*` matches any text type; parameters such as charset are ignored
+fn is_allowed_type(allowed: &[String], mime_type: &str) -> bool {
+    allowed.is_empty()
+        || allowed
+            .iter()
+            .any(|pattern| match pattern.strip_suffix("/*") {
+                Some(prefix) => mime_type
+                    .split_once('/')
+                    .is_some_and(|(kind, _)| kind.eq_ignore_ascii_case(prefix)),
+                None => pattern.eq_ignore_ascii_case(mime_type),
+            })
 }
 
-async fn list_uploads(State(state): State<Arc<AppState>>) -> Result<Json<Vec<Upload>>, StatusCode> {
-    #[derive(sqlx::FromRow)]
-    struct UploadRow {
-        id: String,
-        filename: String,
-        original_filename: String,
-        file_size: i64,
-        mime_type: Option<String>,
-        created_at: String,
+// Download a file server-side and register it like a normal upload
+async fn upload_from_url(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    Json(request): Json<FromUrlRequest>,
+) -> Result<Response, StatusCode> {
+    let url = match reqwest::Url::parse(request.url.trim()) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => url,
+        _ => {
+            return Ok(
+                json_error(StatusCode::BAD_REQUEST, "URL must be an http(s) URL").into_response(),
+            );
+        }
+    };
+    if request
+        .filename
+        .as_deref()
+        .is_some_and(|name| !is_valid_filename(name))
+    {
+        return Ok(json_error(StatusCode::BAD_REQUEST, "Invalid filename").into_response());
     }
 
-    let uploads = sqlx::query_as!(
-        UploadRow,
-        r#"SELECT id as "id!", filename as "filename!", original_filename as "original_filename!", file_size as "file_size!", mime_type, created_at as "created_at!" FROM uploads ORDER BY created_at DESC"#
+    let UrlDownload {
+        data: file_data,
+        mime_type,
+        filename,
+    } = match download_url(&state, &url).await {
+        Ok(download) => download,
+        Err((status, message)) => return Ok(json_error(status, message).into_response()),
+    };
+    let original_filename = request.filename.unwrap_or(filename);
+    let content = [(original_filename.as_str(), file_data.as_slice())];
+    if let Some(message) = check_storage_quota(&state, &content, &request.tags).await? {
+        return Ok(json_error(StatusCode::INSUFFICIENT_STORAGE, message).into_response());
+    }
+    let received = [(
+        original_filename.as_str(),
+        mime_type.as_deref(),
+        file_data.as_slice(),
+    )];
+    if let Some(refused) = screen_uploads(&state, &received, &request.tags).await? {
+        return Ok(refused);
+    }
+    let upload = store_upload(
+        &state,
+        original_filename,
+        file_data,
+        mime_type,
+        request.tags,
     )
-    .fetch_all(&state.db)
     .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    .map_err(internal_error)?;
+    let origin = request_origin(&headers, peer);
+    record_upload_origin(&state, &upload.id, "url", Some(url.as_str()), &origin, None).await;
+    let location = jobs_location(&upload.id);
+    Ok((StatusCode::CREATED, location, Json(upload)).into_response())
+}
 
-    // Fetch tags and lineage for each upload
-    let mut result = Vec::new();
-    for upload_row in uploads {
-        let tags = sqlx::query_as!(
-            Tag,
-            r#"SELECT t.id as "id!", t.name as "name!", t.color as "color!", t.created_at as "created_at!"
-               FROM tags t
-               INNER JOIN upload_tags ut ON t.id = ut.tag_id
-               WHERE ut.upload_id = ?"#,
-            upload_row.id
-        )
-        .fetch_all(&state.db)
-        .await
-        .unwrap_or_default();
+/// A file fetched from a URL, within the download limit and of an allowed type
+struct UrlDownload {
+    data: Vec<u8>,
+    mime_type: Option<String>,
+    filename: String, // from Content-Disposition or the URL path; "download" without either
+}
 
-        // Check for lineage
-        let lineage = sqlx::query!(
-            r#"
-            SELECT 
-                fl.success as "success!",
-                fl.source_upload_id as "source_upload_id!",
-                fl.function_id as "function_id!",
-                u.original_filename as "source_filename!",
-                f.name as "function_name!"
-            FROM file_lineage fl
-            INNER JOIN uploads u ON fl.source_upload_id = u.id
-            INNER JOIN functions f ON fl.function_id = f.id
-            WHERE fl.output_upload_id = ?
-            "#,
-            upload_row.id
-        )
-        .fetch_optional(&state.db)
-        .await
-        .ok()
-        .flatten()
-        .map(|row| crate::models::FileLineageInfo {
-            source_upload_id: row.source_upload_id,
-            source_filename: row.source_filename,
-            function_id: row.function_id,
-            function_name: row.function_name,
-            success: row.success != 0,
-        });
+// Download for `POST /uploads/from-url` and import manifests; Err has the status to answer with
+// and why
+async fn download_url(
+    state: &AppState,
+    url: &reqwest::Url,
+) -> Result<UrlDownload, (StatusCode, String)> {
+    let settings = state.settings();
+    let bad_gateway = |message: String| Err((StatusCode::BAD_GATEWAY, message));
+    let too_large = || {
+        Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!(
+                "File exceeds the download limit of {} MB",
+                settings.url_max_bytes / (1024 * 1024)
+            ),
+        ))
+    };
 
-        result.push(Upload {
-            id: upload_row.id,
-            filename: upload_row.filename,
-            original_filename: upload_row.original_filename,
-            file_size: upload_row.file_size,
-            mime_type: upload_row.mime_type,
-            created_at: upload_row.created_at,
-            tags,
-            lineage,
-        });
+    let mut response = match state.http.get(url.clone()).send().await {
+        Ok(response) => response,
+        Err(e) => return bad_gateway(format!("Failed to download {}: {}", url, e)),
+    };
+    if !response.status().is_success() {
+        return bad_gateway(format!("{} responded with {}", url, response.status()));
     }
 
-    Ok(Json(result))
+    let mime_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.split(';').next().unwrap_or(v).trim().to_string());
+    if !is_allowed_type(
+        &settings.values.url_allowed_types,
+        mime_type.as_deref().unwrap_or(""),
+    ) {
+        return Err((
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            format!(
+                "Content type {} is not allowed",
+                mime_type.as_deref().unwrap_or("(none)")
+            ),
+        ));
+    }
+    if response
+        .content_length()
+        .is_some_and(|length| length > settings.url_max_bytes)
+    {
+        return too_large();
+    }
+
+    let filename = content_disposition_filename(response.headers())
+        .or_else(|| {
+            let segment = url.path_segments()?.next_back()?;
+            Some(
+                percent_encoding::percent_decode_str(segment)
+                    .decode_utf8_lossy()
+                    .to_string(),
+            )
+        })
+        .filter(|name| is_valid_filename(name))
+        .unwrap_or_else(|| "download".to_string());
+
+    // The length header is optional (or wrong), so keep counting while reading
+    let mut data = Vec::new();
+    loop {
+        match response.chunk().await {
+            Ok(Some(chunk)) => {
+                if (data.len() + chunk.len()) as u64 > settings.url_max_bytes {
+                    return too_large();
+                }
+                data.extend_from_slice(&chunk);
+            }
+            Ok(None) => break,
+            Err(e) => return bad_gateway(format!("Failed to download {}: {}", url, e)),
+        }
+    }
+
+    tracing::info!("Downloaded {} ({} bytes) as {}", url, data.len(), filename);
+    Ok(UrlDownload {
+        data,
+        mime_type,
+        filename,
+    })
 }
 
-async fn get_upload(
-    State(state): State<Arc<AppState>>,
-    Path(id): Path<String>,
-) -> Result<Json<Upload>, StatusCode> {
-    #[derive(sqlx::FromRow)]
-    struct UploadRow {
-        id: String,
-        filename: String,
-        original_filename: String,
-        file_size: i64,
-        mime_type: Option<String>,
-        created_at: String,
-    }
-
-    let upload_row = sqlx::query_as!(
-        UploadRow,
-        r#"SELECT id as "id!", filename as "filename!", original_filename as "original_filename!", file_size as "file_size!", mime_type, created_at as "created_at!" FROM uploads WHERE id = ?"#,
-        id
-    )
-    .fetch_optional(&state.db)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-    .ok_or(StatusCode::NOT_FOUND)?;
+fn request_origin(headers: &HeaderMap, peer: Option<ConnectInfo<SocketAddr>>) -> RequestOrigin {
+    RequestOrigin::from_request(headers, peer.map(|ConnectInfo(peer)| peer))
+}
 
-    // Fetch tags
-    let tags = sqlx::query_as!(
-        Tag,
-        r#"SELECT t.id as "id!", t.name as "name!", t.color as "color!", t.created_at as "created_at!"
-           FROM tags t
-           INNER JOIN upload_tags ut ON t.id = ut.tag_id
-           WHERE ut.upload_id = ?"#,
-        upload_row.id
-    )
-    .fetch_all(&state.db)
-    .await
-    .unwrap_or_default();
+// Where the jobs of an upload can be polled, including ones triggered later (new tags)
+fn jobs_location(upload_id: &str) -> [(header::HeaderName, String); 1] {
+    [(
+        header::LOCATION,
+        format!("/api/jobs?upload_id={}", upload_id),
+    )]
+}
 
-    // Check for lineage
-    let lineage = sqlx::query!(
-        r#"
-        SELECT 
-            fl.success as "success!",
-            fl.source_upload_id as "source_upload_id!",
-            fl.function_id as "function_id!",
-            u.original_filename as "source_filename!",
-            f.name as "function_name!"
-        FROM file_lineage fl
-        INNER JOIN uploads u ON fl.source_upload_id = u.id
-        INNER JOIN functions f ON fl.function_id = f.id
-        WHERE fl.output_upload_id = ?
-        "#,
-        upload_row.id
-    )
-    .fetch_optional(&state.db)
-    .await
-    .ok()
-    .flatten()
-    .map(|row| crate::models::FileLineageInfo {
-        source_upload_id: row.source_upload_id,
-        source_filename: row.source_filename,
-        function_id: row.function_id,
-        function_name: row.function_name,
-        success: row.success != 0,
-    });
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum UploadOrigin {
+    Root,    // uploaded directly (no lineage)
+    Derived, // output of a function or built-in operation
+}
 
-    Ok(Json(Upload {
-        id: upload_row.id,
-        filename: upload_row.filename,
-        original_filename: upload_row.original_filename,
-        file_size: upload_row.file_size,
-        mime_type: upload_row.mime_type,
-        created_at: upload_row.created_at,
-        tags,
-        lineage,
-    }))
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ArtifactFilter {
+    #[default]
+    Data, // error logs and intermediate outputs are hidden unless asked for
+    ErrorLog,
+    Intermediate,
+    All,
 }
 
-async fn delete_upload(
+#[derive(Debug, serde::Deserialize)]
+struct UploadListQuery {
+    assignee: Option<String>,
+    origin: Option<UploadOrigin>,
+    produced_by_function: Option<String>, // outputs of this function only
+    #[serde(default)]
+    artifact_type: ArtifactFilter,
+    created_after: Option<String>,
+    created_before: Option<String>,
+    tz: Option<String>,        // for dates and times without an offset
+    derived: Option<bool>,     // same as `origin`, which it overrides
+    tags: Option<String>,      // comma-separated tag IDs the uploads must all have
+    tag_names: Option<String>, // the same, by name
+    filename: Option<String>,  // substring of the original filename, case-insensitive
+    limit: Option<i64>,        // all matching uploads if unset
+    #[serde(default)]
+    offset: i64,
+    #[serde(default)]
+    sort: SortKey,
+    order: Option<SortOrder>,
+}
+
+// A comma-separated query param as its non-empty items
+fn comma_list(value: Option<&str>) -> Vec<String> {
+    value
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+// `created_after`/`created_before` query params in the stored form, see `timestamps::parse`
+fn created_range(
+    created_after: Option<&str>,
+    created_before: Option<&str>,
+    tz: Option<&str>,
+) -> Result<(Option<String>, Option<String>), String> {
+    let parse = |value: Option<&str>| value.map(|value| timestamps::parse(value, tz)).transpose();
+    Ok((parse(created_after)?, parse(created_before)?))
+}
+
+async fn list_uploads(
     State(state): State<Arc<AppState>>,
-    Path(id): Path<String>,
-) -> Result<StatusCode, StatusCode> {
-    // Get filename before deleting
-    let upload = sqlx::query!("SELECT filename FROM uploads WHERE id = ?", id)
-        .fetch_optional(&state.db)
+    Query(params): Query<UploadListQuery>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let (created_after, created_before) = match created_range(
+        params.created_after.as_deref(),
+        params.created_before.as_deref(),
+        params.tz.as_deref(),
+    ) {
+        Ok(range) => range,
+        Err(message) => return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response()),
+    };
+    let tag_ids = comma_list(params.tags.as_deref());
+    let tag_names = comma_list(params.tag_names.as_deref());
+    let hidden_tag_ids = hidden_tag_ids(&state, &Requester::from_headers(&headers)).await?;
+    let filter = UploadFilter {
+        assignee: params.assignee.as_deref(),
+        derived: params
+            .derived
+            .or(params.origin.map(|origin| origin == UploadOrigin::Derived)),
+        produced_by_function: params.produced_by_function.as_deref(),
+        artifact_type: match params.artifact_type {
+            ArtifactFilter::Data => Some("data"),
+            ArtifactFilter::ErrorLog => Some("error_log"),
+            ArtifactFilter::Intermediate | ArtifactFilter::All => None,
+        },
+        intermediate: match params.artifact_type {
+            ArtifactFilter::Data => Some(false),
+            ArtifactFilter::Intermediate => Some(true),
+            ArtifactFilter::ErrorLog | ArtifactFilter::All => None,
+        },
+        created_after: created_after.as_deref(),
+        created_before: created_before.as_deref(),
+        tag_ids: &tag_ids,
+        tag_names: &tag_names,
+        filename_contains: params.filename.as_deref().filter(|name| !name.is_empty()),
+        hidden_tag_ids: &hidden_tag_ids,
+    };
+    let limit = params.limit.map(|limit| limit.clamp(1, 1000));
+    let offset = params.offset.max(0);
+    let repo = UploadRepo::new(&state.db);
+    let uploads = repo
+        .page(
+            &filter,
+            Sort {
+                key: params.sort,
+                order: params.order,
+            },
+            limit,
+            offset,
+        )
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-        .ok_or(StatusCode::NOT_FOUND)?;
-
-    // Delete from database
-    sqlx::query!("DELETE FROM uploads WHERE id = ?", id)
-        .execute(&state.db)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let total = repo
+        .count(&filter)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    // Delete file from disk
-    let file_path = format!("uploads/{}", upload.filename);
-    let _ = tokio::fs::remove_file(file_path).await;
+    // Fetch tags and lineage for each upload
+    let mut result = Vec::new();
+    for upload in uploads {
+        result.push(with_tags_and_lineage(&state.db, upload).await);
+    }
 
-    Ok(StatusCode::NO_CONTENT)
+    Ok(Json(UploadPage {
+        uploads: result,
+        total,
+        limit,
+        offset,
+    })
+    .into_response())
+}
+
+// Attach the tags and lineage of an upload, for display
+#[derive(Debug, serde::Deserialize)]
+struct SearchQuery {
+    #[serde(default)]
+    q: String,
+    limit: Option<i64>, // 50 by default, at most 200
 }
 
-async fn download_file(
+/// Full-text search over filenames, tag names and metadata, ranked
+async fn search_uploads(
     State(state): State<Arc<AppState>>,
-    Path(id): Path<String>,
+    Query(params): Query<SearchQuery>,
+    headers: HeaderMap,
 ) -> Result<Response, StatusCode> {
-    // Get file info from database
-    let upload = sqlx::query!(
-        r#"SELECT filename as "filename!", original_filename as "original_filename!", mime_type FROM uploads WHERE id = ?"#,
-        id
-    )
-    .fetch_optional(&state.db)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-    .ok_or(StatusCode::NOT_FOUND)?;
-
-    // Read file from disk
-    let file_path = format!("uploads/{}", upload.filename);
-    let file_data = tokio::fs::read(&file_path)
+    let Some(expression) = match_expression(&params.q) else {
+        return Ok(
+            json_error(StatusCode::BAD_REQUEST, "Search for at least one word").into_response(),
+        );
+    };
+    let limit = params.limit.unwrap_or(50).clamp(1, 200);
+    let hidden = hidden_tag_ids(&state, &Requester::from_headers(&headers)).await?;
+    let found = UploadRepo::new(&state.db)
+        .search(&expression, &hidden, limit)
         .await
-        .map_err(|_| StatusCode::NOT_FOUND)?;
-
-    // Build response with appropriate headers
-    let mut response = Response::builder().status(StatusCode::OK);
+        .map_err(|e| internal_error(format!("Search failed: {}", e)))?;
 
-    // Set content type if available
-    if let Some(mime_type) = upload.mime_type {
-        response = response.header(header::CONTENT_TYPE, mime_type);
+    let mut hits = Vec::with_capacity(found.len());
+    for (upload, score) in found {
+        hits.push(SearchHit {
+            upload: with_tags_and_lineage(&state.db, upload).await,
+            score,
+        });
     }
+    Ok(Json(SearchResults {
+        query: params.q,
+        hits,
+    })
+    .into_response())
+}
 
-    // Set content disposition for download
-    let content_disposition = format!("inline; filename=\"{}\"", upload.original_filename);
-    response = response.header(header::CONTENT_DISPOSITION, content_disposition);
+/// Uploads whose contents contain a string, with the lines it appears on
+async fn search_upload_contents(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<SearchQuery>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    if phrase_expression(&params.q).is_none() {
+        return Ok(
+            json_error(StatusCode::BAD_REQUEST, "Search for at least one word").into_response(),
+        );
+    }
+    let limit = params.limit.unwrap_or(50).clamp(1, 200);
+    let hidden = hidden_tag_ids(&state, &Requester::from_headers(&headers)).await?;
+    let found = search_contents(&state, &params.q, &hidden, limit)
+        .await
+        .map_err(|e| internal_error(format!("Content search failed: {}", e)))?;
 
-    // Set content length
+    let mut hits = Vec::with_capacity(found.len());
+    for (upload, lines) in found {
+        hits.push(ContentHit {
+            upload: with_tags_and_lineage(&state.db, upload).await,
+            lines,
+        });
+    }
+    Ok(Json(ContentSearchResults {
+        query: params.q,
+        hits,
+    })
+    .into_response())
+}
+
+async fn with_tags_and_lineage(db: &sqlx::SqlitePool, upload: StoredUpload) -> Upload {
+    let tags = TagRepo::new(db)
+        .for_upload(&upload.id)
+        .await
+        .unwrap_or_default();
+    let lineage = UploadRepo::new(db).lineage(&upload.id).await.ok().flatten();
+    upload.into_upload(tags, lineage)
+}
+
+// Ids of the restricted tags whose uploads the requester may not see
+async fn hidden_tag_ids(
+    state: &AppState,
+    requester: &Requester,
+) -> Result<Vec<String>, StatusCode> {
+    TagRepo::new(&state.db)
+        .restricted_from(requester)
+        .await
+        .map_err(|e| internal_error(e.to_string()))
+}
+
+// Uploads with a restricted tag the requester is not allowed by are not found for them, as
+// they are left out of their listings
+async fn check_upload_access(
+    state: &AppState,
+    requester: &Requester,
+    upload_id: &str,
+) -> Result<(), StatusCode> {
+    let hidden = hidden_tag_ids(state, requester).await?;
+    if hidden.is_empty() {
+        return Ok(());
+    }
+    let upload_tags = TagRepo::new(&state.db)
+        .for_upload(upload_id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?;
+    if upload_tags.iter().any(|tag| hidden.contains(&tag.id)) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(())
+}
+
+// Layer over every `/uploads/:id` route, so none of them answers for a hidden upload
+async fn require_upload_access(
+    State(state): State<Arc<AppState>>,
+    Path(params): Path<HashMap<String, String>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    if let Some(id) = params.get("id") {
+        let requester = Requester::from_headers(request.headers());
+        check_upload_access(&state, &requester, id).await?;
+    }
+    Ok(next.run(request).await)
+}
+
+// An upload with everything the UI shows about it
+async fn upload_detail(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<Upload>, StatusCode> {
+    let upload = UploadRepo::new(&state.db)
+        .get(&id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    // An image that cannot be read is still an upload, only without metadata
+    let metadata = image_metadata(&state, &upload).await.unwrap_or_else(|e| {
+        tracing::warn!("{}", e);
+        None
+    });
+    let replication = match state.mirror_dir {
+        Some(_) => ReplicationRepo::new(&state.db)
+            .get(&upload.id)
+            .await
+            .map_err(|e| internal_error(e.to_string()))?,
+        None => None,
+    };
+    let mut upload = with_tags_and_lineage(&state.db, upload).await;
+    upload.image_metadata = metadata;
+    upload.replication = replication;
+    Ok(Json(upload))
+}
+
+// Fix the name of an upload. The extension tag follows the new suffix (triggering functions
+// like any other new tag); the file on disk and its lineage stay as they are.
+async fn update_upload(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    Json(payload): Json<UpdateUpload>,
+) -> Result<Response, StatusCode> {
+    let origin = request_origin(&headers, peer);
+    let uploads = UploadRepo::new(&state.db);
+    let mut upload = uploads
+        .get(&id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if let Some(name) = payload.original_filename {
+        let name = name.trim().to_string();
+        if !is_valid_filename(&name) || name == "." || name == ".." {
+            return Ok(json_error(
+                StatusCode::BAD_REQUEST,
+                "Filename must not be empty or contain path separators",
+            )
+            .into_response());
+        }
+
+        let detected = upload.detected_mime_type.as_deref();
+        let old_tag = extension_tag_name(&upload.original_filename, detected);
+        let new_tag = extension_tag_name(&name, detected);
+        uploads
+            .rename(&id, &name)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let detail = format!("{} → {}", upload.original_filename, name);
+        record_custody_event(&state, &id, "renamed", Some(&detail), &origin).await;
+        upload.original_filename = name;
+
+        if old_tag != new_tag {
+            let tags = TagService::new(&state);
+            if let Some(old_tag) = old_tag {
+                if let Some(tag_id) = tags
+                    .id_by_name(&old_tag)
+                    .await
+                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                {
+                    TagRepo::new(&state.db)
+                        .untag_upload(&id, &tag_id)
+                        .await
+                        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+                }
+            }
+            let settings = state.settings();
+            let extension_tags = &settings.tag_policy.extension_tags;
+            if let Some(new_tag) = new_tag.filter(|name| extension_tags.applies_to(name)) {
+                let tag_id = tags
+                    .ensure(&new_tag, &extension_tags.color)
+                    .await
+                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+                TagRepo::new(&state.db)
+                    .tag_upload(&id, &tag_id)
+                    .await
+                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+                state.hooks.tagged(&state, &upload, &[tag_id]).await;
+                trigger_functions_for_upload(state.clone(), id.clone());
+            }
+        }
+    }
+
+    if let Some(protected) = payload.protected {
+        let releases = ReleaseRepo::new(&state.db)
+            .names_for_upload(&id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if !protected && !releases.is_empty() {
+            return Ok(json_error(
+                StatusCode::CONFLICT,
+                format!("Upload is part of release {}", releases.join(", ")),
+            )
+            .into_response());
+        }
+        uploads
+            .set_protected(&id, protected)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if protected != upload.protected {
+            let action = if protected {
+                "protected"
+            } else {
+                "unprotected"
+            };
+            record_custody_event(&state, &id, action, None, &origin).await;
+        }
+        upload.protected = protected;
+    }
+
+    match payload.intermediate {
+        Some(true) => {
+            return Ok(json_error(
+                StatusCode::BAD_REQUEST,
+                "Only functions mark their outputs as intermediate",
+            )
+            .into_response())
+        }
+        Some(false) if upload.expires_at.is_some() => {
+            uploads
+                .clear_expiry(&id)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            record_custody_event(&state, &id, "kept", None, &origin).await;
+            upload.expires_at = None;
+        }
+        _ => {}
+    }
+
+    Ok(Json(with_tags_and_lineage(&state.db, upload).await).into_response())
+}
+
+// Where an upload came from and everything done to it since, as one document for audits
+async fn get_upload_custody(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<ChainOfCustody>, StatusCode> {
+    let upload = UploadRepo::new(&state.db)
+        .get(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    chain_of_custody(&state, upload)
+        .await
+        .map(Json)
+        .map_err(internal_error)
+}
+
+// Store the content of an upload again as a new upload with the same tags (and data
+// dictionary), e.g. to fork a dataset before processing it. The copy is a new upload like any
+// other: functions its tags trigger run on it, and its lineage points to the source.
+async fn copy_upload(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    payload: Option<Json<CopyUpload>>,
+) -> Result<Response, StatusCode> {
+    let Json(payload) = payload.unwrap_or_default();
+    let source = UploadRepo::new(&state.db)
+        .get(&id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let name = match payload.original_filename {
+        Some(name) => name.trim().to_string(),
+        None => source.original_filename.clone(),
+    };
+    if !is_valid_filename(&name) || name == "." || name == ".." {
+        return Ok(json_error(
+            StatusCode::BAD_REQUEST,
+            "Filename must not be empty or contain path separators",
+        )
+        .into_response());
+    }
+
+    let data = read_upload(&state, &source.filename, source.compression.as_deref())
+        .await
+        .map_err(|e| internal_error(format!("Failed to read {}: {}", source.filename, e)))?;
+    let content = [(name.as_str(), data.as_slice())];
+    // The copy gets the extension tag of its own name from the hooks
+    let source_tags = TagRepo::new(&state.db)
+        .for_upload(&id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let extension_tag = applied_extension_tag(
+        &state.settings().tag_policy,
+        &source.original_filename,
+        source.detected_mime_type.as_deref(),
+    );
+    let tag_ids: Vec<String> = source_tags
+        .into_iter()
+        .filter(|tag| Some(&tag.name) != extension_tag.as_ref())
+        .map(|tag| tag.id)
+        .collect();
+    if let Some(message) = check_storage_quota(&state, &content, &tag_ids).await? {
+        return Ok(json_error(StatusCode::INSUFFICIENT_STORAGE, message).into_response());
+    }
+
+    let upload = store_upload(&state, name, data, source.mime_type.clone(), tag_ids)
+        .await
+        .map_err(internal_error)?;
+
+    let uploads = UploadRepo::new(&state.db);
+    let dictionary = uploads
+        .dictionary(&id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !dictionary.columns.is_empty() {
+        uploads
+            .set_dictionary(&upload.id, &dictionary)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
+    uploads
+        .add_lineage(&NewLineage {
+            output_upload_id: &upload.id,
+            source_upload_id: &id,
+            function_id: None,
+            operation: Some("copy"),
+            query: None,
+            success: true,
+            created_at: &upload.created_at,
+        })
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok((StatusCode::CREATED, Json(upload)).into_response())
+}
+
+async fn delete_upload(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Response, StatusCode> {
+    let uploads = UploadRepo::new(&state.db);
+
+    // Get filename before deleting
+    let upload = uploads
+        .get(&id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if upload.protected {
+        return Ok(json_error(StatusCode::CONFLICT, PROTECTED_MESSAGE).into_response());
+    }
+
+    remove_upload(&state, &upload).await?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+const PROTECTED_MESSAGE: &str =
+    "Upload is protected; unset the flag with PATCH {\"protected\": false} to delete it";
+
+// Delete the row, then the file, and let the hooks clean up after it. The row of an upload
+// protected in the meantime stays, and so does its file.
+async fn remove_upload(state: &Arc<AppState>, upload: &StoredUpload) -> Result<(), StatusCode> {
+    let deleted = UploadRepo::new(&state.db)
+        .delete(&upload.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !deleted {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let file_path = state.executor.uploads_dir().join(&upload.filename);
+    let _ = tokio::fs::remove_file(file_path).await;
+    state.hooks.deleted(state, upload).await;
+    Ok(())
+}
+
+// The stored file of an upload, decompressed, as a download
+async fn send_upload_file(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Response, StatusCode> {
+    // Get file info from database
+    let upload = sqlx::query!(
+        r#"SELECT filename as "filename!", original_filename as "original_filename!", mime_type, detected_mime_type, compression FROM uploads WHERE id = ?"#,
+        id
+    )
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    // Read file from disk
+    let file_data = read_upload(&state, &upload.filename, upload.compression.as_deref())
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+
+    // Build response with appropriate headers
+    let mut response = Response::builder().status(StatusCode::OK);
+
+    // Set content type if available, trusting the content over the client's claim
+    if let Some(mime_type) = upload.detected_mime_type.or(upload.mime_type) {
+        response = response.header(header::CONTENT_TYPE, mime_type);
+    }
+
+    // Set content disposition for download
+    let content_disposition = format!("inline; filename=\"{}\"", upload.original_filename);
+    response = response.header(header::CONTENT_DISPOSITION, content_disposition);
+
+    // Set content length
     response = response.header(header::CONTENT_LENGTH, file_data.len());
 
     response
@@ -562,48 +1967,216 @@ async fn download_file(
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
 }
 
+// The uploads picked by IDs and a tag expression, for archives and releases: explicit IDs
+// keep their order and tag matches follow in upload order. Uploads hidden from the requester
+// are unknown to them. Err is the answer to a bad request.
+async fn select_uploads(
+    state: &AppState,
+    requester: &Requester,
+    upload_ids: &[String],
+    tag_expression: Option<&str>,
+) -> Result<Vec<StoredUpload>, Response> {
+    let tag_expr = match tag_expression.map(str::trim) {
+        None | Some("") => None,
+        Some(expression) => match TagExpr::parse(expression) {
+            Ok(expr) => Some(expr),
+            Err(e) => {
+                return Err(json_error(
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid tag expression: {}", e),
+                )
+                .into_response())
+            }
+        },
+    };
+    if upload_ids.is_empty() && tag_expr.is_none() {
+        return Err(json_error(
+            StatusCode::BAD_REQUEST,
+            "Provide upload_ids, a tag_expression, or both",
+        )
+        .into_response());
+    }
+
+    let hidden = hidden_tag_ids(state, requester)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    let uploads = UploadRepo::new(&state.db)
+        .page(
+            &UploadFilter {
+                hidden_tag_ids: &hidden,
+                ..UploadFilter::default()
+            },
+            Sort {
+                key: SortKey::CreatedAt,
+                order: Some(SortOrder::Asc),
+            },
+            None,
+            0,
+        )
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
+
+    let mut by_id: HashMap<&str, &StoredUpload> =
+        uploads.iter().map(|u| (u.id.as_str(), u)).collect();
+    let mut selected: Vec<&StoredUpload> = Vec::new();
+    let mut unknown = Vec::new();
+    for id in upload_ids {
+        match by_id.remove(id.as_str()) {
+            Some(upload) => selected.push(upload),
+            None if selected.iter().any(|u| &u.id == id) => {}
+            None => unknown.push(id.as_str()),
+        }
+    }
+    if !unknown.is_empty() {
+        return Err(json_error(
+            StatusCode::NOT_FOUND,
+            format!("Unknown upload IDs: {}", unknown.join(", ")),
+        )
+        .into_response());
+    }
+    if let Some(expr) = &tag_expr {
+        for upload in &uploads {
+            if !by_id.contains_key(upload.id.as_str()) {
+                continue;
+            }
+            let tags = TagRepo::new(&state.db)
+                .for_upload(&upload.id)
+                .await
+                .unwrap_or_default();
+            if expr.matches(&tags.iter().map(|t| t.name.as_str()).collect()) {
+                selected.push(upload);
+            }
+        }
+    }
+    let selected: Vec<String> = selected.into_iter().map(|u| u.id.clone()).collect();
+    let mut uploads: HashMap<String, StoredUpload> =
+        uploads.into_iter().map(|u| (u.id.clone(), u)).collect();
+    Ok(selected
+        .iter()
+        .filter_map(|id| uploads.remove(id))
+        .collect())
+}
+
+// Stream a zip of the selected uploads, e.g. all inputs and outputs of an experiment
+async fn archive_uploads(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(request): Json<ArchiveRequest>,
+) -> Result<Response, StatusCode> {
+    let selected = match select_uploads(
+        &state,
+        &Requester::from_headers(&headers),
+        &request.upload_ids,
+        request.tag_expression.as_deref(),
+    )
+    .await
+    {
+        Ok(selected) => selected,
+        Err(response) => return Ok(response),
+    };
+
+    let names = unique_entry_names(selected.iter().map(|u| u.original_filename.as_str()));
+    let mut entries = Vec::with_capacity(selected.len());
+    for (upload, name) in selected.into_iter().zip(names) {
+        let path = plain_upload_path(&state, &upload.filename, upload.compression.as_deref())
+            .await
+            .map_err(|e| internal_error(e.to_string()))?;
+        entries.push(ArchiveEntry {
+            name,
+            path,
+            created_at: upload.created_at.clone(),
+        });
+    }
+
+    let filename = match request.filename.as_deref().map(str::trim) {
+        None | Some("") => "datalab-export.zip".to_string(),
+        Some(name) if name.to_lowercase().ends_with(".zip") => name.replace('"', "'"),
+        Some(name) => format!("{}.zip", name.replace('"', "'")),
+    };
+    tracing::info!("📦 Streaming {} upload(s) as {}", entries.len(), filename);
+
+    let (tx, rx) = tokio::sync::mpsc::channel::<ArchiveChunk>(8);
+    tokio::task::spawn_blocking(move || {
+        if let Err(e) = write_archive(&entries, tx) {
+            // The status is already sent, so a failure can only cut the stream short
+            tracing::error!("Failed to stream archive: {}", e);
+        }
+    });
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/zip")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", filename),
+        )
+        .body(axum::body::Body::from_stream(ReceiverStream::new(rx)))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
 async fn add_tags_to_upload(
     State(state): State<Arc<AppState>>,
     Path(id): Path<String>,
+    headers: HeaderMap,
+    peer: Option<ConnectInfo<SocketAddr>>,
     Json(tag_ids): Json<Vec<String>>,
 ) -> Result<StatusCode, StatusCode> {
     // Check if upload exists
-    sqlx::query!("SELECT id FROM uploads WHERE id = ?", id)
-        .fetch_optional(&state.db)
+    let upload = UploadRepo::new(&state.db)
+        .get(&id)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
         .ok_or(StatusCode::NOT_FOUND)?;
 
+    let origin = request_origin(&headers, peer);
+    tag_upload(&state, &upload, &tag_ids, &origin).await;
+    Ok(StatusCode::OK)
+}
+
+// Add tags to an upload as a client asked for it: hooks run, the audit trail records it and
+// the functions the tags match are triggered in the background
+async fn tag_upload(
+    state: &Arc<AppState>,
+    upload: &StoredUpload,
+    tag_ids: &[String],
+    origin: &RequestOrigin,
+) {
     for tag_id in tag_ids {
         let _ = sqlx::query!(
             "INSERT OR IGNORE INTO upload_tags (upload_id, tag_id) VALUES (?, ?)",
-            id,
+            upload.id,
             tag_id
         )
         .execute(&state.db)
         .await;
     }
+    state.hooks.tagged(state, upload, tag_ids).await;
+    let tags = TagRepo::new(&state.db);
+    for tag_id in tag_ids {
+        if let Ok(Some(tag)) = tags.get(tag_id).await {
+            record_custody_event(state, &upload.id, "tagged", Some(&tag.name), origin).await;
+        }
+    }
 
-    // Trigger function execution in the background
-    let upload_id_clone = id.clone();
-    let state_clone = state.clone();
-    trigger_functions_for_upload(state_clone, upload_id_clone);
-
-    Ok(StatusCode::OK)
+    trigger_functions_for_upload(state.clone(), upload.id.clone());
 }
 
 async fn remove_tag_from_upload(
     State(state): State<Arc<AppState>>,
     Path((upload_id, tag_id)): Path<(String, String)>,
+    headers: HeaderMap,
+    peer: Option<ConnectInfo<SocketAddr>>,
 ) -> Result<StatusCode, StatusCode> {
-    sqlx::query!(
-        "DELETE FROM upload_tags WHERE upload_id = ? AND tag_id = ?",
-        upload_id,
-        tag_id
-    )
-    .execute(&state.db)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let removed = TagRepo::new(&state.db)
+        .untag_upload(&upload_id, &tag_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if removed {
+        if let Ok(Some(tag)) = TagRepo::new(&state.db).get(&tag_id).await {
+            let origin = request_origin(&headers, peer);
+            record_custody_event(&state, &upload_id, "untagged", Some(&tag.name), &origin).await;
+        }
+    }
 
     Ok(StatusCode::NO_CONTENT)
 }
@@ -616,14 +2189,15 @@ async fn get_derived_files(
         r#"
         SELECT 
             fl.output_upload_id as "output_upload_id!",
-            fl.function_id as "function_id!",
+            fl.function_id as "function_id?",
+            fl.operation as "operation?",
             fl.success as "success!",
             fl.created_at as "created_at!",
             u.original_filename as "output_filename!",
-            f.name as "function_name!"
+            COALESCE(f.name, fl.operation) as "function_name!: String"
         FROM file_lineage fl
         INNER JOIN uploads u ON fl.output_upload_id = u.id
-        INNER JOIN functions f ON fl.function_id = f.id
+        LEFT JOIN functions f ON fl.function_id = f.id
         WHERE fl.source_upload_id = ?
         ORDER BY fl.created_at DESC
         "#,
@@ -640,6 +2214,7 @@ async fn get_derived_files(
             output_filename: row.output_filename,
             function_id: row.function_id,
             function_name: row.function_name,
+            operation: row.operation,
             success: row.success != 0,
             created_at: row.created_at,
         })
@@ -648,13 +2223,62 @@ async fn get_derived_files(
     Ok(Json(result))
 }
 
+async fn get_lineage_dot(
+    State(state): State<Arc<AppState>>,
+    Path(upload_id): Path<String>,
+) -> Result<Response, StatusCode> {
+    lineage_diagram_response(&state, &upload_id, DiagramFormat::Dot).await
+}
+
+async fn get_lineage_mermaid(
+    State(state): State<Arc<AppState>>,
+    Path(upload_id): Path<String>,
+) -> Result<Response, StatusCode> {
+    lineage_diagram_response(&state, &upload_id, DiagramFormat::Mermaid).await
+}
+
+// The provenance of an upload as a diagram to embed in a wiki or paper
+async fn lineage_diagram_response(
+    state: &AppState,
+    upload_id: &str,
+    format: DiagramFormat,
+) -> Result<Response, StatusCode> {
+    let Some(diagram) = lineage_diagram(state, upload_id)
+        .await
+        .map_err(internal_error)?
+    else {
+        return Ok(json_error(StatusCode::NOT_FOUND, "Upload not found").into_response());
+    };
+    let body = diagram.render(format, "lineage");
+    Ok(([(header::CONTENT_TYPE, format.content_type())], body).into_response())
+}
+
+async fn get_pipeline_dot(State(state): State<Arc<AppState>>) -> Result<Response, StatusCode> {
+    pipeline_diagram_response(&state, DiagramFormat::Dot).await
+}
+
+async fn get_pipeline_mermaid(State(state): State<Arc<AppState>>) -> Result<Response, StatusCode> {
+    pipeline_diagram_response(&state, DiagramFormat::Mermaid).await
+}
+
+// Tags, the functions they trigger and the tags those give their outputs
+async fn pipeline_diagram_response(
+    state: &AppState,
+    format: DiagramFormat,
+) -> Result<Response, StatusCode> {
+    let diagram = pipeline_diagram(state).await.map_err(internal_error)?;
+    let body = diagram.render(format, "pipeline");
+    Ok(([(header::CONTENT_TYPE, format.content_type())], body).into_response())
+}
+
 async fn trigger_function_manually(
     State(state): State<Arc<AppState>>,
     Path((upload_id, function_id)): Path<(String, String)>,
-) -> Result<StatusCode, StatusCode> {
+    body: Option<Json<TriggerRequest>>,
+) -> Result<Response, StatusCode> {
     // Verify upload exists
-    sqlx::query!(r#"SELECT id FROM uploads WHERE id = ?"#, upload_id)
-        .fetch_optional(&state.db)
+    let upload = UploadRepo::new(&state.db)
+        .get(&upload_id)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
         .ok_or(StatusCode::NOT_FOUND)?;
@@ -666,391 +2290,166 @@ async fn trigger_function_manually(
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
         .ok_or(StatusCode::NOT_FOUND)?;
 
+    // A trial run on part of the file runs just this function
+    let Json(request) = body.unwrap_or_default();
+    if let Some(slice) = request.slice {
+        if let Err(message) = slice.validate() {
+            return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response());
+        }
+        let extension = std::path::Path::new(&upload.original_filename)
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase());
+        if slice.is_tabular() && !matches!(extension.as_deref(), Some("csv" | "parquet")) {
+            let message = "Row ranges need a CSV or Parquet file; use a byte range instead";
+            return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response());
+        }
+        let job = run_function_on_slice(&state, &upload_id, &function_id, slice)
+            .await
+            .map_err(internal_error)?
+            .ok_or(StatusCode::NOT_FOUND)?;
+        return Ok((
+            StatusCode::ACCEPTED,
+            jobs_location(&upload_id),
+            Json(serde_json::json!({ "jobs": [job] })),
+        )
+            .into_response());
+    }
+
     // Trigger the function execution
-    trigger_functions_for_upload(state, upload_id);
+    let jobs = enqueue_functions_for_upload(&state, &upload_id).await;
 
-    Ok(StatusCode::ACCEPTED) // 202 - Accepted for processing
-}
+    // 202 - Accepted for processing
+    let location = jobs_location(&upload_id);
+    Ok((
+        StatusCode::ACCEPTED,
+        location,
+        Json(serde_json::json!({ "jobs": jobs })),
+    )
+        .into_response())
+}
 
 // ============= FUNCTIONS =============
 
-// Helper function to trigger function execution for an upload
-fn trigger_functions_for_upload(state: Arc<AppState>, upload_id: String) {
-    tokio::spawn(async move {
-        // Fetch the upload with its tags
-        let upload = match sqlx::query!(
-            r#"SELECT id as "id!", filename as "filename!" FROM uploads WHERE id = ?"#,
-            upload_id
-        )
-        .fetch_optional(&state.db)
-        .await
-        {
-            Ok(Some(u)) => u,
-            _ => return,
-        };
-
-        let upload_tags: Vec<String> = sqlx::query!(
-            r#"SELECT tag_id as "tag_id!" FROM upload_tags WHERE upload_id = ?"#,
-            upload_id
-        )
-        .fetch_all(&state.db)
-        .await
-        .unwrap_or_default()
-        .iter()
-        .map(|r| r.tag_id.clone())
-        .collect();
-
-        // Find all ENABLED functions
-        let functions = sqlx::query!(
-            r#"SELECT id as "id!", script_filename as "script_filename!" FROM functions WHERE enabled = 1"#
-        )
-        .fetch_all(&state.db)
-        .await
-        .unwrap_or_default();
-
-        for function in functions {
-            // Get function's input tags
-            let input_tags: Vec<String> = sqlx::query!(
-                r#"SELECT tag_id as "tag_id!" FROM function_input_tags WHERE function_id = ?"#,
-                function.id
-            )
-            .fetch_all(&state.db)
-            .await
-            .unwrap_or_default()
-            .iter()
-            .map(|r| r.tag_id.clone())
-            .collect();
-
-            // Check if upload has all input tags
-            let has_all_tags = input_tags.iter().all(|tag| upload_tags.contains(tag));
-
-            tracing::debug!(
-                "Function {} check: input_tags={:?}, upload_tags={:?}, match={}",
-                function.id,
-                input_tags,
-                upload_tags,
-                has_all_tags
-            );
-
-            if has_all_tags && !input_tags.is_empty() {
-                tracing::info!(
-                    "MATCH! Triggering function {} for upload {}",
-                    function.id,
-                    upload_id
-                );
-
-                // Create job record
-                let job_id = Uuid::new_v4().to_string();
-                let job_created_at = chrono::Utc::now().to_rfc3339();
-
-                let _ = sqlx::query!(
-                    "INSERT INTO jobs (id, upload_id, function_id, status, created_at) VALUES (?, ?, ?, ?, ?)",
-                    job_id,
-                    upload_id,
-                    function.id,
-                    "SUBMITTED",
-                    job_created_at
-                )
-                .execute(&state.db)
-                .await;
-
-                // Spawn execution task
-                let state_clone = state.clone();
-                let function_id = function.id.clone();
-                let function_script = function.script_filename.clone();
-                let upload_id_clone = upload_id.clone();
-                let upload_filename = upload.filename.clone();
-
-                tokio::spawn(async move {
-                    execute_job(
-                        state_clone,
-                        job_id,
-                        upload_id_clone,
-                        function_id,
-                        function_script,
-                        upload_filename,
-                    )
-                    .await;
-                });
-            }
+// Reject executors this server has no cluster settings for
+fn validate_executor(state: &AppState, executor: &str) -> Result<(), StatusCode> {
+    match ComputeBackend::parse(executor) {
+        Some(backend) if state.executor.supports(backend) => Ok(()),
+        _ => {
+            tracing::warn!("Executor {:?} is unknown or not configured", executor);
+            Err(StatusCode::BAD_REQUEST)
         }
-    });
+    }
 }
 
-// Execute a single job with semaphore control
-async fn execute_job(
-    state: Arc<AppState>,
-    job_id: String,
-    upload_id: String,
-    function_id: String,
-    script_filename: String,
-    input_filename: String,
-) {
-    // Acquire semaphore permit (waits if at capacity)
-    let _permit = state.execution_semaphore.acquire().await.unwrap();
-
-    // Update job status to RUNNING
-    let started_at = chrono::Utc::now().to_rfc3339();
-    let _ = sqlx::query!(
-        "UPDATE jobs SET status = ?, started_at = ? WHERE id = ?",
-        "RUNNING",
-        started_at,
-        job_id
-    )
-    .execute(&state.db)
-    .await;
-
-    tracing::info!(
-        "Executing job {} (function: {}, upload: {})",
-        job_id,
-        function_id,
-        upload_id
-    );
+// Quick functions run a single SELECT over their input, in this process
+fn validate_quick_function_payload(expression: &str, executor: &str) -> Result<(), StatusCode> {
+    if let Err(e) = validate_quick_function(expression) {
+        tracing::warn!("Invalid quick function expression: {}", e);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if executor != "local" {
+        tracing::warn!("Quick functions run locally, not on {}", executor);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    Ok(())
+}
 
-    // Get original filename
-    let original_filename = match sqlx::query!(
-        r#"SELECT original_filename as "original_filename!" FROM uploads WHERE id = ?"#,
-        upload_id
-    )
-    .fetch_optional(&state.db)
-    .await
-    {
-        Ok(Some(u)) => u.original_filename,
-        _ => {
-            let failed_at = chrono::Utc::now().to_rfc3339();
-            let error_msg = "Upload not found";
-            let _ = sqlx::query!(
-                "UPDATE jobs SET status = ?, error_message = ?, completed_at = ? WHERE id = ?",
-                "FAILED",
-                error_msg,
-                failed_at,
-                job_id
-            )
-            .execute(&state.db)
-            .await;
-            return;
+// Reject trigger conditions that can never match, e.g. an unparsable tag expression
+fn validate_trigger_conditions(conditions: &[TriggerCondition]) -> Result<(), StatusCode> {
+    for condition in conditions {
+        if let Err(e) = compile_condition(condition) {
+            tracing::warn!("Invalid trigger condition {:?}: {}", condition, e);
+            return Err(StatusCode::BAD_REQUEST);
         }
-    };
-
-    // Execute function
-    let mut output_upload_ids = Vec::new();
-
-    match state
-        .executor
-        .execute_function(&script_filename, &input_filename, &original_filename)
-        .await
-    {
-        Ok(output_files) => {
-            // Get output tags for this function
-            let output_tag_ids: Vec<String> = sqlx::query!(
-                r#"SELECT tag_id as "tag_id!" FROM function_output_tags WHERE function_id = ?"#,
-                function_id
-            )
-            .fetch_all(&state.db)
-            .await
-            .unwrap_or_default()
-            .iter()
-            .map(|r| r.tag_id.clone())
-            .collect();
-
-            // Register each output file as a new upload
-            for output_file in output_files {
-                let output_path = format!("output/{}", output_file);
-                if let Ok(metadata) = tokio::fs::metadata(&output_path).await {
-                    let new_id = Uuid::new_v4().to_string();
-                    let created_at = chrono::Utc::now().to_rfc3339();
-                    let file_size = metadata.len() as i64;
-                    let is_error_log =
-                        output_file.starts_with("error_") && output_file.ends_with(".log");
-
-                    // Move file to uploads directory
-                    let new_filename = format!("{}_{}", new_id, output_file);
-                    let new_path = format!("uploads/{}", new_filename);
-                    let _ = tokio::fs::rename(&output_path, &new_path).await;
-
-                    // Save to database
-                    let _ = sqlx::query!(
-                                    "INSERT INTO uploads (id, filename, original_filename, file_size, mime_type, created_at) VALUES (?, ?, ?, ?, ?, ?)",
-                                    new_id,
-                                    new_filename,
-                                    output_file,
-                                    file_size,
-                                    None::<String>,
-                                    created_at
-                                )
-                                .execute(&state.db)
-                                .await;
-
-                    // Apply output tags ONLY if not an error log
-                    if !is_error_log {
-                        for tag_id in &output_tag_ids {
-                            let _ = sqlx::query!(
-                                "INSERT INTO upload_tags (upload_id, tag_id) VALUES (?, ?)",
-                                new_id,
-                                tag_id
-                            )
-                            .execute(&state.db)
-                            .await;
-                        }
-                    }
-
-                    // Apply extension tag (for both success and error)
-                    if let Some(extension) = output_file.rsplit('.').next() {
-                        if !extension.is_empty() && extension != output_file {
-                            let ext_tag_name = format!(".{}", extension.to_lowercase());
-                            if let Ok(Some(tag)) = sqlx::query!(
-                                r#"SELECT id as "id!" FROM tags WHERE name = ?"#,
-                                ext_tag_name
-                            )
-                            .fetch_optional(&state.db)
-                            .await
-                            {
-                                let _ = sqlx::query!(
-                                    "INSERT OR IGNORE INTO upload_tags (upload_id, tag_id) VALUES (?, ?)",
-                                    new_id,
-                                    tag.id
-                                )
-                                .execute(&state.db)
-                                .await;
-                            }
-                        }
-                    }
-
-                    // Create lineage record
-                    let lineage_id = Uuid::new_v4().to_string();
-                    let lineage_success = if is_error_log { 0 } else { 1 };
-                    let _ = sqlx::query!(
-                        "INSERT INTO file_lineage (id, output_upload_id, source_upload_id, function_id, success, created_at) VALUES (?, ?, ?, ?, ?, ?)",
-                        lineage_id,
-                        new_id,
-                        upload_id,
-                        function_id,
-                        lineage_success,
-                        created_at
-                    )
-                    .execute(&state.db)
-                    .await;
-
-                    output_upload_ids.push(new_id.clone());
-                    tracing::info!(
-                        "Created output file: {} (success: {})",
-                        output_file,
-                        !is_error_log
-                    );
-                }
-            }
-
-            // Update job status to SUCCESS
-            let completed_at = chrono::Utc::now().to_rfc3339();
-            let output_ids_json = serde_json::to_string(&output_upload_ids).unwrap_or_default();
-            let _ = sqlx::query!(
-                "UPDATE jobs SET status = ?, output_upload_ids = ?, completed_at = ? WHERE id = ?",
-                "SUCCESS",
-                output_ids_json,
-                completed_at,
-                job_id
-            )
-            .execute(&state.db)
-            .await;
-
-            tracing::info!(
-                "Job {} completed successfully with {} outputs",
-                job_id,
-                output_upload_ids.len()
-            );
+    }
+    Ok(())
+}
 
-            // Trigger functions for ALL newly created output files (enables chaining)
-            for output_id in &output_upload_ids {
-                tracing::info!("Checking triggers for output file: {}", output_id);
-                let state_clone = state.clone();
-                let output_id_clone = output_id.clone();
+// An upload can't both need and lack a tag, so such a function would never run
+fn validate_excluded_tags(
+    input_tag_ids: &[String],
+    excluded_tag_ids: &[String],
+) -> Result<(), StatusCode> {
+    if excluded_tag_ids.iter().any(|id| input_tag_ids.contains(id)) {
+        tracing::warn!("A tag cannot be both an input tag and an excluded tag of a function");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    Ok(())
+}
 
-                // Delay slightly to ensure DB commits are visible
-                tokio::spawn(async move {
-                    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-                    trigger_functions_for_upload(state_clone, output_id_clone);
-                });
-            }
-        }
-        Err(e) => {
-            let error_message = e.to_string();
-            tracing::error!("Job {} failed: {}", job_id, error_message);
-
-            // Update job status to FAILED
-            let completed_at = chrono::Utc::now().to_rfc3339();
-            let _ = sqlx::query!(
-                "UPDATE jobs SET status = ?, error_message = ?, completed_at = ? WHERE id = ?",
-                "FAILED",
-                error_message,
-                completed_at,
-                job_id
-            )
-            .execute(&state.db)
-            .await;
-        }
+fn validate_intermediate_outputs(patterns: &[String], ttl_hours: i64) -> Result<(), StatusCode> {
+    if ttl_hours <= 0 || patterns.iter().any(|pattern| pattern.trim().is_empty()) {
+        tracing::warn!("Intermediate outputs need patterns and a TTL of at least an hour");
+        return Err(StatusCode::BAD_REQUEST);
     }
+    Ok(())
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct FunctionListQuery {
+    #[serde(default)]
+    sort: SortKey, // by size means by script size
+    order: Option<SortOrder>,
 }
 
 async fn list_functions(
     State(state): State<Arc<AppState>>,
+    Query(params): Query<FunctionListQuery>,
 ) -> Result<Json<Vec<Function>>, StatusCode> {
-    #[derive(sqlx::FromRow)]
-    struct FunctionRow {
-        id: String,
-        name: String,
-        script_filename: String,
-        enabled: i64,
-        function_type: String,
-        created_at: String,
-    }
-
-    let functions = sqlx::query_as!(
-        FunctionRow,
-        r#"SELECT id as "id!", name as "name!", script_filename as "script_filename!", enabled as "enabled!", function_type as "function_type!", created_at as "created_at!" FROM functions ORDER BY created_at DESC"#
-    )
-    .fetch_all(&state.db)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let mut result = Vec::new();
-    for func_row in functions {
-        // Fetch input tags
-        let input_tags = sqlx::query_as!(
-            Tag,
-            r#"SELECT t.id as "id!", t.name as "name!", t.color as "color!", t.created_at as "created_at!"
-               FROM tags t
-               INNER JOIN function_input_tags fit ON t.id = fit.tag_id
-               WHERE fit.function_id = ?"#,
-            func_row.id
-        )
-        .fetch_all(&state.db)
+    let functions = FunctionRepo::new(&state.db);
+    let mut rows = functions
+        .list()
         .await
-        .unwrap_or_default();
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-        // Fetch output tags
-        let output_tags = sqlx::query_as!(
-            Tag,
-            r#"SELECT t.id as "id!", t.name as "name!", t.color as "color!", t.created_at as "created_at!"
-               FROM tags t
-               INNER JOIN function_output_tags fot ON t.id = fot.tag_id
-               WHERE fot.function_id = ?"#,
-            func_row.id
-        )
-        .fetch_all(&state.db)
-        .await
-        .unwrap_or_default();
+    // Scripts live on disk, so functions are sorted here rather than in SQL
+    let sort = Sort {
+        key: params.sort,
+        order: params.order,
+    };
+    let mut script_sizes = std::collections::HashMap::new();
+    if sort.key == SortKey::Size {
+        for function in &rows {
+            let size = match &function.expression {
+                Some(expression) => expression.len() as u64,
+                None => tokio::fs::metadata(
+                    state.executor.scripts_dir().join(&function.script_filename),
+                )
+                .await
+                .map(|metadata| metadata.len())
+                .unwrap_or(0),
+            };
+            script_sizes.insert(function.id.clone(), size);
+        }
+    }
+    rows.sort_by(|a, b| {
+        let ordering = match sort.key {
+            SortKey::CreatedAt => a.created_at.cmp(&b.created_at),
+            SortKey::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+            SortKey::Size => script_sizes[&a.id].cmp(&script_sizes[&b.id]),
+        };
+        // Ties stay newest first
+        let ordering = if sort.ascending() {
+            ordering
+        } else {
+            ordering.reverse()
+        };
+        ordering.then_with(|| b.created_at.cmp(&a.created_at))
+    });
 
-        result.push(Function {
-            id: func_row.id,
-            name: func_row.name,
-            script_filename: func_row.script_filename,
-            enabled: func_row.enabled != 0,
-            function_type: func_row.function_type,
-            created_at: func_row.created_at,
-            input_tags,
-            output_tags,
-            script_content: None, // Don't load content for list view
-        });
+    let mut result = Vec::new();
+    for function in rows {
+        let input_tags = functions.input_tags(&function.id).await.unwrap_or_default();
+        let output_tags = functions
+            .output_tags(&function.id)
+            .await
+            .unwrap_or_default();
+        let excluded_tags = functions
+            .excluded_tags(&function.id)
+            .await
+            .unwrap_or_default();
+        // Don't load content for list view
+        result.push(function.into_function(input_tags, output_tags, excluded_tags, None));
     }
 
     Ok(Json(result))
@@ -1060,81 +2459,82 @@ async fn create_function(
     State(state): State<Arc<AppState>>,
     Json(payload): Json<CreateFunction>,
 ) -> Result<(StatusCode, Json<Function>), StatusCode> {
-    let id = Uuid::new_v4().to_string();
-    let created_at = chrono::Utc::now().to_rfc3339();
-    let script_filename = format!("{}_{}.py", created_at.replace([':', '-', '.'], "_"), id);
-
-    // Save script to file
-    let script_path = format!("scripts/{}", script_filename);
-    tokio::fs::write(&script_path, &payload.script_content)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    validate_executor(&state, &payload.executor)?;
+    validate_trigger_conditions(&payload.trigger_conditions)?;
+    validate_intermediate_outputs(
+        &payload.intermediate_outputs,
+        payload.intermediate_ttl_hours,
+    )?;
+    validate_excluded_tags(&payload.input_tag_ids, &payload.excluded_tag_ids)?;
+    if let Some(expression) = &payload.expression {
+        validate_quick_function_payload(expression, &payload.executor)?;
+        if !payload.script_content.is_empty() {
+            tracing::warn!("A function has either a script or an expression");
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
 
-    // Save function to database (disabled by default)
-    sqlx::query!(
-        "INSERT INTO functions (id, name, script_filename, function_type, created_at) VALUES (?, ?, ?, ?, ?)",
-        id,
-        payload.name,
-        script_filename,
-        payload.function_type,
-        created_at
-    )
-    .execute(&state.db)
-    .await
-    .map_err(|e| {
-        if e.to_string().contains("UNIQUE constraint failed") {
-            StatusCode::CONFLICT
-        } else {
-            StatusCode::INTERNAL_SERVER_ERROR
+    let id = Uuid::new_v4().to_string();
+    let created_at = timestamps::now();
+
+    // Save script to file; quick functions have none
+    let script_filename = match payload.expression {
+        Some(_) => String::new(),
+        None => {
+            let script_filename = format!("{}_{}.py", created_at.replace([':', '-', '.'], "_"), id);
+            let script_path = state.executor.scripts_dir().join(&script_filename);
+            tokio::fs::write(&script_path, &payload.script_content)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            script_filename
         }
-    })?;
+    };
 
-    // Add input tags
-    for tag_id in &payload.input_tag_ids {
-        let _ = sqlx::query!(
-            "INSERT INTO function_input_tags (function_id, tag_id) VALUES (?, ?)",
-            id,
-            tag_id
-        )
-        .execute(&state.db)
-        .await;
-    }
+    // Save function to database (disabled by default)
+    let functions = FunctionRepo::new(&state.db);
+    functions
+        .insert(&NewFunction {
+            id: &id,
+            name: &payload.name,
+            script_filename: &script_filename,
+            function_type: &payload.function_type,
+            executor: &payload.executor,
+            trigger_conditions: &payload.trigger_conditions,
+            created_at: &created_at,
+            expression: payload.expression.as_deref(),
+        })
+        .await
+        .map_err(conflict_or_internal)?;
 
-    // Add output tags
-    for tag_id in &payload.output_tag_ids {
-        let _ = sqlx::query!(
-            "INSERT INTO function_output_tags (function_id, tag_id) VALUES (?, ?)",
-            id,
-            tag_id
+    functions
+        .set_input_tags(&id, &payload.input_tag_ids)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    functions
+        .set_output_tags(&id, &payload.output_tag_ids)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    functions
+        .set_excluded_tags(&id, &payload.excluded_tag_ids)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    functions
+        .set_intermediate_outputs(
+            &id,
+            &payload.intermediate_outputs,
+            payload.intermediate_ttl_hours,
         )
-        .execute(&state.db)
-        .await;
-    }
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     // Fetch tags for response
-    let input_tags = sqlx::query_as!(
-        Tag,
-        r#"SELECT t.id as "id!", t.name as "name!", t.color as "color!", t.created_at as "created_at!"
-           FROM tags t
-           INNER JOIN function_input_tags fit ON t.id = fit.tag_id
-           WHERE fit.function_id = ?"#,
-        id
-    )
-    .fetch_all(&state.db)
-    .await
-    .unwrap_or_default();
-
-    let output_tags = sqlx::query_as!(
-        Tag,
-        r#"SELECT t.id as "id!", t.name as "name!", t.color as "color!", t.created_at as "created_at!"
-           FROM tags t
-           INNER JOIN function_output_tags fot ON t.id = fot.tag_id
-           WHERE fot.function_id = ?"#,
-        id
-    )
-    .fetch_all(&state.db)
-    .await
-    .unwrap_or_default();
+    let input_tags = functions.input_tags(&id).await.unwrap_or_default();
+    let output_tags = functions.output_tags(&id).await.unwrap_or_default();
+    let excluded_tags = functions.excluded_tags(&id).await.unwrap_or_default();
+    let script_analysis = payload
+        .expression
+        .is_none()
+        .then(|| script_analysis::analyze(&payload.script_content));
 
     Ok((
         StatusCode::CREATED,
@@ -1144,9 +2544,18 @@ async fn create_function(
             script_filename,
             enabled: false, // Always disabled by default
             function_type: payload.function_type,
+            executor: payload.executor,
+            trigger_conditions: payload.trigger_conditions,
+            intermediate_outputs: payload.intermediate_outputs,
+            intermediate_ttl_hours: payload.intermediate_ttl_hours,
             created_at,
+            expression: payload.expression,
+            quarantined_at: None,
+            quarantine_reason: None,
             input_tags,
             output_tags,
+            excluded_tags,
+            script_analysis,
             script_content: None,
         }),
     ))
@@ -1156,65 +2565,27 @@ async fn get_function(
     State(state): State<Arc<AppState>>,
     Path(id): Path<String>,
 ) -> Result<Json<Function>, StatusCode> {
-    #[derive(sqlx::FromRow)]
-    struct FunctionRow {
-        id: String,
-        name: String,
-        script_filename: String,
-        enabled: i64,
-        function_type: String,
-        created_at: String,
-    }
-
-    let func_row = sqlx::query_as!(
-        FunctionRow,
-        r#"SELECT id as "id!", name as "name!", script_filename as "script_filename!", enabled as "enabled!", function_type as "function_type!", created_at as "created_at!" FROM functions WHERE id = ?"#,
-        id
-    )
-    .fetch_optional(&state.db)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-    .ok_or(StatusCode::NOT_FOUND)?;
-
-    let input_tags = sqlx::query_as!(
-        Tag,
-        r#"SELECT t.id as "id!", t.name as "name!", t.color as "color!", t.created_at as "created_at!"
-           FROM tags t
-           INNER JOIN function_input_tags fit ON t.id = fit.tag_id
-           WHERE fit.function_id = ?"#,
-        func_row.id
-    )
-    .fetch_all(&state.db)
-    .await
-    .unwrap_or_default();
+    let functions = FunctionRepo::new(&state.db);
+    let function = functions
+        .get(&id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
 
-    let output_tags = sqlx::query_as!(
-        Tag,
-        r#"SELECT t.id as "id!", t.name as "name!", t.color as "color!", t.created_at as "created_at!"
-           FROM tags t
-           INNER JOIN function_output_tags fot ON t.id = fot.tag_id
-           WHERE fot.function_id = ?"#,
-        func_row.id
-    )
-    .fetch_all(&state.db)
-    .await
-    .unwrap_or_default();
+    let input_tags = functions.input_tags(&id).await.unwrap_or_default();
+    let output_tags = functions.output_tags(&id).await.unwrap_or_default();
+    let excluded_tags = functions.excluded_tags(&id).await.unwrap_or_default();
 
     // Read script content from file
-    let script_path = format!("scripts/{}", func_row.script_filename);
+    let script_path = state.executor.scripts_dir().join(&function.script_filename);
     let script_content = tokio::fs::read_to_string(&script_path).await.ok();
 
-    Ok(Json(Function {
-        id: func_row.id,
-        name: func_row.name,
-        script_filename: func_row.script_filename,
-        enabled: func_row.enabled != 0,
-        function_type: func_row.function_type,
-        created_at: func_row.created_at,
+    Ok(Json(function.into_function(
         input_tags,
         output_tags,
+        excluded_tags,
         script_content,
-    }))
+    )))
 }
 
 async fn update_function(
@@ -1222,447 +2593,6575 @@ async fn update_function(
     Path(id): Path<String>,
     Json(payload): Json<UpdateFunction>,
 ) -> Result<Json<Function>, StatusCode> {
+    let functions = FunctionRepo::new(&state.db);
+
     // Check if function exists
-    let _existing = sqlx::query!(
-        r#"SELECT script_filename as "script_filename!" FROM functions WHERE id = ?"#,
-        id
-    )
-    .fetch_optional(&state.db)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-    .ok_or(StatusCode::NOT_FOUND)?;
+    let function = functions
+        .get(&id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    // Quick functions change their expression, script functions their script
+    let is_quick = function.expression.is_some();
+    if (is_quick && payload.script_content.is_some()) || (!is_quick && payload.expression.is_some())
+    {
+        tracing::warn!(
+            "Function {} cannot switch between a script and an expression",
+            id
+        );
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if is_quick {
+        validate_quick_function_payload(
+            payload
+                .expression
+                .as_deref()
+                .or(function.expression.as_deref())
+                .unwrap_or_default(),
+            payload.executor.as_deref().unwrap_or(&function.executor),
+        )?;
+    }
+    if let Some(expression) = &payload.expression {
+        functions
+            .set_expression(&id, expression)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
 
     // Update script content if provided
     if let Some(script_content) = &payload.script_content {
-        let created_at = chrono::Utc::now().to_rfc3339();
+        let created_at = timestamps::now();
         let script_filename = format!("{}_{}.py", created_at.replace([':', '-', '.'], "_"), id);
-        let script_path = format!("scripts/{}", script_filename);
+        let script_path = state.executor.scripts_dir().join(&script_filename);
 
         tokio::fs::write(&script_path, script_content)
             .await
             .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-        sqlx::query!(
-            "UPDATE functions SET script_filename = ? WHERE id = ?",
-            script_filename,
-            id
-        )
-        .execute(&state.db)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        functions
+            .set_script(&id, &script_filename)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
+    // Update name if provided
+    if let Some(name) = &payload.name {
+        functions
+            .rename(&id, name)
+            .await
+            .map_err(conflict_or_internal)?;
+    }
+
+    if payload.input_tag_ids.is_some() || payload.excluded_tag_ids.is_some() {
+        let input_tag_ids = match &payload.input_tag_ids {
+            Some(ids) => ids.clone(),
+            None => functions
+                .input_tag_ids(&id)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        };
+        let excluded_tag_ids = match &payload.excluded_tag_ids {
+            Some(ids) => ids.clone(),
+            None => functions
+                .excluded_tag_ids(&id)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        };
+        validate_excluded_tags(&input_tag_ids, &excluded_tag_ids)?;
+    }
+
+    // Update input tags if provided
+    if let Some(input_tag_ids) = &payload.input_tag_ids {
+        functions
+            .set_input_tags(&id, input_tag_ids)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
+    // Update output tags if provided
+    if let Some(output_tag_ids) = &payload.output_tag_ids {
+        functions
+            .set_output_tags(&id, output_tag_ids)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
+    // Update excluded tags if provided
+    if let Some(excluded_tag_ids) = &payload.excluded_tag_ids {
+        functions
+            .set_excluded_tags(&id, excluded_tag_ids)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
+    // Update function_type if provided
+    if let Some(function_type) = &payload.function_type {
+        functions
+            .set_function_type(&id, function_type)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
+    // Update executor if provided
+    if let Some(executor) = &payload.executor {
+        validate_executor(&state, executor)?;
+        functions
+            .set_executor(&id, executor)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
+    // Update trigger conditions if provided
+    if let Some(trigger_conditions) = &payload.trigger_conditions {
+        validate_trigger_conditions(trigger_conditions)?;
+        functions
+            .set_trigger_conditions(&id, trigger_conditions)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
+    // Update intermediate outputs if provided
+    if payload.intermediate_outputs.is_some() || payload.intermediate_ttl_hours.is_some() {
+        let patterns = payload
+            .intermediate_outputs
+            .clone()
+            .unwrap_or_else(|| function.intermediate_patterns());
+        let ttl_hours = payload
+            .intermediate_ttl_hours
+            .unwrap_or(function.intermediate_ttl_hours);
+        validate_intermediate_outputs(&patterns, ttl_hours)?;
+        functions
+            .set_intermediate_outputs(&id, &patterns, ttl_hours)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
+    // Update enabled status if provided - check for cycles when enabling
+    if let Some(enabled) = payload.enabled {
+        if enabled {
+            // Check for cycles before enabling
+            let mut graph = DirectedGraph::new();
+
+            // Add all currently ENABLED functions to graph, plus THIS function with its current
+            // tags
+            let mut function_ids: Vec<String> = functions
+                .enabled()
+                .await
+                .unwrap_or_default()
+                .into_iter()
+                .map(|f| f.id)
+                .collect();
+            function_ids.push(id.clone());
+
+            for function_id in &function_ids {
+                let input_tags = functions
+                    .input_tag_ids(function_id)
+                    .await
+                    .unwrap_or_default();
+                let output_tags = functions
+                    .output_tag_ids(function_id)
+                    .await
+                    .unwrap_or_default();
+                graph.add_edges(&input_tags, &output_tags);
+            }
+
+            // Reject if enabling would cause cycle
+            if graph.has_cycle() {
+                tracing::warn!("Cannot enable function {}: would create cycle", id);
+                return Err(StatusCode::CONFLICT); // 409 - Conflict due to cycle
+            }
+        }
+
+        functions
+            .set_enabled(&id, enabled)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        // Enabling a quarantined function is the manual sign-off that it was fixed
+        if enabled && function.quarantined_at.is_some() {
+            functions
+                .release(&id)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            tracing::info!("Function {} was released from quarantine", id);
+        }
+    }
+
+    // Return updated function
+    get_function(State(state), Path(id)).await
+}
+
+async fn delete_function(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    // Delete from database
+    if !FunctionRepo::new(&state.db)
+        .delete(&id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    // Delete script file (all versions)
+    if let Ok(mut entries) = tokio::fs::read_dir("scripts").await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            if let Some(name) = entry.file_name().to_str() {
+                if name.ends_with(&format!("_{}.py", id)) {
+                    let _ = tokio::fs::remove_file(entry.path()).await;
+                }
+            }
+        }
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct AnalyzeScriptRequest {
+    script_content: String,
+}
+
+// What a script looks like it writes and whether it uses the network, before creating a
+// function from it
+async fn analyze_function_script(
+    Json(payload): Json<AnalyzeScriptRequest>,
+) -> Json<ScriptAnalysis> {
+    Json(script_analysis::analyze(&payload.script_content))
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct EstimateQuery {
+    upload_id: Option<String>, // the input to estimate for
+    input_bytes: Option<i64>,  // or just its size
+}
+
+// What running a function on an input is expected to take, before starting it
+async fn estimate_function_run(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Query(params): Query<EstimateQuery>,
+) -> Result<Response, StatusCode> {
+    FunctionRepo::new(&state.db)
+        .get(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let input_bytes = match (&params.upload_id, params.input_bytes) {
+        (Some(upload_id), _) => {
+            let upload = UploadRepo::new(&state.db)
+                .get(upload_id)
+                .await
+                .map_err(|e| internal_error(e.to_string()))?;
+            match upload {
+                Some(upload) => upload.file_size,
+                None => {
+                    return Ok(json_error(StatusCode::NOT_FOUND, "Upload not found").into_response())
+                }
+            }
+        }
+        (None, Some(input_bytes)) if input_bytes >= 0 => input_bytes,
+        _ => {
+            return Ok(json_error(
+                StatusCode::BAD_REQUEST,
+                "Provide an upload_id or input_bytes",
+            )
+            .into_response())
+        }
+    };
+    match estimate_run(&state, &id, input_bytes)
+        .await
+        .map_err(internal_error)?
+    {
+        Some(estimate) => Ok(Json(estimate).into_response()),
+        None => Ok(json_error(
+            StatusCode::NOT_FOUND,
+            "The function has no successful runs to estimate from",
+        )
+        .into_response()),
+    }
+}
+
+// Run a function on a sample of a CSV/Parquet upload and preview what it makes of it
+async fn preview_function_on_sample(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    Json(request): Json<FunctionPreviewRequest>,
+) -> Result<Response, StatusCode> {
+    if let Err(message) = request.sample.validate() {
+        return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response());
+    }
+    // A hidden upload is not found, as when it does not exist
+    let requester = Requester::from_headers(&headers);
+    match check_upload_access(&state, &requester, &request.upload_id).await {
+        Err(StatusCode::NOT_FOUND) => {
+            let message = format!("Upload not found: {}", request.upload_id);
+            return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response());
+        }
+        result => result?,
+    }
+    let function = FunctionRepo::new(&state.db)
+        .get(&id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let Some(upload) = UploadRepo::new(&state.db)
+        .get(&request.upload_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    else {
+        let message = format!("Upload not found: {}", request.upload_id);
+        return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response());
+    };
+    let extension = std::path::Path::new(&upload.original_filename)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase());
+    if !matches!(extension.as_deref(), Some("csv" | "parquet")) {
+        let message = "Previews need a CSV or Parquet upload to sample";
+        return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response());
+    }
+
+    let rows = request.sample.rows() as u64;
+    if rows == 0 {
+        return Ok(
+            json_error(StatusCode::BAD_REQUEST, "The sample must have rows").into_response(),
+        );
+    }
+    let slice = match request.sample.method.as_deref() {
+        Some("random") => InputSlice::Sample {
+            rows,
+            seed: request.sample.seed,
+        },
+        _ => InputSlice::Rows {
+            start: 0,
+            end: rows,
+        },
+    };
+    let preview = preview_function(&state, &function, &upload, slice)
+        .await
+        .map_err(internal_error)?;
+    Ok(Json(preview).into_response())
+}
+
+// ============= JOBS =============
+
+#[derive(Debug, serde::Deserialize)]
+struct JobListQuery {
+    assignee: Option<String>,
+    upload_id: Option<String>,
+    created_after: Option<String>,
+    created_before: Option<String>,
+    tz: Option<String>,
+    label: Option<String>,    // jobs annotated with this triage label
+    snapshot: Option<String>, // jobs queued while this pipeline snapshot was active
+    #[serde(default)]
+    sort: SortKey, // by name means by function name, by size by input size
+    order: Option<SortOrder>,
+}
+
+async fn list_jobs(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<JobListQuery>,
+) -> Result<Response, StatusCode> {
+    let (created_after, created_before) = match created_range(
+        params.created_after.as_deref(),
+        params.created_before.as_deref(),
+        params.tz.as_deref(),
+    ) {
+        Ok(range) => range,
+        Err(message) => return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response()),
+    };
+    let label = params.label.as_deref().and_then(normalize_label);
+    let mut jobs = JobRepo::new(&state.db)
+        .list(
+            &JobFilter {
+                assignee: params.assignee.as_deref(),
+                upload_id: params.upload_id.as_deref(),
+                created_after: created_after.as_deref(),
+                created_before: created_before.as_deref(),
+                label: label.as_deref(),
+                snapshot_id: params.snapshot.as_deref(),
+            },
+            Sort {
+                key: params.sort,
+                order: params.order,
+            },
+        )
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    add_estimates(&state, &mut jobs)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(Json(jobs).into_response())
+}
+
+async fn get_job(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<Job>, StatusCode> {
+    let job = JobRepo::new(&state.db)
+        .get(&id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let mut jobs = [job];
+    add_estimates(&state, &mut jobs)
+        .await
+        .map_err(internal_error)?;
+    let [job] = jobs;
+
+    Ok(Json(job))
+}
+
+async fn list_job_annotations(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Response, StatusCode> {
+    let jobs = JobRepo::new(&state.db);
+    jobs.get(&id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let annotations = jobs
+        .annotations(&id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(annotations).into_response())
+}
+
+// Label a job for triage, e.g. `flaky` with a note on the instrument glitch behind it
+async fn annotate_job(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(request): Json<CreateJobAnnotation>,
+) -> Result<Response, StatusCode> {
+    let Some(label) = normalize_label(&request.label) else {
+        let message = "Labels must be between 1 and 64 characters";
+        return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response());
+    };
+    let author = match normalize_assignee(request.author) {
+        Ok(author) => author,
+        Err(message) => return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response()),
+    };
+    let note = request
+        .note
+        .as_deref()
+        .map(str::trim)
+        .filter(|note| !note.is_empty());
+
+    let jobs = JobRepo::new(&state.db);
+    jobs.get(&id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let annotation = jobs
+        .add_annotation(&id, &label, note, author.as_deref())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok((StatusCode::CREATED, Json(annotation)).into_response())
+}
+
+async fn delete_job_annotation(
+    State(state): State<Arc<AppState>>,
+    Path((id, annotation_id)): Path<(String, String)>,
+) -> Result<StatusCode, StatusCode> {
+    if !JobRepo::new(&state.db)
+        .delete_annotation(&id, &annotation_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct JobStatsQuery {
+    created_after: Option<String>,
+    created_before: Option<String>,
+    tz: Option<String>,
+    exclude_labels: Option<String>, // comma-separated, e.g. `flaky,instrument`
+}
+
+// Failure rates per function, leaving out failures triaged as noise
+async fn get_job_stats(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<JobStatsQuery>,
+) -> Result<Response, StatusCode> {
+    let (created_after, created_before) = match created_range(
+        params.created_after.as_deref(),
+        params.created_before.as_deref(),
+        params.tz.as_deref(),
+    ) {
+        Ok(range) => range,
+        Err(message) => return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response()),
+    };
+    let excluded_labels: Vec<String> = params
+        .exclude_labels
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(normalize_label)
+        .collect();
+
+    let outcomes = JobRepo::new(&state.db)
+        .outcomes(
+            created_after.as_deref(),
+            created_before.as_deref(),
+            SHUTDOWN_MESSAGE,
+        )
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(summarize(outcomes, excluded_labels)).into_response())
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct CapacityQuery {
+    concurrency: Option<String>, // comma-separated slot counts; the current setting if unset
+    created_after: Option<String>,
+    created_before: Option<String>,
+    tz: Option<String>,
+}
+
+// Replay earlier local jobs through other --max-concurrent-jobs settings, to tune it
+async fn get_capacity(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<CapacityQuery>,
+) -> Result<Response, StatusCode> {
+    let current = state.settings().values.max_concurrent_jobs;
+    let concurrencies = match parse_concurrencies(params.concurrency.as_deref(), current) {
+        Ok(concurrencies) => concurrencies,
+        Err(message) => return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response()),
+    };
+    let (created_after, created_before) = match created_range(
+        params.created_after.as_deref(),
+        params.created_before.as_deref(),
+        params.tz.as_deref(),
+    ) {
+        Ok(range) => range,
+        Err(message) => return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response()),
+    };
+    let runs = JobRepo::new(&state.db)
+        .local_runs(created_after.as_deref(), created_before.as_deref())
+        .await
+        .map_err(|e| internal_error(e.to_string()))?;
+    Ok(Json(capacity::report(&runs, &concurrencies, current)).into_response())
+}
+
+async fn get_table_preview(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Query(query): Query<TableQuery>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    // Get file info from database
+    let upload = sqlx::query!(
+        r#"SELECT filename as "filename!", original_filename as "original_filename!", detected_mime_type, compression FROM uploads WHERE id = ?"#,
+        id
+    )
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    // Get file extension
+    let extension = effective_extension(
+        &upload.original_filename,
+        upload.detected_mime_type.as_deref(),
+    )
+    .unwrap_or_default();
+
+    // Check if file type is supported for table preview
+    if !matches!(extension.as_str(), "csv" | "parquet") {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    // Build file path
+    let file_path = plain_upload_path(&state, &upload.filename, upload.compression.as_deref())
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?
+        .to_string_lossy()
+        .to_string();
+
+    let arrow = query.format.as_deref() == Some("arrow");
+
+    // Polars drives its own runtime for lazy scans, so table work runs on the blocking pool
+    let result = tokio::task::spawn_blocking({
+        let file_path = file_path.clone();
+        move || {
+            validate_table_query(&file_path, &extension, &query)
+                .map_err(|message| (StatusCode::BAD_REQUEST, message))?;
+            plan_table_page(&file_path, &extension, &query)
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+        }
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let page = match result {
+        Ok(page) => page,
+        Err((StatusCode::BAD_REQUEST, message)) => {
+            // Invalid columns or filter: tell the client what was wrong
+            return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response());
+        }
+        Err((status, message)) => {
+            tracing::error!("Failed to parse table file {}: {}", file_path, message);
+            return Err(status);
+        }
+    };
+
+    // Large pages are streamed a batch of rows at a time instead of being read at once
+    if !arrow && accepts_ndjson(&headers) {
+        return stream_table_ndjson(page.batches(NDJSON_BATCH_ROWS)).await;
+    }
+
+    let slice = tokio::task::spawn_blocking(move || page.collect().map_err(|e| e.to_string()))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map_err(|e| {
+            tracing::error!("Failed to parse table file {}: {}", file_path, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    if arrow {
+        return table_arrow_response(slice).await;
+    }
+
+    Ok(Json(slice.into_preview()).into_response())
+}
+
+fn accepts_ndjson(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| accept.contains("application/x-ndjson"))
+}
+
+// Encode a table page as an Arrow IPC stream for zero-copy loading in pandas/pyarrow/arrow-js
+async fn table_arrow_response(mut slice: TableSlice) -> Result<Response, StatusCode> {
+    let buffer =
+        tokio::task::spawn_blocking(move || slice.write_arrow_ipc().map_err(|e| e.to_string()))
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .map_err(|e| {
+                tracing::error!("Failed to encode table as Arrow: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/vnd.apache.arrow.stream")
+        .header(header::CONTENT_LENGTH, buffer.len())
+        .body(axum::body::Body::from(buffer))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+// Stream a table as NDJSON, reading its batches and flushing rows in chunks as they are
+// serialized. A batch that cannot be read ends the response early.
+async fn stream_table_ndjson(
+    batches: impl Iterator<Item = Result<TableSlice, Box<dyn std::error::Error>>> + Send + 'static,
+) -> Result<Response, StatusCode> {
+    let (tx, rx) = tokio::sync::mpsc::channel::<Result<String, std::io::Error>>(8);
+    tokio::task::spawn_blocking(move || {
+        let mut chunk = String::new();
+        for (i, batch) in batches.enumerate() {
+            let slice = match batch {
+                Ok(slice) => slice,
+                Err(e) => {
+                    tracing::error!("Failed to read table batch: {}", e);
+                    let _ = tx.blocking_send(Err(std::io::Error::other(e.to_string())));
+                    return;
+                }
+            };
+            let meta = (i == 0).then(|| slice.ndjson_meta());
+            for line in meta.into_iter().chain(slice.ndjson_rows()) {
+                chunk.push_str(&line);
+                chunk.push('\n');
+                if chunk.len() >= NDJSON_CHUNK_BYTES
+                    && tx.blocking_send(Ok(std::mem::take(&mut chunk))).is_err()
+                {
+                    return; // Client went away
+                }
+            }
+        }
+        if !chunk.is_empty() {
+            let _ = tx.blocking_send(Ok(chunk));
+        }
+    });
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/x-ndjson")
+        .body(axum::body::Body::from_stream(ReceiverStream::new(rx)))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// A CSV/Parquet upload resolved to its file on disk
+struct TableUpload {
+    file_path: String,
+    extension: String,
+    original_filename: String,
+}
+
+async fn fetch_table_upload(state: &Arc<AppState>, id: &str) -> Result<TableUpload, StatusCode> {
+    let upload = sqlx::query!(
+        r#"SELECT filename as "filename!", original_filename as "original_filename!", detected_mime_type, compression FROM uploads WHERE id = ?"#,
+        id
+    )
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    let extension = effective_extension(
+        &upload.original_filename,
+        upload.detected_mime_type.as_deref(),
+    )
+    .unwrap_or_default();
+
+    if !matches!(extension.as_str(), "csv" | "parquet") {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let file_path = plain_upload_path(state, &upload.filename, upload.compression.as_deref())
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+    Ok(TableUpload {
+        file_path: file_path.to_string_lossy().to_string(),
+        extension,
+        original_filename: upload.original_filename,
+    })
+}
+
+// Pivot a CSV/Parquet upload into wide form, e.g. plate-reader exports (row x column -> value)
+async fn pivot_upload(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(request): Json<PivotRequest>,
+) -> Result<Response, StatusCode> {
+    let TableUpload {
+        file_path,
+        extension,
+        ..
+    } = fetch_table_upload(&state, &id).await?;
+
+    let result = tokio::task::spawn_blocking({
+        let file_path = file_path.clone();
+        move || {
+            validate_pivot_request(&file_path, &extension, &request)
+                .map_err(|message| (StatusCode::BAD_REQUEST, message))?;
+            pivot_table(&file_path, &extension, &request)
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+        }
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    match result {
+        Ok(slice) => Ok(Json(slice.into_preview()).into_response()),
+        Err((StatusCode::BAD_REQUEST, message)) => {
+            Ok(json_error(StatusCode::BAD_REQUEST, message).into_response())
+        }
+        Err((status, message)) => {
+            tracing::error!("Failed to pivot table file {}: {}", file_path, message);
+            Err(status)
+        }
+    }
+}
+
+// Return a small sample of a CSV/Parquet upload
+async fn get_table_sample(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Query(query): Query<SampleQuery>,
+) -> Result<Response, StatusCode> {
+    if let Err(message) = query.validate() {
+        return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response());
+    }
+
+    let TableUpload {
+        file_path,
+        extension,
+        ..
+    } = fetch_table_upload(&state, &id).await?;
+
+    let slice = tokio::task::spawn_blocking({
+        let file_path = file_path.clone();
+        move || sample_table(&file_path, &extension, &query).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .map_err(|e| {
+        tracing::error!("Failed to sample table file {}: {}", file_path, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(slice.into_preview()).into_response())
+}
+
+// Save a sample of a CSV/Parquet upload as a new upload in the same format
+async fn materialize_table_sample(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Query(query): Query<SampleQuery>,
+) -> Result<Response, StatusCode> {
+    let request = DeriveRequest {
+        operation: DeriveOperation::Sample(query),
+        filename: None,
+        tags: Vec::new(),
+    };
+    derive_table_upload(&state, &id, request).await
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct SaveParams {
+    #[serde(default)]
+    save: bool,
+}
+
+// Downsample a time series into fixed windows, returning a preview or saving it (`?save=true`)
+async fn resample_upload(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Query(params): Query<SaveParams>,
+    Json(request): Json<ResampleRequest>,
+) -> Result<Response, StatusCode> {
+    let operation = DeriveOperation::Resample(request);
+    if params.save {
+        let request = DeriveRequest {
+            operation,
+            filename: None,
+            tags: Vec::new(),
+        };
+        return derive_table_upload(&state, &id, request).await;
+    }
+
+    let TableUpload {
+        file_path,
+        extension,
+        ..
+    } = fetch_table_upload(&state, &id).await?;
+
+    let result = tokio::task::spawn_blocking({
+        let file_path = file_path.clone();
+        move || {
+            let operation = operation
+                .prepare(&file_path, &extension)
+                .map_err(|message| (StatusCode::BAD_REQUEST, message))?;
+            operation
+                .run(&file_path, &extension)
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+        }
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    match result {
+        Ok(slice) => {
+            let slice = TableSlice {
+                df: slice.df.head(Some(MAX_RESAMPLE_PREVIEW_ROWS)),
+                ..slice
+            };
+            Ok(Json(slice.into_preview()).into_response())
+        }
+        Err((StatusCode::BAD_REQUEST, message)) => {
+            Ok(json_error(StatusCode::BAD_REQUEST, message).into_response())
+        }
+        Err((status, message)) => {
+            tracing::error!("Failed to resample table file {}: {}", file_path, message);
+            Err(status)
+        }
+    }
+}
+
+// Render a PNG/SVG chart of a table, returning the image or saving it (`?save=true`)
+async fn plot_upload(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Query(params): Query<SaveParams>,
+    Json(request): Json<PlotRequest>,
+) -> Result<Response, StatusCode> {
+    if request
+        .filename
+        .as_deref()
+        .is_some_and(|name| !is_valid_filename(name))
+    {
+        return Ok(json_error(StatusCode::BAD_REQUEST, "Invalid filename").into_response());
+    }
+
+    let TableUpload {
+        file_path,
+        extension,
+        original_filename,
+    } = fetch_table_upload(&state, &id).await?;
+
+    let result = tokio::task::spawn_blocking({
+        let file_path = file_path.clone();
+        move || {
+            validate_plot(&file_path, &extension, &request)
+                .map_err(|message| (StatusCode::BAD_REQUEST, message))?;
+            let plot = render_table_plot(&file_path, &extension, &request)
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            Ok((request, plot))
+        }
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let (request, plot) = match result {
+        Ok(output) => output,
+        Err((StatusCode::BAD_REQUEST, message)) => {
+            return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response());
+        }
+        Err((status, message)) => {
+            tracing::error!("Failed to plot table file {}: {}", file_path, message);
+            return Err(status);
+        }
+    };
+
+    if !params.save {
+        return Ok(([(header::CONTENT_TYPE, plot.format.mime_type())], plot.data).into_response());
+    }
+
+    let extension = plot.format.extension();
+    let stem = original_filename
+        .rsplit_once('.')
+        .map_or(original_filename.as_str(), |(stem, _)| stem);
+    let filename = match &request.filename {
+        Some(name) if name.to_lowercase().ends_with(&format!(".{}", extension)) => name.clone(),
+        Some(name) => format!("{}.{}", name, extension),
+        None => format!("{}_{}.{}", stem, request.kind.name(), extension),
+    };
+    // Stored with the lineage so the plot can be re-rendered
+    let query = serde_json::to_string(&request).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let upload = store_upload(
+        &state,
+        filename,
+        plot.data,
+        Some(plot.format.mime_type().to_string()),
+        request.tags,
+    )
+    .await
+    .map_err(internal_error)?;
+
+    let lineage_id = Uuid::new_v4().to_string();
+    sqlx::query!(
+        "INSERT INTO file_lineage (id, output_upload_id, source_upload_id, function_id, operation, query, success, created_at) VALUES (?, ?, ?, NULL, 'plot', ?, 1, ?)",
+        lineage_id,
+        upload.id,
+        id,
+        query,
+        upload.created_at
+    )
+    .execute(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok((StatusCode::CREATED, Json(upload)).into_response())
+}
+
+// Save the full result of a query, pivot, sample, conversion or resample as a new upload with
+// lineage
+async fn derive_upload(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(request): Json<DeriveRequest>,
+) -> Result<Response, StatusCode> {
+    derive_table_upload(&state, &id, request).await
+}
+
+// Filenames chosen by clients (URL uploads, derived files, renames) must not be empty or
+// contain path separators
+fn is_valid_filename(name: &str) -> bool {
+    !name.trim().is_empty() && !name.contains(['/', '\\'])
+}
+
+async fn derive_table_upload(
+    state: &Arc<AppState>,
+    id: &str,
+    request: DeriveRequest,
+) -> Result<Response, StatusCode> {
+    if request
+        .filename
+        .as_deref()
+        .is_some_and(|name| !is_valid_filename(name))
+    {
+        return Ok(json_error(StatusCode::BAD_REQUEST, "Invalid filename").into_response());
+    }
+
+    let TableUpload {
+        file_path,
+        extension,
+        original_filename,
+    } = fetch_table_upload(state, id).await?;
+
+    let operation_name = request.operation.name();
+
+    let result = tokio::task::spawn_blocking({
+        let file_path = file_path.clone();
+        let extension = extension.clone();
+        let operation = request.operation;
+        move || {
+            let operation = operation
+                .prepare(&file_path, &extension)
+                .map_err(|message| (StatusCode::BAD_REQUEST, message))?;
+            let mut slice = operation
+                .run(&file_path, &extension)
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            let data = slice
+                .write_file()
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            Ok((operation, slice.df.height(), data))
+        }
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let (operation, rows, data) = match result {
+        Ok(output) => output,
+        Err((StatusCode::BAD_REQUEST, message)) => {
+            return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response());
+        }
+        Err((status, message)) => {
+            tracing::error!(
+                "Failed to derive {} from {}: {}",
+                operation_name,
+                file_path,
+                message
+            );
+            return Err(status);
+        }
+    };
+    // Stored with the lineage so the derivation can be replayed against /derive
+    let query = serde_json::to_string(&operation).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // Derived files keep the source format, so force the matching extension
+    let stem = original_filename
+        .rsplit_once('.')
+        .map_or(original_filename.as_str(), |(stem, _)| stem);
+    let filename = match request.filename {
+        Some(name) if name.to_lowercase().ends_with(&format!(".{}", extension)) => name,
+        Some(name) => format!("{}.{}", name, extension),
+        None if operation_name == "sample" => format!("{}_sample_{}.{}", stem, rows, extension),
+        None => format!("{}_{}.{}", stem, operation_name, extension),
+    };
+
+    let upload = store_upload(state, filename, data, None, request.tags)
+        .await
+        .map_err(internal_error)?;
+
+    let lineage_id = Uuid::new_v4().to_string();
+    sqlx::query!(
+        "INSERT INTO file_lineage (id, output_upload_id, source_upload_id, function_id, operation, query, success, created_at) VALUES (?, ?, ?, NULL, ?, ?, 1, ?)",
+        lineage_id,
+        upload.id,
+        id,
+        operation_name,
+        query,
+        upload.created_at
+    )
+    .execute(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // Record the new units of converted columns in the output's data dictionary
+    if let DeriveOperation::Convert(request) = &operation {
+        let dictionary = DataDictionary {
+            columns: request
+                .converted_headers()
+                .into_iter()
+                .map(|(column, unit)| {
+                    (
+                        column,
+                        ColumnInfo {
+                            description: None,
+                            unit: Some(unit),
+                        },
+                    )
+                })
+                .collect(),
+        };
+        UploadRepo::new(&state.db)
+            .set_dictionary(&upload.id, &dictionary)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
+    Ok((StatusCode::CREATED, Json(upload)).into_response())
+}
+
+// ============= JOB RESULTS =============
+
+// Read the outputs and manifest a worker pushed, staging files next to the uploads
+async fn read_job_completion(
+    state: &AppState,
+    multipart: &mut Multipart,
+    staged: &mut Vec<(String, std::path::PathBuf)>,
+) -> Result<JobCompletion, Response> {
+    let mut completion = None;
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| multipart_error(state, e))?
+    {
+        match field.name() {
+            Some("file") => {
+                let filename = field
+                    .file_name()
+                    .and_then(|name| name.rsplit(['/', '\\']).next())
+                    .filter(|name| !name.is_empty() && *name != "." && *name != "..")
+                    .map(str::to_string)
+                    .ok_or_else(|| {
+                        json_error(StatusCode::BAD_REQUEST, "Every file part needs a filename")
+                            .into_response()
+                    })?;
+                let data = field.bytes().await.map_err(|e| multipart_error(state, e))?;
+                let path = state
+                    .executor
+                    .uploads_dir()
+                    .join(format!("incoming_{}", Uuid::new_v4()));
+                tokio::fs::write(&path, &data)
+                    .await
+                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
+                staged.push((filename, path));
+            }
+            Some("manifest") => {
+                let text = field.text().await.map_err(|e| multipart_error(state, e))?;
+                completion = Some(serde_json::from_str(&text).map_err(|e| {
+                    json_error(StatusCode::BAD_REQUEST, format!("Invalid manifest: {}", e))
+                        .into_response()
+                })?);
+            }
+            _ => {}
+        }
+    }
+    Ok(completion.unwrap_or_default())
+}
+
+// Back-channel for remote workers: push a job's outputs (or its failure) with the job token
+async fn complete_job(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    mut multipart: Multipart,
+) -> Result<Response, StatusCode> {
+    let jobs = JobRepo::new(&state.db);
+    let job = jobs
+        .completion_state(&id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim);
+    match (token, &job.completion_token_hash) {
+        (Some(token), Some(token_hash)) if sha256_hex(token.as_bytes()) == *token_hash => {}
+        _ => {
+            return Ok(
+                json_error(StatusCode::UNAUTHORIZED, "Missing or invalid job token")
+                    .into_response(),
+            )
+        }
+    }
+
+    // Retries of a finished completion get the job back instead of registering outputs twice
+    if job.completed_via.is_some() {
+        let Json(finished) = get_job(State(state.clone()), Path(id)).await?;
+        if matches!(finished.status.as_str(), "SUCCESS" | "FAILED") {
+            return Ok(Json(finished).into_response());
+        }
+        return Ok(
+            json_error(StatusCode::CONFLICT, "The job is already being completed").into_response(),
+        );
+    }
+
+    let mut staged = Vec::new();
+    let completion = match read_job_completion(&state, &mut multipart, &mut staged).await {
+        Ok(completion) => completion,
+        Err(response) => {
+            for (_, path) in staged {
+                let _ = tokio::fs::remove_file(path).await;
+            }
+            return Ok(response);
+        }
+    };
+
+    if !jobs.claim_completion(&id, "worker").await {
+        for (_, path) in staged {
+            let _ = tokio::fs::remove_file(path).await;
+        }
+        return Ok(
+            json_error(StatusCode::CONFLICT, "The job is already being completed").into_response(),
+        );
+    }
+
+    if let Some(error_message) = &completion.error_message {
+        // The worker could not run the function at all
+        for (_, path) in staged {
+            let _ = tokio::fs::remove_file(path).await;
+        }
+        fail_job(&state, &id, &job.upload_id, error_message).await;
+    } else {
+        let mut dictionaries = completion.dictionaries;
+        let mut outputs: Vec<JobOutput> = Vec::new();
+        if completion.success {
+            for (filename, path) in staged {
+                outputs.push(JobOutput {
+                    dictionary: dictionaries.remove(&filename),
+                    filename,
+                    path,
+                });
+            }
+        } else {
+            // Like a failing local script: the log becomes the only output
+            for (_, path) in staged {
+                let _ = tokio::fs::remove_file(path).await;
+            }
+            let path = state
+                .executor
+                .uploads_dir()
+                .join(format!("incoming_{}", Uuid::new_v4()));
+            tokio::fs::write(&path, completion.log.unwrap_or_default())
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            outputs.push(JobOutput {
+                filename: format!("error_{}.log", Uuid::new_v4()),
+                path,
+                dictionary: None,
+            });
+        }
+        let output_upload_ids =
+            register_job_outputs(&state, &job.upload_id, &job.function_id, outputs).await;
+        finish_job(&state, &id, output_upload_ids).await;
+    }
+
+    let Json(finished) = get_job(State(state.clone()), Path(id)).await?;
+    Ok(Json(finished).into_response())
+}
+
+// ============= DATA DICTIONARY =============
+
+async fn get_dictionary(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<DataDictionary>, StatusCode> {
+    sqlx::query!(r#"SELECT id FROM uploads WHERE id = ?"#, id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    UploadRepo::new(&state.db)
+        .dictionary(&id)
+        .await
+        .map(Json)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+async fn update_dictionary(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(dictionary): Json<DataDictionary>,
+) -> Result<Response, StatusCode> {
+    let TableUpload {
+        file_path,
+        extension,
+        ..
+    } = fetch_table_upload(&state, &id).await?;
+
+    let schema = tokio::task::spawn_blocking(move || {
+        read_table_schema(&file_path, &extension).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .map_err(|e| {
+        tracing::error!("Failed to read schema of {}: {}", id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if let Some(unknown) = dictionary
+        .columns
+        .keys()
+        .find(|name| !schema.columns.iter().any(|c| &c.name == *name))
+    {
+        return Ok(json_error(
+            StatusCode::BAD_REQUEST,
+            format!("Unknown column: {}", unknown),
+        )
+        .into_response());
+    }
+
+    UploadRepo::new(&state.db)
+        .set_dictionary(&id, &dictionary)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(dictionary).into_response())
+}
+
+// Column names and types of a tabular upload, annotated from its data dictionary
+async fn get_table_schema(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<TableSchema>, StatusCode> {
+    let TableUpload {
+        file_path,
+        extension,
+        ..
+    } = fetch_table_upload(&state, &id).await?;
+
+    let mut schema = tokio::task::spawn_blocking({
+        let file_path = file_path.clone();
+        move || read_table_schema(&file_path, &extension).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .map_err(|e| {
+        tracing::error!("Failed to read schema of {}: {}", file_path, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let mut dictionary = UploadRepo::new(&state.db)
+        .dictionary(&id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    for column in &mut schema.columns {
+        if let Some(info) = dictionary.columns.remove(&column.name) {
+            column.description = info.description;
+            // Units documented in the dictionary win over ones read from the header
+            column.unit = info.unit.or(column.unit.take());
+        }
+    }
+
+    Ok(Json(schema))
+}
+
+// Detect unit-looking column headers such as "pressure (kPa)"
+async fn get_table_units(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let TableUpload {
+        file_path,
+        extension,
+        ..
+    } = fetch_table_upload(&state, &id).await?;
+
+    let schema = tokio::task::spawn_blocking({
+        let file_path = file_path.clone();
+        move || read_table_schema(&file_path, &extension).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .map_err(|e| {
+        tracing::error!("Failed to read schema of {}: {}", file_path, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let columns = detect_units(schema.columns.iter().map(|c| c.name.as_str()));
+    Ok(Json(serde_json::json!({ "columns": columns })))
+}
+
+// Per-column outlier statistics (z-score or IQR) of a CSV/Parquet upload
+async fn get_table_anomalies(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Query(query): Query<AnomalyQuery>,
+) -> Result<Json<AnomalyReport>, StatusCode> {
+    let TableUpload {
+        file_path,
+        extension,
+        ..
+    } = fetch_table_upload(&state, &id).await?;
+
+    tokio::task::spawn_blocking({
+        let file_path = file_path.clone();
+        move || detect_table_anomalies(&file_path, &extension, &query).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .map(Json)
+    .map_err(|e| {
+        tracing::error!("Failed to check {} for outliers: {}", file_path, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+// ============= COMPARE =============
+
+// Combine repeated runs that share a schema into one long-format or summary upload
+async fn compare_uploads(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(request): Json<CompareRequest>,
+) -> Result<Response, StatusCode> {
+    let CompareRequest {
+        comparison,
+        filename,
+        tags,
+    } = request;
+
+    if filename
+        .as_deref()
+        .is_some_and(|name| !is_valid_filename(name))
+    {
+        return Ok(json_error(StatusCode::BAD_REQUEST, "Invalid filename").into_response());
+    }
+    let unique: HashSet<&String> = comparison.uploads.iter().collect();
+    if unique.len() != comparison.uploads.len() {
+        return Ok(json_error(
+            StatusCode::BAD_REQUEST,
+            "Each upload can only be compared once",
+        )
+        .into_response());
+    }
+    if !(2..=MAX_COMPARE_UPLOADS).contains(&comparison.uploads.len()) {
+        return Ok(json_error(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Select between 2 and {} uploads to compare",
+                MAX_COMPARE_UPLOADS
+            ),
+        )
+        .into_response());
+    }
+
+    let requester = Requester::from_headers(&headers);
+    let mut runs = Vec::with_capacity(comparison.uploads.len());
+    for upload_id in &comparison.uploads {
+        check_upload_access(&state, &requester, upload_id).await?;
+        let upload = fetch_table_upload(&state, upload_id).await?;
+        runs.push(RunTable {
+            label: upload.original_filename,
+            file_path: upload.file_path,
+            file_type: upload.extension,
+        });
+    }
+    // Runs are labelled by filename unless two of them share one
+    let labels: HashSet<&String> = runs.iter().map(|run| &run.label).collect();
+    if labels.len() != runs.len() {
+        for (run, upload_id) in runs.iter_mut().zip(&comparison.uploads) {
+            run.label = upload_id.clone();
+        }
+    }
+
+    // The result takes the format of the first run
+    let extension = runs[0].file_type.clone();
+    let run_count = runs.len();
+    let result = tokio::task::spawn_blocking({
+        let extension = extension.clone();
+        move || {
+            validate_comparison(&runs, &comparison)
+                .map_err(|message| (StatusCode::BAD_REQUEST, message))?;
+            let mut slice = compare_tables(&runs, &comparison, &extension)
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            let data = slice
+                .write_file()
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            Ok((comparison, data))
+        }
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let (comparison, data) = match result {
+        Ok(output) => output,
+        Err((StatusCode::BAD_REQUEST, message)) => {
+            return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response());
+        }
+        Err((status, message)) => {
+            tracing::error!("Failed to compare uploads: {}", message);
+            return Err(status);
+        }
+    };
+
+    let filename = match filename {
+        Some(name) if name.to_lowercase().ends_with(&format!(".{}", extension)) => name,
+        Some(name) => format!("{}.{}", name, extension),
+        None => format!("comparison_{}_runs.{}", run_count, extension),
+    };
+    let upload = store_upload(&state, filename, data, None, tags)
+        .await
+        .map_err(internal_error)?;
+
+    // One lineage row per run, all sharing the parameters of the comparison
+    let query =
+        serde_json::to_string(&comparison).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    for source_id in &comparison.uploads {
+        let lineage_id = Uuid::new_v4().to_string();
+        sqlx::query!(
+            "INSERT INTO file_lineage (id, output_upload_id, source_upload_id, function_id, operation, query, success, created_at) VALUES (?, ?, ?, NULL, 'compare', ?, 1, ?)",
+            lineage_id,
+            upload.id,
+            source_id,
+            query,
+            upload.created_at
+        )
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
+    Ok((StatusCode::CREATED, Json(upload)).into_response())
+}
+
+// ============= SQL =============
+
+// Run a read-only SQL query over one or more CSV/Parquet uploads
+#[derive(Debug, serde::Deserialize)]
+struct SqlFormat {
+    format: Option<String>, // `arrow` for an Arrow IPC stream
+}
+
+async fn run_sql_query(
+    State(state): State<Arc<AppState>>,
+    Query(output): Query<SqlFormat>,
+    headers: HeaderMap,
+    Json(request): Json<SqlRequest>,
+) -> Result<Response, StatusCode> {
+    let arrow = output.format.as_deref() == Some("arrow");
+    match execute_sql(&state, &Requester::from_headers(&headers), request).await {
+        Ok(rows) if arrow || accepts_ndjson(&headers) => {
+            let truncated = rows.truncated;
+            let mut response = if arrow {
+                table_arrow_response(rows.slice).await?
+            } else {
+                stream_table_ndjson(std::iter::once(rows.slice).map(Ok)).await?
+            };
+            // Neither format has room for the JSON result's `truncated` flag
+            response.headers_mut().insert(
+                header::HeaderName::from_static("x-datalab-truncated"),
+                header::HeaderValue::from_static(if truncated { "true" } else { "false" }),
+            );
+            Ok(response)
+        }
+        Ok(rows) => Ok(Json(rows.into_result()).into_response()),
+        Err((StatusCode::BAD_REQUEST, message)) => {
+            Ok(json_error(StatusCode::BAD_REQUEST, message).into_response())
+        }
+        Err((status, _)) => Err(status),
+    }
+}
+
+// Validate and run a SQL request over uploads visible to the requester; user errors come back
+// as BAD_REQUEST with a message
+async fn execute_sql(
+    state: &Arc<AppState>,
+    requester: &Requester,
+    request: SqlRequest,
+) -> Result<SqlRows, (StatusCode, String)> {
+    let bad_request = |message: String| (StatusCode::BAD_REQUEST, message);
+
+    if request.engine == SqlEngine::Duckdb && state.duckdb_bin.is_none() {
+        return Err(bad_request(
+            "DuckDB engine is not enabled in this build (compile with --features duckdb)"
+                .to_string(),
+        ));
+    }
+    if let Some(name) = request
+        .tables
+        .keys()
+        .find(|name| !is_valid_table_name(name))
+    {
+        return Err(bad_request(format!("Invalid table name: {}", name)));
+    }
+
+    let names: Vec<&str> = request.tables.keys().map(String::as_str).collect();
+    validate_sql(&request.query, &names, request.engine).map_err(bad_request)?;
+
+    let mut tables = Vec::new();
+    for (name, upload_id) in &request.tables {
+        let upload = async {
+            check_upload_access(state, requester, upload_id).await?;
+            fetch_table_upload(state, upload_id).await
+        }
+        .await
+        .map_err(|status| {
+            let message = match status {
+                StatusCode::NOT_FOUND => format!("Upload not found: {}", upload_id),
+                _ => format!("Upload {} is not a CSV or Parquet file", upload_id),
+            };
+            (status, message)
+        })?;
+        tables.push(SqlTable {
+            name: name.clone(),
+            file_path: upload.file_path,
+            file_type: upload.extension,
+        });
+    }
+
+    let limit = request.limit();
+    let duckdb_bin = state.duckdb_bin.clone();
+    tokio::task::spawn_blocking(move || {
+        run_sql(
+            &request.query,
+            &tables,
+            request.engine,
+            limit,
+            duckdb_bin.as_deref(),
+        )
+        .map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    // The query already passed validation, so remaining failures are planning/type errors
+    .map_err(bad_request)
+}
+
+// ============= VIEWS =============
+
+fn validate_view(
+    tag_expression: Option<&str>,
+    within_days: Option<i64>,
+    sort_by: &str,
+    sort_order: &str,
+) -> Result<(), StatusCode> {
+    if let Some(expression) = tag_expression {
+        if !expression.trim().is_empty() {
+            TagExpr::parse(expression).map_err(|e| {
+                tracing::warn!("Invalid tag expression {:?}: {}", expression, e);
+                StatusCode::BAD_REQUEST
+            })?;
+        }
+    }
+    if within_days.is_some_and(|days| days <= 0) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if !matches!(sort_by, "created_at" | "name" | "size") {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if !matches!(sort_order, "asc" | "desc") {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    Ok(())
+}
+
+// Bring a user-supplied timestamp into the stored form so it compares with created_at
+fn normalize_timestamp(
+    value: Option<String>,
+    timezone: Option<&str>,
+) -> Result<Option<String>, StatusCode> {
+    match value.as_deref().map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(raw) => timestamps::parse(raw, timezone)
+            .map(Some)
+            .map_err(|_| StatusCode::BAD_REQUEST),
+    }
+}
+
+async fn list_views(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<SavedView>>, StatusCode> {
+    let views = sqlx::query_as!(
+        SavedView,
+        r#"SELECT id as "id!", name as "name!", tag_expression, name_pattern, created_after, created_before, within_days, sort_by as "sort_by!", sort_order as "sort_order!", created_at as "created_at!" FROM views ORDER BY created_at DESC"#
+    )
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to fetch views: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(views))
+}
+
+async fn create_view(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<CreateView>,
+) -> Result<(StatusCode, Json<SavedView>), StatusCode> {
+    validate_view(
+        payload.tag_expression.as_deref(),
+        payload.within_days,
+        &payload.sort_by,
+        &payload.sort_order,
+    )?;
+
+    let id = Uuid::new_v4().to_string();
+    let created_at = timestamps::now();
+    let tag_expression = payload
+        .tag_expression
+        .filter(|expression| !expression.trim().is_empty());
+    let name_pattern = payload
+        .name_pattern
+        .map(|pattern| pattern.trim().to_string())
+        .filter(|pattern| !pattern.is_empty());
+    let created_after = normalize_timestamp(payload.created_after, payload.timezone.as_deref())?;
+    let created_before = normalize_timestamp(payload.created_before, payload.timezone.as_deref())?;
+
+    sqlx::query!(
+        "INSERT INTO views (id, name, tag_expression, name_pattern, created_after, created_before, within_days, sort_by, sort_order, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        id,
+        payload.name,
+        tag_expression,
+        name_pattern,
+        created_after,
+        created_before,
+        payload.within_days,
+        payload.sort_by,
+        payload.sort_order,
+        created_at
+    )
+    .execute(&state.db)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to create view: {}", e);
+        if e.to_string().contains("UNIQUE constraint failed") {
+            StatusCode::CONFLICT
+        } else {
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(SavedView {
+            id,
+            name: payload.name,
+            tag_expression,
+            name_pattern,
+            created_after,
+            created_before,
+            within_days: payload.within_days,
+            sort_by: payload.sort_by,
+            sort_order: payload.sort_order,
+            created_at,
+        }),
+    ))
+}
+
+async fn get_view(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<SavedView>, StatusCode> {
+    let view = sqlx::query_as!(
+        SavedView,
+        r#"SELECT id as "id!", name as "name!", tag_expression, name_pattern, created_after, created_before, within_days, sort_by as "sort_by!", sort_order as "sort_order!", created_at as "created_at!" FROM views WHERE id = ?"#,
+        id
+    )
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to fetch view: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(view))
+}
+
+async fn update_view(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(payload): Json<UpdateView>,
+) -> Result<Json<SavedView>, StatusCode> {
+    let Json(existing) = get_view(State(state.clone()), Path(id.clone())).await?;
+
+    // Empty strings clear optional fields
+    let tag_expression = match payload.tag_expression {
+        Some(expression) if expression.trim().is_empty() => None,
+        Some(expression) => Some(expression),
+        None => existing.tag_expression,
+    };
+    let name_pattern = match payload.name_pattern {
+        Some(pattern) if pattern.trim().is_empty() => None,
+        Some(pattern) => Some(pattern.trim().to_string()),
+        None => existing.name_pattern,
+    };
+    let created_after = match payload.created_after {
+        Some(value) => normalize_timestamp(Some(value), payload.timezone.as_deref())?,
+        None => existing.created_after,
+    };
+    let created_before = match payload.created_before {
+        Some(value) => normalize_timestamp(Some(value), payload.timezone.as_deref())?,
+        None => existing.created_before,
+    };
+    let within_days = payload.within_days.unwrap_or(existing.within_days);
+    let sort_by = payload.sort_by.unwrap_or(existing.sort_by);
+    let sort_order = payload.sort_order.unwrap_or(existing.sort_order);
+    let name = payload.name.unwrap_or(existing.name);
+
+    validate_view(
+        tag_expression.as_deref(),
+        within_days,
+        &sort_by,
+        &sort_order,
+    )?;
+
+    sqlx::query!(
+        "UPDATE views SET name = ?, tag_expression = ?, name_pattern = ?, created_after = ?, created_before = ?, within_days = ?, sort_by = ?, sort_order = ? WHERE id = ?",
+        name,
+        tag_expression,
+        name_pattern,
+        created_after,
+        created_before,
+        within_days,
+        sort_by,
+        sort_order,
+        id
+    )
+    .execute(&state.db)
+    .await
+    .map_err(|e| {
+        if e.to_string().contains("UNIQUE constraint failed") {
+            StatusCode::CONFLICT
+        } else {
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    get_view(State(state), Path(id)).await
+}
+
+async fn delete_view(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let result = sqlx::query!("DELETE FROM views WHERE id = ?", id)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn list_view_uploads(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<Upload>>, StatusCode> {
+    let Json(view) = get_view(State(state.clone()), Path(id)).await?;
+    let name_pattern = view.name_pattern.as_deref().map(str::to_lowercase);
+    let hidden = hidden_tag_ids(&state, &Requester::from_headers(&headers)).await?;
+
+    let tag_expr = match view.tag_expression.as_deref() {
+        Some(expression) => {
+            Some(TagExpr::parse(expression).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?)
+        }
+        None => None,
+    };
+
+    // Relative ranges ("last 7 days") are resolved at evaluation time
+    let mut created_after = view.created_after;
+    if let Some(days) = view.within_days {
+        let cutoff = timestamps::format(chrono::Utc::now() - chrono::Duration::days(days));
+        created_after = Some(created_after.map_or(cutoff.clone(), |after| after.max(cutoff)));
+    }
+    let created_before = view.created_before;
+
+    let uploads = sqlx::query_as!(
+        StoredUpload,
+        r#"SELECT id as "id!", filename as "filename!", original_filename as "original_filename!", file_size as "file_size!", mime_type, detected_mime_type, created_at as "created_at!", sha256, assignee, artifact_type as "artifact_type!", compression, protected as "protected!: bool", expires_at
+           FROM uploads
+           WHERE (? IS NULL OR created_at >= ?) AND (? IS NULL OR created_at < ?)"#,
+        created_after,
+        created_after,
+        created_before,
+        created_before
+    )
+    .fetch_all(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut result = Vec::new();
+    for upload_row in uploads {
+        // Like intermediate output patterns, case does not matter
+        if let Some(pattern) = &name_pattern {
+            if !glob_match(pattern, &upload_row.original_filename.to_lowercase()) {
+                continue;
+            }
+        }
+        let tags = TagRepo::new(&state.db)
+            .for_upload(&upload_row.id)
+            .await
+            .unwrap_or_default();
+        if tags.iter().any(|tag| hidden.contains(&tag.id)) {
+            continue;
+        }
+
+        if let Some(expr) = &tag_expr {
+            let tag_names = tags.iter().map(|t| t.name.as_str()).collect();
+            if !expr.matches(&tag_names) {
+                continue;
+            }
+        }
+
+        let lineage = UploadRepo::new(&state.db)
+            .lineage(&upload_row.id)
+            .await
+            .ok()
+            .flatten();
+        result.push(upload_row.into_upload(tags, lineage));
+    }
+
+    match view.sort_by.as_str() {
+        "name" => result.sort_by_key(|u| u.original_filename.to_lowercase()),
+        "size" => result.sort_by_key(|u| u.file_size),
+        _ => result.sort_by(|a, b| a.created_at.cmp(&b.created_at)),
+    }
+    if view.sort_order == "desc" {
+        result.reverse();
+    }
+
+    Ok(Json(result))
+}
+
+// ============= REPORTS =============
+
+#[derive(sqlx::FromRow)]
+struct ReportRow {
+    id: String,
+    name: String,
+    description: Option<String>,
+    format: String,
+    template: String,
+    queries: String,
+    schedule_hours: Option<i64>,
+    last_rendered_at: Option<String>,
+    created_at: String,
+}
+
+impl TryFrom<ReportRow> for ReportTemplate {
+    type Error = serde_json::Error;
+
+    fn try_from(row: ReportRow) -> Result<Self, Self::Error> {
+        Ok(ReportTemplate {
+            id: row.id,
+            name: row.name,
+            description: row.description,
+            format: row.format,
+            template: row.template,
+            queries: serde_json::from_str(&row.queries)?,
+            schedule_hours: row.schedule_hours,
+            last_rendered_at: row.last_rendered_at,
+            created_at: row.created_at,
+        })
+    }
+}
+
+fn validate_report(
+    format: &str,
+    template: &str,
+    queries: &BTreeMap<String, SqlRequest>,
+    schedule_hours: Option<i64>,
+) -> Result<(), String> {
+    if report_extension(format).is_none() {
+        return Err(format!(
+            "Unknown report format: {} (expected markdown or html)",
+            format
+        ));
+    }
+    if schedule_hours.is_some_and(|hours| hours < 1) {
+        return Err("schedule_hours must be at least 1".to_string());
+    }
+    validate_template(format, template)?;
+    for (name, request) in queries {
+        let tables: Vec<&str> = request.tables.keys().map(String::as_str).collect();
+        validate_sql(&request.query, &tables, request.engine)
+            .map_err(|e| format!("Query {}: {}", name, e))?;
+    }
+    Ok(())
+}
+
+async fn fetch_report(db: &sqlx::SqlitePool, id: &str) -> Result<ReportTemplate, StatusCode> {
+    let row = sqlx::query_as!(
+        ReportRow,
+        r#"SELECT id as "id!", name as "name!", description, format as "format!", template as "template!", queries as "queries!", schedule_hours, last_rendered_at, created_at as "created_at!" FROM report_templates WHERE id = ?"#,
+        id
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    ReportTemplate::try_from(row).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+async fn list_reports(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<ReportTemplate>>, StatusCode> {
+    let rows = sqlx::query_as!(
+        ReportRow,
+        r#"SELECT id as "id!", name as "name!", description, format as "format!", template as "template!", queries as "queries!", schedule_hours, last_rendered_at, created_at as "created_at!" FROM report_templates ORDER BY name"#
+    )
+    .fetch_all(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    rows.into_iter()
+        .map(ReportTemplate::try_from)
+        .collect::<Result<Vec<_>, _>>()
+        .map(Json)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+async fn create_report(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<CreateReport>,
+) -> Result<Response, StatusCode> {
+    if let Err(message) = validate_report(
+        &payload.format,
+        &payload.template,
+        &payload.queries,
+        payload.schedule_hours,
+    ) {
+        return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response());
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let created_at = timestamps::now();
+    let queries =
+        serde_json::to_string(&payload.queries).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    sqlx::query!(
+        "INSERT INTO report_templates (id, name, description, format, template, queries, schedule_hours, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        id,
+        payload.name,
+        payload.description,
+        payload.format,
+        payload.template,
+        queries,
+        payload.schedule_hours,
+        created_at
+    )
+    .execute(&state.db)
+    .await
+    .map_err(|e| {
+        if e.to_string().contains("UNIQUE constraint failed") {
+            StatusCode::CONFLICT
+        } else {
+            tracing::error!("Failed to create report: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    let report = fetch_report(&state.db, &id).await?;
+    Ok((StatusCode::CREATED, Json(report)).into_response())
+}
+
+async fn get_report(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<ReportTemplate>, StatusCode> {
+    fetch_report(&state.db, &id).await.map(Json)
+}
+
+async fn update_report(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(payload): Json<UpdateReport>,
+) -> Result<Response, StatusCode> {
+    let existing = fetch_report(&state.db, &id).await?;
+
+    let name = payload.name.unwrap_or(existing.name);
+    // Empty strings clear the description, a zero schedule switches scheduling off
+    let description = match payload.description {
+        Some(description) if description.trim().is_empty() => None,
+        Some(description) => Some(description),
+        None => existing.description,
+    };
+    let format = payload.format.unwrap_or(existing.format);
+    let template = payload.template.unwrap_or(existing.template);
+    let queries = payload.queries.unwrap_or(existing.queries);
+    let schedule_hours = match payload.schedule_hours {
+        Some(0) => None,
+        Some(hours) => Some(hours),
+        None => existing.schedule_hours,
+    };
+
+    if let Err(message) = validate_report(&format, &template, &queries, schedule_hours) {
+        return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response());
+    }
+    let queries = serde_json::to_string(&queries).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    sqlx::query!(
+        "UPDATE report_templates SET name = ?, description = ?, format = ?, template = ?, queries = ?, schedule_hours = ? WHERE id = ?",
+        name,
+        description,
+        format,
+        template,
+        queries,
+        schedule_hours,
+        id
+    )
+    .execute(&state.db)
+    .await
+    .map_err(|e| {
+        if e.to_string().contains("UNIQUE constraint failed") {
+            StatusCode::CONFLICT
+        } else {
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    let report = fetch_report(&state.db, &id).await?;
+    Ok(Json(report).into_response())
+}
+
+async fn delete_report(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let result = sqlx::query!("DELETE FROM report_templates WHERE id = ?", id)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Jobs and uploads since the previous rendering (or the schedule period, or the last week)
+async fn summarize_jobs(
+    state: &AppState,
+    report: &ReportTemplate,
+) -> Result<JobSummary, StatusCode> {
+    let since = report.last_rendered_at.clone().unwrap_or_else(|| {
+        let hours = report.schedule_hours.unwrap_or(7 * 24);
+        timestamps::format(chrono::Utc::now() - chrono::Duration::hours(hours))
+    });
+
+    let counts = sqlx::query!(
+        r#"SELECT
+            COUNT(*) as "total!: i64",
+            COALESCE(SUM(status = 'SUCCESS'), 0) as "success!: i64",
+            COALESCE(SUM(status = 'FAILED'), 0) as "failed!: i64",
+            COALESCE(SUM(status IN ('SUBMITTED', 'RUNNING')), 0) as "pending!: i64"
+        FROM jobs WHERE created_at >= ?"#,
+        since
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let uploads = sqlx::query!(
+        r#"SELECT COUNT(*) as "count!: i64" FROM uploads WHERE created_at >= ?"#,
+        since
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .count;
+
+    let failures = sqlx::query_as!(
+        FailedJob,
+        r#"SELECT j.id as "id!", f.name as "function_name!", u.original_filename as "filename!", j.error_message, j.completed_at
+           FROM jobs j
+           INNER JOIN functions f ON j.function_id = f.id
+           INNER JOIN uploads u ON j.upload_id = u.id
+           WHERE j.status = 'FAILED' AND j.created_at >= ?
+           ORDER BY j.created_at DESC
+           LIMIT 50"#,
+        since
+    )
+    .fetch_all(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(JobSummary {
+        since,
+        total: counts.total,
+        success: counts.success,
+        failed: counts.failed,
+        pending: counts.pending,
+        uploads,
+        failures,
+    })
+}
+
+// Run the report's queries and fill in its template
+async fn render_report_template(
+    state: &Arc<AppState>,
+    report: &ReportTemplate,
+) -> Result<String, (StatusCode, String)> {
+    let mut queries = BTreeMap::new();
+    for (name, request) in &report.queries {
+        // Rendered reports are stored as ordinary uploads, so they only query unrestricted ones
+        let rows = execute_sql(state, &Requester::default(), request.clone())
+            .await
+            .map_err(|(status, message)| (status, format!("Query {}: {}", name, message)))?;
+        queries.insert(name.clone(), rows.into_result());
+    }
+
+    let now = chrono::Utc::now();
+    let context = ReportContext {
+        report: ReportInfo {
+            name: report.name.clone(),
+            description: report.description.clone(),
+        },
+        now: timestamps::format(now),
+        today: now.format("%Y-%m-%d").to_string(),
+        jobs: summarize_jobs(state, report)
+            .await
+            .map_err(|status| (status, "Failed to summarize jobs".to_string()))?,
+        queries,
+    };
+
+    render_report(&report.format, &report.template, &context)
+        .map_err(|message| (StatusCode::BAD_REQUEST, message))
+}
+
+// Save a rendered report as a new upload, e.g. "weekly_summary_2024-01-08.md"
+async fn store_rendered_report(
+    state: &Arc<AppState>,
+    report: &ReportTemplate,
+    content: String,
+) -> Result<UploadResponse, StatusCode> {
+    let now = chrono::Utc::now();
+    let stem: String = report
+        .name
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect();
+    let extension = report_extension(&report.format).unwrap_or("txt");
+    let filename = format!("{}_{}.{}", stem, now.format("%Y-%m-%d"), extension);
+    let mime_type = match extension {
+        "html" => "text/html",
+        _ => "text/markdown",
+    };
+
+    let upload = store_upload(
+        state,
+        filename,
+        content.into_bytes(),
+        Some(mime_type.to_string()),
+        Vec::new(),
+    )
+    .await
+    .map_err(internal_error)?;
+
+    let rendered_at = timestamps::format(now);
+    sqlx::query!(
+        "UPDATE report_templates SET last_rendered_at = ? WHERE id = ?",
+        rendered_at,
+        report.id
+    )
+    .execute(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(upload)
+}
+
+// Render a report without saving it, for editing templates
+async fn preview_report(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Response, StatusCode> {
+    let report = fetch_report(&state.db, &id).await?;
+    match render_report_template(&state, &report).await {
+        Ok(content) => {
+            let content_type = match report.format.as_str() {
+                "html" => "text/html; charset=utf-8",
+                _ => "text/markdown; charset=utf-8",
+            };
+            Ok(([(header::CONTENT_TYPE, content_type)], content).into_response())
+        }
+        Err((status, message)) => Ok(json_error(status, message).into_response()),
+    }
+}
+
+// Render a report and save it as a new upload
+async fn render_report_upload(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Response, StatusCode> {
+    let report = fetch_report(&state.db, &id).await?;
+    let content = match render_report_template(&state, &report).await {
+        Ok(content) => content,
+        Err((status, message)) => return Ok(json_error(status, message).into_response()),
+    };
+
+    let upload = store_rendered_report(&state, &report, content).await?;
+    Ok((StatusCode::CREATED, Json(upload)).into_response())
+}
+
+// Render scheduled reports once they are due, checking every minute
+pub fn spawn_report_scheduler(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+
+            let Ok(rows) = sqlx::query_as!(
+                ReportRow,
+                r#"SELECT id as "id!", name as "name!", description, format as "format!", template as "template!", queries as "queries!", schedule_hours, last_rendered_at, created_at as "created_at!" FROM report_templates WHERE schedule_hours IS NOT NULL"#
+            )
+            .fetch_all(&state.db)
+            .await
+            else {
+                continue;
+            };
+
+            let now = chrono::Utc::now();
+            for report in rows
+                .into_iter()
+                .filter_map(|row| ReportTemplate::try_from(row).ok())
+            {
+                let Some(hours) = report.schedule_hours else {
+                    continue;
+                };
+                let last = report
+                    .last_rendered_at
+                    .as_deref()
+                    .unwrap_or(&report.created_at);
+                let due = chrono::DateTime::parse_from_rfc3339(last)
+                    .map_or(true, |last| now - chrono::Duration::hours(hours) >= last);
+                if !due {
+                    continue;
+                }
+
+                let result = match render_report_template(&state, &report).await {
+                    Ok(content) => store_rendered_report(&state, &report, content)
+                        .await
+                        .map(|upload| upload.original_filename)
+                        .map_err(|status| status.to_string()),
+                    Err((_, message)) => Err(message),
+                };
+                match result {
+                    Ok(filename) => {
+                        tracing::info!("Rendered scheduled report {} to {}", report.name, filename)
+                    }
+                    Err(message) => {
+                        tracing::warn!("Scheduled report {} failed: {}", report.name, message);
+                        // Wait for the next period instead of retrying every minute
+                        let attempted_at = timestamps::format(now);
+                        let _ = sqlx::query!(
+                            "UPDATE report_templates SET last_rendered_at = ? WHERE id = ?",
+                            attempted_at,
+                            report.id
+                        )
+                        .execute(&state.db)
+                        .await;
+                    }
+                }
+            }
+        }
+    });
+}
+
+// ============= REVIEWS =============
+
+#[derive(sqlx::FromRow)]
+struct ReviewQueueRow {
+    id: String,
+    name: String,
+    description: Option<String>,
+    input_tag_id: String,
+    approve_tag_id: String,
+    reject_tag_id: Option<String>,
+    created_at: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ReviewItemsQuery {
+    status: Option<String>, // pending (default), approved or rejected
+}
+
+async fn review_queue_from_row(
+    db: &sqlx::SqlitePool,
+    row: ReviewQueueRow,
+) -> Result<ReviewQueue, StatusCode> {
+    let tag = |id: String| async move {
+        TagRepo::new(db)
+            .get(&id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .ok_or(StatusCode::INTERNAL_SERVER_ERROR)
+    };
+    let input_tag = tag(row.input_tag_id).await?;
+    let approve_tag = tag(row.approve_tag_id).await?;
+    let reject_tag = match row.reject_tag_id {
+        Some(id) => Some(tag(id).await?),
+        None => None,
+    };
+
+    let pending = sqlx::query!(
+        r#"SELECT COUNT(*) as "count!: i64" FROM upload_tags ut
+           WHERE ut.tag_id = ?
+             AND NOT EXISTS (SELECT 1 FROM reviews r WHERE r.queue_id = ? AND r.upload_id = ut.upload_id)"#,
+        input_tag.id,
+        row.id
+    )
+    .fetch_one(db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .count;
+
+    Ok(ReviewQueue {
+        id: row.id,
+        name: row.name,
+        description: row.description,
+        input_tag,
+        approve_tag,
+        reject_tag,
+        pending,
+        created_at: row.created_at,
+    })
+}
+
+async fn fetch_review_queue(db: &sqlx::SqlitePool, id: &str) -> Result<ReviewQueue, StatusCode> {
+    let row = sqlx::query_as!(
+        ReviewQueueRow,
+        r#"SELECT id as "id!", name as "name!", description, input_tag_id as "input_tag_id!", approve_tag_id as "approve_tag_id!", reject_tag_id, created_at as "created_at!" FROM review_queues WHERE id = ?"#,
+        id
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    review_queue_from_row(db, row).await
+}
+
+// Check that the queue's tags exist and that approval changes something
+async fn validate_review_queue(
+    db: &sqlx::SqlitePool,
+    input_tag_id: &str,
+    approve_tag_id: &str,
+    reject_tag_id: Option<&str>,
+) -> Result<Result<(), String>, StatusCode> {
+    for tag_id in [Some(input_tag_id), Some(approve_tag_id), reject_tag_id]
+        .into_iter()
+        .flatten()
+    {
+        let tag = TagRepo::new(db)
+            .get(tag_id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if tag.is_none() {
+            return Ok(Err(format!("Tag not found: {}", tag_id)));
+        }
+    }
+    if approve_tag_id == input_tag_id || reject_tag_id == Some(input_tag_id) {
+        return Ok(Err(
+            "Approve and reject tags must differ from the input tag".to_string(),
+        ));
+    }
+    Ok(Ok(()))
+}
+
+async fn list_review_queues(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<ReviewQueue>>, StatusCode> {
+    let rows = sqlx::query_as!(
+        ReviewQueueRow,
+        r#"SELECT id as "id!", name as "name!", description, input_tag_id as "input_tag_id!", approve_tag_id as "approve_tag_id!", reject_tag_id, created_at as "created_at!" FROM review_queues ORDER BY name"#
+    )
+    .fetch_all(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut queues = Vec::new();
+    for row in rows {
+        queues.push(review_queue_from_row(&state.db, row).await?);
+    }
+    Ok(Json(queues))
+}
+
+async fn create_review_queue(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<CreateReviewQueue>,
+) -> Result<Response, StatusCode> {
+    if payload.name.trim().is_empty() {
+        return Ok(json_error(StatusCode::BAD_REQUEST, "Name is required").into_response());
+    }
+    if let Err(message) = validate_review_queue(
+        &state.db,
+        &payload.input_tag_id,
+        &payload.approve_tag_id,
+        payload.reject_tag_id.as_deref(),
+    )
+    .await?
+    {
+        return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response());
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let created_at = timestamps::now();
+    sqlx::query!(
+        "INSERT INTO review_queues (id, name, description, input_tag_id, approve_tag_id, reject_tag_id, created_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        id,
+        payload.name,
+        payload.description,
+        payload.input_tag_id,
+        payload.approve_tag_id,
+        payload.reject_tag_id,
+        created_at
+    )
+    .execute(&state.db)
+    .await
+    .map_err(|e| {
+        if e.to_string().contains("UNIQUE constraint failed") {
+            StatusCode::CONFLICT
+        } else {
+            tracing::error!("Failed to create review queue: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    let queue = fetch_review_queue(&state.db, &id).await?;
+    Ok((StatusCode::CREATED, Json(queue)).into_response())
+}
+
+async fn get_review_queue(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<ReviewQueue>, StatusCode> {
+    fetch_review_queue(&state.db, &id).await.map(Json)
+}
+
+async fn update_review_queue(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(payload): Json<UpdateReviewQueue>,
+) -> Result<Response, StatusCode> {
+    let existing = fetch_review_queue(&state.db, &id).await?;
+
+    let name = payload.name.unwrap_or(existing.name);
+    let description = payload.description.or(existing.description);
+    let input_tag_id = payload.input_tag_id.unwrap_or(existing.input_tag.id);
+    let approve_tag_id = payload.approve_tag_id.unwrap_or(existing.approve_tag.id);
+    let reject_tag_id = match payload.reject_tag_id {
+        Some(tag_id) if tag_id.is_empty() => None,
+        Some(tag_id) => Some(tag_id),
+        None => existing.reject_tag.map(|tag| tag.id),
+    };
+
+    if let Err(message) = validate_review_queue(
+        &state.db,
+        &input_tag_id,
+        &approve_tag_id,
+        reject_tag_id.as_deref(),
+    )
+    .await?
+    {
+        return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response());
+    }
+
+    sqlx::query!(
+        "UPDATE review_queues SET name = ?, description = ?, input_tag_id = ?, approve_tag_id = ?, reject_tag_id = ? WHERE id = ?",
+        name,
+        description,
+        input_tag_id,
+        approve_tag_id,
+        reject_tag_id,
+        id
+    )
+    .execute(&state.db)
+    .await
+    .map_err(|e| {
+        if e.to_string().contains("UNIQUE constraint failed") {
+            StatusCode::CONFLICT
+        } else {
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    let queue = fetch_review_queue(&state.db, &id).await?;
+    Ok(Json(queue).into_response())
+}
+
+async fn delete_review_queue(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let result = sqlx::query!("DELETE FROM review_queues WHERE id = ?", id)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Uploads waiting for review (oldest first), or the decided ones (newest first)
+async fn list_review_items(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Query(params): Query<ReviewItemsQuery>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let queue = fetch_review_queue(&state.db, &id).await?;
+    let hidden = hidden_tag_ids(&state, &Requester::from_headers(&headers)).await?;
+    let visible = |upload: &Upload| !upload.tags.iter().any(|tag| hidden.contains(&tag.id));
+
+    let status = params.status.as_deref().unwrap_or("pending");
+    let mut items = Vec::new();
+    match status {
+        "pending" => {
+            let uploads = sqlx::query!(
+                r#"SELECT u.id as "id!" FROM uploads u
+                   INNER JOIN upload_tags ut ON ut.upload_id = u.id
+                   WHERE ut.tag_id = ?
+                     AND NOT EXISTS (SELECT 1 FROM reviews r WHERE r.queue_id = ? AND r.upload_id = u.id)
+                   ORDER BY u.created_at"#,
+                queue.input_tag.id,
+                queue.id
+            )
+            .fetch_all(&state.db)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+            for upload in uploads {
+                let Json(upload) = upload_detail(State(state.clone()), Path(upload.id)).await?;
+                if !visible(&upload) {
+                    continue;
+                }
+                items.push(ReviewItem {
+                    upload,
+                    review: None,
+                });
+            }
+        }
+        "approved" | "rejected" => {
+            let reviews = sqlx::query_as!(
+                Review,
+                r#"SELECT id as "id!", queue_id as "queue_id!", upload_id as "upload_id!", decision as "decision!", comment, reviewer, created_at as "created_at!"
+                   FROM reviews
+                   WHERE queue_id = ? AND decision = ?
+                   ORDER BY created_at DESC"#,
+                queue.id,
+                status
+            )
+            .fetch_all(&state.db)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+            for review in reviews {
+                let Json(upload) =
+                    upload_detail(State(state.clone()), Path(review.upload_id.clone())).await?;
+                if !visible(&upload) {
+                    continue;
+                }
+                items.push(ReviewItem {
+                    upload,
+                    review: Some(review),
+                });
+            }
+        }
+        _ => {
+            return Ok(json_error(
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "Unknown status: {} (expected pending, approved or rejected)",
+                    status
+                ),
+            )
+            .into_response());
+        }
+    }
+
+    Ok(Json(items).into_response())
+}
+
+// Record an approve/reject decision and apply the queue's tag for it
+async fn submit_review(
+    State(state): State<Arc<AppState>>,
+    Path((id, upload_id)): Path<(String, String)>,
+    Json(payload): Json<SubmitReview>,
+) -> Result<Response, StatusCode> {
+    let queue = fetch_review_queue(&state.db, &id).await?;
+
+    let (decision, tag) = match payload.decision.as_str() {
+        "approve" | "approved" => ("approved", Some(queue.approve_tag)),
+        "reject" | "rejected" => ("rejected", queue.reject_tag),
+        _ => {
+            return Ok(json_error(
+                StatusCode::BAD_REQUEST,
+                "Decision must be approve or reject",
+            )
+            .into_response());
+        }
+    };
+    let reviewer = match normalize_assignee(payload.reviewer) {
+        Ok(reviewer) => reviewer,
+        Err(message) => return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response()),
+    };
+
+    let Json(upload) = upload_detail(State(state.clone()), Path(upload_id.clone())).await?;
+    if !upload.tags.iter().any(|t| t.id == queue.input_tag.id) {
+        return Ok(json_error(
+            StatusCode::BAD_REQUEST,
+            format!("Upload is not tagged {}", queue.input_tag.name),
+        )
+        .into_response());
+    }
+
+    let review = Review {
+        id: Uuid::new_v4().to_string(),
+        queue_id: queue.id,
+        upload_id: upload_id.clone(),
+        decision: decision.to_string(),
+        comment: payload.comment.filter(|c| !c.trim().is_empty()),
+        reviewer,
+        created_at: timestamps::now(),
+    };
+    let inserted = sqlx::query!(
+        "INSERT INTO reviews (id, queue_id, upload_id, decision, comment, reviewer, created_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        review.id,
+        review.queue_id,
+        review.upload_id,
+        review.decision,
+        review.comment,
+        review.reviewer,
+        review.created_at
+    )
+    .execute(&state.db)
+    .await;
+    if let Err(e) = inserted {
+        if e.to_string().contains("UNIQUE constraint failed") {
+            return Ok(
+                json_error(StatusCode::CONFLICT, "Upload was already reviewed").into_response(),
+            );
+        }
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    // Approval tags usually feed the next pipeline step
+    if let Some(tag) = tag {
+        sqlx::query!(
+            "INSERT OR IGNORE INTO upload_tags (upload_id, tag_id) VALUES (?, ?)",
+            upload_id,
+            tag.id
+        )
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if let Ok(Some(stored)) = UploadRepo::new(&state.db).get(&upload_id).await {
+            state.hooks.tagged(&state, &stored, &[tag.id]).await;
+        }
+        trigger_functions_for_upload(state.clone(), upload_id.clone());
+    }
+
+    let Json(upload) = upload_detail(State(state), Path(upload_id)).await?;
+    Ok(Json(ReviewItem {
+        upload,
+        review: Some(review),
+    })
+    .into_response())
+}
+
+// ============= ASSIGNMENT =============
+
+// "@alice" and "alice" name the same person; blank values mean nobody
+fn normalize_assignee(assignee: Option<String>) -> Result<Option<String>, String> {
+    let Some(assignee) = assignee else {
+        return Ok(None);
+    };
+    let assignee = assignee.trim().trim_start_matches('@');
+    if assignee.is_empty() {
+        return Ok(None);
+    }
+    if assignee.len() > 64 || assignee.chars().any(char::is_whitespace) {
+        return Err("Names must be a single word of at most 64 characters".to_string());
+    }
+    Ok(Some(assignee.to_string()))
+}
+
+async fn assign_upload(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    Json(request): Json<AssignRequest>,
+) -> Result<Response, StatusCode> {
+    let assignee = match normalize_assignee(request.assignee) {
+        Ok(assignee) => assignee,
+        Err(message) => return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response()),
+    };
+
+    let upload = sqlx::query!(
+        r#"SELECT original_filename as "original_filename!" FROM uploads WHERE id = ?"#,
+        id
+    )
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    UploadRepo::new(&state.db)
+        .set_assignee(&id, assignee.as_deref())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let origin = request_origin(&headers, peer);
+    match &assignee {
+        Some(assignee) => {
+            record_custody_event(&state, &id, "assigned", Some(assignee), &origin).await
+        }
+        None => record_custody_event(&state, &id, "unassigned", None, &origin).await,
+    }
+
+    if let Some(assignee) = &assignee {
+        let title = format!("{} assigned to @{}", upload.original_filename, assignee);
+        add_notification(&state, "assigned", &title, None, Some(&id), None).await;
+    }
+
+    let Json(upload) = upload_detail(State(state), Path(id)).await?;
+    Ok(Json(upload).into_response())
+}
+
+async fn assign_job(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(request): Json<AssignRequest>,
+) -> Result<Response, StatusCode> {
+    let assignee = match normalize_assignee(request.assignee) {
+        Ok(assignee) => assignee,
+        Err(message) => return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response()),
+    };
+
+    let job = sqlx::query!(
+        r#"SELECT
+            j.upload_id as "upload_id!",
+            COALESCE(f.name, j.function_id) as "function_name!: String",
+            COALESCE(u.original_filename, j.upload_id) as "upload_filename!: String"
+        FROM jobs j
+        LEFT JOIN uploads u ON j.upload_id = u.id
+        LEFT JOIN functions f ON j.function_id = f.id
+        WHERE j.id = ?"#,
+        id
+    )
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    sqlx::query!("UPDATE jobs SET assignee = ? WHERE id = ?", assignee, id)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if let Some(assignee) = &assignee {
+        let title = format!(
+            "{} job on {} assigned to @{}",
+            job.function_name, job.upload_filename, assignee
+        );
+        add_notification(
+            &state,
+            "assigned",
+            &title,
+            None,
+            Some(&job.upload_id),
+            Some(&id),
+        )
+        .await;
+    }
+
+    let Json(job) = get_job(State(state), Path(id)).await?;
+    Ok(Json(job).into_response())
+}
+
+// ============= NOTIFICATIONS =============
+
+#[derive(Debug, serde::Deserialize)]
+struct NotificationQuery {
+    #[serde(default)]
+    unread: bool,
+    limit: Option<i64>,
+}
+
+async fn list_notifications(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<NotificationQuery>,
+) -> Result<Json<NotificationList>, StatusCode> {
+    let limit = params.limit.unwrap_or(50).clamp(1, 500);
+    let notifications = sqlx::query_as!(
+        Notification,
+        r#"SELECT id as "id!", kind as "kind!", title as "title!", message, upload_id, job_id, read_at, created_at as "created_at!"
+        FROM notifications
+        WHERE (? = 0 OR read_at IS NULL)
+        ORDER BY created_at DESC
+        LIMIT ?"#,
+        params.unread,
+        limit
+    )
+    .fetch_all(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let unread = sqlx::query!(
+        r#"SELECT COUNT(*) as "count!: i64" FROM notifications WHERE read_at IS NULL"#
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .count;
+
+    Ok(Json(NotificationList {
+        notifications,
+        unread,
+    }))
+}
+
+async fn mark_notification_read(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let read_at = timestamps::now();
+    let result = sqlx::query!(
+        "UPDATE notifications SET read_at = COALESCE(read_at, ?) WHERE id = ?",
+        read_at,
+        id
+    )
+    .execute(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn mark_all_notifications_read(
+    State(state): State<Arc<AppState>>,
+) -> Result<StatusCode, StatusCode> {
+    let read_at = timestamps::now();
+    sqlx::query!(
+        "UPDATE notifications SET read_at = ? WHERE read_at IS NULL",
+        read_at
+    )
+    .execute(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// ============= QUARANTINE =============
+
+async fn list_quarantine(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<QuarantinedUpload>>, StatusCode> {
+    list_quarantined(&state)
+        .await
+        .map(Json)
+        .map_err(internal_error)
+}
+
+async fn get_quarantined_upload(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<QuarantinedUpload>, StatusCode> {
+    get_quarantined(&state, &id)
+        .await
+        .map_err(internal_error)?
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+// Store a quarantined file as an upload after all, e.g. a false positive
+async fn release_quarantined_upload(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Response, StatusCode> {
+    let upload = release_quarantined(&state, &id)
+        .await
+        .map_err(internal_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok((StatusCode::CREATED, Json(upload)).into_response())
+}
+
+async fn discard_quarantined_upload(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    if discard_quarantined(&state, &id)
+        .await
+        .map_err(internal_error)?
+    {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
+
+// ============= RETENTION =============
+
+#[derive(sqlx::FromRow)]
+struct RetentionRuleRow {
+    id: String,
+    name: String,
+    tag_id: Option<String>,
+    error_logs_only: i64,
+    max_age_days: i64,
+    enabled: i64,
+    created_at: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct SweepQuery {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct PurgesQuery {
+    limit: Option<i64>,
+}
+
+async fn retention_rule_from_row(
+    db: &sqlx::SqlitePool,
+    row: RetentionRuleRow,
+) -> Result<RetentionRule, StatusCode> {
+    let tag = match &row.tag_id {
+        Some(tag_id) => TagRepo::new(db)
+            .get(tag_id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        None => None,
+    };
+    Ok(RetentionRule {
+        id: row.id,
+        name: row.name,
+        tag,
+        error_logs_only: row.error_logs_only != 0,
+        max_age_days: row.max_age_days,
+        enabled: row.enabled != 0,
+        created_at: row.created_at,
+    })
+}
+
+async fn fetch_retention_rule(
+    db: &sqlx::SqlitePool,
+    id: &str,
+) -> Result<RetentionRule, StatusCode> {
+    let row = sqlx::query_as!(
+        RetentionRuleRow,
+        r#"SELECT id as "id!", name as "name!", tag_id, error_logs_only as "error_logs_only!", max_age_days as "max_age_days!", enabled as "enabled!", created_at as "created_at!" FROM retention_rules WHERE id = ?"#,
+        id
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    retention_rule_from_row(db, row).await
+}
+
+async fn validate_retention_rule(
+    db: &sqlx::SqlitePool,
+    name: &str,
+    tag_id: Option<&str>,
+    max_age_days: i64,
+) -> Result<Result<(), String>, StatusCode> {
+    if name.trim().is_empty() {
+        return Ok(Err("Name is required".to_string()));
+    }
+    if max_age_days < 1 {
+        return Ok(Err("max_age_days must be at least 1".to_string()));
+    }
+    if let Some(tag_id) = tag_id {
+        let tag = TagRepo::new(db)
+            .get(tag_id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if tag.is_none() {
+            return Ok(Err(format!("Tag not found: {}", tag_id)));
+        }
+    }
+    Ok(Ok(()))
+}
+
+async fn list_retention_rules(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<RetentionRule>>, StatusCode> {
+    let rows = sqlx::query_as!(
+        RetentionRuleRow,
+        r#"SELECT id as "id!", name as "name!", tag_id, error_logs_only as "error_logs_only!", max_age_days as "max_age_days!", enabled as "enabled!", created_at as "created_at!" FROM retention_rules ORDER BY name"#
+    )
+    .fetch_all(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut rules = Vec::new();
+    for row in rows {
+        rules.push(retention_rule_from_row(&state.db, row).await?);
+    }
+    Ok(Json(rules))
+}
+
+async fn create_retention_rule(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<CreateRetentionRule>,
+) -> Result<Response, StatusCode> {
+    if let Err(message) = validate_retention_rule(
+        &state.db,
+        &payload.name,
+        payload.tag_id.as_deref(),
+        payload.max_age_days,
+    )
+    .await?
+    {
+        return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response());
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let created_at = timestamps::now();
+    sqlx::query!(
+        "INSERT INTO retention_rules (id, name, tag_id, error_logs_only, max_age_days, enabled, created_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        id,
+        payload.name,
+        payload.tag_id,
+        payload.error_logs_only,
+        payload.max_age_days,
+        payload.enabled,
+        created_at
+    )
+    .execute(&state.db)
+    .await
+    .map_err(|e| {
+        if e.to_string().contains("UNIQUE constraint failed") {
+            StatusCode::CONFLICT
+        } else {
+            tracing::error!("Failed to create retention rule: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    let rule = fetch_retention_rule(&state.db, &id).await?;
+    Ok((StatusCode::CREATED, Json(rule)).into_response())
+}
+
+async fn get_retention_rule(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<RetentionRule>, StatusCode> {
+    fetch_retention_rule(&state.db, &id).await.map(Json)
+}
+
+async fn update_retention_rule(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(payload): Json<UpdateRetentionRule>,
+) -> Result<Response, StatusCode> {
+    let existing = fetch_retention_rule(&state.db, &id).await?;
+
+    let name = payload.name.unwrap_or(existing.name);
+    let tag_id = match payload.tag_id {
+        Some(tag_id) if tag_id.is_empty() => None,
+        Some(tag_id) => Some(tag_id),
+        None => existing.tag.map(|tag| tag.id),
+    };
+    let error_logs_only = payload.error_logs_only.unwrap_or(existing.error_logs_only);
+    let max_age_days = payload.max_age_days.unwrap_or(existing.max_age_days);
+    let enabled = payload.enabled.unwrap_or(existing.enabled);
+
+    if let Err(message) =
+        validate_retention_rule(&state.db, &name, tag_id.as_deref(), max_age_days).await?
+    {
+        return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response());
+    }
+
+    sqlx::query!(
+        "UPDATE retention_rules SET name = ?, tag_id = ?, error_logs_only = ?, max_age_days = ?, enabled = ? WHERE id = ?",
+        name,
+        tag_id,
+        error_logs_only,
+        max_age_days,
+        enabled,
+        id
+    )
+    .execute(&state.db)
+    .await
+    .map_err(|e| {
+        if e.to_string().contains("UNIQUE constraint failed") {
+            StatusCode::CONFLICT
+        } else {
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    let rule = fetch_retention_rule(&state.db, &id).await?;
+    Ok(Json(rule).into_response())
+}
+
+async fn delete_retention_rule(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let result = sqlx::query!("DELETE FROM retention_rules WHERE id = ?", id)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Apply every enabled rule once and delete expired intermediate outputs; with dry_run, only
+// report what would be deleted
+async fn sweep_retention(
+    state: &Arc<AppState>,
+    dry_run: bool,
+) -> Result<Vec<RetentionPurge>, sqlx::Error> {
+    let rules = sqlx::query!(
+        r#"SELECT id as "id!", name as "name!", tag_id, error_logs_only as "error_logs_only!", max_age_days as "max_age_days!" FROM retention_rules WHERE enabled = 1 ORDER BY created_at"#
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    let now = chrono::Utc::now();
+    let mut purged = Vec::new();
+    let mut seen = HashSet::new();
+    for rule in rules {
+        let cutoff = timestamps::format(now - chrono::Duration::days(rule.max_age_days));
+        let uploads = sqlx::query!(
+            r#"SELECT u.id as "id!", u.filename as "filename!", u.original_filename as "original_filename!", u.file_size as "file_size!", u.created_at as "created_at!"
+               FROM uploads u
+               WHERE u.created_at < ?
+                 AND (? IS NULL OR EXISTS (SELECT 1 FROM upload_tags ut WHERE ut.upload_id = u.id AND ut.tag_id = ?))
+                 AND (? = 0 OR u.artifact_type = 'error_log')
+                 AND u.protected = 0
+               ORDER BY u.created_at"#,
+            cutoff,
+            rule.tag_id,
+            rule.tag_id,
+            rule.error_logs_only
+        )
+        .fetch_all(&state.db)
+        .await?;
+
+        for upload in uploads {
+            if !seen.insert(upload.id.clone()) {
+                continue; // Already matched by an earlier rule
+            }
+            let purge = RetentionPurge {
+                id: Uuid::new_v4().to_string(),
+                rule_id: Some(rule.id.clone()),
+                rule_name: rule.name.clone(),
+                upload_id: upload.id,
+                original_filename: upload.original_filename,
+                file_size: upload.file_size,
+                uploaded_at: upload.created_at,
+                purged_at: timestamps::now(),
+            };
+
+            if dry_run || purge_upload(state, &purge, &upload.filename).await? {
+                purged.push(purge);
+            }
+        }
+    }
+
+    // Intermediate outputs past their function's TTL, without a rule
+    let now = timestamps::format(now);
+    let expired = UploadRepo::new(&state.db).expired(&now).await?;
+    for upload in expired {
+        if !seen.insert(upload.id.clone()) {
+            continue;
+        }
+        let purge = RetentionPurge {
+            id: Uuid::new_v4().to_string(),
+            rule_id: None,
+            rule_name: "intermediate output expired".to_string(),
+            upload_id: upload.id,
+            original_filename: upload.original_filename,
+            file_size: upload.file_size,
+            uploaded_at: upload.created_at,
+            purged_at: timestamps::now(),
+        };
+        if dry_run || purge_upload(state, &purge, &upload.filename).await? {
+            purged.push(purge);
+        }
+    }
+
+    Ok(purged)
+}
+
+// Delete the upload and its file, and record the purge in the log; false if the upload was
+// protected since it was selected
+async fn purge_upload(
+    state: &Arc<AppState>,
+    purge: &RetentionPurge,
+    filename: &str,
+) -> Result<bool, sqlx::Error> {
+    let uploads = UploadRepo::new(&state.db);
+    let stored = uploads.get(&purge.upload_id).await?;
+    if !uploads.delete(&purge.upload_id).await? {
+        return Ok(false);
+    }
+    // Compressed uploads keep their name; the decompressed copy goes with the `deleted` hooks
+    let path = state.executor.uploads_dir().join(filename);
+    if let Err(e) = tokio::fs::remove_file(&path).await {
+        tracing::warn!("Could not delete purged file {}: {}", path.display(), e);
+    }
+    if let Some(stored) = stored {
+        state.hooks.deleted(state, &stored).await;
+    }
+    sqlx::query!(
+        "INSERT INTO retention_purges (id, rule_id, rule_name, upload_id, original_filename, file_size, uploaded_at, purged_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        purge.id,
+        purge.rule_id,
+        purge.rule_name,
+        purge.upload_id,
+        purge.original_filename,
+        purge.file_size,
+        purge.uploaded_at,
+        purge.purged_at
+    )
+    .execute(&state.db)
+    .await?;
+    Ok(true)
+}
+
+async fn run_retention_sweep(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<SweepQuery>,
+) -> Result<Json<RetentionSweep>, StatusCode> {
+    let purged = sweep_retention(&state, params.dry_run).await.map_err(|e| {
+        tracing::error!("Retention sweep failed: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(Json(RetentionSweep {
+        dry_run: params.dry_run,
+        purged,
+    }))
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ErrorLogPurgeQuery {
+    older_than_days: i64,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+// One-off cleanup of error logs older than `older_than_days`, without setting up a rule;
+// purges are logged like a rule's, under the name "error-log purge"
+async fn purge_error_logs(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ErrorLogPurgeQuery>,
+) -> Result<Response, StatusCode> {
+    if params.older_than_days < 0 {
+        return Ok(json_error(
+            StatusCode::BAD_REQUEST,
+            "older_than_days must not be negative",
+        )
+        .into_response());
+    }
+
+    let cutoff =
+        timestamps::format(chrono::Utc::now() - chrono::Duration::days(params.older_than_days));
+    let uploads = sqlx::query!(
+        r#"SELECT id as "id!", filename as "filename!", original_filename as "original_filename!", file_size as "file_size!", created_at as "created_at!"
+           FROM uploads
+           WHERE artifact_type = 'error_log' AND created_at < ? AND protected = 0
+           ORDER BY created_at"#,
+        cutoff
+    )
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| internal_error(e.to_string()))?;
+
+    let mut purged = Vec::new();
+    for upload in uploads {
+        let purge = RetentionPurge {
+            id: Uuid::new_v4().to_string(),
+            rule_id: None,
+            rule_name: "error-log purge".to_string(),
+            upload_id: upload.id,
+            original_filename: upload.original_filename,
+            file_size: upload.file_size,
+            uploaded_at: upload.created_at,
+            purged_at: timestamps::now(),
+        };
+        if params.dry_run
+            || purge_upload(&state, &purge, &upload.filename)
+                .await
+                .map_err(|e| internal_error(e.to_string()))?
+        {
+            purged.push(purge);
+        }
+    }
+
+    Ok(Json(RetentionSweep {
+        dry_run: params.dry_run,
+        purged,
+    })
+    .into_response())
+}
+
+async fn list_retention_purges(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<PurgesQuery>,
+) -> Result<Json<Vec<RetentionPurge>>, StatusCode> {
+    let limit = params.limit.unwrap_or(100).clamp(1, 1000);
+    let purges = sqlx::query_as!(
+        RetentionPurge,
+        r#"SELECT id as "id!", rule_id, rule_name as "rule_name!", upload_id as "upload_id!", original_filename as "original_filename!", file_size as "file_size!", uploaded_at as "uploaded_at!", purged_at as "purged_at!"
+           FROM retention_purges ORDER BY purged_at DESC LIMIT ?"#,
+        limit
+    )
+    .fetch_all(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(purges))
+}
+
+/// Enforce retention rules in the background, once at startup and then every hour
+pub fn spawn_retention_sweeper(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60 * 60));
+        loop {
+            interval.tick().await;
+            match sweep_retention(&state, false).await {
+                Ok(purged) if !purged.is_empty() => {
+                    tracing::info!("🧹 Retention sweep deleted {} upload(s)", purged.len())
+                }
+                Ok(_) => {}
+                Err(e) => tracing::error!("Retention sweep failed: {}", e),
+            }
+        }
+    });
+}
+
+// ============= WATCH FOLDERS =============
+
+/// Poll the watch folders every `interval` and register the files that stopped changing
+pub fn spawn_watch_folders(state: Arc<AppState>, interval: std::time::Duration) {
+    if state.watch_folders.is_empty() {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut trackers: Vec<SettleTracker> = state
+            .watch_folders
+            .iter()
+            .map(|_| SettleTracker::default())
+            .collect();
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            for (folder, tracker) in state.watch_folders.iter().zip(&mut trackers) {
+                let entries = match list_files(&folder.path).await {
+                    Ok(entries) => entries,
+                    Err(e) => {
+                        tracing::warn!("Cannot read watch folder {}: {}", folder.path.display(), e);
+                        continue;
+                    }
+                };
+                for entry in tracker.settled(entries) {
+                    if let Err(e) = pick_up_watched_file(&state, folder, &entry).await {
+                        tracing::error!("Failed to pick up {}: {}", entry.path.display(), e);
+                    }
+                }
+            }
+        }
+    });
+}
+
+// Register a settled file as an upload, unless this version of it was picked up before.
+// The same checks as for client uploads apply; refused files stay where they are.
+async fn pick_up_watched_file(
+    state: &Arc<AppState>,
+    folder: &WatchFolder,
+    entry: &FolderEntry,
+) -> Result<(), String> {
+    let path = entry.path.to_string_lossy().to_string();
+    let seen = ImportRepo::new(&state.db)
+        .watched_file_seen(&path, entry.size as i64, &entry.modified)
+        .await
+        .map_err(|e| e.to_string())?;
+    if seen {
+        return Ok(());
+    }
+
+    if entry.size > state.max_upload_bytes {
+        let message = format!(
+            "File exceeds the upload limit of {} MB",
+            state.max_upload_bytes / (1024 * 1024)
+        );
+        return reject_watched_file(state, entry, &message).await;
+    }
+    let data = tokio::fs::read(&entry.path)
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+
+    let tags = TagService::new(state);
+    let mut tag_ids = Vec::new();
+    for name in &folder.tags {
+        tag_ids.push(
+            tags.ensure(name, &state.settings().tag_policy.system_color)
+                .await
+                .map_err(|e| e.to_string())?,
+        );
+    }
+
+    let files = [(entry.name.as_str(), data.as_slice())];
+    if let Some(message) = check_storage_quota(state, &files, &tag_ids)
+        .await
+        .map_err(|status| status.to_string())?
+    {
+        return reject_watched_file(state, entry, &message).await;
+    }
+    let screened = [(entry.name.as_str(), None, data.as_slice())];
+    if screen_uploads(state, &screened, &tag_ids)
+        .await
+        .map_err(|status| status.to_string())?
+        .is_some()
+    {
+        tracing::warn!("Watched file {} was quarantined", path);
+        return record_watched_file(
+            state,
+            entry,
+            "quarantined",
+            None,
+            Some("Flagged by the malware scan"),
+        )
+        .await;
+    }
+
+    let upload = store_upload(state, entry.name.clone(), data, None, tag_ids).await?;
+    tracing::info!("📥 Picked up {} as upload {}", path, upload.id);
+    record_watched_file(state, entry, "ingested", Some(&upload.id), None).await
+}
+
+async fn reject_watched_file(
+    state: &AppState,
+    entry: &FolderEntry,
+    message: &str,
+) -> Result<(), String> {
+    tracing::warn!(
+        "Watched file {} was not picked up: {}",
+        entry.path.display(),
+        message
+    );
+    let title = format!("{} was not picked up from its watch folder", entry.name);
+    add_notification(
+        state,
+        "watch_file_rejected",
+        &title,
+        Some(message),
+        None,
+        None,
+    )
+    .await;
+    record_watched_file(state, entry, "rejected", None, Some(message)).await
+}
+
+async fn record_watched_file(
+    state: &AppState,
+    entry: &FolderEntry,
+    status: &str,
+    upload_id: Option<&str>,
+    message: Option<&str>,
+) -> Result<(), String> {
+    let file = WatchedFile {
+        path: entry.path.to_string_lossy().to_string(),
+        file_size: entry.size as i64,
+        modified_at: entry.modified.clone(),
+        status: status.to_string(),
+        upload_id: upload_id.map(str::to_string),
+        message: message.map(str::to_string),
+        picked_up_at: timestamps::now(),
+    };
+    ImportRepo::new(&state.db)
+        .record_watched_file(&file)
+        .await
+        .map_err(|e| format!("Failed to record watched file: {}", e))
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct WatchedFilesQuery {
+    limit: Option<i64>,
+}
+
+// The configured folders and the files most recently picked up from them
+async fn list_watch_folders(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<WatchedFilesQuery>,
+) -> Result<Json<WatchFolderReport>, StatusCode> {
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+    let files = ImportRepo::new(&state.db)
+        .watched_files(limit)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?;
+    Ok(Json(WatchFolderReport {
+        folders: state.watch_folders.clone(),
+        files,
+    }))
+}
+
+// ============= STORAGE =============
+
+#[derive(sqlx::FromRow)]
+struct StorageQuotaRow {
+    tag_id: String,
+    tag_name: String,
+    tag_color: String,
+    tag_description: Option<String>,
+    tag_created_at: String,
+    max_bytes: i64,
+    used_bytes: i64,
+    upload_count: i64,
+}
+
+impl From<StorageQuotaRow> for TagStorageUsage {
+    fn from(row: StorageQuotaRow) -> Self {
+        TagStorageUsage {
+            tag: Tag {
+                id: row.tag_id,
+                name: row.tag_name,
+                color: row.tag_color,
+                description: row.tag_description,
+                created_at: row.tag_created_at,
+            },
+            used_bytes: row.used_bytes,
+            upload_count: row.upload_count,
+            max_bytes: row.max_bytes,
+        }
+    }
+}
+
+// Quotas with their current usage; all of them, or only the one for `tag_id`
+async fn fetch_storage_quotas(
+    db: &sqlx::SqlitePool,
+    tag_id: Option<&str>,
+) -> Result<Vec<TagStorageUsage>, StatusCode> {
+    let rows = sqlx::query_as!(
+        StorageQuotaRow,
+        r#"SELECT t.id as "tag_id!", t.name as "tag_name!", t.color as "tag_color!", t.description as tag_description, t.created_at as "tag_created_at!", q.max_bytes as "max_bytes!",
+                  (SELECT COALESCE(SUM(u.file_size), 0) FROM uploads u INNER JOIN upload_tags ut ON ut.upload_id = u.id WHERE ut.tag_id = q.tag_id) as "used_bytes!: i64",
+                  (SELECT COUNT(*) FROM upload_tags ut WHERE ut.tag_id = q.tag_id) as "upload_count!: i64"
+           FROM storage_quotas q
+           INNER JOIN tags t ON t.id = q.tag_id
+           WHERE (? IS NULL OR q.tag_id = ?)
+           ORDER BY t.name"#,
+        tag_id,
+        tag_id
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(rows.into_iter().map(TagStorageUsage::from).collect())
+}
+
+// `512 B`, `1.5 MB`, `2.0 GB`
+fn format_size(bytes: i64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+// Why storing these files (name and content) with `tag_ids` would go over a quota, if it
+// would. Extension tags count too, since every upload gets one.
+async fn check_storage_quota(
+    state: &AppState,
+    files: &[(&str, &[u8])],
+    tag_ids: &[String],
+) -> Result<Option<String>, StatusCode> {
+    let settings = state.settings();
+    let files: Vec<(Option<String>, i64)> = files
+        .iter()
+        .map(|(name, data)| {
+            let detected = detect_mime_type(&data[..data.len().min(SNIFF_BYTES)], name);
+            (
+                applied_extension_tag(&settings.tag_policy, name, detected),
+                data.len() as i64,
+            )
+        })
+        .collect();
+    check_quota_for_sizes(state, &files, tag_ids).await
+}
+
+// The same for files known only by their extension tag and size
+async fn check_quota_for_sizes(
+    state: &AppState,
+    files: &[(Option<String>, i64)],
+    tag_ids: &[String],
+) -> Result<Option<String>, StatusCode> {
+    let incoming: i64 = files.iter().map(|(_, size)| size).sum();
+
+    if let Some(max_bytes) = state.settings().storage_quota_bytes {
+        let max_bytes = max_bytes as i64;
+        let used_bytes = sqlx::query_scalar!(
+            r#"SELECT COALESCE(SUM(file_size), 0) as "used!: i64" FROM uploads"#
+        )
+        .fetch_one(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if used_bytes + incoming > max_bytes {
+            return Ok(Some(format!(
+                "Storage quota exceeded: {} of {} used, this upload needs {}",
+                format_size(used_bytes),
+                format_size(max_bytes),
+                format_size(incoming)
+            )));
+        }
+    }
+
+    for quota in fetch_storage_quotas(&state.db, None).await? {
+        let tagged_bytes: i64 = files
+            .iter()
+            .filter(|(extension_tag, _)| {
+                tag_ids.contains(&quota.tag.id)
+                    || extension_tag.as_deref() == Some(quota.tag.name.as_str())
+            })
+            .map(|(_, size)| size)
+            .sum();
+        if tagged_bytes > 0 && quota.used_bytes + tagged_bytes > quota.max_bytes {
+            return Ok(Some(format!(
+                "Storage quota for tag '{}' exceeded: {} of {} used, this upload needs {}",
+                quota.tag.name,
+                format_size(quota.used_bytes),
+                format_size(quota.max_bytes),
+                format_size(tagged_bytes)
+            )));
+        }
+    }
+    Ok(None)
+}
+
+async fn get_storage_usage(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<StorageUsage>, StatusCode> {
+    let totals = sqlx::query!(
+        r#"SELECT COALESCE(SUM(file_size), 0) as "used_bytes!: i64", COUNT(*) as "upload_count!: i64" FROM uploads"#
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(StorageUsage {
+        used_bytes: totals.used_bytes,
+        upload_count: totals.upload_count,
+        max_bytes: state
+            .settings()
+            .storage_quota_bytes
+            .map(|bytes| bytes as i64),
+        tags: fetch_storage_quotas(&state.db, None).await?,
+    }))
+}
+
+async fn get_storage_stats(State(state): State<Arc<AppState>>) -> Result<Response, StatusCode> {
+    let stats = storage_stats(&state).await.map_err(internal_error)?;
+    Ok(Json(stats).into_response())
+}
+
+async fn set_storage_quota(
+    State(state): State<Arc<AppState>>,
+    Path(tag_id): Path<String>,
+    Json(payload): Json<SetStorageQuota>,
+) -> Result<Response, StatusCode> {
+    if payload.max_bytes < 0 {
+        return Ok(
+            json_error(StatusCode::BAD_REQUEST, "max_bytes must not be negative").into_response(),
+        );
+    }
+    TagRepo::new(&state.db)
+        .get(&tag_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let created_at = timestamps::now();
+    sqlx::query!(
+        "INSERT INTO storage_quotas (tag_id, max_bytes, created_at) VALUES (?, ?, ?)
+         ON CONFLICT(tag_id) DO UPDATE SET max_bytes = excluded.max_bytes",
+        tag_id,
+        payload.max_bytes,
+        created_at
+    )
+    .execute(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let quota = fetch_storage_quotas(&state.db, Some(&tag_id))
+        .await?
+        .pop()
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(quota).into_response())
+}
+
+async fn delete_storage_quota(
+    State(state): State<Arc<AppState>>,
+    Path(tag_id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let result = sqlx::query!("DELETE FROM storage_quotas WHERE tag_id = ?", tag_id)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if result.rows_affected() == 0 {
+        Err(StatusCode::NOT_FOUND)
+    } else {
+        Ok(StatusCode::NO_CONTENT)
+    }
+}
+
+// ============= ADMIN =============
+
+// Compare uploads/, scripts/, output/, quarantine/ and the thumbnail and decompressed caches
+// with the database, optionally deleting files no row points to. Rows whose file is gone are
+// only reported.
+async fn collect_orphans(state: &AppState, delete: bool) -> Result<OrphanReport, String> {
+    let uploads =
+        sqlx::query!(r#"SELECT id as "id!", filename as "filename!", compression FROM uploads"#)
+            .fetch_all(&state.db)
+            .await
+            .map_err(|e| e.to_string())?;
+    let functions =
+        sqlx::query!(r#"SELECT id as "id!", script_filename as "script_filename!" FROM functions"#)
+            .fetch_all(&state.db)
+            .await
+            .map_err(|e| e.to_string())?;
+    let quarantined =
+        sqlx::query_scalar!(r#"SELECT filename as "filename!" FROM quarantined_uploads"#)
+            .fetch_all(&state.db)
+            .await
+            .map_err(|e| e.to_string())?;
+
+    let uploads_dir = state.executor.uploads_dir().to_path_buf();
+    let scripts_dir = state.executor.scripts_dir().to_path_buf();
+    let mut report = OrphanReport::default();
+    for upload in &uploads {
+        if !uploads_dir.join(&upload.filename).exists() {
+            report.missing.push(MissingFile {
+                directory: "uploads".to_string(),
+                name: upload.filename.clone(),
+                referenced_by: format!("upload {}", upload.id),
+            });
+        }
+    }
+    // Quick functions have no script
+    let functions: Vec<_> = functions
+        .into_iter()
+        .filter(|function| !function.script_filename.is_empty())
+        .collect();
+    for function in &functions {
+        if !scripts_dir.join(&function.script_filename).exists() {
+            report.missing.push(MissingFile {
+                directory: "scripts".to_string(),
+                name: function.script_filename.clone(),
+                referenced_by: format!("function {}", function.id),
+            });
+        }
+    }
+
+    // Earlier versions of a script (`<timestamp>_<function id>.py`) are kept until the
+    // function is deleted. Output files only live there while a job is being registered.
+    let version_suffixes: Vec<String> = functions.iter().map(|f| format!("_{}.py", f.id)).collect();
+    // Thumbnails are a cache: any size of a live upload may be there
+    let thumbnails = uploads
+        .iter()
+        .flat_map(|u| thumbnail_names(&u.id))
+        .collect();
+    let decompressed = uploads
+        .iter()
+        .filter(|u| u.compression.is_some())
+        .map(|u| u.filename.clone())
+        .collect();
+    let referenced = [
+        (
+            "uploads",
+            uploads_dir,
+            uploads.into_iter().map(|u| u.filename).collect(),
+        ),
+        (
+            "scripts",
+            scripts_dir,
+            functions.into_iter().map(|f| f.script_filename).collect(),
+        ),
+        (
+            "output",
+            state.executor.output_dir().to_path_buf(),
+            HashSet::new(),
+        ),
+        ("thumbnails", state.thumbnails_dir.clone(), thumbnails),
+        ("decompressed", state.decompressed_dir.clone(), decompressed),
+        (
+            "quarantine",
+            state.quarantine_dir.clone(),
+            quarantined.into_iter().collect(),
+        ),
+    ];
+    report.orphans = tokio::task::spawn_blocking(move || {
+        let mut orphans = Vec::new();
+        for (directory, path, names) in &referenced {
+            orphans.extend(
+                scan_directory(path, directory, names, ORPHAN_GRACE)?
+                    .into_iter()
+                    .filter(|orphan| {
+                        *directory != "scripts"
+                            || !version_suffixes
+                                .iter()
+                                .any(|suffix| orphan.name.ends_with(suffix.as_str()))
+                    }),
+            );
+        }
+        if delete {
+            let failed = delete_orphans(&orphans);
+            if failed > 0 {
+                tracing::warn!("{} orphaned file(s) could not be deleted", failed);
+            }
+        }
+        Ok::<_, std::io::Error>(orphans)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())?;
+    report.orphan_bytes = report.orphans.iter().map(|o| o.size_bytes).sum();
+    report.deleted = delete;
+    Ok(report)
+}
+
+async fn list_orphans(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<OrphanReport>, StatusCode> {
+    collect_orphans(&state, false).await.map(Json).map_err(|e| {
+        tracing::error!("Orphan scan failed: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+async fn cleanup_orphans(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<OrphanReport>, StatusCode> {
+    let report = collect_orphans(&state, true).await.map_err(|e| {
+        tracing::error!("Orphan cleanup failed: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    tracing::info!(
+        "🧹 Deleted {} orphaned file(s), {} bytes",
+        report.orphans.len(),
+        report.orphan_bytes
+    );
+    Ok(Json(report))
+}
+
+// Background work in flight: job executions, trigger evaluations, outlier checks
+// Local execution slots, and the jobs waiting for one by source
+async fn job_queue(State(state): State<Arc<AppState>>) -> Json<QueueSummary> {
+    Json(state.job_slots.summary())
+}
+
+async fn warm_pool(State(state): State<Arc<AppState>>) -> Result<Response, StatusCode> {
+    match state.executor.warm_pool() {
+        Some(pool) => Ok(Json(pool.summary()).into_response()),
+        None => Ok(json_error(
+            StatusCode::NOT_FOUND,
+            "The warm pool is disabled (see --warm-pool-size)",
+        )
+        .into_response()),
+    }
+}
+
+const MIRROR_DISABLED: &str = "Mirroring is disabled (see --mirror-dir)";
+
+async fn get_mirror_status(State(state): State<Arc<AppState>>) -> Result<Response, StatusCode> {
+    match mirror_status(&state).await.map_err(internal_error)? {
+        Some(status) => Ok(Json(status).into_response()),
+        None => Ok(json_error(StatusCode::NOT_FOUND, MIRROR_DISABLED).into_response()),
+    }
+}
+
+// Retry the uploads missing from the mirror and snapshot the database, without waiting for
+// the next --mirror-interval-minutes
+async fn sync_mirror_now(State(state): State<Arc<AppState>>) -> Result<Response, StatusCode> {
+    if state.mirror_dir.is_none() {
+        return Ok(json_error(StatusCode::NOT_FOUND, MIRROR_DISABLED).into_response());
+    }
+    let sync = sync_mirror(&state).await.map_err(internal_error)?;
+    Ok(Json(sync).into_response())
+}
+
+async fn get_settings(State(state): State<Arc<AppState>>) -> Response {
+    Json(state.settings().values.clone()).into_response()
+}
+
+// Change some settings, e.g. `{"extension_tags": false}`, until the next reload or restart
+async fn update_settings(
+    State(state): State<Arc<AppState>>,
+    Json(changes): Json<serde_json::Map<String, serde_json::Value>>,
+) -> Response {
+    match change_settings(&state, &changes) {
+        Ok(changed) => Json(serde_json::json!({
+            "changed": changed,
+            "settings": state.settings().values.clone(),
+        }))
+        .into_response(),
+        Err(message) => json_error(StatusCode::BAD_REQUEST, message).into_response(),
+    }
+}
+
+// Same as SIGHUP, but says what changed or why the file was refused
+async fn reload_config(State(state): State<Arc<AppState>>) -> Result<Response, StatusCode> {
+    if state.config_file.is_none() {
+        return Ok(json_error(
+            StatusCode::NOT_FOUND,
+            "No config file to reload (see --config-file)",
+        )
+        .into_response());
+    }
+    match reload_settings(&state) {
+        Ok(changed) => Ok(Json(serde_json::json!({
+            "changed": changed,
+            "settings": state.settings().values.clone(),
+        }))
+        .into_response()),
+        Err(message) => Ok(json_error(StatusCode::BAD_REQUEST, message).into_response()),
+    }
+}
+
+async fn list_tasks(State(state): State<Arc<AppState>>) -> Json<TaskSummary> {
+    Json(state.tasks.summary())
+}
+
+/// Check the storage directories against the database once at startup, logging what is out
+/// of sync and deleting orphaned files if `delete` is set
+pub fn spawn_orphan_scan(state: Arc<AppState>, delete: bool) {
+    tokio::spawn(async move {
+        match collect_orphans(&state, delete).await {
+            Ok(report) => {
+                for orphan in &report.orphans {
+                    tracing::warn!(
+                        "Orphaned file {}/{} ({} bytes){}",
+                        orphan.directory,
+                        orphan.name,
+                        orphan.size_bytes,
+                        if delete { ", deleted" } else { "" }
+                    );
+                }
+                for missing in &report.missing {
+                    tracing::warn!(
+                        "Missing file {}/{} referenced by {}",
+                        missing.directory,
+                        missing.name,
+                        missing.referenced_by
+                    );
+                }
+                tracing::info!(
+                    "✅ Orphan scan: {} orphaned file(s), {} missing file(s)",
+                    report.orphans.len(),
+                    report.missing.len()
+                );
+            }
+            Err(e) => tracing::error!("Orphan scan failed: {}", e),
+        }
+    });
+}
+
+// ============= SHARE LINKS =============
+
+async fn list_share_links(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<ShareLink>>, StatusCode> {
+    UploadRepo::new(&state.db)
+        .get(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let links = ShareRepo::new(&state.db)
+        .list_for_upload(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?;
+    Ok(Json(links))
+}
+
+// Make a public link to an upload, e.g. for a collaborator without an account
+async fn create_share_link(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    payload: Option<Json<CreateShareLink>>,
+) -> Result<Response, StatusCode> {
+    let payload = payload.map(|Json(payload)| payload).unwrap_or_default();
+    UploadRepo::new(&state.db)
+        .get(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    // Whoever holds a link is anonymous, so only unrestricted uploads can be shared
+    if check_upload_access(&state, &Requester::default(), &id)
+        .await
+        .is_err()
+    {
+        return Ok(json_error(
+            StatusCode::FORBIDDEN,
+            "Uploads with a restricted tag cannot be shared by link",
+        )
+        .into_response());
+    }
+    let expires_at = match payload.expires_in_hours {
+        Some(hours) if hours <= 0 => {
+            return Ok(
+                json_error(StatusCode::BAD_REQUEST, "expires_in_hours must be positive")
+                    .into_response(),
+            )
+        }
+        Some(hours) => match chrono::TimeDelta::try_hours(hours)
+            .and_then(|delta| chrono::Utc::now().checked_add_signed(delta))
+        {
+            Some(at) => Some(timestamps::format(at)),
+            None => {
+                return Ok(
+                    json_error(StatusCode::BAD_REQUEST, "expires_in_hours is too large")
+                        .into_response(),
+                )
+            }
+        },
+        None => None,
+    };
+    let label = payload
+        .label
+        .as_deref()
+        .map(str::trim)
+        .filter(|l| !l.is_empty());
+
+    let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    let shares = ShareRepo::new(&state.db);
+    shares
+        .insert(
+            &token,
+            &id,
+            label,
+            &timestamps::now(),
+            expires_at.as_deref(),
+        )
+        .await
+        .map_err(|e| internal_error(e.to_string()))?;
+    let link = shares
+        .get(&token)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    let origin = request_origin(&headers, peer);
+    record_custody_event(&state, &id, "shared", link.label.as_deref(), &origin).await;
+    Ok((StatusCode::CREATED, Json(link)).into_response())
+}
+
+async fn revoke_share_link(
+    State(state): State<Arc<AppState>>,
+    Path((id, token)): Path<(String, String)>,
+    headers: HeaderMap,
+    peer: Option<ConnectInfo<SocketAddr>>,
+) -> Result<StatusCode, StatusCode> {
+    let revoked = ShareRepo::new(&state.db)
+        .revoke(&id, &token, &timestamps::now())
+        .await
+        .map_err(|e| internal_error(e.to_string()))?;
+    if !revoked {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let origin = request_origin(&headers, peer);
+    record_custody_event(&state, &id, "share_revoked", None, &origin).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Public pages of shared uploads at `/share/<token>`, outside `/api`: what a link shows
+/// does not depend on the rest of the API staying open
+pub fn share_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/share/:token", get(get_shared_upload))
+        .route("/share/:token/preview", get(preview_shared_upload))
+        .route("/share/:token/download", get(download_shared_upload))
+}
+
+// The link behind a token, counting the request; revoked and expired links are gone (410), and
+// links to an upload that has since been given a restricted tag are not found
+async fn open_share_link(
+    state: &AppState,
+    token: &str,
+    download: bool,
+) -> Result<ShareLink, StatusCode> {
+    let shares = ShareRepo::new(&state.db);
+    let link = shares
+        .get(token)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let now = timestamps::now();
+    if link.revoked_at.is_some() || link.expires_at.as_ref().is_some_and(|at| *at <= now) {
+        return Err(StatusCode::GONE);
+    }
+    check_upload_access(state, &Requester::default(), &link.upload_id).await?;
+    shares
+        .record_access(token, download, &now)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?;
+    Ok(link)
+}
+
+async fn get_shared_upload(
+    State(state): State<Arc<AppState>>,
+    Path(token): Path<String>,
+) -> Result<Json<SharedUpload>, StatusCode> {
+    let link = open_share_link(&state, &token, false).await?;
+    let upload = UploadRepo::new(&state.db)
+        .get(&link.upload_id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let extension = effective_extension(
+        &upload.original_filename,
+        upload.detected_mime_type.as_deref(),
+    );
+    Ok(Json(SharedUpload {
+        preview_url: matches!(extension.as_deref(), Some("csv" | "parquet"))
+            .then(|| format!("/share/{}/preview", token)),
+        download_url: format!("/share/{}/download", token),
+        filename: upload.original_filename,
+        file_size: upload.file_size,
+        mime_type: upload.detected_mime_type.or(upload.mime_type),
+        created_at: upload.created_at,
+        sha256: upload.sha256,
+        expires_at: link.expires_at,
+    }))
+}
+
+// Same query options as `/api/uploads/:id/table-preview`
+async fn preview_shared_upload(
+    State(state): State<Arc<AppState>>,
+    Path(token): Path<String>,
+    query: Query<TableQuery>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let link = open_share_link(&state, &token, false).await?;
+    // Only the format negotiation carries over; the link holder's other headers are ignored
+    let mut accept = HeaderMap::new();
+    if let Some(value) = headers.get(header::ACCEPT) {
+        accept.insert(header::ACCEPT, value.clone());
+    }
+    get_table_preview(State(state), Path(link.upload_id), query, accept).await
+}
+
+async fn download_shared_upload(
+    State(state): State<Arc<AppState>>,
+    Path(token): Path<String>,
+) -> Result<Response, StatusCode> {
+    let link = open_share_link(&state, &token, true).await?;
+    send_upload_file(State(state), Path(link.upload_id)).await
+}
+
+// ============= WEBDAV =============
+
+// Where the upload folder is mounted
+const DAV_ROOT: &str = "/dav/";
+
+/// The upload store as a WebDAV folder, for instruments and file explorers; mounted at
+/// `/dav/` when --webdav is set
+pub fn webdav_routes(state: &AppState) -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/dav", any(webdav_root))
+        .route("/dav/", any(webdav_root))
+        .route("/dav/*name", any(webdav_file))
+        .route_layer(upload_body_limit(state))
+}
+
+// Data uploads (no error logs) visible to the requester under the names they appear with in
+// the folder: the original names, with `name (2).ext` for repeats in upload order
+async fn dav_listing(
+    state: &AppState,
+    requester: &Requester,
+    filename_contains: Option<&str>,
+) -> Result<Vec<(String, StoredUpload)>, StatusCode> {
+    let hidden = hidden_tag_ids(state, requester).await?;
+    let mut uploads = UploadRepo::new(&state.db)
+        .list(&UploadFilter {
+            artifact_type: Some("data"),
+            filename_contains,
+            hidden_tag_ids: &hidden,
+            ..Default::default()
+        })
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    uploads.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+    let names = unique_entry_names(uploads.iter().map(|u| u.original_filename.as_str()));
+    Ok(names.into_iter().zip(uploads).collect())
+}
+
+// The upload shown as `name` in the folder. Repeats of a name share its stem without the
+// ` (2)` counters, so only uploads whose name contains that stem need to be numbered. Path
+// separators are shown as `_`, so the longest piece without one is matched.
+async fn dav_entry(
+    state: &AppState,
+    requester: &Requester,
+    name: &str,
+) -> Result<Option<StoredUpload>, StatusCode> {
+    let mut stem = match name.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() => stem,
+        _ => name,
+    };
+    while let Some(rest) = stem.strip_suffix(')').and_then(|rest| {
+        rest.trim_end_matches(|c: char| c.is_ascii_digit())
+            .strip_suffix(" (")
+    }) {
+        stem = rest;
+    }
+    let piece = stem
+        .split('_')
+        .max_by_key(|piece| piece.len())
+        .unwrap_or("");
+    // Nameless uploads are shown as `file`
+    let filename_contains =
+        (!piece.is_empty() && !stem.eq_ignore_ascii_case("file")).then_some(piece);
+    Ok(dav_listing(state, requester, filename_contains)
+        .await?
+        .into_iter()
+        .find(|(entry, _)| entry == name)
+        .map(|(_, upload)| upload))
+}
+
+// Write a request body to a hidden file in the uploads directory, so it can be moved into
+// place once checked. Bodies over --max-upload-size-mb get the same 413 as other uploads; on
+// any failure the partial file is removed.
+async fn stage_upload_body(
+    state: &AppState,
+    body: axum::body::Body,
+) -> Result<Result<std::path::PathBuf, Response>, StatusCode> {
+    let staged = state
+        .executor
+        .uploads_dir()
+        .join(format!(".{}.part", Uuid::new_v4()));
+    match write_staged_body(state, &staged, body).await {
+        Ok(Ok(())) => Ok(Ok(staged)),
+        Ok(Err(refused)) => {
+            let _ = tokio::fs::remove_file(&staged).await;
+            Ok(Err(refused))
+        }
+        Err(status) => {
+            let _ = tokio::fs::remove_file(&staged).await;
+            Err(status)
+        }
+    }
+}
+
+async fn write_staged_body(
+    state: &AppState,
+    staged: &std::path::Path,
+    body: axum::body::Body,
+) -> Result<Result<(), Response>, StatusCode> {
+    let mut file = tokio::fs::File::create(staged)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?;
+    let mut stream = body.into_data_stream();
+    let mut size = 0u64;
+    while let Some(chunk) = stream.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                return Ok(Err(
+                    json_error(StatusCode::BAD_REQUEST, e.to_string()).into_response()
+                ))
+            }
+        };
+        size += chunk.len() as u64;
+        if size > state.max_upload_bytes {
+            return Ok(Err(upload_body_error(
+                state,
+                StatusCode::PAYLOAD_TOO_LARGE,
+                String::new(),
+            )));
+        }
+        file.write_all(&chunk)
+            .await
+            .map_err(|e| internal_error(e.to_string()))?;
+    }
+    file.flush()
+        .await
+        .map_err(|e| internal_error(e.to_string()))?;
+    Ok(Ok(()))
+}
+
+fn dav_file_resource(name: &str, upload: &StoredUpload) -> DavResource {
+    DavResource {
+        href: file_href(DAV_ROOT, name),
+        collection: false,
+        size: upload.file_size,
+        content_type: upload
+            .detected_mime_type
+            .clone()
+            .or_else(|| upload.mime_type.clone()),
+        modified: Some(upload.created_at.clone()),
+        etag: upload.sha256.clone(),
+    }
+}
+
+fn dav_multistatus(resources: &[DavResource]) -> Response {
+    (
+        StatusCode::MULTI_STATUS,
+        [(header::CONTENT_TYPE, "application/xml; charset=utf-8")],
+        render_multistatus(resources),
+    )
+        .into_response()
+}
+
+fn dav_options(access: DavAccess) -> Response {
+    (
+        StatusCode::OK,
+        [
+            (header::HeaderName::from_static("dav"), "1"),
+            (header::ALLOW, access.allowed_methods()),
+            (header::HeaderName::from_static("ms-author-via"), "DAV"),
+        ],
+    )
+        .into_response()
+}
+
+fn dav_not_allowed(access: DavAccess) -> Response {
+    (
+        StatusCode::METHOD_NOT_ALLOWED,
+        [(header::ALLOW, access.allowed_methods())],
+    )
+        .into_response()
+}
+
+async fn webdav_root(
+    State(state): State<Arc<AppState>>,
+    method: Method,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let access = state.webdav.ok_or(StatusCode::NOT_FOUND)?;
+    match method.as_str() {
+        "OPTIONS" => Ok(dav_options(access)),
+        "PROPFIND" => {
+            let mut resources = vec![DavResource {
+                href: DAV_ROOT.to_string(),
+                collection: true,
+                size: 0,
+                content_type: None,
+                modified: None,
+                etag: None,
+            }];
+            // The folder is flat, so `infinity` lists the same as 1
+            let depth = headers.get("depth").and_then(|v| v.to_str().ok());
+            if depth != Some("0") {
+                for (name, upload) in
+                    dav_listing(&state, &Requester::from_headers(&headers), None).await?
+                {
+                    resources.push(dav_file_resource(&name, &upload));
+                }
+            }
+            Ok(dav_multistatus(&resources))
+        }
+        _ => Ok(dav_not_allowed(access)),
+    }
+}
+
+async fn webdav_file(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    method: Method,
+    headers: HeaderMap,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    body: axum::body::Body,
+) -> Result<Response, StatusCode> {
+    let access = state.webdav.ok_or(StatusCode::NOT_FOUND)?;
+    if method == Method::OPTIONS {
+        return Ok(dav_options(access));
+    }
+    let found = dav_entry(&state, &Requester::from_headers(&headers), &name).await?;
+
+    match (method.as_str(), found) {
+        ("PROPFIND", Some(upload)) => Ok(dav_multistatus(&[dav_file_resource(&name, &upload)])),
+        ("GET" | "HEAD", Some(upload)) => {
+            let mut response = Response::builder()
+                .header(header::CONTENT_LENGTH, upload.file_size)
+                .header(
+                    header::CONTENT_TYPE,
+                    upload
+                        .detected_mime_type
+                        .as_deref()
+                        .or(upload.mime_type.as_deref())
+                        .unwrap_or("application/octet-stream"),
+                );
+            if let Some(sha256) = &upload.sha256 {
+                response = response.header(header::ETAG, format!("\"{}\"", sha256));
+            }
+            if let Some(modified) = http_date(&upload.created_at) {
+                response = response.header(header::LAST_MODIFIED, modified);
+            }
+            let body = if method == Method::HEAD {
+                axum::body::Body::empty()
+            } else {
+                // Mounted drives open large files, so they are sent from disk as they are read
+                let path =
+                    plain_upload_path(&state, &upload.filename, upload.compression.as_deref())
+                        .await
+                        .map_err(|_| StatusCode::NOT_FOUND)?;
+                let file = tokio::fs::File::open(&path)
+                    .await
+                    .map_err(|_| StatusCode::NOT_FOUND)?;
+                axum::body::Body::from_stream(tokio_util::io::ReaderStream::new(file))
+            };
+            response
+                .body(body)
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+        }
+        ("DELETE", Some(upload)) if access == DavAccess::ReadWrite => {
+            if upload.protected {
+                return Ok(json_error(StatusCode::LOCKED, PROTECTED_MESSAGE).into_response());
+            }
+            remove_upload(&state, &upload).await?;
+            Ok(StatusCode::NO_CONTENT.into_response())
+        }
+        // Uploads never change once stored, and the folder has no subfolders
+        ("PUT", Some(_)) if access == DavAccess::ReadWrite => Ok(json_error(
+            StatusCode::CONFLICT,
+            "Uploads cannot be overwritten; store the file under a new name",
+        )
+        .into_response()),
+        ("PUT", None) if access == DavAccess::ReadWrite => {
+            if !is_valid_filename(&name) || name == "." || name == ".." {
+                return Ok(
+                    json_error(StatusCode::CONFLICT, "Folders are not supported").into_response(),
+                );
+            }
+            let staged = match stage_upload_body(&state, body).await? {
+                Ok(staged) => staged,
+                Err(refused) => return Ok(refused),
+            };
+            let stored = store_dav_upload(&state, &name, &staged).await;
+            if !matches!(stored, Ok(Ok(_))) {
+                let _ = tokio::fs::remove_file(&staged).await;
+            }
+            let upload = match stored? {
+                Ok(upload) => upload,
+                Err(refused) => return Ok(refused),
+            };
+            let origin = request_origin(&headers, peer);
+            record_upload_origin(&state, &upload.id, "webdav", None, &origin, None).await;
+            Ok(StatusCode::CREATED.into_response())
+        }
+        ("PROPFIND" | "GET" | "HEAD" | "DELETE", None) => Err(StatusCode::NOT_FOUND),
+        _ => Ok(dav_not_allowed(access)),
+    }
+}
+
+// Check a staged `PUT` body against the quotas and the `on_received` hooks like any other
+// upload, and store it if it passes
+async fn store_dav_upload(
+    state: &Arc<AppState>,
+    name: &str,
+    staged: &std::path::Path,
+) -> Result<Result<UploadResponse, Response>, StatusCode> {
+    let size = tokio::fs::metadata(staged)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .len() as i64;
+    let mut head = Vec::with_capacity(SNIFF_BYTES);
+    tokio::fs::File::open(staged)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .take(SNIFF_BYTES as u64)
+        .read_to_end(&mut head)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?;
+    let extension_tag = applied_extension_tag(
+        &state.settings().tag_policy,
+        name,
+        detect_mime_type(&head, name),
+    );
+    if let Some(message) = check_quota_for_sizes(state, &[(extension_tag, size)], &[]).await? {
+        return Ok(Err(
+            json_error(StatusCode::INSUFFICIENT_STORAGE, message).into_response()
+        ));
+    }
+    if let Some(refused) = screen_staged_upload(state, name, staged).await? {
+        return Ok(Err(refused));
+    }
+    store_upload_file(state, name.to_string(), staged, None, Vec::new())
+        .await
+        .map(Ok)
+        .map_err(internal_error)
+}
+
+// ============= PIPELINE SNAPSHOTS =============
+
+fn snapshot_model(
+    stored: StoredSnapshot,
+    active_id: Option<&str>,
+    with_pipeline: bool,
+) -> Result<PipelineSnapshot, StatusCode> {
+    let pipeline: Pipeline =
+        serde_json::from_str(&stored.pipeline).map_err(|e| internal_error(e.to_string()))?;
+    Ok(PipelineSnapshot {
+        active: active_id == Some(stored.id.as_str()),
+        id: stored.id,
+        label: stored.label,
+        description: stored.description,
+        created_at: stored.created_at,
+        activated_at: stored.activated_at,
+        function_count: pipeline.functions.len(),
+        tag_count: pipeline.tags.len(),
+        pipeline: with_pipeline.then_some(pipeline),
+    })
+}
+
+async fn list_pipeline_snapshots(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<PipelineSnapshot>>, StatusCode> {
+    let snapshots = SnapshotRepo::new(&state.db);
+    let active_id = snapshots
+        .active_id()
+        .await
+        .map_err(|e| internal_error(e.to_string()))?;
+    let stored = snapshots
+        .list()
+        .await
+        .map_err(|e| internal_error(e.to_string()))?;
+    let mut result = Vec::with_capacity(stored.len());
+    for snapshot in stored {
+        result.push(snapshot_model(snapshot, active_id.as_deref(), false)?);
+    }
+    Ok(Json(result))
+}
+
+// Capture the functions, their scripts, the tags and the wiring under a label; the new
+// snapshot becomes the active one
+async fn create_pipeline_snapshot(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<CreatePipelineSnapshot>,
+) -> Result<Response, StatusCode> {
+    let label = payload.label.trim();
+    if label.is_empty() {
+        return Ok(json_error(StatusCode::BAD_REQUEST, "Label must not be empty").into_response());
+    }
+    let pipeline = current_pipeline(&state, true)
+        .await
+        .map_err(internal_error)?;
+    let id = Uuid::new_v4().to_string();
+    let snapshots = SnapshotRepo::new(&state.db);
+    let content = serde_json::to_string(&pipeline).map_err(|e| internal_error(e.to_string()))?;
+    if let Err(e) = snapshots
+        .insert(&id, label, payload.description.as_deref(), &content)
+        .await
+    {
+        return match conflict_or_internal(e) {
+            StatusCode::CONFLICT => Ok(json_error(
+                StatusCode::CONFLICT,
+                format!("A snapshot is already labelled {}", label),
+            )
+            .into_response()),
+            status => Err(status),
+        };
+    }
+
+    let stored = snapshots
+        .get(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    let snapshot = snapshot_model(stored, Some(&id), true)?;
+    Ok((StatusCode::CREATED, Json(snapshot)).into_response())
+}
+
+async fn get_pipeline_snapshot(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<PipelineSnapshot>, StatusCode> {
+    let snapshots = SnapshotRepo::new(&state.db);
+    let stored = snapshots
+        .get(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let active_id = snapshots
+        .active_id()
+        .await
+        .map_err(|e| internal_error(e.to_string()))?;
+    snapshot_model(stored, active_id.as_deref(), true).map(Json)
+}
+
+async fn delete_pipeline_snapshot(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    let deleted = SnapshotRepo::new(&state.db)
+        .delete(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?;
+    if deleted {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct SnapshotDiffQuery {
+    against: Option<String>, // another snapshot; the current pipeline if missing
+}
+
+// What changed from a snapshot to another one, or to the pipeline as it is now
+async fn diff_pipeline_snapshot(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Query(params): Query<SnapshotDiffQuery>,
+) -> Result<Response, StatusCode> {
+    let snapshots = SnapshotRepo::new(&state.db);
+    let Some(stored) = snapshots
+        .get(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+    else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+    let from: Pipeline =
+        serde_json::from_str(&stored.pipeline).map_err(|e| internal_error(e.to_string()))?;
+    let to: Pipeline = match &params.against {
+        Some(against) => {
+            let Some(other) = snapshots
+                .get(against)
+                .await
+                .map_err(|e| internal_error(e.to_string()))?
+            else {
+                return Ok(
+                    json_error(StatusCode::BAD_REQUEST, "Unknown snapshot to diff against")
+                        .into_response(),
+                );
+            };
+            serde_json::from_str(&other.pipeline).map_err(|e| internal_error(e.to_string()))?
+        }
+        None => current_pipeline(&state, false)
+            .await
+            .map_err(internal_error)?,
+    };
+    Ok(Json(snapshots::diff(&from, &to)).into_response())
+}
+
+async fn restore_pipeline_snapshot(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Response, StatusCode> {
+    let snapshots = SnapshotRepo::new(&state.db);
+    let stored = snapshots
+        .get(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let pipeline: Pipeline =
+        serde_json::from_str(&stored.pipeline).map_err(|e| internal_error(e.to_string()))?;
+
+    let conflicts = restore_conflicts(&state, &pipeline)
+        .await
+        .map_err(internal_error)?;
+    if !conflicts.is_empty() {
+        return Ok(json_error(
+            StatusCode::CONFLICT,
+            format!(
+                "Functions created since hold names the snapshot needs: {}",
+                conflicts.join(", ")
+            ),
+        )
+        .into_response());
+    }
+
+    let before = current_pipeline(&state, false)
+        .await
+        .map_err(internal_error)?;
+    let disabled_functions = restore_pipeline(&state, &pipeline)
+        .await
+        .map_err(internal_error)?;
+    snapshots
+        .activate(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?;
+    tracing::info!("Restored pipeline snapshot {}", stored.label);
+
+    let stored = snapshots
+        .get(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(SnapshotRestore {
+        snapshot: snapshot_model(stored, Some(&id), false)?,
+        changes: snapshots::diff(&before, &pipeline),
+        disabled_functions,
+    })
+    .into_response())
+}
+
+// ============= RELEASES =============
+
+async fn release_model(
+    state: &AppState,
+    stored: StoredRelease,
+    with_files: bool,
+) -> Result<Release, StatusCode> {
+    let files = match with_files {
+        true => Some(
+            ReleaseRepo::new(&state.db)
+                .files(&stored.id)
+                .await
+                .map_err(|e| internal_error(e.to_string()))?,
+        ),
+        false => None,
+    };
+    Ok(Release {
+        selection: serde_json::from_str(&stored.selection).unwrap_or_default(),
+        id: stored.id,
+        name: stored.name,
+        identifier: stored.identifier,
+        description: stored.description,
+        created_at: stored.created_at,
+        checksum: stored.checksum,
+        file_count: stored.file_count,
+        total_bytes: stored.total_bytes,
+        files,
+    })
+}
+
+async fn list_releases(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<Release>>, StatusCode> {
+    let stored = ReleaseRepo::new(&state.db)
+        .list()
+        .await
+        .map_err(|e| internal_error(e.to_string()))?;
+    let mut releases = Vec::with_capacity(stored.len());
+    for release in stored {
+        releases.push(release_model(&state, release, false).await?);
+    }
+    Ok(Json(releases))
+}
+
+// Freeze the selected uploads into a release: their checksums are recorded and the uploads
+// protected, so the bundle downloads the same for as long as the release exists
+async fn create_release(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    Json(payload): Json<CreateRelease>,
+) -> Result<Response, StatusCode> {
+    let name = payload.name.trim();
+    if name.is_empty() {
+        return Ok(json_error(StatusCode::BAD_REQUEST, "Name must not be empty").into_response());
+    }
+    let identifier = payload
+        .identifier
+        .as_deref()
+        .map(str::trim)
+        .filter(|i| !i.is_empty());
+    if identifier.is_some_and(|i| !is_doi_like(i)) {
+        return Ok(json_error(
+            StatusCode::BAD_REQUEST,
+            "Identifier must look like a DOI, e.g. 10.5281/zenodo.1234567",
+        )
+        .into_response());
+    }
+    let uploads = match select_uploads(
+        &state,
+        &Requester::from_headers(&headers),
+        &payload.selection.upload_ids,
+        payload.selection.tag_expression.as_deref(),
+    )
+    .await
+    {
+        Ok(uploads) => uploads,
+        Err(response) => return Ok(response),
+    };
+    if uploads.is_empty() {
+        return Ok(json_error(StatusCode::BAD_REQUEST, "No uploads match").into_response());
+    }
+
+    let files = release_files(&state, &uploads)
+        .await
+        .map_err(internal_error)?;
+    let id = Uuid::new_v4().to_string();
+    let selection =
+        serde_json::to_string(&payload.selection).map_err(|e| internal_error(e.to_string()))?;
+    let checksum = sha256_hex(sha256sums(&files).as_bytes());
+    let releases = ReleaseRepo::new(&state.db);
+    let inserted = releases
+        .insert(
+            &NewRelease {
+                id: &id,
+                name,
+                identifier,
+                description: payload.description.as_deref(),
+                selection: &selection,
+                checksum: &checksum,
+                created_at: &timestamps::now(),
+            },
+            &files,
+        )
+        .await;
+    if let Err(e) = inserted {
+        return match conflict_or_internal(e) {
+            StatusCode::CONFLICT => Ok(json_error(
+                StatusCode::CONFLICT,
+                "A release with this name or identifier exists",
+            )
+            .into_response()),
+            status => Err(status),
+        };
+    }
+    tracing::info!("Released {} file(s) as {}", files.len(), name);
+    let origin = request_origin(&headers, peer);
+    for file in &files {
+        record_custody_event(&state, &file.upload_id, "released", Some(name), &origin).await;
+    }
+
+    let stored = releases
+        .get(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    let release = release_model(&state, stored, true).await?;
+    Ok((StatusCode::CREATED, Json(release)).into_response())
+}
+
+async fn get_release(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<Release>, StatusCode> {
+    let stored = ReleaseRepo::new(&state.db)
+        .get(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    release_model(&state, stored, true).await.map(Json)
+}
+
+// Record the identifier minted after the release was made, e.g. once a repository assigned a
+// DOI; the files stay frozen, and an identifier cannot be changed once set
+async fn update_release(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(payload): Json<UpdateRelease>,
+) -> Result<Response, StatusCode> {
+    let releases = ReleaseRepo::new(&state.db);
+    let stored = releases
+        .get(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let identifier = payload.identifier.trim();
+    if !is_doi_like(identifier) {
+        return Ok(json_error(
+            StatusCode::BAD_REQUEST,
+            "Identifier must look like a DOI, e.g. 10.5281/zenodo.1234567",
+        )
+        .into_response());
+    }
+    if stored.identifier.is_some() {
+        return Ok(json_error(
+            StatusCode::CONFLICT,
+            "The release already has an identifier",
+        )
+        .into_response());
+    }
+    match releases.set_identifier(&id, identifier).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return Ok(json_error(
+                StatusCode::CONFLICT,
+                "The release already has an identifier",
+            )
+            .into_response())
+        }
+        Err(e) => {
+            return match conflict_or_internal(e) {
+                StatusCode::CONFLICT => Ok(json_error(
+                    StatusCode::CONFLICT,
+                    "Another release has this identifier",
+                )
+                .into_response()),
+                status => Err(status),
+            }
+        }
+    }
+
+    let stored = releases
+        .get(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(release_model(&state, stored, true).await?).into_response())
+}
+
+// The whole release as one zip: the manifest, a SHA256SUMS listing and the files as released
+async fn download_release(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Response, StatusCode> {
+    let stored = ReleaseRepo::new(&state.db)
+        .get(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let release = release_model(&state, stored, true).await?;
+    let files = release.files.as_deref().unwrap_or_default();
+
+    let uploads = UploadRepo::new(&state.db);
+    let mut entries = Vec::with_capacity(files.len());
+    for file in files {
+        let Some(upload) = uploads
+            .get(&file.upload_id)
+            .await
+            .map_err(|e| internal_error(e.to_string()))?
+        else {
+            continue; // cannot happen while uploads are protected; verify reports it
+        };
+        let path = plain_upload_path(&state, &upload.filename, upload.compression.as_deref())
+            .await
+            .map_err(|e| internal_error(e.to_string()))?;
+        entries.push(ArchiveEntry {
+            name: file.name.clone(),
+            path,
+            created_at: upload.created_at,
+        });
+    }
+    let manifest =
+        serde_json::to_vec_pretty(&release).map_err(|e| internal_error(e.to_string()))?;
+    let generated = vec![
+        (MANIFEST_NAME.to_string(), manifest),
+        (CHECKSUMS_NAME.to_string(), sha256sums(files).into_bytes()),
+    ];
+
+    let filename = format!("{}.zip", release.name.replace(['"', '/', '\\'], "_"));
+    tracing::info!("📦 Streaming release {} as {}", release.name, filename);
+    let (tx, rx) = tokio::sync::mpsc::channel::<ArchiveChunk>(8);
+    tokio::task::spawn_blocking(move || {
+        if let Err(e) = write_archive_with(&generated, &entries, tx) {
+            tracing::error!("Failed to stream release: {}", e);
+        }
+    });
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/zip")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", filename),
+        )
+        .body(axum::body::Body::from_stream(ReceiverStream::new(rx)))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+// Hash every released file again and compare with the manifest
+async fn verify_release_files(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<ReleaseVerification>, StatusCode> {
+    let releases = ReleaseRepo::new(&state.db);
+    releases
+        .get(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let files = releases
+        .files(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?;
+    let (mismatched, missing) = verify_release(&state, &files)
+        .await
+        .map_err(internal_error)?;
+    Ok(Json(ReleaseVerification {
+        release_id: id,
+        intact: mismatched.is_empty() && missing.is_empty(),
+        checked: files.len(),
+        mismatched,
+        missing,
+    }))
+}
+
+// ============= REMOTES =============
+
+async fn list_remotes(State(state): State<Arc<AppState>>) -> Result<Json<Vec<Remote>>, StatusCode> {
+    let remotes = RemoteRepo::new(&state.db)
+        .list()
+        .await
+        .map_err(|e| internal_error(e.to_string()))?;
+    Ok(Json(remotes))
+}
+
+async fn create_remote(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<CreateRemote>,
+) -> Result<Response, StatusCode> {
+    let name = payload.name.trim();
+    if name.is_empty() {
+        return Ok(json_error(StatusCode::BAD_REQUEST, "Name must not be empty").into_response());
+    }
+    let base_url = match remote_base_url(&payload.base_url) {
+        Ok(base_url) => base_url,
+        Err(message) => return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response()),
+    };
+    let remote = Remote {
+        id: Uuid::new_v4().to_string(),
+        name: name.to_string(),
+        base_url,
+        created_at: timestamps::now(),
+    };
+    if let Err(e) = RemoteRepo::new(&state.db).insert(&remote).await {
+        return match conflict_or_internal(e) {
+            StatusCode::CONFLICT => Ok(json_error(
+                StatusCode::CONFLICT,
+                "A remote with this name exists",
+            )
+            .into_response()),
+            status => Err(status),
+        };
+    }
+    tracing::info!(
+        "🌐 Registered remote {} at {}",
+        remote.name,
+        remote.base_url
+    );
+    Ok((StatusCode::CREATED, Json(remote)).into_response())
+}
+
+async fn find_remote(state: &AppState, id: &str) -> Result<Remote, StatusCode> {
+    RemoteRepo::new(&state.db)
+        .get(id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn get_remote(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<Remote>, StatusCode> {
+    Ok(Json(find_remote(&state, &id).await?))
+}
+
+// Forget a remote; what was imported from it stays, with its origin
+async fn delete_remote(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    match RemoteRepo::new(&state.db).delete(&id).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(StatusCode::NOT_FOUND),
+        Err(e) => Err(internal_error(e.to_string())),
+    }
+}
+
+// The uploads of a remote, read-only: the query goes to its `GET /api/uploads` as it is
+async fn browse_remote_uploads(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    RawQuery(query): RawQuery,
+) -> Result<Response, StatusCode> {
+    let remote = find_remote(&state, &id).await?;
+    match browse_remote(&state, &remote, query.as_deref()).await {
+        Ok(page) => Ok(Json(page).into_response()),
+        Err(message) => Ok(json_error(StatusCode::BAD_GATEWAY, message).into_response()),
+    }
+}
+
+async fn get_remote_upload(
+    State(state): State<Arc<AppState>>,
+    Path((id, upload_id)): Path<(String, String)>,
+) -> Result<Response, StatusCode> {
+    let remote = find_remote(&state, &id).await?;
+    match remote_upload(&state, &remote, &upload_id).await {
+        Ok(Some(upload)) => Ok(Json(upload).into_response()),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(message) => Ok(json_error(StatusCode::BAD_GATEWAY, message).into_response()),
+    }
+}
+
+// Copy uploads of a remote in, all or none: each becomes a local upload like one fetched
+// from a URL, with the remote and its download URL as its origin
+async fn import_remote_uploads(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    Json(request): Json<RemoteImport>,
+) -> Result<Response, StatusCode> {
+    let remote = find_remote(&state, &id).await?;
+    if request.upload_ids.is_empty() {
+        return Ok(json_error(StatusCode::BAD_REQUEST, "Provide upload_ids").into_response());
+    }
+    let tags = TagRepo::new(&state.db);
+    for tag_id in &request.tags {
+        if tags
+            .get(tag_id)
+            .await
+            .map_err(|e| internal_error(e.to_string()))?
+            .is_none()
+        {
+            return Ok(
+                json_error(StatusCode::BAD_REQUEST, format!("Unknown tag: {}", tag_id))
+                    .into_response(),
+            );
+        }
+    }
+
+    let settings = state.settings();
+    let mut files = Vec::with_capacity(request.upload_ids.len());
+    let mut unknown = Vec::new();
+    for upload_id in &request.upload_ids {
+        match fetch_remote_upload(&state, &remote, upload_id, settings.url_max_bytes).await {
+            Ok(RemoteFetch::Fetched(file)) => files.push((upload_id, file)),
+            Ok(RemoteFetch::NotFound) => unknown.push(upload_id.as_str()),
+            Ok(RemoteFetch::TooLarge) => {
+                return Ok(json_error(
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    format!(
+                        "{} exceeds the download limit of {} MB",
+                        upload_id,
+                        settings.url_max_bytes / (1024 * 1024)
+                    ),
+                )
+                .into_response())
+            }
+            Err(message) => {
+                return Ok(json_error(StatusCode::BAD_GATEWAY, message).into_response());
+            }
+        }
+    }
+    if !unknown.is_empty() {
+        return Ok(json_error(
+            StatusCode::NOT_FOUND,
+            format!(
+                "Unknown upload IDs on {}: {}",
+                remote.name,
+                unknown.join(", ")
+            ),
+        )
+        .into_response());
+    }
+
+    let content: Vec<(&str, &[u8])> = files
+        .iter()
+        .map(|(_, file)| (file.upload.original_filename.as_str(), file.data.as_slice()))
+        .collect();
+    if let Some(message) = check_storage_quota(&state, &content, &request.tags).await? {
+        return Ok(json_error(StatusCode::INSUFFICIENT_STORAGE, message).into_response());
+    }
+    let received: Vec<(&str, Option<&str>, &[u8])> = files
+        .iter()
+        .map(|(_, file)| {
+            (
+                file.upload.original_filename.as_str(),
+                file.mime_type.as_deref(),
+                file.data.as_slice(),
+            )
+        })
+        .collect();
+    if let Some(refused) = screen_uploads(&state, &received, &request.tags).await? {
+        return Ok(refused);
+    }
+
+    let origin = request_origin(&headers, peer);
+    let service = TagService::new(&state);
+    let mut imported = Vec::with_capacity(files.len());
+    for (remote_upload_id, file) in files {
+        let mut tag_ids = request.tags.clone();
+        if request.copy_tags {
+            // System tags such as extension tags are set here as for any upload
+            for tag in &file.upload.tags {
+                let policy = &settings.tag_policy;
+                if policy.is_system_tag(&tag.name) || policy.validate_name(&tag.name).is_err() {
+                    continue;
+                }
+                let tag_id = service
+                    .ensure(&tag.name, &tag.color)
+                    .await
+                    .map_err(|e| internal_error(e.to_string()))?;
+                if !tag_ids.contains(&tag_id) {
+                    tag_ids.push(tag_id);
+                }
+            }
+        }
+        let upload = store_upload(
+            &state,
+            file.upload.original_filename,
+            file.data,
+            file.mime_type,
+            tag_ids,
+        )
+        .await
+        .map_err(internal_error)?;
+        record_upload_origin(
+            &state,
+            &upload.id,
+            "remote",
+            Some(&file.source_url),
+            &origin,
+            None,
+        )
+        .await;
+        record_custody_event(
+            &state,
+            &upload.id,
+            "imported",
+            Some(&format!("from {} ({})", remote.name, remote_upload_id)),
+            &origin,
+        )
+        .await;
+        imported.push(ImportedUpload {
+            remote_upload_id: remote_upload_id.clone(),
+            upload,
+        });
+    }
+    tracing::info!(
+        "🌐 Imported {} upload(s) from remote {}",
+        imported.len(),
+        remote.name
+    );
+    Ok((StatusCode::CREATED, Json(imported)).into_response())
+}
+
+// ============= IMPORT MANIFESTS =============
+
+#[derive(Debug, serde::Deserialize)]
+struct ImportRowQuery {
+    status: Option<String>, // pending, imported, failed or quarantined
+}
+
+// Every row is checked before any is imported, so a typo in row 900 does not leave half an
+// archive behind; what can only fail while importing (a missing file, a URL that does not
+// answer) is reported per row
+async fn import_manifest(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    body: Bytes,
+) -> Result<Response, StatusCode> {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok());
+    let manifest = match manifest::parse(&body, content_type) {
+        Ok(rows) => rows,
+        Err(message) => return Ok(json_error(StatusCode::BAD_REQUEST, message).into_response()),
+    };
+
+    let settings = state.settings();
+    let policy = &settings.tag_policy;
+    let mut rows = Vec::with_capacity(manifest.len());
+    for (row_number, row) in (1..).zip(manifest) {
+        let invalid = |message: String| {
+            Ok(json_error(
+                StatusCode::BAD_REQUEST,
+                format!("Row {}: {}", row_number, message),
+            )
+            .into_response())
+        };
+        if let Err(message) = Source::parse(&row.source, &state.import_dirs) {
+            return invalid(message);
+        }
+        if row
+            .filename
+            .as_deref()
+            .is_some_and(|name| !is_valid_filename(name))
+        {
+            return invalid("Invalid filename".to_string());
+        }
+        for name in &row.tags {
+            if let Err(message) = policy.validate_name(name) {
+                return invalid(message);
+            }
+            if policy.is_system_tag(name) {
+                return invalid(format!("{} is a system tag", name));
+            }
+        }
+        let assignee = match normalize_assignee(row.assignee) {
+            Ok(assignee) => assignee,
+            Err(message) => return invalid(message),
+        };
+        rows.push((
+            row.tags,
+            ImportRow {
+                row_number,
+                source: row.source.trim().to_string(),
+                filename: row.filename,
+                tags: Vec::new(),
+                assignee,
+                metadata: row.metadata,
+                status: "pending".to_string(),
+                upload_id: None,
+                message: None,
+            },
+        ));
+    }
+
+    let tags = TagRepo::new(&state.db);
+    let service = TagService::new(&state);
+    let mut used: Vec<String> = tags
+        .list()
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .into_iter()
+        .map(|tag| tag.color)
+        .collect();
+    let mut tag_ids: HashMap<String, String> = HashMap::new();
+    let mut resolved = Vec::with_capacity(rows.len());
+    for (names, mut row) in rows {
+        for name in names {
+            let id = match tag_ids.get(&name) {
+                Some(id) => id.clone(),
+                None => {
+                    let id = manifest_tag_id(&tags, &service, policy, &name, &mut used)
+                        .await
+                        .map_err(|e| internal_error(e.to_string()))?;
+                    tag_ids.insert(name, id.clone());
+                    id
+                }
+            };
+            if !row.tags.contains(&id) {
+                row.tags.push(id);
+            }
+        }
+        resolved.push(row);
+    }
+    let rows = resolved;
+
+    let id = Uuid::new_v4().to_string();
+    let origin = request_origin(&headers, peer);
+    let imports = ImportRepo::new(&state.db);
+    imports
+        .create(&id, &origin, &timestamps::now(), &rows)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?;
+    tracing::info!(
+        "📦 Importing a manifest of {} row(s) as batch {}",
+        rows.len(),
+        id
+    );
+    spawn_manifest_import(state.clone(), id.clone());
+
+    let batch = imports
+        .get(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    let location = [(
+        header::LOCATION,
+        format!("/api/uploads/import-manifest/{}", id),
+    )];
+    Ok((
+        StatusCode::ACCEPTED,
+        location,
+        Json(ImportBatchDetail { batch, rows }),
+    )
+        .into_response())
+}
+
+// The ID of a tag a manifest names; a missing tag is created with the next color of the
+// palette, as when created by hand
+async fn manifest_tag_id(
+    tags: &TagRepo<'_>,
+    service: &TagService<'_>,
+    policy: &TagPolicy,
+    name: &str,
+    used: &mut Vec<String>,
+) -> sqlx::Result<String> {
+    if let Some(id) = tags.id_by_name(name).await? {
+        return Ok(id);
+    }
+    let color = policy.next_color(used);
+    let id = service.ensure(name, &color).await?;
+    used.push(color);
+    Ok(id)
+}
+
+async fn list_import_batches(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<ImportBatch>>, StatusCode> {
+    let batches = ImportRepo::new(&state.db)
+        .list()
+        .await
+        .map_err(|e| internal_error(e.to_string()))?;
+    Ok(Json(batches))
+}
+
+async fn get_import_batch(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Query(query): Query<ImportRowQuery>,
+) -> Result<Response, StatusCode> {
+    if let Some(status) = &query.status {
+        if !matches!(
+            status.as_str(),
+            "pending" | "imported" | "failed" | "quarantined"
+        ) {
+            return Ok(json_error(
+                StatusCode::BAD_REQUEST,
+                format!("Unknown status: {}", status),
+            )
+            .into_response());
+        }
+    }
+    let imports = ImportRepo::new(&state.db);
+    let batch = imports
+        .get(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let rows = imports
+        .rows(&id, query.status.as_deref())
+        .await
+        .map_err(|e| internal_error(e.to_string()))?;
+    Ok(Json(ImportBatchDetail { batch, rows }).into_response())
+}
+
+/// Carry on with the batches a restart interrupted; rows are only marked once done, so the
+/// pending ones are what is left
+pub async fn resume_manifest_imports(state: Arc<AppState>) {
+    match ImportRepo::new(&state.db).running_ids().await {
+        Ok(ids) => {
+            for id in ids {
+                tracing::info!("📦 Resuming import batch {}", id);
+                spawn_manifest_import(state.clone(), id);
+            }
+        }
+        Err(e) => tracing::warn!("Cannot resume import batches: {}", e),
+    }
+}
+
+fn spawn_manifest_import(state: Arc<AppState>, batch_id: String) {
+    let task_state = state.clone();
+    state.tasks.spawn("manifest_import", async move {
+        if let Err(e) = run_manifest_import(&task_state, &batch_id).await {
+            tracing::error!("Import batch {} stopped: {}", batch_id, e);
+        }
+    });
+}
+
+// Import the pending rows one after the other, then mark the batch completed
+async fn run_manifest_import(state: &Arc<AppState>, batch_id: &str) -> Result<(), String> {
+    let imports = ImportRepo::new(&state.db);
+    let origin = imports.origin(batch_id).await.map_err(|e| e.to_string())?;
+    let rows = imports
+        .rows(batch_id, Some("pending"))
+        .await
+        .map_err(|e| e.to_string())?;
+    for row in rows {
+        let (status, upload_id, message) =
+            match import_manifest_row(state, batch_id, &row, &origin).await {
+                Ok(Some(upload_id)) => ("imported", Some(upload_id), None),
+                Ok(None) => (
+                    "quarantined",
+                    None,
+                    Some("Flagged by the malware scan".to_string()),
+                ),
+                Err(message) => {
+                    tracing::warn!(
+                        "Row {} of import batch {} failed: {}",
+                        row.row_number,
+                        batch_id,
+                        message
+                    );
+                    ("failed", None, Some(message))
+                }
+            };
+        imports
+            .set_row_status(
+                batch_id,
+                row.row_number,
+                status,
+                upload_id.as_deref(),
+                message.as_deref(),
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    imports
+        .finish(batch_id, &timestamps::now())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let Some(batch) = imports.get(batch_id).await.map_err(|e| e.to_string())? else {
+        return Ok(());
+    };
+    tracing::info!(
+        "📦 Import batch {} finished: {} of {} row(s) imported",
+        batch_id,
+        batch.imported,
+        batch.total
+    );
+    let title = format!(
+        "Imported {} of {} manifest row(s)",
+        batch.imported, batch.total
+    );
+    let message = (batch.failed + batch.quarantined > 0).then(|| {
+        format!(
+            "{} failed and {} quarantined; see /api/uploads/import-manifest/{}",
+            batch.failed, batch.quarantined, batch_id
+        )
+    });
+    add_notification(
+        state,
+        "import_finished",
+        &title,
+        message.as_deref(),
+        None,
+        None,
+    )
+    .await;
+    Ok(())
+}
+
+// Import one row as an upload, with the same checks as for client uploads; None if the
+// malware scan quarantined it
+async fn import_manifest_row(
+    state: &Arc<AppState>,
+    batch_id: &str,
+    row: &ImportRow,
+    origin: &RequestOrigin,
+) -> Result<Option<String>, String> {
+    let (data, mime_type, name, source_url) = match Source::parse(&row.source, &state.import_dirs)?
+    {
+        Source::Url(url) => {
+            let download = download_url(state, &url)
+                .await
+                .map_err(|(_, message)| message)?;
+            (
+                download.data,
+                download.mime_type,
+                download.filename,
+                url.to_string(),
+            )
+        }
+        Source::Path(path) => {
+            let data = read_import_file(state, &path).await?;
+            let name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .filter(|name| is_valid_filename(name))
+                .unwrap_or_else(|| "import".to_string());
+            let source_url = reqwest::Url::from_file_path(&path)
+                .map(String::from)
+                .unwrap_or_else(|_| row.source.clone());
+            (data, None, name, source_url)
+        }
+    };
+    let original_filename = row.filename.clone().unwrap_or(name);
+
+    let files = [(original_filename.as_str(), data.as_slice())];
+    if let Some(message) = check_storage_quota(state, &files, &row.tags)
+        .await
+        .map_err(|status| status.to_string())?
+    {
+        return Err(message);
+    }
+    let screened = [(
+        original_filename.as_str(),
+        mime_type.as_deref(),
+        data.as_slice(),
+    )];
+    if screen_uploads(state, &screened, &row.tags)
+        .await
+        .map_err(|status| status.to_string())?
+        .is_some()
+    {
+        return Ok(None);
+    }
+
+    let upload = store_upload(state, original_filename, data, mime_type, row.tags.clone()).await?;
+    record_upload_origin(
+        state,
+        &upload.id,
+        "manifest",
+        Some(&source_url),
+        origin,
+        None,
+    )
+    .await;
+    let mut detail = format!("row {} of import batch {}", row.row_number, batch_id);
+    if !row.metadata.is_empty() {
+        detail.push_str(&format!(
+            ", metadata {}",
+            serde_json::Value::Object(row.metadata.clone())
+        ));
+    }
+    record_custody_event(state, &upload.id, "imported", Some(&detail), origin).await;
+    if let Some(assignee) = &row.assignee {
+        UploadRepo::new(&state.db)
+            .set_assignee(&upload.id, Some(assignee))
+            .await
+            .map_err(|e| e.to_string())?;
+        record_custody_event(state, &upload.id, "assigned", Some(assignee), origin).await;
+    }
+    Ok(Some(upload.id))
+}
+
+// Read a file a manifest names, once it is clear that with symlinks resolved it is still
+// inside an import directory
+async fn read_import_file(state: &AppState, path: &std::path::Path) -> Result<Vec<u8>, String> {
+    let real = tokio::fs::canonicalize(path)
+        .await
+        .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    let mut inside = false;
+    for dir in &state.import_dirs {
+        if let Ok(dir) = tokio::fs::canonicalize(dir).await {
+            inside |= real.starts_with(dir);
+        }
+    }
+    if !inside {
+        return Err(format!(
+            "{} is not inside an import directory",
+            path.display()
+        ));
+    }
+    let metadata = tokio::fs::metadata(&real)
+        .await
+        .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    if !metadata.is_file() {
+        return Err(format!("{} is not a file", path.display()));
+    }
+    if metadata.len() > state.max_upload_bytes {
+        return Err(format!(
+            "File exceeds the upload limit of {} MB",
+            state.max_upload_bytes / (1024 * 1024)
+        ));
+    }
+    tokio::fs::read(&real)
+        .await
+        .map_err(|e| format!("Cannot read {}: {}", path.display(), e))
+}
+
+// ============= DATASETS =============
+
+async fn list_datasets(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<Dataset>>, StatusCode> {
+    let datasets = DatasetRepo::new(&state.db)
+        .list()
+        .await
+        .map_err(|e| internal_error(e.to_string()))?;
+    Ok(Json(datasets))
+}
+
+// A dataset with the members the requester may see; hidden ones are left out
+async fn dataset_detail(
+    state: &AppState,
+    requester: &Requester,
+    dataset: Dataset,
+) -> Result<DatasetDetail, StatusCode> {
+    let ids = DatasetRepo::new(&state.db)
+        .upload_ids(&dataset.id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?;
+    let hidden = hidden_tag_ids(state, requester).await?;
+    let repo = UploadRepo::new(&state.db);
+    let mut uploads = Vec::with_capacity(ids.len());
+    for id in ids {
+        if let Some(upload) = repo
+            .get(&id)
+            .await
+            .map_err(|e| internal_error(e.to_string()))?
+        {
+            let upload = with_tags_and_lineage(&state.db, upload).await;
+            if !upload.tags.iter().any(|tag| hidden.contains(&tag.id)) {
+                uploads.push(upload);
+            }
+        }
+    }
+    Ok(DatasetDetail { dataset, uploads })
+}
+
+// The uploads a selector picks, oldest first, or the response refusing it
+async fn filter_uploads(
+    state: &AppState,
+    requester: &Requester,
+    selector: &UploadSelector,
+) -> Result<Result<Vec<StoredUpload>, Response>, StatusCode> {
+    let (created_after, created_before) = match created_range(
+        selector.created_after.as_deref(),
+        selector.created_before.as_deref(),
+        selector.tz.as_deref(),
+    ) {
+        Ok(range) => range,
+        Err(message) => {
+            return Ok(Err(
+                json_error(StatusCode::BAD_REQUEST, message).into_response()
+            ))
+        }
+    };
+    let hidden = hidden_tag_ids(state, requester).await?;
+    let filter = UploadFilter {
+        derived: selector.derived,
+        artifact_type: Some("data"),
+        intermediate: Some(false),
+        created_after: created_after.as_deref(),
+        created_before: created_before.as_deref(),
+        tag_ids: &selector.tag_ids,
+        tag_names: &selector.tag_names,
+        filename_contains: selector.filename.as_deref().filter(|name| !name.is_empty()),
+        hidden_tag_ids: &hidden,
+        ..UploadFilter::default()
+    };
+    let uploads = UploadRepo::new(&state.db)
+        .page(
+            &filter,
+            Sort {
+                key: SortKey::CreatedAt,
+                order: Some(SortOrder::Asc),
+            },
+            None,
+            0,
+        )
+        .await
+        .map_err(|e| internal_error(e.to_string()))?;
+    Ok(Ok(uploads))
+}
+
+// Add the selected uploads to a dataset, recording it in their audit trails
+async fn add_dataset_members(
+    state: &AppState,
+    dataset: &Dataset,
+    members: &DatasetMembers,
+    requester: &Requester,
+    origin: &RequestOrigin,
+) -> Result<Option<Response>, StatusCode> {
+    let mut ids = Vec::new();
+    if !members.upload_ids.is_empty() || members.tag_expression.is_some() {
+        match select_uploads(
+            state,
+            requester,
+            &members.upload_ids,
+            members.tag_expression.as_deref(),
+        )
+        .await
+        {
+            Ok(uploads) => ids.extend(uploads.into_iter().map(|upload| upload.id)),
+            Err(response) => return Ok(Some(response)),
+        }
+    }
+    if let Some(selector) = &members.filter {
+        match filter_uploads(state, requester, selector).await? {
+            Ok(uploads) => ids.extend(uploads.into_iter().map(|upload| upload.id)),
+            Err(response) => return Ok(Some(response)),
+        }
+    }
+    let added = DatasetRepo::new(&state.db)
+        .add_uploads(&dataset.id, &ids, &timestamps::now())
+        .await
+        .map_err(|e| internal_error(e.to_string()))?;
+    for upload_id in &added {
+        record_custody_event(
+            state,
+            upload_id,
+            "added_to_dataset",
+            Some(&dataset.name),
+            origin,
+        )
+        .await;
+    }
+    Ok(None)
+}
+
+// Create a dataset, with the selected uploads if any were given
+async fn create_dataset(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    Json(payload): Json<CreateDataset>,
+) -> Result<Response, StatusCode> {
+    let name = payload.name.trim();
+    if name.is_empty() {
+        return Ok(json_error(StatusCode::BAD_REQUEST, "Name must not be empty").into_response());
+    }
+    let datasets = DatasetRepo::new(&state.db);
+    let id = Uuid::new_v4().to_string();
+    if let Err(e) = datasets
+        .insert(
+            &id,
+            name,
+            payload.description.as_deref(),
+            &timestamps::now(),
+        )
+        .await
+    {
+        return match conflict_or_internal(e) {
+            StatusCode::CONFLICT => Ok(json_error(
+                StatusCode::CONFLICT,
+                "A dataset with this name exists",
+            )
+            .into_response()),
+            status => Err(status),
+        };
+    }
+    let dataset = datasets
+        .get(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let members = &payload.members;
+    let requester = Requester::from_headers(&headers);
+    if !members.is_empty() {
+        let origin = request_origin(&headers, peer);
+        if let Some(refused) =
+            add_dataset_members(&state, &dataset, members, &requester, &origin).await?
+        {
+            // Nothing half-made is left behind
+            let _ = datasets.delete(&id).await;
+            return Ok(refused);
+        }
+    }
+
+    let dataset = datasets
+        .get(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    let detail = dataset_detail(&state, &requester, dataset).await?;
+    Ok((StatusCode::CREATED, Json(detail)).into_response())
+}
+
+async fn get_dataset(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<DatasetDetail>, StatusCode> {
+    let dataset = DatasetRepo::new(&state.db)
+        .get(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let requester = Requester::from_headers(&headers);
+    Ok(Json(dataset_detail(&state, &requester, dataset).await?))
+}
+
+async fn update_dataset(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(payload): Json<UpdateDataset>,
+) -> Result<Response, StatusCode> {
+    let name = payload.name.as_deref().map(str::trim);
+    if name == Some("") {
+        return Ok(json_error(StatusCode::BAD_REQUEST, "Name must not be empty").into_response());
+    }
+    let datasets = DatasetRepo::new(&state.db);
+    match datasets
+        .update(
+            &id,
+            name,
+            payload.description.as_deref(),
+            &timestamps::now(),
+        )
+        .await
+    {
+        Ok(true) => {}
+        Ok(false) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            return match conflict_or_internal(e) {
+                StatusCode::CONFLICT => Ok(json_error(
+                    StatusCode::CONFLICT,
+                    "A dataset with this name exists",
+                )
+                .into_response()),
+                status => Err(status),
+            }
+        }
+    }
+    let dataset = datasets
+        .get(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(dataset).into_response())
+}
+
+// Delete a dataset; its uploads stay
+async fn delete_dataset(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    match DatasetRepo::new(&state.db).delete(&id).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(StatusCode::NOT_FOUND),
+        Err(e) => Err(internal_error(e.to_string())),
+    }
+}
+
+async fn add_dataset_uploads(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    Json(members): Json<DatasetMembers>,
+) -> Result<Response, StatusCode> {
+    let datasets = DatasetRepo::new(&state.db);
+    let dataset = datasets
+        .get(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let origin = request_origin(&headers, peer);
+    let requester = Requester::from_headers(&headers);
+    if let Some(refused) =
+        add_dataset_members(&state, &dataset, &members, &requester, &origin).await?
+    {
+        return Ok(refused);
+    }
+    let dataset = datasets
+        .get(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(dataset).into_response())
+}
+
+async fn remove_dataset_upload(
+    State(state): State<Arc<AppState>>,
+    Path((id, upload_id)): Path<(String, String)>,
+    headers: HeaderMap,
+    peer: Option<ConnectInfo<SocketAddr>>,
+) -> Result<StatusCode, StatusCode> {
+    let datasets = DatasetRepo::new(&state.db);
+    let dataset = datasets
+        .get(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let removed = datasets
+        .remove_upload(&id, &upload_id, &timestamps::now())
+        .await
+        .map_err(|e| internal_error(e.to_string()))?;
+    if !removed {
+        return Err(StatusCode::NOT_FOUND);
     }
+    let origin = request_origin(&headers, peer);
+    record_custody_event(
+        &state,
+        &upload_id,
+        "removed_from_dataset",
+        Some(&dataset.name),
+        &origin,
+    )
+    .await;
+    Ok(StatusCode::NO_CONTENT)
+}
 
-    // Update name if provided
-    if let Some(name) = &payload.name {
-        sqlx::query!("UPDATE functions SET name = ? WHERE id = ?", name, id)
-            .execute(&state.db)
+// Tag every upload of a dataset, as if each was tagged on its own: hooks run and the
+// functions the tags match are triggered for every upload
+async fn tag_dataset(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    Json(tag_ids): Json<Vec<String>>,
+) -> Result<Response, StatusCode> {
+    let datasets = DatasetRepo::new(&state.db);
+    datasets
+        .get(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let tags = TagRepo::new(&state.db);
+    for tag_id in &tag_ids {
+        if tags
+            .get(tag_id)
             .await
-            .map_err(|e| {
-                if e.to_string().contains("UNIQUE constraint failed") {
-                    StatusCode::CONFLICT
-                } else {
-                    StatusCode::INTERNAL_SERVER_ERROR
-                }
-            })?;
+            .map_err(|e| internal_error(e.to_string()))?
+            .is_none()
+        {
+            return Ok(
+                json_error(StatusCode::BAD_REQUEST, format!("Unknown tag: {}", tag_id))
+                    .into_response(),
+            );
+        }
     }
 
-    // Update input tags if provided
-    if let Some(input_tag_ids) = &payload.input_tag_ids {
-        sqlx::query!("DELETE FROM function_input_tags WHERE function_id = ?", id)
-            .execute(&state.db)
+    let origin = request_origin(&headers, peer);
+    let uploads = UploadRepo::new(&state.db);
+    let mut tagged = 0;
+    for upload_id in datasets
+        .upload_ids(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+    {
+        if let Some(upload) = uploads
+            .get(&upload_id)
             .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-        for tag_id in input_tag_ids {
-            let _ = sqlx::query!(
-                "INSERT INTO function_input_tags (function_id, tag_id) VALUES (?, ?)",
-                id,
-                tag_id
-            )
-            .execute(&state.db)
-            .await;
+            .map_err(|e| internal_error(e.to_string()))?
+        {
+            tag_upload(&state, &upload, &tag_ids, &origin).await;
+            tagged += 1;
         }
     }
+    Ok(Json(serde_json::json!({ "tagged": tagged })).into_response())
+}
 
-    // Update output tags if provided
-    if let Some(output_tag_ids) = &payload.output_tag_ids {
-        sqlx::query!("DELETE FROM function_output_tags WHERE function_id = ?", id)
-            .execute(&state.db)
+async fn get_dataset_stats(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<DatasetStats>, StatusCode> {
+    let dataset = DatasetRepo::new(&state.db)
+        .get(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let stats = dataset_stats(&state, &dataset)
+        .await
+        .map_err(internal_error)?;
+    Ok(Json(stats))
+}
+
+// The uploads of a dataset the requester may see, as a zip named after it
+async fn download_dataset(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let dataset = DatasetRepo::new(&state.db)
+        .get(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let requester = Requester::from_headers(&headers);
+    let detail = dataset_detail(&state, &requester, dataset).await?;
+    let uploads = UploadRepo::new(&state.db);
+
+    let names = unique_entry_names(detail.uploads.iter().map(|u| u.original_filename.as_str()));
+    let mut entries = Vec::with_capacity(detail.uploads.len());
+    for (upload, name) in detail.uploads.iter().zip(names) {
+        let Some(upload) = uploads
+            .get(&upload.id)
             .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            .map_err(|e| internal_error(e.to_string()))?
+        else {
+            continue;
+        };
+        let path = plain_upload_path(&state, &upload.filename, upload.compression.as_deref())
+            .await
+            .map_err(|e| internal_error(e.to_string()))?;
+        entries.push(ArchiveEntry {
+            name,
+            path,
+            created_at: upload.created_at,
+        });
+    }
 
-        for tag_id in output_tag_ids {
-            let _ = sqlx::query!(
-                "INSERT INTO function_output_tags (function_id, tag_id) VALUES (?, ?)",
-                id,
-                tag_id
-            )
-            .execute(&state.db)
-            .await;
+    let filename = format!("{}.zip", detail.dataset.name.replace(['"', '/', '\\'], "_"));
+    tracing::info!(
+        "📦 Streaming dataset {} as {}",
+        detail.dataset.name,
+        filename
+    );
+    let (tx, rx) = tokio::sync::mpsc::channel::<ArchiveChunk>(8);
+    tokio::task::spawn_blocking(move || {
+        if let Err(e) = write_archive(&entries, tx) {
+            tracing::error!("Failed to stream dataset: {}", e);
         }
-    }
+    });
 
-    // Update function_type if provided
-    if let Some(function_type) = &payload.function_type {
-        sqlx::query!(
-            "UPDATE functions SET function_type = ? WHERE id = ?",
-            function_type,
-            id
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/zip")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", filename),
         )
-        .execute(&state.db)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    }
-
-    // Update enabled status if provided - check for cycles when enabling
-    if let Some(enabled) = payload.enabled {
-        if enabled {
-            // Check for cycles before enabling
-            let mut graph = DirectedGraph::new();
+        .body(axum::body::Body::from_stream(ReceiverStream::new(rx)))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
 
-            // Add all currently ENABLED functions to graph
-            let all_functions =
-                sqlx::query!(r#"SELECT id as "id!" FROM functions WHERE enabled = 1"#)
-                    .fetch_all(&state.db)
-                    .await
-                    .unwrap_or_default();
+// ============= FEEDS =============
 
-            for func in all_functions {
-                let input_tags: Vec<String> = sqlx::query!(
-                    r#"SELECT tag_id as "tag_id!" FROM function_input_tags WHERE function_id = ?"#,
-                    func.id
-                )
-                .fetch_all(&state.db)
-                .await
-                .unwrap_or_default()
-                .iter()
-                .map(|r| r.tag_id.clone())
-                .collect();
+#[derive(Debug, serde::Deserialize)]
+struct FeedQuery {
+    limit: Option<i64>,
+}
 
-                let output_tags: Vec<String> = sqlx::query!(
-                    r#"SELECT tag_id as "tag_id!" FROM function_output_tags WHERE function_id = ?"#,
-                    func.id
-                )
-                .fetch_all(&state.db)
-                .await
-                .unwrap_or_default()
-                .iter()
-                .map(|r| r.tag_id.clone())
-                .collect();
+impl FeedQuery {
+    // At most 200 entries; a negative limit is a mistake rather than "no limit"
+    fn limit(&self, default: i64) -> Result<i64, StatusCode> {
+        match self.limit {
+            Some(limit) if limit < 0 => Err(StatusCode::BAD_REQUEST),
+            limit => Ok(limit.unwrap_or(default).clamp(1, 200)),
+        }
+    }
+}
 
-                graph.add_edges(&input_tags, &output_tags);
-            }
+async fn fetch_feed_entries(
+    state: &AppState,
+    failures_only: bool,
+    limit: i64,
+) -> Result<Vec<FeedEntry>, StatusCode> {
+    let rows = sqlx::query!(
+        r#"SELECT
+            j.id as "id!",
+            j.status as "status!",
+            j.error_message,
+            j.created_at as "created_at!",
+            j.started_at,
+            j.completed_at,
+            COALESCE(u.original_filename, j.upload_id) as "upload_filename!: String",
+            COALESCE(f.name, j.function_id) as "function_name!: String"
+        FROM jobs j
+        LEFT JOIN uploads u ON j.upload_id = u.id
+        LEFT JOIN functions f ON j.function_id = f.id
+        WHERE (? = 0 OR j.status = 'FAILED')
+        ORDER BY j.created_at DESC
+        LIMIT ?"#,
+        failures_only,
+        limit
+    )
+    .fetch_all(&state.db)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to fetch feed entries: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
 
-            // Add THIS function (the one being enabled) with its current tags
-            let input_tags: Vec<String> = sqlx::query!(
-                r#"SELECT tag_id as "tag_id!" FROM function_input_tags WHERE function_id = ?"#,
-                id
-            )
-            .fetch_all(&state.db)
-            .await
-            .unwrap_or_default()
-            .iter()
-            .map(|r| r.tag_id.clone())
-            .collect();
+    Ok(rows
+        .into_iter()
+        .map(|row| FeedEntry {
+            id: row.id,
+            status: row.status,
+            function_name: row.function_name,
+            upload_filename: row.upload_filename,
+            error_message: row.error_message,
+            created_at: row.created_at,
+            started_at: row.started_at,
+            completed_at: row.completed_at,
+        })
+        .collect())
+}
 
-            let output_tags: Vec<String> = sqlx::query!(
-                r#"SELECT tag_id as "tag_id!" FROM function_output_tags WHERE function_id = ?"#,
-                id
-            )
-            .fetch_all(&state.db)
-            .await
-            .unwrap_or_default()
-            .iter()
-            .map(|r| r.tag_id.clone())
-            .collect();
+fn feed_base_url(headers: &HeaderMap) -> String {
+    let host = headers
+        .get(header::HOST)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("localhost:8080");
+    format!("http://{}", host)
+}
 
-            graph.add_edges(&input_tags, &output_tags);
+fn feed_response(content_type: &str, body: String) -> Result<Response, StatusCode> {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, content_type)
+        .body(axum::body::Body::from(body))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
 
-            // Reject if enabling would cause cycle
-            if graph.has_cycle() {
-                tracing::warn!("Cannot enable function {}: would create cycle", id);
-                return Err(StatusCode::CONFLICT); // 409 - Conflict due to cycle
-            }
-        }
+async fn jobs_rss_feed(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<FeedQuery>,
+) -> Result<Response, StatusCode> {
+    let entries = fetch_feed_entries(&state, false, query.limit(50)?).await?;
+    let rss = render_rss("DataLab jobs", &feed_base_url(&headers), &entries);
+    feed_response("application/rss+xml; charset=utf-8", rss)
+}
 
-        let enabled_int = if enabled { 1 } else { 0 };
-        sqlx::query!(
-            "UPDATE functions SET enabled = ? WHERE id = ?",
-            enabled_int,
-            id
-        )
-        .execute(&state.db)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    }
+async fn failures_rss_feed(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<FeedQuery>,
+) -> Result<Response, StatusCode> {
+    let entries = fetch_feed_entries(&state, true, query.limit(50)?).await?;
+    let rss = render_rss("DataLab failed jobs", &feed_base_url(&headers), &entries);
+    feed_response("application/rss+xml; charset=utf-8", rss)
+}
 
-    // Return updated function
-    get_function(State(state), Path(id)).await
+async fn jobs_ics_feed(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<FeedQuery>,
+) -> Result<Response, StatusCode> {
+    let entries = fetch_feed_entries(&state, false, query.limit(200)?).await?;
+    let ics = render_ics("DataLab jobs", &entries);
+    feed_response("text/calendar; charset=utf-8", ics)
 }
 
-async fn delete_function(
+async fn get_array_info(
     State(state): State<Arc<AppState>>,
     Path(id): Path<String>,
-) -> Result<StatusCode, StatusCode> {
-    // Get script filename before deleting
-    let _function = sqlx::query!(
-        r#"SELECT script_filename as "script_filename!" FROM functions WHERE id = ?"#,
+) -> Result<Json<ArrayInspection>, (StatusCode, Json<serde_json::Value>)> {
+    let upload = sqlx::query!(
+        r#"SELECT filename as "filename!", original_filename as "original_filename!", detected_mime_type, compression FROM uploads WHERE id = ?"#,
         id
     )
     .fetch_optional(&state.db)
     .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-    .ok_or(StatusCode::NOT_FOUND)?;
+    .map_err(|_| json_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to fetch upload"))?
+    .ok_or_else(|| json_error(StatusCode::NOT_FOUND, "Upload not found"))?;
 
-    // Delete from database
-    sqlx::query!("DELETE FROM functions WHERE id = ?", id)
-        .execute(&state.db)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let extension = effective_extension(
+        &upload.original_filename,
+        upload.detected_mime_type.as_deref(),
+    )
+    .unwrap_or_default();
 
-    // Delete script file (all versions)
-    if let Ok(mut entries) = tokio::fs::read_dir("scripts").await {
-        while let Ok(Some(entry)) = entries.next_entry().await {
-            if let Some(name) = entry.file_name().to_str() {
-                if name.ends_with(&format!("_{}.py", id)) {
-                    let _ = tokio::fs::remove_file(entry.path()).await;
-                }
-            }
-        }
+    if is_unsafe_serialization(&extension) {
+        return Err(json_error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!(
+                "Refusing to inspect .{} files: unpickling can execute arbitrary code. \
+                 Save arrays with numpy.save/savez or tables as parquet to get a preview.",
+                extension
+            ),
+        ));
     }
 
-    Ok(StatusCode::NO_CONTENT)
-}
-
-// ============= JOBS =============
-
-async fn list_jobs(State(state): State<Arc<AppState>>) -> Result<Json<Vec<Job>>, StatusCode> {
-    #[derive(sqlx::FromRow)]
-    struct JobRow {
-        id: String,
-        upload_id: String,
-        function_id: String,
-        status: String,
-        error_message: Option<String>,
-        output_upload_ids: Option<String>,
-        created_at: String,
-        started_at: Option<String>,
-        completed_at: Option<String>,
-    }
-
-    let jobs = sqlx::query_as!(
-        JobRow,
-        r#"SELECT 
-            id as "id!", 
-            upload_id as "upload_id!", 
-            function_id as "function_id!", 
-            status as "status!", 
-            error_message, 
-            output_upload_ids, 
-            created_at as "created_at!", 
-            started_at, 
-            completed_at 
-        FROM jobs 
-        ORDER BY created_at DESC"#
-    )
-    .fetch_all(&state.db)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let mut result = Vec::new();
-    for job_row in jobs {
-        // Get upload filename
-        let upload_filename = sqlx::query!(
-            r#"SELECT original_filename as "original_filename!" FROM uploads WHERE id = ?"#,
-            job_row.upload_id
-        )
-        .fetch_optional(&state.db)
-        .await
-        .ok()
-        .flatten()
-        .map(|r| r.original_filename);
+    if !matches!(extension.as_str(), "npy" | "npz") {
+        return Err(json_error(
+            StatusCode::BAD_REQUEST,
+            format!("Array inspection is not supported for .{} files", extension),
+        ));
+    }
 
-        // Get function name
-        let function_name = sqlx::query!(
-            r#"SELECT name as "name!" FROM functions WHERE id = ?"#,
-            job_row.function_id
-        )
-        .fetch_optional(&state.db)
+    let file_path = plain_upload_path(&state, &upload.filename, upload.compression.as_deref())
         .await
-        .ok()
-        .flatten()
-        .map(|r| r.name);
-
-        let output_upload_ids: Vec<String> = job_row
-            .output_upload_ids
-            .as_ref()
-            .and_then(|s| serde_json::from_str(s).ok())
-            .unwrap_or_default();
-
-        // Fetch output filenames
-        let mut output_filenames = Vec::new();
-        for output_id in &output_upload_ids {
-            if let Ok(Some(upload)) = sqlx::query!(
-                r#"SELECT original_filename as "original_filename!" FROM uploads WHERE id = ?"#,
-                output_id
-            )
-            .fetch_optional(&state.db)
-            .await
-            {
-                output_filenames.push(upload.original_filename);
-            }
+        .map_err(|_| json_error(StatusCode::NOT_FOUND, "Upload file not found"))?
+        .to_string_lossy()
+        .to_string();
+
+    // Reading the headers out of a zip is blocking file IO
+    let inspected = tokio::task::spawn_blocking({
+        let file_path = file_path.clone();
+        move || inspect_array_file(&file_path, &extension).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|_| json_error(StatusCode::INTERNAL_SERVER_ERROR, "Array inspection failed"))?;
+    match inspected {
+        Ok(inspection) => Ok(Json(inspection)),
+        Err(e) => {
+            tracing::error!("Failed to inspect array file {}: {}", file_path, e);
+            Err(json_error(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("Could not read array metadata: {}", e),
+            ))
         }
-
-        result.push(Job {
-            id: job_row.id,
-            upload_id: job_row.upload_id,
-            function_id: job_row.function_id,
-            status: job_row.status,
-            error_message: job_row.error_message,
-            output_upload_ids,
-            created_at: job_row.created_at,
-            started_at: job_row.started_at,
-            completed_at: job_row.completed_at,
-            upload_filename,
-            function_name,
-            output_filenames,
-        });
     }
-
-    Ok(Json(result))
 }
 
-async fn get_job(
+async fn get_media_info(
     State(state): State<Arc<AppState>>,
     Path(id): Path<String>,
-) -> Result<Json<Job>, StatusCode> {
-    #[derive(sqlx::FromRow)]
-    struct JobRow {
-        id: String,
-        upload_id: String,
-        function_id: String,
-        status: String,
-        error_message: Option<String>,
-        output_upload_ids: Option<String>,
-        created_at: String,
-        started_at: Option<String>,
-        completed_at: Option<String>,
-    }
-
-    let job_row = sqlx::query_as!(
-        JobRow,
-        r#"SELECT 
-            id as "id!", 
-            upload_id as "upload_id!", 
-            function_id as "function_id!", 
-            status as "status!", 
-            error_message, 
-            output_upload_ids, 
-            created_at as "created_at!", 
-            started_at, 
-            completed_at 
-        FROM jobs 
-        WHERE id = ?"#,
+) -> Result<Json<MediaInfo>, (StatusCode, Json<serde_json::Value>)> {
+    let upload = sqlx::query!(
+        r#"SELECT filename as "filename!", original_filename as "original_filename!", detected_mime_type, compression FROM uploads WHERE id = ?"#,
         id
     )
     .fetch_optional(&state.db)
     .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-    .ok_or(StatusCode::NOT_FOUND)?;
+    .map_err(|_| json_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to fetch upload"))?
+    .ok_or_else(|| json_error(StatusCode::NOT_FOUND, "Upload not found"))?;
 
-    let upload_filename = sqlx::query!(
-        r#"SELECT original_filename as "original_filename!" FROM uploads WHERE id = ?"#,
-        job_row.upload_id
+    let extension = effective_extension(
+        &upload.original_filename,
+        upload.detected_mime_type.as_deref(),
     )
-    .fetch_optional(&state.db)
-    .await
-    .ok()
-    .flatten()
-    .map(|r| r.original_filename);
+    .unwrap_or_default();
 
-    let function_name = sqlx::query!(
-        r#"SELECT name as "name!" FROM functions WHERE id = ?"#,
-        job_row.function_id
-    )
-    .fetch_optional(&state.db)
+    let file_path = plain_upload_path(&state, &upload.filename, upload.compression.as_deref())
+        .await
+        .map_err(|_| json_error(StatusCode::NOT_FOUND, "Upload file not found"))?
+        .to_string_lossy()
+        .to_string();
+
+    // Walking the IFD chain of a large stack is blocking IO, keep it off the async workers
+    let result = tokio::task::spawn_blocking({
+        let file_path = file_path.clone();
+        move || read_media_info(&file_path, &extension).map_err(|e| e.to_string())
+    })
     .await
-    .ok()
-    .flatten()
-    .map(|r| r.name);
+    .map_err(|_| json_error(StatusCode::INTERNAL_SERVER_ERROR, "Media info task failed"))?;
 
-    let output_upload_ids: Vec<String> = job_row
-        .output_upload_ids
-        .as_ref()
-        .and_then(|s| serde_json::from_str(s).ok())
-        .unwrap_or_default();
+    match result {
+        Ok(info) => Ok(Json(info)),
+        Err(e) => {
+            tracing::warn!("Failed to read media info for {}: {}", file_path, e);
+            Err(json_error(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("Could not read media metadata: {}", e),
+            ))
+        }
+    }
+}
 
-    // Fetch output filenames
-    let mut output_filenames = Vec::new();
-    for output_id in &output_upload_ids {
-        if let Ok(Some(upload)) = sqlx::query!(
-            r#"SELECT original_filename as "original_filename!" FROM uploads WHERE id = ?"#,
-            output_id
+// The start of a text-like upload, e.g. a log, a JSON config or a README, decoded as text
+async fn get_upload_content(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Query(query): Query<ContentQuery>,
+) -> Result<Response, StatusCode> {
+    let upload = UploadRepo::new(&state.db)
+        .get(&id)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let max_kb = query.max_kb.unwrap_or(DEFAULT_PREVIEW_KB);
+    if !(1..=MAX_PREVIEW_KB).contains(&max_kb) {
+        return Ok(json_error(
+            StatusCode::BAD_REQUEST,
+            format!("max_kb must be between 1 and {}", MAX_PREVIEW_KB),
         )
-        .fetch_optional(&state.db)
+        .into_response());
+    }
+
+    let path = plain_upload_path(&state, &upload.filename, upload.compression.as_deref())
         .await
-        {
-            output_filenames.push(upload.original_filename);
-        }
-    }
-
-    Ok(Json(Job {
-        id: job_row.id,
-        upload_id: job_row.upload_id,
-        function_id: job_row.function_id,
-        status: job_row.status,
-        error_message: job_row.error_message,
-        output_upload_ids,
-        created_at: job_row.created_at,
-        started_at: job_row.started_at,
-        completed_at: job_row.completed_at,
-        upload_filename,
-        function_name,
-        output_filenames,
-    }))
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+    let file = tokio::fs::File::open(&path)
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+    let total_bytes = file
+        .metadata()
+        .await
+        .map_err(|e| internal_error(e.to_string()))?
+        .len();
+    let mut head = Vec::with_capacity(max_kb * 1024);
+    file.take((max_kb * 1024) as u64)
+        .read_to_end(&mut head)
+        .await
+        .map_err(|e| internal_error(e.to_string()))?;
+    let truncated = (head.len() as u64) < total_bytes;
+
+    let Some((content, encoding)) = decode_text(&head, truncated) else {
+        return Ok(
+            json_error(StatusCode::BAD_REQUEST, "Upload is not a text file").into_response(),
+        );
+    };
+    let extension = effective_extension(
+        &upload.original_filename,
+        upload.detected_mime_type.as_deref(),
+    );
+    Ok(Json(TextContent {
+        content,
+        encoding,
+        syntax: syntax_hint(extension.as_deref(), upload.detected_mime_type.as_deref()),
+        bytes_read: head.len(),
+        total_bytes,
+        truncated,
+    })
+    .into_response())
 }
 
-async fn get_table_preview(
+async fn get_waveform(
     State(state): State<Arc<AppState>>,
     Path(id): Path<String>,
-    Query(query): Query<TableQuery>,
-) -> Result<Json<TablePreview>, StatusCode> {
-    // Get file info from database
+    Query(query): Query<WaveformQuery>,
+) -> Result<Json<WaveformSummary>, (StatusCode, Json<serde_json::Value>)> {
     let upload = sqlx::query!(
-        r#"SELECT filename as "filename!", original_filename as "original_filename!" FROM uploads WHERE id = ?"#,
+        r#"SELECT filename as "filename!", original_filename as "original_filename!", detected_mime_type, compression FROM uploads WHERE id = ?"#,
         id
     )
     .fetch_optional(&state.db)
     .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-    .ok_or(StatusCode::NOT_FOUND)?;
+    .map_err(|_| json_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to fetch upload"))?
+    .ok_or_else(|| json_error(StatusCode::NOT_FOUND, "Upload not found"))?;
 
-    // Get file extension
-    let extension = upload
-        .original_filename
-        .rsplit('.')
-        .next()
-        .unwrap_or("")
-        .to_lowercase();
+    let extension = effective_extension(
+        &upload.original_filename,
+        upload.detected_mime_type.as_deref(),
+    )
+    .unwrap_or_default();
 
-    // Check if file type is supported for table preview
-    if !matches!(extension.as_str(), "csv" | "parquet") {
-        return Err(StatusCode::BAD_REQUEST);
+    if !is_audio_extension(&extension) {
+        return Err(json_error(
+            StatusCode::BAD_REQUEST,
+            format!("Waveform preview is not supported for .{} files", extension),
+        ));
     }
 
-    // Build file path
-    let file_path = format!("uploads/{}", upload.filename);
+    let file_path = plain_upload_path(&state, &upload.filename, upload.compression.as_deref())
+        .await
+        .map_err(|_| json_error(StatusCode::NOT_FOUND, "Upload file not found"))?
+        .to_string_lossy()
+        .to_string();
+    let points = query.points.unwrap_or(DEFAULT_POINTS);
+
+    // Decoding long recordings is CPU-bound, keep it off the async workers
+    let result = tokio::task::spawn_blocking({
+        let file_path = file_path.clone();
+        move || summarize_audio(&file_path, &extension, points).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|_| json_error(StatusCode::INTERNAL_SERVER_ERROR, "Waveform task failed"))?;
 
-    // Parse table data
-    match parse_table_preview(&file_path, &extension, &query) {
-        Ok(preview) => Ok(Json(preview)),
+    match result {
+        Ok(summary) => Ok(Json(summary)),
         Err(e) => {
-            tracing::error!("Failed to parse table file {}: {}", file_path, e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+            tracing::warn!("Failed to decode audio file {}: {}", file_path, e);
+            Err(json_error(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("Could not decode audio: {}", e),
+            ))
         }
     }
 }
+
+async fn get_thumbnail(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Query(query): Query<ThumbnailQuery>,
+) -> Result<Response, (StatusCode, Json<serde_json::Value>)> {
+    let upload = UploadRepo::new(&state.db)
+        .get(&id)
+        .await
+        .map_err(|_| json_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to fetch upload"))?
+        .ok_or_else(|| json_error(StatusCode::NOT_FOUND, "Upload not found"))?;
+
+    let extension = effective_extension(
+        &upload.original_filename,
+        upload.detected_mime_type.as_deref(),
+    )
+    .unwrap_or_default();
+
+    if !is_image_extension(&extension) {
+        return Err(json_error(
+            StatusCode::BAD_REQUEST,
+            format!("Thumbnails are not supported for .{} files", extension),
+        ));
+    }
+
+    let size = query.size.unwrap_or(DEFAULT_THUMBNAIL_SIZE);
+    if !THUMBNAIL_SIZES.contains(&size) {
+        return Err(json_error(
+            StatusCode::BAD_REQUEST,
+            format!("size must be one of {:?}", THUMBNAIL_SIZES),
+        ));
+    }
+
+    let path = match cached_thumbnail(&state, &id, &upload.filename, size).await {
+        Ok(path) => path,
+        Err(e) => {
+            tracing::warn!("Failed to render thumbnail of {}: {}", upload.filename, e);
+            return Err(json_error(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("Could not decode image: {}", e),
+            ));
+        }
+    };
+    let png = tokio::fs::read(&path).await.map_err(|_| {
+        json_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to read thumbnail",
+        )
+    })?;
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "image/png")
+        .header(header::CONTENT_LENGTH, png.len())
+        .header(header::CACHE_CONTROL, "private, max-age=86400")
+        .body(axum::body::Body::from(png))
+        .map_err(|_| {
+            json_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to build response",
+            )
+        })
+}
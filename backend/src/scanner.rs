@@ -0,0 +1,214 @@
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+
+/// Longest a single scan may take before the file counts as not scanned
+const SCAN_TIMEOUT: Duration = Duration::from_secs(300);
+
+// clamd accepts INSTREAM chunks up to its StreamMaxLength, in pieces of any size
+const CLAMD_CHUNK_BYTES: usize = 64 * 1024;
+
+/// What a scanner made of a file
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScanVerdict {
+    Clean,
+    Infected(String), // what was found, e.g. `Eicar-Test-Signature`
+    Failed(String),   // the scanner could not give an answer
+}
+
+/// A malware scanner uploads are passed through before they are stored
+#[derive(Debug, Clone)]
+pub enum Scanner {
+    /// A program that reads the file on stdin and exits 0 when clean and 1 when infected,
+    /// like `clamscan --no-summary -`; what it prints is kept as the finding
+    Command(Vec<String>),
+    /// A clamd daemon listening on a Unix socket
+    Clamd(PathBuf),
+}
+
+impl Scanner {
+    /// The scanner configured by --scan-command or --clamd-socket, if any
+    pub fn from_config(command: Option<&str>, clamd_socket: Option<PathBuf>) -> Option<Self> {
+        let command: Vec<String> = command
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(str::to_string)
+            .collect();
+        match (command.is_empty(), clamd_socket) {
+            (false, _) => Some(Scanner::Command(command)),
+            (true, Some(socket)) => Some(Scanner::Clamd(socket)),
+            (true, None) => None,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Scanner::Command(command) => command.join(" "),
+            Scanner::Clamd(socket) => format!("clamd at {}", socket.display()),
+        }
+    }
+
+    pub async fn scan(&self, data: &[u8]) -> ScanVerdict {
+        self.scan_reader(std::io::Cursor::new(data.to_vec())).await
+    }
+
+    /// Scan a file on disk without reading it into memory
+    pub async fn scan_file(&self, path: &Path) -> ScanVerdict {
+        match tokio::fs::File::open(path).await {
+            Ok(file) => self.scan_reader(file).await,
+            Err(e) => ScanVerdict::Failed(format!("Could not open {}: {}", path.display(), e)),
+        }
+    }
+
+    async fn scan_reader(&self, content: impl AsyncRead + Unpin + Send + 'static) -> ScanVerdict {
+        let result = match self {
+            Scanner::Command(command) => {
+                tokio::time::timeout(SCAN_TIMEOUT, scan_with_command(command, content)).await
+            }
+            Scanner::Clamd(socket) => {
+                tokio::time::timeout(SCAN_TIMEOUT, scan_with_clamd(socket, content)).await
+            }
+        };
+        result.unwrap_or_else(|_| ScanVerdict::Failed("Scan timed out".to_string()))
+    }
+}
+
+async fn scan_with_command(
+    command: &[String],
+    mut content: impl AsyncRead + Unpin + Send + 'static,
+) -> ScanVerdict {
+    let mut child = match tokio::process::Command::new(&command[0])
+        .args(&command[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => return ScanVerdict::Failed(format!("Could not run {}: {}", command[0], e)),
+    };
+
+    // Write and read at the same time, or a scanner answering early could block on its output
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let writer = tokio::spawn(async move {
+        let _ = tokio::io::copy(&mut content, &mut stdin).await;
+    });
+    let output = match child.wait_with_output().await {
+        Ok(output) => output,
+        Err(e) => return ScanVerdict::Failed(e.to_string()),
+    };
+    let _ = writer.await;
+
+    let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    match output.status.code() {
+        Some(0) => ScanVerdict::Clean,
+        Some(1) if stdout.is_empty() => ScanVerdict::Infected(format!("flagged by {}", command[0])),
+        Some(1) => ScanVerdict::Infected(stdout),
+        _ => {
+            let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            ScanVerdict::Failed(format!(
+                "{} exited with {}: {}",
+                command[0],
+                output.status,
+                if stderr.is_empty() { stdout } else { stderr }
+            ))
+        }
+    }
+}
+
+async fn scan_with_clamd(socket: &Path, mut content: impl AsyncRead + Unpin) -> ScanVerdict {
+    let exchange = async {
+        let mut stream = tokio::net::UnixStream::connect(socket).await?;
+        stream.write_all(b"zINSTREAM\0").await?;
+        let mut chunk = vec![0u8; CLAMD_CHUNK_BYTES];
+        loop {
+            let len = content.read(&mut chunk).await?;
+            if len == 0 {
+                break;
+            }
+            stream.write_all(&(len as u32).to_be_bytes()).await?;
+            stream.write_all(&chunk[..len]).await?;
+        }
+        stream.write_all(&0u32.to_be_bytes()).await?;
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply).await?;
+        Ok::<_, std::io::Error>(reply)
+    };
+    match exchange.await {
+        Ok(reply) => parse_clamd_reply(&String::from_utf8_lossy(&reply)),
+        Err(e) => ScanVerdict::Failed(format!("clamd at {}: {}", socket.display(), e)),
+    }
+}
+
+// `stream: OK`, `stream: Eicar-Test-Signature FOUND` or `... ERROR`, NUL-terminated
+fn parse_clamd_reply(reply: &str) -> ScanVerdict {
+    let reply = reply.trim_end_matches('\0').trim();
+    let result = reply.strip_prefix("stream:").unwrap_or(reply).trim();
+    if result == "OK" {
+        ScanVerdict::Clean
+    } else if let Some(signature) = result.strip_suffix("FOUND") {
+        ScanVerdict::Infected(signature.trim().to_string())
+    } else {
+        ScanVerdict::Failed(format!("clamd replied: {}", reply))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_clamd_reply() {
+        assert_eq!(parse_clamd_reply("stream: OK\0"), ScanVerdict::Clean);
+        assert_eq!(
+            parse_clamd_reply("stream: Eicar-Test-Signature FOUND\0"),
+            ScanVerdict::Infected("Eicar-Test-Signature".to_string())
+        );
+        assert!(matches!(
+            parse_clamd_reply("INSTREAM size limit exceeded. ERROR\0"),
+            ScanVerdict::Failed(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_scan_with_command() {
+        let scanner = |command: &str| Scanner::from_config(Some(command), None).unwrap();
+        assert_eq!(scanner("cat").scan(b"data").await, ScanVerdict::Clean);
+        // `grep -c` prints the match count and exits 1 when there is no match
+        assert_eq!(
+            scanner("grep -c EICAR").scan(b"clean").await,
+            ScanVerdict::Infected("0".to_string())
+        );
+        assert!(matches!(
+            scanner("grep -c [").scan(b"x").await,
+            ScanVerdict::Failed(_)
+        ));
+        assert!(matches!(
+            scanner("/nonexistent/scanner").scan(b"x").await,
+            ScanVerdict::Failed(_)
+        ));
+        assert!(Scanner::from_config(Some("  "), None).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_scan_file() {
+        let dir = std::env::temp_dir().join(format!("datalab-scan-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("upload.bin");
+        std::fs::write(&path, "EICAR\n".repeat(100_000)).unwrap();
+
+        let scanner = |command: &str| Scanner::from_config(Some(command), None).unwrap();
+        assert_eq!(scanner("cat").scan_file(&path).await, ScanVerdict::Clean);
+        assert_eq!(
+            scanner("grep -c clean").scan_file(&path).await,
+            ScanVerdict::Infected("0".to_string())
+        );
+        assert!(matches!(
+            scanner("cat").scan_file(&dir.join("missing.bin")).await,
+            ScanVerdict::Failed(_)
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,241 @@
+//! Fair scheduling of local job executions: the --max-concurrent-jobs slots are handed out
+//! round-robin across the sources of the waiting jobs, so a bulk backfill from one source
+//! queues behind itself instead of starving everyone else. Jobs of one source still run in
+//! the order they were queued.
+
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+
+/// Where the job came from, for fairness: the tags of its input upload, without extension
+/// tags, as those say little about who or what sent the file
+pub fn job_source<'a>(tag_names: impl IntoIterator<Item = &'a String>) -> String {
+    let mut tags: Vec<&str> = tag_names
+        .into_iter()
+        .map(String::as_str)
+        .filter(|name| !name.starts_with('.'))
+        .collect();
+    if tags.is_empty() {
+        return "(untagged)".to_string();
+    }
+    tags.sort_unstable();
+    tags.join(", ")
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueueSummary {
+    pub slots: usize,
+    pub available: usize,
+    pub waiting: BTreeMap<String, usize>, // by source
+}
+
+#[derive(Default)]
+struct Queues {
+    slots: usize,
+    available: usize,
+    surplus: usize, // slots in use beyond `slots` after it was lowered, retired when released
+    waiting: BTreeMap<String, VecDeque<oneshot::Sender<()>>>,
+    turns: VecDeque<String>, // sources with waiting jobs, next to be served first
+}
+
+pub struct FairScheduler {
+    queues: Mutex<Queues>,
+}
+
+/// A held execution slot; dropping it passes the slot on
+pub struct Slot {
+    scheduler: Arc<FairScheduler>,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.scheduler.release();
+    }
+}
+
+impl FairScheduler {
+    pub fn new(slots: usize) -> Arc<Self> {
+        Arc::new(Self {
+            queues: Mutex::new(Queues {
+                slots,
+                available: slots,
+                ..Default::default()
+            }),
+        })
+    }
+
+    /// Wait for a slot for a job from `source`
+    pub async fn acquire(self: &Arc<Self>, source: &str) -> Slot {
+        let receiver = {
+            let mut queues = self.queues.lock().unwrap();
+            if queues.available > 0 && queues.turns.is_empty() {
+                queues.available -= 1;
+                return Slot {
+                    scheduler: self.clone(),
+                };
+            }
+            let (sender, receiver) = oneshot::channel();
+            let waiting = queues.waiting.entry(source.to_string()).or_default();
+            waiting.push_back(sender);
+            if waiting.len() == 1 {
+                queues.turns.push_back(source.to_string());
+            }
+            receiver
+        };
+        // The sender is only dropped after a slot was handed over
+        let _ = receiver.await;
+        Slot {
+            scheduler: self.clone(),
+        }
+    }
+
+    /// Change the number of slots. Extra slots go to waiting jobs at once; when lowered, running
+    /// jobs keep their slots and the surplus is retired as they finish.
+    pub fn set_slots(&self, slots: usize) {
+        let mut queues = self.queues.lock().unwrap();
+        if slots >= queues.slots {
+            let mut added = slots - queues.slots;
+            let retired = added.min(queues.surplus);
+            queues.surplus -= retired;
+            added -= retired;
+            queues.slots = slots;
+            for _ in 0..added {
+                Self::hand_over(&mut queues);
+            }
+        } else {
+            let removed = queues.slots - slots;
+            let idle = removed.min(queues.available);
+            queues.available -= idle;
+            queues.surplus += removed - idle;
+            queues.slots = slots;
+        }
+    }
+
+    fn release(&self) {
+        let mut queues = self.queues.lock().unwrap();
+        if queues.surplus > 0 {
+            queues.surplus -= 1;
+            return;
+        }
+        Self::hand_over(&mut queues);
+    }
+
+    // Hand a free slot to the next waiting job, taking the sources in turns; jobs that stopped
+    // waiting (their task was dropped) are skipped
+    fn hand_over(queues: &mut Queues) {
+        while let Some(source) = queues.turns.pop_front() {
+            let waiting = queues
+                .waiting
+                .get_mut(&source)
+                .expect("sources in turn wait");
+            let sender = waiting.pop_front().expect("sources in turn wait");
+            if waiting.is_empty() {
+                queues.waiting.remove(&source);
+            } else {
+                queues.turns.push_back(source);
+            }
+            if sender.send(()).is_ok() {
+                return;
+            }
+        }
+        queues.available += 1;
+    }
+
+    pub fn summary(&self) -> QueueSummary {
+        let queues = self.queues.lock().unwrap();
+        QueueSummary {
+            slots: queues.slots,
+            available: queues.available,
+            waiting: queues
+                .waiting
+                .iter()
+                .map(|(source, waiting)| (source.clone(), waiting.len()))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_job_source() {
+        let tags = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        assert_eq!(job_source(&tags(&["raw", ".csv", "lab-a"])), "lab-a, raw");
+        assert_eq!(job_source(&tags(&[".csv"])), "(untagged)");
+    }
+
+    #[tokio::test]
+    async fn test_slots_alternate_between_sources() {
+        let scheduler = FairScheduler::new(1);
+        let slot = scheduler.acquire("backfill").await;
+
+        // Five backfill jobs queue up before one interactive job
+        let (order_tx, mut order_rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut tasks = Vec::new();
+        for (i, source) in ["backfill"; 5].into_iter().chain(["lab-a"]).enumerate() {
+            let scheduler = scheduler.clone();
+            let order_tx = order_tx.clone();
+            tasks.push(tokio::spawn(async move {
+                let _slot = scheduler.acquire(source).await;
+                order_tx.send(format!("{}{}", source, i)).unwrap();
+            }));
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert_eq!(scheduler.summary().waiting["backfill"], 5);
+
+        drop(slot);
+        for task in tasks {
+            task.await.unwrap();
+        }
+        let mut order = Vec::new();
+        while let Ok(name) = order_rx.try_recv() {
+            order.push(name);
+        }
+        assert_eq!(
+            order,
+            [
+                "backfill0",
+                "lab-a5",
+                "backfill1",
+                "backfill2",
+                "backfill3",
+                "backfill4"
+            ]
+        );
+        assert_eq!(scheduler.summary().available, 1);
+    }
+
+    #[tokio::test]
+    async fn test_slots_can_be_changed_while_in_use() {
+        let scheduler = FairScheduler::new(2);
+        let first = scheduler.acquire("a").await;
+        let second = scheduler.acquire("a").await;
+
+        // Lowered below what runs: nobody loses a slot, and none is handed on when one ends
+        scheduler.set_slots(1);
+        let waiting = {
+            let scheduler = scheduler.clone();
+            tokio::spawn(async move {
+                let _slot = scheduler.acquire("b").await;
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        drop(first);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!waiting.is_finished());
+
+        // Raised again, the waiting job gets one at once
+        scheduler.set_slots(2);
+        waiting.await.unwrap();
+        drop(second);
+        let summary = scheduler.summary();
+        assert_eq!((summary.slots, summary.available), (2, 2));
+
+        scheduler.set_slots(1);
+        assert_eq!(scheduler.summary().available, 1);
+    }
+}
@@ -0,0 +1,467 @@
+//! What a function script looks like it does, read from its source before it ever runs: the
+//! file types and paths it writes and whether it reaches for the network. The script is
+//! tokenized, not executed or imported, so this is advisory: a path built at runtime or a
+//! module whose name is computed goes unseen.
+//!
+//! A small tokenizer rather than a full Python parser (e.g. rustpython-parser): it only needs
+//! names, strings and brackets, and it keeps working on scripts that do not parse, such as
+//! ones written for a newer Python than a parser knows.
+
+use serde::{Deserialize, Serialize};
+
+/// Top-level modules that talk to other machines
+const NETWORK_MODULES: &[&str] = &[
+    "aiohttp",
+    "boto3",
+    "botocore",
+    "ftplib",
+    "http",
+    "httpx",
+    "paramiko",
+    "requests",
+    "smtplib",
+    "socket",
+    "urllib",
+    "urllib3",
+    "websocket",
+    "websockets",
+];
+
+/// Writer methods and the extension they write when no literal path says otherwise
+const WRITERS: &[(&str, &str)] = &[
+    ("to_csv", ".csv"),
+    ("to_excel", ".xlsx"),
+    ("to_feather", ".feather"),
+    ("to_hdf", ".h5"),
+    ("to_json", ".json"),
+    ("to_parquet", ".parquet"),
+    ("to_pickle", ".pkl"),
+    ("write_csv", ".csv"),
+    ("write_ipc", ".arrow"),
+    ("write_json", ".json"),
+    ("write_ndjson", ".ndjson"),
+    ("write_parquet", ".parquet"),
+    ("savefig", ".png"),
+    ("save", ".npy"),
+    ("savez", ".npz"),
+    ("savetxt", ".txt"),
+];
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScriptAnalysis {
+    pub written_extensions: Vec<String>, // e.g. ".csv", sorted
+    pub written_paths: Vec<String>,      // literal paths written to, as in the script
+    pub network_modules: Vec<String>,    // imported modules that use the network
+    pub badges: Vec<String>,             // short labels for the UI, e.g. "writes .csv"
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Name(String),
+    Str(String),
+    FStr(String), // an f-string, with its `{...}` fields as written
+    Op(char),
+    Newline,
+}
+
+/// Look through a Python script for the files it writes and the network modules it imports
+pub fn analyze(script: &str) -> ScriptAnalysis {
+    let tokens = tokenize(script);
+    let mut analysis = ScriptAnalysis::default();
+
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Name(name) if name == "import" || name == "from" => {
+                // Also after `:` and `;`, as in `if fast: import socket`
+                let at_statement_start = i == 0
+                    || matches!(
+                        tokens[i - 1],
+                        Token::Newline | Token::Op(':') | Token::Op(';')
+                    );
+                if at_statement_start {
+                    analysis
+                        .network_modules
+                        .extend(imported_modules(&tokens[i + 1..], name == "from"));
+                }
+            }
+            Token::Name(name) if tokens.get(i + 1) == Some(&Token::Op('(')) => {
+                let args = call_arguments(&tokens[i + 2..]);
+                let is_method = i > 0 && tokens[i - 1] == Token::Op('.');
+                if matches!(name.as_str(), "__import__" | "import_module") {
+                    if let Some(module) = argument(&args, 0, "name").and_then(literal) {
+                        analysis
+                            .network_modules
+                            .push(top_level(&module).to_string());
+                    }
+                } else if name == "open" && !is_method {
+                    let mode = argument(&args, 1, "mode").and_then(literal);
+                    if mode.is_some_and(|mode| is_write_mode(&mode)) {
+                        if let Some(file) = argument(&args, 0, "file") {
+                            analysis.written(file, None);
+                        }
+                    }
+                } else if name == "with_suffix" && is_method {
+                    if let Some(suffix) = args.first().and_then(|arg| literal(arg)) {
+                        analysis.written_extensions.push(suffix.to_lowercase());
+                    }
+                } else if let Some((_, extension)) = WRITERS
+                    .iter()
+                    .find(|(writer, _)| is_method && writer == name)
+                {
+                    match args.first() {
+                        Some(path) => analysis.written(path, Some(extension)),
+                        None => analysis.written_extensions.push(extension.to_string()),
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    analysis
+        .network_modules
+        .retain(|module| NETWORK_MODULES.contains(&module.as_str()));
+    for path in &analysis.written_paths {
+        if let Some(extension) = extension_of(path) {
+            analysis.written_extensions.push(extension);
+        }
+    }
+    for list in [
+        &mut analysis.written_extensions,
+        &mut analysis.written_paths,
+        &mut analysis.network_modules,
+    ] {
+        list.sort();
+        list.dedup();
+    }
+
+    analysis.badges = analysis
+        .written_extensions
+        .iter()
+        .map(|extension| format!("writes {}", extension))
+        .collect();
+    if !analysis.network_modules.is_empty() {
+        analysis.badges.push("network".to_string());
+    }
+    analysis
+}
+
+impl ScriptAnalysis {
+    /// Note the path argument of a write: a literal path, or the extension of an f-string whose
+    /// name is filled in at runtime, or else `fallback`
+    fn written(&mut self, arg: &[Token], fallback: Option<&str>) {
+        let value = match arg {
+            [Token::Name(_), Token::Op('='), value @ ..] => value,
+            _ => arg,
+        };
+        match value {
+            [Token::Str(path)] => self.written_paths.push(path.clone()),
+            [Token::FStr(template)] if extension_of(template).is_some() => {
+                self.written_extensions.extend(extension_of(template))
+            }
+            _ => self.written_extensions.extend(fallback.map(str::to_string)),
+        }
+    }
+}
+
+/// The top-level modules named by an `import a.b, c as d` or `from a.b import x` statement
+fn imported_modules(tokens: &[Token], from: bool) -> Vec<String> {
+    let mut modules = Vec::new();
+    let mut expect_module = true;
+    for token in tokens {
+        match token {
+            Token::Newline | Token::Op(';') => break,
+            Token::Name(name) if from && name == "import" => break,
+            Token::Name(name) if name == "as" => expect_module = false,
+            Token::Name(name) if expect_module => {
+                modules.push(name.clone());
+                expect_module = false;
+            }
+            Token::Op(',') => expect_module = true,
+            _ => {}
+        }
+    }
+    modules
+}
+
+/// `urllib` for `urllib.request`
+fn top_level(module: &str) -> &str {
+    module.split('.').next().unwrap_or(module)
+}
+
+/// The arguments of a call, given the tokens after its opening parenthesis
+fn call_arguments(tokens: &[Token]) -> Vec<&[Token]> {
+    let mut args = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Op('(' | '[' | '{') => depth += 1,
+            Token::Op(')' | ']' | '}') if depth == 0 => {
+                if i > start {
+                    args.push(&tokens[start..i]);
+                }
+                break;
+            }
+            Token::Op(')' | ']' | '}') => depth -= 1,
+            Token::Op(',') if depth == 0 => {
+                args.push(&tokens[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    args
+}
+
+/// The argument at `position`, or the one passed as `keyword=...`, without the keyword
+fn argument<'a>(args: &[&'a [Token]], position: usize, keyword: &str) -> Option<&'a [Token]> {
+    let by_keyword = args.iter().find_map(|arg| match arg {
+        [Token::Name(name), Token::Op('='), value @ ..] if name == keyword => Some(value),
+        _ => None,
+    });
+    let positional = args
+        .iter()
+        .take_while(|arg| !matches!(arg, [Token::Name(_), Token::Op('='), ..]))
+        .nth(position)
+        .copied();
+    by_keyword.or(positional)
+}
+
+/// The string an argument consists of
+fn literal(arg: &[Token]) -> Option<String> {
+    match arg {
+        [Token::Str(value)] => Some(value.clone()),
+        _ => None,
+    }
+}
+
+fn is_write_mode(mode: &str) -> bool {
+    mode.len() <= 3
+        && mode.chars().all(|c| "rwaxbt+".contains(c))
+        && mode.contains(['w', 'a', 'x', '+'])
+}
+
+fn extension_of(path: &str) -> Option<String> {
+    let filename = path.rsplit(['/', '\\']).next()?;
+    let (stem, extension) = filename.rsplit_once('.')?;
+    let plain = !stem.is_empty()
+        && !extension.is_empty()
+        && extension.len() <= 10
+        && extension.chars().all(|c| c.is_ascii_alphanumeric());
+    plain.then(|| format!(".{}", extension.to_lowercase()))
+}
+
+/// Names, string literals (with their prefix and quotes taken off, f-strings kept apart) and
+/// punctuation, one `Newline` per logical line. Comments, numbers and indentation are dropped.
+fn tokenize(source: &str) -> Vec<Token> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut depth = 0usize; // inside brackets, newlines do not end the statement
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '#' => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            '\\' if chars.get(i + 1) == Some(&'\n') => i += 2,
+            '\n' => {
+                if depth == 0 && tokens.last().is_some_and(|t| *t != Token::Newline) {
+                    tokens.push(Token::Newline);
+                }
+                i += 1;
+            }
+            '"' | '\'' => {
+                let (value, end) = string_literal(&chars, i);
+                tokens.push(Token::Str(value));
+                i = end;
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                let is_prefix = word.len() <= 2
+                    && word.chars().all(|c| "rRbBfFuU".contains(c))
+                    && matches!(chars.get(i), Some('"' | '\''));
+                if is_prefix {
+                    let (value, end) = string_literal(&chars, i);
+                    if word.contains(['f', 'F']) {
+                        tokens.push(Token::FStr(value));
+                    } else {
+                        tokens.push(Token::Str(value));
+                    }
+                    i = end;
+                } else {
+                    tokens.push(Token::Name(word));
+                }
+            }
+            c if c.is_ascii_digit() => {
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '.') {
+                    i += 1;
+                }
+            }
+            c if c.is_whitespace() => i += 1,
+            c => {
+                match c {
+                    '(' | '[' | '{' => depth += 1,
+                    ')' | ']' | '}' => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+                tokens.push(Token::Op(c));
+                i += 1;
+            }
+        }
+    }
+    tokens
+}
+
+/// The contents of the string literal whose opening quote is at `start`, and the position
+/// after it; an unterminated literal runs to the end of the source
+fn string_literal(chars: &[char], start: usize) -> (String, usize) {
+    let quote = chars[start];
+    let triple = chars.get(start + 1) == Some(&quote) && chars.get(start + 2) == Some(&quote);
+    let mut i = start + if triple { 3 } else { 1 };
+    let mut value = String::new();
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\\' && i + 1 < chars.len() {
+            value.push(chars[i + 1]);
+            i += 2;
+            continue;
+        }
+        if c == quote {
+            if !triple {
+                return (value, i + 1);
+            }
+            if chars.get(i + 1) == Some(&quote) && chars.get(i + 2) == Some(&quote) {
+                return (value, i + 3);
+            }
+        }
+        if c == '\n' && !triple {
+            return (value, i);
+        }
+        value.push(c);
+        i += 1;
+    }
+    (value, i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_analyze_writes() {
+        let script = r#"
+import pandas as pd
+from pathlib import Path
+
+def main(path: Path):
+    # df.to_excel("commented.xlsx") is not a write
+    df = pd.read_csv(path)
+    out = path.with_suffix(".Parquet")
+    df.to_parquet(out)
+    df.to_csv(path.parent / f"{path.stem}_clean.csv", index=False)
+    with open("summary.txt", mode="w") as f:
+        f.write("done")
+    with open(path) as f:
+        pass
+    return [out]
+"#;
+        let analysis = analyze(script);
+        assert_eq!(
+            analysis.written_extensions,
+            vec![".csv", ".parquet", ".txt"]
+        );
+        assert_eq!(analysis.written_paths, vec!["summary.txt"]);
+        assert!(analysis.network_modules.is_empty());
+        assert_eq!(
+            analysis.badges,
+            vec!["writes .csv", "writes .parquet", "writes .txt"]
+        );
+    }
+
+    #[test]
+    fn test_analyze_network() {
+        let script =
+            "import os, urllib.request as r\nfrom requests import get\nx = 'import socket'\n";
+        let analysis = analyze(script);
+        assert_eq!(analysis.network_modules, vec!["requests", "urllib"]);
+        assert_eq!(analysis.badges, vec!["network"]);
+        assert_eq!(
+            analyze("def main(path):\n    pass\n"),
+            ScriptAnalysis::default()
+        );
+    }
+
+    #[test]
+    fn test_aliased_and_nested_imports() {
+        let script = r#"
+import requests as rq, numpy as np
+from urllib import request as ur
+from . import helpers
+
+def main(path):
+    import socket
+    if path.exists(): import ftplib
+    return []
+"#;
+        assert_eq!(
+            analyze(script).network_modules,
+            vec!["ftplib", "requests", "socket", "urllib"]
+        );
+    }
+
+    #[test]
+    fn test_dynamic_imports() {
+        let script = r#"
+import importlib
+http = __import__("http.client")
+s3 = importlib.import_module(name="boto3")
+np = __import__("numpy")
+mod = importlib.import_module(module_name)
+"#;
+        assert_eq!(analyze(script).network_modules, vec!["boto3", "http"]);
+    }
+
+    #[test]
+    fn test_open_modes() {
+        let script = r#"
+open("a.txt", mode="w")
+open(file="b.log", mode="a")
+open("c.txt", encoding="w")
+open("d.txt", "rb")
+open("e.bin", "wb", buffering=0)
+open(mode="x", file="f.json")
+"#;
+        assert_eq!(
+            analyze(script).written_paths,
+            vec!["a.txt", "b.log", "e.bin", "f.json"]
+        );
+    }
+
+    #[test]
+    fn test_f_string_paths() {
+        let script = r#"
+df.to_csv(f"{path.stem}_clean.csv")
+df.to_parquet(path_or_buf=f"{out}")
+open(f"{name}.{ext}", "w")
+with open(f'{name}-log.TXT', "w") as f:
+    f.write(f"{'done'}")
+"#;
+        let analysis = analyze(script);
+        // Paths filled in at runtime are not literal paths, but their extension still shows
+        assert!(analysis.written_paths.is_empty());
+        assert_eq!(
+            analysis.written_extensions,
+            vec![".csv", ".parquet", ".txt"]
+        );
+    }
+}
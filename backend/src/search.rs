@@ -0,0 +1,87 @@
+//! Turning what users type into the search box into an FTS5 query. The raw FTS5 syntax has
+//! operators and column filters that fail on ordinary input (`a-b`, `"`, `name:`), so every
+//! word is quoted and matched as a prefix, and all words must match.
+
+use crate::models::LineMatch;
+
+const MAX_SNIPPET_CHARS: usize = 200;
+
+// The words of a query as the FTS5 tokenizer sees them
+fn words(query: &str) -> Vec<&str> {
+    query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/// The FTS5 MATCH expression for a search box query; None if it has no words
+pub fn match_expression(query: &str) -> Option<String> {
+    let words: Vec<String> = words(query)
+        .into_iter()
+        .map(|word| format!("\"{}\"*", word))
+        .collect();
+    (!words.is_empty()).then(|| words.join(" "))
+}
+
+/// The FTS5 MATCH expression for a string inside file contents: its words in order, the last
+/// one possibly cut off (`SN-00A1` finds `SN-00A12345`); None if it has no words
+pub fn phrase_expression(query: &str) -> Option<String> {
+    let words = words(query);
+    (!words.is_empty()).then(|| format!("\"{}\"*", words.join(" ")))
+}
+
+/// Up to `max` lines of `content` with every word of `query`, case-insensitively, numbered
+/// from 1 and cut to a readable length
+pub fn matching_lines(content: &str, query: &str, max: usize) -> Vec<LineMatch> {
+    let words: Vec<String> = words(query).iter().map(|w| w.to_lowercase()).collect();
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| {
+            let line = line.to_lowercase();
+            words.iter().all(|word| line.contains(word.as_str()))
+        })
+        .take(max)
+        .map(|(number, line)| LineMatch {
+            line: number + 1,
+            text: line.chars().take(MAX_SNIPPET_CHARS).collect(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_match_expression() {
+        assert_eq!(
+            match_expression("probe-1 temp").as_deref(),
+            Some(r#""probe"* "1"* "temp"*"#)
+        );
+        assert_eq!(
+            match_expression("name:\"x\" OR").as_deref(),
+            Some(r#""name"* "x"* "OR"*"#)
+        );
+        assert_eq!(match_expression(" -- "), None);
+    }
+
+    #[test]
+    fn test_phrase_expression() {
+        assert_eq!(
+            phrase_expression("SN-00A1").as_deref(),
+            Some(r#""SN 00A1"*"#)
+        );
+        assert_eq!(phrase_expression("\""), None);
+    }
+
+    #[test]
+    fn test_matching_lines() {
+        let content = "serial,temp\nsn-00a12345,21.5\nsn-00b99999,22.0\n";
+        let found = matching_lines(content, "SN-00A1", 5);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].line, 2);
+        assert_eq!(found[0].text, "sn-00a12345,21.5");
+        assert_eq!(matching_lines(content, "sn", 1).len(), 1);
+    }
+}
@@ -0,0 +1,81 @@
+use super::read_upload;
+use crate::mime_sniff::is_text_type;
+use crate::models::LineMatch;
+use crate::repos::{StoredUpload, UploadRepo};
+use crate::search::{matching_lines, phrase_expression};
+use crate::AppState;
+
+const MAX_LINES_PER_HIT: usize = 5;
+
+/// Whether the contents of `upload` belong in the content index
+fn is_indexable(state: &AppState, upload: &StoredUpload) -> bool {
+    state.content_index_max_bytes > 0
+        && upload.file_size as u64 <= state.content_index_max_bytes
+        && upload.artifact_type == "data"
+        && upload
+            .detected_mime_type
+            .as_deref()
+            .is_some_and(is_text_type)
+}
+
+/// Add the contents of a small text upload to the content index; other uploads are skipped
+pub async fn index_upload_contents(state: &AppState, upload: &StoredUpload) -> Result<(), String> {
+    if !is_indexable(state, upload) {
+        return Ok(());
+    }
+    let data = read_upload(state, &upload.filename, upload.compression.as_deref())
+        .await
+        .map_err(|e| format!("Failed to read upload {}: {}", upload.id, e))?;
+    UploadRepo::new(&state.db)
+        .index_content(&upload.id, &String::from_utf8_lossy(&data))
+        .await
+        .map_err(|e| format!("Failed to index upload {}: {}", upload.id, e))
+}
+
+/// Index the text uploads stored before content indexing was enabled (or its size limit was
+/// raised); returns how many were indexed
+pub async fn index_missing_contents(state: &AppState) -> Result<usize, String> {
+    let max_bytes = i64::try_from(state.content_index_max_bytes).unwrap_or(i64::MAX);
+    if max_bytes == 0 {
+        return Ok(0);
+    }
+    let uploads = UploadRepo::new(&state.db)
+        .without_content_index(max_bytes)
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut indexed = 0;
+    for upload in uploads.iter().filter(|upload| is_indexable(state, upload)) {
+        match index_upload_contents(state, upload).await {
+            Ok(()) => indexed += 1,
+            Err(e) => tracing::warn!("{}", e),
+        }
+    }
+    Ok(indexed)
+}
+
+/// Uploads whose contents contain `query`, best matches first, with the matching lines
+pub async fn search_contents(
+    state: &AppState,
+    query: &str,
+    hidden_tag_ids: &[String],
+    limit: i64,
+) -> Result<Vec<(StoredUpload, Vec<LineMatch>)>, String> {
+    let Some(expression) = phrase_expression(query) else {
+        return Ok(Vec::new());
+    };
+    let uploads = UploadRepo::new(&state.db)
+        .search_contents(&expression, hidden_tag_ids, limit)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut hits = Vec::with_capacity(uploads.len());
+    for upload in uploads {
+        let lines = match read_upload(state, &upload.filename, upload.compression.as_deref()).await
+        {
+            Ok(data) => matching_lines(&String::from_utf8_lossy(&data), query, MAX_LINES_PER_HIT),
+            Err(_) => Vec::new(), // the file went missing since it was indexed
+        };
+        hits.push((upload, lines));
+    }
+    Ok(hits)
+}
@@ -0,0 +1,119 @@
+use crate::custody::RequestOrigin;
+use crate::models::{ChainOfCustody, CustodyEvent};
+use crate::repos::{CustodyRepo, StoredUpload, UploadRepo};
+use crate::timestamps;
+use crate::AppState;
+
+/// Record how an upload was received; like audit entries, a failure is only logged
+pub async fn record_upload_origin(
+    state: &AppState,
+    upload_id: &str,
+    received_via: &str,
+    source_url: Option<&str>,
+    origin: &RequestOrigin,
+    signature: Option<&str>,
+) {
+    if let Err(e) = CustodyRepo::new(&state.db)
+        .set_origin(upload_id, received_via, source_url, origin, signature)
+        .await
+    {
+        tracing::warn!("Failed to record the origin of upload {}: {}", upload_id, e);
+    }
+}
+
+/// Add an entry to an upload's audit trail. A failure is logged, not passed on: the change
+/// itself has been made by then.
+pub async fn record_custody_event(
+    state: &AppState,
+    upload_id: &str,
+    action: &str,
+    detail: Option<&str>,
+    origin: &RequestOrigin,
+) {
+    if let Err(e) = CustodyRepo::new(&state.db)
+        .record(upload_id, action, detail, origin, &timestamps::now())
+        .await
+    {
+        tracing::warn!("Failed to audit {} of upload {}: {}", action, upload_id, e);
+    }
+}
+
+/// The chain of custody of an upload: how it came in (or what it was made from), then the
+/// audited changes, the jobs run on it and its reviews, oldest first
+pub async fn chain_of_custody(
+    state: &AppState,
+    upload: StoredUpload,
+) -> Result<ChainOfCustody, String> {
+    let custody = CustodyRepo::new(&state.db);
+    let origin = custody
+        .origin(&upload.id)
+        .await
+        .map_err(|e| e.to_string())?;
+    let produced_from = UploadRepo::new(&state.db)
+        .lineage(&upload.id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let received = match (&origin, &produced_from) {
+        (Some(origin), _) => CustodyEvent {
+            at: upload.created_at.clone(),
+            action: "uploaded".to_string(),
+            actor: origin.uploaded_by.clone(),
+            client_ip: origin.client_ip.clone(),
+            detail: Some(match (&origin.source_url, &origin.signature) {
+                (Some(url), _) => format!("Fetched from {}", url),
+                (None, Some(_)) => format!("Signed upload via {}", origin.received_via),
+                (None, None) => format!("Via {}", origin.received_via),
+            }),
+            job_id: None,
+        },
+        (None, Some(lineage)) => CustodyEvent {
+            at: upload.created_at.clone(),
+            action: "derived".to_string(),
+            actor: None,
+            client_ip: None,
+            detail: Some(format!(
+                "From {} by {}",
+                lineage.source_filename, lineage.function_name
+            )),
+            job_id: None,
+        },
+        // Stored before origins were recorded, or by an internal path
+        (None, None) => CustodyEvent {
+            at: upload.created_at.clone(),
+            action: "uploaded".to_string(),
+            actor: None,
+            client_ip: None,
+            detail: None,
+            job_id: None,
+        },
+    };
+    let mut events = vec![received];
+    events.extend(custody.audit(&upload.id).await.map_err(|e| e.to_string())?);
+    events.extend(
+        custody
+            .job_events(&upload.id)
+            .await
+            .map_err(|e| e.to_string())?,
+    );
+    events.extend(
+        custody
+            .review_events(&upload.id)
+            .await
+            .map_err(|e| e.to_string())?,
+    );
+    // Stable, so entries at the same instant keep the order above
+    events.sort_by(|a, b| a.at.cmp(&b.at));
+
+    Ok(ChainOfCustody {
+        upload_id: upload.id,
+        filename: upload.original_filename,
+        file_size: upload.file_size,
+        sha256: upload.sha256,
+        created_at: upload.created_at,
+        origin,
+        produced_from,
+        events,
+        generated_at: timestamps::now(),
+    })
+}
@@ -0,0 +1,70 @@
+use crate::diagrams::{Diagram, Shape};
+use crate::repos::{FunctionRepo, UploadRepo};
+use crate::AppState;
+use std::collections::HashMap;
+
+/// The provenance of an upload: the files it was made from and the files made from it, each
+/// arrow labelled with the function or operation of that step. None if there is no upload.
+pub async fn lineage_diagram(state: &AppState, upload_id: &str) -> Result<Option<Diagram>, String> {
+    let uploads = UploadRepo::new(&state.db);
+    let Some(upload) = uploads.get(upload_id).await.map_err(|e| e.to_string())? else {
+        return Ok(None);
+    };
+    let edges = uploads
+        .lineage_graph(upload_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut diagram = Diagram::default();
+    let mut nodes: HashMap<String, usize> = HashMap::new();
+    let root = diagram.add_node(&upload.original_filename, Shape::File);
+    diagram.nodes[root].highlight = true;
+    nodes.insert(upload.id, root);
+    for edge in edges {
+        let mut node = |id: String, filename: &str| {
+            *nodes
+                .entry(id)
+                .or_insert_with(|| diagram.add_node(filename, Shape::File))
+        };
+        let from = node(edge.source_id, &edge.source_filename);
+        let to = node(edge.output_id, &edge.output_filename);
+        diagram.add_edge(from, to, Some(&edge.step), !edge.success);
+    }
+    Ok(Some(diagram))
+}
+
+/// How data flows through the functions: each function between the tags that trigger it and
+/// the tags it gives its outputs. Disabled functions are drawn muted.
+pub async fn pipeline_diagram(state: &AppState) -> Result<Diagram, String> {
+    let functions = FunctionRepo::new(&state.db);
+    let mut all = functions.list().await.map_err(|e| e.to_string())?;
+    all.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut diagram = Diagram::default();
+    let mut tags: HashMap<String, usize> = HashMap::new();
+    for function in all {
+        let inputs = functions
+            .input_tags(&function.id)
+            .await
+            .map_err(|e| e.to_string())?;
+        let outputs = functions
+            .output_tags(&function.id)
+            .await
+            .map_err(|e| e.to_string())?;
+        let node = diagram.add_node(&function.name, Shape::Function);
+        diagram.nodes[node].muted = !function.enabled;
+        for tag in inputs {
+            let tag_node = *tags
+                .entry(tag.id)
+                .or_insert_with(|| diagram.add_node(&tag.name, Shape::Tag));
+            diagram.add_edge(tag_node, node, None, false);
+        }
+        for tag in outputs {
+            let tag_node = *tags
+                .entry(tag.id)
+                .or_insert_with(|| diagram.add_node(&tag.name, Shape::Tag));
+            diagram.add_edge(node, tag_node, None, false);
+        }
+    }
+    Ok(diagram)
+}
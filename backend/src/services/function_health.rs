@@ -0,0 +1,62 @@
+use crate::repos::{FunctionRepo, JobRepo};
+use crate::services::{add_notification, SHUTDOWN_MESSAGE};
+use crate::AppState;
+
+/// Quarantine a function whose latest runs break the health policy: disable it, record why
+/// and notify. It stays disabled until someone enables it again.
+pub async fn check_function_health(state: &AppState, function_id: &str) {
+    let policy = state.settings().function_health;
+    let runs_needed = policy.runs_needed();
+    if runs_needed == 0 {
+        return;
+    }
+    let functions = FunctionRepo::new(&state.db);
+    let Ok(Some(function)) = functions.get(function_id).await else {
+        return;
+    };
+    if function.quarantined_at.is_some() {
+        return;
+    }
+    let outcomes = match JobRepo::new(&state.db)
+        .recent_outcomes(
+            function_id,
+            function.health_since.as_deref(),
+            SHUTDOWN_MESSAGE,
+            runs_needed,
+        )
+        .await
+    {
+        Ok(outcomes) => outcomes,
+        Err(e) => {
+            tracing::warn!(
+                "Could not check the health of function {}: {}",
+                function_id,
+                e
+            );
+            return;
+        }
+    };
+    let Some(reason) = policy.verdict(&outcomes) else {
+        return;
+    };
+
+    match functions.quarantine(function_id, &reason).await {
+        Ok(true) => {
+            tracing::warn!("Quarantined function {}: {}", function.name, reason);
+            add_notification(
+                state,
+                "function_quarantined",
+                &format!("{} was disabled after repeated failures", function.name),
+                Some(&format!(
+                    "{}. Enable the function again once it is fixed.",
+                    reason
+                )),
+                None,
+                None,
+            )
+            .await;
+        }
+        Ok(false) => {} // quarantined by another job that just finished
+        Err(e) => tracing::warn!("Could not quarantine function {}: {}", function_id, e),
+    }
+}
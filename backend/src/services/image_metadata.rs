@@ -0,0 +1,78 @@
+use super::plain_upload_path;
+use crate::image_metadata::{has_image_metadata, read_image_metadata, ImageMetadata};
+use crate::mime_sniff::effective_extension;
+use crate::repos::{ImageMetadataRepo, StoredUpload};
+use crate::AppState;
+use std::sync::Arc;
+
+fn is_image(upload: &StoredUpload) -> bool {
+    let extension = effective_extension(
+        &upload.original_filename,
+        upload.detected_mime_type.as_deref(),
+    )
+    .unwrap_or_default();
+    has_image_metadata(&extension)
+}
+
+/// Read the dimensions and EXIF tags of an image upload and store them with it
+pub async fn extract_image_metadata(
+    state: &AppState,
+    upload: &StoredUpload,
+) -> Result<ImageMetadata, String> {
+    let path = plain_upload_path(state, &upload.filename, upload.compression.as_deref())
+        .await
+        .map_err(|e| format!("Failed to read upload {}: {}", upload.id, e))?;
+    let metadata = tokio::task::spawn_blocking(move || read_image_metadata(&path))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| {
+            format!(
+                "Could not read image metadata of upload {}: {}",
+                upload.id, e
+            )
+        })?;
+    ImageMetadataRepo::new(&state.db)
+        .set(&upload.id, &metadata)
+        .await
+        .map_err(|e| {
+            format!(
+                "Failed to store image metadata of upload {}: {}",
+                upload.id, e
+            )
+        })?;
+    Ok(metadata)
+}
+
+/// The metadata of an image upload; images stored before metadata was extracted, or whose
+/// extraction is still running, are read now. None for other uploads.
+pub async fn image_metadata(
+    state: &AppState,
+    upload: &StoredUpload,
+) -> Result<Option<ImageMetadata>, String> {
+    if !is_image(upload) {
+        return Ok(None);
+    }
+    let stored = ImageMetadataRepo::new(&state.db)
+        .get(&upload.id)
+        .await
+        .map_err(|e| e.to_string())?;
+    match stored {
+        Some(metadata) => Ok(Some(metadata)),
+        None => extract_image_metadata(state, upload).await.map(Some),
+    }
+}
+
+/// Extract the metadata of a new image upload in the background; other uploads are skipped
+pub fn spawn_image_metadata(state: &Arc<AppState>, upload: &StoredUpload) {
+    if !is_image(upload) {
+        return;
+    }
+
+    let state_clone = state.clone();
+    let upload = upload.clone();
+    state.tasks.spawn("image_metadata", async move {
+        if let Err(e) = extract_image_metadata(&state_clone, &upload).await {
+            tracing::warn!("{}", e);
+        }
+    });
+}
@@ -0,0 +1,661 @@
+use crate::cost_estimate::{estimate, CostEstimate, ESTIMATE_RUNS};
+use crate::executor::{ComputeBackend, RunInput};
+use crate::mime_sniff::{detect_mime_type, SNIFF_BYTES};
+use crate::models::{DataDictionary, FunctionPreview, InputSlice, Job, QueuedJob};
+use crate::repos::{
+    FunctionRepo, JobRepo, NewLineage, NewUpload, StoredFunction, StoredUpload, TagRepo, UploadRepo,
+};
+use crate::scheduler::job_source;
+use crate::services::{
+    check_function_health, compress_stored_file, find_duplicate_uploads, notify_job_failed,
+    sha256_hex,
+};
+use crate::table_parser::{load_table_slice, TableQuery};
+use crate::timestamps;
+use crate::triggers::{ConditionEngine, FunctionTrigger, TriggerEngine, UploadFacts};
+use crate::AppState;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// A function output ready to become an upload
+pub struct JobOutput {
+    pub filename: String, // name as produced by the function
+    pub path: PathBuf,    // current location; moved into the uploads directory
+    pub dictionary: Option<DataDictionary>,
+}
+
+/// Error recorded for jobs the server stopped before they finished
+pub const SHUTDOWN_MESSAGE: &str = "Server shut down before the job finished";
+
+/// Start a job for every enabled function whose trigger matches the upload, in the background
+pub fn trigger_functions_for_upload(state: Arc<AppState>, upload_id: String) {
+    let tasks = state.tasks.clone();
+    tasks.spawn("trigger", async move {
+        enqueue_functions_for_upload(&state, &upload_id).await;
+    });
+}
+
+/// Trigger functions for several uploads in one background pass, one upload after the other
+pub fn trigger_functions_for_uploads(state: Arc<AppState>, upload_ids: Vec<String>) {
+    let tasks = state.tasks.clone();
+    tasks.spawn("trigger", async move {
+        for upload_id in &upload_ids {
+            enqueue_functions_for_upload(&state, upload_id).await;
+        }
+    });
+}
+
+/// What a run of a function on `input_bytes` is expected to take, from its latest successful
+/// runs; None if it has not run yet
+pub async fn estimate_run(
+    state: &AppState,
+    function_id: &str,
+    input_bytes: i64,
+) -> Result<Option<CostEstimate>, String> {
+    let samples = JobRepo::new(&state.db)
+        .run_samples(function_id, ESTIMATE_RUNS)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(estimate(&samples, input_bytes))
+}
+
+/// Add the estimate to jobs still waiting for a slot, so whoever looks at the queue knows
+/// what is coming; trial runs on part of an input get none
+pub async fn add_estimates(state: &AppState, jobs: &mut [Job]) -> Result<(), String> {
+    let repo = JobRepo::new(&state.db);
+    let uploads = UploadRepo::new(&state.db);
+    let mut samples = HashMap::new();
+    for job in jobs.iter_mut() {
+        if job.status != "SUBMITTED" || job.input_slice.is_some() {
+            continue;
+        }
+        let Some(upload) = uploads
+            .get(&job.upload_id)
+            .await
+            .map_err(|e| e.to_string())?
+        else {
+            continue;
+        };
+        if !samples.contains_key(&job.function_id) {
+            let runs = repo
+                .run_samples(&job.function_id, ESTIMATE_RUNS)
+                .await
+                .map_err(|e| e.to_string())?;
+            samples.insert(job.function_id.clone(), runs);
+        }
+        job.estimate = estimate(&samples[&job.function_id], upload.file_size);
+    }
+    Ok(())
+}
+
+/// Enabled functions whose trigger matches an upload with these facts
+pub async fn matching_functions(state: &AppState, upload: &UploadFacts) -> Vec<StoredFunction> {
+    let functions = FunctionRepo::new(&state.db);
+    let enabled = functions.enabled().await.unwrap_or_default();
+    let mut triggers = Vec::new();
+    for function in &enabled {
+        let input_tags = functions
+            .input_tag_ids(&function.id)
+            .await
+            .unwrap_or_default();
+        let excluded_tags = functions
+            .excluded_tag_ids(&function.id)
+            .await
+            .unwrap_or_default();
+        match FunctionTrigger::new(
+            function.id.clone(),
+            input_tags,
+            excluded_tags,
+            &function.conditions(),
+        ) {
+            Ok(trigger) => triggers.push(trigger),
+            Err(e) => tracing::warn!("Skipping function {}: {}", function.id, e),
+        }
+    }
+
+    let selected: Vec<String> = ConditionEngine
+        .select(upload, &triggers)
+        .into_iter()
+        .map(|trigger| trigger.function_id.clone())
+        .collect();
+    enabled
+        .into_iter()
+        .filter(|function| selected.contains(&function.id))
+        .collect()
+}
+
+/// Start a job for every enabled function whose trigger matches the upload, returning the
+/// jobs once they are queued (they run in the background)
+pub async fn enqueue_functions_for_upload(
+    state: &Arc<AppState>,
+    upload_id: &str,
+) -> Vec<QueuedJob> {
+    let mut queued = Vec::new();
+    let Some((upload, filename)) = upload_facts(state, upload_id).await else {
+        return queued;
+    };
+
+    for function in matching_functions(state, &upload).await {
+        tracing::info!(
+            "MATCH! Triggering function {} for upload {}",
+            function.id,
+            upload_id
+        );
+        queued.extend(start_job(state, upload_id, &function, &filename, None).await);
+    }
+    queued
+}
+
+/// Start a trial run of a function on part of an upload, whatever its triggers; None if the
+/// upload or function does not exist. Outputs of trial runs trigger nothing further.
+pub async fn run_function_on_slice(
+    state: &Arc<AppState>,
+    upload_id: &str,
+    function_id: &str,
+    slice: InputSlice,
+) -> Result<Option<QueuedJob>, String> {
+    let Some(upload) = UploadRepo::new(&state.db)
+        .get(upload_id)
+        .await
+        .map_err(|e| e.to_string())?
+    else {
+        return Ok(None);
+    };
+    let Some(function) = FunctionRepo::new(&state.db)
+        .get(function_id)
+        .await
+        .map_err(|e| e.to_string())?
+    else {
+        return Ok(None);
+    };
+    start_job(state, upload_id, &function, &upload.filename, Some(slice))
+        .await
+        .map(Some)
+        .ok_or_else(|| "The job could not be started".to_string())
+}
+
+/// Run a function on part of an upload and preview its first tabular output, without
+/// recording a job or keeping any output. Previews always run locally, in a job slot of their
+/// own source, so an author gets feedback without waiting for the cluster.
+pub async fn preview_function(
+    state: &AppState,
+    function: &StoredFunction,
+    upload: &StoredUpload,
+    slice: InputSlice,
+) -> Result<FunctionPreview, String> {
+    let _slot = state.job_slots.acquire("preview").await;
+    let input = RunInput {
+        filename: &upload.filename,
+        compression: upload.compression.as_deref(),
+        original_filename: &upload.original_filename,
+        slice: Some(slice),
+    };
+    let outputs = match &function.expression {
+        Some(expression) => {
+            state
+                .executor
+                .execute_expression(&function.name, expression, &input)
+                .await?
+        }
+        None => {
+            state
+                .executor
+                .execute_function(
+                    &function.script_filename,
+                    &input,
+                    ComputeBackend::Local,
+                    &[],
+                )
+                .await?
+        }
+    };
+
+    let output_dir = state.executor.output_dir();
+    let mut preview = FunctionPreview {
+        sample: slice.describe(),
+        outputs: outputs.clone(),
+        output: None,
+        preview: None,
+        error_log: None,
+    };
+    for output_file in &outputs {
+        let path = output_dir.join(output_file);
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if output_file.starts_with("error_") && output_file.ends_with(".log") {
+            preview.error_log = tokio::fs::read_to_string(&path).await.ok();
+        } else if preview.output.is_none() && matches!(extension.as_str(), "csv" | "parquet") {
+            let query = TableQuery::default();
+            let table = tokio::task::spawn_blocking(move || {
+                load_table_slice(&path.to_string_lossy(), &extension, &query)
+                    .map(|slice| slice.into_preview())
+                    .map_err(|e| e.to_string())
+            })
+            .await
+            .map_err(|e| e.to_string())?;
+            match table {
+                Ok(table) => {
+                    preview.output = Some(output_file.clone());
+                    preview.preview = Some(table);
+                }
+                Err(e) => tracing::warn!("Could not preview {}: {}", output_file, e),
+            }
+        }
+    }
+
+    for output_file in &outputs {
+        let _ = tokio::fs::remove_file(output_dir.join(output_file)).await;
+        let _ = tokio::fs::remove_file(output_dir.join(format!("{}.dictionary.json", output_file)))
+            .await;
+    }
+    Ok(preview)
+}
+
+// Record a job and run it in the background
+async fn start_job(
+    state: &Arc<AppState>,
+    upload_id: &str,
+    function: &StoredFunction,
+    filename: &str,
+    slice: Option<InputSlice>,
+) -> Option<QueuedJob> {
+    let job_id = match JobRepo::new(&state.db)
+        .create(upload_id, &function.id, slice.as_ref())
+        .await
+    {
+        Ok(job_id) => job_id,
+        Err(e) => {
+            tracing::error!("Failed to create job for function {}: {}", function.id, e);
+            return None;
+        }
+    };
+
+    let on_panic = {
+        let state = state.clone();
+        let job_id = job_id.clone();
+        let upload_id = upload_id.to_string();
+        move |message: String| async move {
+            let error_message = format!("Job crashed: {}", message);
+            fail_job(&state, &job_id, &upload_id, &error_message).await;
+        }
+    };
+    let started = state.tasks.spawn_job(
+        &job_id,
+        execute_job(
+            state.clone(),
+            job_id.clone(),
+            upload_id.to_string(),
+            function.clone(),
+            filename.to_string(),
+            slice,
+        ),
+        on_panic,
+    );
+    if !started {
+        fail_job(state, &job_id, upload_id, SHUTDOWN_MESSAGE).await;
+        return None;
+    }
+    Some(QueuedJob {
+        id: job_id,
+        function_id: function.id.clone(),
+        function_name: function.name.clone(),
+    })
+}
+
+// Everything trigger conditions can look at, plus the stored filename
+async fn upload_facts(state: &AppState, upload_id: &str) -> Option<(UploadFacts, String)> {
+    let upload = UploadRepo::new(&state.db).get(upload_id).await.ok()??;
+    let tags = TagRepo::new(&state.db)
+        .for_upload(upload_id)
+        .await
+        .unwrap_or_default();
+    let lineage = UploadRepo::new(&state.db)
+        .lineage_link(upload_id)
+        .await
+        .ok()
+        .flatten();
+
+    let facts = UploadFacts {
+        filename: upload.original_filename,
+        mime_type: upload.detected_mime_type.or(upload.mime_type),
+        file_size: upload.file_size,
+        tag_ids: tags.iter().map(|t| t.id.clone()).collect(),
+        tag_names: tags.into_iter().map(|t| t.name).collect(),
+        derived: lineage.is_some(),
+        source_function_id: lineage.and_then(|l| l.function_id),
+    };
+    Some((facts, upload.filename))
+}
+
+// Issue a completion token for a remote run and tell the worker how to report back
+async fn completion_env(state: &AppState, job_id: &str) -> Vec<(String, String)> {
+    let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    let token_hash = sha256_hex(token.as_bytes());
+    if JobRepo::new(&state.db)
+        .set_completion_token_hash(job_id, &token_hash)
+        .await
+        .is_err()
+    {
+        return Vec::new();
+    }
+
+    let mut env = vec![
+        ("DATALAB_JOB_ID".to_string(), job_id.to_string()),
+        ("DATALAB_JOB_TOKEN".to_string(), token),
+    ];
+    if let Some(public_url) = &state.public_url {
+        env.push((
+            "DATALAB_COMPLETE_URL".to_string(),
+            format!(
+                "{}/api/jobs/{}/complete",
+                public_url.trim_end_matches('/'),
+                job_id
+            ),
+        ));
+    }
+    env
+}
+
+// Execute a single job once the scheduler gives it a slot
+async fn execute_job(
+    state: Arc<AppState>,
+    job_id: String,
+    upload_id: String,
+    function: StoredFunction,
+    input_filename: String,
+    slice: Option<InputSlice>,
+) {
+    let function_id = function.id;
+    let backend = ComputeBackend::parse(&function.executor).unwrap_or(ComputeBackend::Local);
+    // Wait for an execution slot, taking turns with other sources; the cluster schedules
+    // remote runs itself
+    let _slot = match backend {
+        ComputeBackend::Local => {
+            let tags = TagRepo::new(&state.db)
+                .for_upload(&upload_id)
+                .await
+                .unwrap_or_default();
+            let source = job_source(tags.iter().map(|tag| &tag.name));
+            Some(state.job_slots.acquire(&source).await)
+        }
+        _ => None,
+    };
+
+    let jobs = JobRepo::new(&state.db);
+    let _ = jobs.mark_running(&job_id).await;
+
+    tracing::info!(
+        "Executing job {} (function: {}, upload: {})",
+        job_id,
+        function_id,
+        upload_id
+    );
+
+    // Get original filename
+    let (original_filename, compression) = match UploadRepo::new(&state.db).get(&upload_id).await {
+        Ok(Some(upload)) => (upload.original_filename, upload.compression),
+        _ => {
+            fail_job(&state, &job_id, &upload_id, "Upload not found").await;
+            return;
+        }
+    };
+
+    // Remote workers may push their results back through POST /jobs/:id/complete
+    let env = match backend {
+        ComputeBackend::Local => Vec::new(),
+        _ => completion_env(&state, &job_id).await,
+    };
+
+    // Execute function; quick functions run their query in-process
+    let input = RunInput {
+        filename: &input_filename,
+        compression: compression.as_deref(),
+        original_filename: &original_filename,
+        slice,
+    };
+    let result = match &function.expression {
+        Some(expression) => {
+            state
+                .executor
+                .execute_expression(&function.name, expression, &input)
+                .await
+        }
+        None => {
+            state
+                .executor
+                .execute_function(&function.script_filename, &input, backend, &env)
+                .await
+        }
+    };
+
+    let output_dir = state.executor.output_dir();
+    if !jobs.claim_completion(&job_id, "executor").await {
+        tracing::info!(
+            "Job {} was already completed by its worker; discarding executor results",
+            job_id
+        );
+        for output_file in result.unwrap_or_default() {
+            let _ = tokio::fs::remove_file(output_dir.join(&output_file)).await;
+            let _ =
+                tokio::fs::remove_file(output_dir.join(format!("{}.dictionary.json", output_file)))
+                    .await;
+        }
+        return;
+    }
+
+    match result {
+        Ok(output_files) => {
+            let mut outputs = Vec::new();
+            for output_file in output_files {
+                // Attach a data dictionary emitted by the function, if any
+                let dictionary_path = output_dir.join(format!("{}.dictionary.json", output_file));
+                let mut dictionary = None;
+                if let Ok(content) = tokio::fs::read_to_string(&dictionary_path).await {
+                    match serde_json::from_str::<DataDictionary>(&content) {
+                        Ok(parsed) => dictionary = Some(parsed),
+                        Err(e) => tracing::warn!(
+                            "Ignoring invalid data dictionary {}: {}",
+                            dictionary_path.display(),
+                            e
+                        ),
+                    }
+                    let _ = tokio::fs::remove_file(&dictionary_path).await;
+                }
+                outputs.push(JobOutput {
+                    path: output_dir.join(&output_file),
+                    filename: output_file,
+                    dictionary,
+                });
+            }
+
+            let output_upload_ids =
+                register_job_outputs(&state, &upload_id, &function_id, outputs).await;
+            finish_job(&state, &job_id, output_upload_ids).await;
+        }
+        Err(e) => {
+            tracing::error!("Job {} failed: {}", job_id, e);
+            fail_job(&state, &job_id, &upload_id, &e).await;
+        }
+    }
+}
+
+/// Register function outputs as uploads with output tags and lineage; returns the new upload IDs
+pub async fn register_job_outputs(
+    state: &Arc<AppState>,
+    upload_id: &str,
+    function_id: &str,
+    outputs: Vec<JobOutput>,
+) -> Vec<String> {
+    let uploads = UploadRepo::new(&state.db);
+    let tags = TagRepo::new(&state.db);
+    let functions = FunctionRepo::new(&state.db);
+    let output_tag_ids = functions
+        .output_tag_ids(function_id)
+        .await
+        .unwrap_or_default();
+    let function = functions.get(function_id).await.ok().flatten();
+
+    let mut output_upload_ids = Vec::new();
+
+    // Register each output file as a new upload
+    for JobOutput {
+        filename: output_file,
+        path: output_path,
+        dictionary,
+    } in outputs
+    {
+        let Ok(metadata) = tokio::fs::metadata(&output_path).await else {
+            continue;
+        };
+        let new_id = Uuid::new_v4().to_string();
+        let created_at = timestamps::now();
+        let file_size = metadata.len() as i64;
+        let is_error_log = output_file.starts_with("error_") && output_file.ends_with(".log");
+        // Intermediate outputs are kept for a while for debugging, outside the pipeline
+        let expires_at = function
+            .as_ref()
+            .filter(|function| !is_error_log && function.is_intermediate(&output_file))
+            .map(|function| {
+                timestamps::format(
+                    chrono::Utc::now() + chrono::Duration::hours(function.intermediate_ttl_hours),
+                )
+            });
+
+        // Move file to uploads directory
+        let new_filename = format!("{}_{}", new_id, output_file);
+        let new_path = state.executor.uploads_dir().join(&new_filename);
+        let _ = tokio::fs::rename(&output_path, &new_path).await;
+
+        let (sha256, detected_mime_type) = match tokio::fs::read(&new_path).await {
+            Ok(data) => {
+                let detected = detect_mime_type(&data[..data.len().min(SNIFF_BYTES)], &output_file);
+                let sha256 = tokio::task::spawn_blocking(move || sha256_hex(&data))
+                    .await
+                    .ok();
+                (sha256, detected)
+            }
+            Err(_) => (None, None),
+        };
+        let compression = compress_stored_file(state, &new_path, detected_mime_type).await;
+        if let Some(sha256) = &sha256 {
+            find_duplicate_uploads(state, sha256, &new_path, compression).await;
+        }
+
+        // Save to database
+        let _ = uploads
+            .insert(&NewUpload {
+                id: &new_id,
+                filename: &new_filename,
+                original_filename: &output_file,
+                file_size,
+                mime_type: None,
+                detected_mime_type,
+                created_at: &created_at,
+                sha256: sha256.as_deref(),
+                artifact_type: if is_error_log { "error_log" } else { "data" },
+                compression,
+                expires_at: expires_at.as_deref(),
+            })
+            .await;
+
+        if let Some(dictionary) = &dictionary {
+            let _ = uploads.set_dictionary(&new_id, dictionary).await;
+        }
+
+        // Apply output tags ONLY if not an error log or intermediate output
+        if !is_error_log && expires_at.is_none() {
+            for tag_id in &output_tag_ids {
+                let _ = tags.tag_upload(&new_id, tag_id).await;
+            }
+        }
+
+        let _ = uploads
+            .add_lineage(&NewLineage {
+                output_upload_id: &new_id,
+                source_upload_id: upload_id,
+                function_id: Some(function_id),
+                operation: None,
+                query: None,
+                success: !is_error_log,
+                created_at: &created_at,
+            })
+            .await;
+
+        // Extension tag (for both success and error) and the other hooks
+        let stored = StoredUpload {
+            id: new_id.clone(),
+            filename: new_filename,
+            original_filename: output_file.clone(),
+            file_size,
+            mime_type: None,
+            detected_mime_type: detected_mime_type.map(str::to_string),
+            created_at: created_at.clone(),
+            sha256,
+            assignee: None,
+            artifact_type: if is_error_log { "error_log" } else { "data" }.to_string(),
+            compression: compression.map(str::to_string),
+            protected: false,
+            expires_at,
+        };
+        state.hooks.created(state, &stored).await;
+
+        output_upload_ids.push(new_id);
+        tracing::info!(
+            "Created output file: {} (success: {})",
+            output_file,
+            !is_error_log
+        );
+    }
+
+    output_upload_ids
+}
+
+/// Mark a job successful and trigger functions for its outputs
+pub async fn finish_job(state: &Arc<AppState>, job_id: &str, output_upload_ids: Vec<String>) {
+    let _ = JobRepo::new(&state.db)
+        .mark_succeeded(job_id, &output_upload_ids)
+        .await;
+
+    tracing::info!(
+        "Job {} completed successfully with {} outputs",
+        job_id,
+        output_upload_ids.len()
+    );
+
+    // Trigger functions for ALL newly created output files (enables chaining), unless they
+    // come from a trial run on part of the input
+    let job = match JobRepo::new(&state.db).get(job_id).await {
+        Ok(Some(job)) => job,
+        _ => return,
+    };
+    if job.input_slice.is_some() {
+        return;
+    }
+    // A run that left an error log counts as a failure
+    check_function_health(state, &job.function_id).await;
+    for output_id in output_upload_ids {
+        tracing::info!("Checking triggers for output file: {}", output_id);
+        let state_clone = state.clone();
+
+        // Delay slightly to ensure DB commits are visible
+        state.tasks.spawn("trigger", async move {
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+            trigger_functions_for_upload(state_clone, output_id);
+        });
+    }
+}
+
+/// Mark a job failed and notify about it
+pub async fn fail_job(state: &AppState, job_id: &str, upload_id: &str, error_message: &str) {
+    let _ = JobRepo::new(&state.db)
+        .mark_failed(job_id, error_message)
+        .await;
+    notify_job_failed(state, job_id, upload_id, error_message).await;
+    if let Ok(Some(job)) = JobRepo::new(&state.db).get(job_id).await {
+        check_function_health(state, &job.function_id).await;
+    }
+}
@@ -0,0 +1,194 @@
+use crate::models::{MirrorStatus, MirrorSync};
+use crate::repos::{ReplicationRepo, StoredUpload, UploadRepo};
+use crate::timestamps;
+use crate::AppState;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Name of the database copy in the --mirror-dir
+const MIRROR_DATABASE_NAME: &str = "datalab.db";
+
+const MIRROR_UPLOADS_DIR: &str = "uploads";
+
+// Copy `source` to `target` through a temporary file next to it, so the mirror never holds
+// a partial copy under the real name
+async fn copy_into_place(source: &Path, target: &Path) -> std::io::Result<()> {
+    if let Some(parent) = target.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let name = target
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let partial = target.with_file_name(format!(".{}.{}.partial", name, uuid::Uuid::new_v4()));
+    if let Err(e) = tokio::fs::copy(source, &partial).await {
+        let _ = tokio::fs::remove_file(&partial).await;
+        return Err(e);
+    }
+    tokio::fs::rename(&partial, target).await
+}
+
+async fn record_replication(
+    state: &AppState,
+    upload_id: &str,
+    status: &str,
+    error: Option<&str>,
+) -> Result<(), String> {
+    ReplicationRepo::new(&state.db)
+        .set(upload_id, status, error, &timestamps::now())
+        .await
+        .map_err(|e| format!("Failed to record replication of {}: {}", upload_id, e))
+}
+
+/// Copy the stored file of an upload to the mirror as it is (compressed uploads stay
+/// compressed) and record how that went. Does nothing without a --mirror-dir.
+pub async fn mirror_upload(state: &AppState, upload: &StoredUpload) -> Result<(), String> {
+    let Some(mirror_dir) = &state.mirror_dir else {
+        return Ok(());
+    };
+    record_replication(state, &upload.id, "pending", None).await?;
+    let source = state.executor.uploads_dir().join(&upload.filename);
+    let target = mirror_dir.join(MIRROR_UPLOADS_DIR).join(&upload.filename);
+    match copy_into_place(&source, &target).await {
+        Ok(()) => record_replication(state, &upload.id, "mirrored", None).await,
+        Err(e) => {
+            record_replication(state, &upload.id, "failed", Some(&e.to_string())).await?;
+            Err(format!("Failed to mirror upload {}: {}", upload.id, e))
+        }
+    }
+}
+
+/// Mirror a new upload in the background
+pub fn spawn_mirror_upload(state: &Arc<AppState>, upload: &StoredUpload) {
+    if state.mirror_dir.is_none() {
+        return;
+    }
+
+    let state_clone = state.clone();
+    let upload = upload.clone();
+    state.tasks.spawn("mirror", async move {
+        if let Err(e) = mirror_upload(&state_clone, &upload).await {
+            tracing::warn!("{}", e);
+        }
+    });
+}
+
+/// Drop the mirrored copy of a deleted upload, so the mirror holds what the store holds
+pub async fn remove_mirrored(state: &AppState, filename: &str) {
+    let Some(mirror_dir) = &state.mirror_dir else {
+        return;
+    };
+    let path = mirror_dir.join(MIRROR_UPLOADS_DIR).join(filename);
+    if let Err(e) = tokio::fs::remove_file(&path).await {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            tracing::warn!("Failed to remove mirrored {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Replace the database copy in the mirror with a fresh, consistent one
+pub async fn mirror_database(state: &AppState) -> Result<PathBuf, String> {
+    let Some(mirror_dir) = &state.mirror_dir else {
+        return Err("Mirroring is not set up (see --mirror-dir)".to_string());
+    };
+    tokio::fs::create_dir_all(mirror_dir)
+        .await
+        .map_err(|e| format!("Failed to create {}: {}", mirror_dir.display(), e))?;
+    let target = mirror_dir.join(MIRROR_DATABASE_NAME);
+    let partial = mirror_dir.join(format!(".{}.partial", MIRROR_DATABASE_NAME));
+    // VACUUM INTO refuses to overwrite, and a partial copy is left by an interrupted one
+    let _ = tokio::fs::remove_file(&partial).await;
+    ReplicationRepo::new(&state.db)
+        .snapshot_database(&partial.to_string_lossy())
+        .await
+        .map_err(|e| format!("Failed to snapshot the database: {}", e))?;
+    tokio::fs::rename(&partial, &target)
+        .await
+        .map_err(|e| format!("Failed to move the database snapshot into place: {}", e))?;
+    Ok(target)
+}
+
+/// Copy every upload not yet in the mirror (stored before mirroring was set up, or whose
+/// copy failed), then the database
+pub async fn sync_mirror(state: &AppState) -> Result<MirrorSync, String> {
+    let ids = ReplicationRepo::new(&state.db)
+        .unmirrored_ids()
+        .await
+        .map_err(|e| e.to_string())?;
+    let uploads = UploadRepo::new(&state.db);
+    let mut sync = MirrorSync {
+        mirrored: 0,
+        failed: 0,
+        database_mirrored: false,
+    };
+    for id in ids {
+        let Some(upload) = uploads.get(&id).await.map_err(|e| e.to_string())? else {
+            continue; // deleted meanwhile
+        };
+        match mirror_upload(state, &upload).await {
+            Ok(()) => sync.mirrored += 1,
+            Err(e) => {
+                tracing::warn!("{}", e);
+                sync.failed += 1;
+            }
+        }
+    }
+    match mirror_database(state).await {
+        Ok(_) => sync.database_mirrored = true,
+        Err(e) => tracing::warn!("{}", e),
+    }
+    Ok(sync)
+}
+
+/// Sync the mirror now and then every `interval`; nothing without a --mirror-dir
+pub fn spawn_mirror_sync(state: Arc<AppState>, interval: std::time::Duration) {
+    if state.mirror_dir.is_none() {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            match sync_mirror(&state).await {
+                Ok(sync) if sync.mirrored + sync.failed > 0 => tracing::info!(
+                    "🪞 Mirrored {} upload(s), {} failed",
+                    sync.mirrored,
+                    sync.failed
+                ),
+                Ok(_) => {}
+                Err(e) => tracing::error!("Mirror sync failed: {}", e),
+            }
+        }
+    });
+}
+
+/// How far the mirror is behind; None without a --mirror-dir
+pub async fn mirror_status(state: &AppState) -> Result<Option<MirrorStatus>, String> {
+    let Some(mirror_dir) = &state.mirror_dir else {
+        return Ok(None);
+    };
+    let replication = ReplicationRepo::new(&state.db);
+    let mut status = MirrorStatus {
+        directory: mirror_dir.display().to_string(),
+        mirrored: 0,
+        pending: 0,
+        failed: 0,
+        unmirrored: 0,
+        database_mirrored_at: None,
+        failures: replication.failures().await.map_err(|e| e.to_string())?,
+    };
+    for (replication_status, count) in replication.counts().await.map_err(|e| e.to_string())? {
+        match replication_status.as_deref() {
+            Some("mirrored") => status.mirrored = count,
+            Some("pending") => status.pending = count,
+            Some("failed") => status.failed = count,
+            _ => status.unmirrored += count,
+        }
+    }
+    status.database_mirrored_at = tokio::fs::metadata(mirror_dir.join(MIRROR_DATABASE_NAME))
+        .await
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .map(|modified| timestamps::format(modified.into()));
+    Ok(Some(status))
+}
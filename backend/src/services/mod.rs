@@ -0,0 +1,1773 @@
+//! Workflows shared by the HTTP handlers and background tasks: storing uploads, running the
+//! functions they trigger, registering the results and caching previews of them. Services
+//! report failures as messages; turning them into responses is up to the caller.
+
+mod content_index;
+mod custody;
+mod diagrams;
+mod function_health;
+mod image_metadata;
+mod jobs;
+mod mirror;
+mod notifications;
+mod quarantine;
+mod releases;
+mod remotes;
+mod settings;
+mod snapshots;
+mod storage;
+mod tags;
+mod thumbnails;
+mod uploads;
+
+pub use content_index::{index_missing_contents, index_upload_contents, search_contents};
+pub use custody::{chain_of_custody, record_custody_event, record_upload_origin};
+pub use diagrams::{lineage_diagram, pipeline_diagram};
+pub use function_health::check_function_health;
+pub use image_metadata::{image_metadata, spawn_image_metadata};
+pub use jobs::{
+    add_estimates, enqueue_functions_for_upload, estimate_run, fail_job, finish_job,
+    matching_functions, preview_function, register_job_outputs, run_function_on_slice,
+    trigger_functions_for_upload, trigger_functions_for_uploads, JobOutput, SHUTDOWN_MESSAGE,
+};
+pub use mirror::{
+    mirror_status, remove_mirrored, spawn_mirror_sync, spawn_mirror_upload, sync_mirror,
+};
+pub use notifications::{add_notification, notify_job_failed};
+pub use quarantine::{
+    discard_quarantined, get_quarantined, list_quarantined, quarantine_file, release_quarantined,
+};
+pub use releases::{
+    is_doi_like, release_files, sha256sums, verify_release, CHECKSUMS_NAME, MANIFEST_NAME,
+};
+pub use remotes::{
+    browse_remote, fetch_remote_upload, remote_base_url, remote_upload, RemoteFetch,
+};
+pub use settings::{change_settings, reload_settings};
+pub use snapshots::{current_pipeline, restore_conflicts, restore_pipeline};
+pub use storage::{
+    compress_stored_file, dataset_stats, plain_upload_path, read_upload, remove_decompressed,
+    storage_stats,
+};
+pub use tags::{TagCache, TagService};
+pub use thumbnails::{cached_thumbnail, remove_thumbnails, spawn_thumbnail};
+pub use uploads::{
+    applied_extension_tag, extension_tag_name, find_duplicate_uploads, run_anomaly_check,
+    sha256_hex, store_upload, store_upload_file,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::custody::RequestOrigin;
+    use crate::executor::ScriptExecutor;
+    use crate::hooks::{UploadHook, UploadHooks};
+    use crate::models::{InputSlice, Job};
+    use crate::repos::StoredUpload;
+    use crate::repos::{
+        DatasetRepo, FunctionRepo, JobFilter, JobRepo, NewFunction, NewRelease, ReleaseRepo,
+        ReplicationRepo, ShareRepo, SnapshotRepo, Sort, SortKey, SortOrder, TagRepo, UploadFilter,
+        UploadRepo,
+    };
+    use crate::scanner::{ScanVerdict, Scanner};
+    use crate::scheduler::FairScheduler;
+    use crate::settings::{SettingValues, Settings};
+    use crate::snapshots::diff;
+    use crate::supervisor::TaskSupervisor;
+    use crate::timestamps;
+    use crate::{AppState, Config};
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+
+    // Stands in for `uv`: upper-cases the input into `upper.txt`, or fails if the script says so;
+    // scripts mentioning LEGACY leave it in OUTPUT_DIR instead of returning it
+    const FAKE_UV: &str = r#"#!/bin/sh
+if grep -q FAIL "$3"; then
+    echo "boom" >&2
+    exit 1
+fi
+if grep -q LEGACY "$3"; then
+    tr 'a-z' 'A-Z' < "$SOURCE_PATH" > "$OUTPUT_DIR/upper.txt"
+    printf '{"outputs": []}' > "$OUTPUT_MANIFEST"
+    exit 0
+fi
+out="$(dirname "$SOURCE_PATH")/upper.txt"
+tr 'a-z' 'A-Z' < "$SOURCE_PATH" > "$out"
+printf '{"outputs": ["%s"]}' "$out" > "$OUTPUT_MANIFEST"
+"#;
+
+    /// A server without HTTP: fresh directories, a migrated SQLite file and the fake `uv`
+    struct Harness {
+        root: PathBuf,
+        state: Arc<AppState>,
+    }
+
+    impl Harness {
+        async fn new(name: &str) -> Self {
+            Self::with_hooks(name, UploadHooks::builtin(None, None)).await
+        }
+
+        async fn with_hooks(name: &str, hooks: UploadHooks) -> Self {
+            Self::build(name, hooks, false, false).await
+        }
+
+        /// With --compress-uploads
+        async fn compressing(name: &str) -> Self {
+            Self::build(name, UploadHooks::builtin(None, None), true, false).await
+        }
+
+        /// With a --mirror-dir in `mirror/`
+        async fn mirroring(name: &str) -> Self {
+            Self::build(name, UploadHooks::builtin(None, None), false, true).await
+        }
+
+        async fn build(
+            name: &str,
+            hooks: UploadHooks,
+            compress_uploads: bool,
+            mirror: bool,
+        ) -> Self {
+            let root = std::env::temp_dir().join(format!(
+                "datalab-services-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&root);
+            for dir in [
+                "uploads",
+                "scripts",
+                "output",
+                "thumbnails",
+                "decompressed",
+                "quarantine",
+            ] {
+                std::fs::create_dir_all(root.join(dir)).unwrap();
+            }
+            let uv_bin = root.join("uv");
+            std::fs::write(&uv_bin, FAKE_UV).unwrap();
+            std::fs::set_permissions(&uv_bin, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+            let db = sqlx::SqlitePool::connect(&format!(
+                "sqlite:{}?mode=rwc",
+                root.join("test.db").display()
+            ))
+            .await
+            .unwrap();
+            sqlx::migrate!("./migrations").run(&db).await.unwrap();
+
+            let executor = ScriptExecutor::new(
+                root.join("scripts"),
+                root.join("uploads"),
+                root.join("output"),
+            )
+            .with_uv_bin(uv_bin);
+            let command_line_settings = SettingValues {
+                max_concurrent_jobs: 2,
+                url_max_size_mb: 0,
+                url_allowed_types: Vec::new(),
+                quarantine_after_failures: 3,
+                ..SettingValues::from_config(&Config::default())
+            };
+            let settings = Settings::new(command_line_settings.clone()).unwrap();
+            let state = Arc::new(AppState {
+                db,
+                executor,
+                job_slots: FairScheduler::new(2),
+                duckdb_bin: None,
+                hooks,
+                tag_cache: TagCache::default(),
+                dedupe_uploads: false,
+                compress_uploads,
+                max_upload_bytes: 0,
+                http: reqwest::Client::new(),
+                public_url: None,
+                thumbnails_dir: root.join("thumbnails"),
+                decompressed_dir: root.join("decompressed"),
+                quarantine_dir: root.join("quarantine"),
+                content_index_max_bytes: 1024 * 1024,
+                webdav: None,
+                watch_folders: Vec::new(),
+                import_dirs: Vec::new(),
+                mirror_dir: mirror.then(|| root.join("mirror")),
+                settings: std::sync::RwLock::new(Arc::new(settings)),
+                command_line_settings,
+                config_file: Some(root.join("config.json")), // only read when a test reloads
+                tasks: TaskSupervisor::new(),
+            });
+            Self { root, state }
+        }
+
+        async fn tag(&self, name: &str) -> String {
+            TagRepo::new(&self.state.db)
+                .create(name, "#000000", None)
+                .await
+                .unwrap()
+                .id
+        }
+
+        async fn function(
+            &self,
+            script: &str,
+            input_tags: Vec<String>,
+            output_tags: Vec<String>,
+        ) -> String {
+            let id = uuid::Uuid::new_v4().to_string();
+            let script_filename = format!("{}.py", id);
+            std::fs::write(self.root.join("scripts").join(&script_filename), script).unwrap();
+            let functions = FunctionRepo::new(&self.state.db);
+            functions
+                .insert(&NewFunction {
+                    id: &id,
+                    name: "upper",
+                    script_filename: &script_filename,
+                    function_type: "transform",
+                    executor: "local",
+                    trigger_conditions: &[],
+                    created_at: &timestamps::now(),
+                    expression: None,
+                })
+                .await
+                .unwrap();
+            functions.set_input_tags(&id, &input_tags).await.unwrap();
+            functions.set_output_tags(&id, &output_tags).await.unwrap();
+            functions.set_enabled(&id, true).await.unwrap();
+            id
+        }
+
+        async fn upload(&self, name: &str, content: &str, tags: Vec<String>) -> String {
+            store_upload(
+                &self.state,
+                name.to_string(),
+                content.as_bytes().to_vec(),
+                None,
+                tags,
+            )
+            .await
+            .unwrap()
+            .id
+        }
+
+        /// Wait until every job has finished
+        async fn finished_jobs(&self) -> Vec<Job> {
+            let jobs = JobRepo::new(&self.state.db);
+            for _ in 0..100 {
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                let all = jobs
+                    .list(&JobFilter::default(), Sort::default())
+                    .await
+                    .unwrap();
+                if !all.is_empty()
+                    && all
+                        .iter()
+                        .all(|job| matches!(job.status.as_str(), "SUCCESS" | "FAILED"))
+                {
+                    return all;
+                }
+            }
+            panic!("jobs did not finish");
+        }
+    }
+
+    impl Drop for Harness {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.root);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_upload_runs_function_and_registers_output() {
+        let harness = Harness::new("success").await;
+        let raw = harness.tag("raw").await;
+        let clean = harness.tag("clean").await;
+        harness.tag(".txt").await;
+        let function_id = harness
+            .function(
+                "def main(path):\n    pass\n",
+                vec![raw.clone()],
+                vec![clean],
+            )
+            .await;
+
+        let input = store_upload(
+            &harness.state,
+            "data.csv".to_string(),
+            b"a,b\n1,2\n".to_vec(),
+            None,
+            vec![raw],
+        )
+        .await
+        .unwrap();
+        let input_id = input.id;
+        // The response names the queued job, so clients need not search for it
+        assert_eq!(input.jobs.len(), 1);
+        assert_eq!(input.jobs[0].function_id, function_id);
+        let jobs = harness.finished_jobs().await;
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0].id, input.jobs[0].id);
+        assert_eq!(jobs[0].status, "SUCCESS");
+        assert!(jobs[0].queued_seconds.is_some_and(|s| s >= 0.0));
+        assert!(jobs[0].duration_seconds.is_some_and(|s| s >= 0.0));
+        assert_eq!(jobs[0].upload_id, input_id);
+        assert_eq!(jobs[0].output_filenames, ["upper.txt"]);
+
+        let output_id = &jobs[0].output_upload_ids[0];
+        let uploads = UploadRepo::new(&harness.state.db);
+        let output = uploads.get(output_id).await.unwrap().unwrap();
+        let content =
+            std::fs::read_to_string(harness.root.join("uploads").join(&output.filename)).unwrap();
+        assert_eq!(content, "A,B\n1,2\n");
+
+        let tags = TagRepo::new(&harness.state.db)
+            .for_upload(output_id)
+            .await
+            .unwrap();
+        let mut names: Vec<_> = tags.into_iter().map(|t| t.name).collect();
+        names.sort();
+        assert_eq!(names, [".txt", "clean"]);
+
+        let lineage = uploads.lineage(output_id).await.unwrap().unwrap();
+        assert_eq!(lineage.source_upload_id, input_id);
+        assert_eq!(lineage.function_name, "upper");
+        assert!(lineage.success);
+
+        let roots = uploads
+            .list(&UploadFilter {
+                derived: Some(false),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(roots.len(), 1);
+        assert_eq!(roots[0].id, input_id);
+        let outputs = uploads
+            .list(&UploadFilter {
+                produced_by_function: Some(&function_id),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(&outputs[0].id, output_id);
+    }
+
+    #[tokio::test]
+    async fn test_failing_function_registers_error_log() {
+        let harness = Harness::new("failure").await;
+        let raw = harness.tag("raw").await;
+        let clean = harness.tag("clean").await;
+        harness
+            .function(
+                "def main(path):\n    raise ValueError('FAIL')\n",
+                vec![raw.clone()],
+                vec![clean],
+            )
+            .await;
+
+        harness.upload("data.csv", "x\n", vec![raw]).await;
+        let jobs = harness.finished_jobs().await;
+        assert_eq!(jobs.len(), 1);
+
+        let output_id = &jobs[0].output_upload_ids[0];
+        let output = UploadRepo::new(&harness.state.db)
+            .get(output_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(output.original_filename.starts_with("error_"));
+        assert_eq!(output.artifact_type, "error_log");
+        let log =
+            std::fs::read_to_string(harness.root.join("uploads").join(&output.filename)).unwrap();
+        assert!(log.contains("boom"));
+
+        // Error logs do not get the output tags, so they cannot trigger further functions
+        let tags = TagRepo::new(&harness.state.db)
+            .for_upload(output_id)
+            .await
+            .unwrap();
+        assert!(tags.iter().all(|t| t.name != "clean"));
+        let lineage = UploadRepo::new(&harness.state.db)
+            .lineage(output_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!lineage.success);
+
+        let data = UploadRepo::new(&harness.state.db)
+            .list(&UploadFilter {
+                artifact_type: Some("data"),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert!(data.iter().all(|u| &u.id != output_id));
+    }
+
+    #[tokio::test]
+    async fn test_intermediate_outputs_expire_outside_the_pipeline() {
+        let harness = Harness::new("intermediate").await;
+        let raw = harness.tag("raw").await;
+        let clean = harness.tag("clean").await;
+        let function_id = harness
+            .function("print('hi')", vec![raw.clone()], vec![clean])
+            .await;
+        let functions = FunctionRepo::new(&harness.state.db);
+        functions
+            .set_intermediate_outputs(&function_id, &["UPPER.*".to_string()], 2)
+            .await
+            .unwrap();
+
+        let input_id = harness.upload("data.csv", "a,b\n", vec![raw]).await;
+        let jobs = harness.finished_jobs().await;
+        let output_id = &jobs[0].output_upload_ids[0];
+        let uploads = UploadRepo::new(&harness.state.db);
+        let output = uploads.get(output_id).await.unwrap().unwrap();
+        let expires_at = timestamps::to_utc(output.expires_at.as_deref().unwrap()).unwrap();
+        let ttl = expires_at - chrono::Utc::now();
+        assert!(ttl > chrono::Duration::minutes(119) && ttl <= chrono::Duration::hours(2));
+
+        // No output tags, but still in the lineage of the input
+        let tags = TagRepo::new(&harness.state.db)
+            .for_upload(output_id)
+            .await
+            .unwrap();
+        assert!(tags.iter().all(|t| t.name != "clean"));
+        let lineage = uploads.lineage(output_id).await.unwrap().unwrap();
+        assert_eq!(lineage.source_upload_id, input_id);
+
+        let hidden = UploadFilter {
+            intermediate: Some(false),
+            ..Default::default()
+        };
+        let intermediate = UploadFilter {
+            intermediate: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(uploads.list(&hidden).await.unwrap().len(), 1);
+        assert_eq!(&uploads.list(&intermediate).await.unwrap()[0].id, output_id);
+
+        uploads.clear_expiry(output_id).await.unwrap();
+        assert_eq!(uploads.list(&hidden).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_compressed_uploads_read_back_plain() {
+        let harness = Harness::compressing("compression").await;
+        let raw = harness.tag("raw").await;
+        harness
+            .function("def main(path):\n    pass\n", vec![raw.clone()], vec![])
+            .await;
+
+        let content = "a,b\n".to_string() + &"1,2\n".repeat(1000);
+        let input_id = harness.upload("data.csv", &content, vec![raw]).await;
+        let jobs = harness.finished_jobs().await;
+        assert_eq!(jobs[0].status, "SUCCESS");
+
+        // The function saw plain text, and its text output is stored compressed as well
+        let uploads = UploadRepo::new(&harness.state.db);
+        for (id, expected) in [
+            (&input_id, content.clone()),
+            (&jobs[0].output_upload_ids[0], content.to_uppercase()),
+        ] {
+            let upload = uploads.get(id).await.unwrap().unwrap();
+            assert_eq!(upload.compression.as_deref(), Some("zstd"));
+            assert_eq!(upload.file_size, expected.len() as i64);
+            let stored = std::fs::read(harness.root.join("uploads").join(&upload.filename));
+            assert!(stored.unwrap().len() < expected.len() / 10);
+
+            let data = read_upload(&harness.state, &upload.filename, Some("zstd"));
+            assert_eq!(data.await.unwrap(), expected.as_bytes());
+            let plain = plain_upload_path(&harness.state, &upload.filename, Some("zstd"))
+                .await
+                .unwrap();
+            assert_eq!(std::fs::read_to_string(&plain).unwrap(), expected);
+            harness.state.hooks.deleted(&harness.state, &upload).await;
+            assert!(!plain.exists());
+        }
+
+        // Binary content is left as it is
+        let parquet_id = harness
+            .upload("run.parquet", "PAR1\u{15}\u{4}", vec![])
+            .await;
+        let parquet = uploads.get(&parquet_id).await.unwrap().unwrap();
+        assert_eq!(parquet.compression, None);
+    }
+
+    #[tokio::test]
+    async fn test_upload_without_input_tags_runs_nothing() {
+        let harness = Harness::new("no-match").await;
+        let raw = harness.tag("raw").await;
+        harness
+            .function("def main(path):\n    pass\n", vec![raw], Vec::new())
+            .await;
+
+        let id = harness.upload("data.csv", "x\n", Vec::new()).await;
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+        assert!(JobRepo::new(&harness.state.db)
+            .list(&JobFilter::default(), Sort::default())
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Only the extension tag, created on first use
+        let tags = TagRepo::new(&harness.state.db)
+            .for_upload(&id)
+            .await
+            .unwrap();
+        assert_eq!(tags.len(), 1);
+        assert_eq!(tags[0].name, ".csv");
+    }
+
+    #[tokio::test]
+    async fn test_excluded_tags_keep_functions_from_running() {
+        let harness = Harness::new("excluded-tags").await;
+        let raw = harness.tag("raw").await;
+        let processed = harness.tag("processed").await;
+        let function_id = harness
+            .function("def main(path):\n    pass\n", vec![raw.clone()], Vec::new())
+            .await;
+        FunctionRepo::new(&harness.state.db)
+            .set_excluded_tags(&function_id, std::slice::from_ref(&processed))
+            .await
+            .unwrap();
+
+        harness
+            .upload("done.csv", "x\n", vec![raw.clone(), processed])
+            .await;
+        let todo = harness.upload("todo.csv", "x\n", vec![raw]).await;
+        let jobs = harness.finished_jobs().await;
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0].upload_id, todo);
+    }
+
+    #[tokio::test]
+    async fn test_tags_in_use_are_deleted_with_their_usages() {
+        let harness = Harness::new("force-delete-tag").await;
+        let raw = harness.tag("raw").await;
+        let other = harness.tag("other").await;
+        let function_id = harness
+            .function(
+                "def main(path):\n    pass\n",
+                vec![raw.clone()],
+                vec![raw.clone()],
+            )
+            .await;
+        FunctionRepo::new(&harness.state.db)
+            .set_excluded_tags(&function_id, std::slice::from_ref(&raw))
+            .await
+            .unwrap();
+        let upload_id = harness
+            .upload("a.csv", "x\n", vec![raw.clone(), other.clone()])
+            .await;
+        harness.upload("b.csv", "x\n", vec![raw.clone()]).await;
+
+        let tags = TagService::new(&harness.state);
+        let deletion = tags.delete_with_usages(&raw).await.unwrap().unwrap();
+        assert_eq!(deletion.uploads_untagged, 2);
+        assert_eq!(
+            (
+                deletion.input_functions,
+                deletion.output_functions,
+                deletion.excluded_functions
+            ),
+            (1, 1, 1)
+        );
+        assert!(tags.delete_with_usages(&raw).await.unwrap().is_none());
+
+        let left: Vec<String> = TagRepo::new(&harness.state.db)
+            .for_upload(&upload_id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|tag| tag.id)
+            .collect();
+        assert!(left.contains(&other) && !left.contains(&raw));
+    }
+
+    #[tokio::test]
+    async fn test_runs_are_estimated_from_earlier_ones() {
+        let harness = Harness::new("estimates").await;
+        let raw = harness.tag("raw").await;
+        let script =
+            "def main(path):\n    (path.parent / 'copy.txt').write_text(path.read_text())\n";
+        let function_id = harness
+            .function(script, vec![raw.clone()], Vec::new())
+            .await;
+        assert_eq!(
+            estimate_run(&harness.state, &function_id, 100)
+                .await
+                .unwrap(),
+            None
+        );
+
+        harness.upload("a.csv", "v\n1\n", vec![raw]).await;
+        harness.finished_jobs().await;
+        let estimate = estimate_run(&harness.state, &function_id, 100)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(estimate.based_on_runs, 1);
+        assert_eq!(estimate.output_bytes, 4); // one run: the same as it made
+        assert!(!estimate.scaled_to_input);
+
+        // Only jobs still waiting for a slot are given one
+        let mut jobs = JobRepo::new(&harness.state.db)
+            .list(&JobFilter::default(), Sort::default())
+            .await
+            .unwrap();
+        add_estimates(&harness.state, &mut jobs).await.unwrap();
+        assert!(jobs[0].estimate.is_none());
+        jobs[0].status = "SUBMITTED".to_string();
+        add_estimates(&harness.state, &mut jobs).await.unwrap();
+        assert_eq!(jobs[0].estimate.as_ref().unwrap().input_bytes, 4);
+    }
+
+    #[tokio::test]
+    async fn test_trial_runs_see_only_their_slice() {
+        let harness = Harness::new("slices").await;
+        let raw = harness.tag("raw").await;
+        let function_id = harness
+            .function("def main(path):\n    pass\n", vec![raw], Vec::new())
+            .await;
+        let upload_id = harness
+            .upload("data.csv", "a,b\n1,2\n3,4\n5,6\n", Vec::new())
+            .await;
+
+        let rows = InputSlice::Rows { start: 1, end: 3 };
+        let job = run_function_on_slice(&harness.state, &upload_id, &function_id, rows)
+            .await
+            .unwrap()
+            .unwrap();
+        let jobs = harness.finished_jobs().await;
+        assert_eq!(jobs[0].id, job.id);
+        assert_eq!(jobs[0].status, "SUCCESS");
+        assert_eq!(jobs[0].input_slice, Some(rows));
+        let output = UploadRepo::new(&harness.state.db)
+            .get(&jobs[0].output_upload_ids[0])
+            .await
+            .unwrap()
+            .unwrap();
+        let content =
+            std::fs::read_to_string(harness.root.join("uploads").join(&output.filename)).unwrap();
+        assert_eq!(content, "A,B\n3,4\n5,6\n");
+
+        let bytes = InputSlice::Bytes { start: 4, end: 8 };
+        run_function_on_slice(&harness.state, &upload_id, &function_id, bytes)
+            .await
+            .unwrap()
+            .unwrap();
+        let jobs = harness.finished_jobs().await;
+        let job = jobs
+            .iter()
+            .find(|job| job.input_slice == Some(bytes))
+            .unwrap();
+        let output = UploadRepo::new(&harness.state.db)
+            .get(&job.output_upload_ids[0])
+            .await
+            .unwrap()
+            .unwrap();
+        let content =
+            std::fs::read_to_string(harness.root.join("uploads").join(&output.filename)).unwrap();
+        assert_eq!(content, "1,2\n");
+    }
+
+    #[tokio::test]
+    async fn test_outputs_left_in_output_dir_are_collected() {
+        let harness = Harness::new("legacy-outputs").await;
+        let raw = harness.tag("raw").await;
+        harness
+            .function(
+                "# LEGACY: writes to OUTPUT_DIR\n",
+                vec![raw.clone()],
+                Vec::new(),
+            )
+            .await;
+        harness.upload("data.txt", "abc\n", vec![raw]).await;
+
+        let jobs = harness.finished_jobs().await;
+        assert_eq!(jobs[0].status, "SUCCESS");
+        assert_eq!(jobs[0].output_filenames, ["upper.txt"]);
+        let output = UploadRepo::new(&harness.state.db)
+            .get(&jobs[0].output_upload_ids[0])
+            .await
+            .unwrap()
+            .unwrap();
+        let content =
+            std::fs::read_to_string(harness.root.join("uploads").join(&output.filename)).unwrap();
+        assert_eq!(content, "ABC\n");
+    }
+
+    #[tokio::test]
+    async fn test_uploads_filter_by_tags_and_name() {
+        let harness = Harness::new("filters").await;
+        let raw = harness.tag("raw").await;
+        let lab = harness.tag("lab-a").await;
+        let both = harness
+            .upload("Run_1.csv", "x\n", vec![raw.clone(), lab.clone()])
+            .await;
+        harness.upload("run_2.csv", "x\n", vec![raw.clone()]).await;
+        harness.upload("notes.txt", "x\n", vec![lab.clone()]).await;
+
+        let uploads = UploadRepo::new(&harness.state.db);
+        let ids = |found: Vec<StoredUpload>| found.into_iter().map(|u| u.id).collect::<Vec<_>>();
+        let tagged = [raw.clone(), lab.clone(), raw.clone()];
+        let filter = UploadFilter {
+            tag_ids: &tagged,
+            ..Default::default()
+        };
+        assert_eq!(ids(uploads.list(&filter).await.unwrap()), vec![both]);
+
+        let names = ["raw".to_string(), ".csv".to_string()];
+        let filter = UploadFilter {
+            tag_names: &names,
+            filename_contains: Some("RUN_"),
+            ..Default::default()
+        };
+        assert_eq!(uploads.count(&filter).await.unwrap(), 2);
+        let unknown = ["nope".to_string()];
+        let filter = UploadFilter {
+            tag_names: &unknown,
+            ..Default::default()
+        };
+        assert_eq!(uploads.count(&filter).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_search_ranks_filenames_and_follows_tags() {
+        let harness = Harness::new("search").await;
+        let probe = harness.tag("probe").await;
+        let by_name = harness.upload("probe_run.csv", "x\n", Vec::new()).await;
+        let by_tag = harness
+            .upload("run_2.csv", "x\n", vec![probe.clone()])
+            .await;
+        harness.upload("notes.txt", "x\n", Vec::new()).await;
+
+        let uploads = UploadRepo::new(&harness.state.db);
+        let search_hiding = |query: &str, hidden: Vec<String>| {
+            let expression = crate::search::match_expression(query).unwrap();
+            let uploads = &uploads;
+            async move {
+                uploads
+                    .search(&expression, &hidden, 10)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|(upload, _)| upload.id)
+                    .collect::<Vec<_>>()
+            }
+        };
+        let search = |query: &str| search_hiding(query, Vec::new());
+        assert_eq!(search("prob").await, [by_name.clone(), by_tag.clone()]);
+        assert_eq!(
+            search_hiding("prob", vec![probe.clone()]).await,
+            vec![by_name.clone()]
+        );
+
+        TagRepo::new(&harness.state.db)
+            .rename(&probe, "sensor")
+            .await
+            .unwrap();
+        assert_eq!(search("probe").await, [by_name]);
+        assert_eq!(search("sensor run").await, vec![by_tag.clone()]);
+
+        TagRepo::new(&harness.state.db)
+            .untag_upload(&by_tag, &probe)
+            .await
+            .unwrap();
+        assert!(search("sensor").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_content_search_finds_lines() {
+        let harness = Harness::new("content-search").await;
+        let restricted = harness.tag("restricted").await;
+        let csv = harness
+            .upload(
+                "log.csv",
+                "serial,temp\nSN-00A12345,21.5\nSN-00B2,19.0\n",
+                Vec::new(),
+            )
+            .await;
+        harness
+            .upload(
+                "other.json",
+                "{\"serial\": \"SN-00B2\"}\n",
+                vec![restricted.clone()],
+            )
+            .await;
+
+        let hits = search_contents(&harness.state, "sn-00a1", &[], 10)
+            .await
+            .unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].0.id, csv);
+        assert_eq!(hits[0].1.len(), 1);
+        assert_eq!(hits[0].1[0].line, 2);
+
+        let hits = search_contents(&harness.state, "SN-00B2", &[], 10)
+            .await
+            .unwrap();
+        assert_eq!(hits.len(), 2);
+        let hits = search_contents(&harness.state, "SN-00B2", &[restricted], 10)
+            .await
+            .unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].0.id, csv);
+
+        UploadRepo::new(&harness.state.db)
+            .delete(&csv)
+            .await
+            .unwrap();
+        let hits = search_contents(&harness.state, "SN-00B2", &[], 10)
+            .await
+            .unwrap();
+        assert_eq!(hits.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_quick_function_runs_its_query() {
+        let harness = Harness::new("quick-functions").await;
+        let raw = harness.tag("raw").await;
+        let functions = FunctionRepo::new(&harness.state.db);
+        for (name, expression) in [
+            ("Hot wells", "SELECT well FROM data WHERE od > 1"),
+            ("Broken", "SELECT missing FROM data"),
+        ] {
+            let id = uuid::Uuid::new_v4().to_string();
+            functions
+                .insert(&NewFunction {
+                    id: &id,
+                    name,
+                    script_filename: "",
+                    function_type: "transform",
+                    executor: "local",
+                    trigger_conditions: &[],
+                    created_at: &timestamps::now(),
+                    expression: Some(expression),
+                })
+                .await
+                .unwrap();
+            functions
+                .set_input_tags(&id, std::slice::from_ref(&raw))
+                .await
+                .unwrap();
+            functions.set_enabled(&id, true).await.unwrap();
+        }
+        harness
+            .upload("plate.csv", "well,od\nA1,0.5\nA2,2.5\n", vec![raw])
+            .await;
+
+        let jobs = harness.finished_jobs().await;
+        let job = |name: &str| {
+            jobs.iter()
+                .find(|job| job.function_name.as_deref() == Some(name))
+                .unwrap()
+        };
+        assert_eq!(job("Hot wells").status, "SUCCESS");
+        assert_eq!(job("Hot wells").output_filenames, ["plate_hot_wells.csv"]);
+        let output = UploadRepo::new(&harness.state.db)
+            .get(&job("Hot wells").output_upload_ids[0])
+            .await
+            .unwrap()
+            .unwrap();
+        let content =
+            std::fs::read_to_string(harness.root.join("uploads").join(&output.filename)).unwrap();
+        assert_eq!(content, "well\nA2\n");
+        // A failing query leaves an error log, like a failing script
+        let log = UploadRepo::new(&harness.state.db)
+            .get(&job("Broken").output_upload_ids[0])
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(log.artifact_type, "error_log");
+    }
+
+    #[tokio::test]
+    async fn test_preview_runs_a_function_on_a_sample() {
+        let harness = Harness::new("function-preview").await;
+        let functions = FunctionRepo::new(&harness.state.db);
+        let id = uuid::Uuid::new_v4().to_string();
+        functions
+            .insert(&NewFunction {
+                id: &id,
+                name: "Hot wells",
+                script_filename: "",
+                function_type: "transform",
+                executor: "local",
+                trigger_conditions: &[],
+                created_at: &timestamps::now(),
+                expression: Some("SELECT well FROM data WHERE od > 1"),
+            })
+            .await
+            .unwrap();
+        let function = functions.get(&id).await.unwrap().unwrap();
+        let upload_id = harness
+            .upload("plate.csv", "well,od\nA1,0.5\nA2,2.5\nA3,3.5\n", Vec::new())
+            .await;
+        let upload = UploadRepo::new(&harness.state.db)
+            .get(&upload_id)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let head = InputSlice::Rows { start: 0, end: 2 };
+        let preview = preview_function(&harness.state, &function, &upload, head)
+            .await
+            .unwrap();
+        assert_eq!(preview.output.as_deref(), Some("plate_hot_wells.csv"));
+        let table = preview.preview.unwrap();
+        assert_eq!(table.headers, ["well"]);
+        assert_eq!(table.rows.len(), 1);
+        assert!(table.rows[0][0].contains("A2"));
+
+        let sample = InputSlice::Sample {
+            rows: 2,
+            seed: Some(7),
+        };
+        let preview = preview_function(&harness.state, &function, &upload, sample)
+            .await
+            .unwrap();
+        assert!(preview.preview.unwrap().rows.len() <= 2);
+        // Nothing is recorded or kept
+        assert!(JobRepo::new(&harness.state.db)
+            .list(&JobFilter::default(), Sort::default())
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            std::fs::read_dir(harness.state.executor.output_dir())
+                .unwrap()
+                .count(),
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_storage_stats_break_down_uploads() {
+        let harness = Harness::new("storage-stats").await;
+        let raw = harness.tag("raw").await;
+        harness.tag("unused").await;
+        harness.upload("a.csv", "x,y\n1,2\n", vec![raw]).await;
+        harness.upload("b.csv", "x,y\n", Vec::new()).await;
+        harness.upload("notes.txt", "hello\n", Vec::new()).await;
+
+        let stats = storage_stats(&harness.state).await.unwrap();
+        assert_eq!(stats.upload_count, 3);
+        assert_eq!(stats.used_bytes, 8 + 4 + 6);
+        assert_eq!(stats.on_disk_bytes, 8 + 4 + 6);
+        let csv = &stats.by_extension[0];
+        assert_eq!(csv.extension.as_deref(), Some(".csv"));
+        assert_eq!((csv.upload_count, csv.used_bytes), (2, 12));
+        let tag = |name: &str| stats.by_tag.iter().find(|t| t.name == name).unwrap();
+        assert_eq!((tag("raw").upload_count, tag("raw").used_bytes), (1, 8));
+        assert_eq!(
+            (tag("unused").upload_count, tag("unused").used_bytes),
+            (0, 0)
+        );
+        assert!(stats.disk.total_bytes >= stats.disk.available_bytes);
+    }
+
+    #[tokio::test]
+    async fn test_reload_applies_config_file() {
+        let harness = Harness::new("reload").await;
+        let config_file = harness.root.join("config.json");
+        assert!(reload_settings(&harness.state).is_err()); // no file yet
+
+        std::fs::write(
+            &config_file,
+            r##"{"max_concurrent_jobs": 4, "storage_quota_mb": 1, "system_tag_color": "#123456"}"##,
+        )
+        .unwrap();
+        assert_eq!(
+            reload_settings(&harness.state).unwrap(),
+            [
+                "max_concurrent_jobs",
+                "storage_quota_mb",
+                "system_tag_color"
+            ]
+        );
+        let settings = harness.state.settings();
+        assert_eq!(settings.storage_quota_bytes, Some(1024 * 1024));
+        assert_eq!(settings.tag_policy.system_color, "#123456");
+        assert_eq!(harness.state.job_slots.summary().slots, 4);
+        assert!(reload_settings(&harness.state).unwrap().is_empty());
+
+        // An invalid file changes nothing; settings it leaves out fall back to the command line
+        std::fs::write(&config_file, r#"{"max_concurrent_jobs": 0}"#).unwrap();
+        assert!(reload_settings(&harness.state).is_err());
+        assert_eq!(harness.state.job_slots.summary().slots, 4);
+        std::fs::write(&config_file, "{}").unwrap();
+        reload_settings(&harness.state).unwrap();
+        assert_eq!(harness.state.settings().storage_quota_bytes, None);
+        assert_eq!(harness.state.job_slots.summary().slots, 2);
+    }
+
+    #[tokio::test]
+    async fn test_extension_tags_follow_settings() {
+        let harness = Harness::new("extension-tags").await;
+        let tags = TagRepo::new(&harness.state.db);
+        let tag_names = |id: String| {
+            let tags = &tags;
+            async move {
+                let found = tags.for_upload(&id).await.unwrap();
+                found.into_iter().map(|tag| tag.name).collect::<Vec<_>>()
+            }
+        };
+        let changes = serde_json::json!({
+            "extension_tag_allowlist": [".CSV"],
+            "extension_tag_color": "#abcdef"
+        });
+        assert_eq!(
+            change_settings(&harness.state, changes.as_object().unwrap()).unwrap(),
+            ["extension_tag_allowlist", "extension_tag_color"]
+        );
+        let csv = harness.upload("run.csv", "a\n1\n", Vec::new()).await;
+        assert_eq!(tag_names(csv).await, [".csv"]);
+        let txt = harness.upload("notes.txt", "x\n", Vec::new()).await;
+        assert!(tag_names(txt).await.is_empty());
+        let csv_tag = tags.id_by_name(".csv").await.unwrap().unwrap();
+        assert_eq!(tags.get(&csv_tag).await.unwrap().unwrap().color, "#abcdef");
+
+        let off = serde_json::json!({ "extension_tags": false });
+        change_settings(&harness.state, off.as_object().unwrap()).unwrap();
+        let csv = harness.upload("run_2.csv", "a\n1\n", Vec::new()).await;
+        assert!(tag_names(csv).await.is_empty());
+        let invalid = serde_json::json!({ "extension_tag_color": "blue" });
+        assert!(change_settings(&harness.state, invalid.as_object().unwrap()).is_err());
+        assert!(!harness.state.settings().values.extension_tags);
+    }
+
+    #[tokio::test]
+    async fn test_failing_function_is_quarantined() {
+        let harness = Harness::new("function-health").await;
+        let raw = harness.tag("raw").await;
+        let id = harness
+            .function("raise ValueError('FAIL')\n", vec![raw.clone()], Vec::new())
+            .await;
+        let functions = FunctionRepo::new(&harness.state.db);
+        let quarantined = || async {
+            // The check runs right after the job is marked finished
+            for _ in 0..20 {
+                let function = functions.get(&id).await.unwrap().unwrap();
+                if function.quarantined_at.is_some() {
+                    return Some(function);
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            }
+            None
+        };
+
+        for name in ["a.csv", "b.csv"] {
+            harness.upload(name, "x\n", vec![raw.clone()]).await;
+            harness.finished_jobs().await;
+        }
+        assert!(quarantined().await.is_none());
+
+        harness.upload("c.csv", "x\n", vec![raw.clone()]).await;
+        harness.finished_jobs().await;
+        let function = quarantined().await.unwrap();
+        assert!(!function.enabled);
+        assert_eq!(
+            function.quarantine_reason.as_deref(),
+            Some("The last 3 runs failed")
+        );
+        let alerts: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM notifications WHERE kind = 'function_quarantined'",
+        )
+        .fetch_one(&harness.state.db)
+        .await
+        .unwrap();
+        assert_eq!(alerts, 1);
+
+        // Enabled again, earlier failures no longer count
+        functions.set_enabled(&id, true).await.unwrap();
+        functions.release(&id).await.unwrap();
+        harness.upload("d.csv", "x\n", vec![raw]).await;
+        harness.finished_jobs().await;
+        assert!(quarantined().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_annotated_failures_leave_the_failure_rate() {
+        let harness = Harness::new("job-annotations").await;
+        let raw = harness.tag("raw").await;
+        harness
+            .function("raise ValueError('FAIL')\n", vec![raw.clone()], Vec::new())
+            .await;
+        harness.upload("a.csv", "x\n", vec![raw.clone()]).await;
+        harness.upload("b.csv", "x\n", vec![raw]).await;
+        let jobs = harness.finished_jobs().await;
+
+        let repo = JobRepo::new(&harness.state.db);
+        repo.add_annotation(&jobs[0].id, "flaky", Some("instrument glitch"), None)
+            .await
+            .unwrap();
+        let flaky = repo
+            .list(
+                &JobFilter {
+                    label: Some("flaky"),
+                    ..Default::default()
+                },
+                Sort::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(flaky.len(), 1);
+        assert_eq!(flaky[0].labels, ["flaky"]);
+
+        let outcomes = repo.outcomes(None, None, SHUTDOWN_MESSAGE).await.unwrap();
+        let stats = crate::job_stats::summarize(outcomes, vec!["flaky".to_string()]);
+        let function = &stats.functions[0];
+        assert_eq!((function.counts.runs, function.counts.failed), (2, 2));
+        assert_eq!(function.counts.excluded, 1);
+        assert_eq!(function.counts.failure_rate, Some(1.0));
+        assert_eq!(function.failures_by_label["flaky"], 1);
+        assert_eq!(function.unlabeled_failures, 1);
+    }
+
+    #[tokio::test]
+    async fn test_lineage_and_pipeline_diagrams() {
+        let harness = Harness::new("diagrams").await;
+        let raw = harness.tag("raw").await;
+        let clean = harness.tag("clean").await;
+        harness
+            .function(
+                "def main(path):\n    pass\n",
+                vec![raw.clone()],
+                vec![clean],
+            )
+            .await;
+        let input_id = harness.upload("data.csv", "a\n1\n", vec![raw]).await;
+        let jobs = harness.finished_jobs().await;
+        let output_id = &jobs[0].output_upload_ids[0];
+
+        // The same graph from either end, highlighting the upload asked for
+        let from_input = lineage_diagram(&harness.state, &input_id)
+            .await
+            .unwrap()
+            .unwrap()
+            .to_mermaid();
+        assert!(from_input.contains("  n0[/\"data.csv\"/]\n  n1[/\"upper.txt\"/]\n"));
+        assert!(from_input.contains("  n0 -->|\"upper\"| n1\n"));
+        assert!(from_input.contains("  style n0 fill:#fde68a\n"));
+        let from_output = lineage_diagram(&harness.state, output_id)
+            .await
+            .unwrap()
+            .unwrap()
+            .to_dot("lineage");
+        assert!(from_output.contains("  n0 [label=\"upper.txt\", shape=note, style=filled"));
+        assert!(from_output.contains("  n1 -> n0 [label=\"upper\"];\n"));
+        assert!(lineage_diagram(&harness.state, "missing")
+            .await
+            .unwrap()
+            .is_none());
+
+        let pipeline = pipeline_diagram(&harness.state).await.unwrap().to_mermaid();
+        assert!(pipeline.contains("  n0[\"upper\"]\n  n1([\"raw\"])\n  n2([\"clean\"])\n"));
+        assert!(pipeline.contains("  n1 --> n0\n  n0 --> n2\n"));
+    }
+
+    #[tokio::test]
+    async fn test_protected_uploads_are_not_deleted() {
+        let harness = Harness::new("protected").await;
+        let id = harness
+            .upload("calibration.csv", "a\n1\n", Vec::new())
+            .await;
+        let uploads = UploadRepo::new(&harness.state.db);
+
+        uploads.set_protected(&id, true).await.unwrap();
+        assert!(uploads.get(&id).await.unwrap().unwrap().protected);
+        assert!(!uploads.delete(&id).await.unwrap());
+        assert!(uploads.get(&id).await.unwrap().is_some());
+
+        uploads.set_protected(&id, false).await.unwrap();
+        assert!(uploads.delete(&id).await.unwrap());
+        assert!(uploads.get(&id).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_share_links_count_access_and_go_with_their_upload() {
+        let harness = Harness::new("shares").await;
+        let id = harness.upload("plate.csv", "a\n1\n", Vec::new()).await;
+        let shares = ShareRepo::new(&harness.state.db);
+        let now = timestamps::now();
+        shares
+            .insert("t1", &id, Some("reviewer"), &now, None)
+            .await
+            .unwrap();
+        shares.insert("t2", &id, None, &now, None).await.unwrap();
+
+        shares.record_access("t1", false, &now).await.unwrap();
+        shares.record_access("t1", true, &now).await.unwrap();
+        let link = shares.get("t1").await.unwrap().unwrap();
+        assert_eq!((link.access_count, link.download_count), (2, 1));
+        assert_eq!(link.last_accessed_at.as_deref(), Some(now.as_str()));
+
+        assert!(!shares.revoke("other", "t1", &now).await.unwrap());
+        assert!(shares.revoke(&id, "t1", &now).await.unwrap());
+        assert!(!shares.revoke(&id, "t1", &now).await.unwrap());
+        assert_eq!(shares.list_for_upload(&id).await.unwrap().len(), 2);
+
+        UploadRepo::new(&harness.state.db)
+            .delete(&id)
+            .await
+            .unwrap();
+        assert!(shares.get("t2").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_chain_of_custody() {
+        let harness = Harness::new("custody").await;
+        let raw = harness.tag("raw").await;
+        harness
+            .function("def main(path):\n    pass\n", vec![raw.clone()], vec![])
+            .await;
+        let id = harness.upload("sample.csv", "a\n1\n", vec![raw]).await;
+        let origin = RequestOrigin {
+            user: Some("alice".to_string()),
+            client_ip: Some("192.0.2.1".to_string()),
+            ..Default::default()
+        };
+        record_upload_origin(&harness.state, &id, "api", None, &origin, Some("sig")).await;
+        let jobs = harness.finished_jobs().await;
+        record_custody_event(&harness.state, &id, "protected", None, &origin).await;
+
+        let upload = UploadRepo::new(&harness.state.db)
+            .get(&id)
+            .await
+            .unwrap()
+            .unwrap();
+        let custody = chain_of_custody(&harness.state, upload).await.unwrap();
+        assert_eq!(custody.origin.unwrap().signature.as_deref(), Some("sig"));
+        let actions: Vec<_> = custody.events.iter().map(|e| e.action.as_str()).collect();
+        assert_eq!(actions, ["uploaded", "processed", "protected"]);
+        assert_eq!(custody.events[0].actor.as_deref(), Some("alice"));
+        assert_eq!(custody.events[1].job_id.as_ref(), Some(&jobs[0].id));
+
+        // Outputs have no origin; they were derived
+        let output = &jobs[0].output_upload_ids[0];
+        let output = UploadRepo::new(&harness.state.db)
+            .get(output)
+            .await
+            .unwrap()
+            .unwrap();
+        let custody = chain_of_custody(&harness.state, output).await.unwrap();
+        assert!(custody.origin.is_none());
+        assert_eq!(custody.events[0].action, "derived");
+        assert_eq!(custody.produced_from.unwrap().source_upload_id, id);
+    }
+
+    #[tokio::test]
+    async fn test_image_metadata_is_read_from_stored_images() {
+        let harness = Harness::new("image-metadata").await;
+        let mut tiff = std::io::Cursor::new(Vec::new());
+        image::GrayImage::new(12, 5)
+            .write_to(&mut tiff, image::ImageFormat::Tiff)
+            .unwrap();
+        let upload = store_upload(
+            &harness.state,
+            "capture.tif".to_string(),
+            tiff.into_inner(),
+            None,
+            vec![],
+        )
+        .await
+        .unwrap();
+        let upload = UploadRepo::new(&harness.state.db)
+            .get(&upload.id)
+            .await
+            .unwrap()
+            .unwrap();
+        // Read by the hook, or here if the hook is not done yet
+        let metadata = image_metadata(&harness.state, &upload)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (metadata.format.as_str(), metadata.width, metadata.height),
+            ("tiff", 12, 5)
+        );
+        assert_eq!(metadata.exif["ResolutionUnit"], 1);
+
+        // Anything else has none
+        let csv = harness.upload("sample.csv", "a\n1\n", vec![]).await;
+        let csv = UploadRepo::new(&harness.state.db).get(&csv).await.unwrap();
+        assert_eq!(
+            image_metadata(&harness.state, &csv.unwrap()).await,
+            Ok(None)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_datasets_group_uploads() {
+        let harness = Harness::new("datasets").await;
+        let first = harness.upload("sweep_01.csv", "v\n1\n", vec![]).await;
+        let second = harness.upload("sweep_02.csv", "v\n22\n", vec![]).await;
+        let datasets = DatasetRepo::new(&harness.state.db);
+        datasets
+            .insert("set", "sweep", Some("Bias sweep"), &timestamps::now())
+            .await
+            .unwrap();
+
+        let ids = [first.clone(), second.clone(), first.clone()];
+        let added = datasets
+            .add_uploads("set", &ids, &timestamps::now())
+            .await
+            .unwrap();
+        assert_eq!(added, vec![first.clone(), second.clone()]);
+        let dataset = datasets.get("set").await.unwrap().unwrap();
+        assert_eq!((dataset.upload_count, dataset.total_bytes), (2, 9));
+
+        // Members go with their upload, and only leave the dataset when removed
+        assert!(datasets
+            .remove_upload("set", &first, &timestamps::now())
+            .await
+            .unwrap());
+        assert!(!datasets
+            .remove_upload("set", &first, &timestamps::now())
+            .await
+            .unwrap());
+        UploadRepo::new(&harness.state.db)
+            .delete(&second)
+            .await
+            .unwrap();
+        assert!(datasets.upload_ids("set").await.unwrap().is_empty());
+
+        assert!(datasets.delete("set").await.unwrap());
+        assert!(datasets.list().await.unwrap().is_empty());
+        let upload = UploadRepo::new(&harness.state.db).get(&first).await;
+        assert!(upload.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_dataset_stats_cover_its_uploads() {
+        let harness = Harness::new("dataset-stats").await;
+        let raw = harness.tag("raw").await;
+        let first = harness
+            .upload("sweep_01.csv", "v\n1\n", vec![raw.clone()])
+            .await;
+        let second = harness.upload("notes.txt", "hello\n", vec![]).await;
+        harness.upload("other.csv", "v\n3\n", vec![raw]).await;
+        let datasets = DatasetRepo::new(&harness.state.db);
+        datasets
+            .insert("set", "sweep", None, &timestamps::now())
+            .await
+            .unwrap();
+        datasets
+            .add_uploads("set", &[first, second], &timestamps::now())
+            .await
+            .unwrap();
+
+        let dataset = datasets.get("set").await.unwrap().unwrap();
+        let stats = dataset_stats(&harness.state, &dataset).await.unwrap();
+        assert_eq!((stats.upload_count, stats.total_bytes), (2, 10));
+        assert_eq!(stats.derived_count, 0);
+        assert!(stats.first_created_at <= stats.last_created_at);
+        let extensions: Vec<_> = stats
+            .by_extension
+            .iter()
+            .map(|e| (e.extension.as_deref(), e.upload_count))
+            .collect();
+        assert_eq!(extensions, [(Some(".txt"), 1), (Some(".csv"), 1)]);
+        let raw_usage = stats.by_tag.iter().find(|t| t.name == "raw").unwrap();
+        assert_eq!((raw_usage.upload_count, raw_usage.used_bytes), (1, 4));
+    }
+
+    #[tokio::test]
+    async fn test_uploads_and_database_are_mirrored() {
+        let harness = Harness::mirroring("mirror").await;
+        let state = &harness.state;
+        let mirror = harness.root.join("mirror");
+        let id = harness.upload("run.csv", "a\n1\n", vec![]).await;
+        let upload = UploadRepo::new(&state.db).get(&id).await.unwrap().unwrap();
+        let replication = ReplicationRepo::new(&state.db);
+
+        // Copied in the background by the hook
+        let mut status = None;
+        for _ in 0..50 {
+            status = replication.get(&id).await.unwrap().map(|r| r.status);
+            if status.as_deref() == Some("mirrored") {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert_eq!(status.as_deref(), Some("mirrored"));
+        let copy = mirror.join("uploads").join(&upload.filename);
+        assert_eq!(std::fs::read_to_string(&copy).unwrap(), "a\n1\n");
+
+        // A failed copy is retried by the next sync, which also snapshots the database
+        std::fs::remove_file(&copy).unwrap();
+        replication
+            .set(&id, "failed", Some("disk full"), &timestamps::now())
+            .await
+            .unwrap();
+        assert_eq!(mirror_status(state).await.unwrap().unwrap().failed, 1);
+        let sync = sync_mirror(state).await.unwrap();
+        assert_eq!((sync.mirrored, sync.failed), (1, 0));
+        assert!(sync.database_mirrored);
+        assert!(copy.exists());
+        let status = mirror_status(state).await.unwrap().unwrap();
+        assert_eq!((status.mirrored, status.failed), (1, 0));
+        assert!(status.database_mirrored_at.is_some());
+
+        let snapshot =
+            sqlx::SqlitePool::connect(&format!("sqlite:{}", mirror.join("datalab.db").display()))
+                .await
+                .unwrap();
+        assert!(UploadRepo::new(&snapshot).get(&id).await.unwrap().is_some());
+
+        // Deleted uploads leave the mirror too
+        state.hooks.deleted(state, &upload).await;
+        assert!(!copy.exists());
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_snapshot_is_recorded_and_restored() {
+        let harness = Harness::new("snapshots").await;
+        let raw = harness.tag("raw").await;
+        let clean = harness.tag("clean").await;
+        let script = "def main(path):\n    pass\n";
+        let function_id = harness
+            .function(script, vec![raw.clone()], vec![clean.clone()])
+            .await;
+        let pipeline = current_pipeline(&harness.state, true).await.unwrap();
+        let snapshots = SnapshotRepo::new(&harness.state.db);
+        snapshots
+            .insert("s1", "v1", None, &serde_json::to_string(&pipeline).unwrap())
+            .await
+            .unwrap();
+
+        // Jobs record the active snapshot
+        harness.upload("data.csv", "a\n1\n", vec![raw]).await;
+        let jobs = harness.finished_jobs().await;
+        assert_eq!(jobs[0].snapshot_id.as_deref(), Some("s1"));
+        assert_eq!(jobs[0].snapshot_label.as_deref(), Some("v1"));
+
+        // Edit the script and the wiring, and add a function under the old name
+        let functions = FunctionRepo::new(&harness.state.db);
+        let scripts = harness.root.join("scripts");
+        std::fs::write(scripts.join("edited.py"), "raise ValueError('FAIL')\n").unwrap();
+        functions
+            .set_script(&function_id, "edited.py")
+            .await
+            .unwrap();
+        functions.set_output_tags(&function_id, &[]).await.unwrap();
+        functions.rename(&function_id, "normalize").await.unwrap();
+        let added = harness.function(script, vec![clean], vec![]).await;
+        let changes = diff(
+            &pipeline,
+            &current_pipeline(&harness.state, false).await.unwrap(),
+        );
+        assert_eq!(changes.functions_added, ["upper"]);
+        assert_eq!(
+            changes.functions_changed[0].fields,
+            ["name", "script", "output_tags"]
+        );
+
+        // The new function holds a name the snapshot needs
+        assert_eq!(
+            restore_conflicts(&harness.state, &pipeline).await.unwrap(),
+            ["upper"]
+        );
+        functions.rename(&added, "fit").await.unwrap();
+        let disabled = restore_pipeline(&harness.state, &pipeline).await.unwrap();
+        assert_eq!(disabled, ["fit"]);
+
+        let restored = functions.get(&function_id).await.unwrap().unwrap();
+        assert_eq!(restored.name, "upper");
+        assert_eq!(
+            std::fs::read_to_string(scripts.join(&restored.script_filename)).unwrap(),
+            script
+        );
+        assert_eq!(
+            functions.output_tags(&function_id).await.unwrap()[0].name,
+            "clean"
+        );
+        assert!(!functions.get(&added).await.unwrap().unwrap().enabled);
+        let after = diff(
+            &pipeline,
+            &current_pipeline(&harness.state, false).await.unwrap(),
+        );
+        assert!(after.functions_changed.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_release_freezes_its_uploads() {
+        let harness = Harness::new("releases").await;
+        let data = harness.upload("data.csv", "a\n1\n", Vec::new()).await;
+        let clash = harness.upload("manifest.json", "{}", Vec::new()).await;
+        let uploads = UploadRepo::new(&harness.state.db);
+        let stored = vec![
+            uploads.get(&data).await.unwrap().unwrap(),
+            uploads.get(&clash).await.unwrap().unwrap(),
+        ];
+
+        let files = release_files(&harness.state, &stored).await.unwrap();
+        assert_eq!(files[1].name, "manifest (2).json"); // the bundle's own manifest keeps its name
+        assert_eq!(
+            sha256sums(&files),
+            format!(
+                "{}  data.csv\n{}  manifest (2).json\n",
+                sha256_hex(b"a\n1\n"),
+                sha256_hex(b"{}")
+            )
+        );
+        let releases = ReleaseRepo::new(&harness.state.db);
+        releases
+            .insert(
+                &NewRelease {
+                    id: "r1",
+                    name: "plates-v1",
+                    identifier: None,
+                    description: None,
+                    selection: "{}",
+                    checksum: &sha256_hex(sha256sums(&files).as_bytes()),
+                    created_at: &timestamps::now(),
+                },
+                &files,
+            )
+            .await
+            .unwrap();
+        assert!(uploads.get(&data).await.unwrap().unwrap().protected);
+        assert_eq!(
+            releases.names_for_upload(&data).await.unwrap(),
+            ["plates-v1"]
+        );
+        assert_eq!(releases.get("r1").await.unwrap().unwrap().file_count, 2);
+
+        // An identifier is recorded once
+        assert!(releases
+            .set_identifier("r1", "10.5281/zenodo.1")
+            .await
+            .unwrap());
+        assert!(!releases
+            .set_identifier("r1", "10.5281/zenodo.2")
+            .await
+            .unwrap());
+
+        let (mismatched, missing) = verify_release(&harness.state, &files).await.unwrap();
+        assert!(mismatched.is_empty() && missing.is_empty());
+        let path = plain_upload_path(&harness.state, &stored[0].filename, None)
+            .await
+            .unwrap();
+        std::fs::write(path, "a\n2\n").unwrap();
+        let (mismatched, _) = verify_release(&harness.state, &files).await.unwrap();
+        assert_eq!(mismatched, ["data.csv"]);
+    }
+
+    #[tokio::test]
+    async fn test_uploads_sort_by_name_and_size() {
+        let harness = Harness::new("sorting").await;
+        harness.upload("b.txt", "xxx\n", Vec::new()).await;
+        harness.upload("C.txt", "x\n", Vec::new()).await;
+        harness.upload("a.txt", "xx\n", Vec::new()).await;
+
+        let uploads = &UploadRepo::new(&harness.state.db);
+        let names = |sort| async move {
+            uploads
+                .page(&UploadFilter::default(), sort, None, 0)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|u| u.original_filename)
+                .collect::<Vec<_>>()
+        };
+        let sort = |key, order| Sort { key, order };
+        assert_eq!(
+            names(sort(SortKey::Name, None)).await,
+            ["a.txt", "b.txt", "C.txt"]
+        );
+        assert_eq!(
+            names(sort(SortKey::Size, None)).await,
+            ["b.txt", "a.txt", "C.txt"]
+        );
+        assert_eq!(
+            names(sort(SortKey::Size, Some(SortOrder::Asc))).await,
+            ["C.txt", "a.txt", "b.txt"]
+        );
+    }
+
+    /// Remembers the uploads it saw created
+    struct RecordingHook(Arc<Mutex<Vec<String>>>);
+
+    #[async_trait::async_trait]
+    impl UploadHook for RecordingHook {
+        fn name(&self) -> &'static str {
+            "recording"
+        }
+
+        async fn on_created(&self, _state: &Arc<AppState>, upload: &StoredUpload) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(upload.original_filename.clone());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hooks_see_uploads_and_outputs() {
+        let created = Arc::new(Mutex::new(Vec::new()));
+        let hooks = UploadHooks::builtin(None, None).register(RecordingHook(created.clone()));
+        let harness = Harness::with_hooks("hooks", hooks).await;
+        let raw = harness.tag("raw").await;
+        harness
+            .function("def main(path):\n    pass\n", vec![raw.clone()], Vec::new())
+            .await;
+
+        harness.upload("data.csv", "a\n", vec![raw]).await;
+        let jobs = harness.finished_jobs().await;
+        assert_eq!(*created.lock().unwrap(), ["data.csv", "upper.txt"]);
+
+        // The built-in extension hook ran first and created the output's tag on first use
+        let tags = TagRepo::new(&harness.state.db)
+            .for_upload(&jobs[0].output_upload_ids[0])
+            .await
+            .unwrap();
+        assert_eq!(tags.len(), 1);
+        assert_eq!(tags[0].name, ".txt");
+    }
+
+    #[tokio::test]
+    async fn test_flagged_uploads_are_quarantined_until_released() {
+        // `grep -qv` exits 1 when every line matches, so a file of EICAR lines is "infected"
+        let scanner = Scanner::from_config(Some("grep -qv EICAR"), None);
+        let harness = Harness::with_hooks("quarantine", UploadHooks::builtin(None, scanner)).await;
+        let raw = harness.tag("raw").await;
+        let state = &harness.state;
+
+        let clean = state.hooks.received(state, "data.csv", b"a,b\n").await;
+        assert_eq!(clean, ScanVerdict::Clean);
+        let verdict = state.hooks.received(state, "bad.csv", b"EICAR\n").await;
+        assert!(matches!(verdict, ScanVerdict::Infected(_)));
+
+        let quarantined = quarantine_file(
+            state,
+            "bad.csv",
+            Some("text/csv"),
+            b"EICAR\n",
+            std::slice::from_ref(&raw),
+            &verdict,
+        )
+        .await
+        .unwrap();
+        assert_eq!(quarantined.status, "infected");
+        assert_eq!(list_quarantined(state).await.unwrap().len(), 1);
+        assert!(UploadRepo::new(&state.db)
+            .list(&UploadFilter::default())
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Released as an upload with the tags it was sent with, and gone from quarantine
+        let upload = release_quarantined(state, &quarantined.id)
+            .await
+            .unwrap()
+            .unwrap();
+        let tags = TagRepo::new(&state.db)
+            .for_upload(&upload.id)
+            .await
+            .unwrap();
+        assert!(tags.iter().any(|tag| tag.id == raw));
+        assert!(list_quarantined(state).await.unwrap().is_empty());
+        assert_eq!(
+            std::fs::read_dir(harness.root.join("quarantine"))
+                .unwrap()
+                .count(),
+            0
+        );
+        assert!(release_quarantined(state, &quarantined.id)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_tag_lookups_follow_mutations() {
+        let harness = Harness::new("tags").await;
+        let tags = TagService::new(&harness.state);
+
+        // Concurrent uploads with the same new extension end up with one tag
+        let (a, b) = tokio::join!(
+            tags.ensure(".tif", "#000000"),
+            tags.ensure(".tif", "#000000")
+        );
+        assert_eq!(a.unwrap(), b.unwrap());
+
+        let raw = tags.create("raw", "#000000", None).await.unwrap().id;
+        let found = tags
+            .ids_by_names(&["raw", ".tif", "missing"])
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found["raw"], raw);
+
+        // The cached name must not outlive a rename or delete
+        tags.rename(&raw, "raw-data").await.unwrap();
+        assert_eq!(tags.id_by_name("raw").await.unwrap(), None);
+        assert_eq!(
+            tags.id_by_name("raw-data").await.unwrap(),
+            Some(raw.clone())
+        );
+        tags.delete(&raw).await.unwrap();
+        assert_eq!(tags.id_by_name("raw-data").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_tag_usage_counts_references() {
+        let harness = Harness::new("tag-usage").await;
+        let raw = harness.tag("raw").await;
+        let clean = harness.tag("clean").await;
+        let spare = harness.tag("spare").await;
+        harness
+            .function("print('hi')", vec![raw.clone()], vec![clean.clone()])
+            .await;
+        harness.upload("a.csv", "a\n1\n", vec![spare.clone()]).await;
+        harness.upload("b.csv", "a\n2\n", vec![spare.clone()]).await;
+
+        let usage = TagRepo::new(&harness.state.db)
+            .list_with_usage()
+            .await
+            .unwrap();
+        let counts = |id: &str| {
+            let tag = usage.iter().find(|usage| usage.tag.id == id).unwrap();
+            (
+                tag.upload_count,
+                tag.input_function_count,
+                tag.output_function_count,
+            )
+        };
+        assert_eq!(counts(&raw), (0, 1, 0));
+        assert_eq!(counts(&clean), (0, 0, 1));
+        assert_eq!(counts(&spare), (2, 0, 0));
+    }
+
+    #[tokio::test]
+    async fn test_tag_descriptions_are_kept() {
+        let harness = Harness::new("tag-descriptions").await;
+        let tags = TagRepo::new(&harness.state.db);
+        let validated = tags
+            .create(
+                "validated",
+                "#00ff00",
+                Some("Passed the schema and range checks"),
+            )
+            .await
+            .unwrap();
+        let function = harness
+            .function("print('hi')", vec![validated.id.clone()], Vec::new())
+            .await;
+
+        let input_tags = FunctionRepo::new(&harness.state.db)
+            .input_tags(&function)
+            .await
+            .unwrap();
+        assert_eq!(
+            input_tags[0].description.as_deref(),
+            Some("Passed the schema and range checks")
+        );
+
+        tags.set_description(&validated.id, None).await.unwrap();
+        let usage = tags.list_with_usage().await.unwrap();
+        assert_eq!(usage[0].tag.description, None);
+    }
+}
@@ -0,0 +1,71 @@
+use crate::timestamps;
+use crate::AppState;
+use uuid::Uuid;
+
+/// Record a notification; failures are logged, never surfaced
+pub async fn add_notification(
+    state: &AppState,
+    kind: &str,
+    title: &str,
+    message: Option<&str>,
+    upload_id: Option<&str>,
+    job_id: Option<&str>,
+) {
+    let id = Uuid::new_v4().to_string();
+    let created_at = timestamps::now();
+    if let Err(e) = sqlx::query!(
+        "INSERT INTO notifications (id, kind, title, message, upload_id, job_id, created_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        id,
+        kind,
+        title,
+        message,
+        upload_id,
+        job_id,
+        created_at
+    )
+    .execute(&state.db)
+    .await
+    {
+        tracing::warn!("Failed to record {} notification: {}", kind, e);
+    }
+}
+
+/// Add a notification for a failed job, naming the function and the file it ran on
+pub async fn notify_job_failed(
+    state: &AppState,
+    job_id: &str,
+    upload_id: &str,
+    error_message: &str,
+) {
+    let names = sqlx::query!(
+        r#"SELECT
+            COALESCE(f.name, j.function_id) as "function_name!: String",
+            COALESCE(u.original_filename, j.upload_id) as "upload_filename!: String"
+        FROM jobs j
+        LEFT JOIN uploads u ON j.upload_id = u.id
+        LEFT JOIN functions f ON j.function_id = f.id
+        WHERE j.id = ?"#,
+        job_id
+    )
+    .fetch_optional(&state.db)
+    .await
+    .ok()
+    .flatten();
+    let title = match names {
+        Some(names) => format!(
+            "{} failed on {}",
+            names.function_name, names.upload_filename
+        ),
+        None => "Job failed".to_string(),
+    };
+
+    add_notification(
+        state,
+        "job_failed",
+        &title,
+        Some(error_message),
+        Some(upload_id),
+        Some(job_id),
+    )
+    .await;
+}
@@ -0,0 +1,134 @@
+use crate::models::{QuarantinedUpload, UploadResponse};
+use crate::repos::{QuarantineRepo, StoredQuarantine};
+use crate::scanner::ScanVerdict;
+use crate::services::{add_notification, sha256_hex, store_upload};
+use crate::timestamps;
+use crate::AppState;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Keep a file the scan flagged in the quarantine directory instead of storing it as an
+/// upload, and notify about it. `verdict` must not be `Clean`.
+pub async fn quarantine_file(
+    state: &Arc<AppState>,
+    original_filename: &str,
+    mime_type: Option<&str>,
+    data: &[u8],
+    tag_ids: &[String],
+    verdict: &ScanVerdict,
+) -> Result<QuarantinedUpload, String> {
+    let (status, finding) = match verdict {
+        ScanVerdict::Infected(finding) => ("infected", finding.as_str()),
+        ScanVerdict::Failed(error) => ("scan_failed", error.as_str()),
+        ScanVerdict::Clean => return Err("Clean files are not quarantined".to_string()),
+    };
+    let id = Uuid::new_v4().to_string();
+    let filename = format!("{}_{}", id, original_filename);
+    let path = state.quarantine_dir.join(&filename);
+    tokio::fs::write(&path, data)
+        .await
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+    let stored = StoredQuarantine {
+        id,
+        filename,
+        original_filename: original_filename.to_string(),
+        file_size: data.len() as i64,
+        mime_type: mime_type.map(str::to_string),
+        sha256: sha256_hex(data),
+        status: status.to_string(),
+        finding: finding.to_string(),
+        tag_ids: serde_json::to_string(tag_ids).map_err(|e| e.to_string())?,
+        created_at: timestamps::now(),
+    };
+    QuarantineRepo::new(&state.db)
+        .insert(&stored)
+        .await
+        .map_err(|e| format!("Failed to record quarantined file: {}", e))?;
+
+    let title = match status {
+        "infected" => format!("{} was quarantined: {}", original_filename, finding),
+        _ => format!(
+            "{} was quarantined: it could not be scanned",
+            original_filename
+        ),
+    };
+    add_notification(
+        state,
+        "upload_quarantined",
+        &title,
+        Some(finding),
+        None,
+        None,
+    )
+    .await;
+
+    Ok(stored.into_model())
+}
+
+/// Quarantined files, newest first
+pub async fn list_quarantined(state: &AppState) -> Result<Vec<QuarantinedUpload>, String> {
+    let rows = QuarantineRepo::new(&state.db)
+        .list()
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(rows.into_iter().map(StoredQuarantine::into_model).collect())
+}
+
+pub async fn get_quarantined(
+    state: &AppState,
+    id: &str,
+) -> Result<Option<QuarantinedUpload>, String> {
+    let row = QuarantineRepo::new(&state.db)
+        .get(id)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(row.map(StoredQuarantine::into_model))
+}
+
+/// Store a quarantined file as a normal upload with the tags it was sent with, e.g. after a
+/// false positive; it is not scanned again. None if there is no such file.
+pub async fn release_quarantined(
+    state: &Arc<AppState>,
+    id: &str,
+) -> Result<Option<UploadResponse>, String> {
+    let Some(row) = QuarantineRepo::new(&state.db)
+        .get(id)
+        .await
+        .map_err(|e| e.to_string())?
+    else {
+        return Ok(None);
+    };
+    let path = state.quarantine_dir.join(&row.filename);
+    let data = tokio::fs::read(&path)
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let tag_ids = serde_json::from_str(&row.tag_ids).unwrap_or_default();
+    let upload = store_upload(
+        state,
+        row.original_filename.clone(),
+        data,
+        row.mime_type.clone(),
+        tag_ids,
+    )
+    .await?;
+
+    discard_quarantined(state, id).await?;
+    tracing::info!(
+        "Released {} from quarantine as upload {}",
+        row.original_filename,
+        upload.id
+    );
+    Ok(Some(upload))
+}
+
+/// Delete a quarantined file for good; false if there is no such file
+pub async fn discard_quarantined(state: &AppState, id: &str) -> Result<bool, String> {
+    let quarantine = QuarantineRepo::new(&state.db);
+    let Some(row) = quarantine.get(id).await.map_err(|e| e.to_string())? else {
+        return Ok(false);
+    };
+    quarantine.delete(id).await.map_err(|e| e.to_string())?;
+    let _ = tokio::fs::remove_file(state.quarantine_dir.join(&row.filename)).await;
+    Ok(true)
+}
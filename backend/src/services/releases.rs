@@ -0,0 +1,104 @@
+use super::{read_upload, sha256_hex};
+use crate::archive::unique_entry_names;
+use crate::models::ReleaseFile;
+use crate::repos::{StoredUpload, UploadRepo};
+use crate::AppState;
+
+/// Names the bundle gives its own files; uploads of the same name are renumbered
+pub const MANIFEST_NAME: &str = "manifest.json";
+pub const CHECKSUMS_NAME: &str = "SHA256SUMS";
+
+/// Whether an identifier looks like a DOI: `10.<registrant>/<suffix>`
+pub fn is_doi_like(identifier: &str) -> bool {
+    let Some((prefix, suffix)) = identifier.split_once('/') else {
+        return false;
+    };
+    let Some(registrant) = prefix.strip_prefix("10.") else {
+        return false;
+    };
+    !registrant.is_empty()
+        && registrant.chars().all(|c| c.is_ascii_digit() || c == '.')
+        && !suffix.is_empty()
+        && !suffix.chars().any(char::is_whitespace)
+}
+
+/// The files of a release of these uploads, with the checksums they are frozen with. Uploads
+/// stored before content hashing are hashed now.
+pub async fn release_files(
+    state: &AppState,
+    uploads: &[StoredUpload],
+) -> Result<Vec<ReleaseFile>, String> {
+    let names = unique_entry_names(
+        [MANIFEST_NAME, CHECKSUMS_NAME]
+            .into_iter()
+            .chain(uploads.iter().map(|u| u.original_filename.as_str())),
+    );
+    let mut files = Vec::with_capacity(uploads.len());
+    for (upload, name) in uploads.iter().zip(names.into_iter().skip(2)) {
+        let sha256 = match &upload.sha256 {
+            Some(sha256) => sha256.clone(),
+            None => {
+                let data = read_upload(state, &upload.filename, upload.compression.as_deref())
+                    .await
+                    .map_err(|e| format!("Failed to read {}: {}", upload.original_filename, e))?;
+                sha256_hex(&data)
+            }
+        };
+        files.push(ReleaseFile {
+            upload_id: upload.id.clone(),
+            name,
+            sha256,
+            file_size: upload.file_size,
+        });
+    }
+    Ok(files)
+}
+
+/// The `SHA256SUMS` listing of a release, in the format `sha256sum --check` reads
+pub fn sha256sums(files: &[ReleaseFile]) -> String {
+    files
+        .iter()
+        .map(|file| format!("{}  {}\n", file.sha256, file.name))
+        .collect()
+}
+
+/// Hash the released files again: names of those whose content changed, and of those that
+/// are gone
+pub async fn verify_release(
+    state: &AppState,
+    files: &[ReleaseFile],
+) -> Result<(Vec<String>, Vec<String>), String> {
+    let uploads = UploadRepo::new(&state.db);
+    let (mut mismatched, mut missing) = (Vec::new(), Vec::new());
+    for file in files {
+        let Some(upload) = uploads
+            .get(&file.upload_id)
+            .await
+            .map_err(|e| e.to_string())?
+        else {
+            missing.push(file.name.clone());
+            continue;
+        };
+        match read_upload(state, &upload.filename, upload.compression.as_deref()).await {
+            Ok(data) if sha256_hex(&data) == file.sha256 => {}
+            Ok(_) => mismatched.push(file.name.clone()),
+            Err(_) => missing.push(file.name.clone()),
+        }
+    }
+    Ok((mismatched, missing))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_doi_like() {
+        assert!(is_doi_like("10.5281/zenodo.1234567"));
+        assert!(is_doi_like("10.1000.10/abc-def"));
+        assert!(!is_doi_like("10./x"));
+        assert!(!is_doi_like("11.5281/zenodo"));
+        assert!(!is_doi_like("10.5281/"));
+        assert!(!is_doi_like("10.5281/has space"));
+    }
+}
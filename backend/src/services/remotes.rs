@@ -0,0 +1,159 @@
+use crate::models::{Remote, Upload};
+use crate::AppState;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+
+/// An upload downloaded from a remote, with the remote's record of it
+pub struct RemoteFile {
+    pub upload: Upload,
+    pub source_url: String, // where the content was downloaded from
+    pub mime_type: Option<String>,
+    pub data: Vec<u8>,
+}
+
+pub enum RemoteFetch {
+    Fetched(Box<RemoteFile>),
+    NotFound,
+    TooLarge,
+}
+
+/// The base URL of a remote as it is stored: http(s), without a trailing `/` or `/api`
+pub fn remote_base_url(url: &str) -> Result<String, String> {
+    let parsed = reqwest::Url::parse(url.trim()).map_err(|_| "Invalid URL".to_string())?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err("URL must be an http(s) URL".to_string());
+    }
+    if parsed.query().is_some() || parsed.fragment().is_some() {
+        return Err("URL must not have a query or fragment".to_string());
+    }
+    let base = parsed.as_str().trim_end_matches('/');
+    Ok(base.strip_suffix("/api").unwrap_or(base).to_string())
+}
+
+fn api_url(remote: &Remote, path: &str) -> String {
+    format!("{}/api{}", remote.base_url, path)
+}
+
+fn upload_url(remote: &Remote, upload_id: &str) -> String {
+    api_url(
+        remote,
+        &format!(
+            "/uploads/{}",
+            utf8_percent_encode(upload_id, NON_ALPHANUMERIC)
+        ),
+    )
+}
+
+/// A page of the uploads of a remote, as its `GET /api/uploads` returns it; `query` is passed
+/// on, so it filters and pages as it does there
+pub async fn browse_remote(
+    state: &AppState,
+    remote: &Remote,
+    query: Option<&str>,
+) -> Result<serde_json::Value, String> {
+    let mut url = api_url(remote, "/uploads");
+    if let Some(query) = query.filter(|query| !query.is_empty()) {
+        url = format!("{}?{}", url, query);
+    }
+    let response = state
+        .http
+        .get(&url)
+        .send()
+        .await
+        .map_err(|e| format!("{} cannot be reached: {}", remote.name, e))?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "{} responded with {}",
+            remote.name,
+            response.status()
+        ));
+    }
+    response
+        .json()
+        .await
+        .map_err(|e| format!("{} sent an unexpected answer: {}", remote.name, e))
+}
+
+/// An upload as the remote describes it; None if it has no such upload
+pub async fn remote_upload(
+    state: &AppState,
+    remote: &Remote,
+    upload_id: &str,
+) -> Result<Option<Upload>, String> {
+    let response = state
+        .http
+        .get(upload_url(remote, upload_id))
+        .send()
+        .await
+        .map_err(|e| format!("{} cannot be reached: {}", remote.name, e))?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if !response.status().is_success() {
+        return Err(format!(
+            "{} responded with {}",
+            remote.name,
+            response.status()
+        ));
+    }
+    let upload = response
+        .json()
+        .await
+        .map_err(|e| format!("{} sent an unexpected answer: {}", remote.name, e))?;
+    Ok(Some(upload))
+}
+
+/// Download an upload of a remote, refusing it once it is over `max_bytes`
+pub async fn fetch_remote_upload(
+    state: &AppState,
+    remote: &Remote,
+    upload_id: &str,
+    max_bytes: u64,
+) -> Result<RemoteFetch, String> {
+    let Some(upload) = remote_upload(state, remote, upload_id).await? else {
+        return Ok(RemoteFetch::NotFound);
+    };
+    if upload.file_size as u64 > max_bytes {
+        return Ok(RemoteFetch::TooLarge);
+    }
+
+    let source_url = format!("{}/download", upload_url(remote, upload_id));
+    let mut response = state
+        .http
+        .get(&source_url)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to download {}: {}", source_url, e))?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(RemoteFetch::NotFound); // deleted meanwhile
+    }
+    if !response.status().is_success() {
+        return Err(format!(
+            "{} responded with {}",
+            source_url,
+            response.status()
+        ));
+    }
+    let mime_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.split(';').next().unwrap_or(v).trim().to_string());
+
+    let mut data = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| format!("Failed to download {}: {}", source_url, e))?
+    {
+        if (data.len() + chunk.len()) as u64 > max_bytes {
+            return Ok(RemoteFetch::TooLarge);
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok(RemoteFetch::Fetched(Box::new(RemoteFile {
+        upload,
+        source_url,
+        mime_type,
+        data,
+    })))
+}
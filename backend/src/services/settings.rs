@@ -0,0 +1,50 @@
+use crate::settings::Settings;
+use crate::AppState;
+use serde_json::{Map, Value};
+use std::sync::Arc;
+
+/// Re-read the --config-file and put its settings in effect; returns the names of the ones
+/// that changed. Nothing changes if the file is unreadable or a setting in it is invalid.
+pub fn reload_settings(state: &AppState) -> Result<Vec<String>, String> {
+    let Some(config_file) = &state.config_file else {
+        return Err("No config file to reload (see --config-file)".to_string());
+    };
+    let settings = Settings::load(&state.command_line_settings, Some(config_file))
+        .map_err(|e| format!("Settings not reloaded: {}", e))?;
+    let changed = apply_settings(state, settings);
+    if changed.is_empty() {
+        tracing::info!("🔄 Reloaded {}, nothing changed", config_file.display());
+    } else {
+        tracing::info!(
+            "🔄 Reloaded {}, changed: {}",
+            config_file.display(),
+            changed.join(", ")
+        );
+    }
+    Ok(changed)
+}
+
+/// Put the settings in `changes` (named as in the config file) in effect on top of the current
+/// ones, until the next reload or restart; returns the names of the ones that changed.
+/// Nothing changes if a setting is unknown or invalid.
+pub fn change_settings(
+    state: &AppState,
+    changes: &Map<String, Value>,
+) -> Result<Vec<String>, String> {
+    let values = state.settings().values.overridden_by(changes)?;
+    let changed = apply_settings(state, Settings::new(values)?);
+    if !changed.is_empty() {
+        tracing::info!("🔄 Settings changed: {}", changed.join(", "));
+    }
+    Ok(changed)
+}
+
+// Swap in new settings, resizing the job slots with them
+fn apply_settings(state: &AppState, settings: Settings) -> Vec<String> {
+    let changed = state.settings().values.changed(&settings.values);
+    state
+        .job_slots
+        .set_slots(settings.values.max_concurrent_jobs);
+    *state.settings.write().unwrap() = Arc::new(settings);
+    changed
+}
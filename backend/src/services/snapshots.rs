@@ -0,0 +1,256 @@
+use super::{sha256_hex, TagService};
+use crate::repos::{FunctionRepo, NewFunction, SnapshotRepo, TagRepo};
+use crate::snapshots::{Pipeline, SnapshotFunction, SnapshotTag};
+use crate::{timestamps, AppState};
+use std::collections::{HashMap, HashSet};
+
+/// The pipeline as it is now. With `keep_scripts` the scripts are stored by hash, so that a
+/// snapshot of this pipeline can be restored after they were edited.
+pub async fn current_pipeline(state: &AppState, keep_scripts: bool) -> Result<Pipeline, String> {
+    let functions = FunctionRepo::new(&state.db);
+    let snapshots = SnapshotRepo::new(&state.db);
+    let mut pipeline = Pipeline::default();
+
+    let mut all = functions.list().await.map_err(|e| e.to_string())?;
+    all.sort_by(|a, b| a.name.cmp(&b.name));
+    for function in all {
+        let tag_names = |tags: Vec<crate::models::Tag>| {
+            let mut names: Vec<String> = tags.into_iter().map(|t| t.name).collect();
+            names.sort();
+            names
+        };
+        let input_tags = functions
+            .input_tags(&function.id)
+            .await
+            .map_err(|e| e.to_string())?;
+        let output_tags = functions
+            .output_tags(&function.id)
+            .await
+            .map_err(|e| e.to_string())?;
+        let excluded_tags = functions
+            .excluded_tags(&function.id)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let mut script_sha256 = None;
+        if function.expression.is_none() {
+            let path = state.executor.scripts_dir().join(&function.script_filename);
+            if let Ok(script) = tokio::fs::read_to_string(&path).await {
+                let sha256 = sha256_hex(script.as_bytes());
+                if keep_scripts {
+                    snapshots
+                        .save_script(&sha256, &script)
+                        .await
+                        .map_err(|e| e.to_string())?;
+                }
+                script_sha256 = Some(sha256);
+            }
+        }
+
+        pipeline.functions.push(SnapshotFunction {
+            trigger_conditions: function.conditions(),
+            id: function.id,
+            name: function.name,
+            function_type: function.function_type,
+            executor: function.executor,
+            enabled: function.enabled,
+            expression: function.expression,
+            script_sha256,
+            input_tags: tag_names(input_tags),
+            output_tags: tag_names(output_tags),
+            excluded_tags: tag_names(excluded_tags),
+        });
+    }
+
+    let mut tags = TagRepo::new(&state.db)
+        .list()
+        .await
+        .map_err(|e| e.to_string())?;
+    tags.sort_by(|a, b| a.name.cmp(&b.name));
+    pipeline.tags = tags
+        .into_iter()
+        .map(|t| SnapshotTag {
+            name: t.name,
+            color: t.color,
+        })
+        .collect();
+    Ok(pipeline)
+}
+
+/// Functions outside the snapshot that hold the name of one in it; restoring would clash
+pub async fn restore_conflicts(
+    state: &AppState,
+    pipeline: &Pipeline,
+) -> Result<Vec<String>, String> {
+    let ids: HashSet<&str> = pipeline.functions.iter().map(|f| f.id.as_str()).collect();
+    let names: HashSet<&str> = pipeline.functions.iter().map(|f| f.name.as_str()).collect();
+    let current = FunctionRepo::new(&state.db)
+        .list()
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(current
+        .into_iter()
+        .filter(|f| !ids.contains(f.id.as_str()) && names.contains(f.name.as_str()))
+        .map(|f| f.name)
+        .collect())
+}
+
+/// Put the pipeline back as it was in a snapshot: missing tags and functions are recreated,
+/// the others get their snapshotted settings, scripts and wiring back. Functions created since
+/// are disabled rather than deleted, so their outputs and lineage stay; their names are
+/// returned. Tags created since stay as they are.
+pub async fn restore_pipeline(
+    state: &AppState,
+    pipeline: &Pipeline,
+) -> Result<Vec<String>, String> {
+    let tags = TagService::new(state);
+    let mut tag_ids: HashMap<&str, String> = HashMap::new();
+    for tag in &pipeline.tags {
+        let id = tags
+            .ensure(&tag.name, &tag.color)
+            .await
+            .map_err(|e| e.to_string())?;
+        TagRepo::new(&state.db)
+            .set_color(&id, &tag.color)
+            .await
+            .map_err(|e| e.to_string())?;
+        tag_ids.insert(&tag.name, id);
+    }
+    let ids_of = |names: &[String]| -> Vec<String> {
+        names
+            .iter()
+            .filter_map(|name| tag_ids.get(name.as_str()).cloned())
+            .collect()
+    };
+
+    let functions = FunctionRepo::new(&state.db);
+    let snapshots = SnapshotRepo::new(&state.db);
+    let current: HashMap<String, _> = functions
+        .list()
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|f| (f.id.clone(), f))
+        .collect();
+
+    for function in &pipeline.functions {
+        let existing = current.get(&function.id);
+
+        // Write the script back if it is not the one the function runs now
+        let mut script_filename = existing.map(|f| f.script_filename.clone());
+        if let Some(sha256) = &function.script_sha256 {
+            let current_sha256 = match &script_filename {
+                Some(filename) => tokio::fs::read(state.executor.scripts_dir().join(filename))
+                    .await
+                    .ok()
+                    .map(|script| sha256_hex(&script)),
+                None => None,
+            };
+            if current_sha256.as_ref() != Some(sha256) {
+                let script = snapshots
+                    .script(sha256)
+                    .await
+                    .map_err(|e| e.to_string())?
+                    .ok_or_else(|| format!("The script of {} is missing", function.name))?;
+                let filename = format!(
+                    "{}_{}.py",
+                    timestamps::now().replace([':', '-', '.'], "_"),
+                    function.id
+                );
+                tokio::fs::write(state.executor.scripts_dir().join(&filename), script)
+                    .await
+                    .map_err(|e| format!("Failed to write script: {}", e))?;
+                script_filename = Some(filename);
+            }
+        }
+        let script_filename = script_filename.unwrap_or_default();
+
+        match existing {
+            Some(existing) => {
+                if existing.name != function.name {
+                    functions
+                        .rename(&function.id, &function.name)
+                        .await
+                        .map_err(|e| e.to_string())?;
+                }
+                if existing.script_filename != script_filename {
+                    functions
+                        .set_script(&function.id, &script_filename)
+                        .await
+                        .map_err(|e| e.to_string())?;
+                }
+                if let Some(expression) = &function.expression {
+                    functions
+                        .set_expression(&function.id, expression)
+                        .await
+                        .map_err(|e| e.to_string())?;
+                }
+                functions
+                    .set_function_type(&function.id, &function.function_type)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                functions
+                    .set_executor(&function.id, &function.executor)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                functions
+                    .set_trigger_conditions(&function.id, &function.trigger_conditions)
+                    .await
+                    .map_err(|e| e.to_string())?;
+            }
+            None => {
+                functions
+                    .insert(&NewFunction {
+                        id: &function.id,
+                        name: &function.name,
+                        script_filename: &script_filename,
+                        function_type: &function.function_type,
+                        executor: &function.executor,
+                        trigger_conditions: &function.trigger_conditions,
+                        created_at: &timestamps::now(),
+                        expression: function.expression.as_deref(),
+                    })
+                    .await
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+
+        functions
+            .set_input_tags(&function.id, &ids_of(&function.input_tags))
+            .await
+            .map_err(|e| e.to_string())?;
+        functions
+            .set_output_tags(&function.id, &ids_of(&function.output_tags))
+            .await
+            .map_err(|e| e.to_string())?;
+        functions
+            .set_excluded_tags(&function.id, &ids_of(&function.excluded_tags))
+            .await
+            .map_err(|e| e.to_string())?;
+        functions
+            .set_enabled(&function.id, function.enabled)
+            .await
+            .map_err(|e| e.to_string())?;
+        // Restoring a snapshot in which it was enabled is a sign-off like enabling it by hand
+        if function.enabled && existing.is_some_and(|f| f.quarantined_at.is_some()) {
+            functions
+                .release(&function.id)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+    }
+
+    let restored: HashSet<&str> = pipeline.functions.iter().map(|f| f.id.as_str()).collect();
+    let mut disabled = Vec::new();
+    for function in current.values() {
+        if !restored.contains(function.id.as_str()) && function.enabled {
+            functions
+                .set_enabled(&function.id, false)
+                .await
+                .map_err(|e| e.to_string())?;
+            disabled.push(function.name.clone());
+        }
+    }
+    disabled.sort();
+    Ok(disabled)
+}
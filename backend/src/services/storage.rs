@@ -0,0 +1,187 @@
+use crate::compression::{compress_file, decompress_file, is_compressible, ZSTD};
+use crate::models::{Dataset, DatasetStats, DiskSpace, ExtensionStorage, StorageStats};
+use crate::repos::{DatasetRepo, StoredUpload, TagRepo, UploadFilter, UploadRepo};
+use crate::services::extension_tag_name;
+use crate::AppState;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+/// Compress a file just moved into uploads/ if --compress-uploads is set and its content is
+/// text. Returns the compression to record with the upload; on failure the file stays as
+/// it was.
+pub async fn compress_stored_file(
+    state: &AppState,
+    path: &Path,
+    detected_mime_type: Option<&str>,
+) -> Option<&'static str> {
+    if !state.compress_uploads || !is_compressible(detected_mime_type) {
+        return None;
+    }
+    let path_clone = path.to_path_buf();
+    match tokio::task::spawn_blocking(move || compress_file(&path_clone)).await {
+        Ok(Ok(())) => Some(ZSTD),
+        Ok(Err(e)) => {
+            tracing::warn!("Could not compress {}: {}", path.display(), e);
+            None
+        }
+        Err(_) => None,
+    }
+}
+
+/// The content of an upload as it was uploaded
+pub async fn read_upload(
+    state: &AppState,
+    filename: &str,
+    compression: Option<&str>,
+) -> io::Result<Vec<u8>> {
+    let data = tokio::fs::read(state.executor.uploads_dir().join(filename)).await?;
+    match compression {
+        Some(_) => tokio::task::spawn_blocking(move || zstd::decode_all(data.as_slice()))
+            .await
+            .map_err(io::Error::other)?,
+        None => Ok(data),
+    }
+}
+
+/// A file with the plain content of an upload, for readers that need a path (table
+/// previews, archives, SQL): the stored file itself, or for compressed uploads a copy
+/// decompressed into the cache on first use
+pub async fn plain_upload_path(
+    state: &AppState,
+    filename: &str,
+    compression: Option<&str>,
+) -> io::Result<PathBuf> {
+    let stored = state.executor.uploads_dir().join(filename);
+    if compression.is_none() {
+        return Ok(stored);
+    }
+    let path = state.decompressed_dir.join(filename);
+    if tokio::fs::try_exists(&path).await.unwrap_or(false) {
+        return Ok(path);
+    }
+
+    // Decompress next to the cache entry first, so a concurrent reader never sees half a file
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+    tokio::task::spawn_blocking({
+        let partial = partial.clone();
+        move || decompress_file(&stored, &partial)
+    })
+    .await
+    .map_err(io::Error::other)??;
+    tokio::fs::rename(&partial, &path).await?;
+    Ok(path)
+}
+
+/// Drop the decompressed copy of a deleted upload, if there is one
+pub async fn remove_decompressed(state: &AppState, filename: &str) {
+    let _ = tokio::fs::remove_file(state.decompressed_dir.join(filename)).await;
+}
+
+// Uploads and bytes per extension, largest first
+fn extension_storage(uploads: &[StoredUpload]) -> Vec<ExtensionStorage> {
+    let mut extensions: HashMap<Option<String>, (i64, i64)> = HashMap::new();
+    for upload in uploads {
+        let extension = extension_tag_name(
+            &upload.original_filename,
+            upload.detected_mime_type.as_deref(),
+        );
+        let entry = extensions.entry(extension).or_default();
+        entry.0 += 1;
+        entry.1 += upload.file_size;
+    }
+    let mut by_extension: Vec<ExtensionStorage> = extensions
+        .into_iter()
+        .map(|(extension, (upload_count, used_bytes))| ExtensionStorage {
+            extension,
+            upload_count,
+            used_bytes,
+        })
+        .collect();
+    by_extension.sort_by(|a, b| {
+        b.used_bytes
+            .cmp(&a.used_bytes)
+            .then_with(|| a.extension.cmp(&b.extension))
+    });
+    by_extension
+}
+
+/// The uploads of a dataset by extension and by tag, and when they were made
+pub async fn dataset_stats(state: &AppState, dataset: &Dataset) -> Result<DatasetStats, String> {
+    let datasets = DatasetRepo::new(&state.db);
+    let repo = UploadRepo::new(&state.db);
+    let mut uploads = Vec::new();
+    for id in datasets
+        .upload_ids(&dataset.id)
+        .await
+        .map_err(|e| e.to_string())?
+    {
+        if let Some(upload) = repo.get(&id).await.map_err(|e| e.to_string())? {
+            uploads.push(upload);
+        }
+    }
+    Ok(DatasetStats {
+        dataset_id: dataset.id.clone(),
+        upload_count: uploads.len() as i64,
+        total_bytes: uploads.iter().map(|u| u.file_size).sum(),
+        derived_count: datasets
+            .derived_count(&dataset.id)
+            .await
+            .map_err(|e| e.to_string())?,
+        first_created_at: uploads.iter().map(|u| u.created_at.clone()).min(),
+        last_created_at: uploads.iter().map(|u| u.created_at.clone()).max(),
+        by_extension: extension_storage(&uploads),
+        by_tag: datasets
+            .tag_storage(&dataset.id)
+            .await
+            .map_err(|e| e.to_string())?,
+    })
+}
+
+/// Totals over all uploads, by extension and by tag, and the free space left for more
+pub async fn storage_stats(state: &AppState) -> Result<StorageStats, String> {
+    let uploads = UploadRepo::new(&state.db)
+        .list(&UploadFilter::default())
+        .await
+        .map_err(|e| e.to_string())?;
+    let by_tag = TagRepo::new(&state.db)
+        .storage()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let by_extension = extension_storage(&uploads);
+
+    let uploads_dir = state.executor.uploads_dir().to_path_buf();
+    let filenames: Vec<String> = uploads.iter().map(|u| u.filename.clone()).collect();
+    let (on_disk_bytes, disk) = tokio::task::spawn_blocking(move || {
+        // Deduplicated uploads are hard links to one file, counted once
+        let mut seen = HashSet::new();
+        let on_disk_bytes: u64 = filenames
+            .iter()
+            .filter_map(|name| std::fs::metadata(uploads_dir.join(name)).ok())
+            .filter(|metadata| seen.insert((metadata.dev(), metadata.ino())))
+            .map(|metadata| metadata.len())
+            .sum();
+        let disk = DiskSpace {
+            path: uploads_dir.to_string_lossy().to_string(),
+            total_bytes: fs4::total_space(&uploads_dir)?,
+            available_bytes: fs4::available_space(&uploads_dir)?,
+        };
+        Ok::<_, io::Error>((on_disk_bytes, disk))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| format!("Failed to read disk space: {}", e))?;
+
+    Ok(StorageStats {
+        upload_count: uploads.len() as i64,
+        used_bytes: uploads.iter().map(|u| u.file_size).sum(),
+        on_disk_bytes,
+        by_extension,
+        by_tag,
+        disk,
+    })
+}
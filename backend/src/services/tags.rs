@@ -0,0 +1,108 @@
+use crate::models::{Tag, TagDeletion};
+use crate::repos::TagRepo;
+use crate::AppState;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// Tag names mapped to ids. Every upload and job output looks up its extension tag by name,
+/// while tags themselves rarely change; any mutation through `TagService` clears it.
+#[derive(Default)]
+pub struct TagCache(RwLock<HashMap<String, String>>);
+
+impl TagCache {
+    fn get(&self, name: &str) -> Option<String> {
+        self.0.read().unwrap().get(name).cloned()
+    }
+
+    fn insert(&self, name: String, id: String) {
+        self.0.write().unwrap().insert(name, id);
+    }
+
+    pub fn clear(&self) {
+        self.0.write().unwrap().clear();
+    }
+}
+
+/// Tag lookups by name through the cache, and the tag mutations that invalidate it. Changes
+/// to tags should go through here rather than `TagRepo`, or lookups may return stale ids.
+pub struct TagService<'a> {
+    repo: TagRepo<'a>,
+    cache: &'a TagCache,
+}
+
+impl<'a> TagService<'a> {
+    pub fn new(state: &'a AppState) -> Self {
+        Self {
+            repo: TagRepo::new(&state.db),
+            cache: &state.tag_cache,
+        }
+    }
+
+    /// Ids of the tags with these names; unknown names are left out. Only names missing from
+    /// the cache are looked up, in one query.
+    pub async fn ids_by_names(&self, names: &[&str]) -> sqlx::Result<HashMap<String, String>> {
+        let mut ids = HashMap::new();
+        let mut missing = Vec::new();
+        for name in names {
+            match self.cache.get(name) {
+                Some(id) => {
+                    ids.insert(name.to_string(), id);
+                }
+                None => missing.push(*name),
+            }
+        }
+        if !missing.is_empty() {
+            for (name, id) in self.repo.ids_by_names(&missing).await? {
+                self.cache.insert(name.clone(), id.clone());
+                ids.insert(name, id);
+            }
+        }
+        Ok(ids)
+    }
+
+    pub async fn id_by_name(&self, name: &str) -> sqlx::Result<Option<String>> {
+        Ok(self.ids_by_names(&[name]).await?.remove(name))
+    }
+
+    /// The id of the tag called `name`, creating it with `color` if there is none yet
+    pub async fn ensure(&self, name: &str, color: &str) -> sqlx::Result<String> {
+        if let Some(id) = self.cache.get(name) {
+            return Ok(id);
+        }
+        let id = self.repo.upsert(name, color).await?;
+        self.cache.insert(name.to_string(), id.clone());
+        Ok(id)
+    }
+
+    /// Insert a tag; fails with a UNIQUE constraint error if the name is taken
+    pub async fn create(
+        &self,
+        name: &str,
+        color: &str,
+        description: Option<&str>,
+    ) -> sqlx::Result<Tag> {
+        let tag = self.repo.create(name, color, description).await?;
+        self.cache.insert(tag.name.clone(), tag.id.clone());
+        Ok(tag)
+    }
+
+    pub async fn rename(&self, id: &str, name: &str) -> sqlx::Result<()> {
+        self.repo.rename(id, name).await?;
+        self.cache.clear();
+        Ok(())
+    }
+
+    /// Returns false if there was no such tag
+    pub async fn delete(&self, id: &str) -> sqlx::Result<bool> {
+        let deleted = self.repo.delete(id).await?;
+        self.cache.clear();
+        Ok(deleted)
+    }
+
+    /// Delete a tag even while uploads or functions use it; None if there was no such tag
+    pub async fn delete_with_usages(&self, id: &str) -> sqlx::Result<Option<TagDeletion>> {
+        let deletion = self.repo.delete_with_usages(id).await?;
+        self.cache.clear();
+        Ok(deletion)
+    }
+}
@@ -0,0 +1,69 @@
+use crate::mime_sniff::effective_extension;
+use crate::thumbnails::{
+    is_image_extension, render_thumbnail, thumbnail_path, DEFAULT_THUMBNAIL_SIZE, THUMBNAIL_SIZES,
+};
+use crate::AppState;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// The cached thumbnail of an upload at `size`, rendered first if it is not cached yet
+pub async fn cached_thumbnail(
+    state: &AppState,
+    upload_id: &str,
+    filename: &str,
+    size: u32,
+) -> Result<PathBuf, String> {
+    let path = thumbnail_path(&state.thumbnails_dir, upload_id, size);
+    if tokio::fs::try_exists(&path).await.unwrap_or(false) {
+        return Ok(path);
+    }
+
+    // Decoding full-resolution images is CPU-bound, keep it off the async workers
+    let source = state.executor.uploads_dir().join(filename);
+    let png = tokio::task::spawn_blocking(move || render_thumbnail(&source, size))
+        .await
+        .map_err(|e| e.to_string())??;
+
+    // Write next to the cache entry first, so a concurrent request never reads half a file
+    let partial = path.with_extension("png.partial");
+    tokio::fs::write(&partial, &png)
+        .await
+        .map_err(|e| e.to_string())?;
+    tokio::fs::rename(&partial, &path)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(path)
+}
+
+/// Render the default-size thumbnail of an image upload in the background
+pub fn spawn_thumbnail(
+    state: &Arc<AppState>,
+    upload_id: &str,
+    filename: &str,
+    original_filename: &str,
+    detected_mime_type: Option<&str>,
+) {
+    let extension = effective_extension(original_filename, detected_mime_type).unwrap_or_default();
+    if !is_image_extension(&extension) {
+        return;
+    }
+
+    let state_clone = state.clone();
+    let upload_id = upload_id.to_string();
+    let filename = filename.to_string();
+    state.tasks.spawn("thumbnail", async move {
+        if let Err(e) =
+            cached_thumbnail(&state_clone, &upload_id, &filename, DEFAULT_THUMBNAIL_SIZE).await
+        {
+            tracing::warn!("Could not render thumbnail for upload {}: {}", upload_id, e);
+        }
+    });
+}
+
+/// Drop every cached thumbnail of a deleted upload
+pub async fn remove_thumbnails(state: &AppState, upload_id: &str) {
+    for size in THUMBNAIL_SIZES {
+        let _ =
+            tokio::fs::remove_file(thumbnail_path(&state.thumbnails_dir, upload_id, *size)).await;
+    }
+}
@@ -0,0 +1,331 @@
+use crate::anomalies::{detect_table_anomalies, AnomalyQuery, ANOMALY_TAG};
+use crate::mime_sniff::{detect_mime_type, effective_extension, SNIFF_BYTES};
+use crate::models::UploadResponse;
+use crate::repos::{NewLineage, NewUpload, StoredUpload, TagRepo, UploadRepo};
+use crate::services::{
+    compress_stored_file, enqueue_functions_for_upload, plain_upload_path, TagService,
+};
+use crate::tag_policy::TagPolicy;
+use crate::timestamps;
+use crate::AppState;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::AsyncReadExt;
+use uuid::Uuid;
+
+/// SHA-256 of file content as lowercase hex
+pub fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+/// The same for a file on disk, read in pieces; call from a blocking task
+fn sha256_file(path: &Path) -> std::io::Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// `.csv` for `data.CSV`, `.parquet` for a Parquet file named `data.dat`; None for names
+/// without an extension whose content was not recognised
+pub fn extension_tag_name(filename: &str, detected_mime_type: Option<&str>) -> Option<String> {
+    effective_extension(filename, detected_mime_type).map(|extension| format!(".{}", extension))
+}
+
+/// The extension tag an upload with this name gets under `policy`: None when extension tagging
+/// is off or the extension is not on its allowlist
+pub fn applied_extension_tag(
+    policy: &TagPolicy,
+    filename: &str,
+    detected_mime_type: Option<&str>,
+) -> Option<String> {
+    extension_tag_name(filename, detected_mime_type)
+        .filter(|name| policy.extension_tags.applies_to(name))
+}
+
+/// Find earlier uploads with the same content. With --dedupe-uploads, the freshly written
+/// file is replaced by a hard link to the first of them stored the same way (`compression`),
+/// so the bytes are stored once and deleting either upload leaves the other intact.
+pub async fn find_duplicate_uploads(
+    state: &AppState,
+    sha256: &str,
+    file_path: &Path,
+    compression: Option<&str>,
+) -> Vec<String> {
+    let duplicates = UploadRepo::new(&state.db)
+        .with_sha256(sha256)
+        .await
+        .unwrap_or_default();
+
+    if state.dedupe_uploads {
+        if let Some(original) = duplicates
+            .iter()
+            .find(|d| d.compression.as_deref() == compression)
+        {
+            let original_path = state.executor.uploads_dir().join(&original.filename);
+            let mut linked_path = file_path.as_os_str().to_owned();
+            linked_path.push(".link");
+            // Link next to the file first, so a failed link leaves the written copy in place
+            let linked = match tokio::fs::hard_link(&original_path, &linked_path).await {
+                Ok(()) => tokio::fs::rename(&linked_path, file_path).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = linked {
+                tracing::warn!("Could not deduplicate {}: {}", file_path.display(), e);
+            }
+        }
+    }
+
+    duplicates.into_iter().map(|d| d.id).collect()
+}
+
+/// Save a new file to disk and the database, tag it, and trigger matching functions
+pub async fn store_upload(
+    state: &Arc<AppState>,
+    original_filename: String,
+    file_data: Vec<u8>,
+    mime_type: Option<String>,
+    tag_ids: Vec<String>,
+) -> Result<UploadResponse, String> {
+    let id = Uuid::new_v4().to_string();
+    let filename = format!("{}_{}", id, original_filename);
+    let file_path = state.executor.uploads_dir().join(&filename);
+    let file_size = file_data.len() as i64;
+    let detected_mime_type = detect_mime_type(
+        &file_data[..file_data.len().min(SNIFF_BYTES)],
+        &original_filename,
+    );
+
+    // Save file to disk
+    tokio::fs::write(&file_path, &file_data)
+        .await
+        .map_err(|e| format!("Failed to write {}: {}", file_path.display(), e))?;
+
+    let sha256 = tokio::task::spawn_blocking(move || sha256_hex(&file_data))
+        .await
+        .map_err(|e| e.to_string())?;
+    let written = WrittenFile {
+        id,
+        filename,
+        path: file_path,
+        size: file_size,
+        detected_mime_type,
+        sha256,
+    };
+    record_upload(state, original_filename, written, mime_type, tag_ids).await
+}
+
+/// Store a file already streamed to `staged` in the uploads directory, like `store_upload`.
+/// The staged file is moved into place, so it must be on the same filesystem.
+pub async fn store_upload_file(
+    state: &Arc<AppState>,
+    original_filename: String,
+    staged: &Path,
+    mime_type: Option<String>,
+    tag_ids: Vec<String>,
+) -> Result<UploadResponse, String> {
+    let id = Uuid::new_v4().to_string();
+    let filename = format!("{}_{}", id, original_filename);
+    let file_path = state.executor.uploads_dir().join(&filename);
+    tokio::fs::rename(staged, &file_path)
+        .await
+        .map_err(|e| format!("Failed to write {}: {}", file_path.display(), e))?;
+
+    let read = |e: std::io::Error| format!("Failed to read {}: {}", file_path.display(), e);
+    let file_size = tokio::fs::metadata(&file_path).await.map_err(read)?.len() as i64;
+    let mut head = Vec::with_capacity(SNIFF_BYTES);
+    tokio::fs::File::open(&file_path)
+        .await
+        .map_err(read)?
+        .take(SNIFF_BYTES as u64)
+        .read_to_end(&mut head)
+        .await
+        .map_err(read)?;
+    let detected_mime_type = detect_mime_type(&head, &original_filename);
+
+    let hashed_path = file_path.clone();
+    let sha256 = tokio::task::spawn_blocking(move || sha256_file(&hashed_path))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(read)?;
+    let written = WrittenFile {
+        id,
+        filename,
+        path: file_path,
+        size: file_size,
+        detected_mime_type,
+        sha256,
+    };
+    record_upload(state, original_filename, written, mime_type, tag_ids).await
+}
+
+/// A new upload's file, written to the uploads directory but not yet recorded
+struct WrittenFile {
+    id: String,
+    filename: String,
+    path: PathBuf,
+    size: i64,
+    detected_mime_type: Option<&'static str>,
+    sha256: String,
+}
+
+/// Compress and deduplicate a written file, then record, tag and announce the upload
+async fn record_upload(
+    state: &Arc<AppState>,
+    original_filename: String,
+    written: WrittenFile,
+    mime_type: Option<String>,
+    tag_ids: Vec<String>,
+) -> Result<UploadResponse, String> {
+    let WrittenFile {
+        id,
+        filename,
+        path: file_path,
+        size: file_size,
+        detected_mime_type,
+        sha256,
+    } = written;
+    let created_at = timestamps::now();
+    let compression = compress_stored_file(state, &file_path, detected_mime_type).await;
+    let duplicates = find_duplicate_uploads(state, &sha256, &file_path, compression).await;
+
+    // Save to database
+    UploadRepo::new(&state.db)
+        .insert(&NewUpload {
+            id: &id,
+            filename: &filename,
+            original_filename: &original_filename,
+            file_size,
+            mime_type: mime_type.as_deref(),
+            detected_mime_type,
+            created_at: &created_at,
+            sha256: Some(&sha256),
+            artifact_type: "data",
+            compression,
+            expires_at: None,
+        })
+        .await
+        .map_err(|e| format!("Failed to record upload: {}", e))?;
+
+    // Tag with the user-selected tags; the extension tag and the like come from the hooks
+    let tags = TagRepo::new(&state.db);
+    for tag_id in tag_ids {
+        let _ = tags.tag_upload(&id, &tag_id).await;
+    }
+    let stored = StoredUpload {
+        id: id.clone(),
+        filename: filename.clone(),
+        original_filename: original_filename.clone(),
+        file_size,
+        mime_type: mime_type.clone(),
+        detected_mime_type: detected_mime_type.map(str::to_string),
+        created_at: created_at.clone(),
+        sha256: Some(sha256.clone()),
+        assignee: None,
+        artifact_type: "data".to_string(),
+        compression: compression.map(str::to_string),
+        protected: false,
+        expires_at: None,
+    };
+    state.hooks.created(state, &stored).await;
+
+    // Queue the jobs of matching functions; they run in the background
+    let jobs = enqueue_functions_for_upload(state, &id).await;
+
+    Ok(UploadResponse {
+        id,
+        filename,
+        original_filename,
+        file_size,
+        mime_type,
+        detected_mime_type: detected_mime_type.map(str::to_string),
+        created_at,
+        sha256,
+        duplicate_of: duplicates,
+        jobs,
+    })
+}
+
+/// Save an outlier report next to a tagged table and mark the table if anything was flagged
+pub async fn run_anomaly_check(state: Arc<AppState>, upload_id: String) {
+    let Ok(Some(upload)) = UploadRepo::new(&state.db).get(&upload_id).await else {
+        return;
+    };
+    let extension = effective_extension(
+        &upload.original_filename,
+        upload.detected_mime_type.as_deref(),
+    )
+    .unwrap_or_default();
+    if !matches!(extension.as_str(), "csv" | "parquet") {
+        return;
+    }
+    let Ok(file_path) =
+        plain_upload_path(&state, &upload.filename, upload.compression.as_deref()).await
+    else {
+        return;
+    };
+    let file_path = file_path.to_string_lossy().to_string();
+
+    let query = AnomalyQuery::default();
+    let report = match tokio::task::spawn_blocking({
+        let file_path = file_path.clone();
+        move || detect_table_anomalies(&file_path, &extension, &query).map_err(|e| e.to_string())
+    })
+    .await
+    {
+        Ok(Ok(report)) => report,
+        Ok(Err(e)) => {
+            tracing::warn!("Outlier check failed for {}: {}", file_path, e);
+            return;
+        }
+        Err(_) => return,
+    };
+
+    let Ok(data) = serde_json::to_vec_pretty(&report) else {
+        return;
+    };
+    let original_filename = upload.original_filename;
+    let stem = original_filename
+        .rsplit_once('.')
+        .map_or(original_filename.as_str(), |(stem, _)| stem);
+    let filename = format!("{}_anomalies.json", stem);
+    let Ok(output) = store_upload(
+        &state,
+        filename,
+        data,
+        Some("application/json".to_string()),
+        Vec::new(),
+    )
+    .await
+    else {
+        return;
+    };
+
+    let parameters = serde_json::to_string(&AnomalyQuery::default()).ok();
+    let _ = UploadRepo::new(&state.db)
+        .add_lineage(&NewLineage {
+            output_upload_id: &output.id,
+            source_upload_id: &upload_id,
+            function_id: None,
+            operation: Some("anomalies"),
+            query: parameters.as_deref(),
+            success: true,
+            created_at: &output.created_at,
+        })
+        .await;
+
+    if report.has_anomalies {
+        if let Ok(tag_id) = TagService::new(&state).ensure(ANOMALY_TAG, "#ef4444").await {
+            // red-500
+            let _ = TagRepo::new(&state.db)
+                .tag_upload(&upload_id, &tag_id)
+                .await;
+        }
+    }
+
+    tracing::info!(
+        "Outlier check for upload {}: anomalies={}",
+        upload_id,
+        report.has_anomalies
+    );
+}
@@ -0,0 +1,219 @@
+//! Settings that can change while the server runs: taken from the command line, overridden by
+//! the `--config-file`, and re-read from that file on SIGHUP or `POST /api/admin/reload`. Jobs
+//! that are running keep going; what they do next follows the new settings.
+
+use crate::function_health::HealthPolicy;
+use crate::tag_policy::{ExtensionTagging, TagPolicy};
+use crate::Config;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::path::Path;
+
+/// The reloadable settings, named as in the config file (like the command-line options)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SettingValues {
+    pub max_concurrent_jobs: usize,
+    pub storage_quota_mb: Option<u64>, // null for no quota
+    pub url_max_size_mb: u64,
+    pub url_allowed_types: Vec<String>,
+    pub quarantine_after_failures: usize,
+    pub quarantine_failure_percent: Option<f64>,
+    pub quarantine_window: usize,
+    pub system_tag_color: String,
+    pub system_tag_prefixes: Vec<String>,
+    pub tag_forbidden_chars: String,
+    pub tag_palette: Vec<String>,
+    pub extension_tags: bool,
+    pub extension_tag_allowlist: Vec<String>, // empty for every extension
+    pub extension_tag_color: Option<String>,  // null for the system tag color
+}
+
+impl SettingValues {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            max_concurrent_jobs: config.max_concurrent_jobs,
+            storage_quota_mb: config.storage_quota_mb,
+            url_max_size_mb: config.url_max_size_mb,
+            url_allowed_types: config.url_allowed_types.clone(),
+            quarantine_after_failures: config.quarantine_after_failures,
+            quarantine_failure_percent: config.quarantine_failure_percent,
+            quarantine_window: config.quarantine_window,
+            system_tag_color: config.system_tag_color.clone(),
+            system_tag_prefixes: config.system_tag_prefixes.clone(),
+            tag_forbidden_chars: config.tag_forbidden_chars.clone(),
+            tag_palette: config.tag_palette.clone(),
+            extension_tags: config.extension_tags,
+            extension_tag_allowlist: config.extension_tag_allowlist.clone(),
+            extension_tag_color: config.extension_tag_color.clone(),
+        }
+    }
+
+    fn to_map(&self) -> Map<String, Value> {
+        match serde_json::to_value(self) {
+            Ok(Value::Object(map)) => map,
+            _ => Map::new(),
+        }
+    }
+
+    /// These settings with the ones in `file` in their place; settings the file leaves out
+    /// keep their value
+    pub fn overridden_by(&self, file: &Map<String, Value>) -> Result<Self, String> {
+        let mut values = self.to_map();
+        for (key, value) in file {
+            if !values.contains_key(key) {
+                return Err(format!(
+                    "Unknown setting {}; the config file can set {}",
+                    key,
+                    values.keys().cloned().collect::<Vec<_>>().join(", ")
+                ));
+            }
+            values.insert(key.clone(), value.clone());
+        }
+        serde_json::from_value(Value::Object(values)).map_err(|e| e.to_string())
+    }
+
+    /// Names of the settings whose value differs in `other`
+    pub fn changed(&self, other: &Self) -> Vec<String> {
+        let theirs = other.to_map();
+        self.to_map()
+            .into_iter()
+            .filter(|(key, value)| theirs.get(key) != Some(value))
+            .map(|(key, _)| key)
+            .collect()
+    }
+}
+
+/// Read a config file: a JSON object of settings
+pub fn read_config_file(path: &Path) -> Result<Map<String, Value>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    match serde_json::from_str(&text) {
+        Ok(Value::Object(map)) => Ok(map),
+        Ok(_) => Err(format!("{} must hold a JSON object", path.display())),
+        Err(e) => Err(format!("{} is not valid JSON: {}", path.display(), e)),
+    }
+}
+
+/// The settings in effect, in the form the server uses them
+#[derive(Debug)]
+pub struct Settings {
+    pub values: SettingValues,
+    pub storage_quota_bytes: Option<u64>,
+    pub url_max_bytes: u64,
+    pub function_health: HealthPolicy, // when failing functions are disabled
+    pub tag_policy: TagPolicy,
+}
+
+impl Settings {
+    /// Err says which setting is invalid
+    pub fn new(values: SettingValues) -> Result<Self, String> {
+        if values.max_concurrent_jobs == 0 {
+            return Err("max_concurrent_jobs must be at least 1".to_string());
+        }
+        let mut tag_policy = TagPolicy::new(
+            &values.system_tag_color,
+            values.system_tag_prefixes.clone(),
+            &values.tag_forbidden_chars,
+            values.tag_palette.clone(),
+        )?;
+        tag_policy.extension_tags = ExtensionTagging::new(
+            values.extension_tags,
+            &values.extension_tag_allowlist,
+            values
+                .extension_tag_color
+                .as_deref()
+                .unwrap_or(&values.system_tag_color),
+        )?;
+        Ok(Self {
+            storage_quota_bytes: values.storage_quota_mb.map(|mb| mb * 1024 * 1024),
+            url_max_bytes: values.url_max_size_mb * 1024 * 1024,
+            function_health: HealthPolicy {
+                max_consecutive_failures: Some(values.quarantine_after_failures),
+                max_failure_percent: values.quarantine_failure_percent,
+                window: values.quarantine_window,
+            },
+            tag_policy,
+            values,
+        })
+    }
+
+    /// The `command_line` settings, overridden by `config_file` if there is one
+    pub fn load(command_line: &SettingValues, config_file: Option<&Path>) -> Result<Self, String> {
+        match config_file {
+            Some(path) => Self::new(command_line.overridden_by(&read_config_file(path)?)?),
+            None => Self::new(command_line.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn file(value: Value) -> Map<String, Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn test_config_file_overrides_settings() {
+        let base = SettingValues::from_config(&Config {
+            storage_quota_mb: Some(100),
+            ..Config::default()
+        });
+        let values = base
+            .overridden_by(&file(json!({
+                "max_concurrent_jobs": 2,
+                "storage_quota_mb": null,
+                "tag_forbidden_chars": "~,"
+            })))
+            .unwrap();
+        assert_eq!(values.max_concurrent_jobs, 2);
+        assert_eq!(values.storage_quota_mb, None);
+        assert_eq!(values.url_max_size_mb, base.url_max_size_mb);
+        assert_eq!(
+            base.changed(&values),
+            [
+                "max_concurrent_jobs",
+                "storage_quota_mb",
+                "tag_forbidden_chars"
+            ]
+        );
+
+        let unknown = base.overridden_by(&file(json!({ "port": 9000 })));
+        assert!(unknown.unwrap_err().starts_with("Unknown setting port"));
+        assert!(base
+            .overridden_by(&file(json!({ "max_concurrent_jobs": "many" })))
+            .is_err());
+    }
+
+    #[test]
+    fn test_invalid_settings_are_refused() {
+        let values = SettingValues::from_config(&Config::default());
+        let settings = Settings::new(values.clone()).unwrap();
+        assert_eq!(settings.url_max_bytes, 1024 * 1024 * 1024);
+        assert_eq!(settings.function_health.max_consecutive_failures, Some(10));
+
+        let no_jobs = SettingValues {
+            max_concurrent_jobs: 0,
+            ..values.clone()
+        };
+        assert!(Settings::new(no_jobs).is_err());
+        let bad_color = SettingValues {
+            system_tag_color: "gray".to_string(),
+            ..values.clone()
+        };
+        assert!(Settings::new(bad_color).is_err());
+        let bad_palette = SettingValues {
+            tag_palette: vec!["#ef4444".to_string(), "red".to_string()],
+            ..values.clone()
+        };
+        assert!(Settings::new(bad_palette).is_err());
+        let bad_extension_color = SettingValues {
+            extension_tag_color: Some("gray".to_string()),
+            ..values
+        };
+        assert!(Settings::new(bad_extension_color).is_err());
+    }
+}
@@ -0,0 +1,182 @@
+//! Snapshots of the pipeline as a whole: every function with the hash of its script, every tag,
+//! and the triggers and tags wiring them together. Snapshots are compared by function ID, so a
+//! renamed function shows up as changed rather than as removed and added; tags by name.
+
+use crate::triggers::TriggerCondition;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SnapshotTag {
+    pub name: String,
+    pub color: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SnapshotFunction {
+    pub id: String,
+    pub name: String,
+    pub function_type: String,
+    pub executor: String,
+    pub enabled: bool,
+    pub trigger_conditions: Vec<TriggerCondition>,
+    pub expression: Option<String>,    // quick functions
+    pub script_sha256: Option<String>, // script functions; the content is kept by hash
+    pub input_tags: Vec<String>,       // tag names, sorted
+    pub output_tags: Vec<String>,
+    #[serde(default)] // snapshots taken before functions could exclude tags have none
+    pub excluded_tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Pipeline {
+    pub functions: Vec<SnapshotFunction>, // by name
+    pub tags: Vec<SnapshotTag>,           // by name
+}
+
+#[derive(Debug, Serialize)]
+pub struct FunctionChange {
+    pub function_id: String,
+    pub name: String,              // as in the newer pipeline
+    pub fields: Vec<&'static str>, // e.g. `script`, `enabled`, `input_tags`
+}
+
+/// What changed from one pipeline to another
+#[derive(Debug, Default, Serialize)]
+pub struct PipelineDiff {
+    pub functions_added: Vec<String>, // names
+    pub functions_removed: Vec<String>,
+    pub functions_changed: Vec<FunctionChange>,
+    pub tags_added: Vec<String>,
+    pub tags_removed: Vec<String>,
+    pub tags_recolored: Vec<String>,
+}
+
+fn changed_fields(from: &SnapshotFunction, to: &SnapshotFunction) -> Vec<&'static str> {
+    let mut fields = Vec::new();
+    let mut check = |field, changed| {
+        if changed {
+            fields.push(field);
+        }
+    };
+    check("name", from.name != to.name);
+    check("function_type", from.function_type != to.function_type);
+    check("executor", from.executor != to.executor);
+    check("enabled", from.enabled != to.enabled);
+    check(
+        "trigger_conditions",
+        from.trigger_conditions != to.trigger_conditions,
+    );
+    check("expression", from.expression != to.expression);
+    check("script", from.script_sha256 != to.script_sha256);
+    check("input_tags", from.input_tags != to.input_tags);
+    check("output_tags", from.output_tags != to.output_tags);
+    check("excluded_tags", from.excluded_tags != to.excluded_tags);
+    fields
+}
+
+pub fn diff(from: &Pipeline, to: &Pipeline) -> PipelineDiff {
+    let mut result = PipelineDiff::default();
+
+    let old: HashMap<&str, &SnapshotFunction> =
+        from.functions.iter().map(|f| (f.id.as_str(), f)).collect();
+    let new: HashMap<&str, &SnapshotFunction> =
+        to.functions.iter().map(|f| (f.id.as_str(), f)).collect();
+    for function in &to.functions {
+        match old.get(function.id.as_str()) {
+            None => result.functions_added.push(function.name.clone()),
+            Some(previous) => {
+                let fields = changed_fields(previous, function);
+                if !fields.is_empty() {
+                    result.functions_changed.push(FunctionChange {
+                        function_id: function.id.clone(),
+                        name: function.name.clone(),
+                        fields,
+                    });
+                }
+            }
+        }
+    }
+    for function in &from.functions {
+        if !new.contains_key(function.id.as_str()) {
+            result.functions_removed.push(function.name.clone());
+        }
+    }
+
+    let old_tags: HashMap<&str, &str> = from
+        .tags
+        .iter()
+        .map(|t| (t.name.as_str(), t.color.as_str()))
+        .collect();
+    let new_tags: BTreeSet<&str> = to.tags.iter().map(|t| t.name.as_str()).collect();
+    for tag in &to.tags {
+        match old_tags.get(tag.name.as_str()) {
+            None => result.tags_added.push(tag.name.clone()),
+            Some(color) if *color != tag.color => result.tags_recolored.push(tag.name.clone()),
+            Some(_) => {}
+        }
+    }
+    for tag in &from.tags {
+        if !new_tags.contains(tag.name.as_str()) {
+            result.tags_removed.push(tag.name.clone());
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn function(id: &str, name: &str, script: &str) -> SnapshotFunction {
+        SnapshotFunction {
+            id: id.to_string(),
+            name: name.to_string(),
+            function_type: "transform".to_string(),
+            executor: "local".to_string(),
+            enabled: true,
+            trigger_conditions: Vec::new(),
+            expression: None,
+            script_sha256: Some(script.to_string()),
+            input_tags: vec!["raw".to_string()],
+            output_tags: vec!["clean".to_string()],
+            excluded_tags: Vec::new(),
+        }
+    }
+
+    fn tag(name: &str, color: &str) -> SnapshotTag {
+        SnapshotTag {
+            name: name.to_string(),
+            color: color.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let from = Pipeline {
+            functions: vec![function("1", "normalize", "a"), function("2", "plot", "b")],
+            tags: vec![tag("raw", "#000000"), tag("old", "#000000")],
+        };
+        let mut renamed = function("1", "normalize_v2", "c");
+        renamed.enabled = false;
+        let to = Pipeline {
+            functions: vec![renamed, function("3", "fit", "d")],
+            tags: vec![tag("raw", "#ffffff"), tag("new", "#000000")],
+        };
+
+        let diff = diff(&from, &to);
+        assert_eq!(diff.functions_added, ["fit"]);
+        assert_eq!(diff.functions_removed, ["plot"]);
+        assert_eq!(diff.functions_changed.len(), 1);
+        assert_eq!(diff.functions_changed[0].function_id, "1");
+        assert_eq!(
+            diff.functions_changed[0].fields,
+            ["name", "enabled", "script"]
+        );
+        assert_eq!(diff.tags_added, ["new"]);
+        assert_eq!(diff.tags_removed, ["old"]);
+        assert_eq!(diff.tags_recolored, ["raw"]);
+        let unchanged = super::diff(&to, &to);
+        assert!(unchanged.functions_changed.is_empty() && unchanged.tags_recolored.is_empty());
+    }
+}
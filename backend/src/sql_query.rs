@@ -0,0 +1,335 @@
+use crate::table_parser::{scan_table, TableSlice};
+use polars::prelude::*;
+use polars::sql::SQLContext;
+use serde::{Deserialize, Serialize};
+use sqlparser::ast::{Query, Statement, TableFactor, Visit, Visitor};
+use sqlparser::dialect::{Dialect, DuckDbDialect, GenericDialect};
+use sqlparser::parser::Parser;
+use std::collections::{HashMap, HashSet};
+use std::ops::ControlFlow;
+use std::path::Path;
+
+const DEFAULT_SQL_ROWS: usize = 1000;
+const MAX_SQL_ROWS: usize = 10_000;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SqlEngine {
+    #[default]
+    Polars,
+    Duckdb,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SqlRequest {
+    pub query: String,
+    pub tables: HashMap<String, String>, // table name used in the query -> upload id
+    #[serde(default)]
+    pub engine: SqlEngine,
+    pub limit: Option<usize>,
+}
+
+impl SqlRequest {
+    pub fn limit(&self) -> usize {
+        self.limit.unwrap_or(DEFAULT_SQL_ROWS).min(MAX_SQL_ROWS)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SqlResult {
+    pub engine: SqlEngine,
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+    pub row_count: usize,
+    pub truncated: bool,
+}
+
+/// The rows a query returned, before they are turned into strings for a JSON result
+pub struct SqlRows {
+    pub engine: SqlEngine,
+    pub slice: TableSlice,
+    pub truncated: bool,
+}
+
+impl SqlRows {
+    pub fn into_result(self) -> SqlResult {
+        let preview = self.slice.into_preview();
+        SqlResult {
+            engine: self.engine,
+            headers: preview.headers,
+            rows: preview.rows,
+            row_count: preview.total_rows,
+            truncated: self.truncated,
+        }
+    }
+}
+
+/// An upload registered under a table name for the duration of one query
+pub struct SqlTable {
+    pub name: String,
+    pub file_path: String,
+    pub file_type: String,
+}
+
+pub fn is_valid_table_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Rejects anything but plain reads from the registered tables (and CTEs), so a query
+/// can never reach files through table functions like `read_csv('/etc/passwd')`
+struct ReadOnlyGuard {
+    allowed: HashSet<String>,
+}
+
+impl Visitor for ReadOnlyGuard {
+    type Break = String;
+
+    fn pre_visit_query(&mut self, query: &Query) -> ControlFlow<String> {
+        if let Some(with) = &query.with {
+            for cte in &with.cte_tables {
+                self.allowed.insert(cte.alias.name.value.to_lowercase());
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn pre_visit_table_factor(&mut self, table_factor: &TableFactor) -> ControlFlow<String> {
+        match table_factor {
+            TableFactor::Table { args: None, .. }
+            | TableFactor::Derived { .. }
+            | TableFactor::NestedJoin { .. }
+            | TableFactor::UNNEST { .. } => ControlFlow::Continue(()),
+            TableFactor::Table { name, .. } => {
+                ControlFlow::Break(format!("Table functions are not allowed: {}", name))
+            }
+            other => ControlFlow::Break(format!("Unsupported table source: {}", other)),
+        }
+    }
+
+    fn pre_visit_relation(&mut self, relation: &sqlparser::ast::ObjectName) -> ControlFlow<String> {
+        let name = relation.to_string().trim_matches('"').to_lowercase();
+        if self.allowed.contains(&name) {
+            ControlFlow::Continue(())
+        } else {
+            ControlFlow::Break(format!("Unknown table: {}", relation))
+        }
+    }
+}
+
+/// Check that the query is a single SELECT over the given table names
+pub fn validate_sql(query: &str, tables: &[&str], engine: SqlEngine) -> Result<(), String> {
+    let dialect: &dyn Dialect = match engine {
+        SqlEngine::Polars => &GenericDialect {},
+        SqlEngine::Duckdb => &DuckDbDialect {},
+    };
+    let statements = Parser::parse_sql(dialect, query).map_err(|e| e.to_string())?;
+
+    let statement = match statements.as_slice() {
+        [statement] => statement,
+        [] => return Err("Empty SQL query".to_string()),
+        _ => return Err("Only a single statement is allowed".to_string()),
+    };
+    if !matches!(statement, Statement::Query(_)) {
+        return Err("Only SELECT queries are allowed".to_string());
+    }
+
+    let mut guard = ReadOnlyGuard {
+        allowed: tables.iter().map(|t| t.to_lowercase()).collect(),
+    };
+    match statement.visit(&mut guard) {
+        ControlFlow::Break(message) => Err(message),
+        ControlFlow::Continue(()) => Ok(()),
+    }
+}
+
+/// Run a validated query, returning at most `limit` rows
+pub fn run_sql(
+    query: &str,
+    tables: &[SqlTable],
+    engine: SqlEngine,
+    limit: usize,
+    duckdb_bin: Option<&Path>,
+) -> Result<SqlRows, Box<dyn std::error::Error>> {
+    // Fetch one extra row to detect truncation
+    let df = match engine {
+        SqlEngine::Polars => run_polars(query, tables, limit + 1)?,
+        SqlEngine::Duckdb => {
+            let bin = duckdb_bin.ok_or("DuckDB engine is not enabled")?;
+            run_duckdb(bin, query, tables, limit + 1)?
+        }
+    };
+
+    Ok(SqlRows {
+        engine,
+        truncated: df.height() > limit,
+        slice: TableSlice {
+            total_rows: df.height().min(limit),
+            df: df.head(Some(limit)),
+            file_type: "sql".to_string(),
+        },
+    })
+}
+
+/// The table name a quick function's query reads its input from
+pub const QUICK_FUNCTION_TABLE: &str = "data";
+
+/// Check a quick function's query: a single SELECT over its input
+pub fn validate_quick_function(expression: &str) -> Result<(), String> {
+    validate_sql(expression, &[QUICK_FUNCTION_TABLE], SqlEngine::Polars)
+}
+
+/// Run a validated quick function over a whole table; the result keeps the input's format
+pub fn transform_table(
+    expression: &str,
+    file_path: &str,
+    file_type: &str,
+) -> Result<TableSlice, Box<dyn std::error::Error>> {
+    let mut context = SQLContext::new();
+    context.register(QUICK_FUNCTION_TABLE, scan_table(file_path, file_type)?);
+    let df = context.execute(expression)?.collect()?;
+    Ok(TableSlice {
+        total_rows: df.height(),
+        df,
+        file_type: file_type.to_string(),
+    })
+}
+
+fn run_polars(
+    query: &str,
+    tables: &[SqlTable],
+    limit: usize,
+) -> Result<DataFrame, Box<dyn std::error::Error>> {
+    let mut context = SQLContext::new();
+    for table in tables {
+        context.register(&table.name, scan_table(&table.file_path, &table.file_type)?);
+    }
+    Ok(context.execute(query)?.limit(limit as IdxSize).collect()?)
+}
+
+/// Run the query through the DuckDB CLI. External access is switched off after the
+/// uploads are attached as views, limited to exactly those files (needs DuckDB >= 1.1.3).
+fn run_duckdb(
+    duckdb_bin: &Path,
+    query: &str,
+    tables: &[SqlTable],
+    limit: usize,
+) -> Result<DataFrame, Box<dyn std::error::Error>> {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    let quote = |s: &str| format!("'{}'", s.replace('\'', "''"));
+
+    let mut script = String::new();
+    let mut allowed_paths = Vec::new();
+    for table in tables {
+        let path = std::fs::canonicalize(&table.file_path)?;
+        let path = quote(&path.to_string_lossy());
+        let reader = match table.file_type.as_str() {
+            "parquet" => "read_parquet",
+            _ => "read_csv_auto",
+        };
+        script.push_str(&format!(
+            "CREATE VIEW \"{}\" AS SELECT * FROM {}({});\n",
+            table.name, reader, path
+        ));
+        allowed_paths.push(path);
+    }
+    script.push_str(&format!(
+        "SET allowed_paths = [{}];\nSET enable_external_access = false;\nSET lock_configuration = true;\n",
+        allowed_paths.join(", ")
+    ));
+    script.push_str(&format!(
+        "SELECT * FROM ({}) LIMIT {};\n",
+        query.trim().trim_end_matches(';'),
+        limit
+    ));
+
+    let mut child = Command::new(duckdb_bin)
+        .args(["-csv", "-bail", ":memory:"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    child
+        .stdin
+        .take()
+        .ok_or("Failed to open DuckDB stdin")?
+        .write_all(script.as_bytes())?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr)
+            .trim()
+            .to_string()
+            .into());
+    }
+
+    if output.stdout.is_empty() {
+        return Ok(DataFrame::empty());
+    }
+    Ok(CsvReadOptions::default()
+        .with_has_header(true)
+        .into_reader_with_file_handle(std::io::Cursor::new(output.stdout))
+        .finish()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allows_selects_over_registered_tables() {
+        let tables = ["plates", "samples"];
+        assert!(validate_sql("SELECT * FROM plates", &tables, SqlEngine::Polars).is_ok());
+        assert!(validate_sql(
+            "WITH hot AS (SELECT * FROM plates WHERE od > 2) \
+             SELECT s.name, h.od FROM hot h JOIN samples s ON s.well = h.well",
+            &tables,
+            SqlEngine::Duckdb,
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_transform_table() {
+        let path = std::env::temp_dir().join(format!("datalab-quick-{}.csv", std::process::id()));
+        std::fs::write(&path, "well,od\nA1,0.5\nA2,2.5\n").unwrap();
+        let expression = "SELECT well AS sample FROM data WHERE od > 1";
+        assert!(validate_quick_function(expression).is_ok());
+        assert!(validate_quick_function("SELECT * FROM other").is_err());
+
+        let mut table = transform_table(expression, &path.to_string_lossy(), "csv").unwrap();
+        assert_eq!(table.write_file().unwrap(), b"sample\nA2\n");
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_rejects_file_access_and_writes() {
+        let tables = ["plates"];
+        for query in [
+            "SELECT * FROM read_csv('/etc/passwd')",
+            "SELECT * FROM other",
+            "DROP TABLE plates",
+            "COPY plates TO 'out.csv'",
+            "SELECT 1; SELECT 2",
+            "",
+        ] {
+            assert!(
+                validate_sql(query, &tables, SqlEngine::Duckdb).is_err(),
+                "{}",
+                query
+            );
+        }
+    }
+
+    #[test]
+    fn test_table_names() {
+        assert!(is_valid_table_name("plate_2"));
+        assert!(!is_valid_table_name("2plates"));
+        assert!(!is_valid_table_name("a-b"));
+        assert!(!is_valid_table_name(""));
+    }
+}
@@ -0,0 +1,187 @@
+use crate::timestamps;
+use serde::Serialize;
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// A background task that has not finished yet
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskInfo {
+    pub kind: String,           // e.g. `job`, `trigger`
+    pub job_id: Option<String>, // set for job executions
+    pub started_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TaskSummary {
+    pub total: usize,
+    pub by_kind: BTreeMap<String, usize>,
+    pub shutting_down: bool,
+    pub tasks: Vec<TaskInfo>,
+}
+
+#[derive(Default)]
+struct Inner {
+    tasks: Mutex<HashMap<u64, TaskInfo>>,
+    next_id: AtomicU64,
+    closed: AtomicBool,
+    idle: Notify,
+}
+
+/// Tracks the background tasks spawned for uploads and jobs, so a panic is reported instead of
+/// vanishing and shutdown can wait for work in flight
+#[derive(Clone, Default)]
+pub struct TaskSupervisor {
+    inner: Arc<Inner>,
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&'static str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "unknown panic".to_string(),
+        },
+    }
+}
+
+impl TaskSupervisor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `task` in the background; false (and `task` is dropped) once shutdown has begun
+    pub fn spawn<F>(&self, kind: &'static str, task: F) -> bool
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.start(kind, None, task, |_| std::future::ready(()))
+    }
+
+    /// Like `spawn`, for the execution of a job. If the task panics, `on_panic` gets the panic
+    /// message so the job can be marked failed.
+    pub fn spawn_job<F, P, PF>(&self, job_id: &str, task: F, on_panic: P) -> bool
+    where
+        F: Future<Output = ()> + Send + 'static,
+        P: FnOnce(String) -> PF + Send + 'static,
+        PF: Future<Output = ()> + Send,
+    {
+        self.start("job", Some(job_id.to_string()), task, on_panic)
+    }
+
+    fn start<F, P, PF>(
+        &self,
+        kind: &'static str,
+        job_id: Option<String>,
+        task: F,
+        on_panic: P,
+    ) -> bool
+    where
+        F: Future<Output = ()> + Send + 'static,
+        P: FnOnce(String) -> PF + Send + 'static,
+        PF: Future<Output = ()> + Send,
+    {
+        if self.inner.closed.load(Ordering::SeqCst) {
+            return false;
+        }
+
+        let id = self.inner.next_id.fetch_add(1, Ordering::SeqCst);
+        self.inner.tasks.lock().unwrap().insert(
+            id,
+            TaskInfo {
+                kind: kind.to_string(),
+                job_id,
+                started_at: timestamps::now(),
+            },
+        );
+
+        // The task runs on its own so its panic surfaces as a JoinError here
+        let handle = tokio::spawn(task);
+        let inner = self.inner.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle.await {
+                if e.is_panic() {
+                    let message = panic_message(e.into_panic());
+                    tracing::error!("Background {} task panicked: {}", kind, message);
+                    on_panic(message).await;
+                }
+            }
+
+            let mut tasks = inner.tasks.lock().unwrap();
+            tasks.remove(&id);
+            if tasks.is_empty() {
+                inner.idle.notify_waiters();
+            }
+        });
+        true
+    }
+
+    pub fn summary(&self) -> TaskSummary {
+        let mut tasks: Vec<TaskInfo> = self.inner.tasks.lock().unwrap().values().cloned().collect();
+        tasks.sort_by(|a, b| a.started_at.cmp(&b.started_at));
+        let mut by_kind = BTreeMap::new();
+        for task in &tasks {
+            *by_kind.entry(task.kind.clone()).or_insert(0) += 1;
+        }
+        TaskSummary {
+            total: tasks.len(),
+            by_kind,
+            shutting_down: self.inner.closed.load(Ordering::SeqCst),
+            tasks,
+        }
+    }
+
+    /// Stop accepting tasks and wait up to `timeout` for the running ones; returns those that
+    /// are still running
+    pub async fn shutdown(&self, timeout: Duration) -> Vec<TaskInfo> {
+        self.inner.closed.store(true, Ordering::SeqCst);
+        let _ = tokio::time::timeout(timeout, async {
+            loop {
+                let idle = self.inner.idle.notified();
+                if self.inner.tasks.lock().unwrap().is_empty() {
+                    return;
+                }
+                idle.await;
+            }
+        })
+        .await;
+        self.summary().tasks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_panics_are_reported_and_tasks_tracked() {
+        let supervisor = TaskSupervisor::new();
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        assert!(supervisor.spawn_job(
+            "job-1",
+            async { panic!("boom") },
+            move |message| async move {
+                let _ = tx.send(message);
+            },
+        ));
+        assert_eq!(rx.await.unwrap(), "boom");
+
+        let (release, wait) = tokio::sync::oneshot::channel::<()>();
+        supervisor.spawn("trigger", async {
+            let _ = wait.await;
+        });
+        assert_eq!(supervisor.summary().by_kind.get("trigger"), Some(&1));
+
+        // Shutdown gives up on tasks that outlive the timeout and refuses new ones
+        let unfinished = supervisor.shutdown(Duration::from_millis(50)).await;
+        assert_eq!(unfinished.len(), 1);
+        assert!(!supervisor.spawn("trigger", async {}));
+
+        release.send(()).unwrap();
+        assert!(supervisor.shutdown(Duration::from_secs(5)).await.is_empty());
+    }
+}
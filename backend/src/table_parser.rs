@@ -1,6 +1,8 @@
+use crate::filter_expr::FilterExpr;
+use crate::units;
 use polars::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::fs::File;
+use std::collections::BTreeMap;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TablePreview {
@@ -11,129 +13,1033 @@ pub struct TablePreview {
     pub file_type: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
 pub struct TableQuery {
     pub page: Option<usize>,
     pub page_size: Option<usize>,
     pub search: Option<String>,
+    pub format: Option<String>,  // json (default) or arrow
+    pub columns: Option<String>, // comma-separated projection, e.g. "time,temperature"
+    pub filter: Option<String>,  // row filter, e.g. `temperature > 3 AND site == "north"`
 }
 
-pub fn parse_csv_preview(
-    file_path: &str,
-    page: usize,
-    page_size: usize,
-    search_term: Option<&str>,
-) -> Result<TablePreview, Box<dyn std::error::Error>> {
-    // Read CSV with Polars DataFrame API (eager evaluation)
-    let df = CsvReadOptions::default()
-        .with_has_header(true)
-        .try_into_reader_with_file_path(Some(file_path.into()))?
-        .finish()?;
+/// One page of a table, still held as a DataFrame so callers can choose the output encoding
+pub struct TableSlice {
+    pub df: DataFrame,
+    pub total_rows: usize,
+    pub file_type: String,
+}
 
-    // TODO: Implement search filtering with correct Polars API
-    // For now, skip search to get basic functionality working
-    let _ = search_term;
+impl TableSlice {
+    pub fn headers(&self) -> Vec<String> {
+        self.df
+            .get_column_names()
+            .iter()
+            .map(|s| s.to_string())
+            .collect()
+    }
 
-    let total_rows = df.height();
+    fn row(&self, i: usize) -> Vec<String> {
+        self.df
+            .get_columns()
+            .iter()
+            .map(|col| col.get(i).unwrap_or(AnyValue::Null).to_string())
+            .collect()
+    }
 
-    // Apply pagination
-    let start = page * page_size;
-    let end = std::cmp::min(start + page_size, total_rows);
-    let df = df.slice(start as i64, (end - start) as usize);
+    /// The first line of the NDJSON encoding: column names and the size of the whole table
+    pub fn ndjson_meta(&self) -> String {
+        let headers = self.headers();
+        serde_json::json!({
+            "headers": headers,
+            "total_rows": self.total_rows,
+            "total_columns": headers.len(),
+            "file_type": self.file_type,
+        })
+        .to_string()
+    }
 
-    // Extract headers
-    let headers: Vec<String> = df
-        .get_column_names()
-        .iter()
-        .map(|s| s.to_string())
-        .collect();
-    let total_columns = headers.len();
-
-    // Convert to rows
-    let mut rows = Vec::new();
-    for i in 0..df.height() {
-        let mut row = Vec::new();
-        for col_name in &headers {
-            if let Ok(col) = df.column(col_name) {
-                let value = col.get(i).unwrap_or(AnyValue::Null);
-                row.push(value.to_string());
+    /// The rest of the NDJSON encoding: one array of cell values per row. Rows are serialized
+    /// one at a time, but the slice itself is already in memory; see `TablePage::batches`.
+    pub fn ndjson_rows(&self) -> impl Iterator<Item = String> + '_ {
+        (0..self.df.height())
+            .map(|i| serde_json::to_string(&self.row(i)).unwrap_or_else(|_| "[]".into()))
+    }
+
+    /// Encode the page as an Arrow IPC stream, keeping native column types
+    pub fn write_arrow_ipc(&mut self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut buffer = Vec::new();
+        IpcStreamWriter::new(&mut buffer).finish(&mut self.df)?;
+        Ok(buffer)
+    }
+
+    /// Serialize the rows back into the table's own file format
+    pub fn write_file(&mut self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut buffer = Vec::new();
+        match self.file_type.as_str() {
+            "csv" => CsvWriter::new(&mut buffer).finish(&mut self.df)?,
+            "parquet" => {
+                ParquetWriter::new(&mut buffer).finish(&mut self.df)?;
             }
+            other => return Err(format!("Unsupported file type: {}", other).into()),
         }
-        rows.push(row);
+        Ok(buffer)
     }
 
-    Ok(TablePreview {
-        headers,
-        rows,
-        total_rows,
-        total_columns,
-        file_type: "csv".to_string(),
+    pub fn into_preview(self) -> TablePreview {
+        let headers = self.headers();
+        let rows = (0..self.df.height()).map(|i| self.row(i)).collect();
+        TablePreview {
+            total_columns: headers.len(),
+            headers,
+            rows,
+            total_rows: self.total_rows,
+            file_type: self.file_type,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SchemaColumn {
+    pub name: String,
+    pub dtype: String,
+    pub description: Option<String>,
+    pub unit: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TableSchema {
+    pub file_type: String,
+    pub columns: Vec<SchemaColumn>,
+}
+
+/// Read column names and types without loading any rows
+pub fn read_table_schema(
+    file_path: &str,
+    file_extension: &str,
+) -> Result<TableSchema, Box<dyn std::error::Error>> {
+    let file_type = file_extension.to_lowercase();
+    let schema = scan_table(file_path, &file_type)?.collect_schema()?;
+    let columns = schema
+        .iter()
+        .map(|(name, dtype)| SchemaColumn {
+            name: name.to_string(),
+            dtype: dtype.to_string(),
+            description: None,
+            unit: units::parse_header(name).map(|(_, unit)| unit.symbol.to_string()),
+        })
+        .collect();
+    Ok(TableSchema { file_type, columns })
+}
+
+/// Lazily scan a table so column projections and row slices are pushed down into the reader
+pub fn scan_table(
+    file_path: &str,
+    file_type: &str,
+) -> Result<LazyFrame, Box<dyn std::error::Error>> {
+    let lf = match file_type {
+        "csv" => LazyCsvReader::new(PlPath::new(file_path))
+            .with_has_header(true)
+            .finish()?,
+        "parquet" => LazyFrame::scan_parquet(PlPath::new(file_path), Default::default())?,
+        _ => return Err(format!("Unsupported file type: {}", file_type).into()),
+    };
+    Ok(lf)
+}
+
+/// Rows `start..end` of a table (fewer if it is shorter), e.g. to try a function on part of
+/// a large file
+pub fn slice_table_rows(
+    file_path: &str,
+    file_extension: &str,
+    start: u64,
+    end: u64,
+) -> Result<TableSlice, Box<dyn std::error::Error>> {
+    let file_type = file_extension.to_lowercase();
+    let df = scan_table(file_path, &file_type)?
+        .slice(start as i64, (end - start) as IdxSize)
+        .collect()?;
+    Ok(TableSlice {
+        total_rows: df.height(),
+        df,
+        file_type,
     })
 }
 
-pub fn parse_parquet_preview(
+/// Parse a comma-separated `columns=` parameter
+fn requested_columns(query: &TableQuery) -> Option<Vec<String>> {
+    let columns: Vec<String> = query
+        .columns
+        .as_deref()?
+        .split(',')
+        .map(|c| c.trim().to_string())
+        .filter(|c| !c.is_empty())
+        .collect();
+    (!columns.is_empty()).then_some(columns)
+}
+
+/// Parse the `filter=` parameter, treating a blank value as no filter
+fn requested_filter(query: &TableQuery) -> Result<Option<FilterExpr>, String> {
+    match query.filter.as_deref().map(str::trim) {
+        Some(filter) if !filter.is_empty() => FilterExpr::parse(filter).map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// Check the query against the table schema, returning a user-facing message on failure
+pub fn validate_table_query(
+    file_path: &str,
+    file_extension: &str,
+    query: &TableQuery,
+) -> Result<(), String> {
+    let columns = requested_columns(query);
+    let filter = requested_filter(query)?;
+    if columns.is_none() && filter.is_none() {
+        return Ok(());
+    }
+
+    let schema = scan_table(file_path, &file_extension.to_lowercase())
+        .and_then(|mut lf| Ok(lf.collect_schema()?))
+        .map_err(|e| format!("Failed to read table schema: {}", e))?;
+
+    if let Some(missing) = columns
+        .iter()
+        .flatten()
+        .find(|c| schema.get(c.as_str()).is_none())
+    {
+        return Err(format!("Unknown column: {}", missing));
+    }
+
+    match filter {
+        Some(filter) => filter.check_types(&schema),
+        None => Ok(()),
+    }
+}
+
+/// Apply the filter and column projection of a query to a scan
+fn apply_query(mut lf: LazyFrame, query: &TableQuery) -> Result<LazyFrame, String> {
+    // Filter before projecting so the predicate may use columns that are not returned
+    if let Some(filter) = requested_filter(query)? {
+        lf = lf.filter(filter.to_polars());
+    }
+
+    // Only read the columns the client asked for
+    if let Some(columns) = requested_columns(query) {
+        lf = lf.select(columns.iter().map(|c| col(c.as_str())).collect::<Vec<_>>());
+    }
+
+    Ok(lf)
+}
+
+/// A page of a table whose rows are not read yet
+pub struct TablePage {
+    lf: LazyFrame,
+    start: usize,
+    len: usize,
+    pub total_rows: usize,
+    pub file_type: String,
+}
+
+impl TablePage {
+    pub fn collect(self) -> Result<TableSlice, Box<dyn std::error::Error>> {
+        let df = self
+            .lf
+            .slice(self.start as i64, self.len as IdxSize)
+            .collect()?;
+        Ok(TableSlice {
+            df,
+            total_rows: self.total_rows,
+            file_type: self.file_type,
+        })
+    }
+
+    /// Read the page `batch_rows` rows at a time, so only one batch is held in memory. Each
+    /// batch is a scan of its own: Parquet skips to it, CSV reads up to it. There is always at
+    /// least one batch, empty for an empty page, so the columns are known.
+    pub fn batches(
+        self,
+        batch_rows: usize,
+    ) -> impl Iterator<Item = Result<TableSlice, Box<dyn std::error::Error>>> {
+        let batch_rows = batch_rows.max(1);
+        let end = self.start + self.len;
+        let mut offset = self.start;
+        let mut first = true;
+        std::iter::from_fn(move || {
+            if offset >= end && !first {
+                return None;
+            }
+            first = false;
+            let len = batch_rows.min(end - offset);
+            let batch = self
+                .lf
+                .clone()
+                .slice(offset as i64, len as IdxSize)
+                .collect();
+            offset += len;
+            Some(
+                batch
+                    .map(|df| TableSlice {
+                        df,
+                        total_rows: self.total_rows,
+                        file_type: self.file_type.clone(),
+                    })
+                    .map_err(Into::into),
+            )
+        })
+    }
+}
+
+pub fn load_table_slice(
     file_path: &str,
-    page: usize,
-    page_size: usize,
-    search_term: Option<&str>,
-) -> Result<TablePreview, Box<dyn std::error::Error>> {
-    // Read Parquet with Polars DataFrame API
-    let file = File::open(file_path)?;
-    let df = ParquetReader::new(file).finish()?;
+    file_extension: &str,
+    query: &TableQuery,
+) -> Result<TableSlice, Box<dyn std::error::Error>> {
+    plan_table_page(file_path, file_extension, query)?.collect()
+}
+
+/// Count the rows matching a query and work out which of them its page holds, without
+/// reading the page yet
+pub fn plan_table_page(
+    file_path: &str,
+    file_extension: &str,
+    query: &TableQuery,
+) -> Result<TablePage, Box<dyn std::error::Error>> {
+    let page = query.page.unwrap_or(0);
+    let page_size = query.page_size.unwrap_or(50);
+
+    let file_type = file_extension.to_lowercase();
+    let lf = apply_query(scan_table(file_path, &file_type)?, query)?;
 
     // TODO: Implement search filtering with correct Polars API
     // For now, skip search to get basic functionality working
-    let _ = search_term;
+    let _ = query.search.as_deref();
 
-    let total_rows = df.height();
+    // Parquet answers this from metadata; CSV needs a (projected) pass over the file
+    let total_rows = lf
+        .clone()
+        .select([len()])
+        .collect()?
+        .column("len")?
+        .get(0)?
+        .extract::<usize>()
+        .unwrap_or(0);
 
     // Apply pagination
-    let start = page * page_size;
+    let start = std::cmp::min(page * page_size, total_rows);
     let end = std::cmp::min(start + page_size, total_rows);
-    let df = df.slice(start as i64, (end - start) as usize);
 
-    // Extract headers
-    let headers: Vec<String> = df
-        .get_column_names()
+    Ok(TablePage {
+        lf,
+        start,
+        len: end - start,
+        total_rows,
+        file_type,
+    })
+}
+
+// Pivots materialize one column per distinct value, so keep results browser-sized
+const MAX_PIVOT_COLUMNS: usize = 500;
+const MAX_PIVOT_ROWS: usize = 10_000;
+
+const PIVOT_AGGREGATIONS: &[&str] = &[
+    "sum", "mean", "median", "min", "max", "count", "first", "last",
+];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PivotRequest {
+    pub index: Vec<String>,
+    pub columns: String,
+    pub values: String,
+    pub aggfunc: Option<String>, // sum (default), mean, median, min, max, count, first or last
+}
+
+impl PivotRequest {
+    fn aggfunc(&self) -> String {
+        self.aggfunc.as_deref().unwrap_or("sum").to_lowercase()
+    }
+
+    fn aggregate(&self) -> Expr {
+        let values = col(self.values.as_str());
+        let expr = match self.aggfunc().as_str() {
+            "mean" => values.mean(),
+            "median" => values.median(),
+            "min" => values.min(),
+            "max" => values.max(),
+            "count" => values.count(),
+            "first" => values.first(),
+            "last" => values.last(),
+            _ => values.sum(),
+        };
+        expr.alias(self.values.as_str())
+    }
+}
+
+/// Count the distinct values of the given columns taken together
+fn count_unique(lf: &LazyFrame, columns: &[&str]) -> Result<usize, Box<dyn std::error::Error>> {
+    let df = lf
+        .clone()
+        .select(columns.iter().map(|c| col(*c)).collect::<Vec<_>>())
+        .unique(None, UniqueKeepStrategy::Any)
+        .select([len()])
+        .collect()?;
+    Ok(df.column("len")?.get(0)?.extract::<usize>().unwrap_or(0))
+}
+
+/// Check a pivot request against the table schema and the result-size limits
+pub fn validate_pivot_request(
+    file_path: &str,
+    file_extension: &str,
+    request: &PivotRequest,
+) -> Result<(), String> {
+    if request.index.is_empty() {
+        return Err("Pivot needs at least one index column".to_string());
+    }
+    let aggfunc = request.aggfunc();
+    if !PIVOT_AGGREGATIONS.contains(&aggfunc.as_str()) {
+        return Err(format!(
+            "Unknown aggfunc: {} (expected one of {})",
+            aggfunc,
+            PIVOT_AGGREGATIONS.join(", ")
+        ));
+    }
+
+    let mut lf = scan_table(file_path, &file_extension.to_lowercase())
+        .map_err(|e| format!("Failed to read table: {}", e))?;
+    let schema = lf
+        .collect_schema()
+        .map_err(|e| format!("Failed to read table schema: {}", e))?;
+
+    let mut referenced: Vec<&str> = request.index.iter().map(String::as_str).collect();
+    referenced.extend([request.columns.as_str(), request.values.as_str()]);
+    if let Some(missing) = referenced.iter().find(|c| schema.get(c).is_none()) {
+        return Err(format!("Unknown column: {}", missing));
+    }
+
+    let values_dtype = schema.get(request.values.as_str()).unwrap();
+    if matches!(aggfunc.as_str(), "sum" | "mean" | "median") && !values_dtype.is_primitive_numeric()
+    {
+        return Err(format!(
+            "Cannot {} column {} of type {}",
+            aggfunc, request.values, values_dtype
+        ));
+    }
+
+    let pivot_columns = count_unique(&lf, &[request.columns.as_str()])
+        .map_err(|e| format!("Failed to read table: {}", e))?;
+    if pivot_columns > MAX_PIVOT_COLUMNS {
+        return Err(format!(
+            "Pivot would produce {} columns (limit {})",
+            pivot_columns, MAX_PIVOT_COLUMNS
+        ));
+    }
+
+    let index: Vec<&str> = request.index.iter().map(String::as_str).collect();
+    let pivot_rows =
+        count_unique(&lf, &index).map_err(|e| format!("Failed to read table: {}", e))?;
+    if pivot_rows > MAX_PIVOT_ROWS {
+        return Err(format!(
+            "Pivot would produce {} rows (limit {})",
+            pivot_rows, MAX_PIVOT_ROWS
+        ));
+    }
+
+    Ok(())
+}
+
+/// Aggregate lazily with a group-by, then pivot the (already small) result into wide form
+pub fn pivot_table(
+    file_path: &str,
+    file_extension: &str,
+    request: &PivotRequest,
+) -> Result<TableSlice, Box<dyn std::error::Error>> {
+    let file_type = file_extension.to_lowercase();
+    let mut keys: Vec<Expr> = request.index.iter().map(|c| col(c.as_str())).collect();
+    keys.push(col(request.columns.as_str()));
+
+    let aggregated = scan_table(file_path, &file_type)?
+        .group_by(keys)
+        .agg([request.aggregate()])
+        .collect()?;
+
+    // Every (index, columns) pair is unique now, so the default aggregation just places values
+    let mut df = polars::lazy::frame::pivot::pivot_stable(
+        &aggregated,
+        [request.columns.as_str()],
+        Some(request.index.iter().map(String::as_str)),
+        Some([request.values.as_str()]),
+        true,
+        None,
+        None,
+    )?;
+    df = df
+        .lazy()
+        .sort(
+            request.index.iter().map(String::as_str).collect::<Vec<_>>(),
+            Default::default(),
+        )
+        .collect()?;
+
+    Ok(TableSlice {
+        total_rows: df.height(),
+        df,
+        file_type,
+    })
+}
+
+const DEFAULT_SAMPLE_ROWS: usize = 100;
+const MAX_SAMPLE_ROWS: usize = 100_000;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SampleQuery {
+    pub n: Option<usize>,
+    pub method: Option<String>, // head (default) or random
+    pub seed: Option<u64>,      // makes random samples reproducible
+}
+
+impl SampleQuery {
+    pub fn rows(&self) -> usize {
+        self.n.unwrap_or(DEFAULT_SAMPLE_ROWS).min(MAX_SAMPLE_ROWS)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match self.method.as_deref() {
+            None | Some("head") | Some("random") => Ok(()),
+            Some(other) => Err(format!(
+                "Unknown sample method: {} (expected head or random)",
+                other
+            )),
+        }
+    }
+}
+
+/// Take the first `n` rows, or `n` distinct random rows (reproducible with `seed`)
+pub fn sample_table(
+    file_path: &str,
+    file_extension: &str,
+    query: &SampleQuery,
+) -> Result<TableSlice, Box<dyn std::error::Error>> {
+    let file_type = file_extension.to_lowercase();
+    let lf = scan_table(file_path, &file_type)?;
+    let n = query.rows();
+
+    let df = match query.method.as_deref() {
+        Some("random") => {
+            let all = lf.collect()?;
+            all.sample_n_literal(n.min(all.height()), false, false, query.seed)?
+        }
+        _ => lf.limit(n as IdxSize).collect()?,
+    };
+
+    Ok(TableSlice {
+        total_rows: df.height(),
+        df,
+        file_type,
+    })
+}
+
+// Resampled previews are for plotting, so keep them browser-sized
+pub const MAX_RESAMPLE_PREVIEW_ROWS: usize = 10_000;
+
+const RESAMPLE_AGGREGATIONS: &[&str] = &[
+    "mean", "sum", "median", "min", "max", "count", "first", "last",
+];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResampleRequest {
+    pub time_column: Option<String>, // defaults to the first date/datetime column
+    pub every: String, // window size, e.g. "15m", "1h", "1d" (or "100i" for integer time)
+    pub aggregation: Option<String>, // mean (default), sum, median, min, max, count, first or last
+    #[serde(default)]
+    pub by: Vec<String>, // resample each group separately, e.g. per sensor
+}
+
+impl ResampleRequest {
+    fn aggregation(&self) -> String {
+        self.aggregation.as_deref().unwrap_or("mean").to_lowercase()
+    }
+
+    fn aggregate(&self, column: &str) -> Expr {
+        let values = col(column);
+        let expr = match self.aggregation().as_str() {
+            "sum" => values.sum(),
+            "median" => values.median(),
+            "min" => values.min(),
+            "max" => values.max(),
+            "count" => values.count(),
+            "first" => values.first(),
+            "last" => values.last(),
+            _ => values.mean(),
+        };
+        expr.alias(column)
+    }
+
+    /// Check the request against the table schema, filling in the time column if omitted
+    fn resolve(self, file_path: &str, file_extension: &str) -> Result<Self, String> {
+        let schema = scan_table_with_dates(file_path, &file_extension.to_lowercase())
+            .and_then(|mut lf| Ok(lf.collect_schema()?))
+            .map_err(|e| format!("Failed to read table schema: {}", e))?;
+
+        let time_column = match &self.time_column {
+            Some(column) => {
+                let dtype = schema
+                    .get(column.as_str())
+                    .ok_or_else(|| format!("Unknown column: {}", column))?;
+                if !(dtype.is_temporal() || dtype.is_integer()) {
+                    return Err(format!(
+                        "Time column {} must hold dates, datetimes or integers, not {}",
+                        column, dtype
+                    ));
+                }
+                column.clone()
+            }
+            None => schema
+                .iter()
+                .find(|(_, dtype)| matches!(dtype, DataType::Date | DataType::Datetime(_, _)))
+                .map(|(name, _)| name.to_string())
+                .ok_or("No date or datetime column found; set time_column")?,
+        };
+
+        Duration::try_parse(&self.every)
+            .map_err(|_| format!("Invalid resample interval: {}", self.every))?;
+        let integer_time = schema
+            .get(time_column.as_str())
+            .is_some_and(|d| d.is_integer());
+        if integer_time != self.every.ends_with('i') {
+            return Err(if integer_time {
+                "Integer time columns need an index interval such as \"100i\"".to_string()
+            } else {
+                format!(
+                    "Interval {} cannot be used with a date/datetime column",
+                    self.every
+                )
+            });
+        }
+
+        if !RESAMPLE_AGGREGATIONS.contains(&self.aggregation().as_str()) {
+            return Err(format!(
+                "Unknown aggregation: {} (expected one of {})",
+                self.aggregation(),
+                RESAMPLE_AGGREGATIONS.join(", ")
+            ));
+        }
+        if let Some(missing) = self.by.iter().find(|c| schema.get(c.as_str()).is_none()) {
+            return Err(format!("Unknown column: {}", missing));
+        }
+
+        Ok(ResampleRequest {
+            time_column: Some(time_column),
+            ..self
+        })
+    }
+}
+
+/// Like `scan_table`, but CSV columns that look like dates are parsed as such
+pub fn scan_table_with_dates(
+    file_path: &str,
+    file_type: &str,
+) -> Result<LazyFrame, Box<dyn std::error::Error>> {
+    match file_type {
+        "csv" => Ok(LazyCsvReader::new(PlPath::new(file_path))
+            .with_has_header(true)
+            .with_try_parse_dates(true)
+            .finish()?),
+        _ => scan_table(file_path, file_type),
+    }
+}
+
+/// Aggregate every numeric column over fixed time windows, labelled by window start.
+/// Expects a request that went through `ResampleRequest::resolve`.
+pub fn resample_table(
+    file_path: &str,
+    file_extension: &str,
+    request: &ResampleRequest,
+) -> Result<TableSlice, Box<dyn std::error::Error>> {
+    let file_type = file_extension.to_lowercase();
+    let time_column = request
+        .time_column
+        .as_deref()
+        .ok_or("Resample request has no time column")?;
+
+    let mut lf = scan_table_with_dates(file_path, &file_type)?;
+    let schema = lf.collect_schema()?;
+    let values: Vec<Expr> = schema
         .iter()
-        .map(|s| s.to_string())
+        .filter(|(name, dtype)| {
+            dtype.is_primitive_numeric()
+                && name.as_str() != time_column
+                && !request.by.iter().any(|b| b == name.as_str())
+        })
+        .map(|(name, _)| request.aggregate(name))
         .collect();
-    let total_columns = headers.len();
-
-    // Convert to rows
-    let mut rows = Vec::new();
-    for i in 0..df.height() {
-        let mut row = Vec::new();
-        for col_name in &headers {
-            if let Ok(col) = df.column(col_name) {
-                let value = col.get(i).unwrap_or(AnyValue::Null);
-                row.push(value.to_string());
+
+    let every = Duration::try_parse(&request.every)?;
+    let zero = if request.every.ends_with('i') {
+        "0i"
+    } else {
+        "0ns"
+    };
+    let options = DynamicGroupOptions {
+        every,
+        period: every,
+        offset: Duration::try_parse(zero)?,
+        ..Default::default()
+    };
+
+    // Windows are computed per group, and each group must be sorted by time
+    let mut sort_columns: Vec<&str> = request.by.iter().map(String::as_str).collect();
+    sort_columns.push(time_column);
+    let by: Vec<Expr> = request.by.iter().map(|c| col(c.as_str())).collect();
+
+    let df = lf
+        .sort(sort_columns.clone(), Default::default())
+        .group_by_dynamic(col(time_column), by, options)
+        .agg(values)
+        .sort(sort_columns, Default::default())
+        .collect()?;
+
+    Ok(TableSlice {
+        total_rows: df.height(),
+        df,
+        file_type,
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConvertRequest {
+    #[serde(default)]
+    pub units: BTreeMap<String, String>, // column -> target unit, e.g. {"pressure (kPa)": "Pa"}
+    #[serde(default)]
+    pub normalize: bool, // convert every column with a detected unit to its base unit
+}
+
+impl ConvertRequest {
+    /// Check the conversions against the table headers. With `normalize`, expand into
+    /// the explicit column -> base unit map, so lineage records exactly what was done.
+    fn resolve(self, file_path: &str, file_extension: &str) -> Result<Self, String> {
+        let schema = scan_table(file_path, &file_extension.to_lowercase())
+            .and_then(|mut lf| Ok(lf.collect_schema()?))
+            .map_err(|e| format!("Failed to read table schema: {}", e))?;
+
+        let mut conversions = self.units;
+        if self.normalize {
+            for detected in units::detect_units(schema.iter_names().map(|n| n.as_str())) {
+                if detected.unit != detected.base_unit {
+                    conversions
+                        .entry(detected.column)
+                        .or_insert(detected.base_unit);
+                }
             }
         }
-        rows.push(row);
+        if conversions.is_empty() {
+            return Err("No unit conversions requested".to_string());
+        }
+
+        for (column, target) in &conversions {
+            let dtype = schema
+                .get(column.as_str())
+                .ok_or_else(|| format!("Unknown column: {}", column))?;
+            if !dtype.is_primitive_numeric() {
+                return Err(format!("Column {} is not numeric", column));
+            }
+            let (_, from) = units::parse_header(column)
+                .ok_or_else(|| format!("No unit found in column header: {}", column))?;
+            let to = units::find_unit(target).ok_or_else(|| format!("Unknown unit: {}", target))?;
+            units::conversion(from, to)?;
+        }
+
+        Ok(ConvertRequest {
+            units: conversions,
+            normalize: false,
+        })
     }
 
-    Ok(TablePreview {
-        headers,
-        rows,
-        total_rows,
-        total_columns,
-        file_type: "parquet".to_string(),
-    })
+    /// Headers of the converted columns after renaming, with their new unit
+    pub fn converted_headers(&self) -> Vec<(String, String)> {
+        self.units
+            .iter()
+            .filter_map(|(column, target)| {
+                let (quantity, _) = units::parse_header(column)?;
+                let to = units::find_unit(target)?;
+                Some((
+                    units::rename_header(column, &quantity, to),
+                    to.symbol.to_string(),
+                ))
+            })
+            .collect()
+    }
 }
 
-pub fn get_table_preview(
+/// Rewrite unit columns in place, renaming their headers to the new unit
+pub fn convert_table(
     file_path: &str,
     file_extension: &str,
-    query: &TableQuery,
-) -> Result<TablePreview, Box<dyn std::error::Error>> {
-    let page = query.page.unwrap_or(0);
-    let page_size = query.page_size.unwrap_or(50);
-    let search_term = query.search.as_deref();
+    request: &ConvertRequest,
+) -> Result<TableSlice, Box<dyn std::error::Error>> {
+    let file_type = file_extension.to_lowercase();
+    let mut lf = scan_table(file_path, &file_type)?;
+    let schema = lf.collect_schema()?;
+
+    let mut exprs = Vec::with_capacity(schema.len());
+    for name in schema.iter_names() {
+        let Some(target) = request.units.get(name.as_str()) else {
+            exprs.push(col(name.clone()));
+            continue;
+        };
+        let (quantity, from) =
+            units::parse_header(name).ok_or_else(|| format!("No unit in {}", name))?;
+        let to = units::find_unit(target).ok_or_else(|| format!("Unknown unit: {}", target))?;
+        let (scale, shift) = units::conversion(from, to)?;
+        let converted = col(name.clone()).cast(DataType::Float64) * lit(scale) + lit(shift);
+        exprs.push(converted.alias(units::rename_header(name, &quantity, to)));
+    }
+
+    let df = lf.select(exprs).collect()?;
+    Ok(TableSlice {
+        total_rows: df.height(),
+        df,
+        file_type,
+    })
+}
+
+pub const MAX_COMPARE_UPLOADS: usize = 50;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CompareMode {
+    #[default]
+    Combine, // all rows stacked in long format with a run column
+    Summary, // count, mean, std, min and max of each numeric column per run
+}
 
-    match file_extension.to_lowercase().as_str() {
-        "csv" => parse_csv_preview(file_path, page, page_size, search_term),
-        "parquet" => parse_parquet_preview(file_path, page, page_size, search_term),
-        _ => Err(format!("Unsupported file type: {}", file_extension).into()),
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Comparison {
+    pub uploads: Vec<String>,
+    #[serde(default)]
+    pub mode: CompareMode,
+    pub run_column: Option<String>, // defaults to "run"
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CompareRequest {
+    #[serde(flatten)]
+    pub comparison: Comparison,
+    pub filename: Option<String>, // defaults to e.g. "comparison_3_runs.csv"
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl Comparison {
+    pub fn run_column(&self) -> &str {
+        self.run_column.as_deref().unwrap_or("run")
+    }
+}
+
+/// One upload taking part in a comparison, labelled by its run name
+pub struct RunTable {
+    pub label: String,
+    pub file_path: String,
+    pub file_type: String,
+}
+
+/// Columns of the first run, after checking every run has the same ones
+fn shared_columns(runs: &[RunTable]) -> Result<Vec<(String, DataType)>, String> {
+    let mut columns: Vec<(String, DataType)> = Vec::new();
+    for run in runs {
+        let schema = scan_table(&run.file_path, &run.file_type)
+            .and_then(|mut lf| Ok(lf.collect_schema()?))
+            .map_err(|e| format!("Failed to read table schema of {}: {}", run.label, e))?;
+        if columns.is_empty() {
+            columns = schema
+                .iter()
+                .map(|(name, dtype)| (name.to_string(), dtype.clone()))
+                .collect();
+        } else if schema.len() != columns.len()
+            || columns.iter().any(|(name, _)| schema.get(name).is_none())
+        {
+            return Err(format!(
+                "{} does not have the same columns as {}",
+                run.label, runs[0].label
+            ));
+        }
+    }
+    Ok(columns)
+}
+
+/// Check that the runs share a schema that works for the requested comparison
+pub fn validate_comparison(runs: &[RunTable], comparison: &Comparison) -> Result<(), String> {
+    let columns = shared_columns(runs)?;
+    let run_column = comparison.run_column();
+    if columns.iter().any(|(name, _)| name == run_column) {
+        return Err(format!(
+            "Column {} already exists; choose another run_column",
+            run_column
+        ));
+    }
+    if comparison.mode == CompareMode::Summary
+        && !columns
+            .iter()
+            .any(|(_, dtype)| dtype.is_primitive_numeric())
+    {
+        return Err("The uploads have no numeric columns to summarize".to_string());
+    }
+    Ok(())
+}
+
+/// Stack runs that share a schema, or summarize each of them, into a table of `file_type`
+pub fn compare_tables(
+    runs: &[RunTable],
+    comparison: &Comparison,
+    file_type: &str,
+) -> Result<TableSlice, Box<dyn std::error::Error>> {
+    let columns = shared_columns(runs)?;
+    let run_column = comparison.run_column();
+
+    // Same names in the same order everywhere, with the run label in front
+    let mut projection = vec![col(run_column)];
+    projection.extend(columns.iter().map(|(name, _)| col(name.as_str())));
+    let mut frames = Vec::with_capacity(runs.len());
+    for run in runs {
+        frames.push(
+            scan_table(&run.file_path, &run.file_type)?
+                .with_column(lit(run.label.clone()).alias(run_column))
+                .select(projection.clone()),
+        );
+    }
+    let combined = concat(
+        frames,
+        UnionArgs {
+            to_supertypes: true,
+            ..Default::default()
+        },
+    )?;
+
+    let lf = match comparison.mode {
+        CompareMode::Combine => combined,
+        CompareMode::Summary => {
+            let summaries: Vec<LazyFrame> = columns
+                .iter()
+                .filter(|(_, dtype)| dtype.is_primitive_numeric())
+                .map(|(name, _)| {
+                    let values = col(name.as_str()).cast(DataType::Float64);
+                    combined.clone().group_by_stable([col(run_column)]).agg([
+                        lit(name.clone()).alias("column"),
+                        values.clone().count().alias("count"),
+                        values.clone().mean().alias("mean"),
+                        values.clone().std(1).alias("std"),
+                        values.clone().min().alias("min"),
+                        values.max().alias("max"),
+                    ])
+                })
+                .collect();
+            concat(summaries, Default::default())?
+        }
+    };
+
+    let df = lf.collect()?;
+    Ok(TableSlice {
+        total_rows: df.height(),
+        df,
+        file_type: file_type.to_string(),
+    })
+}
+
+/// A built-in operation whose full result can be saved as a new upload
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "operation", rename_all = "lowercase")]
+pub enum DeriveOperation {
+    Query {
+        columns: Option<String>,
+        filter: Option<String>,
+    },
+    Pivot(PivotRequest),
+    Sample(SampleQuery),
+    Convert(ConvertRequest),
+    Resample(ResampleRequest),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeriveRequest {
+    #[serde(flatten)]
+    pub operation: DeriveOperation,
+    pub filename: Option<String>, // defaults to e.g. "data_pivot.csv"
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl DeriveOperation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DeriveOperation::Query { .. } => "query",
+            DeriveOperation::Pivot(_) => "pivot",
+            DeriveOperation::Sample(_) => "sample",
+            DeriveOperation::Convert(_) => "convert",
+            DeriveOperation::Resample(_) => "resample",
+        }
+    }
+
+    fn table_query(&self) -> Option<TableQuery> {
+        match self {
+            DeriveOperation::Query { columns, filter } => Some(TableQuery {
+                page: None,
+                page_size: None,
+                search: None,
+                format: None,
+                columns: columns.clone(),
+                filter: filter.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Check the operation against the table, resolving anything that depends on its
+    /// schema so the returned operation describes exactly what `run` will do
+    pub fn prepare(self, file_path: &str, file_extension: &str) -> Result<Self, String> {
+        match self {
+            DeriveOperation::Query { .. } => {
+                let query = self.table_query().unwrap();
+                validate_table_query(file_path, file_extension, &query)?;
+                Ok(self)
+            }
+            DeriveOperation::Pivot(ref request) => {
+                validate_pivot_request(file_path, file_extension, request)?;
+                Ok(self)
+            }
+            DeriveOperation::Sample(ref query) => {
+                query.validate()?;
+                Ok(self)
+            }
+            DeriveOperation::Convert(request) => request
+                .resolve(file_path, file_extension)
+                .map(DeriveOperation::Convert),
+            DeriveOperation::Resample(request) => request
+                .resolve(file_path, file_extension)
+                .map(DeriveOperation::Resample),
+        }
+    }
+
+    /// Compute the complete result, not just a preview page
+    pub fn run(
+        &self,
+        file_path: &str,
+        file_extension: &str,
+    ) -> Result<TableSlice, Box<dyn std::error::Error>> {
+        match self {
+            DeriveOperation::Query { .. } => {
+                let query = self.table_query().unwrap();
+                let file_type = file_extension.to_lowercase();
+                let df = apply_query(scan_table(file_path, &file_type)?, &query)?.collect()?;
+                Ok(TableSlice {
+                    total_rows: df.height(),
+                    df,
+                    file_type,
+                })
+            }
+            DeriveOperation::Pivot(request) => pivot_table(file_path, file_extension, request),
+            DeriveOperation::Sample(query) => sample_table(file_path, file_extension, query),
+            DeriveOperation::Convert(request) => convert_table(file_path, file_extension, request),
+            DeriveOperation::Resample(request) => {
+                resample_table(file_path, file_extension, request)
+            }
+        }
     }
 }
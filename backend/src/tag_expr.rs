@@ -0,0 +1,201 @@
+use std::collections::HashSet;
+
+/// A boolean expression over tag names, e.g. `failed AND (.csv OR .parquet) AND NOT archived`
+#[derive(Debug, Clone, PartialEq)]
+pub enum TagExpr {
+    Tag(String),
+    Not(Box<TagExpr>),
+    And(Box<TagExpr>, Box<TagExpr>),
+    Or(Box<TagExpr>, Box<TagExpr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Tag(String),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+}
+
+impl TagExpr {
+    /// Parse an expression. Operators are `AND`, `OR` and `NOT` (case-insensitive),
+    /// parentheses group, and tag names containing spaces can be double-quoted.
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let tokens = tokenize(input)?;
+        if tokens.is_empty() {
+            return Err("Empty tag expression".to_string());
+        }
+
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.parse_or()?;
+        if parser.pos < parser.tokens.len() {
+            return Err(format!(
+                "Unexpected token at position {} in tag expression",
+                parser.pos
+            ));
+        }
+        Ok(expr)
+    }
+
+    /// Evaluate the expression against the set of tag names on an upload
+    pub fn matches(&self, tags: &HashSet<&str>) -> bool {
+        match self {
+            TagExpr::Tag(name) => tags.contains(name.as_str()),
+            TagExpr::Not(inner) => !inner.matches(tags),
+            TagExpr::And(lhs, rhs) => lhs.matches(tags) && rhs.matches(tags),
+            TagExpr::Or(lhs, rhs) => lhs.matches(tags) || rhs.matches(tags),
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::LParen);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::RParen);
+            }
+            '"' => {
+                chars.next();
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => name.push(c),
+                        None => return Err("Unterminated quote in tag expression".to_string()),
+                    }
+                }
+                tokens.push(Token::Tag(name));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' || c == '"' {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                let token = match word.to_uppercase().as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => Token::Tag(word),
+                };
+                tokens.push(token);
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent parser, precedence: NOT > AND > OR
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn parse_or(&mut self) -> Result<TagExpr, String> {
+        let mut lhs = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            let rhs = self.parse_and()?;
+            lhs = TagExpr::Or(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_and(&mut self) -> Result<TagExpr, String> {
+        let mut lhs = self.parse_not()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            let rhs = self.parse_not()?;
+            lhs = TagExpr::And(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_not(&mut self) -> Result<TagExpr, String> {
+        if self.peek() == Some(&Token::Not) {
+            self.pos += 1;
+            let inner = self.parse_not()?;
+            return Ok(TagExpr::Not(Box::new(inner)));
+        }
+        self.parse_atom()
+    }
+
+    fn parse_atom(&mut self) -> Result<TagExpr, String> {
+        match self.tokens.get(self.pos).cloned() {
+            Some(Token::Tag(name)) => {
+                self.pos += 1;
+                Ok(TagExpr::Tag(name))
+            }
+            Some(Token::LParen) => {
+                self.pos += 1;
+                let expr = self.parse_or()?;
+                if self.peek() != Some(&Token::RParen) {
+                    return Err("Missing closing parenthesis in tag expression".to_string());
+                }
+                self.pos += 1;
+                Ok(expr)
+            }
+            Some(_) => Err(format!(
+                "Expected tag name at position {} in tag expression",
+                self.pos
+            )),
+            None => Err("Unexpected end of tag expression".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags<'a>(names: &[&'a str]) -> HashSet<&'a str> {
+        names.iter().copied().collect()
+    }
+
+    #[test]
+    fn test_precedence() {
+        let expr = TagExpr::parse("a OR b AND NOT c").unwrap();
+        assert!(expr.matches(&tags(&["a"])));
+        assert!(expr.matches(&tags(&["b"])));
+        assert!(!expr.matches(&tags(&["b", "c"])));
+    }
+
+    #[test]
+    fn test_parentheses_and_quotes() {
+        let expr = TagExpr::parse(r#"("raw data" or .csv) and not failed"#).unwrap();
+        assert!(expr.matches(&tags(&["raw data"])));
+        assert!(expr.matches(&tags(&[".csv"])));
+        assert!(!expr.matches(&tags(&[".csv", "failed"])));
+    }
+
+    #[test]
+    fn test_invalid_expressions() {
+        assert!(TagExpr::parse("").is_err());
+        assert!(TagExpr::parse("a AND").is_err());
+        assert!(TagExpr::parse("(a OR b").is_err());
+        assert!(TagExpr::parse("a b").is_err());
+        assert!(TagExpr::parse("\"unterminated").is_err());
+    }
+}
@@ -0,0 +1,264 @@
+//! The rules tag names follow, and how DataLab's own tags look. System tags, like the `.csv`
+//! extension tags, are created and applied by DataLab itself; their names carry a reserved
+//! prefix and cannot be changed, since uploads are matched to them by name. Tags created
+//! without a color get one from a palette. Extension tagging can be turned off or limited to
+//! some extensions.
+
+use serde::Serialize;
+
+pub const DEFAULT_SYSTEM_COLOR: &str = "#6b7280"; // gray-500
+pub const DEFAULT_SYSTEM_PREFIX: &str = ".";
+pub const DEFAULT_FORBIDDEN_CHARACTERS: &str = "~";
+/// Tailwind's 500 shades of red, orange, amber, green, teal, blue, violet and pink
+pub const DEFAULT_PALETTE: &str = "#ef4444,#f97316,#f59e0b,#22c55e,#14b8a6,#3b82f6,#8b5cf6,#ec4899";
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TagPolicy {
+    pub system_color: String, // of tags DataLab creates, e.g. extension and watch tags
+    pub system_prefixes: Vec<String>, // names starting with one of these cannot be renamed
+    pub forbidden_characters: Vec<char>,
+    pub palette: Vec<String>, // colors given to tags created without one
+    pub extension_tags: ExtensionTagging,
+}
+
+/// Which uploads DataLab tags with their extension, and the color it creates those tags with
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExtensionTagging {
+    pub enabled: bool,
+    pub allowlist: Vec<String>, // lowercase extensions without the dot; empty for all of them
+    pub color: String,
+}
+
+impl Default for ExtensionTagging {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            allowlist: Vec::new(),
+            color: DEFAULT_SYSTEM_COLOR.to_string(),
+        }
+    }
+}
+
+impl ExtensionTagging {
+    /// Extension tagging from its configuration; `allowlist` entries may start with a dot and
+    /// are matched case-insensitively. Err says which setting is invalid
+    pub fn new(enabled: bool, allowlist: &[String], color: &str) -> Result<Self, String> {
+        if !is_hex_color(color) {
+            return Err(format!(
+                "Extension tag color must be a hex color like {}, not {}",
+                DEFAULT_SYSTEM_COLOR, color
+            ));
+        }
+        let allowlist = allowlist
+            .iter()
+            .map(|extension| extension.trim().trim_start_matches('.').to_lowercase())
+            .filter(|extension| !extension.is_empty())
+            .collect();
+        Ok(Self {
+            enabled,
+            allowlist,
+            color: color.to_string(),
+        })
+    }
+
+    /// Whether uploads get the extension tag `tag_name`, e.g. `.csv`
+    pub fn applies_to(&self, tag_name: &str) -> bool {
+        let extension = tag_name.trim_start_matches('.').to_lowercase();
+        self.enabled && (self.allowlist.is_empty() || self.allowlist.contains(&extension))
+    }
+}
+
+impl Default for TagPolicy {
+    fn default() -> Self {
+        Self {
+            system_color: DEFAULT_SYSTEM_COLOR.to_string(),
+            system_prefixes: vec![DEFAULT_SYSTEM_PREFIX.to_string()],
+            forbidden_characters: DEFAULT_FORBIDDEN_CHARACTERS.chars().collect(),
+            palette: DEFAULT_PALETTE.split(',').map(str::to_string).collect(),
+            extension_tags: ExtensionTagging::default(),
+        }
+    }
+}
+
+impl TagPolicy {
+    /// A policy from its configuration, tagging every extension in the system color; Err says
+    /// which setting is invalid
+    pub fn new(
+        system_color: &str,
+        system_prefixes: Vec<String>,
+        forbidden_characters: &str,
+        palette: Vec<String>,
+    ) -> Result<Self, String> {
+        if !is_hex_color(system_color) {
+            return Err(format!(
+                "System tag color must be a hex color like {}, not {}",
+                DEFAULT_SYSTEM_COLOR, system_color
+            ));
+        }
+        let system_prefixes: Vec<String> = system_prefixes
+            .into_iter()
+            .filter(|prefix| !prefix.is_empty())
+            .collect();
+        let forbidden_characters: Vec<char> = forbidden_characters
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        if let Some(prefix) = system_prefixes
+            .iter()
+            .find(|prefix| prefix.chars().any(|c| forbidden_characters.contains(&c)))
+        {
+            return Err(format!(
+                "System tag prefix {} contains a forbidden character",
+                prefix
+            ));
+        }
+        if palette.is_empty() {
+            return Err("The tag palette needs at least one color".to_string());
+        }
+        if let Some(color) = palette.iter().find(|color| !is_hex_color(color)) {
+            return Err(format!(
+                "Tag palette colors must be hex colors like {}, not {}",
+                DEFAULT_SYSTEM_COLOR, color
+            ));
+        }
+        Ok(Self {
+            system_color: system_color.to_string(),
+            system_prefixes,
+            forbidden_characters,
+            palette,
+            extension_tags: ExtensionTagging {
+                color: system_color.to_string(),
+                ..ExtensionTagging::default()
+            },
+        })
+    }
+
+    /// Whether a tag is one of DataLab's own, whose name cannot be changed
+    pub fn is_system_tag(&self, name: &str) -> bool {
+        self.system_prefixes
+            .iter()
+            .any(|prefix| name.starts_with(prefix.as_str()))
+    }
+
+    /// Check a name given to a tag by a user
+    pub fn validate_name(&self, name: &str) -> Result<(), String> {
+        if name.trim().is_empty() {
+            return Err("Tag name must not be empty".to_string());
+        }
+        match name.chars().find(|c| self.forbidden_characters.contains(c)) {
+            Some(c) => Err(format!("Tag names must not contain '{}'", c)),
+            None => Ok(()),
+        }
+    }
+
+    /// Check a color given to a tag by a user
+    pub fn validate_color(&self, color: &str) -> Result<(), String> {
+        if is_hex_color(color) {
+            Ok(())
+        } else {
+            Err(format!(
+                "Tag colors must be hex colors like #3b82f6, not {}",
+                color
+            ))
+        }
+    }
+
+    /// The color for a new tag: the first of the palette's colors that the fewest of the
+    /// existing tags have, so the palette is gone through in order
+    pub fn next_color(&self, used: &[String]) -> String {
+        let uses = |color: &String| {
+            used.iter()
+                .filter(|other| other.eq_ignore_ascii_case(color))
+                .count()
+        };
+        self.palette
+            .iter()
+            .min_by_key(|color| uses(color))
+            .cloned()
+            .unwrap_or_else(|| self.system_color.clone())
+    }
+}
+
+/// `#rgb` or `#rrggbb`
+fn is_hex_color(color: &str) -> bool {
+    color
+        .strip_prefix('#')
+        .is_some_and(|hex| matches!(hex.len(), 3 | 6) && hex.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn palette() -> Vec<String> {
+        vec!["#111111".to_string(), "#222222".to_string()]
+    }
+
+    #[test]
+    fn test_default_policy() {
+        let policy = TagPolicy::default();
+        assert!(policy.is_system_tag(".csv"));
+        assert!(!policy.is_system_tag("raw"));
+        assert!(policy.validate_name("raw data").is_ok());
+        assert_eq!(
+            policy.validate_name("a~b"),
+            Err("Tag names must not contain '~'".to_string())
+        );
+        assert!(policy.validate_name("  ").is_err());
+    }
+
+    #[test]
+    fn test_configured_policy() {
+        let policy = TagPolicy::new(
+            "#abc",
+            vec!["sys:".to_string(), String::new()],
+            "~ |",
+            palette(),
+        )
+        .unwrap();
+        assert_eq!(policy.system_prefixes, ["sys:"]);
+        assert_eq!(policy.forbidden_characters, ['~', '|']);
+        assert!(policy.is_system_tag("sys:csv"));
+        assert!(!policy.is_system_tag(".csv"));
+        assert!(policy.validate_name("a|b").is_err());
+
+        assert!(TagPolicy::new("gray", vec![], "~", palette()).is_err());
+        assert!(TagPolicy::new("#6b7280", vec!["~x".to_string()], "~", palette()).is_err());
+        assert!(TagPolicy::new("#6b7280", vec![], "~", vec![]).is_err());
+        assert!(TagPolicy::new("#6b7280", vec![], "~", vec!["red".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_extension_tagging() {
+        let all = TagPolicy::default().extension_tags;
+        assert!(all.applies_to(".csv") && all.applies_to(".PNG"));
+
+        let allowlist = vec![".CSV".to_string(), " parquet".to_string(), String::new()];
+        let some = ExtensionTagging::new(true, &allowlist, "#123456").unwrap();
+        assert_eq!(some.allowlist, ["csv", "parquet"]);
+        assert!(some.applies_to(".csv") && some.applies_to(".parquet"));
+        assert!(!some.applies_to(".txt"));
+
+        let none = ExtensionTagging::new(false, &[], "#123456").unwrap();
+        assert!(!none.applies_to(".csv"));
+        assert!(ExtensionTagging::new(true, &[], "gray").is_err());
+    }
+
+    #[test]
+    fn test_colors() {
+        let policy = TagPolicy::new("#6b7280", vec![], "~", palette()).unwrap();
+        assert!(policy.validate_color("#3B82F6").is_ok());
+        assert!(policy.validate_color("#fff").is_ok());
+        assert!(policy.validate_color("blue").is_err());
+        assert!(policy.validate_color("#12345").is_err());
+
+        let used = |colors: &[&str]| colors.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+        assert_eq!(policy.next_color(&[]), "#111111");
+        assert_eq!(policy.next_color(&used(&["#111111", "#6b7280"])), "#222222");
+        assert_eq!(policy.next_color(&used(&["#111111", "#222222"])), "#111111");
+        assert_eq!(
+            policy.next_color(&used(&["#111111", "#222222", "#111111"])),
+            "#222222"
+        );
+    }
+}
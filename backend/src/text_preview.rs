@@ -0,0 +1,178 @@
+//! The start of text-like uploads as text, for reading logs, JSON configs and READMEs in the
+//! UI without downloading them. Only byte order marks and UTF-8 validity are looked at, which
+//! covers what instruments and scripts write: UTF-8, UTF-16 from Windows tools, and Latin-1
+//! from older ones.
+
+use serde::{Deserialize, Serialize};
+
+/// Kilobytes returned unless the request asks for more
+pub const DEFAULT_PREVIEW_KB: usize = 64;
+pub const MAX_PREVIEW_KB: usize = 1024;
+
+#[derive(Debug, Deserialize)]
+pub struct ContentQuery {
+    pub max_kb: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TextContent {
+    pub content: String,
+    pub encoding: TextEncoding,
+    pub syntax: Option<&'static str>,
+    pub bytes_read: usize,
+    pub total_bytes: u64,
+    pub truncated: bool, // the file goes on past `bytes_read`
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum TextEncoding {
+    #[serde(rename = "utf-8")]
+    Utf8,
+    #[serde(rename = "utf-16le")]
+    Utf16Le,
+    #[serde(rename = "utf-16be")]
+    Utf16Be,
+    #[serde(rename = "iso-8859-1")]
+    Latin1,
+}
+
+/// Decode the first bytes of a file; `truncated` says the file goes on, so a character cut in
+/// half at the end is dropped. None for binary content.
+pub fn decode_text(head: &[u8], truncated: bool) -> Option<(String, TextEncoding)> {
+    if let Some(rest) = head.strip_prefix(b"\xef\xbb\xbf") {
+        return decode_utf8(rest, truncated).map(|text| (text, TextEncoding::Utf8));
+    }
+    if let Some(rest) = head.strip_prefix(b"\xff\xfe") {
+        return decode_utf16(rest, u16::from_le_bytes).map(|text| (text, TextEncoding::Utf16Le));
+    }
+    if let Some(rest) = head.strip_prefix(b"\xfe\xff") {
+        return decode_utf16(rest, u16::from_be_bytes).map(|text| (text, TextEncoding::Utf16Be));
+    }
+    if head.contains(&0) {
+        return None;
+    }
+    if let Some(text) = decode_utf8(head, truncated) {
+        return Some((text, TextEncoding::Utf8));
+    }
+    // Every byte is a Latin-1 character; control characters other than whitespace mean binary
+    let binary = head
+        .iter()
+        .any(|&b| (b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c | 0x1b)) || b == 0x7f);
+    (!binary).then(|| {
+        (
+            head.iter().map(|&b| b as char).collect(),
+            TextEncoding::Latin1,
+        )
+    })
+}
+
+fn decode_utf8(bytes: &[u8], truncated: bool) -> Option<String> {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => Some(text.to_string()),
+        // Cut in the middle of the last character
+        Err(e) if truncated && e.error_len().is_none() => {
+            Some(String::from_utf8_lossy(&bytes[..e.valid_up_to()]).to_string())
+        }
+        Err(_) => None,
+    }
+}
+
+fn decode_utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> Option<String> {
+    let mut units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| unit([pair[0], pair[1]]))
+        .collect();
+    // A surrogate pair cut in half at the end
+    if units.last().is_some_and(|u| (0xd800..0xdc00).contains(u)) {
+        units.pop();
+    }
+    char::decode_utf16(units)
+        .collect::<Result<String, _>>()
+        .ok()
+}
+
+/// A language name for highlighting, as highlight.js and Prism call them
+pub fn syntax_hint(extension: Option<&str>, mime_type: Option<&str>) -> Option<&'static str> {
+    let by_extension = match extension.unwrap_or_default() {
+        "json" | "geojson" | "ipynb" => "json",
+        "jsonl" | "ndjson" => "json",
+        "md" | "markdown" => "markdown",
+        "yaml" | "yml" => "yaml",
+        "toml" => "toml",
+        "ini" | "cfg" | "conf" => "ini",
+        "xml" | "svg" => "xml",
+        "html" | "htm" => "html",
+        "py" => "python",
+        "r" => "r",
+        "rs" => "rust",
+        "js" | "mjs" => "javascript",
+        "ts" => "typescript",
+        "sh" | "bash" => "bash",
+        "sql" => "sql",
+        "tex" => "latex",
+        "csv" | "tsv" => "csv",
+        "log" => "log",
+        _ => "",
+    };
+    if !by_extension.is_empty() {
+        return Some(by_extension);
+    }
+    match mime_type {
+        Some("application/json" | "application/x-ndjson") => Some("json"),
+        Some("application/xml") => Some("xml"),
+        Some("text/html") => Some("html"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_text() {
+        assert_eq!(
+            decode_text(b"\xef\xbb\xbf{\"a\": 1}", false),
+            Some(("{\"a\": 1}".to_string(), TextEncoding::Utf8))
+        );
+        // "é" cut in half is dropped when the file goes on, and is not text otherwise
+        assert_eq!(
+            decode_text(b"caf\xc3", true),
+            Some(("caf".to_string(), TextEncoding::Utf8))
+        );
+        assert_eq!(
+            decode_text(b"caf\xe9", false),
+            Some(("café".to_string(), TextEncoding::Latin1))
+        );
+        assert_eq!(
+            decode_text(b"\xff\xfeo\x00k\x00", false),
+            Some(("ok".to_string(), TextEncoding::Utf16Le))
+        );
+        assert_eq!(
+            decode_text(b"\xfe\xff\x00o\x00k\x00", true),
+            Some(("ok".to_string(), TextEncoding::Utf16Be))
+        );
+        assert_eq!(
+            decode_text(b"\xff\xfe=\xd8", true),
+            Some(("".to_string(), TextEncoding::Utf16Le))
+        );
+        assert_eq!(decode_text(b"PAR1\x15\x04\x00", true), None);
+        assert_eq!(decode_text(b"\x01\x02\xe9", false), None);
+    }
+
+    #[test]
+    fn test_syntax_hint() {
+        assert_eq!(
+            syntax_hint(Some("md"), Some("text/plain")),
+            Some("markdown")
+        );
+        assert_eq!(syntax_hint(Some("cfg"), None), Some("ini"));
+        assert_eq!(
+            syntax_hint(Some("txt"), Some("application/json")),
+            Some("json")
+        );
+        assert_eq!(syntax_hint(Some("txt"), Some("text/plain")), None);
+        assert_eq!(syntax_hint(None, Some("application/json")), Some("json"));
+        assert_eq!(syntax_hint(None, None), None);
+    }
+}
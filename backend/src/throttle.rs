@@ -0,0 +1,131 @@
+//! `--max-transfer-rate`: request and response bodies are paced so a large export or upload
+//! leaves room on a shared network. Every body is paced on its own, and HTTP/1.1 connections
+//! carry one request at a time, so this is a limit per connection; several clients together
+//! can still use more. A burst of one second's worth goes out unpaced, so small API responses
+//! are not slowed down at all.
+
+use axum::body::{Body, Bytes, HttpBody};
+use axum::extract::{Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+use std::time::Duration;
+use tokio::time::Instant;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::StreamExt;
+
+/// Keeps the bytes sent so far at or below `bytes_per_second`, after an initial burst
+#[derive(Debug)]
+pub struct Pacer {
+    bytes_per_second: u64,
+    sent: u64,
+}
+
+impl Pacer {
+    pub fn new(bytes_per_second: u64) -> Self {
+        Self {
+            bytes_per_second: bytes_per_second.max(1),
+            sent: 0,
+        }
+    }
+
+    /// Count `bytes` more as sent, and say how long to wait before sending them when the
+    /// transfer has been going on for `elapsed`
+    pub fn delay(&mut self, bytes: u64, elapsed: Duration) -> Duration {
+        self.sent += bytes;
+        let paced = self.sent.saturating_sub(self.bytes_per_second);
+        Duration::from_secs_f64(paced as f64 / self.bytes_per_second as f64).saturating_sub(elapsed)
+    }
+
+    /// Size to split bodies into, so about ten pieces go out per second
+    pub fn piece_size(&self) -> usize {
+        (self.bytes_per_second / 10).clamp(1024, 64 * 1024) as usize
+    }
+
+    // Bodies that fit in the initial burst are never delayed
+    fn is_burst(&self, body: &Body) -> bool {
+        body.size_hint()
+            .exact()
+            .is_some_and(|size| size <= self.bytes_per_second)
+    }
+}
+
+/// Middleware pacing the request and the response body to `bytes_per_second` each
+pub async fn limit_transfer_rate(
+    State(bytes_per_second): State<u64>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let (parts, body) = request.into_parts();
+    let request = Request::from_parts(parts, paced(body, bytes_per_second));
+    let (parts, body) = next.run(request).await.into_parts();
+    Response::from_parts(parts, paced(body, bytes_per_second))
+}
+
+fn paced(body: Body, bytes_per_second: u64) -> Body {
+    let mut pacer = Pacer::new(bytes_per_second);
+    if pacer.is_burst(&body) {
+        return body;
+    }
+
+    let (tx, rx) = tokio::sync::mpsc::channel::<Result<Bytes, axum::Error>>(1);
+    tokio::spawn(async move {
+        let started = Instant::now();
+        let mut stream = body.into_data_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    let _ = tx.send(Err(e)).await;
+                    return;
+                }
+            };
+            let mut offset = 0;
+            while offset < chunk.len() {
+                let end = (offset + pacer.piece_size()).min(chunk.len());
+                let delay = pacer.delay((end - offset) as u64, started.elapsed());
+                tokio::time::sleep(delay).await;
+                if tx.send(Ok(chunk.slice(offset..end))).await.is_err() {
+                    return; // the client went away
+                }
+                offset = end;
+            }
+        }
+    });
+    Body::from_stream(ReceiverStream::new(rx))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pacer() {
+        let mut pacer = Pacer::new(1000);
+        // The first second's worth is a burst
+        assert_eq!(pacer.delay(1000, Duration::ZERO), Duration::ZERO);
+        assert_eq!(pacer.delay(500, Duration::ZERO), Duration::from_millis(500));
+        // Time already spent counts
+        assert_eq!(
+            pacer.delay(500, Duration::from_millis(200)),
+            Duration::from_millis(800)
+        );
+        assert_eq!(pacer.delay(0, Duration::from_secs(5)), Duration::ZERO);
+        assert_eq!(pacer.piece_size(), 1024);
+        assert_eq!(Pacer::new(10 << 20).piece_size(), 64 * 1024);
+    }
+
+    #[tokio::test]
+    async fn test_paced_body() {
+        let body = paced(Body::from(vec![7u8; 5000]), 4000);
+        let started = std::time::Instant::now();
+        let bytes = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        assert_eq!(bytes.len(), 5000);
+        assert!(started.elapsed() >= Duration::from_millis(200));
+
+        // Small bodies are passed on as they are
+        let body = Body::from("ok");
+        assert_eq!(body.size_hint().exact(), Some(2));
+        let body = paced(body, 4000);
+        assert_eq!(body.size_hint().exact(), Some(2));
+    }
+}
@@ -0,0 +1,115 @@
+use image::{DynamicImage, ImageFormat, ImageReader, Luma};
+use serde::Deserialize;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_THUMBNAIL_SIZE: u32 = 256;
+
+/// Sizes (longest edge, in pixels) that can be requested; each is cached once per upload
+pub const THUMBNAIL_SIZES: &[u32] = &[64, 128, 256, 512];
+
+#[derive(Debug, Deserialize)]
+pub struct ThumbnailQuery {
+    pub size: Option<u32>,
+}
+
+pub fn is_image_extension(extension: &str) -> bool {
+    matches!(
+        extension.to_lowercase().as_str(),
+        "png" | "jpg" | "jpeg" | "tif" | "tiff"
+    )
+}
+
+/// Where the thumbnail of `upload_id` at `size` is cached
+pub fn thumbnail_path(dir: &Path, upload_id: &str, size: u32) -> PathBuf {
+    dir.join(format!("{}_{}.png", upload_id, size))
+}
+
+/// Names of every thumbnail an upload can have, e.g. to tell cached files from orphans
+pub fn thumbnail_names(upload_id: &str) -> impl Iterator<Item = String> + '_ {
+    THUMBNAIL_SIZES
+        .iter()
+        .map(move |size| format!("{}_{}.png", upload_id, size))
+}
+
+// 16-bit grayscale (typical for microscope TIFFs) rarely uses the full range; stretch the
+// values that occur to 0-255 so the preview is not almost black
+fn stretch_luma16(image: &DynamicImage) -> Option<DynamicImage> {
+    let DynamicImage::ImageLuma16(gray) = image else {
+        return None;
+    };
+    let (min, max) = gray
+        .pixels()
+        .fold((u16::MAX, u16::MIN), |(min, max), Luma([v])| {
+            (min.min(*v), max.max(*v))
+        });
+    let range = max.saturating_sub(min).max(1) as f32;
+    let stretched = image::ImageBuffer::from_fn(gray.width(), gray.height(), |x, y| {
+        let Luma([v]) = gray.get_pixel(x, y);
+        Luma([((v.saturating_sub(min)) as f32 / range * 255.0).round() as u8])
+    });
+    Some(DynamicImage::ImageLuma8(stretched))
+}
+
+/// Decode an image and scale it to fit in `size` x `size` (aspect ratio kept, never
+/// enlarged), encoded as PNG
+pub fn render_thumbnail(source: &Path, size: u32) -> Result<Vec<u8>, String> {
+    let image = ImageReader::open(source)
+        .map_err(|e| e.to_string())?
+        .with_guessed_format()
+        .map_err(|e| e.to_string())?
+        .decode()
+        .map_err(|e| e.to_string())?;
+
+    let image = if image.width() > size || image.height() > size {
+        image.thumbnail(size, size)
+    } else {
+        image
+    };
+    let image = match stretch_luma16(&image) {
+        Some(stretched) => stretched,
+        None if image.color().has_alpha() => DynamicImage::ImageRgba8(image.to_rgba8()),
+        None if image.color().channel_count() == 1 => DynamicImage::ImageLuma8(image.to_luma8()),
+        None => DynamicImage::ImageRgb8(image.to_rgb8()),
+    };
+
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(|e| e.to_string())?;
+    Ok(png)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_thumbnail() {
+        let dir = std::env::temp_dir().join(format!("datalab-thumbnails-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let wide = dir.join("wide.png");
+        image::RgbImage::from_pixel(1000, 500, image::Rgb([200, 10, 10]))
+            .save(&wide)
+            .unwrap();
+        let gray = dir.join("gray.tif");
+        image::ImageBuffer::from_fn(40, 20, |x, _| Luma([1000 + x as u16]))
+            .save(&gray)
+            .unwrap();
+
+        let thumbnail = image::load_from_memory(&render_thumbnail(&wide, 256).unwrap()).unwrap();
+        assert_eq!((thumbnail.width(), thumbnail.height()), (256, 128));
+
+        // Small images are not enlarged; the 12-bit range is stretched to full contrast
+        let thumbnail = image::load_from_memory(&render_thumbnail(&gray, 256).unwrap())
+            .unwrap()
+            .to_luma8();
+        assert_eq!((thumbnail.width(), thumbnail.height()), (40, 20));
+        assert_eq!(thumbnail.get_pixel(0, 0).0, [0]);
+        assert_eq!(thumbnail.get_pixel(39, 0).0, [255]);
+
+        assert!(render_thumbnail(&dir.join("missing.png"), 256).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,112 @@
+//! Timestamps are stored as text in one form: UTC, millisecond precision, `Z` suffix
+//! (`2024-05-01T12:30:00.000Z`). Every stored value has the same width, so SQLite compares
+//! and sorts them as text in chronological order; anything written to the database goes
+//! through `now()`/`format()`, and times from clients through `parse()`.
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+use chrono_tz::Tz;
+
+pub fn format(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+pub fn now() -> String {
+    format(Utc::now())
+}
+
+/// Read a stored timestamp (or any RFC3339 time)
+pub fn to_utc(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|time| time.with_timezone(&Utc))
+}
+
+/// Seconds from `start` to `end`, e.g. how long a job ran
+pub fn seconds_between(start: &str, end: &str) -> Option<f64> {
+    let elapsed = to_utc(end)? - to_utc(start)?;
+    Some(elapsed.num_milliseconds() as f64 / 1000.0)
+}
+
+// Times without an offset, most specific first
+const NAIVE_FORMATS: &[&str] = &[
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%d %H:%M",
+];
+
+/// Parse a time sent by a client into the stored form. RFC3339 times carry their own offset
+/// (`2024-05-01T14:30:00+02:00`); dates (`2024-05-01`, midnight) and times without an offset
+/// (`2024-05-01T14:30`) are read in `timezone`, an IANA name such as `Europe/Brussels`
+/// (UTC if None).
+pub fn parse(value: &str, timezone: Option<&str>) -> Result<String, String> {
+    let value = value.trim();
+    if let Some(time) = to_utc(value) {
+        return Ok(format(time));
+    }
+
+    let tz: Tz = match timezone.map(str::trim).filter(|tz| !tz.is_empty()) {
+        Some(name) => name
+            .parse()
+            .map_err(|_| format!("Unknown timezone: {}", name))?,
+        None => Tz::UTC,
+    };
+    let naive = NAIVE_FORMATS
+        .iter()
+        .find_map(|fmt| NaiveDateTime::parse_from_str(value, fmt).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })
+        .ok_or_else(|| format!("Invalid timestamp: {}", value))?;
+    // Around a DST change a wall-clock time may occur twice (take the first) or not at all
+    let local = tz
+        .from_local_datetime(&naive)
+        .earliest()
+        .ok_or_else(|| format!("{} does not exist in {}", value, tz))?;
+    Ok(format(local.with_timezone(&Utc)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse("2024-05-01T14:30:00+02:00", None).unwrap(),
+            "2024-05-01T12:30:00.000Z"
+        );
+        assert_eq!(
+            parse("2024-05-01T12:30:00.123456789Z", Some("Asia/Tokyo")).unwrap(),
+            "2024-05-01T12:30:00.123Z"
+        );
+        assert_eq!(
+            parse("2024-05-01", None).unwrap(),
+            "2024-05-01T00:00:00.000Z"
+        );
+        assert_eq!(
+            parse("2024-05-01", Some("Europe/Brussels")).unwrap(),
+            "2024-04-30T22:00:00.000Z"
+        );
+        assert_eq!(
+            parse("2024-01-15 09:00", Some("America/New_York")).unwrap(),
+            "2024-01-15T14:00:00.000Z"
+        );
+        // 02:30 is skipped when clocks go forward
+        assert!(parse("2024-03-31T02:30", Some("Europe/Brussels")).is_err());
+        assert!(parse("2024-05-01", Some("Mars/Olympus")).is_err());
+        assert!(parse("yesterday", None).is_err());
+    }
+
+    #[test]
+    fn test_stored_form_sorts_chronologically() {
+        let earlier = format(to_utc("2024-05-01T23:59:59.5+00:00").unwrap());
+        let later = format(to_utc("2024-05-02T02:00:00+02:00").unwrap());
+        assert_eq!(earlier.len(), later.len());
+        assert!(earlier < later);
+        assert_eq!(seconds_between(&earlier, &later), Some(0.5));
+        assert_eq!(seconds_between("not a time", &later), None);
+    }
+}
@@ -0,0 +1,391 @@
+use crate::tag_expr::TagExpr;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// What trigger conditions can look at for an upload
+#[derive(Debug, Clone, Default)]
+pub struct UploadFacts {
+    pub filename: String,          // original filename
+    pub mime_type: Option<String>, // detected from the content, else as claimed
+    pub file_size: i64,
+    pub tag_ids: HashSet<String>,
+    pub tag_names: HashSet<String>,
+    pub source_function_id: Option<String>, // set for outputs of a function
+    pub derived: bool,                      // output of a function or built-in operation
+}
+
+/// Extra conditions a function can require on top of its input tags, stored as JSON, e.g.
+/// `{"type": "filename", "pattern": "*_raw.csv"}` or `{"type": "size", "max_bytes": 1000000}`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TriggerCondition {
+    /// Glob on the original filename (`*` and `?`, case-insensitive)
+    Filename { pattern: String },
+    /// Glob on the MIME type, e.g. `text/*`; uploads without a type never match
+    Mime { pattern: String },
+    Size {
+        min_bytes: Option<i64>,
+        max_bytes: Option<i64>,
+    },
+    /// `derived: false` for direct uploads only, or only outputs of `source_function_id`
+    Lineage {
+        derived: Option<bool>,
+        source_function_id: Option<String>,
+    },
+    /// Tag expression over tag names, e.g. `raw AND NOT archived`
+    Expression { expression: String },
+}
+
+/// A single check against an upload. New trigger types implement this and are built in
+/// `compile_condition`.
+pub trait Condition: Send + Sync {
+    fn matches(&self, upload: &UploadFacts) -> bool;
+}
+
+/// The upload must carry every tag; an empty list never matches
+pub struct AllTags(pub Vec<String>);
+
+impl Condition for AllTags {
+    fn matches(&self, upload: &UploadFacts) -> bool {
+        !self.0.is_empty() && self.0.iter().all(|tag| upload.tag_ids.contains(tag))
+    }
+}
+
+/// The upload carries none of these tags
+pub struct NoTags(pub Vec<String>);
+
+impl Condition for NoTags {
+    fn matches(&self, upload: &UploadFacts) -> bool {
+        !self.0.iter().any(|tag| upload.tag_ids.contains(tag))
+    }
+}
+
+/// Every inner condition must hold
+pub struct AllOf(pub Vec<Box<dyn Condition>>);
+
+impl Condition for AllOf {
+    fn matches(&self, upload: &UploadFacts) -> bool {
+        self.0.iter().all(|condition| condition.matches(upload))
+    }
+}
+
+struct FilenameGlob(String);
+
+impl Condition for FilenameGlob {
+    fn matches(&self, upload: &UploadFacts) -> bool {
+        glob_match(&self.0, &upload.filename.to_lowercase())
+    }
+}
+
+struct MimeGlob(String);
+
+impl Condition for MimeGlob {
+    fn matches(&self, upload: &UploadFacts) -> bool {
+        upload
+            .mime_type
+            .as_deref()
+            .is_some_and(|mime| glob_match(&self.0, &mime.to_lowercase()))
+    }
+}
+
+struct SizeRange {
+    min_bytes: Option<i64>,
+    max_bytes: Option<i64>,
+}
+
+impl Condition for SizeRange {
+    fn matches(&self, upload: &UploadFacts) -> bool {
+        self.min_bytes.is_none_or(|min| upload.file_size >= min)
+            && self.max_bytes.is_none_or(|max| upload.file_size <= max)
+    }
+}
+
+struct Lineage {
+    derived: Option<bool>,
+    source_function_id: Option<String>,
+}
+
+impl Condition for Lineage {
+    fn matches(&self, upload: &UploadFacts) -> bool {
+        self.derived.is_none_or(|derived| upload.derived == derived)
+            && self
+                .source_function_id
+                .as_ref()
+                .is_none_or(|function_id| upload.source_function_id.as_ref() == Some(function_id))
+    }
+}
+
+struct Expression(TagExpr);
+
+impl Condition for Expression {
+    fn matches(&self, upload: &UploadFacts) -> bool {
+        self.0
+            .matches(&upload.tag_names.iter().map(String::as_str).collect())
+    }
+}
+
+/// Match `*` (any run of characters) and `?` (one character) against the whole text
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None; // position after the last `*`, and text position
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p + 1, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_t)) => {
+                    p = star_p;
+                    t = star_t + 1;
+                    backtrack = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Build the evaluator for a stored condition, rejecting ones that can never work
+pub fn compile_condition(condition: &TriggerCondition) -> Result<Box<dyn Condition>, String> {
+    Ok(match condition {
+        TriggerCondition::Filename { pattern } => {
+            if pattern.trim().is_empty() {
+                return Err("Filename pattern must not be empty".to_string());
+            }
+            Box::new(FilenameGlob(pattern.to_lowercase()))
+        }
+        TriggerCondition::Mime { pattern } => {
+            if pattern.trim().is_empty() {
+                return Err("MIME pattern must not be empty".to_string());
+            }
+            Box::new(MimeGlob(pattern.to_lowercase()))
+        }
+        TriggerCondition::Size {
+            min_bytes,
+            max_bytes,
+        } => {
+            if let (Some(min), Some(max)) = (min_bytes, max_bytes) {
+                if min > max {
+                    return Err("min_bytes must not exceed max_bytes".to_string());
+                }
+            }
+            Box::new(SizeRange {
+                min_bytes: *min_bytes,
+                max_bytes: *max_bytes,
+            })
+        }
+        TriggerCondition::Lineage {
+            derived,
+            source_function_id,
+        } => {
+            if *derived == Some(false) && source_function_id.is_some() {
+                return Err("Direct uploads have no source function".to_string());
+            }
+            Box::new(Lineage {
+                derived: *derived,
+                source_function_id: source_function_id.clone(),
+            })
+        }
+        TriggerCondition::Expression { expression } => {
+            Box::new(Expression(TagExpr::parse(expression)?))
+        }
+    })
+}
+
+/// An enabled function and everything an upload must satisfy to run it
+pub struct FunctionTrigger {
+    pub function_id: String,
+    pub condition: Box<dyn Condition>,
+}
+
+impl FunctionTrigger {
+    /// Input tags are always required and excluded tags always refused; extra conditions
+    /// narrow them down further
+    pub fn new(
+        function_id: String,
+        input_tag_ids: Vec<String>,
+        excluded_tag_ids: Vec<String>,
+        conditions: &[TriggerCondition],
+    ) -> Result<Self, String> {
+        let mut all: Vec<Box<dyn Condition>> = vec![
+            Box::new(AllTags(input_tag_ids)),
+            Box::new(NoTags(excluded_tag_ids)),
+        ];
+        for condition in conditions {
+            all.push(compile_condition(condition)?);
+        }
+        Ok(Self {
+            function_id,
+            condition: Box::new(AllOf(all)),
+        })
+    }
+}
+
+/// Decides which functions an upload triggers
+pub trait TriggerEngine: Send + Sync {
+    fn select<'a>(
+        &self,
+        upload: &UploadFacts,
+        triggers: &'a [FunctionTrigger],
+    ) -> Vec<&'a FunctionTrigger>;
+}
+
+/// Runs every function whose conditions all hold
+pub struct ConditionEngine;
+
+impl TriggerEngine for ConditionEngine {
+    fn select<'a>(
+        &self,
+        upload: &UploadFacts,
+        triggers: &'a [FunctionTrigger],
+    ) -> Vec<&'a FunctionTrigger> {
+        triggers
+            .iter()
+            .filter(|trigger| trigger.condition.matches(upload))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upload() -> UploadFacts {
+        UploadFacts {
+            filename: "Plate_07_raw.CSV".to_string(),
+            mime_type: Some("text/csv".to_string()),
+            file_size: 2048,
+            tag_ids: ["t-csv", "t-raw"].iter().map(|s| s.to_string()).collect(),
+            tag_names: [".csv", "raw"].iter().map(|s| s.to_string()).collect(),
+            source_function_id: None,
+            derived: false,
+        }
+    }
+
+    fn trigger(tags: &[&str], conditions: &[TriggerCondition]) -> FunctionTrigger {
+        FunctionTrigger::new(
+            "f".to_string(),
+            tags.iter().map(|s| s.to_string()).collect(),
+            Vec::new(),
+            conditions,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*_raw.csv", "plate_07_raw.csv"));
+        assert!(glob_match("plate_??_*", "plate_07_raw.csv"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(!glob_match("*_raw.csv", "plate_07_raw.csv.bak"));
+        assert!(!glob_match("plate_?_*", "plate_07_raw.csv"));
+    }
+
+    #[test]
+    fn test_input_tags_are_required() {
+        let upload = upload();
+        assert!(trigger(&["t-csv", "t-raw"], &[]).condition.matches(&upload));
+        assert!(!trigger(&["t-csv", "t-other"], &[])
+            .condition
+            .matches(&upload));
+        assert!(!trigger(&[], &[]).condition.matches(&upload));
+    }
+
+    #[test]
+    fn test_excluded_tags_are_refused() {
+        let upload = upload();
+        let excluding = |excluded: &[&str]| {
+            FunctionTrigger::new(
+                "f".to_string(),
+                vec!["t-csv".to_string()],
+                excluded.iter().map(|s| s.to_string()).collect(),
+                &[],
+            )
+            .unwrap()
+        };
+        assert!(excluding(&["t-processed"]).condition.matches(&upload));
+        assert!(!excluding(&["t-processed", "t-raw"])
+            .condition
+            .matches(&upload));
+    }
+
+    #[test]
+    fn test_conditions_compose() {
+        let upload = upload();
+        let matching = [
+            TriggerCondition::Filename {
+                pattern: "*_RAW.csv".to_string(),
+            },
+            TriggerCondition::Mime {
+                pattern: "text/*".to_string(),
+            },
+            TriggerCondition::Size {
+                min_bytes: Some(1024),
+                max_bytes: None,
+            },
+            TriggerCondition::Lineage {
+                derived: Some(false),
+                source_function_id: None,
+            },
+            TriggerCondition::Expression {
+                expression: "raw AND NOT archived".to_string(),
+            },
+        ];
+        assert!(trigger(&["t-csv"], &matching).condition.matches(&upload));
+
+        let too_small = TriggerCondition::Size {
+            min_bytes: None,
+            max_bytes: Some(1000),
+        };
+        let from_function = TriggerCondition::Lineage {
+            derived: None,
+            source_function_id: Some("f1".to_string()),
+        };
+        for condition in [too_small, from_function] {
+            assert!(!trigger(&["t-csv"], &[condition]).condition.matches(&upload));
+        }
+    }
+
+    #[test]
+    fn test_engine_selects_matching_functions() {
+        let triggers = vec![
+            trigger(&["t-csv"], &[]),
+            trigger(
+                &["t-csv"],
+                &[TriggerCondition::Filename {
+                    pattern: "*.parquet".to_string(),
+                }],
+            ),
+        ];
+        assert_eq!(ConditionEngine.select(&upload(), &triggers).len(), 1);
+    }
+
+    #[test]
+    fn test_invalid_conditions_are_rejected() {
+        let invalid = [
+            TriggerCondition::Expression {
+                expression: "(raw".to_string(),
+            },
+            TriggerCondition::Size {
+                min_bytes: Some(10),
+                max_bytes: Some(1),
+            },
+            TriggerCondition::Filename {
+                pattern: " ".to_string(),
+            },
+        ];
+        for condition in invalid {
+            assert!(compile_condition(&condition).is_err());
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use serde::Serialize;
+
+/// A unit as a linear map onto its dimension's base unit: `base = value * factor + offset`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Unit {
+    pub symbol: &'static str,
+    pub dimension: &'static str,
+    pub factor: f64,
+    pub offset: f64,
+}
+
+const fn unit(symbol: &'static str, dimension: &'static str, factor: f64) -> Unit {
+    Unit {
+        symbol,
+        dimension,
+        factor,
+        offset: 0.0,
+    }
+}
+
+// The first unit of each dimension is its base unit
+const UNITS: &[Unit] = &[
+    unit("Pa", "pressure", 1.0),
+    unit("hPa", "pressure", 1e2),
+    unit("kPa", "pressure", 1e3),
+    unit("MPa", "pressure", 1e6),
+    unit("bar", "pressure", 1e5),
+    unit("mbar", "pressure", 1e2),
+    unit("atm", "pressure", 101_325.0),
+    unit("psi", "pressure", 6_894.757_293_168),
+    unit("Torr", "pressure", 133.322_368_421),
+    unit("mmHg", "pressure", 133.322_387_415),
+    Unit {
+        symbol: "K",
+        dimension: "temperature",
+        factor: 1.0,
+        offset: 0.0,
+    },
+    Unit {
+        symbol: "°C",
+        dimension: "temperature",
+        factor: 1.0,
+        offset: 273.15,
+    },
+    Unit {
+        symbol: "°F",
+        dimension: "temperature",
+        factor: 5.0 / 9.0,
+        offset: 459.67 * 5.0 / 9.0,
+    },
+    unit("m", "length", 1.0),
+    unit("km", "length", 1e3),
+    unit("cm", "length", 1e-2),
+    unit("mm", "length", 1e-3),
+    unit("µm", "length", 1e-6),
+    unit("nm", "length", 1e-9),
+    unit("in", "length", 0.0254),
+    unit("ft", "length", 0.3048),
+    unit("kg", "mass", 1.0),
+    unit("g", "mass", 1e-3),
+    unit("mg", "mass", 1e-6),
+    unit("µg", "mass", 1e-9),
+    unit("lb", "mass", 0.453_592_37),
+    unit("s", "time", 1.0),
+    unit("ms", "time", 1e-3),
+    unit("µs", "time", 1e-6),
+    unit("min", "time", 60.0),
+    unit("h", "time", 3600.0),
+    unit("L", "volume", 1.0),
+    unit("mL", "volume", 1e-3),
+    unit("µL", "volume", 1e-6),
+    unit("J", "energy", 1.0),
+    unit("kJ", "energy", 1e3),
+    unit("cal", "energy", 4.184),
+    unit("kcal", "energy", 4184.0),
+    unit("Wh", "energy", 3600.0),
+    unit("kWh", "energy", 3.6e6),
+    unit("W", "power", 1.0),
+    unit("mW", "power", 1e-3),
+    unit("kW", "power", 1e3),
+    unit("V", "voltage", 1.0),
+    unit("mV", "voltage", 1e-3),
+    unit("A", "current", 1.0),
+    unit("mA", "current", 1e-3),
+    unit("µA", "current", 1e-6),
+    unit("Hz", "frequency", 1.0),
+    unit("kHz", "frequency", 1e3),
+    unit("MHz", "frequency", 1e6),
+    unit("GHz", "frequency", 1e9),
+];
+
+/// Common spellings that map onto a canonical symbol
+const ALIASES: &[(&str, &str)] = &[
+    ("C", "°C"),
+    ("degC", "°C"),
+    ("deg C", "°C"),
+    ("℃", "°C"),
+    ("F", "°F"),
+    ("degF", "°F"),
+    ("deg F", "°F"),
+    ("um", "µm"),
+    ("μm", "µm"),
+    ("ug", "µg"),
+    ("μg", "µg"),
+    ("us", "µs"),
+    ("μs", "µs"),
+    ("sec", "s"),
+    ("hr", "h"),
+    ("l", "L"),
+    ("ml", "mL"),
+    ("uL", "µL"),
+    ("ul", "µL"),
+    ("μL", "µL"),
+    ("uA", "µA"),
+    ("μA", "µA"),
+];
+
+pub fn find_unit(symbol: &str) -> Option<Unit> {
+    let symbol = symbol.trim();
+    let symbol = ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == symbol)
+        .map_or(symbol, |(_, canonical)| *canonical);
+    UNITS.iter().copied().find(|u| u.symbol == symbol)
+}
+
+pub fn base_unit(dimension: &str) -> Unit {
+    *UNITS
+        .iter()
+        .find(|u| u.dimension == dimension)
+        .expect("every dimension has a base unit")
+}
+
+/// Linear coefficients `(scale, shift)` so that `to = from * scale + shift`
+pub fn conversion(from: Unit, to: Unit) -> Result<(f64, f64), String> {
+    if from.dimension != to.dimension {
+        return Err(format!(
+            "Cannot convert {} ({}) to {} ({})",
+            from.symbol, from.dimension, to.symbol, to.dimension
+        ));
+    }
+    Ok((
+        from.factor / to.factor,
+        (from.offset - to.offset) / to.factor,
+    ))
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct DetectedUnit {
+    pub column: String,
+    pub quantity: String,
+    pub unit: String,
+    pub dimension: String,
+    pub base_unit: String,
+}
+
+/// Split a header like `pressure (kPa)` or `temp [°C]` into its quantity and unit
+pub fn parse_header(header: &str) -> Option<(String, Unit)> {
+    let header = header.trim_end();
+    let close = header.chars().last()?;
+    let open = match close {
+        ')' => '(',
+        ']' => '[',
+        _ => return None,
+    };
+    let start = header.rfind(open)?;
+    let unit = find_unit(&header[start + 1..header.len() - 1])?;
+    Some((header[..start].trim().to_string(), unit))
+}
+
+/// Rewrite a header for a new unit, keeping the original bracket style
+pub fn rename_header(header: &str, quantity: &str, to: Unit) -> String {
+    if header.trim_end().ends_with(']') {
+        format!("{} [{}]", quantity, to.symbol)
+    } else {
+        format!("{} ({})", quantity, to.symbol)
+    }
+}
+
+pub fn detect_units<'a>(headers: impl IntoIterator<Item = &'a str>) -> Vec<DetectedUnit> {
+    headers
+        .into_iter()
+        .filter_map(|header| {
+            let (quantity, unit) = parse_header(header)?;
+            Some(DetectedUnit {
+                column: header.to_string(),
+                quantity,
+                unit: unit.symbol.to_string(),
+                dimension: unit.dimension.to_string(),
+                base_unit: base_unit(unit.dimension).symbol.to_string(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(value: f64, from: &str, to: &str) -> f64 {
+        let (scale, shift) = conversion(find_unit(from).unwrap(), find_unit(to).unwrap()).unwrap();
+        value * scale + shift
+    }
+
+    #[test]
+    fn test_detects_units_in_headers() {
+        let detected = detect_units(["time", "pressure (kPa)", "temp [degC]", "id (unknown)"]);
+        assert_eq!(detected.len(), 2);
+        assert_eq!(detected[0].quantity, "pressure");
+        assert_eq!(detected[0].unit, "kPa");
+        assert_eq!(detected[0].base_unit, "Pa");
+        assert_eq!(detected[1].unit, "°C");
+        assert_eq!(detected[1].dimension, "temperature");
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert!((convert(101.325, "kPa", "atm") - 1.0).abs() < 1e-9);
+        assert!((convert(100.0, "C", "K") - 373.15).abs() < 1e-9);
+        assert!((convert(212.0, "°F", "°C") - 100.0).abs() < 1e-9);
+        assert!((convert(1.5, "h", "min") - 90.0).abs() < 1e-9);
+        assert!(conversion(find_unit("kPa").unwrap(), find_unit("K").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_rename_header() {
+        let (quantity, _) = parse_header("temp [°F]").unwrap();
+        assert_eq!(
+            rename_header("temp [°F]", &quantity, find_unit("K").unwrap()),
+            "temp [K]"
+        );
+    }
+}
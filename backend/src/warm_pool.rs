@@ -0,0 +1,295 @@
+//! Warm environments for local function runs. `uv run --script` resolves a script's inline
+//! dependencies on every start, which costs seconds for scripts that themselves take
+//! milliseconds. With a pool, the environment of each function version is resolved once, by
+//! running just the script's PEP 723 header under uv, and later jobs start the resolved
+//! interpreter directly. The environments live in uv's cache; one that was pruned is resolved
+//! again on its next run.
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Instant;
+use tokio::process::Command;
+
+/// The PEP 723 `# /// script` block of a script, empty if it has none
+pub fn script_metadata(script: &str) -> String {
+    let mut block = String::new();
+    let mut inside = false;
+    for line in script.lines() {
+        if !inside && line.trim_end() == "# /// script" {
+            inside = true;
+        } else if inside && line.trim_end() == "# ///" {
+            block.push_str(line);
+            block.push('\n');
+            return block;
+        } else if inside && !line.starts_with('#') {
+            break; // not a metadata block after all
+        }
+        if inside {
+            block.push_str(line);
+            block.push('\n');
+        }
+    }
+    String::new()
+}
+
+/// Identifies one version of a function's script
+pub fn script_version(script: &str) -> String {
+    hex::encode(Sha256::digest(script.as_bytes()))
+}
+
+struct WarmEnv {
+    script: String, // script filename, for the summary
+    python: PathBuf,
+    cold_start_seconds: f64, // `uv run --script` of the bare header
+    warm_start_seconds: f64, // the resolved interpreter on its own
+    runs: u64,
+    last_used: Instant,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WarmEnvSummary {
+    pub version: String,
+    pub script: String,
+    pub python: String,
+    pub cold_start_seconds: f64,
+    pub warm_start_seconds: f64,
+    pub runs: u64,
+    pub saved_seconds: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WarmPoolSummary {
+    pub size: usize,
+    pub hits: u64,          // runs that started a resolved interpreter
+    pub misses: u64,        // runs that had to resolve their environment first
+    pub failures: u64,      // resolutions that failed; those runs fell back to `uv run`
+    pub saved_seconds: f64, // estimated startup time saved by all hits
+    pub environments: Vec<WarmEnvSummary>,
+}
+
+#[derive(Default)]
+struct PoolState {
+    envs: HashMap<String, WarmEnv>, // by script version
+    hits: u64,
+    misses: u64,
+    failures: u64,
+    saved_seconds: f64, // includes environments evicted since
+}
+
+pub struct WarmPool {
+    size: usize, // environments kept; the least recently used one makes room
+    uv_bin: PathBuf,
+    work_dir: PathBuf, // for the header-only probe scripts
+    state: Mutex<PoolState>,
+    resolving: tokio::sync::Mutex<()>, // one resolution at a time
+}
+
+impl WarmPool {
+    pub fn new(size: usize, uv_bin: PathBuf, work_dir: PathBuf) -> Self {
+        Self {
+            size,
+            uv_bin,
+            work_dir,
+            state: Mutex::new(PoolState::default()),
+            resolving: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// The interpreter to start `script` with, resolving its environment on first use; None
+    /// if it could not be resolved, in which case the run goes through `uv run` as usual
+    pub async fn python_for(&self, script_filename: &str, script: &str) -> Option<PathBuf> {
+        let version = script_version(script);
+        if let Some(python) = self.take_hit(&version) {
+            return Some(python);
+        }
+
+        let _resolving = self.resolving.lock().await;
+        // Resolved by another run while this one waited
+        if let Some(python) = self.take_hit(&version) {
+            return Some(python);
+        }
+        match self.resolve(&script_metadata(script)).await {
+            Ok((python, cold_start_seconds, warm_start_seconds)) => {
+                tracing::info!(
+                    "Warmed the environment of {} (startup {:.2}s cold, {:.2}s warm)",
+                    script_filename,
+                    cold_start_seconds,
+                    warm_start_seconds
+                );
+                let mut state = self.state.lock().unwrap();
+                state.misses += 1;
+                if state.envs.len() >= self.size && !state.envs.contains_key(&version) {
+                    let oldest = state
+                        .envs
+                        .iter()
+                        .min_by_key(|(_, env)| env.last_used)
+                        .map(|(version, _)| version.clone());
+                    if let Some(oldest) = oldest {
+                        state.envs.remove(&oldest);
+                    }
+                }
+                state.envs.insert(
+                    version,
+                    WarmEnv {
+                        script: script_filename.to_string(),
+                        python: python.clone(),
+                        cold_start_seconds,
+                        warm_start_seconds,
+                        runs: 1,
+                        last_used: Instant::now(),
+                    },
+                );
+                Some(python)
+            }
+            Err(e) => {
+                tracing::warn!(
+                    "Could not warm the environment of {}: {}",
+                    script_filename,
+                    e
+                );
+                self.state.lock().unwrap().failures += 1;
+                None
+            }
+        }
+    }
+
+    // Count a run of an already resolved version; drops environments uv no longer has
+    fn take_hit(&self, version: &str) -> Option<PathBuf> {
+        let mut state = self.state.lock().unwrap();
+        let env = state.envs.get_mut(version)?;
+        if !env.python.exists() {
+            state.envs.remove(version);
+            return None;
+        }
+        env.runs += 1;
+        env.last_used = Instant::now();
+        let python = env.python.clone();
+        let saved = (env.cold_start_seconds - env.warm_start_seconds).max(0.0);
+        state.hits += 1;
+        state.saved_seconds += saved;
+        Some(python)
+    }
+
+    // Run the bare header under uv, which resolves the environment and reports its
+    // interpreter, then time that interpreter starting on its own
+    async fn resolve(&self, metadata: &str) -> Result<(PathBuf, f64, f64), String> {
+        tokio::fs::create_dir_all(&self.work_dir)
+            .await
+            .map_err(|e| format!("Failed to create {}: {}", self.work_dir.display(), e))?;
+        let probe_path = self
+            .work_dir
+            .join(format!("warm_{}.py", uuid::Uuid::new_v4()));
+        tokio::fs::write(
+            &probe_path,
+            format!("{}import sys\nprint(sys.executable)\n", metadata),
+        )
+        .await
+        .map_err(|e| format!("Failed to write probe script: {}", e))?;
+
+        let started = Instant::now();
+        let output = Command::new(&self.uv_bin)
+            .arg("run")
+            .arg("--script")
+            .arg(&probe_path)
+            .output()
+            .await;
+        let cold_start_seconds = started.elapsed().as_secs_f64();
+        let _ = tokio::fs::remove_file(&probe_path).await;
+        let output = output.map_err(|e| format!("Failed to run uv: {}", e))?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+        }
+        let python = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+        if !python.is_absolute() || !python.exists() {
+            return Err(format!("uv reported no interpreter ({})", python.display()));
+        }
+
+        let started = Instant::now();
+        Command::new(&python)
+            .arg("-c")
+            .arg("pass")
+            .output()
+            .await
+            .map_err(|e| format!("Failed to start {}: {}", python.display(), e))?;
+        Ok((python, cold_start_seconds, started.elapsed().as_secs_f64()))
+    }
+
+    pub fn summary(&self) -> WarmPoolSummary {
+        let state = self.state.lock().unwrap();
+        let mut environments: Vec<WarmEnvSummary> = state
+            .envs
+            .iter()
+            .map(|(version, env)| WarmEnvSummary {
+                version: version[..12].to_string(),
+                script: env.script.clone(),
+                python: env.python.to_string_lossy().to_string(),
+                cold_start_seconds: env.cold_start_seconds,
+                warm_start_seconds: env.warm_start_seconds,
+                runs: env.runs,
+                saved_seconds: (env.runs - 1) as f64
+                    * (env.cold_start_seconds - env.warm_start_seconds).max(0.0),
+            })
+            .collect();
+        environments.sort_by(|a, b| b.runs.cmp(&a.runs).then_with(|| a.script.cmp(&b.script)));
+        WarmPoolSummary {
+            size: self.size,
+            hits: state.hits,
+            misses: state.misses,
+            failures: state.failures,
+            saved_seconds: state.saved_seconds,
+            environments,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_script_metadata() {
+        let script = "# /// script\n# dependencies = [\"polars\"]\n# ///\n\nimport polars\n";
+        assert_eq!(
+            script_metadata(script),
+            "# /// script\n# dependencies = [\"polars\"]\n# ///\n"
+        );
+        assert_eq!(script_metadata("import sys\n"), "");
+        assert_eq!(script_metadata("# /// script\nimport sys\n# ///\n"), "");
+    }
+
+    #[tokio::test]
+    async fn test_environment_is_resolved_once_per_version() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("datalab-warm-pool-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        // Stands in for `uv`: counts resolutions and reports `true` as the interpreter
+        let uv_bin = dir.join("uv");
+        std::fs::write(
+            &uv_bin,
+            format!(
+                "#!/bin/sh\necho x >> {}/resolved\necho /bin/true\n",
+                dir.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&uv_bin, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let pool = WarmPool::new(1, uv_bin, dir.clone());
+        let python = PathBuf::from("/bin/true");
+        assert_eq!(pool.python_for("a.py", "v1").await, Some(python.clone()));
+        assert_eq!(pool.python_for("a.py", "v1").await, Some(python.clone()));
+        assert_eq!(pool.python_for("a.py", "v2").await, Some(python));
+        let resolved = std::fs::read_to_string(dir.join("resolved")).unwrap();
+        assert_eq!(resolved.lines().count(), 2);
+
+        let summary = pool.summary();
+        assert_eq!((summary.hits, summary.misses), (1, 2));
+        assert_eq!(summary.environments.len(), 1); // v1 made room for v2
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,153 @@
+//! Watch folders: local directories instruments drop files into. A background task polls them
+//! and registers each new file as an upload once it has stopped changing; the files are left
+//! in place, and `watched_files` remembers which ones were picked up.
+
+use crate::models::WatchedFile;
+use crate::timestamps;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// A directory given with --watch-dir, and the tags its files get
+#[derive(Debug, Clone, Serialize)]
+pub struct WatchFolder {
+    pub path: PathBuf,
+    pub tags: Vec<String>, // tag names, created if missing
+}
+
+/// `GET /watch-folders`
+#[derive(Debug, Serialize)]
+pub struct WatchFolderReport {
+    pub folders: Vec<WatchFolder>,
+    pub files: Vec<WatchedFile>, // most recently picked up first
+}
+
+/// A file in a watch folder as one poll found it
+#[derive(Debug, Clone, PartialEq)]
+pub struct FolderEntry {
+    pub path: PathBuf,
+    pub name: String,
+    pub size: u64,
+    pub modified: String, // stored form, see `timestamps`
+}
+
+/// Regular files directly in `dir`. Hidden files are skipped, as tools often copy into a
+/// `.name.part` file and rename it when done.
+pub async fn list_files(dir: &Path) -> std::io::Result<Vec<FolderEntry>> {
+    let mut entries = Vec::new();
+    let mut reader = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = reader.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') {
+            continue;
+        }
+        let Ok(metadata) = entry.metadata().await else {
+            continue; // removed since it was listed
+        };
+        if !metadata.is_file() {
+            continue;
+        }
+        let modified = metadata
+            .modified()
+            .map(|time| timestamps::format(time.into()))
+            .unwrap_or_default();
+        entries.push(FolderEntry {
+            path: entry.path(),
+            name,
+            size: metadata.len(),
+            modified,
+        });
+    }
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+/// Remembers what the previous poll saw, so files that are still being written are left
+/// for a later poll
+#[derive(Debug, Default)]
+pub struct SettleTracker {
+    previous: HashMap<PathBuf, Seen>,
+}
+
+#[derive(Debug)]
+struct Seen {
+    size: u64,
+    modified: String,
+    reported: bool,
+}
+
+impl SettleTracker {
+    /// The entries that have not changed since the previous poll, each once until it changes
+    /// again; files that disappeared are forgotten
+    pub fn settled(&mut self, entries: Vec<FolderEntry>) -> Vec<FolderEntry> {
+        let previous = std::mem::take(&mut self.previous);
+        let mut settled = Vec::new();
+        for entry in entries {
+            let reported = match previous.get(&entry.path) {
+                Some(seen) if seen.size == entry.size && seen.modified == entry.modified => {
+                    if !seen.reported {
+                        settled.push(entry.clone());
+                    }
+                    true
+                }
+                _ => false,
+            };
+            self.previous.insert(
+                entry.path,
+                Seen {
+                    size: entry.size,
+                    modified: entry.modified,
+                    reported,
+                },
+            );
+        }
+        settled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, size: u64) -> FolderEntry {
+        FolderEntry {
+            path: PathBuf::from("/watch").join(name),
+            name: name.to_string(),
+            size,
+            modified: "2024-05-01T12:00:00.000Z".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_settle_tracker() {
+        let mut tracker = SettleTracker::default();
+        assert!(tracker
+            .settled(vec![entry("a.csv", 10), entry("b.csv", 5)])
+            .is_empty());
+        // b.csv is still growing
+        let settled = tracker.settled(vec![entry("a.csv", 10), entry("b.csv", 8)]);
+        assert_eq!(settled, vec![entry("a.csv", 10)]);
+        let settled = tracker.settled(vec![entry("a.csv", 10), entry("b.csv", 8)]);
+        assert_eq!(settled, vec![entry("b.csv", 8)]);
+        assert!(tracker.settled(vec![entry("b.csv", 8)]).is_empty());
+        // a.csv disappeared and came back: it has to settle again
+        assert!(tracker.settled(vec![entry("a.csv", 10)]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_list_files() {
+        let dir = std::env::temp_dir().join(format!("datalab-watch-{}", std::process::id()));
+        tokio::fs::create_dir_all(dir.join("sub")).await.unwrap();
+        tokio::fs::write(dir.join("run1.csv"), b"a,b\n")
+            .await
+            .unwrap();
+        tokio::fs::write(dir.join(".run2.csv.part"), b"a")
+            .await
+            .unwrap();
+        let entries = list_files(&dir).await.unwrap();
+        let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["run1.csv"]);
+        assert_eq!(entries[0].size, 4);
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
@@ -0,0 +1,182 @@
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_POINTS: usize = 1000;
+pub const MAX_POINTS: usize = 10_000;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WaveformQuery {
+    pub points: Option<usize>,
+}
+
+/// Min/max/RMS of the mono mixdown over one bucket of frames, normalized to [-1, 1]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct EnvelopePoint {
+    pub min: f32,
+    pub max: f32,
+    pub rms: f32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WaveformSummary {
+    pub format: String,
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub bits_per_sample: u16,
+    pub total_frames: u64,
+    pub duration_seconds: f64,
+    pub frames_per_point: u64,
+    pub envelope: Vec<EnvelopePoint>,
+}
+
+pub fn is_audio_extension(extension: &str) -> bool {
+    matches!(extension.to_lowercase().as_str(), "wav" | "flac")
+}
+
+/// Accumulates interleaved samples into envelope buckets
+struct EnvelopeBuilder {
+    channels: usize,
+    frames_per_point: u64,
+    envelope: Vec<EnvelopePoint>,
+    channel_index: usize,
+    frame_sum: f32,
+    bucket_frames: u64,
+    bucket_min: f32,
+    bucket_max: f32,
+    bucket_square_sum: f64,
+}
+
+impl EnvelopeBuilder {
+    fn new(channels: usize, total_frames: u64, points: usize) -> Self {
+        let frames_per_point = total_frames.div_ceil(points as u64).max(1);
+        Self {
+            channels: channels.max(1),
+            frames_per_point,
+            envelope: Vec::with_capacity(points),
+            channel_index: 0,
+            frame_sum: 0.0,
+            bucket_frames: 0,
+            bucket_min: f32::MAX,
+            bucket_max: f32::MIN,
+            bucket_square_sum: 0.0,
+        }
+    }
+
+    fn push(&mut self, sample: f32) {
+        self.frame_sum += sample;
+        self.channel_index += 1;
+        if self.channel_index < self.channels {
+            return;
+        }
+
+        let value = self.frame_sum / self.channels as f32;
+        self.channel_index = 0;
+        self.frame_sum = 0.0;
+
+        self.bucket_min = self.bucket_min.min(value);
+        self.bucket_max = self.bucket_max.max(value);
+        self.bucket_square_sum += (value as f64) * (value as f64);
+        self.bucket_frames += 1;
+
+        if self.bucket_frames == self.frames_per_point {
+            self.flush();
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.bucket_frames == 0 {
+            return;
+        }
+        self.envelope.push(EnvelopePoint {
+            min: self.bucket_min,
+            max: self.bucket_max,
+            rms: (self.bucket_square_sum / self.bucket_frames as f64).sqrt() as f32,
+        });
+        self.bucket_frames = 0;
+        self.bucket_min = f32::MAX;
+        self.bucket_max = f32::MIN;
+        self.bucket_square_sum = 0.0;
+    }
+
+    fn finish(mut self) -> (u64, Vec<EnvelopePoint>) {
+        self.flush();
+        (self.frames_per_point, self.envelope)
+    }
+}
+
+pub fn summarize_audio(
+    file_path: &str,
+    file_extension: &str,
+    points: usize,
+) -> Result<WaveformSummary, Box<dyn std::error::Error>> {
+    let points = points.clamp(1, MAX_POINTS);
+    match file_extension.to_lowercase().as_str() {
+        "wav" => summarize_wav(file_path, points),
+        "flac" => summarize_flac(file_path, points),
+        _ => Err(format!("Unsupported audio type: {}", file_extension).into()),
+    }
+}
+
+fn summarize_wav(
+    file_path: &str,
+    points: usize,
+) -> Result<WaveformSummary, Box<dyn std::error::Error>> {
+    let mut reader = hound::WavReader::open(file_path)?;
+    let spec = reader.spec();
+    let total_frames = reader.duration() as u64;
+    let mut builder = EnvelopeBuilder::new(spec.channels as usize, total_frames, points);
+
+    match spec.sample_format {
+        hound::SampleFormat::Float => {
+            for sample in reader.samples::<f32>() {
+                builder.push(sample?);
+            }
+        }
+        hound::SampleFormat::Int => {
+            let full_scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+            for sample in reader.samples::<i32>() {
+                builder.push(sample? as f32 / full_scale);
+            }
+        }
+    }
+
+    let (frames_per_point, envelope) = builder.finish();
+    Ok(WaveformSummary {
+        format: "wav".to_string(),
+        sample_rate: spec.sample_rate,
+        channels: spec.channels,
+        bits_per_sample: spec.bits_per_sample,
+        total_frames,
+        duration_seconds: total_frames as f64 / spec.sample_rate as f64,
+        frames_per_point,
+        envelope,
+    })
+}
+
+fn summarize_flac(
+    file_path: &str,
+    points: usize,
+) -> Result<WaveformSummary, Box<dyn std::error::Error>> {
+    let mut reader = claxon::FlacReader::open(file_path)?;
+    let info = reader.streaminfo();
+    let total_frames = info
+        .samples
+        .ok_or("FLAC stream does not declare its length")?;
+    let full_scale = (1i64 << (info.bits_per_sample - 1)) as f32;
+    let mut builder = EnvelopeBuilder::new(info.channels as usize, total_frames, points);
+
+    for sample in reader.samples() {
+        builder.push(sample? as f32 / full_scale);
+    }
+
+    let (frames_per_point, envelope) = builder.finish();
+    Ok(WaveformSummary {
+        format: "flac".to_string(),
+        sample_rate: info.sample_rate,
+        channels: info.channels as u16,
+        bits_per_sample: info.bits_per_sample as u16,
+        total_frames,
+        duration_seconds: total_frames as f64 / info.sample_rate as f64,
+        frames_per_point,
+        envelope,
+    })
+}
@@ -0,0 +1,146 @@
+//! WebDAV (class 1) responses for mounting the upload store as a network drive: uploads
+//! appear as the files of one flat folder. The requests themselves are served by the
+//! `/dav` handler in `routes`.
+
+use crate::timestamps;
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+
+/// What `--webdav` allows
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum DavAccess {
+    ReadOnly,  // browse and download
+    ReadWrite, // also create uploads (PUT) and delete them (DELETE)
+}
+
+impl DavAccess {
+    /// The `Allow` header for the folder and its files
+    pub fn allowed_methods(self) -> &'static str {
+        match self {
+            DavAccess::ReadOnly => "OPTIONS, PROPFIND, GET, HEAD",
+            DavAccess::ReadWrite => "OPTIONS, PROPFIND, GET, HEAD, PUT, DELETE",
+        }
+    }
+}
+
+/// One `<response>` of a PROPFIND answer
+pub struct DavResource {
+    pub href: String,
+    pub collection: bool,
+    pub size: i64,
+    pub content_type: Option<String>,
+    pub modified: Option<String>, // stored form, see `timestamps`
+    pub etag: Option<String>,
+}
+
+// Everything but unreserved characters is encoded in a path segment
+const SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'[')
+    .add(b'\\')
+    .add(b']')
+    .add(b'^')
+    .add(b'`')
+    .add(b'{')
+    .add(b'|')
+    .add(b'}');
+
+/// The href of a file in the folder mounted at `root` (e.g. `/dav/`)
+pub fn file_href(root: &str, name: &str) -> String {
+    format!("{}{}", root, utf8_percent_encode(name, SEGMENT))
+}
+
+/// `Last-Modified` form of a stored timestamp
+pub fn http_date(stored: &str) -> Option<String> {
+    let time = timestamps::to_utc(stored)?;
+    Some(time.format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// A 207 Multi-Status body with the live properties of each resource
+pub fn render_multistatus(resources: &[DavResource]) -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<D:multistatus xmlns:D=\"DAV:\">\n",
+    );
+    for resource in resources {
+        xml.push_str("<D:response>");
+        xml.push_str(&format!("<D:href>{}</D:href>", escape_xml(&resource.href)));
+        xml.push_str("<D:propstat><D:prop>");
+        if resource.collection {
+            xml.push_str("<D:resourcetype><D:collection/></D:resourcetype>");
+        } else {
+            xml.push_str("<D:resourcetype/>");
+            xml.push_str(&format!(
+                "<D:getcontentlength>{}</D:getcontentlength>",
+                resource.size
+            ));
+            if let Some(content_type) = &resource.content_type {
+                xml.push_str(&format!(
+                    "<D:getcontenttype>{}</D:getcontenttype>",
+                    escape_xml(content_type)
+                ));
+            }
+            if let Some(etag) = &resource.etag {
+                xml.push_str(&format!("<D:getetag>\"{}\"</D:getetag>", escape_xml(etag)));
+            }
+        }
+        if let Some(modified) = resource.modified.as_deref().and_then(http_date) {
+            xml.push_str(&format!(
+                "<D:getlastmodified>{}</D:getlastmodified>",
+                modified
+            ));
+        }
+        xml.push_str("</D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat>");
+        xml.push_str("</D:response>\n");
+    }
+    xml.push_str("</D:multistatus>\n");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_multistatus() {
+        let resources = [
+            DavResource {
+                href: "/dav/".to_string(),
+                collection: true,
+                size: 0,
+                content_type: None,
+                modified: None,
+                etag: None,
+            },
+            DavResource {
+                href: file_href("/dav/", "run 1 <a&b>.csv"),
+                collection: false,
+                size: 42,
+                content_type: Some("text/csv".to_string()),
+                modified: Some("2024-05-01T12:30:00.000Z".to_string()),
+                etag: Some("abc".to_string()),
+            },
+        ];
+        let xml = render_multistatus(&resources);
+        assert!(xml
+            .contains("<D:href>/dav/</D:href><D:propstat><D:prop><D:resourcetype><D:collection/>"));
+        assert!(xml.contains("<D:href>/dav/run%201%20%3Ca&amp;b%3E.csv</D:href>"));
+        assert!(xml.contains("<D:getcontentlength>42</D:getcontentlength>"));
+        assert!(
+            xml.contains("<D:getlastmodified>Wed, 01 May 2024 12:30:00 GMT</D:getlastmodified>")
+        );
+        assert!(xml.contains("<D:getetag>\"abc\"</D:getetag>"));
+    }
+}
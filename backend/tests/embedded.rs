@@ -0,0 +1,1418 @@
+//! The whole server run in-process through the library API, answering real HTTP requests
+
+use datalab_backend::{Config, Server};
+use std::path::PathBuf;
+
+/// A server answering on a free local port until it is stopped
+struct Running {
+    origin: String,
+    stop: tokio::sync::oneshot::Sender<()>,
+    task: tokio::task::JoinHandle<Result<(), datalab_backend::Error>>,
+}
+
+impl Running {
+    fn api(&self) -> String {
+        format!("{}/api", self.origin)
+    }
+
+    async fn stop(self) {
+        self.stop.send(()).unwrap();
+        self.task.await.unwrap().unwrap();
+    }
+}
+
+async fn start(config: Config) -> Running {
+    let server = Server::new(config).await.unwrap();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let origin = format!("http://{}", listener.local_addr().unwrap());
+    let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+    let task = tokio::spawn(server.run(listener, async {
+        let _ = stopped.await;
+    }));
+    Running { origin, stop, task }
+}
+
+fn temp_root(name: &str) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("datalab-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&root).unwrap();
+    root
+}
+
+fn config_in(root: &std::path::Path) -> Config {
+    Config {
+        database_url: format!("sqlite:{}?mode=rwc", root.join("datalab.db").display()),
+        uploads_dir: root.join("uploads"),
+        scripts_dir: root.join("scripts"),
+        output_dir: root.join("output"),
+        thumbnails_dir: root.join("thumbnails"),
+        decompressed_dir: root.join("decompressed"),
+        quarantine_dir: root.join("quarantine"),
+        shutdown_timeout_secs: 1,
+        ..Config::default()
+    }
+}
+
+#[tokio::test]
+async fn test_embedded_server_answers_requests() {
+    let root = temp_root("embedded");
+    let server = start(config_in(&root)).await;
+    let base = server.api();
+
+    let http = reqwest::Client::new();
+    let health = http.get(format!("{}/health", base)).send().await.unwrap();
+    assert!(health.status().is_success());
+
+    let created = http
+        .post(format!("{}/tags", base))
+        .json(&serde_json::json!({ "name": "raw", "color": "#ff0000" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(created.status(), 201);
+    let form = reqwest::multipart::Form::new().part(
+        "file",
+        reqwest::multipart::Part::bytes(b"a,b\n1,2\n".to_vec()).file_name("run.csv"),
+    );
+    let uploaded = http
+        .post(format!("{}/uploads", base))
+        .multipart(form)
+        .send()
+        .await
+        .unwrap();
+    assert!(uploaded.status().is_success());
+    assert!(root.join("uploads").read_dir().unwrap().next().is_some());
+
+    let tags: Vec<serde_json::Value> = http
+        .get(format!("{}/tags", base))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let names: Vec<&str> = tags.iter().filter_map(|tag| tag["name"].as_str()).collect();
+    assert!(names.contains(&"raw") && names.contains(&".csv"));
+
+    for (limit, status) in [("", 200), ("?limit=100000", 200), ("?limit=-1", 400)] {
+        let feed = http
+            .get(format!("{}/feeds/jobs.rss{}", base, limit))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(feed.status(), status, "{}", limit);
+    }
+
+    server.stop().await;
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[tokio::test]
+async fn test_upload_size_limit_only_applies_to_uploads() {
+    let root = temp_root("body-limits");
+    let config = Config {
+        max_upload_size_mb: 4,
+        ..config_in(&root)
+    };
+    let server = start(config).await;
+    let base = server.api();
+
+    let http = reqwest::Client::new();
+    let raw = |size: usize| {
+        http.post(format!("{}/uploads/raw?filename=big.bin", base))
+            .body(vec![b'x'; size])
+            .send()
+    };
+    assert!(raw(3 << 20).await.unwrap().status().is_success());
+    assert_eq!(raw(5 << 20).await.unwrap().status(), 413);
+
+    // Other endpoints keep the framework's default of 2 MB
+    let description = "x".repeat(3 << 20);
+    let tag = http
+        .post(format!("{}/tags", base))
+        .json(&serde_json::json!({ "name": "big", "description": description }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(tag.status(), 413);
+
+    server.stop().await;
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[tokio::test]
+async fn test_webdav_puts_are_stored_and_found_by_name() {
+    let root = temp_root("webdav-put");
+    let config = Config {
+        max_upload_size_mb: 1,
+        webdav: Some(datalab_backend::DavAccess::ReadWrite),
+        ..config_in(&root)
+    };
+    let server = start(config).await;
+    let origin = server.origin.clone();
+
+    let http = reqwest::Client::new();
+    let options = http
+        .request(reqwest::Method::OPTIONS, format!("{}/dav/run.csv", origin))
+        .send()
+        .await
+        .unwrap();
+    assert!(options.headers()["allow"].to_str().unwrap().contains("PUT"));
+
+    let put = |name: &str, body: Vec<u8>| {
+        http.put(format!("{}/dav/{}", origin, name))
+            .body(body)
+            .send()
+    };
+    assert_eq!(
+        put("run.csv", b"a\n1\n".to_vec()).await.unwrap().status(),
+        201
+    );
+    assert_eq!(
+        put("run.csv", b"a\n2\n".to_vec()).await.unwrap().status(),
+        409
+    );
+    assert_eq!(
+        put("big.bin", vec![b'x'; 2 << 20]).await.unwrap().status(),
+        413
+    );
+    let staged: Vec<_> = root
+        .join("uploads")
+        .read_dir()
+        .unwrap()
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().ends_with(".part"))
+        .collect();
+    assert!(staged.is_empty());
+
+    // A repeat through the API shows up numbered
+    let repeated = http
+        .post(format!("{}/api/uploads/raw?filename=run.csv", origin))
+        .body("a\n3\n")
+        .send()
+        .await
+        .unwrap();
+    assert!(repeated.status().is_success());
+    let get = |name: &str| http.get(format!("{}/dav/{}", origin, name)).send();
+    assert_eq!(
+        get("run.csv").await.unwrap().text().await.unwrap(),
+        "a\n1\n"
+    );
+    assert_eq!(
+        get("run (2).csv").await.unwrap().text().await.unwrap(),
+        "a\n3\n"
+    );
+    assert_eq!(get("run (3).csv").await.unwrap().status(), 404);
+    assert_eq!(get("big.bin").await.unwrap().status(), 404);
+
+    server.stop().await;
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[tokio::test]
+async fn test_sql_results_are_streamed_on_request() {
+    let root = temp_root("sql-formats");
+    let server = start(config_in(&root)).await;
+    let base = server.api();
+
+    let http = reqwest::Client::new();
+    let upload: serde_json::Value = http
+        .post(format!("{}/uploads/raw?filename=runs.csv", base))
+        .body("site,od\nnorth,1.5\nsouth,2.5\nwest,3.5\n")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let request = serde_json::json!({
+        "query": "SELECT site, od FROM runs WHERE od > 2",
+        "tables": { "runs": upload["id"] },
+        "limit": 1,
+    });
+
+    let streamed = http
+        .post(format!("{}/sql", base))
+        .header("Accept", "application/x-ndjson")
+        .json(&request)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(streamed.headers()["content-type"], "application/x-ndjson");
+    assert_eq!(streamed.headers()["x-datalab-truncated"], "true");
+    let body = streamed.text().await.unwrap();
+    let lines: Vec<serde_json::Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["headers"], serde_json::json!(["site", "od"]));
+    assert_eq!(lines[1][1], "2.5");
+
+    let result: serde_json::Value = http
+        .post(format!("{}/sql", base))
+        .json(&request)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    // The same cells as the streamed rows
+    assert_eq!(result["rows"], serde_json::json!([lines[1]]));
+    assert_eq!(result["truncated"], true);
+
+    let arrow = http
+        .post(format!("{}/sql?format=arrow", base))
+        .json(&request)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        arrow.headers()["content-type"],
+        "application/vnd.apache.arrow.stream"
+    );
+    assert_eq!(arrow.headers()["x-datalab-truncated"], "true");
+    let stream = arrow.bytes().await.unwrap();
+    // IPC streams open with a continuation marker before the schema message
+    assert_eq!(stream[..4], [0xff; 4]);
+
+    // Table pages larger than one batch are streamed in full
+    let rows: String = (0..25_000).map(|i| format!("{}\n", i)).collect();
+    let upload: serde_json::Value = http
+        .post(format!("{}/uploads/raw?filename=long.csv", base))
+        .body(format!("n\n{}", rows))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let page = http
+        .get(format!(
+            "{}/uploads/{}/table-preview?page=1&page_size=12000",
+            base,
+            upload["id"].as_str().unwrap()
+        ))
+        .header("Accept", "application/x-ndjson")
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let lines: Vec<&str> = page.lines().collect();
+    assert_eq!(lines.len(), 1 + 12_000);
+    assert!(lines[0].contains("\"total_rows\":25000"));
+    assert_eq!(lines[1], "[\"12000\"]");
+    assert_eq!(lines[10_001], "[\"22000\"]");
+    assert_eq!(lines[12_000], "[\"23999\"]");
+
+    server.stop().await;
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[tokio::test]
+async fn test_tags_are_assigned_in_bulk() {
+    let root = temp_root("bulk-tags");
+    let server = start(config_in(&root)).await;
+    let base = server.api();
+
+    let http = reqwest::Client::new();
+    let post = |path: &str, body: serde_json::Value| {
+        http.post(format!("{}{}", base, path)).json(&body).send()
+    };
+    let tag: serde_json::Value = post(
+        "/tags",
+        serde_json::json!({ "name": "exp-7", "color": "#00ff00" }),
+    )
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+    let tag_id = tag["id"].as_str().unwrap();
+    let mut upload_ids = Vec::new();
+    for name in ["run_1.csv", "run_2.csv", "notes.txt"] {
+        let form = reqwest::multipart::Form::new().part(
+            "file",
+            reqwest::multipart::Part::bytes(b"a\n1\n".to_vec()).file_name(name),
+        );
+        let uploaded: serde_json::Value = http
+            .post(format!("{}/uploads", base))
+            .multipart(form)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        upload_ids.push(uploaded["id"].as_str().unwrap().to_string());
+    }
+
+    let assigned: serde_json::Value = post(
+        &format!("/tags/{}/assign", tag_id),
+        serde_json::json!({ "filter": { "filename": "run_" }, "triggers": "none" }),
+    )
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+    assert_eq!(
+        (assigned["matched"].as_u64(), assigned["changed"].as_u64()),
+        (Some(2), Some(2))
+    );
+    let again: serde_json::Value = post(
+        &format!("/tags/{}/assign", tag_id),
+        serde_json::json!({ "upload_ids": [upload_ids[0], upload_ids[2]] }),
+    )
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+    assert_eq!(
+        (again["matched"].as_u64(), again["changed"].as_u64()),
+        (Some(2), Some(1))
+    );
+
+    let unassigned: serde_json::Value = post(
+        &format!("/tags/{}/unassign", tag_id),
+        serde_json::json!({ "upload_ids": [upload_ids[0]] }),
+    )
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+    assert_eq!(unassigned["changed"].as_u64(), Some(1));
+    let unknown = post(
+        &format!("/tags/{}/unassign", tag_id),
+        serde_json::json!({ "upload_ids": ["nope"] }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(unknown.status(), 404);
+    let empty = post(&format!("/tags/{}/assign", tag_id), serde_json::json!({}))
+        .await
+        .unwrap();
+    assert_eq!(empty.status(), 400);
+
+    let tags: Vec<serde_json::Value> = http
+        .get(format!("{}/tags", base))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let usage = tags.iter().find(|tag| tag["name"] == "exp-7").unwrap();
+    assert_eq!(usage["upload_count"].as_i64(), Some(2));
+
+    server.stop().await;
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[tokio::test]
+async fn test_uploads_are_imported_from_a_remote() {
+    let mut servers = Vec::new();
+    let mut roots = Vec::new();
+    for name in ["site-a", "site-b"] {
+        let root = temp_root(name);
+        servers.push(start(config_in(&root)).await);
+        roots.push(root);
+    }
+    let (site_a, site_b) = (servers[0].origin.clone(), servers[1].origin.clone());
+
+    // Site B holds a tagged CSV
+    let http = reqwest::Client::new();
+    let tag: serde_json::Value = http
+        .post(format!("{}/api/tags", site_b))
+        .json(&serde_json::json!({ "name": "sweep-3", "color": "#0000ff" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let form = reqwest::multipart::Form::new()
+        .text("tags", serde_json::json!([tag["id"]]).to_string())
+        .part(
+            "file",
+            reqwest::multipart::Part::bytes(b"v\n1\n2\n".to_vec()).file_name("bias.csv"),
+        );
+    let uploaded: serde_json::Value = http
+        .post(format!("{}/api/uploads", site_b))
+        .multipart(form)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let remote_upload_id = uploaded["id"].as_str().unwrap();
+
+    // Site A registers it, browses it and imports the CSV
+    let refused = http
+        .post(format!("{}/api/remotes", site_a))
+        .json(&serde_json::json!({ "name": "b", "base_url": "ftp://site-b" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(refused.status(), 400);
+    let remote: serde_json::Value = http
+        .post(format!("{}/api/remotes", site_a))
+        .json(&serde_json::json!({ "name": "b", "base_url": format!("{}/api/", site_b) }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(remote["base_url"].as_str(), Some(site_b.as_str()));
+    let remote_url = format!("{}/api/remotes/{}", site_a, remote["id"].as_str().unwrap());
+
+    let page: serde_json::Value = http
+        .get(format!("{}/uploads?filename=bias", remote_url))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(page["total"].as_i64(), Some(1));
+    assert_eq!(page["uploads"][0]["id"].as_str(), Some(remote_upload_id));
+
+    let missing = http
+        .post(format!("{}/import", remote_url))
+        .json(&serde_json::json!({ "upload_ids": ["nope"] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(missing.status(), 404);
+    let imported = http
+        .post(format!("{}/import", remote_url))
+        .json(&serde_json::json!({ "upload_ids": [remote_upload_id], "copy_tags": true }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(imported.status(), 201);
+    let imported: serde_json::Value = imported.json().await.unwrap();
+    let local_id = imported[0]["upload"]["id"].as_str().unwrap();
+    assert_ne!(local_id, remote_upload_id);
+
+    let content = http
+        .get(format!("{}/api/uploads/{}/download", site_a, local_id))
+        .send()
+        .await
+        .unwrap()
+        .bytes()
+        .await
+        .unwrap();
+    assert_eq!(&content[..], b"v\n1\n2\n");
+    let local: serde_json::Value = http
+        .get(format!("{}/api/uploads/{}", site_a, local_id))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let tag_names: Vec<&str> = local["tags"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|tag| tag["name"].as_str())
+        .collect();
+    assert!(tag_names.contains(&"sweep-3") && tag_names.contains(&".csv"));
+    let custody: serde_json::Value = http
+        .get(format!("{}/api/uploads/{}/custody", site_a, local_id))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(custody["origin"]["received_via"].as_str(), Some("remote"));
+    assert!(custody["origin"]["source_url"]
+        .as_str()
+        .unwrap()
+        .starts_with(site_b.as_str()));
+
+    for server in servers {
+        server.stop().await;
+    }
+    for root in roots {
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
+
+#[tokio::test]
+async fn test_restricted_tags_hide_uploads() {
+    let root = temp_root("restricted-tags");
+    let server = start(config_in(&root)).await;
+    let base = server.api();
+
+    let http = reqwest::Client::new();
+    let tag: serde_json::Value = http
+        .post(format!("{}/tags", base))
+        .json(&serde_json::json!({ "name": "patient-data" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let tag_id = tag["id"].as_str().unwrap();
+    let mut upload_ids = Vec::new();
+    for (name, tags) in [
+        ("scan.csv", format!("[\"{}\"]", tag_id)),
+        ("plate.csv", "[]".to_string()),
+    ] {
+        let form = reqwest::multipart::Form::new()
+            .part(
+                "file",
+                reqwest::multipart::Part::bytes(b"a\n1\n".to_vec()).file_name(name),
+            )
+            .text("tags", tags);
+        let uploaded: serde_json::Value = http
+            .post(format!("{}/uploads", base))
+            .multipart(form)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        upload_ids.push(uploaded["id"].as_str().unwrap().to_string());
+    }
+
+    let restricted = http
+        .put(format!("{}/tags/{}/access", base, tag_id))
+        .json(&serde_json::json!({ "users": ["alice"], "roles": ["clinician"] }))
+        .send()
+        .await
+        .unwrap();
+    assert!(restricted.status().is_success());
+
+    let listed = |user: &'static str, roles: &'static str| {
+        http.get(format!("{}/uploads", base))
+            .header("X-DataLab-User", user)
+            .header("X-DataLab-Roles", roles)
+            .send()
+    };
+    let page: serde_json::Value = listed("bob", "student")
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(page["total"].as_i64(), Some(1));
+    assert_eq!(
+        page["uploads"][0]["id"].as_str(),
+        Some(upload_ids[1].as_str())
+    );
+    let page: serde_json::Value = listed("bob", "clinician")
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(page["total"].as_i64(), Some(2));
+
+    for path in ["", "/download", "/content"] {
+        let url = format!("{}/uploads/{}{}", base, upload_ids[0], path);
+        let hidden = http.get(&url).send().await.unwrap();
+        assert_eq!(hidden.status(), 404);
+        let allowed = http
+            .get(&url)
+            .header("X-DataLab-User", "alice")
+            .send()
+            .await
+            .unwrap();
+        assert!(allowed.status().is_success());
+    }
+
+    let lifted = http
+        .put(format!("{}/tags/{}/access", base, tag_id))
+        .header("X-DataLab-User", "bob")
+        .json(&serde_json::json!({}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(lifted.status(), 403);
+
+    server.stop().await;
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[tokio::test]
+async fn test_restricted_uploads_stay_hidden_everywhere() {
+    let root = temp_root("restricted-everywhere");
+    let config = datalab_backend::Config {
+        webdav: Some(datalab_backend::DavAccess::ReadOnly),
+        ..config_in(&root)
+    };
+    let server = start(config).await;
+    let origin = server.origin.clone();
+    let base = server.api();
+
+    let http = reqwest::Client::new();
+    let tag: serde_json::Value = http
+        .post(format!("{}/tags", base))
+        .json(&serde_json::json!({ "name": "patient-data" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let tag_id = tag["id"].as_str().unwrap();
+    let form = reqwest::multipart::Form::new()
+        .part(
+            "file",
+            reqwest::multipart::Part::bytes(b"serial\nSN-4411\n".to_vec()).file_name("scan.csv"),
+        )
+        .text("tags", format!("[\"{}\"]", tag_id));
+    let uploaded: serde_json::Value = http
+        .post(format!("{}/uploads", base))
+        .multipart(form)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let id = uploaded["id"].as_str().unwrap().to_string();
+    let link: serde_json::Value = http
+        .post(format!("{}/uploads/{}/shares", base, id))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let token = link["token"].as_str().unwrap();
+    let post = |path: &str, body: serde_json::Value| {
+        http.post(format!("{}{}", base, path)).json(&body).send()
+    };
+    let dataset: serde_json::Value = post(
+        "/datasets",
+        serde_json::json!({ "name": "scans", "upload_ids": [id] }),
+    )
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+    let dataset_id = dataset["id"].as_str().unwrap();
+    let approved: serde_json::Value = post("/tags", serde_json::json!({ "name": "approved" }))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let queue: serde_json::Value = post(
+        "/review-queues",
+        serde_json::json!({
+            "name": "scan review",
+            "input_tag_id": tag_id,
+            "approve_tag_id": approved["id"],
+        }),
+    )
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+    let queue_id = queue["id"].as_str().unwrap();
+    let function: serde_json::Value = post(
+        "/functions",
+        serde_json::json!({
+            "name": "serials",
+            "expression": "SELECT serial FROM data",
+            "input_tag_ids": [],
+            "output_tag_ids": [],
+        }),
+    )
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+    let function_id = function["id"].as_str().unwrap();
+
+    let restricted = http
+        .put(format!("{}/tags/{}/access", base, tag_id))
+        .json(&serde_json::json!({ "users": ["alice"] }))
+        .send()
+        .await
+        .unwrap();
+    assert!(restricted.status().is_success());
+
+    // Every per-upload endpoint answers as if the upload did not exist
+    for path in [
+        "/schema",
+        "/sample",
+        "/table-preview",
+        "/thumbnail",
+        "/media-info",
+        "/waveform",
+        "/array-info",
+        "/shares",
+    ] {
+        let url = format!("{}/uploads/{}{}", base, id, path);
+        let hidden = http.get(&url).send().await.unwrap();
+        assert_eq!(hidden.status(), 404, "{}", path);
+        let allowed = http
+            .get(&url)
+            .header("X-DataLab-User", "alice")
+            .send()
+            .await
+            .unwrap();
+        assert_ne!(allowed.status(), 404, "{}", path);
+    }
+    let queried = http
+        .post(format!("{}/sql", base))
+        .json(&serde_json::json!({ "query": "SELECT * FROM t", "tables": { "t": id } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(queried.status(), 404);
+
+    for path in ["/search?q=scan", "/search/contents?q=SN-4411"] {
+        let found = |user: &'static str| {
+            http.get(format!("{}{}", base, path))
+                .header("X-DataLab-User", user)
+                .send()
+        };
+        let results: serde_json::Value = found("bob").await.unwrap().json().await.unwrap();
+        assert_eq!(
+            results["hits"].as_array().map(Vec::len),
+            Some(0),
+            "{}",
+            path
+        );
+        let results: serde_json::Value = found("alice").await.unwrap().json().await.unwrap();
+        assert_eq!(
+            results["hits"].as_array().map(Vec::len),
+            Some(1),
+            "{}",
+            path
+        );
+    }
+
+    // Datasets and review queues leave the upload out for anyone not allowed to see it
+    for user in ["bob", "alice"] {
+        let visible = usize::from(user == "alice");
+        let get = |path: String| http.get(path).header("X-DataLab-User", user).send();
+        let detail: serde_json::Value = get(format!("{}/datasets/{}", base, dataset_id))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(
+            detail["uploads"].as_array().unwrap().len(),
+            visible,
+            "{}",
+            user
+        );
+        let zip = get(format!("{}/datasets/{}/download", base, dataset_id))
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+        let named = zip.windows(8).any(|window| window == b"scan.csv");
+        assert_eq!(named, user == "alice", "{}", user);
+        let items: serde_json::Value = get(format!("{}/review-queues/{}/items", base, queue_id))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(items.as_array().unwrap().len(), visible, "{}", user);
+        let preview = http
+            .post(format!("{}/functions/{}/preview", base, function_id))
+            .header("X-DataLab-User", user)
+            .json(&serde_json::json!({ "upload_id": id }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(preview.status().is_success(), user == "alice", "{}", user);
+    }
+
+    let archived = http
+        .post(format!("{}/uploads/archive", base))
+        .json(&serde_json::json!({ "upload_ids": [id] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(archived.status(), 404);
+
+    // Links made before the tag was restricted stop working, and no new ones can be made
+    for path in ["", "/preview", "/download"] {
+        let shared = http
+            .get(format!("{}/share/{}{}", origin, token, path))
+            .header("X-DataLab-User", "alice")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(shared.status(), 404, "{}", path);
+    }
+    let refused = http
+        .post(format!("{}/uploads/{}/shares", base, id))
+        .header("X-DataLab-User", "alice")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(refused.status(), 403);
+
+    let propfind = reqwest::Method::from_bytes(b"PROPFIND").unwrap();
+    let listing = http
+        .request(propfind, format!("{}/dav/", origin))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(!listing.contains("scan.csv"));
+    let fetched = http
+        .get(format!("{}/dav/scan.csv", origin))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(fetched.status(), 404);
+    let fetched = http
+        .get(format!("{}/dav/scan.csv", origin))
+        .header("X-DataLab-User", "alice")
+        .send()
+        .await
+        .unwrap();
+    assert!(fetched.status().is_success());
+
+    server.stop().await;
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[tokio::test]
+async fn test_share_link_expiry_is_checked() {
+    let root = temp_root("share-expiry");
+    let server = start(config_in(&root)).await;
+    let base = server.api();
+
+    let http = reqwest::Client::new();
+    let form = reqwest::multipart::Form::new().part(
+        "file",
+        reqwest::multipart::Part::bytes(b"a\n1\n".to_vec()).file_name("run.csv"),
+    );
+    let uploaded: serde_json::Value = http
+        .post(format!("{}/uploads", base))
+        .multipart(form)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let shares = format!(
+        "{}/uploads/{}/shares",
+        base,
+        uploaded["id"].as_str().unwrap()
+    );
+
+    // 2.4 billion hours is a valid TimeDelta, but past the last date chrono can hold
+    for hours in [0, -1, i64::MAX, 1_000_000_000_000, 2_400_000_000] {
+        let refused = http
+            .post(&shares)
+            .json(&serde_json::json!({ "expires_in_hours": hours }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(refused.status(), 400, "{}", hours);
+    }
+    let link: serde_json::Value = http
+        .post(&shares)
+        .json(&serde_json::json!({ "expires_in_hours": 24 * 365 * 100 }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(link["expires_at"].as_str().unwrap().starts_with("21"));
+
+    server.stop().await;
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[tokio::test]
+async fn test_views_filter_on_name_patterns() {
+    let root = temp_root("view-name-patterns");
+    let server = start(config_in(&root)).await;
+    let base = server.api();
+
+    let http = reqwest::Client::new();
+    let tag: serde_json::Value = http
+        .post(format!("{}/tags", base))
+        .json(&serde_json::json!({ "name": "processed" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let tag_id = tag["id"].as_str().unwrap();
+    for (name, tags) in [
+        ("Filter_A.S2P", "[]".to_string()),
+        ("filter_b.s2p", format!("[\"{}\"]", tag_id)),
+        ("filter_a.csv", "[]".to_string()),
+    ] {
+        let form = reqwest::multipart::Form::new()
+            .part(
+                "file",
+                reqwest::multipart::Part::bytes(b"! touchstone\n".to_vec()).file_name(name),
+            )
+            .text("tags", tags);
+        let uploaded = http
+            .post(format!("{}/uploads", base))
+            .multipart(form)
+            .send()
+            .await
+            .unwrap();
+        assert!(uploaded.status().is_success());
+    }
+
+    let view: serde_json::Value = http
+        .post(format!("{}/views", base))
+        .json(&serde_json::json!({
+            "name": "unprocessed touchstone",
+            "name_pattern": "*.s2p",
+            "tag_expression": "NOT processed",
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(view["name_pattern"].as_str(), Some("*.s2p"));
+    let view_uploads = format!("{}/views/{}/uploads", base, view["id"].as_str().unwrap());
+    let uploads: serde_json::Value = http
+        .get(&view_uploads)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let names: Vec<&str> = uploads
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|upload| upload["original_filename"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["Filter_A.S2P"]);
+
+    // An empty pattern clears it
+    let updated = http
+        .put(format!("{}/views/{}", base, view["id"].as_str().unwrap()))
+        .json(&serde_json::json!({ "name_pattern": "" }))
+        .send()
+        .await
+        .unwrap();
+    assert!(updated.status().is_success());
+    let uploads: serde_json::Value = http
+        .get(&view_uploads)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(uploads.as_array().unwrap().len(), 2);
+
+    // A relative range must be positive, and null clears it
+    let view_url = format!("{}/views/{}", base, view["id"].as_str().unwrap());
+    for days in [0, -3] {
+        let refused = http
+            .put(&view_url)
+            .json(&serde_json::json!({ "within_days": days }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(refused.status(), 400);
+    }
+    let update = |body: serde_json::Value| http.put(&view_url).json(&body).send();
+    let updated: serde_json::Value = update(serde_json::json!({ "within_days": 7 }))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(updated["within_days"], 7);
+    let updated: serde_json::Value = update(serde_json::json!({ "name": "renamed" }))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(updated["within_days"], 7);
+    let updated: serde_json::Value = update(serde_json::json!({ "within_days": null }))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(updated["within_days"].is_null());
+
+    server.stop().await;
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[tokio::test]
+async fn test_manifests_are_imported_row_by_row() {
+    let root = temp_root("import-manifest");
+    let archive = root.join("archive");
+    std::fs::create_dir_all(&archive).unwrap();
+    std::fs::write(archive.join("run1.csv"), "a\n1\n").unwrap();
+    let server = start(Config {
+        import_dirs: vec![archive.clone()],
+        ..config_in(&root)
+    })
+    .await;
+    let base = server.api();
+
+    let http = reqwest::Client::new();
+    let outside = http
+        .post(format!("{}/uploads/import-manifest", base))
+        .header("Content-Type", "text/csv")
+        .body(format!("source\n{}\n", root.join("datalab.db").display()))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(outside.status(), 400);
+
+    let manifest = serde_json::json!([
+        {
+            "source": archive.join("run1.csv"),
+            "filename": "2019-run1.csv",
+            "tags": ["legacy"],
+            "assignee": "@alice",
+            "metadata": { "instrument": "VNA-2" },
+        },
+        { "source": archive.join("missing.csv") },
+    ]);
+    let accepted = http
+        .post(format!("{}/uploads/import-manifest", base))
+        .json(&manifest)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(accepted.status(), 202);
+    let location = accepted.headers()["location"].to_str().unwrap().to_string();
+    let batch: serde_json::Value = accepted.json().await.unwrap();
+    assert_eq!(batch["total"].as_i64(), Some(2));
+
+    let mut batch = batch;
+    for _ in 0..50 {
+        if batch["status"] == "completed" {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        batch = http
+            .get(format!("{}{}", server.origin, location))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+    }
+    assert_eq!(batch["status"], "completed");
+    assert_eq!(batch["imported"].as_i64(), Some(1));
+    assert_eq!(batch["failed"].as_i64(), Some(1));
+    assert_eq!(batch["rows"][1]["status"], "failed");
+
+    let upload_id = batch["rows"][0]["upload_id"].as_str().unwrap();
+    let upload: serde_json::Value = http
+        .get(format!("{}/uploads/{}", base, upload_id))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(upload["original_filename"], "2019-run1.csv");
+    assert_eq!(upload["assignee"], "alice");
+    assert!(upload["tags"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|tag| tag["name"] == "legacy"));
+    let custody = http
+        .get(format!("{}/uploads/{}/custody", base, upload_id))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(custody.contains("VNA-2"));
+
+    server.stop().await;
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[tokio::test]
+async fn test_files_are_kept_in_the_configured_directories() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let root = temp_root("configured-dirs");
+    // Records each Kubernetes job it is asked to create; the test plays the worker
+    let kubectl = root.join("kubectl");
+    std::fs::write(
+        &kubectl,
+        format!(
+            "#!/bin/sh\nif [ \"$3\" = create ]; then cat >> '{}'; echo >> '{}'; fi\n",
+            root.join("k8s-jobs.jsonl").display(),
+            root.join("k8s-jobs.jsonl").display()
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&kubectl, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let server = start(Config {
+        cluster_shared_dir: Some(root.join("shared")),
+        kubectl_bin: kubectl,
+        k8s_image: Some("worker".to_string()),
+        ..config_in(&root)
+    })
+    .await;
+    let base = server.api();
+    let uploads_dir = root.join("uploads");
+
+    let http = reqwest::Client::new();
+    let post = |path: &str, body: serde_json::Value| {
+        http.post(format!("{}{}", base, path)).json(&body).send()
+    };
+    let upload = |name: &str, data: Vec<u8>, tag_ids: serde_json::Value| {
+        let form = reqwest::multipart::Form::new()
+            .text("tags", tag_ids.to_string())
+            .part(
+                "file",
+                reqwest::multipart::Part::bytes(data).file_name(name.to_string()),
+            );
+        http.post(format!("{}/uploads", base))
+            .multipart(form)
+            .send()
+    };
+
+    let tag: serde_json::Value = post("/tags", serde_json::json!({ "name": "raw" }))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    for name in ["summarize", "check"] {
+        let function: serde_json::Value = post(
+            "/functions",
+            serde_json::json!({
+                "name": name,
+                "script_content": "def main(path):\n    return path\n",
+                "executor": "kubernetes",
+                "input_tag_ids": [tag["id"]],
+                "output_tag_ids": [],
+            }),
+        )
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+        let enabled = http
+            .put(format!(
+                "{}/functions/{}",
+                base,
+                function["id"].as_str().unwrap()
+            ))
+            .json(&serde_json::json!({ "enabled": true }))
+            .send()
+            .await
+            .unwrap();
+        assert!(enabled.status().is_success());
+    }
+
+    // Both functions stage their script and input for the cluster, then wait for the worker
+    let uploaded = upload(
+        "scan.csv",
+        b"serial\n1\n".to_vec(),
+        serde_json::json!([tag["id"]]),
+    )
+    .await
+    .unwrap();
+    assert!(uploaded.status().is_success());
+    let mut created = Vec::new();
+    for _ in 0..50 {
+        let jobs = std::fs::read_to_string(root.join("k8s-jobs.jsonl")).unwrap_or_default();
+        created = jobs
+            .lines()
+            .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+            .collect();
+        if created.len() == 2 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    assert_eq!(created.len(), 2);
+
+    // One worker pushes an output, the other a failure log
+    let mut outputs = Vec::new();
+    for (job, success) in created.iter().zip([true, false]) {
+        let env = |name: &str| {
+            job["spec"]["template"]["spec"]["containers"][0]["env"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|variable| variable["name"] == name)
+                .and_then(|variable| variable["value"].as_str())
+                .unwrap()
+                .to_string()
+        };
+        let mut form = reqwest::multipart::Form::new().text(
+            "manifest",
+            serde_json::json!({ "success": success, "log": "boom" }).to_string(),
+        );
+        if success {
+            form = form.part(
+                "file",
+                reqwest::multipart::Part::bytes(b"count\n1\n".to_vec()).file_name("summary.csv"),
+            );
+        }
+        let finished: serde_json::Value = http
+            .post(format!("{}/jobs/{}/complete", base, env("DATALAB_JOB_ID")))
+            .bearer_auth(env("DATALAB_JOB_TOKEN"))
+            .multipart(form)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        outputs.push(
+            finished["output_upload_ids"][0]
+                .as_str()
+                .unwrap()
+                .to_string(),
+        );
+    }
+    let mut stored = Vec::new();
+    for id in &outputs {
+        let output: serde_json::Value = http
+            .get(format!("{}/uploads/{}", base, id))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let filename = output["filename"].as_str().unwrap().to_string();
+        assert!(uploads_dir.join(&filename).exists(), "{}", filename);
+        stored.push(filename);
+    }
+
+    // Metadata is read from the stored files
+    let mut tiff = b"II".to_vec();
+    tiff.extend(42u16.to_le_bytes());
+    tiff.extend(8u32.to_le_bytes());
+    tiff.extend(2u16.to_le_bytes());
+    for (tag, value) in [(256u16, 30u16), (257, 20)] {
+        tiff.extend(tag.to_le_bytes());
+        tiff.extend(3u16.to_le_bytes());
+        tiff.extend(1u32.to_le_bytes());
+        tiff.extend(value.to_le_bytes());
+        tiff.extend([0, 0]);
+    }
+    tiff.extend(0u32.to_le_bytes());
+    let header = "{'descr': '<f8', 'fortran_order': False, 'shape': (2, 3), }\n";
+    let mut npy = b"\x93NUMPY\x01\x00".to_vec();
+    npy.extend((header.len() as u16).to_le_bytes());
+    npy.extend(header.as_bytes());
+    npy.extend([0; 48]);
+    let samples: Vec<u8> = [0i16, 1000, -1000, 0]
+        .iter()
+        .flat_map(|sample| sample.to_le_bytes())
+        .collect();
+    let mut wav = b"RIFF".to_vec();
+    wav.extend((36 + samples.len() as u32).to_le_bytes());
+    wav.extend(b"WAVEfmt ");
+    wav.extend(16u32.to_le_bytes());
+    wav.extend(1u16.to_le_bytes()); // PCM
+    wav.extend(1u16.to_le_bytes()); // mono
+    wav.extend(8000u32.to_le_bytes());
+    wav.extend(16000u32.to_le_bytes());
+    wav.extend(2u16.to_le_bytes());
+    wav.extend(16u16.to_le_bytes());
+    wav.extend(b"data");
+    wav.extend((samples.len() as u32).to_le_bytes());
+    wav.extend(&samples);
+    let mut infos = Vec::new();
+    for (name, data, path) in [
+        ("scan.tif", tiff, "media-info"),
+        ("grid.npy", npy, "array-info"),
+        ("tone.wav", wav, "waveform"),
+    ] {
+        let uploaded: serde_json::Value = upload(name, data, serde_json::json!([]))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let info = http
+            .get(format!(
+                "{}/uploads/{}/{}",
+                base,
+                uploaded["id"].as_str().unwrap(),
+                path
+            ))
+            .send()
+            .await
+            .unwrap();
+        assert!(info.status().is_success(), "{}", path);
+        infos.push(info.json::<serde_json::Value>().await.unwrap());
+    }
+    assert_eq!(infos[0]["width"].as_u64(), Some(30));
+    assert_eq!(infos[1]["arrays"][0]["shape"], serde_json::json!([2, 3]));
+    assert_eq!(infos[2]["total_frames"].as_u64(), Some(4));
+
+    // Every row finds its file, so nothing is reported missing
+    let orphans: serde_json::Value = http
+        .get(format!("{}/admin/orphans", base))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(orphans["missing"], serde_json::json!([]));
+
+    // Purging the error log deletes its file
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    let purged: serde_json::Value = post(
+        "/uploads/error-logs/purge?older_than_days=0",
+        serde_json::json!({}),
+    )
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+    assert_eq!(purged["purged"].as_array().unwrap().len(), 1);
+    assert!(uploads_dir.join(&stored[0]).exists());
+    assert!(!uploads_dir.join(&stored[1]).exists());
+
+    server.stop().await;
+    let _ = std::fs::remove_dir_all(&root);
+}
@@ -51,16 +51,20 @@ interface Tag {
 interface FileLineageInfo {
   source_upload_id: string;
   source_filename: string;
-  function_id: string;
+  function_id: string | null;
   function_name: string;
+  operation: string | null;
+  query: string | null;
   success: boolean;
+  other_sources?: { upload_id: string; filename: string }[];
 }
 
 interface DerivedFile {
   output_upload_id: string;
   output_filename: string;
-  function_id: string;
+  function_id: string | null;
   function_name: string;
+  operation: string | null;
   success: boolean;
   created_at: string;
 }
@@ -72,6 +76,8 @@ interface Upload {
   file_size: number;
   mime_type: string | null;
   created_at: string;
+  sha256: string | null;
+  assignee: string | null;
   tags: Tag[];
   lineage?: FileLineageInfo;
 }
@@ -295,6 +301,12 @@ export default function FileDashboard({
                   <Calendar className="h-4 w-4" />
                   <span>{formatDate(file.created_at)}</span>
                 </div>
+                {file.sha256 && (
+                  <span className="font-mono" title={`SHA-256: ${file.sha256}`}>
+                    {file.sha256.slice(0, 12)}
+                  </span>
+                )}
+                {file.assignee && <span>Assigned to @{file.assignee}</span>}
                 {derivedFiles.length > 0 && (
                   <Button
                     variant="ghost"
@@ -377,18 +389,35 @@ export default function FileDashboard({
               <FileIcon className="mr-1 h-4 w-4" />
               {file.lineage.source_filename}
             </Button>
+            {file.lineage.other_sources?.map((source) => (
+              <Button
+                key={source.upload_id}
+                variant="link"
+                className="p-0 h-auto font-medium text-sm"
+                onClick={() => router.push(`/files/${source.upload_id}`)}
+              >
+                <FileIcon className="mr-1 h-4 w-4" />
+                {source.filename}
+              </Button>
+            ))}
             <ArrowRight className="h-4 w-4 text-muted-foreground" />
             <Code className="h-4 w-4 text-muted-foreground" />
             <span>via</span>
-            <Button
-              variant="link"
-              className="p-0 h-auto text-sm"
-              onClick={() =>
-                router.push(`/functions/${file.lineage!.function_id}`)
-              }
-            >
-              {file.lineage.function_name}
-            </Button>
+            {file.lineage.function_id ? (
+              <Button
+                variant="link"
+                className="p-0 h-auto text-sm"
+                onClick={() =>
+                  router.push(`/functions/${file.lineage!.function_id}`)
+                }
+              >
+                {file.lineage.function_name}
+              </Button>
+            ) : (
+              <span className="font-medium" title={file.lineage.query ?? ""}>
+                {file.lineage.function_name}
+              </span>
+            )}
           </div>
         </div>
       )}
@@ -470,18 +499,25 @@ export default function FileDashboard({
                     ) : (
                       <span className="text-xs text-red-600">✗ Failed</span>
                     )}
-                    <Button
-                      variant="link"
-                      className="p-0 h-auto text-sm"
-                      onClick={(e) => {
-                        e.stopPropagation();
-                        setShowDerivedFilesModal(false);
-                        router.push(`/functions/${derived.function_id}`);
-                      }}
-                    >
-                      <Code className="mr-1 h-3 w-3" />
-                      {derived.function_name}
-                    </Button>
+                    {derived.function_id ? (
+                      <Button
+                        variant="link"
+                        className="p-0 h-auto text-sm"
+                        onClick={(e) => {
+                          e.stopPropagation();
+                          setShowDerivedFilesModal(false);
+                          router.push(`/functions/${derived.function_id}`);
+                        }}
+                      >
+                        <Code className="mr-1 h-3 w-3" />
+                        {derived.function_name}
+                      </Button>
+                    ) : (
+                      <span className="flex items-center text-sm text-muted-foreground">
+                        <Code className="mr-1 h-3 w-3" />
+                        {derived.function_name}
+                      </span>
+                    )}
                   </div>
                 </div>
               ))}
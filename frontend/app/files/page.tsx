@@ -133,7 +133,7 @@ export default function DataPage() {
 
       if (uploadsRes.ok) {
         const uploadsData = await uploadsRes.json();
-        setUploads(uploadsData);
+        setUploads(uploadsData.uploads);
       }
       if (tagsRes.ok) {
         const tagsData = await tagsRes.json();
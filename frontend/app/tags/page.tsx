@@ -57,7 +57,7 @@ export default function TagsPage() {
 
                 // Calculate usage count for each tag
                 const tagsWithUsage = tagsData.map((tag: Tag) => {
-                    const usage_count = uploadsData.filter((upload: any) =>
+                    const usage_count = uploadsData.uploads.filter((upload: any) =>
                         upload.tags.some((t: any) => t.id === tag.id),
                     ).length;
                     return { ...tag, usage_count };
@@ -24,8 +24,10 @@ interface Tag {
 interface FileLineageInfo {
   source_upload_id: string;
   source_filename: string;
-  function_id: string;
+  function_id: string | null;
   function_name: string;
+  operation: string | null;
+  query: string | null;
   success: boolean;
 }
 
@@ -31,7 +31,7 @@ export function UploadDialog({
     tags,
     onSuccess,
 }: UploadDialogProps) {
-    const [selectedFile, setSelectedFile] = useState<File | null>(null);
+    const [selectedFiles, setSelectedFiles] = useState<File[]>([]);
     const [selectedTags, setSelectedTags] = useState<string[]>([]);
     const [dragActive, setDragActive] = useState(false);
     const [uploading, setUploading] = useState(false);
@@ -51,14 +51,14 @@ export function UploadDialog({
         e.stopPropagation();
         setDragActive(false);
 
-        if (e.dataTransfer.files && e.dataTransfer.files[0]) {
-            setSelectedFile(e.dataTransfer.files[0]);
+        if (e.dataTransfer.files && e.dataTransfer.files.length > 0) {
+            setSelectedFiles(Array.from(e.dataTransfer.files));
         }
     }, []);
 
     const handleFileChange = (e: React.ChangeEvent<HTMLInputElement>) => {
-        if (e.target.files && e.target.files[0]) {
-            setSelectedFile(e.target.files[0]);
+        if (e.target.files && e.target.files.length > 0) {
+            setSelectedFiles(Array.from(e.target.files));
         }
     };
 
@@ -70,12 +70,16 @@ export function UploadDialog({
         );
     };
 
-    const uploadFile = async () => {
-        if (!selectedFile) return;
+    const removeFile = (index: number) => {
+        setSelectedFiles((prev) => prev.filter((_, i) => i !== index));
+    };
+
+    const uploadFiles = async () => {
+        if (selectedFiles.length === 0) return;
 
         setUploading(true);
         const formData = new FormData();
-        formData.append("file", selectedFile);
+        selectedFiles.forEach((file) => formData.append("file", file));
         formData.append("tags", JSON.stringify(selectedTags));
 
         try {
@@ -85,13 +89,13 @@ export function UploadDialog({
             });
 
             if (response.ok) {
-                setSelectedFile(null);
+                setSelectedFiles([]);
                 setSelectedTags([]);
                 onOpenChange(false);
                 onSuccess();
             }
         } catch (error) {
-            console.error("Failed to upload file:", error);
+            console.error("Failed to upload files:", error);
         } finally {
             setUploading(false);
         }
@@ -108,7 +112,7 @@ export function UploadDialog({
     const handleOpenChange = (newOpen: boolean) => {
         if (!newOpen) {
             // Reset state when closing
-            setSelectedFile(null);
+            setSelectedFiles([]);
             setSelectedTags([]);
             setDragActive(false);
         }
@@ -119,9 +123,9 @@ export function UploadDialog({
         <Dialog open={open} onOpenChange={handleOpenChange}>
             <DialogContent className="max-w-2xl">
                 <DialogHeader>
-                    <DialogTitle>Upload File</DialogTitle>
+                    <DialogTitle>Upload Files</DialogTitle>
                     <DialogDescription>
-                        Upload a file and optionally tag it for organization
+                        Upload one or more files and optionally tag them for organization
                     </DialogDescription>
                 </DialogHeader>
                 <div className="py-4">
@@ -135,23 +139,30 @@ export function UploadDialog({
                                 : "border-slate-300 dark:border-slate-700"
                             }`}
                     >
-                        {selectedFile ? (
+                        {selectedFiles.length > 0 ? (
                             <div className="space-y-4">
-                                <div className="flex items-center justify-center gap-3">
-                                    <FileIcon className="h-8 w-8 text-blue-600" />
-                                    <div className="text-left">
-                                        <p className="font-medium">{selectedFile.name}</p>
-                                        <p className="text-sm text-slate-600 dark:text-slate-400">
-                                            {formatFileSize(selectedFile.size)}
-                                        </p>
-                                    </div>
-                                    <Button
-                                        variant="ghost"
-                                        size="icon"
-                                        onClick={() => setSelectedFile(null)}
-                                    >
-                                        <X className="h-4 w-4" />
-                                    </Button>
+                                <div className="max-h-60 overflow-y-auto space-y-2">
+                                    {selectedFiles.map((file, index) => (
+                                        <div
+                                            key={`${file.name}-${index}`}
+                                            className="flex items-center justify-center gap-3"
+                                        >
+                                            <FileIcon className="h-8 w-8 text-blue-600" />
+                                            <div className="text-left">
+                                                <p className="font-medium">{file.name}</p>
+                                                <p className="text-sm text-slate-600 dark:text-slate-400">
+                                                    {formatFileSize(file.size)}
+                                                </p>
+                                            </div>
+                                            <Button
+                                                variant="ghost"
+                                                size="icon"
+                                                onClick={() => removeFile(index)}
+                                            >
+                                                <X className="h-4 w-4" />
+                                            </Button>
+                                        </div>
+                                    ))}
                                 </div>
 
                                 {tags.length > 0 && (
@@ -170,8 +181,12 @@ export function UploadDialog({
                                     </div>
                                 )}
 
-                                <Button onClick={uploadFile} disabled={uploading} size="lg">
-                                    {uploading ? "Uploading..." : "Upload File"}
+                                <Button onClick={uploadFiles} disabled={uploading} size="lg">
+                                    {uploading
+                                        ? "Uploading..."
+                                        : selectedFiles.length === 1
+                                            ? "Upload File"
+                                            : `Upload ${selectedFiles.length} Files`}
                                 </Button>
                             </div>
                         ) : (
@@ -179,7 +194,7 @@ export function UploadDialog({
                                 <UploadIcon className="mx-auto h-12 w-12 text-slate-400" />
                                 <div>
                                     <p className="text-lg font-medium mb-2">
-                                        Drop your file here
+                                        Drop your files here
                                     </p>
                                     <p className="text-sm text-slate-600 dark:text-slate-400 mb-4">
                                         or click to browse
@@ -188,11 +203,12 @@ export function UploadDialog({
                                         type="file"
                                         id="file-upload-dialog"
                                         className="hidden"
+                                        multiple
                                         onChange={handleFileChange}
                                     />
                                     <label htmlFor="file-upload-dialog">
                                         <Button asChild>
-                                            <span>Select File</span>
+                                            <span>Select Files</span>
                                         </Button>
                                     </label>
                                 </div>